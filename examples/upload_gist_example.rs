@@ -1,6 +1,7 @@
 use awc::Client;
 use case_insensitive_string::CaseInsensitiveString;
 use libsubconverter::upload::gist::upload_gist;
+use libsubconverter::Settings;
 use serde_json::Value;
 use std::collections::HashMap;
 use std::env;
@@ -120,6 +121,7 @@ async fn main() {
     env_logger::Builder::from_env(env_logger::Env::default().default_filter_or("info")).init();
 
     println!("--- Running upload_gist example ---");
+    let settings = Settings::default();
 
     // --- Attempt to fetch existing Gist details if token is available ---
     let github_token = env::var("GITHUB_TOKEN").ok();
@@ -139,6 +141,7 @@ async fn main() {
         "my_subconverter_config.txt".to_string(),
         "This is the content for the new gist file.".to_string(),
         false, // Don't write managed URL for new gist
+        &settings,
     )
     .await
     {
@@ -164,6 +167,7 @@ async fn main() {
         "another_config.yaml".to_string(),
         "YAML content here".to_string(),
         true, // Write managed URL when updating
+        &settings,
     )
     .await
     {
@@ -180,6 +184,7 @@ async fn main() {
         "test_from_ini.txt".to_string(),
         "Content using token from INI file.".to_string(),
         false,
+        &settings,
     )
     .await
     {
@@ -213,6 +218,7 @@ async fn main() {
         "update_test.txt".to_string(), // Assuming this file exists in the gist
         "Updated content using token from INI file.".to_string(),
         true,
+        &settings,
     )
     .await
     {
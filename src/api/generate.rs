@@ -0,0 +1,240 @@
+//! Batch generation of configs listed in a generator config (`generate.ini`)
+//!
+//! Every section names an artifact, `path=` is where its output is written and
+//! the other items are the query `/sub` would receive. `profile=` starts from
+//! the items of a profile, the section's own items override them:
+//!
+//! ```ini
+//! [clash]
+//! path=output/clash.yaml
+//! target=clash
+//! url=https://example.com/subscribe
+//!
+//! [from_profile]
+//! path=output/profile.yaml
+//! profile=profiles/example_profile.ini
+//! ```
+
+use std::path::Path;
+use std::sync::Arc;
+
+use log::info;
+
+use crate::api::{load_profile_items, sub_process_with_settings, SubconverterQuery};
+use crate::utils::ini_reader::IniReader;
+use crate::Settings;
+
+/// Outcome of a single artifact
+#[derive(Debug, Clone, PartialEq)]
+pub struct ArtifactResult {
+    pub name: String,
+    pub path: String,
+    /// Why the artifact was not written, `None` when it was
+    pub error: Option<String>,
+}
+
+impl ArtifactResult {
+    pub fn is_ok(&self) -> bool {
+        self.error.is_none()
+    }
+}
+
+/// Render the artifacts of a generator config one after another
+///
+/// Only the named artifacts are rendered when `artifacts` is not empty, names
+/// missing from the config are reported as failures. A failing artifact does
+/// not stop the others and is left to the caller to report, the error is only
+/// for a config that cannot be read. Artifacts are converted with `settings`,
+/// which are not reloaded from their preference file in between.
+pub async fn generate_artifacts(
+    settings: Arc<Settings>,
+    config_path: &str,
+    artifacts: &[String],
+) -> Result<Vec<ArtifactResult>, String> {
+    let mut ini = IniReader::new();
+    ini.parse_file(config_path)
+        .await
+        .map_err(|e| format!("failed to parse generator config '{}': {}", config_path, e))?;
+
+    let sections = ini.get_section_names().to_vec();
+    let mut results = Vec::new();
+    for name in artifacts {
+        if !sections.contains(name) {
+            results.push(ArtifactResult {
+                name: name.clone(),
+                path: String::new(),
+                error: Some(format!("artifact not found in {}", config_path)),
+            });
+        }
+    }
+
+    for name in sections {
+        if !artifacts.is_empty() && !artifacts.contains(&name) {
+            continue;
+        }
+        let items = match ini.get_items(&name) {
+            Ok(items) => items,
+            Err(e) => {
+                results.push(ArtifactResult {
+                    name,
+                    path: String::new(),
+                    error: Some(format!("failed reading section: {}", e)),
+                });
+                continue;
+            }
+        };
+        info!("Generating artifact {}", name);
+        let result = generate_artifact(&settings, name, items).await;
+        if result.is_ok() {
+            info!("Artifact {} written to {}", result.name, result.path);
+        }
+        results.push(result);
+    }
+    Ok(results)
+}
+
+fn take_item(items: &mut Vec<(String, String)>, key: &str) -> Option<String> {
+    let value = items
+        .iter()
+        .find(|(k, _)| k == key)
+        .map(|(_, value)| value.clone());
+    items.retain(|(k, _)| k != key);
+    value
+}
+
+async fn generate_artifact(
+    settings: &Arc<Settings>,
+    name: String,
+    mut items: Vec<(String, String)>,
+) -> ArtifactResult {
+    let path = take_item(&mut items, "path").unwrap_or_default();
+    let profile = take_item(&mut items, "profile");
+    let mut result = ArtifactResult {
+        name,
+        path,
+        error: None,
+    };
+    if result.path.is_empty() {
+        result.error = Some("no path= given".to_string());
+        return result;
+    }
+
+    if let Some(profile) = profile {
+        let mut profile_items = match load_profile_items(Path::new("."), &profile).await {
+            Ok(profile_items) => profile_items,
+            Err(e) => {
+                result.error = Some(e.to_string());
+                return result;
+            }
+        };
+        profile_items.retain(|(key, _)| !items.iter().any(|(k, _)| k == key));
+        profile_items.append(&mut items);
+        items = profile_items;
+    }
+
+    let query = match SubconverterQuery::from_pairs(&items) {
+        Ok(query) => query,
+        Err(e) => {
+            result.error = Some(format!("invalid parameters: {}", e));
+            return result;
+        }
+    };
+    // Same messages the HTTP API answers with
+    let content = match sub_process_with_settings(None, query, Arc::clone(settings)).await {
+        Ok(response) if response.status_code >= 400 => Err(response.content),
+        Ok(response) => Ok(response.into_content()),
+        Err(e) => Err(e.message()),
+    };
+    match content {
+        Ok(content) => {
+            if let Err(e) = tokio::fs::write(&result.path, content).await {
+                result.error = Some(format!("failed to write {}: {}", result.path, e));
+            }
+        }
+        Err(e) => result.error = Some(e),
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn generator_settings() -> Arc<Settings> {
+        Arc::new(Settings {
+            generator_mode: true,
+            ..Settings::default()
+        })
+    }
+
+    #[tokio::test]
+    async fn test_failed_artifacts_do_not_stop_the_others() {
+        let dir = tempfile::tempdir().unwrap();
+        let output = dir.path().join("nodes.txt");
+        let config = dir.path().join("generate.ini");
+        std::fs::write(
+            &config,
+            format!(
+                "[nodes]\npath={}\ntarget=ss\nurl=ss://YWVzLTI1Ni1nY206cGFzcw@1.2.3.4:8388#Test\n\
+                 [nopath]\ntarget=ss\n\
+                 [badtarget]\npath={}\ntarget=bogus\n",
+                output.display(),
+                dir.path().join("bad.txt").display()
+            ),
+        )
+        .unwrap();
+        let config = config.to_str().unwrap();
+
+        let results = generate_artifacts(generator_settings(), config, &[])
+            .await
+            .unwrap();
+        let names: Vec<&str> = results.iter().map(|r| r.name.as_str()).collect();
+        assert_eq!(names, ["nodes", "nopath", "badtarget"]);
+        assert!(results[0].is_ok(), "{:?}", results[0]);
+        assert!(!std::fs::read_to_string(&output).unwrap().is_empty());
+        assert_eq!(results[1].error.as_deref(), Some("no path= given"));
+        assert_eq!(
+            results[2].error.as_deref(),
+            Some("Invalid target parameter")
+        );
+        assert!(!dir.path().join("bad.txt").exists());
+
+        let artifacts = ["nopath".to_string(), "missing".to_string()];
+        let results = generate_artifacts(generator_settings(), config, &artifacts)
+            .await
+            .unwrap();
+        let names: Vec<&str> = results.iter().map(|r| r.name.as_str()).collect();
+        assert_eq!(names, ["missing", "nopath"]);
+        assert!(results.iter().all(|r| !r.is_ok()));
+    }
+
+    #[tokio::test]
+    async fn test_profile_items_are_overridden_by_the_section() {
+        let dir = tempfile::tempdir().unwrap();
+        let output = dir.path().join("profile.txt");
+        let config = dir.path().join("generate.ini");
+        // base/profiles/example_profile.ini targets clash, the section asks for ss
+        std::fs::write(
+            &config,
+            format!(
+                "[test_profile]\npath={}\nprofile=profiles/example_profile.ini\ntarget=ss\n\
+                 [missing_profile]\npath={}\nprofile=profiles/missing.ini\n",
+                output.display(),
+                dir.path().join("missing.txt").display()
+            ),
+        )
+        .unwrap();
+
+        let results = generate_artifacts(generator_settings(), config.to_str().unwrap(), &[])
+            .await
+            .unwrap();
+        assert!(results[0].is_ok(), "{:?}", results[0]);
+        let content = std::fs::read_to_string(&output).unwrap();
+        let links = crate::utils::base64::url_safe_base64_decode(&content);
+        assert!(links.contains("www.example.com"), "{}", links);
+        assert_eq!(
+            results[1].error.as_deref(),
+            Some("profile not found: profiles/missing.ini")
+        );
+    }
+}
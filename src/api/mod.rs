@@ -1,7 +1,10 @@
 #[cfg(target_arch = "wasm32")]
 pub mod admin;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod generate;
 #[cfg(target_arch = "wasm32")]
 pub mod init;
+pub mod profile;
 #[cfg(target_arch = "wasm32")]
 pub mod rules;
 #[cfg(target_arch = "wasm32")]
@@ -9,8 +12,11 @@ pub mod short_urls;
 pub mod sub;
 #[cfg(target_arch = "wasm32")]
 pub use admin::*;
+#[cfg(not(target_arch = "wasm32"))]
+pub use generate::*;
 #[cfg(target_arch = "wasm32")]
 pub use init::*;
+pub use profile::*;
 #[cfg(target_arch = "wasm32")]
 pub use rules::*;
 #[cfg(target_arch = "wasm32")]
@@ -0,0 +1,55 @@
+//! Profiles are stored `/sub` queries, an INI file with the query items in its
+//! `[Profile]` section
+
+use std::path::Path;
+
+use crate::interfaces::SubconverterError;
+use crate::utils::file_exists;
+use crate::utils::ini_reader::IniReader;
+
+/// Read the `[Profile]` items of `profile_name`, resolved against `root` and
+/// falling back to the same name under `base/`
+pub async fn load_profile_items(
+    root: &Path,
+    profile_name: &str,
+) -> Result<Vec<(String, String)>, SubconverterError> {
+    let mut candidate_paths = vec![root.join(profile_name)];
+    if !profile_name.starts_with("base/") {
+        candidate_paths.push(root.join("base").join(profile_name));
+    }
+
+    for path in candidate_paths {
+        let path = path.to_string_lossy().to_string();
+        if !file_exists(&path).await {
+            continue;
+        }
+
+        let parse_error = |detail: String| SubconverterError::ParseError {
+            what: "profile".to_string(),
+            detail,
+        };
+        let mut ini = IniReader::new();
+        if let Err(e) = ini.parse_file(&path).await {
+            return Err(parse_error(format!(
+                "failed to parse profile '{}': {}",
+                path, e
+            )));
+        }
+
+        if ini.enter_section("Profile").is_err() {
+            return Err(parse_error(format!(
+                "profile '{}' has no [Profile] section",
+                path
+            )));
+        }
+
+        return ini
+            .get_items("Profile")
+            .map_err(|e| parse_error(format!("failed reading [Profile] in '{}': {}", path, e)));
+    }
+
+    Err(SubconverterError::NotFound(format!(
+        "profile not found: {}",
+        profile_name
+    )))
+}
@@ -1,15 +1,22 @@
-use log::{debug, error};
+use log::{debug, error, warn};
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
+use std::sync::Arc;
 
 use crate::constants::regex_black_list::REGEX_BLACK_LIST;
-use crate::interfaces::subconverter::{subconverter, SubconverterConfigBuilder, UploadStatus};
+use crate::generator::exports::ConfigChunks;
+use crate::generator::validate::{validate_config, ValidationReport};
+use crate::interfaces::subconverter::{
+    subconverter, SubconverterConfigBuilder, SubconverterError, UploadStatus,
+};
 use crate::models::ruleset::RulesetConfigs;
-use crate::models::{ProxyGroupConfigs, RegexMatchConfigs, SubconverterTarget};
+use crate::models::{
+    Base64Style, DedupMode, ProxyGroupConfigs, RegexMatchConfigs, SubconverterTarget,
+};
 use crate::settings::external::ExternalSettings;
 use crate::settings::settings::init_settings;
 use crate::settings::{refresh_configuration, FromIni, FromIniWithDelimiter};
-use crate::utils::reg_valid;
+use crate::utils::{file_exists, file_get_async, is_link, reg_valid};
 use crate::{RuleBases, Settings, TemplateArgs};
 
 #[cfg(target_arch = "wasm32")]
@@ -86,6 +93,8 @@ pub struct SubconverterQuery {
     pub ruleset: Option<String>,
     /// External configuration file (optional)
     pub config: Option<String>,
+    /// Base template of the target, a file name under the base directory
+    pub base: Option<String>,
 
     /// Device ID (for device-specific configurations)
     pub dev_id: Option<String>,
@@ -109,6 +118,8 @@ pub struct SubconverterQuery {
         deserialize_with = "bool_deserializer::deserialize_option_bool"
     )]
     pub append_type: Option<bool>,
+    /// Format of remarks with `append_type`, e.g. `{remark} | {type}`
+    pub append_type_format: Option<String>,
     /// Whether to remove old emoji and add new emoji
     #[serde(
         default,
@@ -133,6 +144,18 @@ pub struct SubconverterQuery {
         deserialize_with = "bool_deserializer::deserialize_option_bool"
     )]
     pub list: Option<bool>,
+    /// Whether Surge gets a module of rules instead of a full profile
+    #[serde(
+        default,
+        deserialize_with = "bool_deserializer::deserialize_option_bool"
+    )]
+    pub module: Option<bool>,
+    /// Whether Loon gets a plugin of rules instead of a full profile
+    #[serde(
+        default,
+        deserialize_with = "bool_deserializer::deserialize_option_bool"
+    )]
+    pub plugin: Option<bool>,
     /// Sort nodes
     #[serde(
         default,
@@ -143,6 +166,84 @@ pub struct SubconverterQuery {
     /// Sort Script
     pub sort_script: Option<String>,
 
+    /// Merge the DNS section configured by `clash_dns` into Clash configs
+    #[serde(
+        default,
+        deserialize_with = "bool_deserializer::deserialize_option_bool"
+    )]
+    pub dns: Option<bool>,
+
+    /// Generate a Clash.Meta (mihomo) config, enabling its extra sections
+    #[serde(
+        default,
+        deserialize_with = "bool_deserializer::deserialize_option_bool"
+    )]
+    pub meta: Option<bool>,
+
+    /// Merge the TUN section configured by `clash_tun` into Clash.Meta configs
+    #[serde(
+        default,
+        deserialize_with = "bool_deserializer::deserialize_option_bool"
+    )]
+    pub tun: Option<bool>,
+
+    /// Merge the sniffer section configured by `clash_sniffer` into Clash.Meta configs
+    #[serde(
+        default,
+        deserialize_with = "bool_deserializer::deserialize_option_bool"
+    )]
+    pub sniff: Option<bool>,
+
+    /// Keep group regex rules as Clash.Meta `filter` fields instead of listing nodes
+    #[serde(
+        default,
+        deserialize_with = "bool_deserializer::deserialize_option_bool"
+    )]
+    pub native_filter: Option<bool>,
+
+    /// Reference subscription URLs as Clash proxy providers instead of inlining their nodes
+    #[serde(
+        default,
+        deserialize_with = "bool_deserializer::deserialize_option_bool"
+    )]
+    pub providers: Option<bool>,
+
+    /// Reference subscription URLs as QuantumultX `server_remote` resources
+    /// instead of inlining their nodes
+    #[serde(
+        default,
+        deserialize_with = "bool_deserializer::deserialize_option_bool"
+    )]
+    pub server_remote: Option<bool>,
+
+    /// Generate groups by country ahead of the configured groups
+    #[serde(
+        default,
+        deserialize_with = "bool_deserializer::deserialize_option_bool"
+    )]
+    pub autogroup: Option<bool>,
+
+    /// Prepend nodes showing subscription traffic and expiry
+    #[serde(
+        default,
+        deserialize_with = "bool_deserializer::deserialize_option_bool"
+    )]
+    pub info_nodes: Option<bool>,
+
+    /// Add the airport name and traffic ratio to the remarks of SSD nodes
+    #[serde(
+        default,
+        deserialize_with = "bool_deserializer::deserialize_option_bool"
+    )]
+    pub ssd_info: Option<bool>,
+
+    /// Remove duplicate nodes, `true` compares servers and `remark` compares remarks
+    pub dedup: Option<String>,
+
+    /// Base64 encoding of link subscriptions, `padded` or `urlsafe` instead
+    /// of the standard alphabet without padding
+    pub b64: Option<String>,
+
     /// argFilterDeprecated
     #[serde(
         default,
@@ -152,12 +253,20 @@ pub struct SubconverterQuery {
 
     /// Information for filtering, rename, emoji addition
     pub rename: Option<String>,
+    /// Template renaming nodes after sorting, e.g. `{country} {index:02}`
+    pub index_remark: Option<String>,
     /// Whether to enable TCP Fast Open
     #[serde(
         default,
         deserialize_with = "bool_deserializer::deserialize_option_bool"
     )]
     pub tfo: Option<bool>,
+    /// Whether Surge nodes block QUIC
+    #[serde(
+        default,
+        deserialize_with = "bool_deserializer::deserialize_option_bool"
+    )]
+    pub block_quic: Option<bool>,
     /// Whether to enable UDP
     #[serde(
         default,
@@ -176,6 +285,8 @@ pub struct SubconverterQuery {
         deserialize_with = "bool_deserializer::deserialize_option_bool"
     )]
     pub tls13: Option<bool>,
+    /// TLS server name of TLS nodes that have none
+    pub sni: Option<String>,
     /// Enable rule generator
     #[serde(
         default,
@@ -212,6 +323,18 @@ pub struct SubconverterQuery {
         deserialize_with = "bool_deserializer::deserialize_option_bool"
     )]
     pub classic: Option<bool>,
+    /// Drop the proxies of the Clash base instead of keeping them
+    #[serde(
+        default,
+        deserialize_with = "bool_deserializer::deserialize_option_bool"
+    )]
+    pub overwrite_original_proxies: Option<bool>,
+    /// Drop the groups of the Clash base instead of merging into them
+    #[serde(
+        default,
+        deserialize_with = "bool_deserializer::deserialize_option_bool"
+    )]
+    pub overwrite_original_groups: Option<bool>,
 
     #[serde(
         default,
@@ -219,12 +342,155 @@ pub struct SubconverterQuery {
     )]
     pub expand: Option<bool>,
 
+    /// Check the generated config, errors answer 422 with a JSON report and
+    /// warnings are appended as comments
+    #[serde(
+        default,
+        deserialize_with = "bool_deserializer::deserialize_option_bool"
+    )]
+    pub validate: Option<bool>,
+
+    /// List the subscription entries which were dropped, with the reason, as
+    /// comments at the end of the config
+    #[serde(
+        default,
+        deserialize_with = "bool_deserializer::deserialize_option_bool"
+    )]
+    pub debug: Option<bool>,
+
+    /// Generate the config even when filtering leaves no node, for rule-only
+    /// configs
+    #[serde(
+        default,
+        deserialize_with = "bool_deserializer::deserialize_option_bool"
+    )]
+    pub allow_empty: Option<bool>,
+
     /// Singbox specific parameters
     #[serde(default)]
     pub singbox: HashMap<String, String>,
 
     /// Request headers
     pub request_headers: Option<HashMap<String, String>>,
+
+    /// Base URL the request reached this server at, set by the web handlers
+    #[serde(skip)]
+    pub external_base_url: Option<String>,
+
+    /// `tpl_*` parameters, base templates see them as `request.tpl_*`
+    #[serde(skip)]
+    pub template_params: HashMap<String, String>,
+}
+
+impl SubconverterQuery {
+    /// Build a query from key/value pairs the same way `/sub` would receive
+    /// them in its query string
+    pub fn from_pairs(items: &[(String, String)]) -> Result<Self, serde_urlencoded::de::Error> {
+        let encoded = items
+            .iter()
+            .map(|(k, v)| format!("{}={}", urlencoding::encode(k), urlencoding::encode(v)))
+            .collect::<Vec<_>>()
+            .join("&");
+
+        let mut query: Self = serde_urlencoded::from_str(&encoded)?;
+        query.add_template_params(items.iter().map(|(k, v)| (k.as_str(), v.as_str())));
+        Ok(query)
+    }
+
+    /// Keep the `tpl_*` parameters among `pairs` for base templates, names
+    /// with anything but letters, digits and `_` are left out and the
+    /// parameters kept before win
+    pub fn add_template_params<K, V>(&mut self, pairs: impl IntoIterator<Item = (K, V)>)
+    where
+        K: AsRef<str>,
+        V: AsRef<str>,
+    {
+        for (key, value) in pairs {
+            let key = key.as_ref();
+            let valid = key.strip_prefix("tpl_").is_some_and(|name| {
+                !name.is_empty() && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+            });
+            if valid {
+                self.template_params
+                    .entry(key.to_string())
+                    .or_insert_with(|| value.as_ref().to_string());
+            }
+        }
+    }
+
+    /// Take the options this query leaves unset from `defaults`, the
+    /// subscriptions, target and token are never taken
+    pub fn fill_unset_from(&mut self, defaults: SubconverterQuery) {
+        macro_rules! fill {
+            ($($field:ident),* $(,)?) => {
+                $(
+                    if self.$field.is_none() {
+                        self.$field = defaults.$field;
+                    }
+                )*
+            };
+        }
+        fill!(
+            new_name,
+            group,
+            include,
+            exclude,
+            groups,
+            ruleset,
+            config,
+            base,
+            dev_id,
+            insert,
+            prepend,
+            append_type,
+            append_type_format,
+            emoji,
+            add_emoji,
+            remove_emoji,
+            list,
+            module,
+            plugin,
+            sort,
+            sort_script,
+            dns,
+            meta,
+            tun,
+            sniff,
+            native_filter,
+            providers,
+            server_remote,
+            autogroup,
+            info_nodes,
+            ssd_info,
+            dedup,
+            fdn,
+            rename,
+            index_remark,
+            tfo,
+            block_quic,
+            udp,
+            scv,
+            tls13,
+            sni,
+            rename_node,
+            interval,
+            strict,
+            filter,
+            script,
+            classic,
+            overwrite_original_proxies,
+            overwrite_original_groups,
+            expand,
+            debug,
+            allow_empty,
+        );
+        for (key, value) in defaults.singbox {
+            self.singbox.entry(key).or_insert(value);
+        }
+        for (key, value) in defaults.template_params {
+            self.template_params.entry(key).or_insert(value);
+        }
+    }
 }
 
 /// Parse a query string into a HashMap
@@ -249,6 +515,9 @@ pub struct SubResponse {
     pub status_code: u16,
     #[serde(skip_serializing_if = "is_not_attempted")] // Don't include if upload wasn't attempted
     pub upload_status: UploadStatus,
+    /// Body still to be produced while it is sent, `content` is empty when set
+    #[serde(skip)]
+    pub stream: Option<ConfigChunks>,
 }
 
 // Helper function for skip_serializing_if
@@ -264,6 +533,7 @@ impl SubResponse {
             headers: HashMap::new(),
             status_code: 200,
             upload_status: UploadStatus::NotAttempted, // Default to not attempted
+            stream: None,
         }
     }
 
@@ -274,6 +544,7 @@ impl SubResponse {
             headers: HashMap::new(),
             status_code,
             upload_status: UploadStatus::NotAttempted, // Default to not attempted
+            stream: None,
         }
     }
 
@@ -286,13 +557,100 @@ impl SubResponse {
         self.upload_status = status;
         self
     }
+
+    pub fn with_stream(mut self, stream: Option<ConfigChunks>) -> Self {
+        self.stream = stream;
+        self
+    }
+
+    /// The whole body, producing whatever is left to stream
+    pub fn into_content(self) -> String {
+        match self.stream {
+            Some(stream) => stream.into_string(),
+            None => self.content,
+        }
+    }
+}
+
+/// Path of the base template `name` picked with `base=`, which has to stay
+/// within `base_dir` unless it is a web address
+fn base_template_path(base_dir: &str, name: &str) -> Result<String, String> {
+    if name.starts_with("http://") || name.starts_with("https://") {
+        return Ok(name.to_string());
+    }
+    if is_link(name)
+        || name.starts_with('/')
+        || name.contains('\\')
+        || name.contains(':')
+        || name.split('/').any(|part| part.is_empty() || part == "..")
+    {
+        return Err(format!("Base template path is not allowed: {}", name));
+    }
+    Ok(format!("{}/{}", base_dir, name))
+}
+
+/// Loads a section merged into Clash configs, the file holds either the
+/// section itself or a config with a top level `key`. Failures are logged and
+/// leave the section out
+async fn load_clash_section(path: &str, key: &str) -> Option<serde_yaml::Value> {
+    if path.is_empty() {
+        return None;
+    }
+    match read_clash_section(path, key).await {
+        Ok(section) => Some(section),
+        Err(e) => {
+            error!("Failed to load Clash {} section from {}: {}", key, path, e);
+            None
+        }
+    }
+}
+
+async fn read_clash_section(path: &str, key: &str) -> Result<serde_yaml::Value, String> {
+    let content = file_get_async(path, None)
+        .await
+        .map_err(|e| e.to_string())?;
+    let mut section: serde_yaml::Value =
+        serde_yaml::from_str(&content).map_err(|e| e.to_string())?;
+    if let Some(inner) = section.get_mut(key) {
+        section = std::mem::take(inner);
+    }
+    if !section.is_mapping() {
+        return Err("not a mapping".to_string());
+    }
+    Ok(section)
 }
 
 /// Handler for subscription conversion
 pub async fn sub_process(
     req_url: Option<String>,
     query: SubconverterQuery,
-) -> Result<SubResponse, Box<dyn std::error::Error>> {
+) -> Result<SubResponse, SubconverterError> {
+    let settings = request_settings().await?;
+    process_query(req_url, query, settings, Vec::new()).await
+}
+
+/// Same as [`sub_process`] but converts subscription content handed in by the
+/// caller, `url=` is only fetched when given as well
+pub async fn sub_process_content(
+    query: SubconverterQuery,
+    content: String,
+) -> Result<SubResponse, SubconverterError> {
+    let settings = request_settings().await?;
+    process_query(None, query, settings, vec![content]).await
+}
+
+/// Same as [`sub_process`] with the settings handed in, they are neither
+/// initialized nor refreshed from the preference file
+pub async fn sub_process_with_settings(
+    req_url: Option<String>,
+    query: SubconverterQuery,
+    global: Arc<Settings>,
+) -> Result<SubResponse, SubconverterError> {
+    process_query(req_url, query, global, Vec::new()).await
+}
+
+/// Settings for a request, loaded on first use and refreshed when configured to
+async fn request_settings() -> Result<Arc<Settings>, SubconverterError> {
     let mut global = Settings::current();
 
     // not initialized, in wasm that's common for cold start.
@@ -311,7 +669,7 @@ pub async fn sub_process(
 
         #[cfg(not(target_arch = "wasm32"))]
         if let Err(e) = result {
-            return Err(e);
+            return Err(SubconverterError::Internal(e.to_string()));
         }
 
         global = Settings::current();
@@ -320,8 +678,21 @@ pub async fn sub_process(
         global = Settings::current();
     }
 
+    Ok(Arc::clone(&global))
+}
+
+async fn process_query(
+    req_url: Option<String>,
+    mut query: SubconverterQuery,
+    global: Arc<Settings>,
+    contents: Vec<String>,
+) -> Result<SubResponse, SubconverterError> {
     // Start building configuration
     let mut builder = SubconverterConfigBuilder::new();
+    builder.settings(Arc::clone(&global));
+    for content in &contents {
+        builder.add_content(content);
+    }
 
     let target;
     if let Some(_target) = &query.target {
@@ -329,17 +700,8 @@ pub async fn sub_process(
             Some(_target) => {
                 target = _target.clone();
                 if _target == SubconverterTarget::Auto {
-                    // TODO: Check user agent and set target accordingly
-                    // if let Some(user_agent) = req.headers().get("User-Agent") {
-                    //     if let Ok(user_agent) = user_agent.to_str() {
-
-                    //         // match_user_agent(
-                    //         //     user_agent,
-                    //         //     &target,
-                    //         //      query.new_name,
-                    //         //      &query.ver);
-                    //     }
-                    // }
+                    // The web handler replaces `auto` by the target of known
+                    // User-Agents, see `Settings::user_agent_args`
                     return Ok(SubResponse::error(
                         "Auto user agent is not supported for now.".to_string(),
                         400,
@@ -361,33 +723,57 @@ pub async fn sub_process(
         ));
     }
 
+    // Base templates see the `tpl_*` parameters of the request
+    if let Some((_, query_string)) = req_url.as_deref().and_then(|url| url.split_once('?')) {
+        query.add_template_params(url::form_urlencoded::parse(query_string.as_bytes()));
+    }
+
+    // Per target defaults of the operator come after the request and the
+    // profile, but before the global defaults
+    if let Some(args) = query
+        .target
+        .as_deref()
+        .and_then(|target| global.target_defaults_args(target))
+    {
+        match serde_urlencoded::from_str::<SubconverterQuery>(args) {
+            Ok(defaults) => query.fill_unset_from(defaults),
+            Err(e) => warn!("Invalid target defaults '{}': {}", args, e),
+        }
+    }
+
+    // The managed config URL repeats the request, below the base path which
+    // is part of the managed config prefix already
+    builder.request_path(req_url.map(|url| {
+        url.strip_prefix(global.api_base_path.as_str())
+            .unwrap_or(&url)
+            .to_string()
+    }));
     builder.update_interval(match query.interval {
         Some(interval) => interval,
         None => global.update_interval,
     });
-    // Check if we should authorize the request, if we are in API mode
+    // Check if we should authorize the request, if we are in API mode.
+    // Conversions run from the command line are always trusted
     #[cfg(not(feature = "js-runtime"))]
-    let authorized = false;
+    let authorized = global.generator_mode;
 
     #[cfg(feature = "js-runtime")]
-    let authorized =
-        !global.api_mode || query.token.as_deref().unwrap_or_default() == global.api_access_token;
+    let authorized = global.generator_mode
+        || !global.api_mode
+        || query.token.as_deref().unwrap_or_default() == global.api_access_token;
     builder.authorized(authorized);
     builder.update_strict(query.strict.unwrap_or(global.update_strict));
 
-    if query
-        .include
-        .clone()
-        .is_some_and(|include| REGEX_BLACK_LIST.contains(&include))
-        || query
-            .exclude
-            .clone()
-            .is_some_and(|exclude| REGEX_BLACK_LIST.contains(&exclude))
-    {
-        return Ok(SubResponse::error(
-            "Invalid regex in request!".to_string(),
-            400,
-        ));
+    // Reject unusable remark filters up front, naming the offending pattern
+    for (name, pattern) in [("include", &query.include), ("exclude", &query.exclude)] {
+        if let Some(pattern) = pattern.as_deref().filter(|pattern| !pattern.is_empty()) {
+            if REGEX_BLACK_LIST.iter().any(|black| black == pattern) || !reg_valid(pattern) {
+                return Ok(SubResponse::error(
+                    format!("Invalid regex in request: {}={}", name, pattern),
+                    400,
+                ));
+            }
+        }
     }
 
     let enable_insert = match query.insert {
@@ -404,7 +790,7 @@ pub async fn sub_process(
     let urls = match query.url.as_deref() {
         Some(query_url) => query_url.split('|').map(|s| s.to_owned()).collect(),
         None => {
-            if authorized {
+            if authorized && contents.is_empty() {
                 global.default_urls.clone()
             } else {
                 vec![]
@@ -418,10 +804,15 @@ pub async fn sub_process(
     // Create template args from request parameters and other settings
     let mut template_args = TemplateArgs::default();
     template_args.global_vars = global.template_vars.clone();
+    template_args.managed_prefix = global.managed_config_url();
 
     template_args.request_params = query.clone();
 
     builder.append_proxy_type(query.append_type.unwrap_or(global.append_type));
+    if let Some(format) = &query.append_type_format {
+        builder.append_type_format(format);
+    }
+    builder.autogroup(query.autogroup.unwrap_or(global.autogroup));
 
     let mut arg_expand_rulesets = query.expand;
     if target.is_clash() && query.script.is_none() {
@@ -430,11 +821,29 @@ pub async fn sub_process(
 
     // flags
     builder.tfo(query.tfo.or(global.tfo_flag));
+    builder.block_quic(query.block_quic);
     builder.udp(query.udp.or(global.udp_flag));
     builder.skip_cert_verify(query.scv.or(global.skip_cert_verify));
     builder.tls13(query.tls13.or(global.tls13_flag));
+    builder.sni(query.sni.clone().filter(|sni| !sni.is_empty()));
     builder.sort(query.sort.unwrap_or(global.enable_sort));
     builder.sort_script(query.sort_script.unwrap_or(global.sort_script.clone()));
+    builder.sort_priority(global.sort_priority.clone());
+    builder.dedup(
+        query
+            .dedup
+            .as_deref()
+            .map_or(DedupMode::Disabled, DedupMode::from_arg),
+    );
+    builder.base64_style(
+        query
+            .b64
+            .as_deref()
+            .map_or(Base64Style::Unpadded, Base64Style::from_arg),
+    );
+    if let Some(template) = &query.index_remark {
+        builder.index_remark(template);
+    }
 
     let filter_deprecated = query.fdn.unwrap_or(global.filter_deprecated);
     debug!(
@@ -445,20 +854,60 @@ pub async fn sub_process(
     let mut clash_new_field_name = query.new_name.unwrap_or(global.clash_use_new_field);
     builder.clash_new_field_name(clash_new_field_name);
     builder.clash_script(query.script.unwrap_or_default());
+    // Clash script configs only get info nodes when asked for explicitly
+    builder.append_info_nodes(
+        query
+            .info_nodes
+            .unwrap_or(global.append_info_nodes && query.script != Some(true)),
+    );
+    builder.ssd_remark_info(query.ssd_info.unwrap_or_default());
     builder.clash_classical_ruleset(query.classic.unwrap_or_default());
     let nodelist = query.list.unwrap_or_default();
     builder.nodelist(nodelist);
+    builder.surge_module(query.module.unwrap_or_default());
+    builder.loon_plugin(query.plugin.unwrap_or_default());
+
+    if target.is_clash() {
+        let clash_meta = query.meta.unwrap_or_default();
+        builder.clash_meta(clash_meta);
+        builder.clash_meta_overwrite(global.clash_meta_overwrite);
+        builder.native_filter(query.native_filter.unwrap_or_default());
+        builder.clash_providers(query.providers.unwrap_or_default());
+        builder.overwrite_original_proxies(
+            query
+                .overwrite_original_proxies
+                .unwrap_or(global.overwrite_original_proxies),
+        );
+        builder.overwrite_original_groups(
+            query
+                .overwrite_original_groups
+                .unwrap_or(global.overwrite_original_groups),
+        );
+        if query.dns == Some(true) {
+            builder.clash_dns(load_clash_section(&global.clash_dns, "dns").await);
+        }
+        if clash_meta && query.tun == Some(true) {
+            builder.clash_tun(load_clash_section(&global.clash_tun, "tun").await);
+        }
+        if clash_meta && query.sniff == Some(true) {
+            builder.clash_sniffer(load_clash_section(&global.clash_sniffer, "sniffer").await);
+        }
+    }
+
+    if target == SubconverterTarget::QuantumultX {
+        builder.quanx_server_remote(query.server_remote.unwrap_or_default());
+    }
 
     // Keep managed config prefix when converters need remote ruleset URLs.
     if !target.is_clash() || query.script == Some(true) {
-        builder.managed_config_prefix(global.managed_config_prefix.clone());
+        builder.managed_config_prefix(global.managed_config_url());
     }
 
     if arg_expand_rulesets != Some(true) {
         clash_new_field_name = true;
         builder.clash_new_field_name(clash_new_field_name);
     } else {
-        builder.managed_config_prefix(global.managed_config_prefix.clone());
+        builder.managed_config_prefix(global.managed_config_url());
         builder.clash_script(false);
     }
 
@@ -493,6 +942,28 @@ pub async fn sub_process(
         sssub_rule_base: global.ssub_base.clone(),
         singbox_rule_base: global.singbox_base.clone(),
     };
+    // A base template picked by the request stands in for the configured
+    // one, the external config may still override it
+    if let Some(base) = query.base.as_deref().filter(|base| !base.is_empty()) {
+        let base_dir = if global.base_path.is_empty() {
+            "base"
+        } else {
+            global.base_path.trim_end_matches('/')
+        };
+        let path = match base_template_path(base_dir, base) {
+            Ok(path) => path,
+            Err(e) => return Ok(SubResponse::error(e, 400)),
+        };
+        if !is_link(&path) && !file_exists(&path).await {
+            return Ok(SubResponse::error(
+                format!("Base template not found: {}", path),
+                400,
+            ));
+        }
+        if let Some(rule_base) = rule_bases.base_mut(&target) {
+            *rule_base = path;
+        }
+    }
     builder.rule_bases(rule_bases.clone());
     builder.template_args(template_args.clone());
 
@@ -503,6 +974,18 @@ pub async fn sub_process(
     if !ext_config.is_empty() {
         debug!("Loading external config from {}", ext_config);
 
+        // Only trusted callers may point config= outside the base directory
+        if query.config.is_some()
+            && !authorized
+            && !is_link(&ext_config)
+            && (ext_config.starts_with('/') || ext_config.contains(".."))
+        {
+            return Ok(SubResponse::error(
+                format!("External config path is not allowed: {}", ext_config),
+                403,
+            ));
+        }
+
         // In WebAssembly environment, we can't use std::thread::spawn
         // Instead, we use the async version directly
         let extconf_result = ExternalSettings::load_from_file(&ext_config).await;
@@ -557,23 +1040,33 @@ pub async fn sub_process(
                 if extconf.remove_old_emoji.is_some() {
                     builder.remove_emoji(extconf.remove_old_emoji.unwrap());
                 }
+                builder.quanx_rewrite_remote(extconf.quanx_rewrite_remote);
+                builder.quanx_task_local(extconf.quanx_task_local);
             }
             Err(e) => {
                 error!("Failed to load external config from {}: {}", ext_config, e);
+                return Ok(SubResponse::error(
+                    format!("Failed to load external config from {}: {}", ext_config, e),
+                    400,
+                ));
             }
         }
     }
 
     // 请求参数的覆盖优先级最高
-    if let Some(include) = query.include.as_deref() {
-        if reg_valid(&include) {
-            builder.include_remarks(vec![include.to_owned()]);
-        }
+    if let Some(include) = query
+        .include
+        .as_deref()
+        .filter(|include| !include.is_empty())
+    {
+        builder.include_remarks(vec![include.to_owned()]);
     }
-    if let Some(exclude) = query.exclude.as_deref() {
-        if reg_valid(&exclude) {
-            builder.exclude_remarks(vec![exclude.to_owned()]);
-        }
+    if let Some(exclude) = query
+        .exclude
+        .as_deref()
+        .filter(|exclude| !exclude.is_empty())
+    {
+        builder.exclude_remarks(vec![exclude.to_owned()]);
     }
     if let Some(emoji) = query.emoji {
         builder.add_emoji(emoji);
@@ -630,6 +1123,10 @@ pub async fn sub_process(
     builder.group_name(query.group.clone());
     builder.filename(query.filename.clone());
     builder.upload(query.upload.unwrap_or_default());
+    // Only the HTTP server can send a body while it is being produced
+    builder.stream_output(cfg!(feature = "web-api"));
+    builder.debug(query.debug.unwrap_or_default());
+    builder.allow_empty(query.allow_empty.unwrap_or_default());
 
     // Process filter script
     let filter = query.filter.unwrap_or(global.filter_script.clone());
@@ -650,6 +1147,9 @@ pub async fn sub_process(
     if let Some(request_headers) = &query.request_headers {
         builder.request_headers(request_headers.clone());
     }
+    if let Some(external_base_url) = &query.external_base_url {
+        builder.external_base_url(external_base_url.clone());
+    }
 
     // Build and validate configuration
     let config = match builder.build() {
@@ -669,27 +1169,67 @@ pub async fn sub_process(
     let subconverter_result = subconverter(config).await;
 
     match subconverter_result {
-        Ok(result) => {
-            // Determine content type based on target
-            let content_type = match target {
-                SubconverterTarget::Clash | SubconverterTarget::ClashR => "application/yaml",
-                SubconverterTarget::SingBox => "application/json",
-                SubconverterTarget::SSSub | SubconverterTarget::SSD => "application/json",
-                _ => "text/plain",
+        Ok(result) if query.validate == Some(true) => {
+            let content = match result.stream {
+                Some(stream) => result.content + stream.into_string().as_str(),
+                None => result.content,
             };
+            let report = validate_config(&target, &content);
+            if report.has_errors() {
+                let mut response = SubResponse::ok(
+                    serde_json::to_string(&report)
+                        .map_err(|e| SubconverterError::Internal(e.to_string()))?,
+                    "application/json".to_string(),
+                );
+                response.status_code = 422;
+                return Ok(response);
+            }
 
+            Ok(SubResponse::ok(
+                append_findings(&target, content, &report),
+                target.content_type().to_string(),
+            )
+            .with_headers(result.headers)
+            .with_upload_status(result.upload_status))
+        }
+        Ok(result) => {
             debug!("Subconverter completed successfully");
-            Ok(SubResponse::ok(result.content, content_type.to_string())
-                .with_headers(result.headers)
-                .with_upload_status(result.upload_status))
+            Ok(
+                SubResponse::ok(result.content, target.content_type().to_string())
+                    .with_headers(result.headers)
+                    .with_upload_status(result.upload_status)
+                    .with_stream(result.stream),
+            )
         }
         Err(e) => {
             error!("Subconverter error: {}", e);
-            Ok(SubResponse::error(format!("Conversion error: {}", e), 500))
+            Ok(SubResponse::error(
+                format!("Conversion error: {}", e),
+                e.status_code(),
+            ))
         }
     }
 }
 
+/// Append warnings as comments, JSON configs have no comments and are left as
+/// they are
+fn append_findings(
+    target: &SubconverterTarget,
+    mut content: String,
+    report: &ValidationReport,
+) -> String {
+    if report.findings.is_empty() || target.content_type() == "application/json" {
+        return content;
+    }
+    if !content.ends_with('\n') {
+        content.push('\n');
+    }
+    for finding in &report.findings {
+        content.push_str(&format!("# warning: {}\n", finding.message));
+    }
+    content
+}
+
 #[cfg(target_arch = "wasm32")]
 #[wasm_bindgen]
 pub fn sub_process_wasm(query_json: &str) -> Promise {
@@ -726,6 +1266,36 @@ pub fn sub_process_wasm(query_json: &str) -> Promise {
     future_to_promise(future)
 }
 
+/// Convert `subscription_text` with the `/sub` options in `options_json`,
+/// resolving to the generated config. Remote configs and rulesets are fetched
+/// through the handler given to `set_fetch_handler` when there is one
+#[cfg(all(target_arch = "wasm32", feature = "wasm"))]
+#[wasm_bindgen]
+pub fn convert(subscription_text: &str, options_json: &str) -> Promise {
+    let query = match serde_json::from_str::<SubconverterQuery>(options_json) {
+        Ok(q) => q,
+        Err(e) => {
+            return Promise::reject(&JsValue::from_str(&format!(
+                "Failed to parse options: {}",
+                e
+            )));
+        }
+    };
+
+    let content = subscription_text.to_string();
+    let future = async move {
+        match sub_process_content(query, content).await {
+            Ok(response) if response.status_code < 400 => {
+                Ok(JsValue::from_str(&response.into_content()))
+            }
+            Ok(response) => Err(JsValue::from_str(&response.content)),
+            Err(e) => Err(JsValue::from_str(&format!("Conversion error: {}", e))),
+        }
+    };
+
+    future_to_promise(future)
+}
+
 #[cfg(target_arch = "wasm32")]
 #[wasm_bindgen]
 pub fn init_settings_wasm(pref_path: &str) -> Promise {
@@ -742,3 +1312,252 @@ pub fn init_settings_wasm(pref_path: &str) -> Promise {
 
     future_to_promise(future)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_content_is_converted_without_url() {
+        let settings = Arc::new(Settings {
+            generator_mode: true,
+            ..Settings::default()
+        });
+        let query = SubconverterQuery {
+            target: Some("clash".to_string()),
+            list: Some(true),
+            ..Default::default()
+        };
+
+        let response = process_query(
+            None,
+            query,
+            settings,
+            vec!["ss://YWVzLTI1Ni1nY206cGFzcw@1.2.3.4:8388#Content".to_string()],
+        )
+        .await
+        .unwrap();
+        assert_eq!(response.status_code, 200, "{}", response.content);
+        assert_eq!(response.content_type, "application/yaml");
+        assert!(response.into_content().contains("name: Content"));
+    }
+
+    #[tokio::test]
+    async fn test_validate_reports_unknown_group_members() {
+        let settings = Arc::new(Settings {
+            generator_mode: true,
+            enable_rule_gen: false,
+            ..Settings::default()
+        });
+        let query = SubconverterQuery {
+            target: Some("clash".to_string()),
+            groups: Some("Proxy`select`.*`[]Missing".to_string()),
+            validate: Some(true),
+            ..Default::default()
+        };
+
+        let response = process_query(
+            None,
+            query,
+            settings,
+            vec!["ss://YWVzLTI1Ni1nY206cGFzcw@1.2.3.4:8388#Content".to_string()],
+        )
+        .await
+        .unwrap();
+        assert_eq!(response.status_code, 422, "{}", response.content);
+        assert_eq!(response.content_type, "application/json");
+        let report: serde_json::Value = serde_json::from_str(&response.content).unwrap();
+        assert_eq!(
+            report["findings"][0]["message"],
+            "group 'Proxy' references unknown proxy or group 'Missing'"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_base_template_sees_request_values() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(
+            dir.path().join("named.yml"),
+            "mixed-port: {{ request.tpl_port }}\nmode: {{ default(request.tpl_mode, \"rule\") }}\nproxies: ~\n",
+        )
+        .unwrap();
+        std::fs::write(dir.path().join("broken.yml"), "proxies: ~\n{% if %}\n").unwrap();
+        let settings = Arc::new(Settings {
+            generator_mode: true,
+            enable_rule_gen: false,
+            base_path: dir.path().to_string_lossy().to_string(),
+            ..Settings::default()
+        });
+        let convert = |base: &str, req_url: &str| {
+            let query = SubconverterQuery {
+                target: Some("clash".to_string()),
+                base: Some(base.to_string()),
+                ..Default::default()
+            };
+            process_query(
+                Some(req_url.to_string()),
+                query,
+                settings.clone(),
+                vec!["ss://YWVzLTI1Ni1nY206cGFzcw@1.2.3.4:8388#Content".to_string()],
+            )
+        };
+
+        let response = convert(
+            "named.yml",
+            "/sub?target=clash&tpl_port=7777&tpl_mode=global%0Adns%3A%20x&tpl.bad=1",
+        )
+        .await
+        .unwrap();
+        assert_eq!(response.status_code, 200, "{}", response.content);
+        let content = response.into_content();
+        assert!(content.contains("mixed-port: 7777\n"), "{}", content);
+        assert!(content.contains("mode: 'globaldns: x'\n"), "{}", content);
+
+        let response = convert("broken.yml", "/sub?target=clash").await.unwrap();
+        assert_eq!(response.status_code, 400);
+        assert!(
+            response
+                .content
+                .contains("Invalid base template: syntax error at line 2"),
+            "{}",
+            response.content
+        );
+    }
+
+    #[test]
+    fn test_template_params_are_kept_from_pairs() {
+        let pairs = [
+            ("target", "clash"),
+            ("tpl_region", "HK"),
+            ("tpl_", "empty"),
+            ("tpl_a.b", "dotted"),
+            ("tpl_region", "JP"),
+        ]
+        .map(|(k, v)| (k.to_string(), v.to_string()));
+        let query = SubconverterQuery::from_pairs(&pairs).unwrap();
+        assert_eq!(
+            query.template_params,
+            HashMap::from([("tpl_region".to_string(), "HK".to_string())])
+        );
+    }
+
+    #[tokio::test]
+    async fn test_base_parameter_picks_template() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(
+            dir.path().join("minimal.yml"),
+            "mixed-port: 7777\nproxies: ~\n",
+        )
+        .unwrap();
+        let settings = Arc::new(Settings {
+            generator_mode: true,
+            enable_rule_gen: false,
+            base_path: dir.path().to_string_lossy().to_string(),
+            clash_base: "base/all_base.tpl".to_string(),
+            ..Settings::default()
+        });
+        let convert = |base: &str| {
+            let query = SubconverterQuery {
+                target: Some("clash".to_string()),
+                base: Some(base.to_string()),
+                ..Default::default()
+            };
+            process_query(
+                None,
+                query,
+                settings.clone(),
+                vec!["ss://YWVzLTI1Ni1nY206cGFzcw@1.2.3.4:8388#Content".to_string()],
+            )
+        };
+
+        let response = convert("minimal.yml").await.unwrap();
+        assert_eq!(response.status_code, 200, "{}", response.content);
+        assert!(response.into_content().contains("mixed-port: 7777"));
+
+        let response = convert("missing.yml").await.unwrap();
+        assert_eq!(response.status_code, 400);
+        assert_eq!(
+            response.content,
+            format!(
+                "Base template not found: {}/missing.yml",
+                dir.path().display()
+            )
+        );
+
+        for name in [
+            "../pref.ini",
+            "/etc/passwd",
+            "a//b",
+            "data:text/plain,mode: rule",
+        ] {
+            let response = convert(name).await.unwrap();
+            assert_eq!(response.status_code, 400, "{}", name);
+            assert!(response.content.contains("not allowed"), "{}", name);
+        }
+
+        // A web address is fetched, one which can not be fails the request
+        let response = convert("https://base.invalid/clash.yml").await.unwrap();
+        assert_eq!(response.status_code, 502);
+        assert!(
+            response
+                .content
+                .contains("failed to fetch base template https://base.invalid/clash.yml"),
+            "{}",
+            response.content
+        );
+    }
+
+    #[test]
+    fn test_target_defaults_only_fill_unset_options() {
+        let mut query = SubconverterQuery {
+            target: Some("clash".to_string()),
+            udp: Some(false),
+            emoji: Some(true),
+            ..Default::default()
+        };
+        let defaults =
+            serde_urlencoded::from_str("udp=true&tfo=true&scv=true&emoji=false&target=ss&url=x")
+                .unwrap();
+        query.fill_unset_from(defaults);
+
+        assert_eq!(query.udp, Some(false));
+        assert_eq!(query.emoji, Some(true));
+        assert_eq!(query.tfo, Some(true));
+        assert_eq!(query.scv, Some(true));
+        assert_eq!(query.target.as_deref(), Some("clash"));
+        assert_eq!(query.url, None);
+    }
+
+    #[tokio::test]
+    async fn test_target_defaults_come_before_global_defaults() {
+        let settings = Arc::new(Settings {
+            generator_mode: true,
+            tfo_flag: Some(false),
+            target_defaults: HashMap::from([(
+                "Clash".to_string(),
+                "udp=true&tfo=true&scv=true".to_string(),
+            )]),
+            ..Settings::default()
+        });
+        let query = SubconverterQuery {
+            target: Some("clash".to_string()),
+            list: Some(true),
+            udp: Some(false),
+            ..Default::default()
+        };
+
+        let response = process_query(
+            None,
+            query,
+            settings,
+            vec!["trojan://secret@1.2.3.4:443#Content".to_string()],
+        )
+        .await
+        .unwrap();
+        assert_eq!(response.status_code, 200, "{}", response.content);
+        let content = response.into_content();
+        assert!(content.contains("udp: false"), "{}", content);
+        assert!(content.contains("tfo: true"), "{}", content);
+        assert!(content.contains("skip-cert-verify: true"), "{}", content);
+    }
+}
@@ -0,0 +1,87 @@
+//! Parsed INI style bases shared across requests
+//!
+//! Surge, Quantumult, QuantumultX, Loon and Mellow bases are parsed with
+//! [`IniReader`] on every request. The parsed reader is kept per target and
+//! base text and each request works on its own copy.
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+use once_cell::sync::Lazy;
+
+use crate::utils::ini_reader::IniReader;
+use crate::utils::lock_recovering;
+use crate::utils::string::md5;
+
+/// Parsed bases keyed by target and the md5 of the rendered base text, so an
+/// edited template never hits a stale entry
+type BaseCache = HashMap<(&'static str, String), Arc<IniReader>>;
+
+static INI_BASE_CACHE: Lazy<Mutex<BaseCache>> = Lazy::new(|| Mutex::new(HashMap::new()));
+
+/// Upper bound of cached bases, the cache is emptied once it is reached
+const INI_BASE_CACHE_CAPACITY: usize = 64;
+
+/// Parse an INI style base with a reader from `new_reader`, reusing the result
+/// of an earlier request for the same `target` and base text
+///
+/// The error is the reader's last error, failed parses are not cached.
+pub fn parse_ini_base(
+    target: &'static str,
+    base_conf: &str,
+    new_reader: fn() -> IniReader,
+) -> Result<IniReader, String> {
+    let key = (target, md5(base_conf));
+    if let Some(ini) = lock_recovering(&INI_BASE_CACHE).get(&key) {
+        return Ok(IniReader::clone(ini));
+    }
+
+    let mut ini = new_reader();
+    if ini.parse(base_conf).is_err() {
+        return Err(ini.get_last_error());
+    }
+
+    let mut cache = lock_recovering(&INI_BASE_CACHE);
+    if cache.len() >= INI_BASE_CACHE_CAPACITY {
+        cache.clear();
+    }
+    cache.insert(key, Arc::new(ini.clone()));
+    Ok(ini)
+}
+
+/// Drop every parsed INI base, called when the server configuration is reloaded
+pub fn clear_ini_base_cache() {
+    lock_recovering(&INI_BASE_CACHE).clear();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn reader() -> IniReader {
+        let mut ini = IniReader::new();
+        ini.store_any_line = true;
+        ini.add_direct_save_section("General");
+        ini
+    }
+
+    #[test]
+    fn test_parsed_base_is_reused_per_target_and_content() {
+        let base = "[General]\nloglevel = notify\n[Proxy]\nDIRECT = direct\n";
+        let mut first = parse_ini_base("test", base, reader).unwrap();
+        assert_eq!(first.get("General", "{NONAME}"), "loglevel = notify");
+
+        // Changes to a copy never reach the cached base
+        first.set_current_section("Proxy");
+        first.erase_section();
+        let second = parse_ini_base("test", base, reader).unwrap();
+        assert_eq!(second.get("Proxy", "DIRECT"), "direct");
+        assert!(lock_recovering(&INI_BASE_CACHE).contains_key(&("test", md5(base))));
+
+        let edited = parse_ini_base("test", &base.replace("notify", "info"), reader).unwrap();
+        assert_eq!(edited.get("General", "{NONAME}"), "loglevel = info");
+
+        assert!(parse_ini_base("test", "", reader).is_err());
+        assert!(!lock_recovering(&INI_BASE_CACHE).contains_key(&("test", md5(""))));
+    }
+}
@@ -1,15 +1,29 @@
+use crate::generator::config::formats::base_cache::parse_ini_base;
 use crate::generator::config::group::group_generate;
-use crate::generator::config::remark::process_remark;
+use crate::generator::config::remark::{process_remark, AppendTypeFormat};
+use crate::generator::exports::ExportError;
 use crate::generator::ruleconvert::ruleset_to_surge::ruleset_to_surge;
 use crate::models::{
     BalanceStrategy, ExtraSettings, Proxy, ProxyGroupConfigs, ProxyGroupType, ProxyType,
     RulesetContent,
 };
 use crate::utils::ini_reader::IniReader;
+use crate::utils::network::join_host_port;
 use crate::utils::string::join;
-use log::error;
+use crate::utils::tribool::TriboolExt;
+use crate::Settings;
+use log::{error, warn};
 use std::collections::HashMap;
 
+/// INI reader set up for Loon bases
+fn loon_reader() -> IniReader {
+    let mut ini = IniReader::new();
+    ini.store_any_line = true;
+    ini.strip_inline_comments = false;
+    ini.add_direct_save_section("Plugin");
+    ini
+}
+
 /// Convert proxies to Loon format
 ///
 /// This function converts a list of proxies to the Loon configuration format,
@@ -24,46 +38,54 @@ use std::collections::HashMap;
 ///
 /// # Returns
 /// * Converted configuration as a string
+///
+/// # Errors
+/// Returns [`ExportError::InvalidBase`] if the base config cannot be parsed
 pub async fn proxy_to_loon(
     nodes: &mut Vec<Proxy>,
     base_conf: &str,
     ruleset_content_array: &mut Vec<RulesetContent>,
     extra_proxy_group: &ProxyGroupConfigs,
     ext: &mut ExtraSettings,
-) -> String {
-    let mut ini = IniReader::new();
+) -> Result<String, ExportError> {
     let mut output_nodelist = String::new();
     let mut nodelist = Vec::new();
     let mut remarks_list = Vec::new();
 
-    // Configure INI reader
-    ini.store_any_line = true;
-    ini.add_direct_save_section("Plugin");
-
     // Parse base configuration
-    if ini.parse(base_conf).is_err() && !ext.nodelist {
-        error!(
-            "Loon base loader failed with error: {}",
-            ini.get_last_error()
-        );
-        return String::new();
-    }
+    let mut ini = match parse_ini_base("loon", base_conf, loon_reader) {
+        Ok(ini) => ini,
+        Err(_) if ext.nodelist => loon_reader(),
+        Err(e) => {
+            error!("Loon base loader failed with error: {}", e);
+            return Err(ExportError::InvalidBase(e));
+        }
+    };
 
     // Prepare Proxy section
     ini.set_current_section("Proxy");
     ini.erase_section();
 
     // Process each proxy node
+    let type_format = ext
+        .append_proxy_type
+        .then(|| AppendTypeFormat::new(&ext.append_type_format));
     for node in nodes.iter_mut() {
         // Add proxy type prefix if enabled
-        if ext.append_proxy_type {
-            let proxy_type = node.proxy_type.to_string();
-            node.remark = format!("[{}] {}", proxy_type, node.remark);
+        if let Some(format) = &type_format {
+            node.remark = format.apply(node);
         }
 
         // Process remark
         let mut remark = node.remark.clone();
-        process_remark(&mut remark, &remarks_list, false);
+        process_remark(
+            &mut remark,
+            &remarks_list,
+            false,
+            &ext.duplicate_remark_format,
+        );
+        // Groups are resolved against the names written out
+        node.remark = remark.clone();
 
         // Extract node properties for easier access
         let hostname = &node.hostname;
@@ -83,9 +105,9 @@ pub async fn proxy_to_loon(
         let pluginopts = node.plugin_option.as_deref().unwrap_or("");
         let tls_secure = node.tls_secure;
 
-        // Define tribool values with defaults from ext and override with node-specific values
+        // Values passed with the request override the node, otherwise keep what the node has
         let scv = ext.skip_cert_verify;
-        let scv = node.allow_insecure.as_ref().map_or(scv, |val| Some(*val));
+        let scv = scv.define(node.allow_insecure);
 
         let mut proxy;
 
@@ -125,7 +147,9 @@ pub async fn proxy_to_loon(
                 );
 
                 if tls_secure {
-                    proxy.push_str(&format!(",tls-name={}", host));
+                    if let Some(tls_name) = node.tls_server_name() {
+                        proxy.push_str(&format!(",tls-name={}", tls_name));
+                    }
                 }
 
                 match transproto {
@@ -195,8 +219,8 @@ pub async fn proxy_to_loon(
             ProxyType::Trojan => {
                 proxy = format!("trojan,{},{},\"{}\"", hostname, port, password);
 
-                if !host.is_empty() {
-                    proxy.push_str(&format!(",tls-name={}", host));
+                if let Some(tls_name) = node.tls_server_name() {
+                    proxy.push_str(&format!(",tls-name={}", tls_name));
                 }
 
                 if scv.is_some() {
@@ -280,12 +304,12 @@ pub async fn proxy_to_loon(
         }
 
         // Add fast-open option if enabled
-        if ext.tfo.unwrap_or(false) {
+        if ext.tfo.define(node.tcp_fast_open).unwrap_or(false) {
             proxy.push_str(",fast-open=true");
         }
 
         // Add UDP option if enabled
-        if ext.udp.unwrap_or(false) {
+        if ext.udp.define(node.udp).unwrap_or(false) {
             proxy.push_str(",udp=true");
         }
 
@@ -296,12 +320,12 @@ pub async fn proxy_to_loon(
             ini.set_current("{NONAME}", &format!("{} = {}", remark, proxy))
                 .unwrap_or(());
             nodelist.push(node.clone());
-            remarks_list.push(remark);
         }
+        remarks_list.push(remark);
     }
 
     if ext.nodelist {
-        return output_nodelist;
+        return Ok(output_nodelist);
     }
 
     // Process proxy groups
@@ -314,6 +338,13 @@ pub async fn proxy_to_loon(
         let mut group_str;
 
         match group.group_type {
+            ProxyGroupType::Relay => {
+                warn!(
+                    "Skipping relay group {}: Loon has no equivalent",
+                    group.name
+                );
+                continue;
+            }
             ProxyGroupType::Select
             | ProxyGroupType::LoadBalance
             | ProxyGroupType::URLTest
@@ -335,7 +366,14 @@ pub async fn proxy_to_loon(
 
         // Generate filtered proxy list
         for proxy_name in &group.proxies {
-            group_generate(proxy_name, &nodelist, &mut filtered_nodelist, true, ext);
+            group_generate(
+                proxy_name,
+                &nodelist,
+                &group.group_type,
+                &mut filtered_nodelist,
+                true,
+                ext,
+            );
         }
 
         if filtered_nodelist.is_empty() {
@@ -374,16 +412,16 @@ pub async fn proxy_to_loon(
                 group_str.push_str(&format!(",algorithm={}", algorithm));
 
                 if group.timeout > 0 {
-                    group_str.push_str(&format!(",max-timeout={}", group.timeout));
+                    group_str.push_str(&format!(",max-timeout={}", group.timeout * 1000));
                 }
             } else if group.group_type == ProxyGroupType::URLTest {
                 // For URL-Test, add tolerance
                 if group.tolerance > 0 {
                     group_str.push_str(&format!(",tolerance={}", group.tolerance));
                 }
-            } else if group.group_type == ProxyGroupType::Fallback {
-                // For Fallback, add max-timeout
-                group_str.push_str(&format!(",max-timeout={}", group.timeout));
+            } else if group.group_type == ProxyGroupType::Fallback && group.timeout > 0 {
+                // Loon takes the timeout in milliseconds
+                group_str.push_str(&format!(",max-timeout={}", group.timeout * 1000));
             }
         }
 
@@ -402,12 +440,45 @@ pub async fn proxy_to_loon(
             &mut ini,
             ruleset_content_array,
             ext.overwrite_original_rules,
-            &ext.managed_config_prefix,
+            ext.self_url_prefix(),
+            &ext.settings,
         )
         .await;
     }
 
-    ini.to_string()
+    if ext.loon_plugin {
+        return Ok(loon_plugin(ini));
+    }
+
+    Ok(ini.to_string())
+}
+
+/// Turn a generated profile into a plugin keeping its rules, `FINAL` is left
+/// to the profile since plugin rules come before its own
+fn loon_plugin(mut ini: IniReader) -> String {
+    let sections = ini.get_section_names().to_vec();
+    for section in sections
+        .iter()
+        .filter(|section| !["Rule", "Remote Rule"].contains(&section.as_str()))
+    {
+        ini.remove_section(section);
+    }
+
+    if let Ok(items) = ini.get_items("Rule") {
+        ini.erase_section_by_name("Rule");
+        for (key, value) in items
+            .iter()
+            .filter(|(key, value)| key != "{NONAME}" || !value.starts_with("FINAL"))
+        {
+            ini.set("Rule", key, value).unwrap_or(());
+        }
+    }
+
+    ini.sort_sections(&["Rule", "Remote Rule"]);
+    format!(
+        "#!name=subconverter\n#!desc=Rules generated by subconverter, policies come from the profile\n\n{}",
+        ini.to_string()
+    )
 }
 
 /// Generate a WireGuard peer configuration string
@@ -425,7 +496,10 @@ fn generate_peer(node: &Proxy, client_id_as_reserved: bool) -> String {
         peer.push_str(&format!("public-key={}", public_key));
     }
 
-    peer.push_str(&format!(", endpoint={}:{}", node.hostname, node.port));
+    peer.push_str(&format!(
+        ", endpoint={}",
+        join_host_port(&node.hostname, node.port)
+    ));
 
     if !node.allowed_ips.is_empty() {
         peer.push_str(&format!(", allowed-ips={}", node.allowed_ips));
@@ -449,6 +523,7 @@ async fn ruleset_to_loon(
     ruleset_content_array: &mut Vec<RulesetContent>,
     overwrite_original_rules: bool,
     managed_config_prefix: &str,
+    settings: &Settings,
 ) {
     ruleset_to_surge(
         ini,
@@ -456,6 +531,85 @@ async fn ruleset_to_loon(
         -4,
         overwrite_original_rules,
         managed_config_prefix,
+        settings,
     )
     .await;
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::RulesetType;
+
+    fn trojan_node(remark: &str) -> Proxy {
+        Proxy {
+            proxy_type: ProxyType::Trojan,
+            remark: remark.to_string(),
+            hostname: "example.com".to_string(),
+            port: 443,
+            password: Some("secret".to_string()),
+            ..Default::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn test_nodelist_emits_node_lines_only() {
+        let mut nodes = vec![trojan_node("HK"), trojan_node("JP")];
+        let mut ext = ExtraSettings {
+            nodelist: true,
+            ..Default::default()
+        };
+
+        let output = proxy_to_loon(&mut nodes, "", &mut Vec::new(), &vec![], &mut ext)
+            .await
+            .unwrap();
+
+        let lines: Vec<&str> = output.lines().collect();
+        assert_eq!(lines.len(), 2);
+        assert!(lines[0].starts_with("HK = trojan,example.com,443"));
+        assert!(lines[1].starts_with("JP = trojan,example.com,443"));
+    }
+
+    #[tokio::test]
+    async fn test_plugin_keeps_rules_with_policies() {
+        let mut nodes = vec![trojan_node("HK")];
+        let mut clash = RulesetContent::new("https://example.com/ads.yaml", "AdBlock");
+        clash.rule_type = RulesetType::ClashDomain;
+        let mut last = RulesetContent::new("", "DIRECT");
+        last.set_rule_content("[]FINAL");
+        let mut rulesets = vec![
+            RulesetContent::new("https://example.com/apple.list", "Apple"),
+            clash,
+            last,
+        ];
+        let mut ext = ExtraSettings {
+            enable_rule_generator: true,
+            managed_config_prefix: "https://sub.example.com".to_string(),
+            loon_plugin: true,
+            ..Default::default()
+        };
+
+        let output = proxy_to_loon(
+            &mut nodes,
+            "[General]\nskip-proxy = 192.168.0.0/16\n[Rule]\nDOMAIN,example.com,DIRECT\nFINAL,DIRECT\n",
+            &mut rulesets,
+            &vec![],
+            &mut ext,
+        )
+        .await
+        .unwrap();
+
+        assert!(output.starts_with("#!name=subconverter\n#!desc="));
+        let headers: Vec<&str> = output
+            .lines()
+            .filter(|line| line.starts_with('['))
+            .collect();
+        assert_eq!(headers, ["[Rule]", "[Remote Rule]"]);
+        assert!(output.contains("DOMAIN,example.com,DIRECT\n"));
+        assert!(!output.contains("FINAL"));
+        assert!(output
+            .contains("https://example.com/apple.list, policy=Apple, tag=Apple, enabled=true\n"));
+        assert!(output.contains("https://sub.example.com/getruleset?type=1&url="));
+        assert!(output.contains(", policy=AdBlock, tag=AdBlock, enabled=true\n"));
+    }
+}
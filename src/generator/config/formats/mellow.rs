@@ -1,5 +1,7 @@
+use crate::generator::config::formats::base_cache::parse_ini_base;
 use crate::generator::config::group::group_generate;
-use crate::generator::config::remark::process_remark;
+use crate::generator::config::remark::{process_remark, AppendTypeFormat};
+use crate::generator::exports::ExportError;
 use crate::generator::ruleconvert::ruleset_to_surge::ruleset_to_surge;
 use crate::models::{
     ExtraSettings, Proxy, ProxyGroupConfigs, ProxyGroupType, ProxyType, RulesetContent,
@@ -7,9 +9,17 @@ use crate::models::{
 use crate::utils::base64::url_safe_base64_encode;
 use crate::utils::ini_reader::IniReader;
 use crate::utils::string::{hash, join};
-use crate::utils::tribool::BoolTriboolExt;
+use crate::utils::tribool::{BoolTriboolExt, TriboolExt};
 use crate::utils::url::url_encode;
-use log::error;
+use log::{error, warn};
+
+/// INI reader set up for Mellow bases
+fn mellow_reader() -> IniReader {
+    let mut ini = IniReader::new();
+    ini.store_any_line = true;
+    ini.strip_inline_comments = false;
+    ini
+}
 
 /// Convert proxies to Mellow format (main entry point)
 ///
@@ -25,24 +35,24 @@ use log::error;
 ///
 /// # Returns
 /// * Mellow configuration as a string
+///
+/// # Errors
+/// Returns [`ExportError::InvalidBase`] if the base config cannot be parsed
 pub async fn proxy_to_mellow(
     nodes: &mut Vec<Proxy>,
     base_conf: &str,
     ruleset_content_array: &mut Vec<RulesetContent>,
     extra_proxy_group: &ProxyGroupConfigs,
     ext: &mut ExtraSettings,
-) -> String {
-    let mut ini = IniReader::new();
-    ini.store_any_line = true;
-
+) -> Result<String, ExportError> {
     // Parse base configuration
-    if ini.parse(base_conf).is_err() {
-        error!(
-            "Mellow base loader failed with error: {}",
-            ini.get_last_error()
-        );
-        return String::new();
-    }
+    let mut ini = match parse_ini_base("mellow", base_conf, mellow_reader) {
+        Ok(ini) => ini,
+        Err(e) => {
+            error!("Mellow base loader failed with error: {}", e);
+            return Err(ExportError::InvalidBase(e));
+        }
+    };
 
     // Process nodes and rules
     proxy_to_mellow_internal(
@@ -55,7 +65,7 @@ pub async fn proxy_to_mellow(
     .await;
 
     // Return the INI as a string
-    ini.to_string()
+    Ok(ini.to_string())
 }
 
 /// Internal function for converting proxies to Mellow format
@@ -82,16 +92,23 @@ async fn proxy_to_mellow_internal(
     ini.set_current_section("Endpoint");
 
     // Process each proxy node
+    let type_format = ext
+        .append_proxy_type
+        .then(|| AppendTypeFormat::new(&ext.append_type_format));
     for node in nodes {
         // Add proxy type prefix if enabled
-        if ext.append_proxy_type {
-            let proxy_type = node.proxy_type.to_string();
-            node.remark = format!("[{}] {}", proxy_type, node.remark);
+        if let Some(format) = &type_format {
+            node.remark = format.apply(node);
         }
 
         // Process remark
         let mut remark = node.remark.clone();
-        process_remark(&mut remark, &remarks_list, true);
+        process_remark(
+            &mut remark,
+            &remarks_list,
+            true,
+            &ext.duplicate_remark_format,
+        );
         node.remark = remark;
 
         // Extract node properties for easier access
@@ -113,9 +130,9 @@ async fn proxy_to_mellow_internal(
         let mut tfo = ext.tfo;
         let mut scv = ext.skip_cert_verify;
 
-        // Override with node-specific values if present
-        tfo = node.tcp_fast_open.as_ref().map_or(tfo, |val| Some(*val));
-        scv = node.allow_insecure.as_ref().map_or(scv, |val| Some(*val));
+        // Fall back to node-specific values when the request leaves them unset
+        tfo = tfo.define(node.tcp_fast_open);
+        scv = scv.define(node.allow_insecure);
 
         let mut _proxy_str: String = String::new();
 
@@ -233,16 +250,37 @@ async fn proxy_to_mellow_internal(
     for group in extra_proxy_group {
         // Only process certain group types
         match group.group_type {
+            ProxyGroupType::Relay => {
+                warn!(
+                    "Skipping relay group {}: Mellow has no equivalent",
+                    group.name
+                );
+                continue;
+            }
             ProxyGroupType::Select
             | ProxyGroupType::URLTest
             | ProxyGroupType::Fallback
             | ProxyGroupType::LoadBalance => {
+                if group.group_type != ProxyGroupType::URLTest {
+                    warn!(
+                        "Converting {} group {} to latency: Mellow has no equivalent",
+                        group.type_str(),
+                        group.name
+                    );
+                }
                 // Generate node list
                 let mut filtered_nodelist = Vec::new();
 
                 // Process each proxy in the group
                 for proxy_name in &group.proxies {
-                    group_generate(proxy_name, &nodelist, &mut filtered_nodelist, false, ext);
+                    group_generate(
+                        proxy_name,
+                        &nodelist,
+                        &group.group_type,
+                        &mut filtered_nodelist,
+                        false,
+                        ext,
+                    );
                 }
 
                 // Use default if filtered list is empty
@@ -276,6 +314,7 @@ async fn proxy_to_mellow_internal(
             0,
             ext.overwrite_original_rules,
             "",
+            &ext.settings,
         )
         .await;
     }
@@ -1,9 +1,140 @@
+pub mod base_cache;
 pub mod loon;
 pub mod mellow;
 pub mod quan;
 pub mod quanx;
+pub mod remote_base;
 pub mod singbox;
 pub mod single;
 pub mod ss_sub;
 pub mod ssd;
 pub mod surge;
+
+#[cfg(test)]
+mod tests {
+    use crate::models::{
+        ExtraSettings, Proxy, ProxyGroupConfig, ProxyGroupConfigs, ProxyGroupType, ProxyType,
+    };
+    use crate::utils::base64::url_safe_base64_decode;
+
+    fn nodes() -> Vec<Proxy> {
+        ["HK", "JP"]
+            .iter()
+            .map(|remark| Proxy {
+                proxy_type: ProxyType::Shadowsocks,
+                remark: remark.to_string(),
+                hostname: format!("{}.example.com", remark.to_lowercase()),
+                port: 8388,
+                encrypt_method: Some("aes-128-gcm".to_string()),
+                password: Some("secret".to_string()),
+                ..Default::default()
+            })
+            .collect()
+    }
+
+    fn fallback_group() -> ProxyGroupConfigs {
+        let mut group = ProxyGroupConfig::new("Auto".to_string(), ProxyGroupType::Fallback);
+        group.proxies = vec![".*".to_string()];
+        group.url = "http://www.gstatic.com/generate_204".to_string();
+        group.interval = 300;
+        group.timeout = 5;
+        group.tolerance = 50;
+        vec![group]
+    }
+
+    fn group_line(output: &str, name: &str) -> String {
+        output
+            .lines()
+            .find(|line| line.contains(name))
+            .unwrap_or_default()
+            .to_string()
+    }
+
+    #[tokio::test]
+    async fn test_fallback_group_per_target() {
+        let groups = fallback_group();
+
+        let output = super::surge::proxy_to_surge(
+            &mut nodes(),
+            "[General]\nloglevel = notify\n",
+            &mut Vec::new(),
+            &groups,
+            4,
+            &mut ExtraSettings::default(),
+        )
+        .await
+        .unwrap();
+        assert_eq!(
+            group_line(&output, "Auto ="),
+            "Auto = fallback,HK,JP,url=http://www.gstatic.com/generate_204,interval=300,timeout=5"
+        );
+
+        let output = super::quanx::proxy_to_quanx(
+            &mut nodes(),
+            "[general]\n",
+            &mut Vec::new(),
+            &groups,
+            &mut ExtraSettings::default(),
+        )
+        .await
+        .unwrap();
+        assert_eq!(
+            group_line(&output, "=Auto"),
+            "available=Auto, HK, JP, check-interval=300"
+        );
+
+        let output = super::loon::proxy_to_loon(
+            &mut nodes(),
+            "[General]\n",
+            &mut Vec::new(),
+            &groups,
+            &mut ExtraSettings::default(),
+        )
+        .await
+        .unwrap();
+        assert_eq!(
+            group_line(&output, "Auto ="),
+            "Auto = fallback,HK,JP,url=http://www.gstatic.com/generate_204,interval=300,max-timeout=5000"
+        );
+
+        let output = super::singbox::proxy_to_singbox(
+            &mut nodes(),
+            "{}",
+            &mut Vec::new(),
+            &groups,
+            &mut ExtraSettings::default(),
+        );
+        // sing-box has no fallback, the closest is urltest
+        let json: serde_json::Value = serde_json::from_str(&output).unwrap();
+        let group = json["outbounds"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .find(|outbound| outbound["tag"] == "Auto")
+            .unwrap();
+        assert_eq!(
+            group,
+            &serde_json::json!({
+                "type": "urltest",
+                "tag": "Auto",
+                "outbounds": ["HK", "JP"],
+                "url": "http://www.gstatic.com/generate_204",
+                "interval": "5m",
+                "tolerance": 50
+            })
+        );
+
+        let output = super::quan::proxy_to_quan(
+            &mut nodes(),
+            "[SERVER]\n",
+            &mut Vec::new(),
+            &groups,
+            &mut ExtraSettings::default(),
+        )
+        .await
+        .unwrap();
+        // Quantumult has no fallback either, auto is the closest
+        let policy = output.split("[POLICY]").nth(1).unwrap();
+        assert_eq!(url_safe_base64_decode(policy), "Auto : auto\nHK\nJP\n");
+    }
+}
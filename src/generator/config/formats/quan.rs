@@ -1,5 +1,7 @@
+use crate::generator::config::formats::base_cache::parse_ini_base;
 use crate::generator::config::group::group_generate;
-use crate::generator::config::remark::process_remark;
+use crate::generator::config::remark::{process_remark, AppendTypeFormat};
+use crate::generator::exports::ExportError;
 use crate::generator::ruleconvert::ruleset_to_surge::ruleset_to_surge;
 use crate::models::{
     ExtraSettings, Proxy, ProxyGroupConfigs, ProxyGroupType, ProxyType, RulesetContent,
@@ -8,9 +10,17 @@ use crate::utils::base64::{base64_encode, url_safe_base64_encode};
 use crate::utils::ini_reader::IniReader;
 use crate::utils::regexp::reg_get_match;
 use crate::utils::string::{join, replace_all_distinct, trim_of};
-use crate::utils::tribool::BoolTriboolExt;
+use crate::utils::tribool::{BoolTriboolExt, TriboolExt};
 use crate::utils::url::url_encode;
-use log::error;
+use log::{error, warn};
+
+/// INI reader set up for Quantumult bases
+fn quan_reader() -> IniReader {
+    let mut ini = IniReader::new();
+    ini.store_any_line = true;
+    ini.strip_inline_comments = false;
+    ini
+}
 
 /// Convert proxies to Quantumult format (main entry point)
 ///
@@ -26,24 +36,28 @@ use log::error;
 ///
 /// # Returns
 /// * Quantumult configuration as a string
+///
+/// # Errors
+/// Returns [`ExportError::InvalidBase`] if the base config cannot be parsed
 pub async fn proxy_to_quan(
     nodes: &mut Vec<Proxy>,
     base_conf: &str,
     ruleset_content_array: &mut Vec<RulesetContent>,
     extra_proxy_group: &ProxyGroupConfigs,
     ext: &mut ExtraSettings,
-) -> String {
-    let mut ini = IniReader::new();
-    ini.store_any_line = true;
-
+) -> Result<String, ExportError> {
     // Parse base configuration if not in nodelist mode
-    if !ext.nodelist && ini.parse(base_conf).is_err() {
-        error!(
-            "Quantumult base loader failed with error: {}",
-            ini.get_last_error()
-        );
-        return String::new();
-    }
+    let mut ini = if ext.nodelist {
+        quan_reader()
+    } else {
+        match parse_ini_base("quan", base_conf, quan_reader) {
+            Ok(ini) => ini,
+            Err(e) => {
+                error!("Quantumult base loader failed with error: {}", e);
+                return Err(ExportError::InvalidBase(e));
+            }
+        }
+    };
 
     // Process nodes and rules
     proxy_to_quan_internal(
@@ -64,13 +78,13 @@ pub async fn proxy_to_quan(
 
         if !all_nodes.is_empty() {
             let all_links = join(&all_nodes, "\n");
-            return base64_encode(&all_links);
+            return Ok(base64_encode(&all_links));
         }
 
-        return String::new();
+        return Ok(String::new());
     }
 
-    ini.to_string()
+    Ok(ini.to_string())
 }
 
 /// Internal function for converting proxies to Quantumult format
@@ -98,16 +112,23 @@ async fn proxy_to_quan_internal(
     ini.erase_section();
 
     // Process each proxy node
+    let type_format = ext
+        .append_proxy_type
+        .then(|| AppendTypeFormat::new(&ext.append_type_format));
     for node in nodes {
         // Add proxy type prefix if enabled
-        if ext.append_proxy_type {
-            let proxy_type = node.proxy_type.to_string();
-            node.remark = format!("[{}] {}", proxy_type, node.remark);
+        if let Some(format) = &type_format {
+            node.remark = format.apply(node);
         }
 
         // Process remark
         let mut remark = node.remark.clone();
-        process_remark(&mut remark, &remarks_list, false);
+        process_remark(
+            &mut remark,
+            &remarks_list,
+            false,
+            &ext.duplicate_remark_format,
+        );
         node.remark = remark;
 
         // Extract node properties for easier access
@@ -130,9 +151,9 @@ async fn proxy_to_quan_internal(
         let tls_secure = node.tls_secure;
         let group = node.group.as_ref();
 
-        // Define skip_cert_verify with default from ext and override with node-specific value
+        // skip_cert_verify passed with the request overrides the node-specific value
         let mut scv = ext.skip_cert_verify;
-        scv = node.allow_insecure.as_ref().map_or(scv, |val| Some(*val));
+        scv = scv.define(node.allow_insecure);
 
         let mut proxy_str;
 
@@ -334,10 +355,24 @@ async fn proxy_to_quan_internal(
 
         // Determine group type and format accordingly
         match group.group_type {
-            ProxyGroupType::Select | ProxyGroupType::Fallback => {
+            ProxyGroupType::Relay => {
+                warn!(
+                    "Skipping relay group {}: Quantumult has no equivalent",
+                    group.name
+                );
+                continue;
+            }
+            ProxyGroupType::Select => {
                 // Process as static type
                 for proxy_name in &group.proxies {
-                    group_generate(proxy_name, &nodelist, &mut filtered_nodelist, true, ext);
+                    group_generate(
+                        proxy_name,
+                        &nodelist,
+                        &group.group_type,
+                        &mut filtered_nodelist,
+                        true,
+                        ext,
+                    );
                 }
 
                 if filtered_nodelist.is_empty() {
@@ -349,10 +384,23 @@ async fn proxy_to_quan_internal(
                 single_group = format!("{} : static, {}", group.name, filtered_nodelist[0]);
                 single_group.push_str(&format!("\n{}\n", proxies));
             }
-            ProxyGroupType::URLTest => {
+            ProxyGroupType::URLTest | ProxyGroupType::Fallback => {
+                if group.group_type == ProxyGroupType::Fallback {
+                    warn!(
+                        "Converting fallback group {} to auto: Quantumult has no equivalent",
+                        group.name
+                    );
+                }
                 // Process as auto type
                 for proxy_name in &group.proxies {
-                    group_generate(proxy_name, &nodelist, &mut filtered_nodelist, true, ext);
+                    group_generate(
+                        proxy_name,
+                        &nodelist,
+                        &group.group_type,
+                        &mut filtered_nodelist,
+                        true,
+                        ext,
+                    );
                 }
 
                 if filtered_nodelist.is_empty() {
@@ -373,7 +421,14 @@ async fn proxy_to_quan_internal(
             ProxyGroupType::LoadBalance => {
                 // Process as balance type
                 for proxy_name in &group.proxies {
-                    group_generate(proxy_name, &nodelist, &mut filtered_nodelist, true, ext);
+                    group_generate(
+                        proxy_name,
+                        &nodelist,
+                        &group.group_type,
+                        &mut filtered_nodelist,
+                        true,
+                        ext,
+                    );
                 }
 
                 if filtered_nodelist.is_empty() {
@@ -453,6 +508,7 @@ async fn proxy_to_quan_internal(
             -2,
             ext.overwrite_original_rules,
             "",
+            &ext.settings,
         )
         .await;
     }
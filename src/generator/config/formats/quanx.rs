@@ -1,14 +1,35 @@
+use crate::generator::config::formats::base_cache::parse_ini_base;
 use crate::generator::config::group::group_generate;
-use crate::generator::config::remark::process_remark;
+use crate::generator::config::remark::{process_remark, AppendTypeFormat};
+use crate::generator::exports::ExportError;
 use crate::generator::ruleconvert::ruleset_to_surge::ruleset_to_surge;
 use crate::models::{
     ExtraSettings, Proxy, ProxyGroupConfigs, ProxyGroupType, ProxyType, RulesetContent,
 };
 use crate::utils::ini_reader::IniReader;
+use crate::utils::network::bracket_ipv6;
 use crate::utils::string::{hash, join, replace_all_distinct, trim};
-use crate::utils::tribool::BoolTriboolExt;
+use crate::utils::tribool::{BoolTriboolExt, TriboolExt};
 use crate::utils::url::get_url_arg;
-use log::error;
+use log::{error, warn};
+use std::collections::HashSet;
+
+/// INI reader set up for QuantumultX bases
+fn quanx_reader() -> IniReader {
+    let mut ini = IniReader::new();
+    ini.store_any_line = true;
+    ini.strip_inline_comments = false;
+
+    // Add direct save sections
+    ini.add_direct_save_section("general");
+    ini.add_direct_save_section("dns");
+    ini.add_direct_save_section("rewrite_remote");
+    ini.add_direct_save_section("rewrite_local");
+    ini.add_direct_save_section("task_local");
+    ini.add_direct_save_section("mitm");
+    ini.add_direct_save_section("server_remote");
+    ini
+}
 
 /// Convert proxies to QuantumultX format (main entry point)
 ///
@@ -24,33 +45,28 @@ use log::error;
 ///
 /// # Returns
 /// * QuantumultX configuration as a string
+///
+/// # Errors
+/// Returns [`ExportError::InvalidBase`] if the base config cannot be parsed
 pub async fn proxy_to_quanx(
     nodes: &mut Vec<Proxy>,
     base_conf: &str,
     ruleset_content_array: &mut Vec<RulesetContent>,
     extra_proxy_group: &ProxyGroupConfigs,
     ext: &mut ExtraSettings,
-) -> String {
-    let mut ini = IniReader::new();
-    ini.store_any_line = true;
-
-    // Add direct save sections
-    ini.add_direct_save_section("general");
-    ini.add_direct_save_section("dns");
-    ini.add_direct_save_section("rewrite_remote");
-    ini.add_direct_save_section("rewrite_local");
-    ini.add_direct_save_section("task_local");
-    ini.add_direct_save_section("mitm");
-    ini.add_direct_save_section("server_remote");
-
+) -> Result<String, ExportError> {
     // Parse base configuration if not in nodelist mode
-    if !ext.nodelist && ini.parse(base_conf).is_err() {
-        error!(
-            "QuantumultX base loader failed with error: {}",
-            ini.get_last_error()
-        );
-        return String::new();
-    }
+    let mut ini = if ext.nodelist {
+        quanx_reader()
+    } else {
+        match parse_ini_base("quanx", base_conf, quanx_reader) {
+            Ok(ini) => ini,
+            Err(e) => {
+                error!("QuantumultX base loader failed with error: {}", e);
+                return Err(ExportError::InvalidBase(e));
+            }
+        }
+    };
 
     // Process nodes and rules
     proxy_to_quanx_internal(
@@ -70,13 +86,13 @@ pub async fn proxy_to_quanx(
         }
 
         if !all_nodes.is_empty() {
-            return join(&all_nodes, "\n");
+            return Ok(join(&all_nodes, "\n"));
         }
 
-        return String::new();
+        return Ok(String::new());
     }
 
-    ini.to_string()
+    Ok(ini.to_string())
 }
 
 /// Internal function for converting proxies to QuantumultX format
@@ -104,20 +120,27 @@ async fn proxy_to_quanx_internal(
     ini.erase_section();
 
     // Process each proxy node
+    let type_format = ext
+        .append_proxy_type
+        .then(|| AppendTypeFormat::new(&ext.append_type_format));
     for node in nodes {
         // Add proxy type prefix if enabled
-        if ext.append_proxy_type {
-            let proxy_type = node.proxy_type.to_string();
-            node.remark = format!("[{}] {}", proxy_type, node.remark);
+        if let Some(format) = &type_format {
+            node.remark = format.apply(node);
         }
 
         // Process remark
         let mut remark = node.remark.clone();
-        process_remark(&mut remark, &remarks_list, false);
+        process_remark(
+            &mut remark,
+            &remarks_list,
+            false,
+            &ext.duplicate_remark_format,
+        );
         node.remark = remark;
 
-        // Extract node properties for easier access
-        let hostname = &node.hostname;
+        // Extract node properties for easier access, QuanX writes `host:port`
+        let hostname = bracket_ipv6(&node.hostname);
         let port = node.port.to_string();
         let username = node.username.as_deref().unwrap_or("");
         let password = node.password.as_deref().unwrap_or("");
@@ -140,11 +163,11 @@ async fn proxy_to_quanx_internal(
         let mut scv = ext.skip_cert_verify;
         let mut tls13 = ext.tls13;
 
-        // Override with node-specific values if present
-        udp = node.udp.as_ref().map_or(udp, |val| Some(*val));
-        tfo = node.tcp_fast_open.as_ref().map_or(tfo, |val| Some(*val));
-        scv = node.allow_insecure.as_ref().map_or(scv, |val| Some(*val));
-        tls13 = node.tls13.as_ref().map_or(tls13, |val| Some(*val));
+        // Fall back to node-specific values when the request leaves them unset
+        udp = udp.define(node.udp);
+        tfo = tfo.define(node.tcp_fast_open);
+        scv = scv.define(node.allow_insecure);
+        tls13 = tls13.define(node.tls13);
 
         let mut _proxy_str = String::new();
 
@@ -296,7 +319,10 @@ async fn proxy_to_quanx_internal(
                 _proxy_str = format!("trojan = {}:{}, password={}", hostname, port, password);
 
                 if tls_secure {
-                    _proxy_str.push_str(&format!(", over-tls=true, tls-host={}", host));
+                    _proxy_str.push_str(&format!(
+                        ", over-tls=true, tls-host={}",
+                        node.tls_server_name().unwrap_or("")
+                    ));
                     if !tls13.is_undef() {
                         _proxy_str.push_str(&format!(
                             ", tls13={}",
@@ -405,6 +431,13 @@ async fn proxy_to_quanx_internal(
 
         // Determine group type
         match group.group_type {
+            ProxyGroupType::Relay => {
+                warn!(
+                    "Skipping relay group {}: Quantumult X has no equivalent",
+                    group.name
+                );
+                continue;
+            }
             ProxyGroupType::Select => {
                 _type_str = "static".to_string();
             }
@@ -430,8 +463,35 @@ async fn proxy_to_quanx_internal(
 
         // Generate node list for non-SSID groups
         if group.group_type != ProxyGroupType::SSID {
-            for proxy_name in &group.proxies {
-                group_generate(proxy_name, &nodelist, &mut filtered_nodelist, true, ext);
+            // Nodes of `server_remote` resources are only known to the client,
+            // rules matching them become tag filters
+            let (tag_rules, node_rules): (Vec<&String>, Vec<&String>) =
+                if ext.proxy_providers.is_empty() || group.using_provider.is_empty() {
+                    (Vec::new(), group.proxies.iter().collect())
+                } else {
+                    group
+                        .proxies
+                        .iter()
+                        .partition(|rule| !rule.starts_with("[]"))
+                };
+            for proxy_name in node_rules {
+                group_generate(
+                    proxy_name,
+                    &nodelist,
+                    &group.group_type,
+                    &mut filtered_nodelist,
+                    true,
+                    ext,
+                );
+            }
+            if !tag_rules.is_empty() {
+                filtered_nodelist.push(format!("server-tag-regex={}", join_regexes(&tag_rules)));
+                let resources: Vec<String> = group
+                    .using_provider
+                    .iter()
+                    .map(|name| regex::escape(name))
+                    .collect();
+                filtered_nodelist.push(format!("resource-tag-regex=^({})$", resources.join("|")));
             }
 
             if filtered_nodelist.is_empty() {
@@ -467,11 +527,11 @@ async fn proxy_to_quanx_internal(
         // Create group string
         let mut single_group = format!("{}={}, {}", _type_str, group.name, proxies);
 
-        // Add type-specific options
-        if group.group_type != ProxyGroupType::Select && group.group_type != ProxyGroupType::SSID {
+        // Add type-specific options, `available` only takes the interval
+        if _type_str != "static" && group.group_type != ProxyGroupType::SSID {
             single_group.push_str(&format!(", check-interval={}", group.interval));
 
-            if group.tolerance > 0 {
+            if _type_str != "available" && group.tolerance > 0 {
                 single_group.push_str(&format!(", tolerance={}", group.tolerance));
             }
         }
@@ -487,8 +547,92 @@ async fn proxy_to_quanx_internal(
             ruleset_content_array,
             -1,
             ext.overwrite_original_rules,
-            &ext.managed_config_prefix,
+            ext.self_url_prefix(),
+            &ext.settings,
         )
         .await;
     }
+
+    let server_remote: Vec<String> = ext
+        .proxy_providers
+        .iter()
+        .map(|provider| {
+            let mut line = format!("{}, tag={}", provider.url, provider.name);
+            if provider.interval > 0 {
+                line.push_str(&format!(", update-interval={}", provider.interval));
+            }
+            line.push_str(", opt-parser=false, enabled=true");
+            line
+        })
+        .collect();
+    merge_section(ini, "server_remote", &server_remote, "");
+    merge_section(
+        ini,
+        "rewrite_remote",
+        &ext.quanx_rewrite_remote,
+        &ext.managed_config_prefix,
+    );
+    merge_section(
+        ini,
+        "task_local",
+        &ext.quanx_task_local,
+        &ext.managed_config_prefix,
+    );
+}
+
+/// Regex matching the tags of nodes any of `rules` matches
+fn join_regexes(rules: &[&String]) -> String {
+    match rules {
+        [rule] => rule.to_string(),
+        _ => rules
+            .iter()
+            .map(|rule| format!("(?:{})", rule))
+            .collect::<Vec<_>>()
+            .join("|"),
+    }
+}
+
+/// The resource a line of a QuantumultX resource section loads, the last
+/// word before the first comma, e.g. the script of `0 9 * * * a.js, tag=a`
+fn resource_link(line: &str) -> &str {
+    let first = line.split(',').next().unwrap_or("").trim();
+    first.split_whitespace().last().unwrap_or(first)
+}
+
+/// Append `lines` to `section`, leaving out those loading a resource the
+/// section already has
+///
+/// Links starting with `/` point at this server and are prefixed with
+/// `managed_prefix`, without one such lines are skipped.
+fn merge_section(ini: &mut IniReader, section: &str, lines: &[String], managed_prefix: &str) {
+    if lines.is_empty() {
+        return;
+    }
+    let mut known: HashSet<String> = ini
+        .get_items(section)
+        .unwrap_or_default()
+        .iter()
+        .map(|(_, line)| resource_link(line).to_string())
+        .collect();
+
+    ini.set_current_section(section);
+    for line in lines {
+        let line = line.trim();
+        let link = resource_link(line);
+        let line = if link.starts_with('/') {
+            if managed_prefix.is_empty() {
+                warn!(
+                    "Skipping {} entry '{}': relative links need a managed config prefix",
+                    section, line
+                );
+                continue;
+            }
+            line.replacen(link, &format!("{}{}", managed_prefix, link), 1)
+        } else {
+            line.to_string()
+        };
+        if known.insert(resource_link(&line).to_string()) {
+            ini.set_current("{NONAME}", &line).unwrap_or(());
+        }
+    }
 }
@@ -0,0 +1,270 @@
+//! Base templates fetched over HTTP
+//!
+//! A rule base, or the `base=` of a request, may be a web address such as the
+//! raw URL of a template kept in a Git repository. Fetched templates are kept
+//! by URL for `cache_config` seconds and revalidated with `If-None-Match` and
+//! `If-Modified-Since` afterwards. A template which can not be fetched again
+//! is served from the cache however old it is, only a template which was never
+//! fetched fails the request.
+
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime};
+
+use case_insensitive_string::CaseInsensitiveString;
+use log::{debug, warn};
+use once_cell::sync::Lazy;
+
+use crate::utils::http::{web_get_with_redirects_async, HttpError, HttpResponse, ProxyConfig};
+use crate::utils::lock_recovering;
+use crate::utils::system::safe_system_time;
+
+/// Upper bound of cached templates, the oldest one is dropped once it is
+/// reached
+const REMOTE_BASE_CACHE_CAPACITY: usize = 64;
+
+/// A fetched template with what the server said about its version
+#[derive(Debug, Clone)]
+struct CachedBase {
+    content: String,
+    etag: Option<String>,
+    last_modified: Option<String>,
+    fetched: SystemTime,
+}
+
+impl CachedBase {
+    /// Whether the template was fetched or revalidated less than `ttl` ago
+    fn is_fresh(&self, ttl: u32, now: SystemTime) -> bool {
+        now.duration_since(self.fetched)
+            .is_ok_and(|age| age < Duration::from_secs(u64::from(ttl)))
+    }
+
+    /// The headers asking the server to answer 304 when the template did not
+    /// change
+    fn conditional_headers(&self) -> HashMap<CaseInsensitiveString, String> {
+        let mut headers = HashMap::new();
+        if let Some(etag) = &self.etag {
+            headers.insert(
+                CaseInsensitiveString::new("If-None-Match"),
+                etag.to_string(),
+            );
+        }
+        if let Some(last_modified) = &self.last_modified {
+            headers.insert(
+                CaseInsensitiveString::new("If-Modified-Since"),
+                last_modified.to_string(),
+            );
+        }
+        headers
+    }
+}
+
+static REMOTE_BASES: Lazy<Mutex<HashMap<String, CachedBase>>> = Lazy::new(Default::default);
+
+/// Fetch the base template at `url`, from the cache while it is younger than
+/// `ttl` seconds
+///
+/// # Returns
+/// * `Ok(String)` - The template, possibly a stale copy when the server could
+///   not be reached
+/// * `Err(HttpError)` - The template could not be fetched and was never cached
+pub async fn fetch_remote_base(
+    url: &str,
+    proxy_config: &ProxyConfig,
+    ttl: u32,
+    max_redirects: u8,
+) -> Result<String, HttpError> {
+    let cached = lock_recovering(&REMOTE_BASES).get(url).cloned();
+    if let Some(cached) = cached
+        .as_ref()
+        .filter(|c| c.is_fresh(ttl, safe_system_time()))
+    {
+        debug!("Using cached base template: {}", url);
+        return Ok(cached.content.clone());
+    }
+
+    let headers = cached
+        .as_ref()
+        .map(CachedBase::conditional_headers)
+        .unwrap_or_default();
+    let answer =
+        web_get_with_redirects_async(url, proxy_config, Some(&headers), max_redirects).await;
+    let entry = settle(url, cached, answer, safe_system_time())?;
+    let content = entry.content.clone();
+
+    let mut cache = lock_recovering(&REMOTE_BASES);
+    if cache.len() >= REMOTE_BASE_CACHE_CAPACITY && !cache.contains_key(url) {
+        let oldest = cache
+            .iter()
+            .min_by_key(|(_, entry)| entry.fetched)
+            .map(|(url, _)| url.clone());
+        if let Some(oldest) = oldest {
+            cache.remove(&oldest);
+        }
+    }
+    cache.insert(url.to_string(), entry);
+    Ok(content)
+}
+
+/// The entry to cache after the server answered `answer` for a template
+/// `cached` before
+///
+/// A 304 keeps the cached template, a failure falls back to it.
+fn settle(
+    url: &str,
+    cached: Option<CachedBase>,
+    answer: Result<HttpResponse, HttpError>,
+    now: SystemTime,
+) -> Result<CachedBase, HttpError> {
+    let error = match answer {
+        Ok(response) if response.status == 304 => match cached {
+            Some(cached) => {
+                debug!("Base template not modified: {}", url);
+                return Ok(CachedBase {
+                    fetched: now,
+                    ..cached
+                });
+            }
+            None => HttpError {
+                message: "Not modified, but nothing is cached".to_string(),
+                status: Some(304),
+            },
+        },
+        Ok(response) if (200..300).contains(&response.status) => {
+            debug!("Loaded base template from URL: {}", url);
+            let header = |name: &str| {
+                response
+                    .headers
+                    .iter()
+                    .find(|(key, _)| key.eq_ignore_ascii_case(name))
+                    .map(|(_, value)| value.clone())
+            };
+            return Ok(CachedBase {
+                etag: header("etag"),
+                last_modified: header("last-modified"),
+                content: response.body,
+                fetched: now,
+            });
+        }
+        Ok(response) => HttpError {
+            message: format!("Server answered {}", response.status),
+            status: Some(response.status),
+        },
+        Err(e) => e,
+    };
+
+    match cached {
+        Some(cached) => {
+            warn!(
+                "Failed to fetch base template {}, using the cached copy: {}",
+                url, error
+            );
+            Ok(cached)
+        }
+        None => Err(error),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const URL: &str = "https://raw.example.com/team/templates/clash.yml";
+
+    fn response(status: u16, body: &str, headers: &[(&str, &str)]) -> HttpResponse {
+        HttpResponse {
+            status,
+            body: body.to_string(),
+            headers: headers
+                .iter()
+                .map(|(key, value)| (key.to_string(), value.to_string()))
+                .collect(),
+        }
+    }
+
+    fn at(secs: u64) -> SystemTime {
+        SystemTime::UNIX_EPOCH + Duration::from_secs(secs)
+    }
+
+    #[test]
+    fn test_fetched_template_is_revalidated() {
+        let fetched = settle(
+            URL,
+            None,
+            Ok(response(200, "mode: rule\n", &[("ETag", "\"v1\"")])),
+            at(1_000),
+        )
+        .unwrap();
+        assert_eq!(fetched.content, "mode: rule\n");
+        assert!(fetched.is_fresh(300, at(1_299)));
+        assert!(!fetched.is_fresh(300, at(1_300)));
+        assert!(!fetched.is_fresh(0, at(1_000)));
+
+        let headers = fetched.conditional_headers();
+        assert_eq!(
+            headers.get(&CaseInsensitiveString::new("if-none-match")),
+            Some(&"\"v1\"".to_string())
+        );
+        assert!(!headers.contains_key(&CaseInsensitiveString::new("If-Modified-Since")));
+
+        let revalidated =
+            settle(URL, Some(fetched), Ok(response(304, "", &[])), at(2_000)).unwrap();
+        assert_eq!(revalidated.content, "mode: rule\n");
+        assert_eq!(revalidated.etag.as_deref(), Some("\"v1\""));
+        assert!(revalidated.is_fresh(300, at(2_100)));
+
+        let changed = settle(
+            URL,
+            Some(revalidated),
+            Ok(response(
+                200,
+                "mode: global\n",
+                &[("last-modified", "Wed, 14 Oct 2026 08:00:00 GMT")],
+            )),
+            at(3_000),
+        )
+        .unwrap();
+        assert_eq!(changed.content, "mode: global\n");
+        assert_eq!(changed.etag, None);
+        assert_eq!(
+            changed.last_modified.as_deref(),
+            Some("Wed, 14 Oct 2026 08:00:00 GMT")
+        );
+    }
+
+    #[test]
+    fn test_failed_fetch_falls_back_to_cache() {
+        let cached = settle(URL, None, Ok(response(200, "mode: rule\n", &[])), at(1_000)).unwrap();
+        let timed_out = HttpError {
+            message: "Failed to send request: timeout".to_string(),
+            status: None,
+        };
+        let stale = settle(URL, Some(cached.clone()), Err(timed_out.clone()), at(9_000)).unwrap();
+        assert_eq!(stale.content, "mode: rule\n");
+        // The stale copy is tried again on the next request
+        assert_eq!(stale.fetched, at(1_000));
+
+        let missing = settle(
+            URL,
+            Some(cached),
+            Ok(response(404, "Not Found", &[])),
+            at(9_000),
+        );
+        assert_eq!(missing.unwrap().content, "mode: rule\n");
+
+        assert_eq!(
+            settle(URL, None, Err(timed_out), at(9_000))
+                .unwrap_err()
+                .message,
+            "Failed to send request: timeout"
+        );
+        let error = settle(URL, None, Ok(response(404, "Not Found", &[])), at(9_000)).unwrap_err();
+        assert_eq!(error.status, Some(404));
+        assert_eq!(
+            settle(URL, None, Ok(response(304, "", &[])), at(9_000))
+                .unwrap_err()
+                .status,
+            Some(304)
+        );
+    }
+}
@@ -1,12 +1,12 @@
 use crate::generator::config::group::group_generate;
-use crate::generator::config::remark::process_remark;
+use crate::generator::config::remark::{process_remark, AppendTypeFormat};
 use crate::generator::ruleconvert::ruleset_to_sing_box::ruleset_to_sing_box;
 use crate::models::{
     ExtraSettings, Proxy, ProxyGroupConfigs, ProxyGroupType, ProxyType, RulesetContent,
 };
 use crate::utils::base64::base64_encode;
-use crate::Settings;
-use log::error;
+use crate::utils::tribool::TriboolExt;
+use log::{error, warn};
 use serde_json::{json, Map, Value as JsonValue};
 
 /// Format SingBox interval from seconds
@@ -212,26 +212,33 @@ pub fn proxy_to_singbox(
     }
 
     // Process each proxy node
+    let type_format = ext
+        .append_proxy_type
+        .then(|| AppendTypeFormat::new(&ext.append_type_format));
     for node in nodes.iter_mut() {
         // Add proxy type prefix if enabled
-        if ext.append_proxy_type {
-            let proxy_type = node.proxy_type.to_string();
-            node.remark = format!("[{}] {}", proxy_type, node.remark);
+        if let Some(format) = &type_format {
+            node.remark = format.apply(node);
         }
 
         // Process remark
         let mut remark = node.remark.clone();
-        process_remark(&mut remark, &remarks_list, false);
+        process_remark(
+            &mut remark,
+            &remarks_list,
+            false,
+            &ext.duplicate_remark_format,
+        );
         node.remark = remark;
 
-        // Define tribool values with defaults from ext and override with node-specific values
+        // Values passed with the request override the node, otherwise keep what the node has
         let mut udp = ext.udp;
         let mut tfo = ext.tfo;
         let mut scv = ext.skip_cert_verify;
 
-        udp = node.udp.as_ref().map_or(udp, |val| Some(*val));
-        tfo = node.tcp_fast_open.as_ref().map_or(tfo, |val| Some(*val));
-        scv = node.allow_insecure.as_ref().map_or(scv, |val| Some(*val));
+        udp = udp.define(node.udp);
+        tfo = tfo.define(node.tcp_fast_open);
+        scv = scv.define(node.allow_insecure);
 
         // Create proxy object based on type
         let mut proxy_obj = match node.proxy_type {
@@ -492,14 +499,20 @@ pub fn proxy_to_singbox(
                 let mut tls = Map::new();
                 tls.insert("enabled".to_string(), JsonValue::Bool(true));
 
+                if let Some(server_name) = node.tls_server_name() {
+                    tls.insert(
+                        "server_name".to_string(),
+                        JsonValue::String(server_name.to_string()),
+                    );
+                }
+
                 tls.insert(
                     "insecure".to_string(),
                     JsonValue::Bool(scv.unwrap_or(false)),
                 );
 
-                if !node.alpn.is_empty() && node.alpn.len() > 0 {
-                    let alpn = vec![JsonValue::String(node.alpn.iter().next().unwrap().clone())];
-                    tls.insert("alpn".to_string(), JsonValue::Array(alpn));
+                if let Some(alpn) = &node.alpn {
+                    tls.insert("alpn".to_string(), JsonValue::from(alpn.clone()));
                 }
 
                 if let Some(ca) = &node.ca {
@@ -564,14 +577,20 @@ pub fn proxy_to_singbox(
                 let mut tls = Map::new();
                 tls.insert("enabled".to_string(), JsonValue::Bool(true));
 
+                if let Some(server_name) = node.tls_server_name() {
+                    tls.insert(
+                        "server_name".to_string(),
+                        JsonValue::String(server_name.to_string()),
+                    );
+                }
+
                 tls.insert(
                     "insecure".to_string(),
                     JsonValue::Bool(scv.unwrap_or(false)),
                 );
 
-                if !node.alpn.is_empty() && node.alpn.len() > 0 {
-                    let alpn = vec![JsonValue::String(node.alpn.iter().next().unwrap().clone())];
-                    tls.insert("alpn".to_string(), JsonValue::Array(alpn));
+                if let Some(alpn) = &node.alpn {
+                    tls.insert("alpn".to_string(), JsonValue::from(alpn.clone()));
                 }
 
                 if let Some(ca) = &node.ca {
@@ -629,18 +648,11 @@ pub fn proxy_to_singbox(
             let mut tls = Map::new();
             tls.insert("enabled".to_string(), JsonValue::Bool(true));
 
-            // Set server_name from ServerName or Host
-            if let Some(server_name) = &node.server_name {
-                if !server_name.is_empty() {
-                    tls.insert(
-                        "server_name".to_string(),
-                        JsonValue::String(server_name.clone()),
-                    );
-                }
-            } else if let Some(host) = &node.host {
-                if !host.is_empty() {
-                    tls.insert("server_name".to_string(), JsonValue::String(host.clone()));
-                }
+            if let Some(server_name) = node.tls_server_name() {
+                tls.insert(
+                    "server_name".to_string(),
+                    JsonValue::String(server_name.to_string()),
+                );
             }
 
             // Add insecure option
@@ -649,6 +661,10 @@ pub fn proxy_to_singbox(
                 JsonValue::Bool(scv.unwrap_or(false)),
             );
 
+            if let Some(alpn) = &node.alpn {
+                tls.insert("alpn".to_string(), JsonValue::from(alpn.clone()));
+            }
+
             proxy_obj.insert("tls".to_string(), JsonValue::Object(tls));
         }
 
@@ -684,8 +700,21 @@ pub fn proxy_to_singbox(
 
         // Determine group type
         let group_type = match group.group_type {
+            ProxyGroupType::Relay => {
+                warn!(
+                    "Skipping relay group {}: sing-box has no equivalent",
+                    group.name
+                );
+                continue;
+            }
             ProxyGroupType::Select => "selector",
-            ProxyGroupType::URLTest | ProxyGroupType::Fallback | ProxyGroupType::LoadBalance => {
+            ProxyGroupType::URLTest => "urltest",
+            ProxyGroupType::Fallback | ProxyGroupType::LoadBalance => {
+                warn!(
+                    "Converting {} group {} to urltest: sing-box has no equivalent",
+                    group.type_str(),
+                    group.name
+                );
                 "urltest"
             }
             _ => continue, // Skip unsupported types
@@ -693,7 +722,14 @@ pub fn proxy_to_singbox(
 
         // Generate filtered proxy list
         for proxy_name in &group.proxies {
-            group_generate(proxy_name, &nodelist, &mut filtered_nodelist, true, ext);
+            group_generate(
+                proxy_name,
+                &nodelist,
+                &group.group_type,
+                &mut filtered_nodelist,
+                true,
+                ext,
+            );
         }
 
         // Add DIRECT if empty
@@ -717,8 +753,8 @@ pub fn proxy_to_singbox(
 
         group_obj.insert("outbounds".to_string(), JsonValue::Array(group_outbounds));
 
-        // Add URL Test specific settings
-        if group.group_type == ProxyGroupType::URLTest {
+        // Add URL Test specific settings, fallback and load-balance groups map to urltest too
+        if group_type == "urltest" {
             group_obj.insert("url".to_string(), JsonValue::String(group.url.clone()));
             group_obj.insert(
                 "interval".to_string(),
@@ -736,10 +772,8 @@ pub fn proxy_to_singbox(
         outbounds.push(JsonValue::Object(group_obj));
     }
 
-    let global = Settings::current();
-
     // Add global group if enabled
-    if global.singbox_add_clash_modes {
+    if ext.settings.singbox_add_clash_modes {
         let mut global_group = Map::new();
         global_group.insert(
             "type".to_string(),
@@ -773,6 +807,7 @@ pub fn proxy_to_singbox(
         &mut json,
         ruleset_content_array,
         ext.overwrite_original_rules,
+        &ext.settings,
     );
 
     serde_json::to_string_pretty(&json).unwrap_or_default()
@@ -1,7 +1,10 @@
+use crate::models::proxy_node::combined::CombinedProxy;
 use crate::models::{ExtraSettings, Proxy, ProxyType, SSR_CIPHERS, SS_CIPHERS};
 use crate::utils::base64::{base64_encode, url_safe_base64_encode};
+use crate::utils::network::bracket_ipv6;
 use crate::utils::url::url_encode;
 use log::error;
+use std::collections::BTreeMap;
 // Bitflags for proxy types used in conversions
 use bitflags::bitflags;
 
@@ -12,7 +15,12 @@ bitflags! {
         const SSR = 0b0010;
         const VMESS = 0b0100;
         const TROJAN = 0b1000;
-        const MIXED = Self::SS.bits() | Self::SSR.bits() | Self::VMESS.bits() | Self::TROJAN.bits();
+        const VLESS = 0b10000;
+        const MIXED = Self::SS.bits()
+            | Self::SSR.bits()
+            | Self::VMESS.bits()
+            | Self::TROJAN.bits()
+            | Self::VLESS.bits();
     }
 }
 
@@ -29,6 +37,9 @@ bitflags! {
 /// * `path` - Path
 /// * `host` - Host
 /// * `tls` - TLS setting
+/// * `sni` - TLS server name, left out when empty
+/// * `alpn` - Comma separated ALPN protocols, left out when empty
+/// * `fingerprint` - TLS client fingerprint, left out when empty
 ///
 /// # Returns
 /// * VMess link as JSON string
@@ -43,6 +54,9 @@ fn vmess_link_construct(
     path: &str,
     host: &str,
     tls: &str,
+    sni: &str,
+    alpn: &str,
+    fingerprint: &str,
 ) -> String {
     let mut json = serde_json::json!({
         "v": "2",
@@ -60,6 +74,11 @@ fn vmess_link_construct(
     if let Some(ft) = fake_type {
         json["type"] = serde_json::Value::String(ft.to_string());
     }
+    for (key, value) in [("sni", sni), ("alpn", alpn), ("fp", fingerprint)] {
+        if !value.is_empty() {
+            json[key] = serde_json::Value::String(value.to_string());
+        }
+    }
 
     match serde_json::to_string(&json) {
         Ok(result) => result,
@@ -70,189 +89,671 @@ fn vmess_link_construct(
     }
 }
 
-/// Convert proxies to single links
-///
-/// This function converts a list of proxies to single URL format links.
-///
-/// # Arguments
-/// * `nodes` - List of proxy nodes to convert
-/// * `types` - Bitflags indicating which proxy types to include (SS, SSR, VMess, Trojan)
-/// * `ext` - Extra settings for conversion
-///
-/// # Returns
-/// * String containing the converted proxies
-pub fn proxy_to_single(
-    nodes: &mut Vec<Proxy>,
-    types: ProxyUriTypes,
-    ext: &mut ExtraSettings,
+/// Add `key` to the query of a share link unless `value` is empty
+fn push_param(params: &mut Vec<(&'static str, String)>, key: &'static str, value: &str) {
+    if !value.is_empty() {
+        params.push((key, value.to_string()));
+    }
+}
+
+/// Add the `sni`, `alpn` and `fp` query arguments
+fn push_tls_params(
+    params: &mut Vec<(&'static str, String)>,
+    sni: Option<&str>,
+    alpn: Option<&Vec<String>>,
+    fingerprint: Option<&str>,
+) {
+    push_param(params, "sni", sni.unwrap_or(""));
+    push_param(
+        params,
+        "alpn",
+        &alpn.map(|alpn| alpn.join(",")).unwrap_or_default(),
+    );
+    push_param(params, "fp", fingerprint.unwrap_or(""));
+}
+
+/// Share link in the `scheme://user@host:port?query#remark` form used by
+/// Trojan and VLESS, with the query values and remark percent-encoded
+fn query_link(
+    scheme: &str,
+    user: &str,
+    hostname: &str,
+    port: u16,
+    params: &[(&str, String)],
+    remark: &str,
 ) -> String {
-    let mut all_links = String::new();
+    let query: Vec<String> = params
+        .iter()
+        .map(|(key, value)| format!("{}={}", key, url_encode(value)))
+        .collect();
+    format!(
+        "{}://{}@{}:{}?{}#{}",
+        scheme,
+        url_encode(user),
+        bracket_ipv6(hostname),
+        port,
+        query.join("&"),
+        url_encode(remark)
+    )
+}
 
-    for node in nodes {
-        let remark = &node.remark;
-        let hostname = &node.hostname;
-        let port = node.port.to_string();
-
-        // Extract optional fields with safe defaults
-        let password = node.password.as_deref().unwrap_or("");
-        let method = node.encrypt_method.as_deref().unwrap_or("");
-        let plugin = node.plugin.as_deref().unwrap_or("");
-        let plugin_opts = node.plugin_option.as_deref().unwrap_or("");
-        let protocol = node.protocol.as_deref().unwrap_or("");
-        let protocol_param = node.protocol_param.as_deref().unwrap_or("");
-        let obfs = node.obfs.as_deref().unwrap_or("");
-        let obfs_param = node.obfs_param.as_deref().unwrap_or("");
-        let user_id = node.user_id.as_deref().unwrap_or("");
-        let transfer_protocol = node.transfer_protocol.as_deref().unwrap_or("");
-        let host = node.host.as_deref().unwrap_or("");
-        let path = node.path.as_deref().unwrap_or("");
-        let fake_type = node.fake_type.as_deref();
-        let tls_secure = node.tls_secure;
-        let alter_id = node.alter_id;
-        let group = node.group.as_ref();
-
-        let mut _proxy_str = String::new();
-
-        match node.proxy_type {
-            ProxyType::Shadowsocks => {
-                if types.contains(ProxyUriTypes::SS) {
-                    // SS format
-                    _proxy_str = format!(
-                        "ss://{}@{}:{}",
-                        url_safe_base64_encode(&format!("{}:{}", method, password)),
-                        hostname,
-                        port
-                    );
+/// Share link of `node`, if `types` allows one for it
+fn node_link(node: &Proxy, types: ProxyUriTypes) -> Option<String> {
+    let remark = &node.remark;
+    let hostname = &node.hostname;
+    let port = node.port.to_string();
 
-                    if !plugin.is_empty() && !plugin_opts.is_empty() {
-                        _proxy_str.push_str(&format!(
-                            "/?plugin={}",
-                            url_encode(&format!("{};{}", plugin, plugin_opts))
-                        ));
-                    }
+    // Extract optional fields with safe defaults
+    let password = node.password.as_deref().unwrap_or("");
+    let method = node.encrypt_method.as_deref().unwrap_or("");
+    let plugin = node.plugin.as_deref().unwrap_or("");
+    let plugin_opts = node.plugin_option.as_deref().unwrap_or("");
+    let protocol = node.protocol.as_deref().unwrap_or("");
+    let protocol_param = node.protocol_param.as_deref().unwrap_or("");
+    let obfs = node.obfs.as_deref().unwrap_or("");
+    let obfs_param = node.obfs_param.as_deref().unwrap_or("");
+    let user_id = node.user_id.as_deref().unwrap_or("");
+    let transfer_protocol = node.transfer_protocol.as_deref().unwrap_or("");
+    let host = node.host.as_deref().unwrap_or("");
+    let path = node.path.as_deref().unwrap_or("");
+    let fake_type = node.fake_type.as_deref();
+    let tls_secure = node.tls_secure;
+    let alter_id = node.alter_id;
+    let group = node.group.as_ref();
 
-                    _proxy_str.push_str(&format!("#{}", url_encode(remark)));
-                } else if types.contains(ProxyUriTypes::SSR) {
-                    // Convert SS to SSR if compatible
-                    if SSR_CIPHERS.contains(&method) && plugin.is_empty() {
-                        _proxy_str = format!(
-                            "ssr://{}",
-                            url_safe_base64_encode(&format!(
-                                "{}:{}:origin:{}:plain:{}/?group={}&remarks={}",
-                                hostname,
-                                port,
-                                method,
-                                url_safe_base64_encode(password),
-                                url_safe_base64_encode(group),
-                                url_safe_base64_encode(remark)
-                            ))
-                        );
+    let mut _proxy_str = String::new();
+
+    match node.proxy_type {
+        ProxyType::Shadowsocks => {
+            if types.contains(ProxyUriTypes::SS) {
+                // SS format
+                _proxy_str = format!(
+                    "ss://{}@{}:{}",
+                    url_safe_base64_encode(&format!("{}:{}", method, password)),
+                    bracket_ipv6(hostname),
+                    port
+                );
+
+                // SIP002 keeps the plugin and its options URL-encoded in one argument
+                if !plugin.is_empty() {
+                    let plugin = if plugin_opts.is_empty() {
+                        plugin.to_string()
                     } else {
-                        continue;
-                    }
-                } else {
-                    continue;
+                        format!("{};{}", plugin, plugin_opts)
+                    };
+                    _proxy_str.push_str(&format!("/?plugin={}", url_encode(&plugin)));
                 }
-            }
-            ProxyType::ShadowsocksR => {
-                if types.contains(ProxyUriTypes::SSR) {
-                    // SSR format
+
+                _proxy_str.push_str(&format!("#{}", url_encode(remark)));
+            } else if types.contains(ProxyUriTypes::SSR) {
+                // Convert SS to SSR if compatible
+                if SSR_CIPHERS.contains(&method) && plugin.is_empty() {
                     _proxy_str = format!(
                         "ssr://{}",
                         url_safe_base64_encode(&format!(
-                            "{}:{}:{}:{}:{}:{}/?group={}&remarks={}&obfsparam={}&protoparam={}",
+                            "{}:{}:origin:{}:plain:{}/?group={}&remarks={}",
                             hostname,
                             port,
-                            protocol,
                             method,
-                            obfs,
                             url_safe_base64_encode(password),
                             url_safe_base64_encode(group),
-                            url_safe_base64_encode(remark),
-                            url_safe_base64_encode(obfs_param),
-                            url_safe_base64_encode(protocol_param)
+                            url_safe_base64_encode(remark)
                         ))
                     );
-                } else if types.contains(ProxyUriTypes::SS) {
-                    // Convert SSR to SS if compatible
-                    if SS_CIPHERS.contains(&method) && protocol == "origin" && obfs == "plain" {
-                        _proxy_str = format!(
-                            "ss://{}@{}:{}#{}",
-                            url_safe_base64_encode(&format!("{}:{}", method, password)),
-                            hostname,
-                            port,
-                            url_encode(remark)
-                        );
-                    } else {
-                        continue;
-                    }
                 } else {
-                    continue;
+                    return None;
                 }
+            } else {
+                return None;
             }
-            ProxyType::VMess => {
-                if !types.contains(ProxyUriTypes::VMESS) {
-                    continue;
+        }
+        ProxyType::ShadowsocksR => {
+            if types.contains(ProxyUriTypes::SSR) {
+                // SSR format
+                _proxy_str = format!(
+                    "ssr://{}",
+                    url_safe_base64_encode(&format!(
+                        "{}:{}:{}:{}:{}:{}/?group={}&remarks={}&obfsparam={}&protoparam={}",
+                        hostname,
+                        port,
+                        protocol,
+                        method,
+                        obfs,
+                        url_safe_base64_encode(password),
+                        url_safe_base64_encode(group),
+                        url_safe_base64_encode(remark),
+                        url_safe_base64_encode(obfs_param),
+                        url_safe_base64_encode(protocol_param)
+                    ))
+                );
+            } else if types.contains(ProxyUriTypes::SS) {
+                // Convert SSR to SS if compatible
+                if SS_CIPHERS.contains(&method) && protocol == "origin" && obfs == "plain" {
+                    _proxy_str = format!(
+                        "ss://{}@{}:{}#{}",
+                        url_safe_base64_encode(&format!("{}:{}", method, password)),
+                        bracket_ipv6(hostname),
+                        port,
+                        url_encode(remark)
+                    );
+                } else {
+                    return None;
                 }
+            } else {
+                return None;
+            }
+        }
+        ProxyType::VMess => {
+            if !types.contains(ProxyUriTypes::VMESS) {
+                return None;
+            }
 
-                // VMess format
-                let vmess_json = vmess_link_construct(
-                    remark,
-                    hostname,
-                    node.port,
-                    fake_type,
-                    user_id,
-                    alter_id,
-                    transfer_protocol,
-                    path,
-                    host,
-                    if tls_secure { "tls" } else { "" },
-                );
+            // VMess format
+            let alpn = node.alpn.as_ref().map(|alpn| alpn.join(","));
+            let vmess_json = vmess_link_construct(
+                remark,
+                hostname,
+                node.port,
+                fake_type,
+                user_id,
+                alter_id,
+                transfer_protocol,
+                path,
+                host,
+                if tls_secure { "tls" } else { "" },
+                node.sni.as_deref().unwrap_or(""),
+                alpn.as_deref().unwrap_or(""),
+                node.fingerprint.as_deref().unwrap_or(""),
+            );
 
-                _proxy_str = format!("vmess://{}", base64_encode(&vmess_json));
+            _proxy_str = format!("vmess://{}", base64_encode(&vmess_json));
+        }
+        ProxyType::Trojan => {
+            if !types.contains(ProxyUriTypes::TROJAN) {
+                return None;
             }
-            ProxyType::Trojan => {
-                if !types.contains(ProxyUriTypes::TROJAN) {
-                    continue;
-                }
 
-                // Trojan format
-                _proxy_str = format!(
-                    "trojan://{}@{}:{}?allowInsecure={}",
-                    password,
-                    hostname,
-                    port,
-                    if node.allow_insecure.unwrap_or(false) {
-                        "1"
-                    } else {
-                        "0"
-                    }
-                );
+            let mut params = vec![("security", "tls".to_string())];
+            push_tls_params(
+                &mut params,
+                node.sni.as_deref(),
+                node.alpn.as_ref(),
+                node.fingerprint.as_deref(),
+            );
+            if node.allow_insecure == Some(true) {
+                params.push(("allowInsecure", "1".to_string()));
+            }
+            push_param(&mut params, "type", transfer_protocol);
+            push_param(&mut params, "host", host);
+            let path_key = if transfer_protocol == "grpc" {
+                "serviceName"
+            } else {
+                "path"
+            };
+            push_param(&mut params, path_key, path);
 
-                if !host.is_empty() {
-                    _proxy_str.push_str(&format!("&sni={}", host));
-                }
+            _proxy_str = query_link("trojan", password, hostname, node.port, &params, remark);
+        }
+        ProxyType::Vless => {
+            if !types.contains(ProxyUriTypes::VLESS) {
+                return None;
+            }
+            let Some(CombinedProxy::Vless(vless)) = &node.combined_proxy else {
+                return None;
+            };
 
-                if transfer_protocol == "ws" {
-                    _proxy_str.push_str("&ws=1");
-                    if !path.is_empty() {
-                        _proxy_str.push_str(&format!("&wspath={}", url_encode(path)));
+            let mut params = vec![("encryption", "none".to_string())];
+            if vless.reality_public_key.is_some() {
+                params.push(("security", "reality".to_string()));
+            } else if vless.tls {
+                params.push(("security", "tls".to_string()));
+            }
+            push_tls_params(
+                &mut params,
+                vless.servername.as_deref(),
+                vless.alpn.as_ref(),
+                vless.client_fingerprint.as_deref(),
+            );
+            push_param(
+                &mut params,
+                "pbk",
+                vless.reality_public_key.as_deref().unwrap_or(""),
+            );
+            push_param(
+                &mut params,
+                "sid",
+                vless.reality_short_id.as_deref().unwrap_or(""),
+            );
+            push_param(&mut params, "flow", vless.flow.as_deref().unwrap_or(""));
+            push_param(
+                &mut params,
+                "packetEncoding",
+                vless.packet_encoding.as_deref().unwrap_or(""),
+            );
+
+            let network = vless.network.as_deref().unwrap_or("");
+            push_param(&mut params, "type", network);
+            match network {
+                "tcp" => {
+                    if let Some(headers) = &vless.http_headers {
+                        params.push(("headerType", "http".to_string()));
+                        let host = headers.get("Host").and_then(|hosts| hosts.first());
+                        push_param(&mut params, "host", host.map_or("", String::as_str));
+                        push_param(
+                            &mut params,
+                            "path",
+                            vless.http_path.as_deref().unwrap_or(""),
+                        );
                     }
                 }
+                "http" | "h2" => {
+                    let hosts = vless.h2_host.as_deref().unwrap_or_default();
+                    push_param(&mut params, "host", &hosts.join(","));
+                    push_param(&mut params, "path", vless.h2_path.as_deref().unwrap_or(""));
+                }
+                "ws" | "httpupgrade" => {
+                    let host = vless
+                        .ws_headers
+                        .as_ref()
+                        .and_then(|headers| headers.get("Host"));
+                    push_param(&mut params, "host", host.map_or("", String::as_str));
+                    push_param(&mut params, "path", vless.ws_path.as_deref().unwrap_or(""));
+                }
+                "grpc" => push_param(
+                    &mut params,
+                    "serviceName",
+                    vless.grpc_service_name.as_deref().unwrap_or(""),
+                ),
+                _ => {}
+            }
 
-                _proxy_str.push_str(&format!("#{}", url_encode(remark)));
+            _proxy_str = query_link("vless", &vless.uuid, hostname, node.port, &params, remark);
+        }
+        _ => return None,
+    }
+
+    Some(_proxy_str)
+}
+
+/// Convert proxies to single links
+///
+/// This function converts a list of proxies to single URL format links.
+/// Nodes which can not be written as one of `types` are left out, with
+/// `ext.debug` their number is noted in a comment before the links.
+///
+/// # Arguments
+/// * `nodes` - List of proxy nodes to convert
+/// * `types` - Bitflags indicating which proxy types to include (SS, SSR, VMess, Trojan,
+///   VLESS)
+/// * `ext` - Extra settings for conversion
+///
+/// # Returns
+/// * The links one per line with `ext.nodelist`, base64 encoded in
+///   `ext.base64_style` otherwise
+pub fn proxy_to_single(
+    nodes: &mut Vec<Proxy>,
+    types: ProxyUriTypes,
+    ext: &mut ExtraSettings,
+) -> String {
+    let mut all_links = String::new();
+    let mut skipped: BTreeMap<&str, usize> = BTreeMap::new();
+
+    for node in nodes {
+        match node_link(node, types) {
+            Some(link) => {
+                all_links.push_str(&link);
+                all_links.push('\n');
             }
-            _ => continue,
+            None => *skipped.entry(node.proxy_type.to_string()).or_default() += 1,
         }
+    }
 
-        all_links.push_str(&_proxy_str);
-        all_links.push('\n');
+    if ext.debug && !skipped.is_empty() {
+        let count: usize = skipped.values().sum();
+        let types: Vec<String> = skipped
+            .iter()
+            .map(|(proxy_type, count)| format!("{} x{}", proxy_type, count))
+            .collect();
+        all_links.insert_str(
+            0,
+            &format!(
+                "# Skipped {} nodes without a share link for this target: {}\n",
+                count,
+                types.join(", ")
+            ),
+        );
     }
 
     // Return raw links or base64 encoded based on settings
     if ext.nodelist {
         all_links
     } else {
-        base64_encode(&all_links)
+        ext.base64_style.encode(&all_links)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::{Base64Style, SS_DEFAULT_GROUP};
+    use crate::parser::explodes::{
+        explode_ss, explode_ssr, explode_trojan, explode_vless, explode_vmess,
+    };
+    use crate::utils::base64::url_safe_base64_decode;
+
+    fn vmess_node() -> Proxy {
+        let mut node = Proxy::vmess_construct(
+            "V2Ray",
+            "HK VMess",
+            "vmess.example.com",
+            443,
+            "",
+            "b831381d-6324-4d53-ad4f-8cda48b30811",
+            0,
+            "ws",
+            "auto",
+            "/ws",
+            "cdn.example.com",
+            "",
+            "tls",
+            "sni.example.com",
+            None,
+            None,
+            None,
+            None,
+            "",
+        );
+        node.alpn = Some(vec!["http/1.1".to_string(), "h2".to_string()]);
+        node.fingerprint = Some("chrome".to_string());
+        node
+    }
+
+    #[test]
+    fn test_vmess_links_keep_tls_fields() {
+        let mut ext = ExtraSettings::default();
+        ext.nodelist = true;
+        let links = proxy_to_single(&mut vec![vmess_node()], ProxyUriTypes::VMESS, &mut ext);
+
+        let json: serde_json::Value =
+            serde_json::from_str(&url_safe_base64_decode(&links.trim()[8..])).unwrap();
+        assert_eq!(json["sni"], "sni.example.com");
+        assert_eq!(json["alpn"], "http/1.1,h2");
+        assert_eq!(json["fp"], "chrome");
+
+        let mut parsed = Proxy::default();
+        assert!(explode_vmess(links.trim(), &mut parsed));
+        assert_eq!(parsed.sni.as_deref(), Some("sni.example.com"));
+        assert_eq!(parsed.alpn, vmess_node().alpn);
+        assert_eq!(parsed.fingerprint.as_deref(), Some("chrome"));
+    }
+
+    #[test]
+    fn test_base64_styles_and_skipped_nodes() {
+        let wireguard = Proxy {
+            proxy_type: ProxyType::WireGuard,
+            remark: "WG".to_string(),
+            ..Default::default()
+        };
+        let mut nodes = vec![vmess_node(), wireguard];
+        let mut ext = ExtraSettings::default();
+        ext.nodelist = true;
+        let links = proxy_to_single(&mut nodes, ProxyUriTypes::MIXED, &mut ext);
+        assert_eq!(links.lines().count(), 1);
+        assert!(links.starts_with("vmess://"));
+
+        ext.debug = true;
+        let links = proxy_to_single(&mut nodes, ProxyUriTypes::MIXED, &mut ext);
+        assert!(links.starts_with(
+            "# Skipped 1 nodes without a share link for this target: WireGuard x1\nvmess://"
+        ));
+
+        ext.debug = false;
+        ext.nodelist = false;
+        let unpadded = proxy_to_single(&mut nodes, ProxyUriTypes::MIXED, &mut ext);
+        assert!(!unpadded.ends_with('='));
+        assert_eq!(
+            url_safe_base64_decode(&unpadded),
+            links.splitn(2, '\n').nth(1).unwrap()
+        );
+
+        ext.base64_style = Base64Style::Padded;
+        let padded = proxy_to_single(&mut nodes, ProxyUriTypes::MIXED, &mut ext);
+        assert_eq!(padded.len() % 4, 0);
+        assert_eq!(padded.trim_end_matches('='), unpadded);
+
+        ext.base64_style = Base64Style::UrlSafe;
+        let url_safe = proxy_to_single(&mut nodes, ProxyUriTypes::MIXED, &mut ext);
+        assert!(!url_safe.contains(['+', '/', '=']));
+        assert_eq!(
+            url_safe_base64_decode(&url_safe),
+            url_safe_base64_decode(&unpadded)
+        );
+    }
+
+    /// Parse the links written for `nodes` back with `explode`
+    fn round_trip(
+        nodes: &[Proxy],
+        types: ProxyUriTypes,
+        explode: fn(&str, &mut Proxy) -> bool,
+    ) -> Vec<Proxy> {
+        let mut ext = ExtraSettings::default();
+        ext.nodelist = true;
+        let links = proxy_to_single(&mut nodes.to_vec(), types, &mut ext);
+        links
+            .lines()
+            .map(|link| {
+                let mut node = Proxy::default();
+                assert!(explode(link, &mut node), "{}", link);
+                node
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_ss_links_round_trip_with_plugins() {
+        let plugins = [
+            ("", ""),
+            ("obfs-local", "obfs=http;obfs-host=www.bing.com"),
+            ("simple-obfs", "obfs=tls;obfs-host=cdn.example.com"),
+            ("v2ray-plugin", "tls;host=ws.example.com;path=/ws?ed=2048"),
+            ("v2ray-plugin", ""),
+            ("xray-plugin", "mode=websocket;path=/a b"),
+            (
+                "shadow-tls",
+                "host=cloud.tencent.com;password=a%b+c#d;version=3",
+            ),
+        ];
+        let nodes: Vec<Proxy> = plugins
+            .iter()
+            .enumerate()
+            .map(|(i, (plugin, opts))| {
+                Proxy::ss_construct(
+                    SS_DEFAULT_GROUP,
+                    &format!("香港 {} #{}", i, plugin),
+                    "ss.example.com",
+                    8388 + i as u16,
+                    "pass:word/+=",
+                    "2022-blake3-aes-128-gcm",
+                    plugin,
+                    opts,
+                    None,
+                    None,
+                    None,
+                    None,
+                    "",
+                )
+            })
+            .collect();
+
+        let parsed = round_trip(&nodes, ProxyUriTypes::SS, explode_ss);
+        assert_eq!(parsed.len(), nodes.len());
+        for (node, parsed) in nodes.iter().zip(&parsed) {
+            assert_eq!(parsed.proxy_type, ProxyType::Shadowsocks);
+            assert_eq!(parsed.remark, node.remark);
+            assert_eq!(parsed.hostname, node.hostname);
+            assert_eq!(parsed.port, node.port);
+            assert_eq!(parsed.password, node.password);
+            assert_eq!(parsed.encrypt_method, node.encrypt_method);
+            assert_eq!(parsed.plugin, node.plugin, "{}", node.remark);
+            assert_eq!(parsed.plugin_option, node.plugin_option, "{}", node.remark);
+        }
+    }
+
+    #[test]
+    fn test_ssr_links_round_trip_with_group() {
+        let nodes = [
+            Proxy::ssr_construct(
+                "我的机场 Group",
+                "香港 01 #x",
+                "ssr.example.com",
+                443,
+                "auth_aes128_md5",
+                "chacha20-ietf",
+                "tls1.2_ticket_auth",
+                "pass:word",
+                "cdn.example.com",
+                "1234:abcd",
+                None,
+                None,
+                None,
+                "",
+            ),
+            Proxy::ssr_construct(
+                "IPv6",
+                "v6",
+                "2001:db8::1",
+                8443,
+                "auth_chain_a",
+                "none",
+                "http_simple",
+                "password",
+                "",
+                "",
+                None,
+                None,
+                None,
+                "",
+            ),
+        ];
+
+        let parsed = round_trip(&nodes, ProxyUriTypes::SSR, explode_ssr);
+        assert_eq!(parsed.len(), nodes.len());
+        for (node, parsed) in nodes.iter().zip(&parsed) {
+            assert_eq!(parsed.proxy_type, ProxyType::ShadowsocksR);
+            assert_eq!(parsed.group, node.group);
+            assert_eq!(parsed.remark, node.remark);
+            assert_eq!(parsed.hostname, node.hostname);
+            assert_eq!(parsed.port, node.port);
+            assert_eq!(parsed.password, node.password);
+            assert_eq!(parsed.encrypt_method, node.encrypt_method);
+            assert_eq!(parsed.protocol, node.protocol);
+            assert_eq!(parsed.protocol_param, node.protocol_param);
+            assert_eq!(parsed.obfs, node.obfs);
+            assert_eq!(parsed.obfs_param, node.obfs_param);
+        }
+    }
+
+    #[test]
+    fn test_nonstandard_ssr_links_round_trip() {
+        let link = |inner: String| format!("ssr://{}", base64_encode(&inner));
+        let links = [
+            // `_` padding, no obfsparam and protoparam
+            link(format!(
+                "hk.example.com:443:auth_aes128_md5:chacha20-ietf:tls1.2_ticket_auth:cGFzcw__/?remarks={}&group={}",
+                base64_encode("%E9%A6%99%E6%B8%AF%2001"),
+                base64_encode("Group").replace('=', "_")
+            )),
+            // Parameters which were never encoded, empty protocol and obfs
+            link("jp.example.com:8443::none::dGVzdA/?protoparam=1234:abcd&obfsparam=cdn.example.com".to_string()),
+        ];
+        let links: Vec<&str> = links.iter().map(String::as_str).collect();
+
+        let written = assert_links_round_trip(&links, ProxyUriTypes::SSR, explode_ssr);
+        let decoded = url_safe_base64_decode(written[0].trim_start_matches("ssr://"));
+        assert!(decoded.contains("&obfsparam=&protoparam="), "{}", decoded);
+        let mut node = Proxy::default();
+        assert!(explode_ssr(&written[0], &mut node));
+        assert_eq!(node.remark, "香港 01");
+        assert_eq!(node.group, "Group");
+        let mut node = Proxy::default();
+        assert!(explode_ssr(&written[1], &mut node));
+        assert_eq!(node.protocol_param.as_deref(), Some("1234:abcd"));
+    }
+
+    /// Parse `links`, write them out again and check they parse back the same
+    fn assert_links_round_trip(
+        links: &[&str],
+        types: ProxyUriTypes,
+        explode: fn(&str, &mut Proxy) -> bool,
+    ) -> Vec<String> {
+        let nodes: Vec<Proxy> = links
+            .iter()
+            .map(|link| {
+                let mut node = Proxy::default();
+                assert!(explode(link, &mut node), "{}", link);
+                node
+            })
+            .collect();
+        let parsed = round_trip(&nodes, types, explode);
+        assert_eq!(parsed.len(), nodes.len());
+        for (node, parsed) in nodes.iter().zip(&parsed) {
+            assert_eq!(
+                serde_json::to_value(parsed).unwrap(),
+                serde_json::to_value(node).unwrap(),
+                "{}",
+                node.remark
+            );
+        }
+        nodes
+            .iter()
+            .filter_map(|node| node_link(node, types))
+            .collect()
+    }
+
+    #[test]
+    fn test_trojan_links_round_trip() {
+        let links = assert_links_round_trip(
+            &[
+                "trojan://pass%40word@trojan.example.com:443?sni=sni.example.com&alpn=h2,http/1.1&fp=firefox&type=ws&host=cdn.example.com&path=%2Fws%3Fed%3D2048&allowInsecure=1#HK%20Trojan%20%2301",
+                "trojan://secret@[2001:db8::1]:8443?sni=grpc.example.com&type=grpc&serviceName=tunnel#gRPC",
+                "trojan://secret@trojan.example.com:443?ws=1&wspath=%2Flegacy&peer=legacy.example.com#Legacy",
+                "trojan://secret@trojan.example.com:443#Plain",
+            ],
+            ProxyUriTypes::TROJAN,
+            explode_trojan,
+        );
+        assert!(links[0].contains("alpn=h2%2Chttp%2F1.1&fp=firefox&allowInsecure=1&type=ws"));
+        assert!(links[0].ends_with("#HK%20Trojan%20%2301"));
+        assert!(links[1].starts_with("trojan://secret@[2001:db8::1]:8443?"));
+        assert!(links[1].contains("&type=grpc&serviceName=tunnel#"));
+        assert_eq!(
+            links[3],
+            "trojan://secret@trojan.example.com:443?security=tls#Plain"
+        );
+    }
+
+    #[test]
+    fn test_vless_links_round_trip() {
+        let links = assert_links_round_trip(
+            &[
+                "vless://b831381d-6324-4d53-ad4f-8cda48b30811@reality.example.com:443?encryption=none&security=reality&sni=www.microsoft.com&fp=chrome&pbk=SbVKOEMjK0sIlbwg4akyBg5mL5KZwwB-ed4eEE7YnRc&sid=6ba85179e30d4fc2&flow=xtls-rprx-vision&type=tcp#%E9%A6%99%E6%B8%AF%20Reality",
+                "vless://b831381d-6324-4d53-ad4f-8cda48b30811@ws.example.com:443?security=tls&sni=ws.example.com&alpn=h2,http/1.1&type=ws&host=cdn.example.com&path=%2Fvless%3Fed%3D2048#WS",
+                "vless://b831381d-6324-4d53-ad4f-8cda48b30811@grpc.example.com:443?security=tls&type=grpc&serviceName=tunnel&packetEncoding=xudp#gRPC",
+                "vless://b831381d-6324-4d53-ad4f-8cda48b30811@h2.example.com:443?security=tls&type=h2&host=h2.example.com&path=%2Fh2#H2",
+                "vless://b831381d-6324-4d53-ad4f-8cda48b30811@http.example.com:80?type=tcp&headerType=http&host=www.bing.com&path=%2F#HTTP",
+                "vless://b831381d-6324-4d53-ad4f-8cda48b30811@plain.example.com:80#Plain",
+            ],
+            ProxyUriTypes::MIXED,
+            explode_vless,
+        );
+        assert!(links[0].contains(
+            "security=reality&sni=www.microsoft.com&fp=chrome&pbk=SbVKOEMjK0sIlbwg4akyBg5mL5KZwwB-ed4eEE7YnRc&sid=6ba85179e30d4fc2&flow=xtls-rprx-vision&type=tcp#"
+        ));
+        assert!(links[1].contains("&type=ws&host=cdn.example.com&path=%2Fvless%3Fed%3D2048#WS"));
+        assert!(links[4].contains("&type=tcp&headerType=http&host=www.bing.com&path=%2F#"));
+        assert_eq!(
+            links[5],
+            "vless://b831381d-6324-4d53-ad4f-8cda48b30811@plain.example.com:80?encryption=none&type=tcp#Plain"
+        );
     }
 }
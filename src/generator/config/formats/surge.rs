@@ -1,15 +1,18 @@
+use crate::generator::config::formats::base_cache::parse_ini_base;
 use crate::generator::config::group::group_generate;
-use crate::generator::config::remark::process_remark;
+use crate::generator::config::remark::{process_remark, AppendTypeFormat};
+use crate::generator::exports::ExportError;
 use crate::generator::ruleconvert::ruleset_to_surge::ruleset_to_surge;
 use crate::models::{
     ExtraSettings, Proxy, ProxyGroupConfigs, ProxyGroupType, ProxyType, RulesetContent,
 };
 use crate::utils::ini_reader::IniReader;
-use crate::utils::network::{hostname_to_ip_addr, is_ipv4, is_ipv6};
+use crate::utils::network::{hostname_to_ip_addr, is_ipv4, is_ipv6, join_host_port};
 use crate::utils::string::{hash, join, to_lower};
 use crate::utils::tribool::{BoolTriboolExt, TriboolExt};
-use crate::Settings;
-use log::error;
+use log::{error, warn};
+use std::collections::HashMap;
+use std::sync::Arc;
 
 /// Generate a WireGuard peer configuration string
 ///
@@ -29,9 +32,7 @@ fn generate_peer(node: &Proxy, client_id_as_reserved: bool) -> String {
     }
 
     peer.push_str("endpoint = ");
-    peer.push_str(&node.hostname);
-    peer.push_str(":");
-    peer.push_str(&node.port.to_string());
+    peer.push_str(&join_host_port(&node.hostname, node.port));
 
     if !node.allowed_ips.is_empty() {
         peer.push_str(", allowed-ips = ");
@@ -53,6 +54,85 @@ fn generate_peer(node: &Proxy, client_id_as_reserved: bool) -> String {
     peer
 }
 
+/// Order Surge lists the sections of a profile in, sections of the base
+/// missing here (e.g. WireGuard peers) follow them
+const SURGE_SECTION_ORDER: &[&str] = &[
+    "General",
+    "Replica",
+    "Proxy",
+    "Proxy Group",
+    "Rule",
+    "Host",
+    "URL Rewrite",
+    "Header Rewrite",
+    "Map Local",
+    "SSID Setting",
+    "Script",
+    "MITM",
+];
+
+/// Sections a Surge module may carry, proxies and groups stay in the profile
+const SURGE_MODULE_SECTIONS: &[&str] = &[
+    "Rule",
+    "Host",
+    "URL Rewrite",
+    "Header Rewrite",
+    "Map Local",
+    "Script",
+    "MITM",
+];
+
+/// Whether Surge takes `tfo` for the type, which runs over TCP and is no
+/// external program
+fn surge_takes_tfo(proxy_type: &ProxyType) -> bool {
+    matches!(
+        proxy_type,
+        ProxyType::Shadowsocks
+            | ProxyType::VMess
+            | ProxyType::Trojan
+            | ProxyType::Snell
+            | ProxyType::Socks5
+            | ProxyType::HTTP
+            | ProxyType::HTTPS
+    )
+}
+
+/// Whether Surge takes `udp-relay` for the type, HTTP proxies carry no UDP
+/// and WireGuard and Hysteria relay it anyway
+fn surge_takes_udp_relay(proxy_type: &ProxyType) -> bool {
+    matches!(
+        proxy_type,
+        ProxyType::Shadowsocks
+            | ProxyType::VMess
+            | ProxyType::Trojan
+            | ProxyType::Snell
+            | ProxyType::Socks5
+    )
+}
+
+/// INI reader set up for Surge bases
+fn surge_reader() -> IniReader {
+    let mut ini = IniReader::new();
+    ini.store_any_line = true;
+    ini.strip_inline_comments = false;
+
+    // Add direct save sections
+    let direct_save_sections = vec![
+        "General",
+        "Replica",
+        "Rule",
+        "MITM",
+        "Script",
+        "Host",
+        "URL Rewrite",
+        "Header Rewrite",
+    ];
+    for section in direct_save_sections {
+        ini.add_direct_save_section(section);
+    }
+    ini
+}
+
 /// Convert proxies to Surge format
 ///
 /// This function converts a list of proxies to the Surge configuration format,
@@ -68,6 +148,9 @@ fn generate_peer(node: &Proxy, client_id_as_reserved: bool) -> String {
 ///
 /// # Returns
 /// * Converted configuration as a string
+///
+/// # Errors
+/// Returns [`ExportError::InvalidBase`] if the base config cannot be parsed
 pub async fn proxy_to_surge(
     nodes: &mut Vec<Proxy>,
     base_conf: &str,
@@ -75,39 +158,58 @@ pub async fn proxy_to_surge(
     extra_proxy_group: &ProxyGroupConfigs,
     surge_ver: i32,
     ext: &mut ExtraSettings,
-) -> String {
-    let mut ini = IniReader::new();
+) -> Result<String, ExportError> {
     let mut output_nodelist = String::new();
     let mut nodelist = Vec::new();
     let mut local_port = 1080;
     let mut remarks_list = Vec::new();
-    let global = Settings::current();
-
-    // Configure INI reader
-    ini.store_any_line = true;
-
-    // Add direct save sections
-    let direct_save_sections = vec![
-        "General",
-        "Replica",
-        "Rule",
-        "MITM",
-        "Script",
-        "Host",
-        "URL Rewrite",
-        "Header Rewrite",
-    ];
-    for section in direct_save_sections {
-        ini.add_direct_save_section(section);
-    }
+    let global = Arc::clone(&ext.settings);
 
     // Parse base configuration
-    if ini.parse(base_conf).is_err() && !ext.nodelist {
-        error!(
-            "Surge base loader failed with error: {}",
-            ini.get_last_error()
-        );
-        return String::new();
+    let mut ini = match parse_ini_base("surge", base_conf, surge_reader) {
+        Ok(ini) => ini,
+        Err(_) if ext.nodelist => surge_reader(),
+        Err(e) => {
+            error!("Surge base loader failed with error: {}", e);
+            return Err(ExportError::InvalidBase(e));
+        }
+    };
+
+    // Surge chains proxies through `underlying-proxy` on the last hop, so a
+    // relay of two nodes becomes the second node dialing through the first
+    let mut relay_exits = HashMap::new();
+    for group in extra_proxy_group
+        .iter()
+        .filter(|group| group.group_type == ProxyGroupType::Relay)
+    {
+        let mut members = Vec::new();
+        for proxy_name in &group.proxies {
+            group_generate(
+                proxy_name,
+                nodes,
+                &group.group_type,
+                &mut members,
+                true,
+                ext,
+            );
+        }
+        let exit = match members.as_slice() {
+            [entry, exit] => nodes
+                .iter_mut()
+                .find(|node| &node.remark == exit)
+                .map(|node| node.underlying_proxy = Some(entry.clone()))
+                .map(|_| exit.clone()),
+            _ => None,
+        };
+        match exit {
+            Some(exit) => {
+                relay_exits.insert(group.name.clone(), exit);
+            }
+            None => warn!(
+                "Skipping relay group {}: Surge can only chain two nodes, got {:?}",
+                group.name, members
+            ),
+        }
     }
 
     // Prepare Proxy section
@@ -116,16 +218,25 @@ pub async fn proxy_to_surge(
     ini.set("{NONAME}", "DIRECT", "direct").unwrap_or(());
 
     // Process each proxy node
+    let type_format = ext
+        .append_proxy_type
+        .then(|| AppendTypeFormat::new(&ext.append_type_format));
     for node in nodes.iter_mut() {
         // Add proxy type prefix if enabled
-        if ext.append_proxy_type {
-            let proxy_type = node.proxy_type.to_string();
-            node.remark = format!("[{}] {}", proxy_type, node.remark);
+        if let Some(format) = &type_format {
+            node.remark = format.apply(node);
         }
 
         // Process remark
         let mut remark = node.remark.clone();
-        process_remark(&mut remark, &remarks_list, false);
+        process_remark(
+            &mut remark,
+            &remarks_list,
+            false,
+            &ext.duplicate_remark_format,
+        );
+        // Groups are resolved against the names written out
+        node.remark = remark.clone();
 
         // Extract node properties for easier access
         let hostname = &node.hostname;
@@ -147,16 +258,17 @@ pub async fn proxy_to_surge(
         let underlying_proxy = node.underlying_proxy.as_deref().unwrap_or("");
         let tls_secure = node.tls_secure;
 
-        // Define tribool values with defaults from ext and override with node-specific values
+        // Values passed with the request override the node, otherwise keep what the node has
         let mut udp = ext.udp;
         let mut tfo = ext.tfo;
         let mut scv = ext.skip_cert_verify;
         let mut tls13 = ext.tls13;
+        let block_quic = ext.block_quic.define(node.block_quic);
 
-        udp = node.udp.define(udp);
-        tfo = node.tcp_fast_open.define(tfo);
-        scv = node.allow_insecure.define(scv);
-        tls13 = node.tls13.define(tls13);
+        udp = udp.define(node.udp);
+        tfo = tfo.define(node.tcp_fast_open);
+        scv = scv.define(node.allow_insecure);
+        tls13 = tls13.define(node.tls13);
 
         let mut _proxy = String::new();
         let mut _section = String::new();
@@ -213,17 +325,16 @@ pub async fn proxy_to_surge(
                     ));
                 }
 
+                if tls_secure {
+                    if let Some(sni) = node.tls_server_name() {
+                        _proxy.push_str(&format!(", sni={}", sni));
+                    }
+                }
+
                 match transproto {
                     "tcp" => {}
                     "ws" => {
-                        if host.is_empty() {
-                            _proxy.push_str(&format!(
-                                ", ws=true, ws-path={}, sni={}",
-                                path, hostname
-                            ));
-                        } else {
-                            _proxy.push_str(&format!(", ws=true, ws-path={}, sni={}", path, host));
-                        }
+                        _proxy.push_str(&format!(", ws=true, ws-path={}", path));
 
                         if !host.is_empty() {
                             headers.push(format!("Host:{}", host));
@@ -351,6 +462,17 @@ pub async fn proxy_to_surge(
                     if tls_secure { "true" } else { "false" }
                 ));
 
+                if tls_secure && !tls13.is_undef() {
+                    _proxy.push_str(&format!(
+                        ", tls13={}",
+                        if tls13.unwrap_or(false) {
+                            "true"
+                        } else {
+                            "false"
+                        }
+                    ));
+                }
+
                 if scv.is_some() {
                     _proxy.push_str(&format!(
                         ", skip-cert-verify={}",
@@ -404,6 +526,17 @@ pub async fn proxy_to_surge(
                     _proxy.push_str(&format!(", sni={}", host));
                 }
 
+                if !tls13.is_undef() {
+                    _proxy.push_str(&format!(
+                        ", tls13={}",
+                        if tls13.unwrap_or(false) {
+                            "true"
+                        } else {
+                            "false"
+                        }
+                    ));
+                }
+
                 if scv.is_some() {
                     _proxy.push_str(&format!(
                         ", skip-cert-verify={}",
@@ -440,12 +573,6 @@ pub async fn proxy_to_surge(
                 _real_section = format!("WireGuard {}", _section);
                 _proxy = format!("wireguard, section-name={}", _section);
 
-                if let Some(test_url) = &node.test_url {
-                    if !test_url.is_empty() {
-                        _proxy.push_str(&format!(", test-url={}", test_url));
-                    }
-                }
-
                 if let Some(private_key) = &node.private_key {
                     ini.set(&_real_section, "private-key", private_key)
                         .unwrap_or(());
@@ -530,8 +657,8 @@ pub async fn proxy_to_surge(
             _ => continue,
         }
 
-        // Add common options
-        if !tfo.is_undef() {
+        // Add common options, each only where Surge takes it
+        if surge_takes_tfo(&node.proxy_type) && !tfo.is_undef() {
             _proxy.push_str(&format!(
                 ", tfo={}",
                 if tfo.unwrap_or(false) {
@@ -542,7 +669,7 @@ pub async fn proxy_to_surge(
             ));
         }
 
-        if !udp.is_undef() {
+        if surge_takes_udp_relay(&node.proxy_type) && !udp.is_undef() {
             _proxy.push_str(&format!(
                 ", udp-relay={}",
                 if udp.unwrap_or(false) {
@@ -553,6 +680,21 @@ pub async fn proxy_to_surge(
             ));
         }
 
+        if surge_ver >= 4 && !block_quic.is_undef() {
+            _proxy.push_str(&format!(
+                ", block-quic={}",
+                if block_quic.unwrap_or(false) {
+                    "on"
+                } else {
+                    "off"
+                }
+            ));
+        }
+
+        if let Some(test_url) = node.test_url.as_deref().filter(|url| !url.is_empty()) {
+            _proxy.push_str(&format!(", test-url={}", test_url));
+        }
+
         if !underlying_proxy.is_empty() {
             _proxy.push_str(&format!(", underlying-proxy={}", underlying_proxy));
         }
@@ -570,7 +712,7 @@ pub async fn proxy_to_surge(
     }
 
     if ext.nodelist {
-        return output_nodelist;
+        return Ok(output_nodelist);
     }
 
     // Process proxy groups
@@ -593,6 +735,14 @@ pub async fn proxy_to_surge(
                     continue;
                 }
             }
+            ProxyGroupType::Relay => {
+                // The exit node already dials through the entry node
+                if let Some(exit) = relay_exits.get(&group.name) {
+                    ini.set_current("{NONAME}", &format!("{} = select, {}", group.name, exit))
+                        .unwrap_or(());
+                }
+                continue;
+            }
             ProxyGroupType::SSID => {
                 _group_str = format!("{},default={},", group.type_str(), group.proxies[0]);
                 _group_str.push_str(&join(&group.proxies[1..], ","));
@@ -600,12 +750,18 @@ pub async fn proxy_to_surge(
                     .unwrap_or(());
                 continue;
             }
-            _ => continue,
         }
 
         // Generate filtered proxy list
         for proxy_name in &group.proxies {
-            group_generate(proxy_name, &nodelist, &mut filtered_nodelist, true, ext);
+            group_generate(
+                proxy_name,
+                &nodelist,
+                &group.group_type,
+                &mut filtered_nodelist,
+                true,
+                ext,
+            );
         }
 
         if filtered_nodelist.is_empty() {
@@ -638,7 +794,9 @@ pub async fn proxy_to_surge(
         {
             _group_str.push_str(&format!(",url={},interval={}", group.url, group.interval));
 
-            if group.tolerance > 0 {
+            // Fallback groups take the first available node, tolerance is
+            // only known to url-test
+            if group.group_type == ProxyGroupType::URLTest && group.tolerance > 0 {
                 _group_str.push_str(&format!(",tolerance={}", group.tolerance));
             }
 
@@ -646,8 +804,7 @@ pub async fn proxy_to_surge(
                 _group_str.push_str(&format!(",timeout={}", group.timeout));
             }
 
-            // Handle persistent field directly
-            if group.persistent {
+            if group.group_type == ProxyGroupType::LoadBalance && group.persistent {
                 _group_str.push_str(",persistent=true");
             }
 
@@ -668,10 +825,312 @@ pub async fn proxy_to_surge(
             ruleset_content_array,
             surge_ver,
             ext.overwrite_original_rules,
-            &ext.managed_config_prefix,
+            ext.self_url_prefix(),
+            &ext.settings,
         )
         .await;
     }
 
-    ini.to_string()
+    // Modules came with Surge 3, Surfboard has none
+    if ext.surge_module && surge_ver >= 3 {
+        return Ok(surge_module(ini));
+    }
+
+    ini.sort_sections(SURGE_SECTION_ORDER);
+    Ok(ini.to_string())
+}
+
+/// Turn a generated profile into an sgmodule keeping the rules and the
+/// sections passed through from the base
+///
+/// The MITM hostnames are appended to the ones of the profile instead of
+/// replacing them, and `FINAL` is left to the profile since module rules come
+/// before its own.
+fn surge_module(mut ini: IniReader) -> String {
+    let sections = ini.get_section_names().to_vec();
+    for section in sections
+        .iter()
+        .filter(|section| !SURGE_MODULE_SECTIONS.contains(&section.as_str()))
+    {
+        ini.remove_section(section);
+    }
+
+    if let Ok(items) = ini.get_items("Rule") {
+        ini.erase_section_by_name("Rule");
+        for (_, line) in items.iter().filter(|(_, line)| !line.starts_with("FINAL")) {
+            ini.set("Rule", "{NONAME}", line).unwrap_or(());
+        }
+    }
+
+    if let Ok(items) = ini.get_items("MITM") {
+        ini.erase_section_by_name("MITM");
+        for (_, line) in items {
+            let line = match line.split_once('=') {
+                Some((key, hosts))
+                    if key.trim() == "hostname" && !hosts.trim().starts_with('%') =>
+                {
+                    format!("hostname = %APPEND% {}", hosts.trim())
+                }
+                _ => line,
+            };
+            ini.set("MITM", "{NONAME}", &line).unwrap_or(());
+        }
+    }
+
+    ini.sort_sections(SURGE_SECTION_ORDER);
+    format!(
+        "#!name=subconverter\n#!desc=Rules generated by subconverter, policies come from the profile\n\n{}",
+        ini.to_string()
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::ProxyGroupConfig;
+
+    fn trojan_node(remark: &str) -> Proxy {
+        Proxy {
+            proxy_type: ProxyType::Trojan,
+            remark: remark.to_string(),
+            hostname: "example.com".to_string(),
+            port: 443,
+            password: Some("secret".to_string()),
+            ..Default::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn test_two_node_relay_becomes_underlying_proxy() {
+        let mut nodes = vec![trojan_node("Exit"), trojan_node("Entry")];
+        let mut chain = ProxyGroupConfig::new("Chain".to_string(), ProxyGroupType::Relay);
+        chain.proxies = vec!["^Entry$".to_string(), "^Exit$".to_string()];
+        let mut long = ProxyGroupConfig::new("Long".to_string(), ProxyGroupType::Relay);
+        long.proxies = vec![".*".to_string(), "[]DIRECT".to_string()];
+        let mut ext = ExtraSettings::default();
+
+        let output = proxy_to_surge(
+            &mut nodes,
+            "[General]\nloglevel = notify\n",
+            &mut Vec::new(),
+            &vec![chain, long],
+            4,
+            &mut ext,
+        )
+        .await
+        .unwrap();
+
+        let exit_line = output
+            .lines()
+            .find(|line| line.starts_with("Exit = "))
+            .unwrap();
+        assert!(exit_line.ends_with(", underlying-proxy=Entry"));
+        assert!(output.contains("Chain = select, Exit"));
+        assert!(!output.contains("Long = "));
+    }
+
+    #[tokio::test]
+    async fn test_tls13_only_added_to_tls_nodes() {
+        let mut nodes = vec![
+            trojan_node("Trojan"),
+            Proxy {
+                proxy_type: ProxyType::Shadowsocks,
+                remark: "SS".to_string(),
+                hostname: "example.com".to_string(),
+                port: 8388,
+                encrypt_method: Some("aes-128-gcm".to_string()),
+                password: Some("secret".to_string()),
+                ..Default::default()
+            },
+        ];
+        let mut ext = ExtraSettings {
+            tls13: Some(true),
+            ..Default::default()
+        };
+
+        let output = proxy_to_surge(
+            &mut nodes,
+            "[General]\nloglevel = notify\n",
+            &mut Vec::new(),
+            &vec![],
+            4,
+            &mut ext,
+        )
+        .await
+        .unwrap();
+
+        let line = |name: &str| {
+            output
+                .lines()
+                .find(|line| line.starts_with(&format!("{} = ", name)))
+                .unwrap()
+                .to_string()
+        };
+        assert!(line("Trojan").contains(", tls13=true"));
+        assert!(!line("SS").contains("tls13"));
+    }
+
+    #[tokio::test]
+    async fn test_flags_only_added_where_surge_takes_them() {
+        let mut nodes = vec![
+            Proxy {
+                block_quic: Some(false),
+                test_url: Some("http://cp.cloudflare.com/".to_string()),
+                ..trojan_node("Trojan")
+            },
+            Proxy {
+                proxy_type: ProxyType::HTTP,
+                remark: "HTTP".to_string(),
+                hostname: "example.com".to_string(),
+                port: 8080,
+                udp: Some(true),
+                ..Default::default()
+            },
+            Proxy {
+                proxy_type: ProxyType::Hysteria2,
+                remark: "Hy2".to_string(),
+                hostname: "example.com".to_string(),
+                port: 443,
+                password: Some("secret".to_string()),
+                ..Default::default()
+            },
+        ];
+        let mut ext = ExtraSettings {
+            tfo: Some(true),
+            udp: Some(true),
+            ..Default::default()
+        };
+
+        let output = proxy_to_surge(
+            &mut nodes.clone(),
+            "[General]\nloglevel = notify\n",
+            &mut Vec::new(),
+            &vec![],
+            4,
+            &mut ext,
+        )
+        .await
+        .unwrap();
+        let line = |name: &str| {
+            output
+                .lines()
+                .find(|line| line.starts_with(&format!("{} = ", name)))
+                .unwrap()
+                .to_string()
+        };
+        assert!(line("Trojan").ends_with(
+            ", tfo=true, udp-relay=true, block-quic=off, test-url=http://cp.cloudflare.com/"
+        ));
+        assert!(line("HTTP").ends_with(", tls=false, tfo=true"));
+        assert!(!line("Hy2").contains("tfo") && !line("Hy2").contains("udp-relay"));
+
+        // The request overrides the nodes
+        ext.block_quic = Some(true);
+        let output = proxy_to_surge(
+            &mut nodes,
+            "[General]\nloglevel = notify\n",
+            &mut Vec::new(),
+            &vec![],
+            4,
+            &mut ext,
+        )
+        .await
+        .unwrap();
+        assert!(output.contains("block-quic=on"));
+        assert!(!output.contains("block-quic=off"));
+    }
+
+    const SCRIPT_BASE: &str = "[Script]\n\
+        http-response ^https://api\\.example\\.com script-path=https://example.com/a.js\n\
+        [MITM]\n\
+        hostname = api.example.com\n\
+        [General]\n\
+        loglevel = notify\n\
+        [URL Rewrite]\n\
+        ^https://ad\\.example\\.com - reject\n\
+        [Rule]\n\
+        DOMAIN,example.com,Proxy\n\
+        FINAL,DIRECT\n";
+
+    #[tokio::test]
+    async fn test_base_sections_kept_in_surge_order() {
+        let mut nodes = vec![trojan_node("Trojan")];
+        let mut ext = ExtraSettings::default();
+
+        let output = proxy_to_surge(
+            &mut nodes,
+            SCRIPT_BASE,
+            &mut Vec::new(),
+            &vec![],
+            4,
+            &mut ext,
+        )
+        .await
+        .unwrap();
+
+        let headers: Vec<&str> = output
+            .lines()
+            .filter(|line| line.starts_with('['))
+            .collect();
+        assert_eq!(
+            headers,
+            [
+                "[General]",
+                "[Proxy]",
+                "[Rule]",
+                "[URL Rewrite]",
+                "[Script]",
+                "[MITM]"
+            ]
+        );
+        assert!(output.contains(
+            "http-response ^https://api\\.example\\.com script-path=https://example.com/a.js\n"
+        ));
+        assert!(output.contains("^https://ad\\.example\\.com - reject\n"));
+        assert!(output.contains("hostname = api.example.com\n"));
+    }
+
+    #[tokio::test]
+    async fn test_surge_module_keeps_rules_only() {
+        let mut nodes = vec![trojan_node("Trojan")];
+        let mut ext = ExtraSettings {
+            surge_module: true,
+            ..Default::default()
+        };
+
+        let output = proxy_to_surge(
+            &mut nodes,
+            SCRIPT_BASE,
+            &mut Vec::new(),
+            &vec![],
+            4,
+            &mut ext,
+        )
+        .await
+        .unwrap();
+
+        assert!(output.starts_with("#!name=subconverter\n#!desc="));
+        let headers: Vec<&str> = output
+            .lines()
+            .filter(|line| line.starts_with('['))
+            .collect();
+        assert_eq!(headers, ["[Rule]", "[URL Rewrite]", "[Script]", "[MITM]"]);
+        assert!(output.contains("DOMAIN,example.com,Proxy\n"));
+        assert!(!output.contains("FINAL"));
+        assert!(output.contains("hostname = %APPEND% api.example.com\n"));
+        assert!(!output.contains("Trojan = "));
+
+        // Surfboard has no modules
+        let output = proxy_to_surge(
+            &mut nodes,
+            SCRIPT_BASE,
+            &mut Vec::new(),
+            &vec![],
+            -3,
+            &mut ext,
+        )
+        .await
+        .unwrap();
+        assert!(output.contains("[Proxy]\n"));
+    }
 }
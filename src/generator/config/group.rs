@@ -2,10 +2,18 @@
 //!
 //! This module provides functionality for generating proxy groups.
 
+use std::collections::{HashMap, HashSet};
+
+use log::warn;
+
 use crate::{
-    models::ExtraSettings,
+    generator::ruleconvert::common::is_builtin_policy,
+    models::{
+        ExtraSettings, ProxyGroupConfig, ProxyGroupConfigs, ProxyGroupType, RegexMatchConfig,
+        RulesetContent, SubconverterTarget,
+    },
     utils::{
-        matcher::{apply_matcher, reg_find},
+        matcher::{apply_matcher, out_of_range_group_ids, reg_find},
         starts_with,
     },
     Proxy,
@@ -17,8 +25,10 @@ use crate::{
 ///
 /// * `rule` - The rule to apply to filter nodes
 /// * `nodelist` - List of all available proxy nodes
+/// * `group_type` - Type of the group being generated, subscription info nodes
+///   are only added to select groups
 /// * `filtered_nodelist` - Output parameter that will contain the filtered node list
-/// * `add_direct` - Whether to add direct connection to the list
+/// * `add_direct` - Whether to fall back to DIRECT when no node matches
 /// * `ext` - Extra settings
 ///
 /// # Returns
@@ -27,25 +37,23 @@ use crate::{
 pub fn group_generate(
     rule: &str,
     nodelist: &[Proxy],
+    group_type: &ProxyGroupType,
     filtered_nodelist: &mut Vec<String>,
     add_direct: bool,
     ext: &ExtraSettings,
 ) {
-    // Rule parsing
-    if starts_with(rule, "[]") && add_direct {
-        filtered_nodelist.push(rule[2..].to_string());
+    // Rule parsing, literal members are never matched against the nodes and
+    // are listed once, where they are first given
+    if let Some(name) = rule.strip_prefix("[]") {
+        push_literal(filtered_nodelist, name);
+    } else if is_builtin_policy(rule) {
+        // Built-in policies are never node names, even without the `[]`
+        push_literal(filtered_nodelist, rule);
     } else if starts_with(rule, "script:") && ext.authorized {
         // TODO: javascript
     } else {
         // Include only nodes that match the rule
-        for node in nodelist {
-            let mut real_rule = String::new();
-            if apply_matcher(rule, &mut real_rule, node) {
-                if real_rule.is_empty() || reg_find(&node.remark, &real_rule) {
-                    filtered_nodelist.push(node.remark.clone());
-                }
-            }
-        }
+        filtered_nodelist.extend(matching_remarks(rule, nodelist, group_type));
 
         if filtered_nodelist.is_empty() && add_direct {
             filtered_nodelist.push("DIRECT".to_string());
@@ -53,6 +61,310 @@ pub fn group_generate(
     }
 }
 
+/// Add a literal member unless the group already lists it
+fn push_literal(filtered_nodelist: &mut Vec<String>, name: &str) {
+    if !filtered_nodelist.iter().any(|member| member == name) {
+        filtered_nodelist.push(name.to_string());
+    }
+}
+
+/// Node remarks matched by each rule, shared by all groups generated from the
+/// same node list so a rule used by several groups scans the nodes only once
+#[derive(Debug, Default)]
+pub struct GroupMatchCache {
+    matches: HashMap<(String, bool), Vec<String>>,
+}
+
+/// Same as [`group_generate`], but looks matched nodes up in `cache` first
+///
+/// The cache must only be shared between calls with the same `nodelist`.
+pub fn group_generate_cached(
+    rule: &str,
+    nodelist: &[Proxy],
+    group_type: &ProxyGroupType,
+    filtered_nodelist: &mut Vec<String>,
+    add_direct: bool,
+    ext: &ExtraSettings,
+    cache: &mut GroupMatchCache,
+) {
+    if starts_with(rule, "[]") || starts_with(rule, "script:") || is_builtin_policy(rule) {
+        group_generate(
+            rule,
+            nodelist,
+            group_type,
+            filtered_nodelist,
+            add_direct,
+            ext,
+        );
+        return;
+    }
+
+    // Info nodes only go to select groups, so those are cached apart
+    let key = (rule.to_string(), *group_type == ProxyGroupType::Select);
+    let matched = cache
+        .matches
+        .entry(key)
+        .or_insert_with(|| matching_remarks(rule, nodelist, group_type));
+    filtered_nodelist.extend(matched.iter().cloned());
+
+    if filtered_nodelist.is_empty() && add_direct {
+        filtered_nodelist.push("DIRECT".to_string());
+    }
+}
+
+/// Remarks of the nodes a matcher rule selects, in node order
+///
+/// Rules with group ids no node comes from match nothing, so a typo in a
+/// negated range does not pull in every node.
+fn matching_remarks(rule: &str, nodelist: &[Proxy], group_type: &ProxyGroupType) -> Vec<String> {
+    if let Some(ids) = out_of_range_group_ids(rule, nodelist) {
+        warn!(
+            "Group member {} refers to ids {} no subscription has, it matches no node",
+            rule, ids
+        );
+        return Vec::new();
+    }
+
+    let mut remarks = Vec::new();
+    for node in nodelist {
+        if node.info_node && *group_type != ProxyGroupType::Select {
+            continue;
+        }
+        let mut real_rule = String::new();
+        if apply_matcher(rule, &mut real_rule, node) {
+            if real_rule.is_empty() || reg_find(&node.remark, &real_rule) {
+                remarks.push(node.remark.clone());
+            }
+        }
+    }
+    remarks
+}
+
+/// Turn members naming a built-in policy or a group defined before into
+/// literal `[]` members, so they are kept as they are instead of being
+/// matched against node remarks
+///
+/// Groups have to come before the groups listing them, members naming a group
+/// defined later are warned about and left to node matching.
+pub fn resolve_group_references(groups: &mut ProxyGroupConfigs) {
+    let names: Vec<String> = groups.iter().map(|group| group.name.clone()).collect();
+    for (index, group) in groups.iter_mut().enumerate() {
+        for rule in group.proxies.iter_mut() {
+            if starts_with(rule, "[]") || starts_with(rule, "!!") || starts_with(rule, "script:") {
+                continue;
+            }
+            if is_builtin_policy(rule) || names[..index].contains(rule) {
+                rule.insert_str(0, "[]");
+            } else if names[index..].contains(rule) {
+                warn!(
+                    "Group {} lists group {} before it is defined, define {} first to keep it as a member",
+                    group.name, rule, rule
+                );
+            }
+        }
+    }
+}
+
+/// `name` changed into a group name `target` can refer to
+///
+/// Targets writing a group as `name = type, member, ...` lose everything after
+/// a comma or an equals sign in the name, sing-box tags must not start with a
+/// digit.
+fn target_group_name(name: &str, target: &SubconverterTarget) -> String {
+    match target {
+        SubconverterTarget::Surge(_)
+        | SubconverterTarget::Surfboard
+        | SubconverterTarget::Mellow
+        | SubconverterTarget::Quantumult
+        | SubconverterTarget::QuantumultX
+        | SubconverterTarget::Loon => name.replace([',', '='], "_").trim().to_string(),
+        SubconverterTarget::SingBox if name.starts_with(|c: char| c.is_ascii_digit()) => {
+            format!("_{}", name)
+        }
+        _ => name.to_string(),
+    }
+}
+
+/// Rename the groups `target` can not refer to, in the groups, their `[]`
+/// members and the rulesets sending traffic to them alike
+///
+/// A renamed group colliding with another group or a built-in policy gets a
+/// `_2`, `_3`, ... suffix. Every rename is logged.
+///
+/// # Returns
+/// The old and new names of the renamed groups
+pub fn sanitize_group_names(
+    target: &SubconverterTarget,
+    groups: &mut ProxyGroupConfigs,
+    rulesets: &mut [RulesetContent],
+) -> Vec<(String, String)> {
+    let mut taken: HashSet<String> = groups.iter().map(|group| group.name.clone()).collect();
+    let mut renames = Vec::new();
+    for group in groups.iter() {
+        let legal = target_group_name(&group.name, target);
+        if legal == group.name || renames.iter().any(|(old, _)| old == &group.name) {
+            continue;
+        }
+        let mut name = legal.clone();
+        let mut suffix = 2;
+        while name.is_empty() || taken.contains(&name) || is_builtin_policy(&name) {
+            name = format!("{}_{}", legal, suffix);
+            suffix += 1;
+        }
+        warn!(
+            "Group {} is renamed to {} for {}",
+            group.name,
+            name,
+            target.to_str()
+        );
+        taken.insert(name.clone());
+        renames.push((group.name.clone(), name));
+    }
+    if renames.is_empty() {
+        return renames;
+    }
+
+    let renamed: HashMap<&str, &str> = renames
+        .iter()
+        .map(|(old, new)| (old.as_str(), new.as_str()))
+        .collect();
+    for group in groups.iter_mut() {
+        if let Some(name) = renamed.get(group.name.as_str()) {
+            group.name = name.to_string();
+        }
+        for member in group.proxies.iter_mut() {
+            if let Some(name) = member.strip_prefix("[]").and_then(|name| renamed.get(name)) {
+                *member = format!("[]{}", name);
+            }
+        }
+    }
+    for ruleset in rulesets.iter_mut() {
+        if let Some(name) = renamed.get(ruleset.group.as_str()) {
+            ruleset.group = name.to_string();
+        }
+    }
+    renames
+}
+
+/// Countries recognized by `autogroup` when the settings list none, as the
+/// group name and the pattern matched against remarks
+pub const DEFAULT_AUTOGROUP_COUNTRIES: &[(&str, &str)] = &[
+    ("🇭🇰 Hong Kong", "(?i)🇭🇰|香港|(^|[^a-z])HK([^a-z]|$)|Hong ?Kong"),
+    ("🇹🇼 Taiwan", "(?i)🇹🇼|台湾|台灣|(^|[^a-z])TW([^a-z]|$)|Taiwan"),
+    ("🇯🇵 Japan", "(?i)🇯🇵|日本|东京|大阪|(^|[^a-z])JP([^a-z]|$)|Japan|Tokyo|Osaka"),
+    ("🇸🇬 Singapore", "(?i)🇸🇬|新加坡|狮城|(^|[^a-z])SG([^a-z]|$)|Singapore"),
+    ("🇰🇷 Korea", "(?i)🇰🇷|韩国|韓國|(^|[^a-z])KR([^a-z]|$)|Korea|Seoul"),
+    ("🇺🇸 United States", "(?i)🇺🇸|美国|美國|(^|[^a-z])US([^a-z]|$)|United States|America|Los Angeles|San Jose|Seattle"),
+    ("🇬🇧 United Kingdom", "(?i)🇬🇧|英国|英國|(^|[^a-z])(UK|GB)([^a-z]|$)|United Kingdom|Britain|London"),
+    ("🇩🇪 Germany", "(?i)🇩🇪|德国|德國|(^|[^a-z])DE([^a-z]|$)|Germany|Frankfurt"),
+];
+
+/// Name of the group collecting the nodes of small and unknown countries
+pub const AUTOGROUP_OTHER: &str = "Other";
+
+/// [`DEFAULT_AUTOGROUP_COUNTRIES`] as rules, the pattern to match and the
+/// country name as its replacement
+pub fn default_countries() -> Vec<RegexMatchConfig> {
+    DEFAULT_AUTOGROUP_COUNTRIES
+        .iter()
+        .map(|(name, pattern)| {
+            RegexMatchConfig::new(pattern.to_string(), name.to_string(), String::new())
+        })
+        .collect()
+}
+
+/// Index of the first country whose pattern matches `remark`
+pub fn node_country(remark: &str, countries: &[RegexMatchConfig]) -> Option<usize> {
+    countries
+        .iter()
+        .position(|country| reg_find(remark, &country._match))
+}
+
+/// Index of the first country whose pattern matches the country code the
+/// GeoIP database found for `node`, like `HK` for the `(^|[^a-z])HK([^a-z]|$)`
+/// of the built-in countries
+pub fn geo_country(node: &Proxy, countries: &[RegexMatchConfig]) -> Option<usize> {
+    if node.country.is_empty() {
+        return None;
+    }
+    node_country(&node.country, countries)
+}
+
+/// Groups for the `autogroup` option: a url-test group per country with at
+/// least `min_nodes` nodes, a select group for the remaining nodes and a
+/// select group named `name` choosing between them
+///
+/// Each node belongs to the first country in `countries` whose pattern
+/// matches its remark, the group name is the replacement of the rule. The
+/// country groups select nodes by the same pattern, so they still match once
+/// remarks are changed while generating the config. Nodes matching no pattern
+/// go by their GeoIP country (see [`geo_country`]) and are listed by remark.
+/// Without `countries` the [`DEFAULT_AUTOGROUP_COUNTRIES`] are used.
+pub fn auto_groups(
+    nodes: &[Proxy],
+    countries: &[RegexMatchConfig],
+    min_nodes: usize,
+    name: &str,
+) -> ProxyGroupConfigs {
+    let defaults: Vec<RegexMatchConfig>;
+    let countries = if countries.is_empty() {
+        defaults = default_countries();
+        &defaults
+    } else {
+        countries
+    };
+
+    let mut counts = vec![0usize; countries.len()];
+    let mut located = vec![Vec::new(); countries.len()];
+    let mut unknown = Vec::new();
+    for node in nodes.iter().filter(|node| !node.info_node) {
+        if let Some(index) = node_country(&node.remark, countries) {
+            counts[index] += 1;
+        } else if let Some(index) = geo_country(node, countries) {
+            counts[index] += 1;
+            located[index].push(regex::escape(&node.remark));
+        } else {
+            unknown.push(regex::escape(&node.remark));
+        }
+    }
+
+    let mut groups = Vec::new();
+    let mut other = ProxyGroupConfig::new(AUTOGROUP_OTHER.to_string(), ProxyGroupType::Select);
+    for ((country, count), located) in countries.iter().zip(counts).zip(located) {
+        if count == 0 {
+            continue;
+        }
+        let mut rules = vec![country._match.clone()];
+        if !located.is_empty() {
+            rules.push(located.join("|"));
+        }
+        if count < min_nodes.max(1) {
+            other.proxies.append(&mut rules);
+            continue;
+        }
+        let mut group = ProxyGroupConfig::new(country.replace.clone(), ProxyGroupType::URLTest);
+        group.proxies = rules;
+        groups.push(group);
+    }
+    if !unknown.is_empty() {
+        other.proxies.push(unknown.join("|"));
+    }
+    if !other.proxies.is_empty() {
+        groups.push(other);
+    }
+    if groups.is_empty() {
+        return groups;
+    }
+
+    let mut select = ProxyGroupConfig::new(name.to_string(), ProxyGroupType::Select);
+    select.proxies = groups
+        .iter()
+        .map(|group| format!("[]{}", group.name))
+        .collect();
+    groups.insert(0, select);
+    groups
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -105,6 +417,26 @@ mod tests {
         ]
     }
 
+    #[test]
+    fn test_group_generate_keeps_rule_order_for_relays() {
+        let nodes = create_test_nodes();
+        let mut filtered = Vec::new();
+        let ext = ExtraSettings::default();
+
+        for rule in ["^US", "[]Proxy", "^HK Node 2$"] {
+            group_generate(
+                rule,
+                &nodes,
+                &ProxyGroupType::Relay,
+                &mut filtered,
+                true,
+                &ext,
+            );
+        }
+
+        assert_eq!(filtered, vec!["US Node 1", "Proxy", "HK Node 2"]);
+    }
+
     #[test]
     fn test_group_generate_direct_string() {
         let nodes = create_test_nodes();
@@ -112,12 +444,74 @@ mod tests {
         let ext = ExtraSettings::default();
 
         // 测试直接字符串规则
-        group_generate("[]DIRECT", &nodes, &mut filtered, true, &ext);
+        group_generate(
+            "[]DIRECT",
+            &nodes,
+            &ProxyGroupType::Select,
+            &mut filtered,
+            true,
+            &ext,
+        );
 
         assert_eq!(filtered.len(), 1);
         assert_eq!(filtered[0], "DIRECT");
     }
 
+    #[test]
+    fn test_group_generate_literal_members_once_in_position() {
+        let nodes = create_test_nodes();
+        let mut filtered = Vec::new();
+        let ext = ExtraSettings::default();
+
+        for rule in ["JP", "[]REJECT", "[]HK Node 1", "US", "[]REJECT", "[]Auto"] {
+            group_generate(
+                rule,
+                &nodes,
+                &ProxyGroupType::Select,
+                &mut filtered,
+                true,
+                &ext,
+            );
+        }
+        assert_eq!(
+            filtered,
+            ["JP Node 1", "REJECT", "HK Node 1", "US Node 1", "Auto"]
+        );
+
+        // Literals are kept even where nothing falls back to DIRECT
+        let mut filtered = Vec::new();
+        group_generate(
+            "[]DIRECT",
+            &nodes,
+            &ProxyGroupType::Select,
+            &mut filtered,
+            false,
+            &ext,
+        );
+        assert_eq!(filtered, ["DIRECT"]);
+    }
+
+    #[test]
+    fn test_group_generate_builtin_policy_not_matched_against_nodes() {
+        let mut nodes = create_test_nodes();
+        nodes[0].remark = "REJECT backup".to_string();
+        let mut filtered = Vec::new();
+        let ext = ExtraSettings::default();
+
+        for rule in ["REJECT", "reject-tinygif", "PASS"] {
+            group_generate(
+                rule,
+                &nodes,
+                &ProxyGroupType::Select,
+                &mut filtered,
+                true,
+                &ext,
+            );
+        }
+
+        assert_eq!(filtered, ["REJECT", "reject-tinygif", "PASS"]);
+    }
+
     #[test]
     fn test_group_generate_with_group_match() {
         let nodes = create_test_nodes();
@@ -125,7 +519,14 @@ mod tests {
         let ext = ExtraSettings::default();
 
         // 测试组匹配规则
-        group_generate("!!GROUP=HK", &nodes, &mut filtered, true, &ext);
+        group_generate(
+            "!!GROUP=HK",
+            &nodes,
+            &ProxyGroupType::Select,
+            &mut filtered,
+            true,
+            &ext,
+        );
 
         assert_eq!(filtered.len(), 2);
         assert!(filtered.contains(&"HK Node 1".to_string()));
@@ -139,7 +540,14 @@ mod tests {
         let ext = ExtraSettings::default();
 
         // 测试类型匹配规则
-        group_generate("!!TYPE=VMESS", &nodes, &mut filtered, true, &ext);
+        group_generate(
+            "!!TYPE=VMESS",
+            &nodes,
+            &ProxyGroupType::Select,
+            &mut filtered,
+            true,
+            &ext,
+        );
 
         assert_eq!(filtered.len(), 1);
         assert_eq!(filtered[0], "JP Node 1");
@@ -152,13 +560,53 @@ mod tests {
         let ext = ExtraSettings::default();
 
         // 测试端口匹配规则
-        group_generate("!!PORT=443", &nodes, &mut filtered, true, &ext);
+        group_generate(
+            "!!PORT=443",
+            &nodes,
+            &ProxyGroupType::Select,
+            &mut filtered,
+            true,
+            &ext,
+        );
 
         assert_eq!(filtered.len(), 2);
         assert!(filtered.contains(&"HK Node 1".to_string()));
         assert!(filtered.contains(&"JP Node 1".to_string()));
     }
 
+    #[test]
+    fn test_group_generate_with_group_id_match() {
+        let mut nodes = create_test_nodes();
+        nodes.push(Proxy {
+            id: 5,
+            group_id: -1,
+            remark: "HK Self Hosted".to_string(),
+            ..Default::default()
+        });
+        let ext = ExtraSettings::default();
+        let generate = |rule: &str| {
+            let mut filtered = Vec::new();
+            group_generate(
+                rule,
+                &nodes,
+                &ProxyGroupType::Select,
+                &mut filtered,
+                false,
+                &ext,
+            );
+            filtered
+        };
+
+        assert_eq!(generate("!!GROUPID=1!!Node 2"), ["HK Node 2"]);
+        assert_eq!(generate("!!GROUPID=2-3"), ["JP Node 1", "US Node 1"]);
+        assert_eq!(generate("!!GROUPID=!1"), ["JP Node 1", "US Node 1"]);
+        assert_eq!(generate("!!INSERT=1!!HK"), ["HK Self Hosted"]);
+        // Ids no subscription has match nothing, even negated
+        assert!(generate("!!GROUPID=7").is_empty());
+        assert!(generate("!!GROUPID=!7").is_empty());
+        assert!(generate("!!INSERT=2").is_empty());
+    }
+
     #[test]
     fn test_group_generate_empty_result_with_direct() {
         let nodes = create_test_nodes();
@@ -166,7 +614,14 @@ mod tests {
         let ext = ExtraSettings::default();
 
         // 测试没有匹配节点时添加 DIRECT
-        group_generate("!!GROUP=SG", &nodes, &mut filtered, true, &ext);
+        group_generate(
+            "!!GROUP=SG",
+            &nodes,
+            &ProxyGroupType::Select,
+            &mut filtered,
+            true,
+            &ext,
+        );
 
         assert_eq!(filtered.len(), 1);
         assert_eq!(filtered[0], "DIRECT");
@@ -179,7 +634,14 @@ mod tests {
         let ext = ExtraSettings::default();
 
         // 测试没有匹配节点且不添加 DIRECT
-        group_generate("!!GROUP=SG", &nodes, &mut filtered, false, &ext);
+        group_generate(
+            "!!GROUP=SG",
+            &nodes,
+            &ProxyGroupType::Select,
+            &mut filtered,
+            false,
+            &ext,
+        );
 
         assert_eq!(filtered.len(), 0);
     }
@@ -191,7 +653,14 @@ mod tests {
         let ext = ExtraSettings::default();
 
         // 测试正则表达式匹配
-        group_generate("Node \\d", &nodes, &mut filtered, true, &ext);
+        group_generate(
+            "Node \\d",
+            &nodes,
+            &ProxyGroupType::Select,
+            &mut filtered,
+            true,
+            &ext,
+        );
 
         assert_eq!(filtered.len(), 4);
         assert!(filtered.contains(&"HK Node 1".to_string()));
@@ -199,4 +668,209 @@ mod tests {
         assert!(filtered.contains(&"JP Node 1".to_string()));
         assert!(filtered.contains(&"US Node 1".to_string()));
     }
+
+    #[test]
+    fn test_group_generate_info_nodes_only_in_select() {
+        let mut nodes = create_test_nodes();
+        nodes.push(Proxy {
+            remark: "剩余流量: 1.00GB / 10.00GB".to_string(),
+            proxy_type: ProxyType::Shadowsocks,
+            info_node: true,
+            ..Default::default()
+        });
+        let ext = ExtraSettings::default();
+
+        let mut filtered = Vec::new();
+        group_generate(
+            ".*",
+            &nodes,
+            &ProxyGroupType::Select,
+            &mut filtered,
+            true,
+            &ext,
+        );
+        assert_eq!(filtered.len(), 5);
+
+        let mut filtered = Vec::new();
+        group_generate(
+            ".*",
+            &nodes,
+            &ProxyGroupType::URLTest,
+            &mut filtered,
+            true,
+            &ext,
+        );
+        assert_eq!(filtered.len(), 4);
+        assert!(!filtered.iter().any(|name| name.starts_with("剩余流量")));
+    }
+
+    #[test]
+    fn test_resolve_group_references() {
+        let group = |name: &str, proxies: &[&str]| {
+            let mut group = ProxyGroupConfig::new(name.to_string(), ProxyGroupType::Select);
+            group.proxies = proxies.iter().map(|rule| rule.to_string()).collect();
+            group
+        };
+        let mut groups = vec![
+            group("HK", &["HK", "Proxy"]),
+            group("Auto", &[".*"]),
+            group(
+                "Proxy",
+                &["Auto", "HK", "[]Auto", "REJECT", "!!GROUP=US", "JP"],
+            ),
+        ];
+
+        resolve_group_references(&mut groups);
+
+        // A group naming itself or a later group is still a node pattern
+        assert_eq!(groups[0].proxies, ["HK", "Proxy"]);
+        assert_eq!(groups[1].proxies, [".*"]);
+        assert_eq!(
+            groups[2].proxies,
+            ["[]Auto", "[]HK", "[]Auto", "[]REJECT", "!!GROUP=US", "JP"]
+        );
+    }
+
+    #[test]
+    fn test_sanitize_group_names() {
+        let group = |name: &str, proxies: &[&str]| {
+            let mut group = ProxyGroupConfig::new(name.to_string(), ProxyGroupType::Select);
+            group.proxies = proxies.iter().map(|rule| rule.to_string()).collect();
+            group
+        };
+        let groups = vec![
+            group("HK, Fast", &[".*"]),
+            group("HK_ Fast", &[".*"]),
+            group("1 Media", &[".*"]),
+            group("Proxy", &["[]HK, Fast", "[]1 Media", "HK, Fast"]),
+        ];
+        let rulesets = || {
+            let mut rulesets = vec![
+                RulesetContent::new("rules/Streaming.list", "1 Media"),
+                RulesetContent::new("", "HK, Fast"),
+            ];
+            rulesets[1].set_rule_content("[]FINAL");
+            rulesets
+        };
+
+        let mut surge_groups = groups.clone();
+        let mut surge_rulesets = rulesets();
+        let renames = sanitize_group_names(
+            &SubconverterTarget::Surge(4),
+            &mut surge_groups,
+            &mut surge_rulesets,
+        );
+        assert_eq!(
+            renames,
+            [("HK, Fast".to_string(), "HK_ Fast_2".to_string())]
+        );
+        assert_eq!(surge_groups[0].name, "HK_ Fast_2");
+        assert_eq!(surge_groups[1].name, "HK_ Fast");
+        // Node patterns are no group references and stay as they are
+        assert_eq!(
+            surge_groups[3].proxies,
+            ["[]HK_ Fast_2", "[]1 Media", "HK, Fast"]
+        );
+        assert_eq!(surge_rulesets[1].group, "HK_ Fast_2");
+
+        let mut singbox_groups = groups.clone();
+        let mut singbox_rulesets = rulesets();
+        sanitize_group_names(
+            &SubconverterTarget::SingBox,
+            &mut singbox_groups,
+            &mut singbox_rulesets,
+        );
+        assert_eq!(singbox_groups[0].name, "HK, Fast");
+        assert_eq!(singbox_groups[2].name, "_1 Media");
+        assert_eq!(singbox_groups[3].proxies[1], "[]_1 Media");
+        assert_eq!(singbox_rulesets[0].group, "_1 Media");
+
+        let mut clash_groups = groups.clone();
+        assert!(sanitize_group_names(
+            &SubconverterTarget::Clash,
+            &mut clash_groups,
+            &mut rulesets()
+        )
+        .is_empty());
+    }
+
+    #[test]
+    fn test_auto_groups_by_country() {
+        let mut nodes = create_test_nodes();
+        nodes.push(Proxy {
+            remark: "Relay (1)".to_string(),
+            ..Default::default()
+        });
+        nodes.push(Proxy {
+            remark: "HK traffic: 1 GB".to_string(),
+            info_node: true,
+            ..Default::default()
+        });
+
+        let groups = auto_groups(&nodes, &[], 2, "Proxy");
+        let names: Vec<&str> = groups.iter().map(|group| group.name.as_str()).collect();
+        assert_eq!(names, vec!["Proxy", "🇭🇰 Hong Kong", "Other"]);
+        assert_eq!(groups[0].group_type, ProxyGroupType::Select);
+        assert_eq!(groups[0].proxies, vec!["[]🇭🇰 Hong Kong", "[]Other"]);
+        assert_eq!(groups[1].group_type, ProxyGroupType::URLTest);
+
+        let ext = ExtraSettings::default();
+        let members = |group: &ProxyGroupConfig| {
+            let mut filtered = Vec::new();
+            for rule in &group.proxies {
+                group_generate(rule, &nodes, &group.group_type, &mut filtered, false, &ext);
+            }
+            filtered
+        };
+        assert_eq!(members(&groups[1]), vec!["HK Node 1", "HK Node 2"]);
+        // Japan and the US have one node each and fold into Other
+        assert_eq!(
+            members(&groups[2]),
+            vec!["JP Node 1", "US Node 1", "Relay (1)"]
+        );
+
+        let countries = vec![RegexMatchConfig::new(
+            "Node".to_string(),
+            "Nodes".to_string(),
+            String::new(),
+        )];
+        let groups = auto_groups(&nodes[..4], &countries, 2, "Auto");
+        let names: Vec<&str> = groups.iter().map(|group| group.name.as_str()).collect();
+        assert_eq!(names, vec!["Auto", "Nodes"]);
+        assert!(auto_groups(&[], &countries, 2, "Auto").is_empty());
+    }
+
+    #[test]
+    fn test_auto_groups_by_geoip_country() {
+        let mut nodes = create_test_nodes();
+        for (remark, country) in [("Node-01", "HK"), ("Node-02", "FR"), ("Node-03", "")] {
+            nodes.push(Proxy {
+                remark: remark.to_string(),
+                country: country.to_string(),
+                ..Default::default()
+            });
+        }
+
+        let groups = auto_groups(&nodes, &[], 2, "Proxy");
+        let names: Vec<&str> = groups.iter().map(|group| group.name.as_str()).collect();
+        assert_eq!(names, vec!["Proxy", "🇭🇰 Hong Kong", "Other"]);
+
+        let ext = ExtraSettings::default();
+        let members = |group: &ProxyGroupConfig| {
+            let mut filtered = Vec::new();
+            for rule in &group.proxies {
+                group_generate(rule, &nodes, &group.group_type, &mut filtered, false, &ext);
+            }
+            filtered
+        };
+        assert_eq!(
+            members(&groups[1]),
+            vec!["HK Node 1", "HK Node 2", "Node-01"]
+        );
+        // France is no built-in country, the node stays unknown
+        assert_eq!(
+            members(&groups[2]),
+            vec!["JP Node 1", "US Node 1", "Node-02", "Node-03"]
+        );
+    }
 }
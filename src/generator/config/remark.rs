@@ -2,8 +2,159 @@
 //!
 //! This module provides functionality for processing proxy remarks.
 
+use std::collections::HashSet;
+
+use crate::generator::config::group::{
+    default_countries, geo_country, node_country, AUTOGROUP_OTHER,
+};
+use crate::models::{Proxy, RegexMatchConfig};
+use crate::utils::cached_regex;
 use lazy_static::lazy_static;
+use log::{debug, warn};
 use regex::Regex;
+use std::collections::HashMap;
+
+/// Pattern renaming a node whose remark is taken, `{name}` is the remark and
+/// `{n}` counts up from 2
+pub const DEFAULT_DUPLICATE_REMARK_FORMAT: &str = "{name} {n}";
+
+/// Format of remarks with `append_type`, used when none or an invalid one is
+/// configured
+pub const DEFAULT_APPEND_TYPE_FORMAT: &str = "[{type}] {remark}";
+
+/// A value of the node an [`AppendTypeFormat`] placeholder stands for
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum TypeField {
+    Type,
+    Remark,
+    Host,
+    Port,
+}
+
+/// Letter case of a placeholder value, `{type:lower}` or `{type:upper}`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum TypeCase {
+    Keep,
+    Lower,
+    Upper,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum TypeSegment {
+    Text(String),
+    Field(TypeField, TypeCase),
+}
+
+/// Format adding the proxy type to remarks, e.g. `{remark} | {type:lower}`
+///
+/// The placeholders are `{type}`, `{remark}`, `{host}` and `{port}`, each
+/// may end in `:lower` or `:upper`. `{{` and `}}` stand for literal braces.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AppendTypeFormat {
+    segments: Vec<TypeSegment>,
+}
+
+impl AppendTypeFormat {
+    /// Parses `format`, naming the first placeholder or brace which is not
+    /// understood
+    pub fn parse(format: &str) -> Result<Self, String> {
+        let mut segments = Vec::new();
+        let mut text = String::new();
+        let mut chars = format.chars().peekable();
+        while let Some(c) = chars.next() {
+            match c {
+                '{' if chars.peek() == Some(&'{') => {
+                    chars.next();
+                    text.push('{');
+                }
+                '}' if chars.peek() == Some(&'}') => {
+                    chars.next();
+                    text.push('}');
+                }
+                '}' => return Err("unmatched '}'".to_string()),
+                '{' => {
+                    let mut placeholder = String::new();
+                    loop {
+                        match chars.next() {
+                            Some('}') => break,
+                            Some(c) => placeholder.push(c),
+                            None => return Err(format!("unclosed '{{{}'", placeholder)),
+                        }
+                    }
+                    let (name, case) = match placeholder.split_once(':') {
+                        Some((name, "lower")) => (name, TypeCase::Lower),
+                        Some((name, "upper")) => (name, TypeCase::Upper),
+                        Some(_) => return Err(format!("unknown modifier in {{{}}}", placeholder)),
+                        None => (placeholder.as_str(), TypeCase::Keep),
+                    };
+                    let field = match name {
+                        "type" => TypeField::Type,
+                        "remark" => TypeField::Remark,
+                        "host" => TypeField::Host,
+                        "port" => TypeField::Port,
+                        _ => return Err(format!("unknown placeholder {{{}}}", placeholder)),
+                    };
+                    if !text.is_empty() {
+                        segments.push(TypeSegment::Text(std::mem::take(&mut text)));
+                    }
+                    segments.push(TypeSegment::Field(field, case));
+                }
+                c => text.push(c),
+            }
+        }
+        if !text.is_empty() {
+            segments.push(TypeSegment::Text(text));
+        }
+        Ok(AppendTypeFormat { segments })
+    }
+
+    /// Parses `format`, an empty one is [`DEFAULT_APPEND_TYPE_FORMAT`] and
+    /// invalid ones fall back to it with a warning
+    pub fn new(format: &str) -> Self {
+        if format.is_empty() {
+            return Self::default();
+        }
+        Self::parse(format).unwrap_or_else(|e| {
+            warn!(
+                "Invalid append_type format '{}', using '{}': {}",
+                format, DEFAULT_APPEND_TYPE_FORMAT, e
+            );
+            Self::default()
+        })
+    }
+
+    /// Remark of `node` written in this format
+    pub fn apply(&self, node: &Proxy) -> String {
+        let mut remark = String::with_capacity(node.remark.len() + 8);
+        for segment in &self.segments {
+            let (field, case) = match segment {
+                TypeSegment::Text(text) => {
+                    remark.push_str(text);
+                    continue;
+                }
+                TypeSegment::Field(field, case) => (field, case),
+            };
+            let value = match field {
+                TypeField::Type => node.proxy_type.to_string().to_string(),
+                TypeField::Remark => node.remark.clone(),
+                TypeField::Host => node.hostname.clone(),
+                TypeField::Port => node.port.to_string(),
+            };
+            match case {
+                TypeCase::Keep => remark.push_str(&value),
+                TypeCase::Lower => remark.push_str(&value.to_lowercase()),
+                TypeCase::Upper => remark.push_str(&value.to_uppercase()),
+            }
+        }
+        remark
+    }
+}
+
+impl Default for AppendTypeFormat {
+    fn default() -> Self {
+        Self::parse(DEFAULT_APPEND_TYPE_FORMAT).expect("the default format is valid")
+    }
+}
 
 /// Processes a remark string according to a list of remark rules
 ///
@@ -12,11 +163,17 @@ use regex::Regex;
 /// * `remark` - The remark to process (will be modified in-place)
 /// * `remarks_list` - List of already processed remarks to avoid duplicates
 /// * `proc_comma` - Whether to process comma replacements
+/// * `duplicate_format` - Pattern renaming duplicates, see [`unique_remark`]
 ///
 /// # Returns
 ///
 /// Nothing, modifies the remark in-place
-pub fn process_remark(remark: &mut String, remarks_list: &Vec<String>, proc_comma: bool) {
+pub fn process_remark(
+    remark: &mut String,
+    remarks_list: &Vec<String>,
+    proc_comma: bool,
+    duplicate_format: &str,
+) {
     // Replace every '=' with '-' in the remark string to avoid parse errors from clients
     *remark = remark.replace('=', "-");
 
@@ -28,13 +185,11 @@ pub fn process_remark(remark: &mut String, remarks_list: &Vec<String>, proc_comm
     }
 
     // Ensure uniqueness by adding a number suffix if needed
-    let mut temp_remark = remark.clone();
-    let mut cnt = 2;
-    while remarks_list.contains(&temp_remark) {
-        temp_remark = format!("{} {}", remark, cnt);
-        cnt += 1;
-    }
-    *remark = temp_remark;
+    *remark = unique_remark(
+        remark,
+        |name| remarks_list.iter().any(|taken| taken == name),
+        duplicate_format,
+    );
 
     // Filter-related processing was added in the Rust implementation but isn't in
     // the original C++ processRemark function, so we'll keep it for additional functionality
@@ -42,6 +197,113 @@ pub fn process_remark(remark: &mut String, remarks_list: &Vec<String>, proc_comm
     // process_filters(remark, remarks_list);
 }
 
+/// Returns `remark`, or when `is_taken` says it is taken, the first name
+/// built from `format` which is not, e.g. `HK 2`, `HK 3`, ...
+///
+/// A format without `{n}` falls back to [`DEFAULT_DUPLICATE_REMARK_FORMAT`].
+pub fn unique_remark(remark: &str, is_taken: impl Fn(&str) -> bool, format: &str) -> String {
+    if !is_taken(remark) {
+        return remark.to_string();
+    }
+
+    let format = if format.contains("{n}") {
+        format
+    } else {
+        DEFAULT_DUPLICATE_REMARK_FORMAT
+    };
+    (2..)
+        .map(|n| {
+            format
+                .replace("{name}", remark)
+                .replace("{n}", &n.to_string())
+        })
+        .find(|candidate| !is_taken(candidate))
+        .unwrap_or_default()
+}
+
+/// Renames nodes sharing a remark with an earlier node, keeping their order,
+/// so that every exporter and group sees the same final names
+pub fn disambiguate_remarks(nodes: &mut [Proxy], format: &str) {
+    let mut taken: HashSet<String> = HashSet::with_capacity(nodes.len());
+    for node in nodes.iter_mut() {
+        let remark = unique_remark(&node.remark, |name| taken.contains(name), format);
+        if remark != node.remark {
+            debug!("Renamed duplicate remark '{}' to '{}'", node.remark, remark);
+            node.remark = remark;
+        }
+        taken.insert(node.remark.clone());
+    }
+}
+
+/// Renames every node after `template`, numbering them in their current order
+///
+/// The placeholders are `{remark}` for the current remark, `{country}` for
+/// the name of the first of `countries` matching it or else its GeoIP country
+/// code (`Other` when neither does, the built-in countries of `autogroup`
+/// when the list is empty), `{index}`
+/// counting the nodes of the same country, `{global_index}` counting all
+/// nodes and `{tag}` for the `tag:` of the subscription URL. Both counters start at 1 and take a zero padded width like
+/// `{index:02}`. Other text in braces is kept as written.
+///
+/// Remarks may collide afterwards, see [`disambiguate_remarks`].
+pub fn index_remarks(nodes: &mut [Proxy], template: &str, countries: &[RegexMatchConfig]) {
+    let defaults: Vec<RegexMatchConfig>;
+    let countries = if countries.is_empty() {
+        defaults = default_countries();
+        &defaults
+    } else {
+        countries
+    };
+
+    let mut counters: HashMap<String, usize> = HashMap::new();
+    for (position, node) in nodes.iter_mut().enumerate() {
+        let country =
+            match node_country(&node.remark, countries).or_else(|| geo_country(node, countries)) {
+                Some(index) => countries[index].replace.clone(),
+                None if !node.country.is_empty() => node.country.clone(),
+                None => AUTOGROUP_OTHER.to_string(),
+            };
+        let index = counters.entry(country.clone()).or_default();
+        *index += 1;
+        node.remark = render_index_template(template, |name, width| match name {
+            "remark" => Some(node.remark.clone()),
+            "country" => Some(country.clone()),
+            "tag" => Some(node.tag.clone()),
+            "index" => Some(format!("{:0width$}", *index, width = width)),
+            "global_index" => Some(format!("{:0width$}", position + 1, width = width)),
+            _ => None,
+        });
+    }
+}
+
+/// Replaces the `{name}` and `{name:0N}` placeholders of `template` with what
+/// `value` returns for the name and the width N, keeping those it has no
+/// value for
+fn render_index_template(template: &str, value: impl Fn(&str, usize) -> Option<String>) -> String {
+    let mut output = String::with_capacity(template.len());
+    let mut rest = template;
+    while let Some(start) = rest.find('{') {
+        output.push_str(&rest[..start]);
+        let Some(length) = rest[start..].find('}') else {
+            rest = &rest[start..];
+            break;
+        };
+        let placeholder = &rest[start..start + length + 1];
+        let inner = &placeholder[1..placeholder.len() - 1];
+        let (name, width) = match inner.split_once(':') {
+            Some((name, width)) => (name, width.parse().ok()),
+            None => (inner, Some(0)),
+        };
+        match width.and_then(|width| value(name, width)) {
+            Some(value) => output.push_str(&value),
+            None => output.push_str(placeholder),
+        }
+        rest = &rest[start + length + 1..];
+    }
+    output.push_str(rest);
+    output
+}
+
 /// Process filters in the remark string
 pub fn process_filters(remark: &mut String, remarks_list: &Vec<String>) {
     lazy_static! {
@@ -76,7 +338,7 @@ pub fn process_filters(remark: &mut String, remarks_list: &Vec<String>) {
             } else if arguments.starts_with("regex:") {
                 // Regex-based filter
                 let regex_arg = &arguments[6..];
-                if let Ok(re) = Regex::new(regex_arg) {
+                if let Ok(re) = cached_regex(regex_arg) {
                     if re.is_match(remark) {
                         if item.starts_with("filter") {
                             // For filter, we just remove the matched part
@@ -101,3 +363,125 @@ pub fn process_filters(remark: &mut String, remarks_list: &Vec<String>) {
     *remark = MULTI_SPACE_REGEX.replace_all(remark, " ").to_string();
     *remark = remark.trim().to_string();
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn nodes(remarks: &[&str]) -> Vec<Proxy> {
+        remarks
+            .iter()
+            .map(|remark| Proxy::default().set_remark(remark.to_string()))
+            .collect()
+    }
+
+    fn remarks(nodes: &[Proxy]) -> Vec<&str> {
+        nodes.iter().map(|node| node.remark.as_str()).collect()
+    }
+
+    #[test]
+    fn test_disambiguate_remarks() {
+        let mut list = nodes(&["HK", "HK", "JP", "HK 2", "HK"]);
+        disambiguate_remarks(&mut list, DEFAULT_DUPLICATE_REMARK_FORMAT);
+        assert_eq!(remarks(&list), ["HK", "HK 2", "JP", "HK 2 2", "HK 3"]);
+
+        let mut list = nodes(&["HK", "HK", "HK"]);
+        disambiguate_remarks(&mut list, "{name} #{n}");
+        assert_eq!(remarks(&list), ["HK", "HK #2", "HK #3"]);
+
+        // A format without a counter could never produce a free name
+        let mut list = nodes(&["HK", "HK"]);
+        disambiguate_remarks(&mut list, "{name}-copy");
+        assert_eq!(remarks(&list), ["HK", "HK 2"]);
+    }
+
+    #[test]
+    fn test_process_remark_is_stable() {
+        let mut remarks_list = Vec::new();
+        for remark in ["a=b", "a-b", "a-b"] {
+            let mut remark = remark.to_string();
+            process_remark(
+                &mut remark,
+                &remarks_list,
+                false,
+                DEFAULT_DUPLICATE_REMARK_FORMAT,
+            );
+            remarks_list.push(remark);
+        }
+        assert_eq!(remarks_list, ["a-b", "a-b 2", "a-b 3"]);
+    }
+
+    #[test]
+    fn test_index_remarks() {
+        let countries = vec![
+            RegexMatchConfig::new("(?i)HK|香港".to_string(), "HK".to_string(), String::new()),
+            RegexMatchConfig::new("JP".to_string(), "JP".to_string(), String::new()),
+        ];
+
+        let mut list = nodes(&["HK A", "JP B", "香港 C", "Relay"]);
+        index_remarks(&mut list, "{country} {index:02}", &countries);
+        assert_eq!(remarks(&list), ["HK 01", "JP 01", "HK 02", "Other 01"]);
+
+        let mut list = nodes(&["HK A", "JP B"]);
+        list[1].tag = "airport1".to_string();
+        index_remarks(&mut list, "{tag}|{remark}", &countries);
+        assert_eq!(remarks(&list), ["|HK A", "airport1|JP B"]);
+
+        let mut list = nodes(&["HK A", "JP B"]);
+        index_remarks(
+            &mut list,
+            "{global_index:03} {remark} {index} {unknown} {index:x} {",
+            &countries,
+        );
+        assert_eq!(
+            remarks(&list),
+            [
+                "001 HK A 1 {unknown} {index:x} {",
+                "002 JP B 1 {unknown} {index:x} {"
+            ]
+        );
+
+        // Templates without a counter collide and are numbered as duplicates
+        let mut list = nodes(&["HK A", "hk B", "JP C"]);
+        index_remarks(&mut list, "{country}", &countries);
+        disambiguate_remarks(&mut list, DEFAULT_DUPLICATE_REMARK_FORMAT);
+        assert_eq!(remarks(&list), ["HK", "HK 2", "JP"]);
+
+        // Remarks naming no country go by the GeoIP country
+        let mut list = nodes(&["Node-01", "Node-02", "Node-03", "HK A"]);
+        list[0].country = "JP".to_string();
+        list[1].country = "FR".to_string();
+        list[3].country = "JP".to_string();
+        index_remarks(&mut list, "{country} {index}", &countries);
+        assert_eq!(remarks(&list), ["JP 1", "FR 1", "Other 1", "HK 1"]);
+    }
+
+    #[test]
+    fn test_append_type_format() {
+        let node = Proxy {
+            proxy_type: crate::models::ProxyType::Trojan,
+            remark: "HK 01".to_string(),
+            hostname: "hk.example.com".to_string(),
+            port: 443,
+            ..Default::default()
+        };
+
+        assert_eq!(AppendTypeFormat::default().apply(&node), "[Trojan] HK 01");
+        assert_eq!(AppendTypeFormat::new("").apply(&node), "[Trojan] HK 01");
+        let format = AppendTypeFormat::parse("{remark} | {type:lower}").unwrap();
+        assert_eq!(format.apply(&node), "HK 01 | trojan");
+        let error = AppendTypeFormat::parse("{{{TYPE}}}").unwrap_err();
+        assert_eq!(error, "unknown placeholder {TYPE}");
+        let format = AppendTypeFormat::parse("{{{type:upper}}} {host}:{port}}}").unwrap();
+        assert_eq!(format.apply(&node), "{TROJAN} hk.example.com:443}");
+
+        for (invalid, error) in [
+            ("{remark", "unclosed '{remark'"),
+            ("{remark} }", "unmatched '}'"),
+            ("{type:title}", "unknown modifier in {type:title}"),
+        ] {
+            assert_eq!(AppendTypeFormat::parse(invalid).unwrap_err(), error);
+            assert_eq!(AppendTypeFormat::new(invalid), AppendTypeFormat::default());
+        }
+    }
+}
@@ -1 +1,57 @@
 pub mod proxy_to_clash;
+
+use std::fmt;
+
+use thiserror::Error;
+
+/// Errors raised while generating a config from its base template
+#[derive(Error, Debug)]
+pub enum ExportError {
+    /// The base config could not be parsed
+    #[error("Invalid base config: {0}")]
+    InvalidBase(String),
+
+    /// The generated config could not be written out
+    #[error("Failed to serialize config: {0}")]
+    Serialize(String),
+}
+
+/// Size of the rulesets from which a generated config is sent while it is
+/// written, smaller configs are built as a single string
+pub const STREAM_THRESHOLD: usize = 1024 * 1024;
+
+/// A generated config produced piece by piece while it is written out, so a
+/// large config never has to be held as a single string
+pub struct ConfigChunks {
+    chunks: Box<dyn Iterator<Item = String> + Send>,
+}
+
+impl ConfigChunks {
+    pub fn new<I>(chunks: I) -> Self
+    where
+        I: Iterator<Item = String> + Send + 'static,
+    {
+        Self {
+            chunks: Box::new(chunks),
+        }
+    }
+
+    /// Produce the whole config at once
+    pub fn into_string(self) -> String {
+        self.collect()
+    }
+}
+
+impl Iterator for ConfigChunks {
+    type Item = String;
+
+    fn next(&mut self) -> Option<String> {
+        self.chunks.next()
+    }
+}
+
+impl fmt::Debug for ConfigChunks {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ConfigChunks").finish_non_exhaustive()
+    }
+}
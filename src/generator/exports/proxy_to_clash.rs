@@ -1,14 +1,27 @@
-use crate::generator::config::group::group_generate;
-use crate::generator::config::remark::process_remark;
-use crate::generator::ruleconvert::convert_ruleset::convert_ruleset;
-use crate::generator::ruleconvert::ruleset_to_clash_str;
+use crate::generator::config::group::{group_generate_cached, GroupMatchCache};
+use crate::generator::config::remark::{process_remark, AppendTypeFormat};
+use crate::generator::exports::{ConfigChunks, ExportError};
+use crate::generator::ruleconvert::common::{translate_policy, PolicyTarget};
+use crate::generator::ruleconvert::convert_cache::convert_ruleset_cached;
+use crate::generator::ruleconvert::ruleset_to_clash_str::ruleset_to_clash_chunks;
+use crate::generator::ruleconvert::{detect_ruleset_behavior, Behavior};
 use crate::generator::yaml::clash::clash_output::ClashProxyOutput;
 use crate::generator::yaml::proxy_group_output::convert_proxy_groups;
-use crate::models::{ExtraSettings, Proxy, ProxyGroupConfigs, ProxyType, RulesetContent};
+use crate::generator::yaml::style::{dump_clash_yaml, dump_clash_yaml_split};
+use crate::models::{
+    ExtraSettings, Proxy, ProxyGroupConfig, ProxyGroupConfigs, ProxyProvider, ProxyType,
+    RulesetContent,
+};
 use crate::utils::base64::url_safe_base64_encode;
-use log::error;
+use crate::utils::lock_recovering;
+use crate::utils::parallel::parallel_map;
+use crate::utils::string::md5;
+use crate::Settings;
+use log::{error, warn};
+use once_cell::sync::Lazy;
 use serde_yaml::{self, Mapping, Sequence, Value as YamlValue};
 use std::collections::{HashMap, HashSet};
+use std::sync::{Arc, Mutex};
 
 // Lists of supported protocols and encryption methods for filtering in ClashR
 lazy_static::lazy_static! {
@@ -74,6 +87,10 @@ lazy_static::lazy_static! {
 /// * `extra_proxy_group` - Extra proxy group configurations
 /// * `clash_r` - Whether to use ClashR format
 /// * `ext` - Extra settings for conversion
+///
+/// # Errors
+/// Returns [`ExportError::InvalidBase`] if the base config cannot be parsed and
+/// [`ExportError::Serialize`] if the generated config cannot be written out
 pub fn proxy_to_clash(
     nodes: &mut Vec<Proxy>,
     base_conf: &str,
@@ -81,19 +98,30 @@ pub fn proxy_to_clash(
     extra_proxy_group: &ProxyGroupConfigs,
     clash_r: bool,
     ext: &mut ExtraSettings,
-) -> String {
-    // Parse the base configuration
-    let mut yaml_node: YamlValue = match serde_yaml::from_str(base_conf) {
-        Ok(node) => node,
-        Err(e) => {
-            error!("Clash base loader failed with error: {}", e);
-            return String::new();
-        }
-    };
+) -> Result<String, ExportError> {
+    proxy_to_clash_chunks(
+        nodes,
+        base_conf,
+        ruleset_content_array,
+        extra_proxy_group,
+        clash_r,
+        ext,
+    )
+    .map(ConfigChunks::into_string)
+}
 
-    if yaml_node.is_null() {
-        yaml_node = YamlValue::Mapping(Mapping::new());
-    }
+/// Same as [`proxy_to_clash`], but leaves the rules section to be converted one
+/// ruleset at a time as the returned chunks are consumed
+pub fn proxy_to_clash_chunks(
+    nodes: &mut Vec<Proxy>,
+    base_conf: &str,
+    ruleset_content_array: &mut Vec<RulesetContent>,
+    extra_proxy_group: &ProxyGroupConfigs,
+    clash_r: bool,
+    ext: &mut ExtraSettings,
+) -> Result<ConfigChunks, ExportError> {
+    // Every request gets its own copy of the shared parsed base
+    let mut yaml_node = YamlValue::clone(&*parse_clash_base(base_conf)?);
 
     // Apply conversion to the YAML node
     proxy_to_clash_yaml(
@@ -107,18 +135,24 @@ pub fn proxy_to_clash(
 
     // If nodelist mode is enabled, just return the YAML node
     if ext.nodelist {
-        return match serde_yaml::to_string(&yaml_node) {
-            Ok(result) => result,
-            Err(_) => String::new(),
-        };
+        return dump_clash_yaml(
+            &yaml_node,
+            &ext.clash_proxies_style,
+            &ext.clash_proxy_groups_style,
+        )
+        .map(single_chunk)
+        .map_err(|e| ExportError::Serialize(e.to_string()));
     }
 
     // Handle rule generation if enabled
     if !ext.enable_rule_generator {
-        return match serde_yaml::to_string(&yaml_node) {
-            Ok(result) => result,
-            Err(_) => String::new(),
-        };
+        return dump_clash_yaml(
+            &yaml_node,
+            &ext.clash_proxies_style,
+            &ext.clash_proxy_groups_style,
+        )
+        .map(single_chunk)
+        .map_err(|e| ExportError::Serialize(e.to_string()));
     }
 
     // Handle clash script mode
@@ -144,9 +178,10 @@ pub fn proxy_to_clash(
             }
         }
 
-        if !ext.managed_config_prefix.is_empty() {
+        let url_prefix = ext.self_url_prefix();
+        if !url_prefix.is_empty() {
             let (rule_providers, script_code) =
-                build_clash_script_parts(ruleset_content_array, &ext.managed_config_prefix, 86400);
+                build_clash_script_parts(ruleset_content_array, url_prefix, 86400, &ext.settings);
 
             if let Some(map) = yaml_node.as_mapping_mut() {
                 map.insert(
@@ -165,35 +200,63 @@ pub fn proxy_to_clash(
             }
         }
 
-        return match serde_yaml::to_string(&yaml_node) {
-            Ok(result) => result,
-            Err(_) => String::new(),
-        };
+        return dump_clash_yaml(
+            &yaml_node,
+            &ext.clash_proxies_style,
+            &ext.clash_proxy_groups_style,
+        )
+        .map(single_chunk)
+        .map_err(|e| ExportError::Serialize(e.to_string()));
     }
 
-    // Generate rules and return combined output
+    // Generate rules, written where the base config keeps them
+    let rules_key = if ext.clash_new_field_name {
+        "rules"
+    } else {
+        "Rule"
+    };
     if let Some(map) = yaml_node.as_mapping_mut() {
-        for key in ["rules", "Rule"] {
+        for key in ["rules", "Rule"]
+            .into_iter()
+            .filter(|key| *key != rules_key)
+        {
             let yaml_key = YamlValue::String(key.to_string());
             if map.get(&yaml_key).is_some_and(|v| v.is_null()) {
-                map.remove(&yaml_key);
+                map.shift_remove(&yaml_key);
             }
         }
     }
 
-    let rules_str = ruleset_to_clash_str(
+    let (before, after) = dump_clash_yaml_split(
+        &yaml_node,
+        &ext.clash_proxies_style,
+        &ext.clash_proxy_groups_style,
+        rules_key,
+    )
+    .map_err(|e| ExportError::Serialize(e.to_string()))?;
+
+    let mut rules = ruleset_to_clash_chunks(
         &yaml_node,
         ruleset_content_array,
         ext.overwrite_original_rules,
         ext.clash_new_field_name,
+        ext.clash_meta,
+        &ext.settings,
     );
+    // The rules header starts on a fresh line of its own
+    let header = rules.next().unwrap_or_default();
+    let header = header.trim_start_matches('\n').to_string();
+
+    Ok(ConfigChunks::new(
+        [before, header]
+            .into_iter()
+            .chain(rules)
+            .chain(std::iter::once(after)),
+    ))
+}
 
-    let yaml_output = match serde_yaml::to_string(&yaml_node) {
-        Ok(result) => result,
-        Err(_) => String::new(),
-    };
-
-    format!("{}{}", yaml_output, rules_str)
+fn single_chunk(output: String) -> ConfigChunks {
+    ConfigChunks::new(std::iter::once(output))
 }
 
 #[derive(Clone)]
@@ -214,17 +277,28 @@ struct ScriptRuleLayout {
     ipcidr: Option<ScriptRuleProvider>,
 }
 
+/// Check of the script, in the order of the rulesets they come from
+enum ScriptStep {
+    Providers(Box<ScriptRuleLayout>),
+    GeoIp { code: String, group: String },
+}
+
 fn build_clash_script_parts(
     ruleset_content_array: &[RulesetContent],
     managed_config_prefix: &str,
     default_interval: u32,
+    settings: &Settings,
 ) -> (Mapping, String) {
     let mut providers = Vec::<ScriptRuleProvider>::new();
-    let mut layouts = Vec::<ScriptRuleLayout>::new();
-    let mut geoips: Vec<(String, String)> = Vec::new();
+    let mut steps = Vec::<ScriptStep>::new();
     let mut final_group = "DIRECT".to_string();
 
     for ruleset in ruleset_content_array {
+        // Clash script mode is a premium core feature, Clash.Meta policies
+        // are not available
+        let Some(group) = translate_policy(&ruleset.group, PolicyTarget::Clash) else {
+            continue;
+        };
         let content = ruleset.get_rule_content();
         if content.is_empty() {
             continue;
@@ -236,38 +310,38 @@ fn build_clash_script_parts(
                 let mut parts = inline.split(',');
                 let _ = parts.next();
                 if let Some(code) = parts.next() {
-                    geoips.push((code.trim().to_string(), ruleset.group.clone()));
+                    // Country codes are written into the script, anything but
+                    // two letters is left out
+                    let code = code.trim().to_ascii_uppercase();
+                    if code.len() == 2 && code.bytes().all(|b| b.is_ascii_uppercase()) {
+                        steps.push(ScriptStep::GeoIp {
+                            code,
+                            group: group.clone(),
+                        });
+                    } else {
+                        warn!(
+                            "Clash script leaves out GEOIP rule with invalid code {:?}",
+                            code
+                        );
+                    }
                 }
             } else if inline == "FINAL" || inline == "MATCH" {
-                final_group = ruleset.group.clone();
+                // Nothing after the final rule is ever reached
+                final_group = group.clone();
+                break;
             }
             continue;
         }
 
-        let converted = convert_ruleset(&content, ruleset.rule_type);
+        let converted = convert_ruleset_cached(&content, ruleset.rule_type, settings);
         if converted.trim().is_empty() {
             continue;
         }
 
-        let mut has_domain = false;
-        let mut has_ipcidr = false;
-
-        for raw in converted.lines() {
-            let line = raw.trim();
-            if line.is_empty()
-                || line.starts_with('#')
-                || line.starts_with(';')
-                || line.starts_with("//")
-            {
-                continue;
-            }
-            let rule_type = line.split(',').next().unwrap_or("").trim();
-            match rule_type {
-                "DOMAIN" | "DOMAIN-SUFFIX" | "DOMAIN-KEYWORD" => has_domain = true,
-                "IP-CIDR" => has_ipcidr = true,
-                _ => {}
-            }
-        }
+        // Rules a domain or ipcidr provider can not hold need a classical one
+        let behavior = ruleset
+            .behavior
+            .unwrap_or_else(|| detect_ruleset_behavior(&converted));
 
         let provider_base_name = ruleset
             .rule_path
@@ -291,32 +365,31 @@ fn build_clash_script_parts(
             default_interval
         };
 
-        let force_classical = provider_base_name == "MOO" || provider_base_name == "Download";
-        if force_classical || (!has_domain && !has_ipcidr) {
+        if behavior == Behavior::Classical {
             let provider = ScriptRuleProvider {
                 name: provider_base_name,
                 behavior: "classical",
                 request_type: 6,
-                group: ruleset.group.clone(),
+                group: group.clone(),
                 label: "rule",
                 typed_path,
                 interval,
             };
             providers.push(provider.clone());
-            layouts.push(ScriptRuleLayout {
+            steps.push(ScriptStep::Providers(Box::new(ScriptRuleLayout {
                 classical: Some(provider),
                 ..Default::default()
-            });
+            })));
             continue;
         }
 
         let mut layout = ScriptRuleLayout::default();
-        if has_domain {
+        if behavior.has_domain() {
             let provider = ScriptRuleProvider {
                 name: format!("{}_domain", provider_base_name),
                 behavior: "domain",
                 request_type: 3,
-                group: ruleset.group.clone(),
+                group: group.clone(),
                 label: "DOMAIN rule",
                 typed_path: typed_path.clone(),
                 interval,
@@ -324,12 +397,12 @@ fn build_clash_script_parts(
             providers.push(provider.clone());
             layout.domain = Some(provider);
         }
-        if has_ipcidr && provider_base_name != "Apple" {
+        if behavior.has_ipcidr() {
             let provider = ScriptRuleProvider {
                 name: format!("{}_ipcidr", provider_base_name),
                 behavior: "ipcidr",
                 request_type: 4,
-                group: ruleset.group.clone(),
+                group: group.clone(),
                 label: "IP rule",
                 typed_path,
                 interval,
@@ -337,7 +410,7 @@ fn build_clash_script_parts(
             providers.push(provider.clone());
             layout.ipcidr = Some(provider);
         }
-        layouts.push(layout);
+        steps.push(ScriptStep::Providers(Box::new(layout)));
     }
 
     let mut providers_map = Mapping::new();
@@ -351,10 +424,15 @@ fn build_clash_script_parts(
             YamlValue::String("behavior".to_string()),
             YamlValue::String(p.behavior.to_string()),
         );
+        // Text payloads are a rule per line, smaller than the YAML ones
+        item.insert(
+            YamlValue::String("format".to_string()),
+            YamlValue::String("text".to_string()),
+        );
         item.insert(
             YamlValue::String("url".to_string()),
             YamlValue::String(format!(
-                "{}/getruleset?type={}&url={}",
+                "{}/getruleset?type={}&url={}&format=text",
                 managed_config_prefix,
                 p.request_type,
                 url_safe_base64_encode(&p.typed_path)
@@ -362,7 +440,7 @@ fn build_clash_script_parts(
         );
         item.insert(
             YamlValue::String("path".to_string()),
-            YamlValue::String(format!("./providers/rule-provider_{}.yaml", p.name)),
+            YamlValue::String(format!("./providers/rule-provider_{}.txt", p.name)),
         );
         item.insert(
             YamlValue::String("interval".to_string()),
@@ -372,58 +450,117 @@ fn build_clash_script_parts(
     }
 
     let mut code = String::from("def main(ctx, md):\n  host = md[\"host\"]\n\n");
-    for layout in &layouts {
+    // The destination IP is only looked up once the first GEOIP check needs it
+    let mut ip_resolved = false;
+    for step in &steps {
+        let layout = match step {
+            ScriptStep::Providers(layout) => layout,
+            ScriptStep::GeoIp {
+                code: country,
+                group,
+            } => {
+                if !ip_resolved {
+                    code.push_str(&format!(
+                        "  ip = md[\"dst_ip\"]\n  if ip == \"\":\n    ip = ctx.resolve_ip(host)\n    if ip == \"\":\n      ctx.log({})\n      return {}\n\n",
+                        python_string(&format!("[Script] dns lookup error use {}", final_group), '\''),
+                        python_string(&final_group, '"')
+                    ));
+                    ip_resolved = true;
+                }
+                code.push_str(&format!(
+                    "  if ctx.geoip(ip) == {}:\n    ctx.log({})\n    return {}\n\n",
+                    python_string(country, '"'),
+                    python_string(&format!("[Script] matched {} GEOIP rule", group), '\''),
+                    python_string(group, '"')
+                ));
+                continue;
+            }
+        };
+
         if let Some(p) = &layout.classical {
-            code.push_str(&format!(
-                "  if ctx.rule_providers[\"{}\"].match(md):\n    ctx.log('[Script] matched {} {}')\n    return \"{}\"\n\n",
-                p.name, p.group, p.label, p.group
-            ));
+            code.push_str(&provider_check(p));
             continue;
         }
 
         if let Some(p) = &layout.domain {
-            code.push_str(&format!(
-                "  if ctx.rule_providers[\"{}\"].match(md):\n    ctx.log('[Script] matched {} {}')\n    return \"{}\"\n\n",
-                p.name, p.group, p.label, p.group
-            ));
+            code.push_str(&provider_check(p));
         } else {
             code.push_str("\n\n");
         }
 
         if let Some(p) = &layout.ipcidr {
-            code.push_str(&format!(
-                "  if ctx.rule_providers[\"{}\"].match(md):\n    ctx.log('[Script] matched {} {}')\n    return \"{}\"\n\n",
-                p.name, p.group, p.label, p.group
-            ));
+            code.push_str(&provider_check(p));
         } else {
             code.push_str("\n\n");
         }
     }
+    code.push_str(&format!("  return {}", python_string(&final_group, '"')));
+
+    (providers_map, code)
+}
 
-    code.push('\n');
-    code.push_str("  geoips = {");
-    if !geoips.is_empty() {
-        code.push(' ');
-        for (idx, (code_name, group)) in geoips.iter().enumerate() {
-            if idx > 0 {
-                code.push_str(", ");
+/// Script lines returning the group of `provider` when it matches
+fn provider_check(provider: &ScriptRuleProvider) -> String {
+    format!(
+        "  if ctx.rule_providers[{}].match(md):\n    ctx.log({})\n    return {}\n\n",
+        python_string(&provider.name, '"'),
+        python_string(
+            &format!("[Script] matched {} {}", provider.group, provider.label),
+            '\''
+        ),
+        python_string(&provider.group, '"')
+    )
+}
+
+/// `value` as a Python string literal enclosed in `quote`
+fn python_string(value: &str, quote: char) -> String {
+    let mut literal = String::with_capacity(value.len() + 2);
+    literal.push(quote);
+    for c in value.chars() {
+        match c {
+            '\\' => literal.push_str("\\\\"),
+            '\n' => literal.push_str("\\n"),
+            '\r' => literal.push_str("\\r"),
+            // Both quotes are escaped so no part of `value` reads as code
+            // to anything scanning the script
+            '"' | '\'' => {
+                literal.push('\\');
+                literal.push(c);
             }
-            code.push_str(&format!("\"{}\": \"{}\"", code_name, group));
+            c => literal.push(c),
         }
-        code.push(' ');
     }
-    code.push_str("}\n");
-    code.push_str(
-        "  ip = md[\"dst_ip\"]\n  if ip == \"\":\n    ip = ctx.resolve_ip(host)\n    if ip == \"\":\n      ctx.log('[Script] dns lookup error use ",
-    );
-    code.push_str(&final_group);
-    code.push_str("')\n      return \"");
-    code.push_str(&final_group);
-    code.push_str("\"\n  for key in geoips:\n    if ctx.geoip(ip) == key:\n      return geoips[key]\n  return \"");
-    code.push_str(&final_group);
-    code.push('"');
+    literal.push(quote);
+    literal
+}
 
-    (providers_map, code)
+/// Why Clash configs leave out `node`, `None` when it is written
+pub(crate) fn clash_skip_reason(
+    node: &Proxy,
+    clash_r: bool,
+    filter_deprecated: bool,
+) -> Option<&'static str> {
+    match node.proxy_type {
+        // Snell v4+ is not understood, exactly matching C++ behavior
+        ProxyType::Snell if node.snell_version >= 4 => Some("Snell v4+"),
+        ProxyType::Shadowsocks
+            if filter_deprecated && node.encrypt_method.as_deref() == Some("chacha20") =>
+        {
+            Some("SS chacha20 (filter_deprecated=true)")
+        }
+        ProxyType::ShadowsocksR if filter_deprecated => {
+            let encrypt_method = node.encrypt_method.as_deref().unwrap_or("");
+            let protocol = node.protocol.as_deref().unwrap_or("");
+            let obfs = node.obfs.as_deref().unwrap_or("");
+
+            ((!clash_r && !CLASH_SSR_CIPHERS.contains(encrypt_method))
+                || !CLASHR_PROTOCOLS.contains(protocol)
+                || !CLASHR_OBFS.contains(obfs))
+            .then_some("SSR deprecated features")
+        }
+        ProxyType::Unknown | ProxyType::HTTPS => Some("Unknown/HTTPS"),
+        _ => None,
+    }
 }
 
 /// Convert proxies to Clash format with YAML node
@@ -446,110 +583,87 @@ pub fn proxy_to_clash_yaml(
     clash_r: bool,
     ext: &mut ExtraSettings,
 ) {
-    // Style settings - in C++ this is used to set serialization style but in Rust we have less control
-    // over the serialization format. We keep them for compatibility but their actual effect may differ.
-    let _proxy_block = ext.clash_proxies_style == "block";
-    let _proxy_compact = ext.clash_proxies_style == "compact";
-    let _group_block = ext.clash_proxy_groups_style == "block";
-    let _group_compact = ext.clash_proxy_groups_style == "compact";
-
-    // Create JSON structure for the proxies
-    let mut proxies_json = Vec::new();
+    // Remarks are deduplicated against earlier nodes, so nodes are picked
+    // and named in order before the conversion itself runs in parallel
+    let mut selected = Vec::new();
     let mut remarks_list = Vec::new();
 
     // Process each node
-    for node in nodes.iter_mut() {
+    let type_format = ext
+        .append_proxy_type
+        .then(|| AppendTypeFormat::new(&ext.append_type_format));
+    for node in nodes.iter() {
         // Create a local copy of the node for processing
         let mut remark = node.remark.clone();
 
         // Add proxy type prefix if enabled
-        if ext.append_proxy_type {
-            remark = format!("[{}] {}", node.proxy_type.to_string(), remark);
+        if let Some(format) = &type_format {
+            remark = format.apply(node);
         }
 
         // Process remark with optional remarks list
-        process_remark(&mut remark, &remarks_list, false);
+        process_remark(
+            &mut remark,
+            &remarks_list,
+            false,
+            &ext.duplicate_remark_format,
+        );
         remarks_list.push(remark.clone());
         // Check if this proxy type should be skipped
-        let should_skip = match node.proxy_type {
-            // Skip Snell v4+ if exists - exactly matching C++ behavior
-            ProxyType::Snell if node.snell_version >= 4 => {
-                error!("Skipping Snell v4+ node: {}", remark);
-                true
-            }
-
-            // Skip chacha20 encryption if filter_deprecated is enabled
-            ProxyType::Shadowsocks
-                if ext.filter_deprecated && node.encrypt_method.as_deref() == Some("chacha20") =>
-            {
-                error!(
-                    "Skipping SS chacha20 node (filter_deprecated=true): {}",
-                    remark
-                );
-                true
-            }
-
-            // Skip ShadowsocksR with deprecated features if filter_deprecated is enabled
-            ProxyType::ShadowsocksR if ext.filter_deprecated => {
-                let encrypt_method = node.encrypt_method.as_deref().unwrap_or("");
-                let protocol = node.protocol.as_deref().unwrap_or("");
-                let obfs = node.obfs.as_deref().unwrap_or("");
-
-                if (!clash_r && !CLASH_SSR_CIPHERS.contains(encrypt_method))
-                    || !CLASHR_PROTOCOLS.contains(protocol)
-                    || !CLASHR_OBFS.contains(obfs)
-                {
-                    error!("Skipping SSR deprecated features node: {}", remark);
-                    true
-                } else {
-                    false
-                }
-            }
-
-            // Skip unsupported proxy types
-            ProxyType::Unknown | ProxyType::HTTPS => {
-                error!(
-                    "Skipping Unknown/HTTPS node: {} (type: {:?})",
-                    remark, node.proxy_type
-                );
-                true
-            }
-
-            // Process all other types
-            _ => false,
-        };
-
-        if should_skip {
+        if let Some(reason) = clash_skip_reason(node, clash_r, ext.filter_deprecated) {
+            error!("Skipping {} node: {}", reason, remark);
             continue;
         }
 
-        // 创建代理副本，并应用所有必要的属性设置
-        let proxy_copy = node.clone().set_remark(remark).apply_default_values(
-            ext.udp,
-            ext.tfo,
-            ext.skip_cert_verify,
-        );
-
-        // 使用 From trait 自动转换为 ClashProxyOutput
-        let clash_proxy = ClashProxyOutput::from(proxy_copy);
-
-        // 添加到代理列表
-        proxies_json.push(clash_proxy);
+        selected.push((node, remark));
     }
 
+    let (udp, tfo, scv) = (ext.udp, ext.tfo, ext.skip_cert_verify);
+    let proxies: Sequence = parallel_map(&selected, |(node, remark)| {
+        // Convert straight from the node, only the written fields are copied
+        serde_yaml::to_value(ClashProxyOutput::from_node(node, remark, udp, tfo, scv))
+    })
+    .into_iter()
+    .zip(&selected)
+    .filter_map(|(proxy, (_, remark))| match proxy {
+        Ok(proxy) => Some(proxy),
+        Err(e) => {
+            error!("Failed to convert node {}: {}", remark, e);
+            None
+        }
+    })
+    .collect();
+
     if ext.nodelist {
         let mut provider = YamlValue::Mapping(Mapping::new());
-        provider["proxies"] =
-            serde_yaml::to_value(&proxies_json).unwrap_or(YamlValue::Sequence(Vec::new()));
+        provider["proxies"] = YamlValue::Sequence(proxies);
         *yaml_node = provider;
         return;
     }
 
+    // The base proxies and groups written under the field name in use are
+    // replaced in place, the other spelling is only dropped when asked to
+    let (other_proxies_key, other_groups_key) = if ext.clash_new_field_name {
+        ("Proxy", "Proxy Group")
+    } else {
+        ("proxies", "proxy-groups")
+    };
+    if let Some(ref mut map) = yaml_node.as_mapping_mut() {
+        if ext.overwrite_original_proxies {
+            map.shift_remove(other_proxies_key);
+        }
+        if ext.overwrite_original_groups {
+            map.shift_remove(other_groups_key);
+            if extra_proxy_group.is_empty() {
+                map.shift_remove("proxy-groups");
+                map.shift_remove("Proxy Group");
+            }
+        }
+    }
+
     // Update the YAML node with proxies
     if let Some(ref mut map) = yaml_node.as_mapping_mut() {
-        // Convert JSON proxies array to YAML
-        let proxies_yaml_value =
-            serde_yaml::to_value(&proxies_json).unwrap_or(YamlValue::Sequence(Vec::new()));
+        let proxies_yaml_value = YamlValue::Sequence(proxies);
         if ext.clash_new_field_name {
             map.insert(YamlValue::String("proxies".to_string()), proxies_yaml_value);
         } else {
@@ -560,7 +674,9 @@ pub fn proxy_to_clash_yaml(
     // Add proxy groups if present
     if !extra_proxy_group.is_empty() {
         // Get existing proxy groups if any
-        let mut original_groups = if ext.clash_new_field_name {
+        let mut original_groups = if ext.overwrite_original_groups {
+            Sequence::new()
+        } else if ext.clash_new_field_name {
             match yaml_node.get("proxy-groups") {
                 Some(YamlValue::Sequence(seq)) => seq.clone(),
                 _ => Sequence::new(),
@@ -572,12 +688,70 @@ pub fn proxy_to_clash_yaml(
             }
         };
 
-        // Build filtered nodes map for each group
+        // Groups list the nodes by the names they were written with
+        let named_nodes: Vec<Proxy> = selected
+            .iter()
+            .map(|(node, remark)| (*node).clone().set_remark(remark.clone()))
+            .collect();
+
+        // Build filtered nodes map for each group, rules shared between
+        // groups are matched against the nodes only once
+        let mut match_cache = GroupMatchCache::default();
         let mut filtered_nodes_map = HashMap::new();
+        let mut native_groups = HashMap::new();
         for group in extra_proxy_group {
             let mut filtered_nodes = Vec::new();
+            // Clash.Meta fills include-all groups with the nodes itself, only
+            // the explicit `[]` members are listed
+            if group.include_all {
+                for rule in group.proxies.iter().filter(|rule| rule.starts_with("[]")) {
+                    group_generate_cached(
+                        rule,
+                        &named_nodes,
+                        &group.group_type,
+                        &mut filtered_nodes,
+                        true,
+                        ext,
+                        &mut match_cache,
+                    );
+                }
+                filtered_nodes_map.insert(group.name.clone(), filtered_nodes);
+                continue;
+            }
+            // Clash.Meta re-filters natively filtered groups whenever providers refresh,
+            // groups taking nodes from generated providers can only filter that way
+            let native = ext.clash_meta && (ext.native_filter || group.native_filter);
+            let provided = !ext.proxy_providers.is_empty() && !group.using_provider.is_empty();
+            if native || provided {
+                if let Some((mut entries, filter)) = native_group_filter(group) {
+                    // Nodes inlined next to providers are not covered by `use`
+                    if provided {
+                        for rule in group.proxies.iter().filter(|rule| !rule.starts_with("[]")) {
+                            group_generate_cached(
+                                rule,
+                                &named_nodes,
+                                &group.group_type,
+                                &mut entries,
+                                false,
+                                ext,
+                                &mut match_cache,
+                            );
+                        }
+                    }
+                    native_groups.insert(group.name.clone(), ((entries, filter), group));
+                    continue;
+                }
+            }
             for proxy_name in &group.proxies {
-                group_generate(proxy_name, nodes, &mut filtered_nodes, true, ext);
+                group_generate_cached(
+                    proxy_name,
+                    &named_nodes,
+                    &group.group_type,
+                    &mut filtered_nodes,
+                    true,
+                    ext,
+                    &mut match_cache,
+                );
             }
 
             // Add DIRECT if empty
@@ -589,7 +763,17 @@ pub fn proxy_to_clash_yaml(
         }
 
         // Convert proxy groups using the new serialization
-        let clash_proxy_groups = convert_proxy_groups(extra_proxy_group, Some(&filtered_nodes_map));
+        let mut clash_proxy_groups =
+            convert_proxy_groups(extra_proxy_group, Some(&filtered_nodes_map));
+        for clash_group in clash_proxy_groups.iter_mut() {
+            if let Some(((entries, filter), group)) = native_groups.remove(&clash_group.name) {
+                clash_group.proxies = entries;
+                clash_group.filter = filter;
+                clash_group.exclude_filter = group.exclude_filter.clone();
+                clash_group.exclude_type = group.exclude_type.clone();
+                clash_group.include_all = clash_group.using_provider.is_empty();
+            }
+        }
 
         // Merge with existing groups
         for group in clash_proxy_groups {
@@ -637,11 +821,216 @@ pub fn proxy_to_clash_yaml(
             }
         }
     }
+
+    // Reference the subscriptions left out of the proxies section
+    if !ext.proxy_providers.is_empty() {
+        let mut providers = match yaml_node.get("proxy-providers") {
+            Some(YamlValue::Mapping(map)) => map.clone(),
+            _ => Mapping::new(),
+        };
+        for provider in &ext.proxy_providers {
+            providers.insert(
+                YamlValue::String(provider.name.clone()),
+                provider_to_yaml(provider, &ext.settings),
+            );
+        }
+        if let Some(map) = yaml_node.as_mapping_mut() {
+            map.insert(
+                YamlValue::String("proxy-providers".to_string()),
+                YamlValue::Mapping(providers),
+            );
+        }
+    }
+
+    if let Some(map) = yaml_node.as_mapping_mut() {
+        // The configured DNS section wins over whatever the base provides
+        if let Some(dns) = &ext.clash_dns {
+            merge_section(map, "dns", dns, true);
+        }
+
+        // Vanilla Clash rejects unknown fields, so these are Clash.Meta only
+        if ext.clash_meta {
+            if let Some(tun) = &ext.clash_tun {
+                merge_section(map, "tun", tun, ext.clash_meta_overwrite);
+            }
+            if let Some(sniffer) = &ext.clash_sniffer {
+                merge_section(map, "sniffer", sniffer, ext.clash_meta_overwrite);
+            }
+        }
+    }
+}
+
+/// Merges a configured section into the top level `key` of a config
+fn merge_section(map: &mut Mapping, key: &str, section: &YamlValue, overwrite: bool) {
+    let key = YamlValue::String(key.to_string());
+    match map.get_mut(&key) {
+        Some(base) => merge_yaml(base, section, overwrite),
+        None => {
+            map.insert(key, section.clone());
+        }
+    }
+}
+
+/// Deep merges `overlay` into `base`, nested mappings are merged key by key.
+/// Other values present in both are taken from `overlay` when `overwrite` is
+/// set and kept from `base` otherwise, null values in `base` count as missing
+fn merge_yaml(base: &mut YamlValue, overlay: &YamlValue, overwrite: bool) {
+    match (base, overlay) {
+        (YamlValue::Mapping(base_map), YamlValue::Mapping(overlay_map)) => {
+            for (key, value) in overlay_map {
+                match base_map.get_mut(key) {
+                    Some(base_value) => merge_yaml(base_value, value, overwrite),
+                    None => {
+                        base_map.insert(key.clone(), value.clone());
+                    }
+                }
+            }
+        }
+        (base, overlay) => {
+            if overwrite || base.is_null() {
+                *base = overlay.clone();
+            }
+        }
+    }
+}
+
+/// Parsed Clash bases keyed by the md5 of the rendered base text, so an edited
+/// template or a different set of template arguments never hits a stale entry
+static BASE_CACHE: Lazy<Mutex<HashMap<String, Arc<YamlValue>>>> =
+    Lazy::new(|| Mutex::new(HashMap::new()));
+
+/// Upper bound of cached bases, the cache is emptied once it is reached
+const BASE_CACHE_CAPACITY: usize = 64;
+
+/// Parse a Clash base config with its merge keys expanded, reusing the result
+/// of an earlier request with the same base text
+fn parse_clash_base(base_conf: &str) -> Result<Arc<YamlValue>, ExportError> {
+    let key = md5(base_conf);
+    if let Some(node) = lock_recovering(&BASE_CACHE).get(&key) {
+        return Ok(node.clone());
+    }
+
+    let mut yaml_node: YamlValue = serde_yaml::from_str(base_conf).map_err(|e| {
+        error!("Clash base loader failed with error: {}", e);
+        ExportError::InvalidBase(e.to_string())
+    })?;
+
+    if yaml_node.is_null() {
+        yaml_node = YamlValue::Mapping(Mapping::new());
+    }
+
+    // Aliases are expanded while parsing, merge keys are left to us
+    apply_merge_keys(&mut yaml_node).map_err(|e| {
+        error!("Clash base loader failed with error: {}", e);
+        ExportError::InvalidBase(e)
+    })?;
+
+    let node = Arc::new(yaml_node);
+    let mut cache = lock_recovering(&BASE_CACHE);
+    if cache.len() >= BASE_CACHE_CAPACITY {
+        cache.clear();
+    }
+    cache.insert(key, node.clone());
+    Ok(node)
+}
+
+/// Drop every parsed Clash base, called when the server configuration is reloaded
+pub fn clear_clash_base_cache() {
+    lock_recovering(&BASE_CACHE).clear();
+}
+
+/// Expands `<<` merge keys in place, merged entries take the position of the
+/// merge key and never override keys the mapping sets itself
+fn apply_merge_keys(value: &mut YamlValue) -> Result<(), String> {
+    match value {
+        YamlValue::Mapping(map) => {
+            for child in map.values_mut() {
+                apply_merge_keys(child)?;
+            }
+            let merge_key = YamlValue::String("<<".to_string());
+            if !map.contains_key(&merge_key) {
+                return Ok(());
+            }
+            let original = std::mem::take(map);
+            for (key, value) in &original {
+                if key != &merge_key {
+                    map.insert(key.clone(), value.clone());
+                    continue;
+                }
+                let sources = match value {
+                    YamlValue::Mapping(source) => vec![source],
+                    YamlValue::Sequence(seq) => seq
+                        .iter()
+                        .map(|item| item.as_mapping())
+                        .collect::<Option<Vec<_>>>()
+                        .ok_or("merge key expects a mapping or a list of mappings")?,
+                    _ => return Err("merge key expects a mapping or a list of mappings".into()),
+                };
+                for source in sources {
+                    for (k, v) in source {
+                        if !original.contains_key(k) && !map.contains_key(k) {
+                            map.insert(k.clone(), v.clone());
+                        }
+                    }
+                }
+            }
+            Ok(())
+        }
+        YamlValue::Sequence(seq) => seq.iter_mut().try_for_each(apply_merge_keys),
+        YamlValue::Tagged(tagged) => apply_merge_keys(&mut tagged.value),
+        _ => Ok(()),
+    }
+}
+
+/// Build the `proxy-providers` entry of a subscription, health checked like
+/// url-test groups without their own settings
+fn provider_to_yaml(provider: &ProxyProvider, global: &Settings) -> YamlValue {
+    let mut health_check = Mapping::new();
+    health_check.insert("enable".into(), YamlValue::Bool(true));
+    health_check.insert("url".into(), global.group_test_url.clone().into());
+    health_check.insert("interval".into(), global.group_interval.into());
+
+    let mut item = Mapping::new();
+    item.insert("type".into(), "http".into());
+    item.insert("url".into(), provider.url.clone().into());
+    item.insert("interval".into(), provider.interval.into());
+    item.insert(
+        "path".into(),
+        format!("./providers/{}.yaml", provider.name).into(),
+    );
+    item.insert("health-check".into(), YamlValue::Mapping(health_check));
+    YamlValue::Mapping(item)
+}
+
+/// Split the rules of a group into the names it lists directly and the regexes
+/// Clash.Meta can match itself, joined by backticks as its `filter` expects
+///
+/// Returns `None` when a rule needs a matcher only subconverter understands
+fn native_group_filter(group: &ProxyGroupConfig) -> Option<(Vec<String>, String)> {
+    let mut entries = Vec::new();
+    let mut filters = Vec::new();
+    for rule in &group.proxies {
+        if let Some(name) = rule.strip_prefix("[]") {
+            entries.push(name.to_string());
+        } else if rule.starts_with("!!") || rule.starts_with("script:") {
+            return None;
+        } else {
+            filters.push(rule.as_str());
+        }
+    }
+    if filters.is_empty() {
+        return None;
+    }
+    Some((entries, filters.join("`")))
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::generator::config::group::group_generate;
+    use crate::models::proxy_node::combined::CombinedProxy;
+    use crate::models::proxy_node::vless::VlessProxy;
+    use crate::models::ProxyGroupType;
 
     fn build_ssr_proxy(name: &str, cipher: &str, protocol: &str, obfs: &str) -> Proxy {
         Proxy {
@@ -682,6 +1071,635 @@ mod tests {
             .unwrap_or_default()
     }
 
+    #[test]
+    fn clash_output_same_for_both_vmess_link_flavors() {
+        use crate::parser::explodes::explode;
+        use crate::utils::base64::base64_encode;
+
+        let v2rayn = format!(
+            "vmess://{}",
+            base64_encode(
+                r#"{"v":"2","ps":"HK 01","add":"vmess.example.com","port":"443","id":"b831381d-6324-4d53-ad4f-8cda48b30811","aid":"0","net":"ws","type":"none","host":"cdn.example.com","path":"/ws","tls":"tls","sni":"sni.example.com"}"#
+            )
+        );
+        let shadowrocket = format!(
+            "vmess://{}?remarks=HK%2001&obfs=websocket&path=%2Fws&obfsParam=cdn.example.com&tls=1&peer=sni.example.com",
+            base64_encode("auto:b831381d-6324-4d53-ad4f-8cda48b30811@vmess.example.com:443")
+        );
+
+        let clash = |link: &str| {
+            let mut node = Proxy::default();
+            assert!(explode(link, &mut node), "{}", link);
+            let mut yaml_node = YamlValue::Mapping(Mapping::new());
+            let mut ext = ExtraSettings {
+                clash_new_field_name: true,
+                ..Default::default()
+            };
+            proxy_to_clash_yaml(
+                &mut vec![node],
+                &mut yaml_node,
+                &vec![],
+                &vec![],
+                false,
+                &mut ext,
+            );
+            serde_yaml::to_string(&yaml_node["proxies"]).unwrap()
+        };
+        let output = clash(&v2rayn);
+        assert!(output.contains("servername: sni.example.com"), "{}", output);
+        assert!(output.contains("Host: cdn.example.com"), "{}", output);
+        assert_eq!(clash(&shadowrocket), output);
+    }
+
+    #[test]
+    fn clash_url_test_group_keeps_tuning_fields() {
+        let mut nodes = vec![build_ss_proxy("HK 01", "aes-128-gcm")];
+        let mut yaml_node = YamlValue::Mapping(Mapping::new());
+        let mut ext = ExtraSettings {
+            clash_new_field_name: true,
+            ..Default::default()
+        };
+        let mut group = ProxyGroupConfig::new("Auto".to_string(), ProxyGroupType::URLTest);
+        group.proxies = vec![".*".to_string()];
+        group.url = "http://cp.cloudflare.com".to_string();
+        group.interval = 60;
+        group.timeout = 3;
+        group.tolerance = 50;
+        group.lazy = Some(false);
+
+        proxy_to_clash_yaml(
+            &mut nodes,
+            &mut yaml_node,
+            &vec![],
+            &vec![group],
+            false,
+            &mut ext,
+        );
+
+        let group = &yaml_node["proxy-groups"][0];
+        assert_eq!(group["type"].as_str(), Some("url-test"));
+        assert_eq!(group["url"].as_str(), Some("http://cp.cloudflare.com"));
+        assert_eq!(group["interval"].as_u64(), Some(60));
+        assert_eq!(group["timeout"].as_u64(), Some(3000));
+        assert_eq!(group["tolerance"].as_u64(), Some(50));
+        assert_eq!(group["lazy"].as_bool(), Some(false));
+    }
+
+    #[test]
+    fn clash_output_keeps_base_key_order_and_unknown_keys() {
+        let base = r#"mixed-port: 7890
+allow-lan: false
+mode: rule
+log-level: info
+external-controller: 127.0.0.1:9090
+secret: ""
+unified-delay: true
+geodata-mode: true
+geox-url:
+  geoip: https://example.com/geoip.dat
+dns:
+  enable: true
+  enhanced-mode: fake-ip
+  nameserver: [223.5.5.5, 119.29.29.29]
+proxies: ~
+proxy-groups: ~
+rules:
+  - DOMAIN-SUFFIX,local,DIRECT
+x-vendor-extension:
+  keep: me
+"#;
+        let mut nodes = vec![build_ss_proxy("HK 01", "aes-128-gcm")];
+        let mut group = ProxyGroupConfig::new("Proxy".to_string(), ProxyGroupType::Select);
+        group.proxies = vec![".*".to_string()];
+        let mut ext = ExtraSettings {
+            clash_new_field_name: true,
+            enable_rule_generator: true,
+            ..Default::default()
+        };
+
+        let output = proxy_to_clash(
+            &mut nodes,
+            base,
+            &mut Vec::new(),
+            &vec![group],
+            false,
+            &mut ext,
+        )
+        .unwrap();
+
+        let base: YamlValue = serde_yaml::from_str(base).unwrap();
+        let output: YamlValue = serde_yaml::from_str(&output).unwrap();
+        let base_map = base.as_mapping().unwrap();
+        let output_map = output.as_mapping().unwrap();
+        assert_eq!(
+            output_map.keys().collect::<Vec<_>>(),
+            base_map.keys().collect::<Vec<_>>()
+        );
+        for (key, value) in base_map {
+            if !matches!(key.as_str(), Some("proxies" | "proxy-groups")) {
+                assert_eq!(output_map.get(key), Some(value));
+            }
+        }
+        assert_eq!(extract_proxy_names(&output), vec!["HK 01".to_string()]);
+    }
+
+    #[test]
+    fn clash_overwrite_original_proxies_and_groups() {
+        let base = r#"Proxy:
+  - {name: Placeholder, type: ss, server: example.org, port: 1, cipher: aes-128-gcm, password: x}
+proxies:
+  - {name: Template, type: ss, server: example.org, port: 2, cipher: aes-128-gcm, password: x}
+proxy-groups:
+  - {name: Stale, type: select, proxies: [Template]}
+  - {name: Proxy, type: select, proxies: [Template]}
+"#;
+        let convert = |overwrite: bool| {
+            let mut nodes = vec![build_ss_proxy("HK 01", "aes-128-gcm")];
+            let mut group = ProxyGroupConfig::new("Proxy".to_string(), ProxyGroupType::Select);
+            group.proxies = vec![".*".to_string()];
+            let mut ext = ExtraSettings {
+                clash_new_field_name: true,
+                overwrite_original_proxies: overwrite,
+                overwrite_original_groups: overwrite,
+                ..Default::default()
+            };
+            let output = proxy_to_clash(
+                &mut nodes,
+                base,
+                &mut Vec::new(),
+                &vec![group],
+                false,
+                &mut ext,
+            )
+            .unwrap();
+            serde_yaml::from_str::<YamlValue>(&output).unwrap()
+        };
+        let group_names = |output: &YamlValue| -> Vec<String> {
+            output["proxy-groups"]
+                .as_sequence()
+                .unwrap()
+                .iter()
+                .map(|group| group["name"].as_str().unwrap().to_string())
+                .collect()
+        };
+
+        // Groups named like generated ones are replaced either way
+        let merged = convert(false);
+        assert_eq!(extract_proxy_names(&merged), vec!["HK 01".to_string()]);
+        assert!(merged.get("Proxy").is_some());
+        assert_eq!(group_names(&merged), ["Stale", "Proxy"]);
+        assert_eq!(
+            merged["proxy-groups"][1]["proxies"][0].as_str(),
+            Some("HK 01")
+        );
+
+        let overwritten = convert(true);
+        assert_eq!(extract_proxy_names(&overwritten), vec!["HK 01".to_string()]);
+        assert!(overwritten.get("Proxy").is_none());
+        assert_eq!(group_names(&overwritten), ["Proxy"]);
+    }
+
+    #[test]
+    fn clash_base_merge_keys_are_expanded() {
+        let base = r#"x-dns: &dns
+  enable: true
+  ipv6: false
+x-group: &group
+  type: url-test
+  url: http://www.gstatic.com/generate_204
+  interval: 300
+dns:
+  <<: *dns
+  ipv6: true
+  enhanced-mode: fake-ip
+proxy-groups:
+  - name: Auto
+    <<: *group
+    proxies: [DIRECT]
+"#;
+        let mut nodes = vec![build_ss_proxy("HK 01", "aes-128-gcm")];
+        let mut ext = ExtraSettings {
+            clash_new_field_name: true,
+            ..Default::default()
+        };
+
+        let output =
+            proxy_to_clash(&mut nodes, base, &mut Vec::new(), &vec![], false, &mut ext).unwrap();
+        assert!(!output.contains("<<"));
+
+        let output: YamlValue = serde_yaml::from_str(&output).unwrap();
+        let dns = output["dns"].as_mapping().unwrap();
+        assert_eq!(
+            dns.keys().filter_map(|k| k.as_str()).collect::<Vec<_>>(),
+            vec!["enable", "ipv6", "enhanced-mode"]
+        );
+        assert_eq!(output["dns"]["ipv6"].as_bool(), Some(true));
+        let group = &output["proxy-groups"][0];
+        assert_eq!(group["type"].as_str(), Some("url-test"));
+        assert_eq!(group["interval"].as_u64(), Some(300));
+
+        // A node nested in its own anchor cannot be expanded
+        let cyclic = "a: &a\n  b: *a\n";
+        let result = proxy_to_clash(
+            &mut nodes,
+            cyclic,
+            &mut Vec::new(),
+            &vec![],
+            false,
+            &mut ext,
+        );
+        assert!(matches!(result, Err(ExportError::InvalidBase(_))));
+    }
+
+    #[test]
+    fn clash_base_is_parsed_once_per_content() {
+        let rules: String = (0..5000)
+            .map(|i| format!("  - DOMAIN-SUFFIX,site{}.example.com,DIRECT\n", i))
+            .collect();
+        let base = format!("mixed-port: 7890\nmode: rule\nrules:\n{}", rules);
+
+        let started = std::time::Instant::now();
+        let first = parse_clash_base(&base).unwrap();
+        let parse_time = started.elapsed();
+        let started = std::time::Instant::now();
+        let second = parse_clash_base(&base).unwrap();
+        let hit_time = started.elapsed();
+        // A hit only hashes the text, about 10x faster than parsing this base
+        // (roughly 60ms against 6ms in debug builds)
+        assert!(Arc::ptr_eq(&first, &second));
+        assert!(hit_time < parse_time);
+
+        // Generating a config works on a copy and leaves the cached base untouched
+        let mut nodes = vec![build_ss_proxy("HK 01", "aes-128-gcm")];
+        let mut ext = ExtraSettings {
+            clash_new_field_name: true,
+            ..Default::default()
+        };
+        proxy_to_clash(&mut nodes, &base, &mut Vec::new(), &vec![], false, &mut ext).unwrap();
+        let third = parse_clash_base(&base).unwrap();
+        assert!(Arc::ptr_eq(&first, &third));
+        assert!(third.get("proxies").is_none());
+
+        // Any change to the text is a different entry
+        let edited = parse_clash_base(&base.replace("7890", "7891")).unwrap();
+        assert!(!Arc::ptr_eq(&first, &edited));
+        assert_eq!(edited["mixed-port"].as_u64(), Some(7891));
+    }
+
+    #[test]
+    fn clash_large_subscription_matches_serial_conversion() {
+        let mut nodes: Vec<Proxy> = (0..1000)
+            .map(|i| build_ss_proxy(&format!("Node {}", i % 100), "aes-128-gcm"))
+            .collect();
+        nodes[7].proxy_type = ProxyType::Unknown;
+        let mut ext = ExtraSettings {
+            clash_new_field_name: true,
+            udp: Some(true),
+            ..Default::default()
+        };
+
+        let mut yaml_node = YamlValue::Mapping(Mapping::new());
+        proxy_to_clash_yaml(
+            &mut nodes,
+            &mut yaml_node,
+            &vec![],
+            &vec![],
+            false,
+            &mut ext,
+        );
+
+        let mut remarks_list = Vec::new();
+        let mut expected = Vec::new();
+        for node in nodes.iter() {
+            let mut remark = node.remark.clone();
+            process_remark(
+                &mut remark,
+                &remarks_list,
+                false,
+                &ext.duplicate_remark_format,
+            );
+            remarks_list.push(remark.clone());
+            if node.proxy_type == ProxyType::Unknown {
+                continue;
+            }
+            let node = node
+                .clone()
+                .set_remark(remark)
+                .apply_overrides(Some(true), None, None);
+            expected.push(ClashProxyOutput::from(node));
+        }
+        assert_eq!(expected.len(), 999);
+        assert_eq!(
+            serde_yaml::to_string(&yaml_node["proxies"]).unwrap(),
+            serde_yaml::to_string(&expected).unwrap()
+        );
+    }
+
+    #[test]
+    fn clash_borrowed_conversion_matches_node_copies() {
+        let uuid = "b831381d-6324-4d53-ad4f-8cda48b30811";
+        let mut nodes = Vec::new();
+        for i in 0..2000 {
+            let line = match i % 5 {
+                0 => format!("{{name: HK {i}, type: ss, server: s{i}.example.com, port: 443, cipher: aes-128-gcm, password: pwd, plugin: obfs, plugin-opts: {{mode: tls, host: a.com}}}}"),
+                1 => format!("{{name: JP {i}, type: vmess, server: s{i}.example.com, port: 443, uuid: {uuid}, alterId: 0, cipher: auto, tls: true, tfo: false}}"),
+                2 => format!("{{name: US {i}, type: trojan, server: s{i}.example.com, port: 443, password: pwd, sni: a.com, skip-cert-verify: true}}"),
+                3 => format!("{{name: SG {i}, type: hysteria2, server: s{i}.example.com, port: 443, password: pwd, alpn: [h3]}}"),
+                _ => {
+                    // VLESS nodes carry their udp setting in the combined proxy
+                    nodes.push(Proxy {
+                        proxy_type: ProxyType::Vless,
+                        remark: format!("TW {i}"),
+                        hostname: format!("s{i}.example.com"),
+                        port: 443,
+                        combined_proxy: Some(CombinedProxy::Vless(VlessProxy {
+                            uuid: uuid.to_string(),
+                            udp: false,
+                            tls: true,
+                            ..Default::default()
+                        })),
+                        ..Default::default()
+                    });
+                    continue;
+                }
+            };
+            assert!(crate::parser::explodes::explode_clash(
+                &format!("proxies:\n  - {}\n", line),
+                &mut nodes
+            ));
+        }
+        assert_eq!(nodes.len(), 2000);
+        for node in nodes.iter_mut().step_by(97) {
+            node.info_node = true;
+        }
+
+        let rules: [&[&str]; 5] = [
+            &["HK"],
+            &["JP|TW"],
+            &["!!TYPE=SS|VMess"],
+            &[".*"],
+            &["US", "[]DIRECT"],
+        ];
+        let groups: ProxyGroupConfigs = (0..20)
+            .map(|i| {
+                let group_type = if i % 2 == 0 {
+                    ProxyGroupType::Select
+                } else {
+                    ProxyGroupType::URLTest
+                };
+                let mut group = ProxyGroupConfig::new(format!("Group {}", i), group_type);
+                group.proxies = rules[i % 5].iter().map(|r| r.to_string()).collect();
+                group
+            })
+            .collect();
+
+        let (udp, tfo, scv) = (Some(true), Some(true), Some(false));
+        let mut ext = ExtraSettings {
+            clash_new_field_name: true,
+            udp,
+            tfo,
+            skip_cert_verify: scv,
+            ..Default::default()
+        };
+        let mut yaml_node = YamlValue::Mapping(Mapping::new());
+        proxy_to_clash_yaml(
+            &mut nodes,
+            &mut yaml_node,
+            &vec![],
+            &groups,
+            false,
+            &mut ext,
+        );
+
+        let expected: Vec<ClashProxyOutput> = nodes
+            .iter()
+            .map(|node| {
+                ClashProxyOutput::from(
+                    node.clone()
+                        .set_remark(node.remark.clone())
+                        .apply_overrides(udp, tfo, scv),
+                )
+            })
+            .collect();
+        // Plugin options come from a HashMap, so compare values rather than text
+        assert_eq!(
+            yaml_node["proxies"],
+            serde_yaml::to_value(&expected).unwrap()
+        );
+
+        for (i, group) in groups.iter().enumerate() {
+            let mut expected = Vec::new();
+            for rule in &group.proxies {
+                group_generate(rule, &nodes, &group.group_type, &mut expected, true, &ext);
+            }
+            let output: Vec<String> =
+                serde_yaml::from_value(yaml_node["proxy-groups"][i]["proxies"].clone()).unwrap();
+            assert_eq!(output, expected, "{}", group.name);
+        }
+    }
+
+    #[test]
+    fn clash_chunks_join_to_buffered_output() {
+        let rulesets: Vec<RulesetContent> = (0..3)
+            .map(|i| {
+                let mut ruleset = RulesetContent::new(&format!("rules/{}.list", i), "Proxy");
+                ruleset.set_rule_content(&format!(
+                    "DOMAIN-SUFFIX,site{i}.example.com\nIP-CIDR,10.{i}.0.0/16\n"
+                ));
+                ruleset
+            })
+            .collect();
+        let base = "port: 7890\nrules:\n  - DOMAIN,base.example.com,DIRECT\nmode: rule\n";
+        let mut ext = ExtraSettings {
+            clash_new_field_name: true,
+            enable_rule_generator: true,
+            ..Default::default()
+        };
+
+        let mut nodes = vec![build_ss_proxy("HK 01", "aes-128-gcm")];
+        let buffered = proxy_to_clash(
+            &mut nodes,
+            base,
+            &mut rulesets.clone(),
+            &vec![],
+            false,
+            &mut ext,
+        )
+        .unwrap();
+        let mut nodes = vec![build_ss_proxy("HK 01", "aes-128-gcm")];
+        let chunks: Vec<String> = proxy_to_clash_chunks(
+            &mut nodes,
+            base,
+            &mut rulesets.clone(),
+            &vec![],
+            false,
+            &mut ext,
+        )
+        .unwrap()
+        .collect();
+
+        // Config before the rules, the rules header, one chunk per ruleset and the rest
+        assert_eq!(chunks.len(), 6);
+        assert!(chunks[2].contains("site0.example.com"));
+        assert!(chunks[5].starts_with("mode: rule\nproxies:\n"));
+        assert_eq!(chunks.concat(), buffered);
+        assert!(buffered.contains("rules:\n  - DOMAIN,base.example.com,DIRECT\n"));
+    }
+
+    #[test]
+    fn clash_udp_override_only_applies_when_requested() {
+        let content = "proxies:\n  - {name: HK 01, type: ss, server: example.com, port: 443, cipher: aes-128-gcm, password: pwd, udp: true}\n";
+        let mut nodes = Vec::new();
+        assert!(crate::parser::explodes::explode_clash(content, &mut nodes));
+
+        let mut yaml_node = YamlValue::Mapping(Mapping::new());
+        let mut ext = ExtraSettings {
+            clash_new_field_name: true,
+            ..Default::default()
+        };
+        proxy_to_clash_yaml(
+            &mut nodes,
+            &mut yaml_node,
+            &vec![],
+            &vec![],
+            false,
+            &mut ext,
+        );
+        assert_eq!(yaml_node["proxies"][0]["udp"].as_bool(), Some(true));
+
+        let mut yaml_node = YamlValue::Mapping(Mapping::new());
+        ext.udp = Some(false);
+        proxy_to_clash_yaml(
+            &mut nodes,
+            &mut yaml_node,
+            &vec![],
+            &vec![],
+            false,
+            &mut ext,
+        );
+        assert_eq!(yaml_node["proxies"][0]["udp"].as_bool(), Some(false));
+    }
+
+    #[test]
+    fn clash_include_all_group_skips_node_listing() {
+        let mut nodes = vec![build_ss_proxy("HK 01", "aes-128-gcm")];
+        let mut yaml_node = YamlValue::Mapping(Mapping::new());
+        let mut ext = ExtraSettings {
+            clash_new_field_name: true,
+            ..Default::default()
+        };
+        let mut group = ProxyGroupConfig::new("All".to_string(), ProxyGroupType::Select);
+        group.include_all = true;
+        group.hidden = true;
+        group.icon = "https://example.com/all.png".to_string();
+
+        proxy_to_clash_yaml(
+            &mut nodes,
+            &mut yaml_node,
+            &vec![],
+            &vec![group],
+            false,
+            &mut ext,
+        );
+
+        let group = &yaml_node["proxy-groups"][0];
+        assert_eq!(group["include-all"].as_bool(), Some(true));
+        assert_eq!(group["hidden"].as_bool(), Some(true));
+        assert_eq!(group["icon"].as_str(), Some("https://example.com/all.png"));
+        assert!(group.get("proxies").is_none());
+    }
+
+    #[test]
+    fn clash_include_all_group_keeps_explicit_members() {
+        let mut nodes = vec![build_ss_proxy("HK 01", "aes-128-gcm")];
+        let mut yaml_node = YamlValue::Mapping(Mapping::new());
+        let mut ext = ExtraSettings {
+            clash_new_field_name: true,
+            ..Default::default()
+        };
+        let mut group = ProxyGroupConfig::new("All".to_string(), ProxyGroupType::Select);
+        group.include_all = true;
+        group.proxies = vec![
+            "[]Auto".to_string(),
+            "HK".to_string(),
+            "[]DIRECT".to_string(),
+        ];
+
+        proxy_to_clash_yaml(
+            &mut nodes,
+            &mut yaml_node,
+            &vec![],
+            &vec![group],
+            false,
+            &mut ext,
+        );
+
+        let group = &yaml_node["proxy-groups"][0];
+        assert_eq!(group["include-all"].as_bool(), Some(true));
+        let proxies: Vec<&str> = group["proxies"]
+            .as_sequence()
+            .unwrap()
+            .iter()
+            .filter_map(|proxy| proxy.as_str())
+            .collect();
+        assert_eq!(proxies, ["Auto", "DIRECT"]);
+    }
+
+    #[test]
+    fn clash_meta_native_filter_keeps_regex_rules() {
+        let mut nodes = vec![
+            build_ss_proxy("HK 01", "aes-128-gcm"),
+            build_ss_proxy("JP 01", "aes-128-gcm"),
+        ];
+        let mut group = ProxyGroupConfig::new("Asia".to_string(), ProxyGroupType::Select);
+        group.proxies = vec!["HK".to_string(), "JP".to_string(), "[]DIRECT".to_string()];
+        group.exclude_type = "ss|http".to_string();
+        let mut typed = ProxyGroupConfig::new("SS".to_string(), ProxyGroupType::Select);
+        typed.proxies = vec!["!!TYPE=SS".to_string()];
+        let groups = vec![group, typed];
+
+        let mut yaml_node = YamlValue::Mapping(Mapping::new());
+        let mut ext = ExtraSettings {
+            clash_new_field_name: true,
+            clash_meta: true,
+            native_filter: true,
+            ..Default::default()
+        };
+        proxy_to_clash_yaml(
+            &mut nodes,
+            &mut yaml_node,
+            &vec![],
+            &groups,
+            false,
+            &mut ext,
+        );
+
+        let native = &yaml_node["proxy-groups"][0];
+        assert_eq!(native["filter"].as_str(), Some("HK`JP"));
+        assert_eq!(native["exclude-type"].as_str(), Some("ss|http"));
+        assert_eq!(native["include-all"].as_bool(), Some(true));
+        assert_eq!(native["proxies"][0].as_str(), Some("DIRECT"));
+        // Matchers Clash.Meta lacks keep listing nodes
+        let typed = &yaml_node["proxy-groups"][1];
+        assert!(typed.get("filter").is_none());
+        assert_eq!(typed["proxies"].as_sequence().map(|s| s.len()), Some(2));
+
+        // Plain Clash keeps listing nodes
+        let mut yaml_node = YamlValue::Mapping(Mapping::new());
+        ext.clash_meta = false;
+        proxy_to_clash_yaml(
+            &mut nodes,
+            &mut yaml_node,
+            &vec![],
+            &groups,
+            false,
+            &mut ext,
+        );
+        let listed = &yaml_node["proxy-groups"][0];
+        assert!(listed.get("filter").is_none());
+        assert_eq!(listed["proxies"].as_sequence().map(|s| s.len()), Some(3));
+    }
+
     #[test]
     fn clash_with_filter_deprecated_keeps_supported_ssr() {
         let mut nodes = vec![build_ssr_proxy(
@@ -753,4 +1771,461 @@ mod tests {
         let names = extract_proxy_names(&yaml_node);
         assert!(names.is_empty());
     }
+
+    #[test]
+    fn clash_dns_is_deep_merged_into_base() {
+        let mut nodes = vec![build_ss_proxy("ss", "aes-128-gcm")];
+        let mut yaml_node: YamlValue = serde_yaml::from_str(
+            r#"
+mode: rule
+dns:
+  enable: false
+  listen: ":53"
+  fallback-filter:
+    geoip: false
+    ipcidr: [240.0.0.0/4]
+"#,
+        )
+        .unwrap();
+        let mut ext = ExtraSettings {
+            clash_new_field_name: true,
+            clash_dns: Some(
+                serde_yaml::from_str(
+                    r#"
+enable: true
+enhanced-mode: fake-ip
+nameserver: [223.5.5.5]
+fallback-filter:
+  geoip: true
+  geoip-code: CN
+"#,
+                )
+                .unwrap(),
+            ),
+            ..Default::default()
+        };
+
+        proxy_to_clash_yaml(
+            &mut nodes,
+            &mut yaml_node,
+            &vec![],
+            &vec![],
+            false,
+            &mut ext,
+        );
+
+        let dns = &yaml_node["dns"];
+        assert_eq!(yaml_node["mode"].as_str(), Some("rule"));
+        assert_eq!(dns["enable"].as_bool(), Some(true));
+        assert_eq!(dns["listen"].as_str(), Some(":53"));
+        assert_eq!(dns["enhanced-mode"].as_str(), Some("fake-ip"));
+        assert_eq!(dns["nameserver"][0].as_str(), Some("223.5.5.5"));
+        assert_eq!(dns["fallback-filter"]["geoip"].as_bool(), Some(true));
+        assert_eq!(dns["fallback-filter"]["geoip-code"].as_str(), Some("CN"));
+        assert_eq!(
+            dns["fallback-filter"]["ipcidr"][0].as_str(),
+            Some("240.0.0.0/4")
+        );
+    }
+
+    #[test]
+    fn clash_dns_is_added_when_base_has_none() {
+        let mut nodes = vec![build_ss_proxy("ss", "aes-128-gcm")];
+        let mut yaml_node = YamlValue::Mapping(Mapping::new());
+        let mut ext = ExtraSettings {
+            clash_new_field_name: true,
+            clash_dns: Some(serde_yaml::from_str("enable: true").unwrap()),
+            ..Default::default()
+        };
+
+        proxy_to_clash_yaml(
+            &mut nodes,
+            &mut yaml_node,
+            &vec![],
+            &vec![],
+            false,
+            &mut ext,
+        );
+
+        assert_eq!(yaml_node["dns"]["enable"].as_bool(), Some(true));
+    }
+
+    #[test]
+    fn clash_meta_sections_keep_base_values_unless_overwritten() {
+        let base = r#"
+tun:
+  enable: false
+  stack: system
+"#;
+        let tun: YamlValue =
+            serde_yaml::from_str("enable: true\nstack: mixed\nauto-route: true").unwrap();
+        let sniffer: YamlValue = serde_yaml::from_str("enable: true").unwrap();
+
+        for (clash_meta, overwrite) in [(false, false), (true, false), (true, true)] {
+            let mut nodes = vec![build_ss_proxy("ss", "aes-128-gcm")];
+            let mut yaml_node: YamlValue = serde_yaml::from_str(base).unwrap();
+            let mut ext = ExtraSettings {
+                clash_new_field_name: true,
+                clash_meta,
+                clash_tun: Some(tun.clone()),
+                clash_sniffer: Some(sniffer.clone()),
+                clash_meta_overwrite: overwrite,
+                ..Default::default()
+            };
+
+            proxy_to_clash_yaml(
+                &mut nodes,
+                &mut yaml_node,
+                &vec![],
+                &vec![],
+                false,
+                &mut ext,
+            );
+
+            let tun = &yaml_node["tun"];
+            if !clash_meta {
+                assert!(yaml_node.get("sniffer").is_none());
+                assert!(tun.get("auto-route").is_none());
+                continue;
+            }
+            assert_eq!(yaml_node["sniffer"]["enable"].as_bool(), Some(true));
+            assert_eq!(tun["auto-route"].as_bool(), Some(true));
+            assert_eq!(tun["enable"].as_bool(), Some(overwrite));
+            let stack = if overwrite { "mixed" } else { "system" };
+            assert_eq!(tun["stack"].as_str(), Some(stack));
+        }
+    }
+
+    #[test]
+    fn rules_translate_builtin_policies() {
+        let ruleset = |group: &str, content: &str| {
+            let mut ruleset = RulesetContent::new("", group);
+            ruleset.set_rule_content(content);
+            ruleset
+        };
+        let rulesets = vec![
+            ruleset("REJECT-TINYGIF", "[]DOMAIN-SUFFIX,ads.example.com"),
+            ruleset("REJECT-DROP", "[]DOMAIN-SUFFIX,track.example.com"),
+            ruleset("PASS", "[]DOMAIN,skip.example.com"),
+            ruleset("direct", "[]GEOIP,CN"),
+        ];
+        let rules = |clash_meta: bool| {
+            crate::generator::ruleconvert::ruleset_to_clash_str(
+                &YamlValue::Null,
+                &rulesets,
+                false,
+                true,
+                clash_meta,
+                &Arc::new(Settings::default()),
+            )
+        };
+
+        assert_eq!(
+            rules(false),
+            "\nrules:\n  - DOMAIN-SUFFIX,ads.example.com,REJECT\n  - DOMAIN-SUFFIX,track.example.com,REJECT\n  - GEOIP,CN,DIRECT\n"
+        );
+        assert_eq!(
+            rules(true),
+            "\nrules:\n  - DOMAIN-SUFFIX,ads.example.com,REJECT\n  - DOMAIN-SUFFIX,track.example.com,REJECT-DROP\n  - DOMAIN,skip.example.com,PASS\n  - GEOIP,CN,DIRECT\n"
+        );
+    }
+
+    #[test]
+    fn rules_keep_asn_for_clash_meta() {
+        let ruleset = |path: &str, group: &str, content: &str| {
+            let mut ruleset = RulesetContent::new(path, group);
+            ruleset.set_rule_content(content);
+            ruleset
+        };
+        let rulesets = vec![
+            ruleset(
+                "https://example.com/Cloudflare.list",
+                "Proxy",
+                "IP-ASN,13335,no-resolve\nIP-CIDR,1.1.1.0/24,no-resolve\n",
+            ),
+            ruleset("", "DIRECT", "[]IP-ASN,4134"),
+        ];
+        let rules = |clash_meta: bool| {
+            crate::generator::ruleconvert::ruleset_to_clash_str(
+                &YamlValue::Null,
+                &rulesets,
+                false,
+                true,
+                clash_meta,
+                &Arc::new(Settings::default()),
+            )
+        };
+
+        assert_eq!(
+            rules(true),
+            "\nrules:\n  - IP-ASN,13335,Proxy,no-resolve\n  - IP-CIDR,1.1.1.0/24,Proxy,no-resolve\n  - IP-ASN,4134,DIRECT\n"
+        );
+        assert_eq!(
+            rules(false),
+            "\nrules:\n  - IP-CIDR,1.1.1.0/24,Proxy,no-resolve\n"
+        );
+    }
+
+    #[test]
+    fn geo_rules_replace_rulesets_for_clash_meta() {
+        let ruleset = |path: &str, group: &str, content: &str| {
+            let mut ruleset = RulesetContent::new(path, group);
+            ruleset.set_rule_content(content);
+            ruleset
+        };
+        let mut china = ruleset(
+            "https://example.com/ChinaDomain.list",
+            "DIRECT",
+            "DOMAIN-SUFFIX,cn\nDOMAIN-SUFFIX,baidu.com\n",
+        );
+        china.geo = vec!["GEOSITE,cn".to_string(), "GEOIP,CN".to_string()];
+        let rulesets = vec![
+            ruleset(
+                "https://example.com/Ads.list",
+                "REJECT",
+                "DOMAIN,ads.example.com\n",
+            ),
+            china,
+            ruleset("", "Proxy", "[]FINAL"),
+        ];
+        let rules = |clash_meta: bool| {
+            crate::generator::ruleconvert::ruleset_to_clash_str(
+                &YamlValue::Null,
+                &rulesets,
+                false,
+                true,
+                clash_meta,
+                &Arc::new(Settings::default()),
+            )
+        };
+
+        assert_eq!(
+            rules(true),
+            "\nrules:\n  - DOMAIN,ads.example.com,REJECT\n  - GEOSITE,cn,DIRECT\n  - GEOIP,CN,DIRECT\n  - MATCH,Proxy\n"
+        );
+        assert_eq!(
+            rules(false),
+            "\nrules:\n  - DOMAIN,ads.example.com,REJECT\n  - DOMAIN-SUFFIX,cn,DIRECT\n  - DOMAIN-SUFFIX,baidu.com,DIRECT\n  - MATCH,Proxy\n"
+        );
+    }
+
+    #[test]
+    fn script_code_escapes_group_names() {
+        let ruleset = |path: &str, group: &str, content: &str| {
+            let mut ruleset = RulesetContent::new(path, group);
+            ruleset.set_rule_content(content);
+            ruleset
+        };
+        let rulesets = vec![
+            ruleset(
+                "https://example.com/Quote.list",
+                r#"Say "Hi""#,
+                "DOMAIN,hi.example.com",
+            ),
+            ruleset("", "It's CN", "[]GEOIP,CN"),
+            ruleset("", r"Back\slash", "[]FINAL"),
+        ];
+
+        let (_, code) = build_clash_script_parts(
+            &rulesets,
+            "https://sub.example.com",
+            86400,
+            &Settings::default(),
+        );
+        assert!(code.contains(r#"return "Say \"Hi\"""#), "{}", code);
+        assert!(code.contains(r#"ctx.log('[Script] matched Say \"Hi\" DOMAIN rule')"#));
+        assert!(code.contains(r"ctx.log('[Script] matched It\'s CN GEOIP rule')"));
+        assert!(code.contains(r#"return "It\'s CN""#));
+        assert!(code.ends_with(r#"  return "Back\\slash""#), "{}", code);
+    }
+
+    /// Whether every string literal of the Python `code` is closed on its line
+    fn python_strings_closed(code: &str) -> bool {
+        code.lines().all(|line| {
+            let mut quote = None;
+            let mut chars = line.chars();
+            while let Some(c) = chars.next() {
+                match (quote, c) {
+                    (Some(_), '\\') => {
+                        chars.next();
+                    }
+                    (Some(open), c) if c == open => quote = None,
+                    (None, '"' | '\'') => quote = Some(c),
+                    (None, '#') => break,
+                    _ => {}
+                }
+            }
+            quote.is_none()
+        })
+    }
+
+    #[test]
+    fn script_code_survives_hostile_names() {
+        let ruleset = |path: &str, group: &str, content: &str| {
+            let mut ruleset = RulesetContent::new(path, group);
+            ruleset.set_rule_content(content);
+            ruleset
+        };
+        let hostile = [
+            r#"HK" or True or ""#,
+            r#"x')
+    return "DIRECT" #"#,
+            "Line\nbreak\\",
+        ];
+        let rulesets = vec![
+            ruleset(
+                "https://example.com/Ads\".list",
+                hostile[0],
+                "DOMAIN,ads.example.com",
+            ),
+            ruleset("", hostile[1], "[]GEOIP,cn"),
+            ruleset("", hostile[1], r#"[]GEOIP,CN" or True or ""#),
+            ruleset("", hostile[2], "[]FINAL"),
+        ];
+
+        let (_, code) = build_clash_script_parts(
+            &rulesets,
+            "https://sub.example.com",
+            86400,
+            &Settings::default(),
+        );
+        assert!(python_strings_closed(&code), "{}", code);
+        for payload in hostile {
+            assert!(!code.contains(payload), "{}", code);
+        }
+        assert!(code.contains(r#"return "HK\" or True or \"""#), "{}", code);
+        assert!(code.contains("if ctx.geoip(ip) == \"CN\":"));
+        assert_eq!(code.matches("ctx.geoip(").count(), 1);
+        assert!(code.ends_with(r#"  return "Line\nbreak\\""#), "{}", code);
+        assert!(!python_strings_closed("  return \"open"));
+    }
+
+    #[test]
+    fn script_providers_follow_ruleset_behavior() {
+        let ruleset = |path: &str, content: &str| {
+            let mut ruleset = RulesetContent::new(path, "DIRECT");
+            ruleset.set_rule_content(content);
+            ruleset
+        };
+        let mut forced = ruleset("https://example.com/Lan.list", "DOMAIN,lan.example.com");
+        forced.behavior = Some(Behavior::Classical);
+        let rulesets = vec![
+            ruleset(
+                "https://example.com/Download.list",
+                "DOMAIN-SUFFIX,dl.example.com",
+            ),
+            ruleset(
+                "https://example.com/MOO.list",
+                "DOMAIN-SUFFIX,moo.example.com\nPROCESS-NAME,moo.exe",
+            ),
+            ruleset(
+                "https://example.com/Telegram.list",
+                "DOMAIN,t.me\nIP-CIDR6,2001:b28:f23d::/48",
+            ),
+            ruleset(
+                "https://example.com/Apple.list",
+                "DOMAIN-SUFFIX,apple.com\nIP-CIDR,17.0.0.0/8,no-resolve",
+            ),
+            forced,
+        ];
+
+        let (providers, _) = build_clash_script_parts(
+            &rulesets,
+            "https://sub.example.com",
+            86400,
+            &Settings::default(),
+        );
+        let behaviors: Vec<(&str, &str)> = providers
+            .iter()
+            .map(|(name, provider)| {
+                (
+                    name.as_str().unwrap(),
+                    provider["behavior"].as_str().unwrap(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            behaviors,
+            [
+                ("Download_domain", "domain"),
+                ("MOO", "classical"),
+                ("Telegram_domain", "domain"),
+                ("Telegram_ipcidr", "ipcidr"),
+                ("Apple_domain", "domain"),
+                ("Apple_ipcidr", "ipcidr"),
+                ("Lan", "classical"),
+            ]
+        );
+        assert!(providers["MOO"]["url"]
+            .as_str()
+            .unwrap()
+            .contains("type=6&"));
+        assert!(providers["Telegram_ipcidr"]["url"]
+            .as_str()
+            .unwrap()
+            .ends_with("&format=text"));
+        assert_eq!(providers["Telegram_ipcidr"]["format"], "text");
+        assert_eq!(
+            providers["Telegram_ipcidr"]["path"],
+            "./providers/rule-provider_Telegram_ipcidr.txt"
+        );
+    }
+
+    #[test]
+    fn rules_keep_ruleset_order_around_geoip_and_final() {
+        let ruleset = |path: &str, group: &str, content: &str| {
+            let mut ruleset = RulesetContent::new(path, group);
+            ruleset.set_rule_content(content);
+            ruleset
+        };
+        let rulesets = vec![
+            ruleset(
+                "https://example.com/Ads.list",
+                "REJECT",
+                "DOMAIN-SUFFIX,ads.example.com",
+            ),
+            ruleset("", "DIRECT", "[]GEOIP,CN"),
+            ruleset(
+                "https://example.com/Telegram.list",
+                "Proxy",
+                "IP-CIDR,91.108.4.0/22",
+            ),
+            ruleset("", "Proxy", "[]FINAL"),
+            ruleset(
+                "https://example.com/Late.list",
+                "DIRECT",
+                "DOMAIN,late.example.com",
+            ),
+        ];
+
+        let rules = crate::generator::ruleconvert::ruleset_to_clash_str(
+            &YamlValue::Null,
+            &rulesets,
+            false,
+            true,
+            false,
+            &Arc::new(Settings::default()),
+        );
+        let position = |text: &str, needle: &str| text.find(needle).unwrap();
+        assert!(position(&rules, "ads.example.com") < position(&rules, "GEOIP,CN,DIRECT"));
+        assert!(position(&rules, "GEOIP,CN,DIRECT") < position(&rules, "91.108.4.0/22"));
+        assert!(position(&rules, "91.108.4.0/22") < position(&rules, "MATCH,Proxy"));
+
+        let (providers, code) = build_clash_script_parts(
+            &rulesets,
+            "https://sub.example.com",
+            86400,
+            &Settings::default(),
+        );
+        assert!(providers.contains_key("Ads_domain"));
+        assert!(!providers.contains_key("Late_domain"));
+        let ads = position(&code, "rule_providers[\"Ads_domain\"]");
+        let lookup = position(&code, "ip = ctx.resolve_ip(host)");
+        let geoip = position(&code, "if ctx.geoip(ip) == \"CN\":");
+        let telegram = position(&code, "rule_providers[\"Telegram_ipcidr\"]");
+        assert!(ads < lookup && lookup < geoip && geoip < telegram);
+        assert!(code.contains("dns lookup error use Proxy"));
+        assert!(code.ends_with("  return \"Proxy\""));
+        assert!(!code.contains("geoips"));
+    }
 }
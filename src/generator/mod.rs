@@ -1,6 +1,7 @@
 pub mod config;
 pub mod exports;
 pub mod ruleconvert;
+pub mod validate;
 pub mod yaml;
 
 // Re-export rule conversion functions
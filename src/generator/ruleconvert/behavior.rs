@@ -0,0 +1,150 @@
+//! Behavior of the Clash rule providers a ruleset is served as
+//!
+//! A `domain` or `ipcidr` provider only holds the values of its own rule
+//! types, so a ruleset with any other rule has to be served as `classical`,
+//! otherwise those rules would be lost.
+
+use std::fmt;
+use std::str::FromStr;
+
+/// How a ruleset is handed to Clash
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Behavior {
+    /// Only `DOMAIN`, `DOMAIN-SUFFIX` and `DOMAIN-KEYWORD` rules
+    Domain,
+    /// Only `IP-CIDR` and `IP-CIDR6` rules
+    Ipcidr,
+    /// Domain and IP rules, served as one provider of each
+    DomainAndIpcidr,
+    /// Any other rule, served as it is
+    Classical,
+}
+
+impl Behavior {
+    /// The behavior a provider of rules of `rule_type` can have, `None` when
+    /// only a classical provider holds them
+    pub fn of_rule_type(rule_type: &str) -> Option<Behavior> {
+        match rule_type {
+            "DOMAIN" | "DOMAIN-SUFFIX" | "DOMAIN-KEYWORD" => Some(Behavior::Domain),
+            "IP-CIDR" | "IP-CIDR6" => Some(Behavior::Ipcidr),
+            _ => None,
+        }
+    }
+
+    /// Whether a domain provider is served
+    pub fn has_domain(self) -> bool {
+        matches!(self, Behavior::Domain | Behavior::DomainAndIpcidr)
+    }
+
+    /// Whether an ipcidr provider is served
+    pub fn has_ipcidr(self) -> bool {
+        matches!(self, Behavior::Ipcidr | Behavior::DomainAndIpcidr)
+    }
+
+    /// The name Clash and ruleset lines give the behavior
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Behavior::Domain => "domain",
+            Behavior::Ipcidr => "ipcidr",
+            Behavior::DomainAndIpcidr => "domain-ipcidr",
+            Behavior::Classical => "classical",
+        }
+    }
+}
+
+impl FromStr for Behavior {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "domain" => Ok(Behavior::Domain),
+            "ipcidr" => Ok(Behavior::Ipcidr),
+            "domain-ipcidr" => Ok(Behavior::DomainAndIpcidr),
+            "classical" | "classic" => Ok(Behavior::Classical),
+            _ => Err(format!("Unknown ruleset behavior: {}", s)),
+        }
+    }
+}
+
+impl fmt::Display for Behavior {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// Detects the behavior of a ruleset in Surge format
+///
+/// Blank and comment lines are skipped. A ruleset holding no rule at all is
+/// classical, as is one with any rule which is neither a domain nor an IP
+/// rule.
+pub fn detect_ruleset_behavior(content: &str) -> Behavior {
+    let mut has_domain = false;
+    let mut has_ipcidr = false;
+
+    for line in content.lines().map(str::trim) {
+        if line.is_empty()
+            || line.starts_with('#')
+            || line.starts_with(';')
+            || line.starts_with("//")
+        {
+            continue;
+        }
+        let rule_type = line.split(',').next().unwrap_or("").trim();
+        match Behavior::of_rule_type(rule_type) {
+            Some(Behavior::Domain) => has_domain = true,
+            Some(Behavior::Ipcidr) => has_ipcidr = true,
+            _ => return Behavior::Classical,
+        }
+    }
+
+    match (has_domain, has_ipcidr) {
+        (true, true) => Behavior::DomainAndIpcidr,
+        (true, false) => Behavior::Domain,
+        (false, true) => Behavior::Ipcidr,
+        (false, false) => Behavior::Classical,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_detect_ruleset_behavior() {
+        assert_eq!(
+            detect_ruleset_behavior("# Ads\nDOMAIN-SUFFIX,ad.com\n\nDOMAIN-KEYWORD,ads\n"),
+            Behavior::Domain
+        );
+        assert_eq!(
+            detect_ruleset_behavior("IP-CIDR,10.0.0.0/8,no-resolve\nIP-CIDR6,fc00::/7\n"),
+            Behavior::Ipcidr
+        );
+        assert_eq!(
+            detect_ruleset_behavior("DOMAIN,t.me\n// Telegram\nIP-CIDR,91.108.4.0/22\n"),
+            Behavior::DomainAndIpcidr
+        );
+        assert_eq!(
+            detect_ruleset_behavior("DOMAIN-SUFFIX,example.com\nPROCESS-NAME,aria2c\n"),
+            Behavior::Classical
+        );
+        assert_eq!(
+            detect_ruleset_behavior("IP-CIDR,1.1.1.1/32\nDST-PORT,6881\n"),
+            Behavior::Classical
+        );
+        assert_eq!(detect_ruleset_behavior("# nothing\n"), Behavior::Classical);
+    }
+
+    #[test]
+    fn test_behavior_names() {
+        for behavior in [
+            Behavior::Domain,
+            Behavior::Ipcidr,
+            Behavior::DomainAndIpcidr,
+            Behavior::Classical,
+        ] {
+            assert_eq!(behavior.as_str().parse::<Behavior>(), Ok(behavior));
+        }
+        assert_eq!(" Classic".parse::<Behavior>(), Ok(Behavior::Classical));
+        assert!("rule".parse::<Behavior>().is_err());
+    }
+}
@@ -1,3 +1,5 @@
+use log::warn;
+
 /// Transforms a rule to a common format for use in different proxy clients
 ///
 /// # Arguments
@@ -38,3 +40,151 @@ pub fn transform_rule_to_common(input: &str, group: &str, no_resolve_only: bool)
         result
     }
 }
+
+/// Clients rules are written for, as far as their built-in policies differ
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PolicyTarget {
+    Clash,
+    ClashMeta,
+    Surge,
+    Surfboard,
+    QuantumultX,
+    Quantumult,
+    Loon,
+    Mellow,
+    SingBox,
+}
+
+/// Built-in policies rules may send traffic to besides the groups
+pub const BUILTIN_POLICIES: &[&str] = &[
+    "DIRECT",
+    "REJECT",
+    "REJECT-DROP",
+    "REJECT-NO-DROP",
+    "REJECT-TINYGIF",
+    "PASS",
+];
+
+/// Whether `name` is one of the [`BUILTIN_POLICIES`] rather than a group
+pub fn is_builtin_policy(name: &str) -> bool {
+    BUILTIN_POLICIES
+        .iter()
+        .any(|policy| policy.eq_ignore_ascii_case(name))
+}
+
+/// Name `target` knows the built-in policy `name` by
+///
+/// Rejects fall back to the plain reject of clients lacking the variant,
+/// `None` means the client has no equivalent at all (`PASS` outside of
+/// Clash.Meta) and rules sending traffic there are left out. Groups and
+/// policies not listed in [`BUILTIN_POLICIES`] are kept as they are.
+pub fn translate_policy(name: &str, target: PolicyTarget) -> Option<String> {
+    use PolicyTarget::*;
+
+    if !is_builtin_policy(name) || target == Mellow {
+        return Some(name.to_string());
+    }
+    let policy = name.to_ascii_uppercase();
+    let translated = match (policy.as_str(), target) {
+        ("PASS", ClashMeta) => "PASS",
+        ("PASS", _) => return None,
+        ("DIRECT", QuantumultX) => "direct",
+        ("DIRECT", _) => "DIRECT",
+        ("REJECT-TINYGIF", QuantumultX) => "reject-img",
+        ("REJECT-TINYGIF", Loon) => "REJECT-IMG",
+        ("REJECT-TINYGIF", Surge | Surfboard) => "REJECT-TINYGIF",
+        ("REJECT-DROP", ClashMeta | Surge | Loon) => "REJECT-DROP",
+        ("REJECT-NO-DROP", Surge) => "REJECT-NO-DROP",
+        (_, QuantumultX) => "reject",
+        _ => "REJECT",
+    };
+    Some(translated.to_string())
+}
+
+/// Whether `rule` is an `IP-ASN` rule, matching the autonomous system the
+/// destination IP belongs to
+pub fn is_asn_rule(rule: &str) -> bool {
+    rule.split(',')
+        .next()
+        .is_some_and(|rule_type| rule_type.trim().eq_ignore_ascii_case("IP-ASN"))
+}
+
+/// Tell that `dropped` `IP-ASN` rules of `rule_path` were left out as
+/// `target` has no equivalent
+///
+/// Only Clash.Meta and Surge match by ASN, the rest of a ruleset still
+/// applies elsewhere.
+pub fn warn_dropped_asn_rules(rule_path: &str, target: PolicyTarget, dropped: usize) {
+    if dropped == 0 {
+        return;
+    }
+    if rule_path.is_empty() {
+        warn!(
+            "Dropped inline IP-ASN rule: {:?} has no IP-ASN rule",
+            target
+        );
+    } else {
+        warn!(
+            "Dropped {} IP-ASN rule(s) of ruleset '{}': {:?} has no IP-ASN rule",
+            dropped, rule_path, target
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_translate_policy() {
+        use PolicyTarget::*;
+
+        let cases = [
+            ("REJECT-TINYGIF", Clash, Some("REJECT")),
+            ("REJECT-TINYGIF", Surge, Some("REJECT-TINYGIF")),
+            ("REJECT-TINYGIF", QuantumultX, Some("reject-img")),
+            ("reject-drop", ClashMeta, Some("REJECT-DROP")),
+            ("REJECT-DROP", Clash, Some("REJECT")),
+            ("REJECT-DROP", SingBox, Some("REJECT")),
+            ("REJECT-NO-DROP", Loon, Some("REJECT")),
+            ("PASS", ClashMeta, Some("PASS")),
+            ("PASS", Clash, None),
+            ("PASS", Surge, None),
+            ("Direct", QuantumultX, Some("direct")),
+            ("direct", Clash, Some("DIRECT")),
+            ("REJECT-DICT", QuantumultX, Some("REJECT-DICT")),
+            ("Proxy", SingBox, Some("Proxy")),
+            ("REJECT", Mellow, Some("REJECT")),
+        ];
+        for (name, target, expected) in cases {
+            assert_eq!(
+                translate_policy(name, target).as_deref(),
+                expected,
+                "{} for {:?}",
+                name,
+                target
+            );
+        }
+    }
+
+    #[test]
+    fn test_asn_rules_keep_no_resolve() {
+        assert!(is_asn_rule("IP-ASN,13335,no-resolve"));
+        assert!(is_asn_rule("ip-asn,13335"));
+        assert!(!is_asn_rule("IP-CIDR,1.1.1.0/24"));
+        assert!(!is_asn_rule("GEOIP,CN"));
+
+        assert_eq!(
+            transform_rule_to_common("IP-ASN,13335,no-resolve", "Proxy", false),
+            "IP-ASN,13335,Proxy,no-resolve"
+        );
+        assert_eq!(
+            transform_rule_to_common("IP-ASN,13335,no-resolve", "Proxy", true),
+            "IP-ASN,13335,Proxy,no-resolve"
+        );
+        assert_eq!(
+            transform_rule_to_common("IP-ASN,13335", "Proxy", false),
+            "IP-ASN,13335,Proxy"
+        );
+    }
+}
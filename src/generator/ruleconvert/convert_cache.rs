@@ -0,0 +1,202 @@
+//! Cache of converted rulesets
+//!
+//! The same ruleset text is converted on every request using it, and more than
+//! once per request when several writers go through it. Conversions are kept in
+//! a least recently used cache bounded by entry count and total bytes.
+
+use std::collections::{BTreeMap, HashMap};
+use std::sync::{Arc, Mutex};
+
+use once_cell::sync::Lazy;
+
+use super::convert_ruleset::convert_ruleset;
+use crate::models::RulesetType;
+use crate::utils::lock_recovering;
+use crate::utils::string::md5;
+use crate::Settings;
+
+/// Converted rulesets keyed by the md5 of the source text and its type, the
+/// output is always in the Surge form written by [`convert_ruleset`]
+type CacheKey = (String, RulesetType);
+
+static CONVERT_CACHE: Lazy<Mutex<ConvertCache>> = Lazy::new(|| Mutex::new(ConvertCache::new(0, 0)));
+
+struct CacheEntry {
+    content: Arc<str>,
+    last_used: u64,
+}
+
+/// Least recently used cache of converted rulesets
+struct ConvertCache {
+    max_entries: usize,
+    max_bytes: usize,
+    bytes: usize,
+    tick: u64,
+    entries: HashMap<CacheKey, CacheEntry>,
+    /// Keys ordered by last use, oldest first
+    recency: BTreeMap<u64, CacheKey>,
+}
+
+impl ConvertCache {
+    fn new(max_entries: usize, max_bytes: usize) -> Self {
+        Self {
+            max_entries,
+            max_bytes,
+            bytes: 0,
+            tick: 0,
+            entries: HashMap::new(),
+            recency: BTreeMap::new(),
+        }
+    }
+
+    /// Apply new bounds, evicting whatever no longer fits
+    fn set_limits(&mut self, max_entries: usize, max_bytes: usize) {
+        self.max_entries = max_entries;
+        self.max_bytes = max_bytes;
+        self.evict();
+    }
+
+    fn get(&mut self, key: &CacheKey) -> Option<Arc<str>> {
+        self.tick += 1;
+        let entry = self.entries.get_mut(key)?;
+        self.recency.remove(&entry.last_used);
+        self.recency.insert(self.tick, key.clone());
+        entry.last_used = self.tick;
+        Some(entry.content.clone())
+    }
+
+    fn insert(&mut self, key: CacheKey, content: Arc<str>) {
+        // An entry larger than the whole cache would only push everything else out
+        if content.len() > self.max_bytes || self.max_entries == 0 {
+            return;
+        }
+        self.tick += 1;
+        self.bytes += content.len();
+        self.recency.insert(self.tick, key.clone());
+        let entry = CacheEntry {
+            content,
+            last_used: self.tick,
+        };
+        if let Some(old) = self.entries.insert(key, entry) {
+            self.bytes -= old.content.len();
+            self.recency.remove(&old.last_used);
+        }
+        self.evict();
+    }
+
+    fn evict(&mut self) {
+        while self.entries.len() > self.max_entries || self.bytes > self.max_bytes {
+            let Some((_, key)) = self.recency.pop_first() else {
+                break;
+            };
+            if let Some(entry) = self.entries.remove(&key) {
+                self.bytes -= entry.content.len();
+            }
+        }
+    }
+
+    fn clear(&mut self) {
+        self.entries.clear();
+        self.recency.clear();
+        self.bytes = 0;
+    }
+}
+
+/// Same as [`convert_ruleset`], but reuses earlier conversions of the same text
+///
+/// The cache is bounded by `convert_cache_entries` and `convert_cache_size` of
+/// `settings` and skipped altogether when `bypass_convert_cache` is set.
+pub fn convert_ruleset_cached(
+    content: &str,
+    ruleset_type: RulesetType,
+    settings: &Settings,
+) -> Arc<str> {
+    let max_entries = settings.convert_cache_entries;
+    let max_bytes = settings.convert_cache_size;
+    if settings.bypass_convert_cache || max_entries == 0 || max_bytes == 0 {
+        return convert_ruleset(content, ruleset_type).into();
+    }
+
+    let key = (md5(content), ruleset_type);
+    {
+        let mut cache = lock_recovering(&CONVERT_CACHE);
+        cache.set_limits(max_entries, max_bytes);
+        if let Some(converted) = cache.get(&key) {
+            return converted;
+        }
+    }
+
+    // Converting outside the lock, a concurrent miss on the same key only
+    // converts twice
+    let converted: Arc<str> = convert_ruleset(content, ruleset_type).into();
+    lock_recovering(&CONVERT_CACHE).insert(key, converted.clone());
+    converted
+}
+
+/// Drop every cached conversion
+pub fn clear_convert_cache() {
+    lock_recovering(&CONVERT_CACHE).clear();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key(name: &str) -> CacheKey {
+        (md5(name), RulesetType::Surge)
+    }
+
+    #[test]
+    fn test_least_recently_used_entry_is_evicted() {
+        let mut cache = ConvertCache::new(2, 1024);
+        cache.insert(key("a"), "DOMAIN,a.com\n".into());
+        cache.insert(key("b"), "DOMAIN,b.com\n".into());
+        assert!(cache.get(&key("a")).is_some());
+
+        cache.insert(key("c"), "DOMAIN,c.com\n".into());
+        assert_eq!(cache.entries.len(), 2);
+        assert!(cache.get(&key("b")).is_none());
+        assert!(cache.get(&key("a")).is_some());
+        assert!(cache.get(&key("c")).is_some());
+    }
+
+    #[test]
+    fn test_total_size_is_bounded() {
+        let mut cache = ConvertCache::new(16, 10);
+        cache.insert(key("a"), "1234".into());
+        cache.insert(key("b"), "5678".into());
+        cache.insert(key("c"), "90ab".into());
+        assert_eq!(cache.bytes, 8);
+        assert!(cache.get(&key("a")).is_none());
+
+        // Too large to be cached at all
+        cache.insert(key("d"), "0123456789ab".into());
+        assert!(cache.get(&key("d")).is_none());
+        assert_eq!(cache.entries.len(), 2);
+
+        // Replacing an entry accounts for the old size
+        cache.insert(key("b"), "56".into());
+        assert_eq!(cache.bytes, 6);
+
+        cache.set_limits(1, 10);
+        assert_eq!(cache.entries.len(), 1);
+        assert!(cache.get(&key("b")).is_some());
+    }
+
+    #[test]
+    fn test_cached_conversion_matches_direct_conversion() {
+        let content = "payload:\n  - DOMAIN-SUFFIX,example.com\n  - IP-CIDR,10.0.0.0/8\n";
+        let settings = Settings::default();
+        let first = convert_ruleset_cached(content, RulesetType::ClashClassical, &settings);
+        let second = convert_ruleset_cached(content, RulesetType::ClashClassical, &settings);
+        assert_eq!(
+            &*first,
+            convert_ruleset(content, RulesetType::ClashClassical)
+        );
+        assert!(Arc::ptr_eq(&first, &second));
+
+        // The same text read as another type is another entry
+        let surge = convert_ruleset_cached(content, RulesetType::Surge, &settings);
+        assert_eq!(&*surge, content);
+    }
+}
@@ -3,7 +3,9 @@
 //! This module provides functionality for converting proxy rules between different formats
 //! such as Clash, Surge, Quantumult X, etc.
 
+pub mod behavior;
 pub mod common;
+pub mod convert_cache;
 pub mod convert_ruleset;
 // Keep the ruleset module for now but don't use its RulesetType
 mod ruleset;
@@ -12,6 +14,8 @@ pub mod ruleset_to_clash_str;
 pub mod ruleset_to_sing_box;
 pub mod ruleset_to_surge;
 
+pub use behavior::{detect_ruleset_behavior, Behavior};
+pub use convert_cache::convert_ruleset_cached;
 pub use convert_ruleset::convert_ruleset;
 pub use ruleset_to_clash_str::ruleset_to_clash_str;
 pub use ruleset_to_sing_box::ruleset_to_sing_box;
@@ -50,6 +50,12 @@ lazy_static::lazy_static! {
         set
     };
 
+    pub static ref CLASH_META_RULE_TYPES: HashSet<&'static str> = {
+        let mut set = CLASH_RULE_TYPES.clone();
+        set.insert("IP-ASN");
+        set
+    };
+
     pub static ref SURGE2_RULE_TYPES: HashSet<&'static str> = {
         let mut set = BASIC_TYPES.clone();
         set.insert("IP-CIDR6");
@@ -67,6 +73,7 @@ lazy_static::lazy_static! {
         set.insert("AND");
         set.insert("OR");
         set.insert("NOT");
+        set.insert("IP-ASN");
         set
     };
 
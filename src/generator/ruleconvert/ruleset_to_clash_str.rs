@@ -9,9 +9,12 @@ use lazy_static::lazy_static;
 use log::warn;
 use serde_yaml::Value as YamlValue;
 use std::collections::HashSet;
+use std::sync::Arc;
 
-use super::common::transform_rule_to_common;
-use super::convert_ruleset::convert_ruleset;
+use super::common::{
+    is_asn_rule, transform_rule_to_common, translate_policy, warn_dropped_asn_rules, PolicyTarget,
+};
+use super::convert_cache::convert_ruleset_cached;
 
 lazy_static! {
     static ref CLASH_RULE_TYPES: HashSet<&'static str> = {
@@ -32,6 +35,13 @@ lazy_static! {
         types.insert("PROCESS-NAME");
         types
     };
+
+    static ref CLASH_META_RULE_TYPES: HashSet<&'static str> = {
+        let mut types = CLASH_RULE_TYPES.clone();
+        // Clash.Meta specific types
+        types.insert("IP-ASN");
+        types
+    };
 }
 
 /// Converts rulesets to Clash YAML string format
@@ -42,6 +52,8 @@ lazy_static! {
 /// * `ruleset_content_array` - Array of ruleset content
 /// * `overwrite_original_rules` - Whether to overwrite original rules
 /// * `new_field_name` - Whether to use "rules" or "Rule" as the field name
+/// * `clash_meta` - Whether the built-in policies of Clash.Meta are available
+/// * `settings` - Settings limiting the rules and caching their conversion
 ///
 /// # Returns
 ///
@@ -51,14 +63,36 @@ pub fn ruleset_to_clash_str(
     ruleset_content_array: &[RulesetContent],
     overwrite_original_rules: bool,
     new_field_name: bool,
+    clash_meta: bool,
+    settings: &Arc<Settings>,
 ) -> String {
-    // Get global settings
-    let settings = Settings::current();
+    ruleset_to_clash_chunks(
+        base_rule,
+        ruleset_content_array,
+        overwrite_original_rules,
+        new_field_name,
+        clash_meta,
+        settings,
+    )
+    .collect()
+}
 
+/// Same as [`ruleset_to_clash_str`], but converts one ruleset at a time as the
+/// text is consumed
+///
+/// The first chunk holds the field name and the base rules, each following one
+/// the rules of a single ruleset.
+pub fn ruleset_to_clash_chunks(
+    base_rule: &YamlValue,
+    ruleset_content_array: &[RulesetContent],
+    overwrite_original_rules: bool,
+    new_field_name: bool,
+    clash_meta: bool,
+    settings: &Arc<Settings>,
+) -> ClashRuleChunks {
     // Set field name based on parameter
     let field_name = if new_field_name { "rules" } else { "Rule" };
-    let mut output_content = format!("\n{}:\n", field_name);
-    let mut total_rules = 0;
+    let mut header = format!("\n{}:\n", field_name);
 
     // Include existing rules if not overwriting
     if !overwrite_original_rules {
@@ -66,103 +100,170 @@ pub fn ruleset_to_clash_str(
             if let Some(rules_array) = rules.as_sequence() {
                 for rule in rules_array {
                     if let Some(rule_str) = rule.as_str() {
-                        output_content.push_str(&format!("  - {}\n", rule_str));
+                        header.push_str(&format!("  - {}\n", rule_str));
                     }
                 }
             }
         }
     }
 
-    // Use the max_allowed_rules from global settings
-    let max_allowed_rules = settings.max_allowed_rules;
-
-    // Process each ruleset content
-    for ruleset in ruleset_content_array {
-        // Check if we've reached the maximum number of rules
-        if max_allowed_rules > 0 && total_rules >= max_allowed_rules {
-            break;
-        }
+    // The chunks outlive the borrowed array, so they own their rulesets
+    let rulesets: Vec<RulesetContent> = ruleset_content_array.to_vec();
+    ClashRuleChunks {
+        header: Some(header),
+        rulesets: rulesets.into_iter(),
+        total_rules: 0,
+        policy_target: if clash_meta {
+            PolicyTarget::ClashMeta
+        } else {
+            PolicyTarget::Clash
+        },
+        settings: Arc::clone(settings),
+    }
+}
 
-        // Get group name from ruleset
-        let rule_group = &ruleset.group;
+/// Rules section of a Clash config, written one ruleset at a time
+pub struct ClashRuleChunks {
+    header: Option<String>,
+    rulesets: std::vec::IntoIter<RulesetContent>,
+    total_rules: usize,
+    policy_target: PolicyTarget,
+    settings: Arc<Settings>,
+}
 
-        // Get rule content from the shared future-like container
-        let retrieved_rules = ruleset.get_rule_content();
+impl Iterator for ClashRuleChunks {
+    type Item = String;
 
-        // Skip empty rulesets
-        if retrieved_rules.is_empty() {
-            warn!(
-                "Failed to fetch ruleset or ruleset is empty: '{}'!",
-                ruleset.rule_path
-            );
-            continue;
+    fn next(&mut self) -> Option<String> {
+        if let Some(header) = self.header.take() {
+            return Some(header);
         }
 
-        // Handle special case for rules that start with "[]"
-        if starts_with(&retrieved_rules, "[]") {
-            let mut rule_line = retrieved_rules[2..].to_string();
+        let max_allowed_rules = self.settings.max_allowed_rules;
 
-            // Replace FINAL with MATCH for Clash compatibility
-            if starts_with(&rule_line, "FINAL") {
-                rule_line = rule_line.replacen("FINAL", "MATCH", 1);
+        // Process each ruleset content
+        for ruleset in self.rulesets.by_ref() {
+            // Check if we've reached the maximum number of rules
+            if max_allowed_rules > 0 && self.total_rules >= max_allowed_rules {
+                return None;
             }
 
-            // Transform rule to common format
-            let transformed = transform_rule_to_common(&rule_line, rule_group, false);
-            output_content.push_str(&format!("  - {}\n", transformed));
-            total_rules += 1;
-            continue;
-        }
-
-        // Convert ruleset based on its type
-        let processed_rules = convert_ruleset(&retrieved_rules, ruleset.rule_type);
-
-        // Detect line break style
-        let _line_break = if processed_rules.contains("\r\n") {
-            "\r\n"
-        } else {
-            "\n"
-        };
+            // Get the policy of the ruleset as Clash names it
+            let Some(rule_group) = translate_policy(&ruleset.group, self.policy_target) else {
+                warn!(
+                    "Skipping ruleset '{}': {:?} has no {} policy",
+                    ruleset.rule_path, self.policy_target, ruleset.group
+                );
+                continue;
+            };
+            let rule_group = &rule_group;
 
-        // Process each line in the ruleset
-        for line in processed_rules.lines() {
-            // Check if we've reached the maximum number of rules
-            if max_allowed_rules > 0 && total_rules >= max_allowed_rules {
-                break;
+            // Clash.Meta has the lists built in, the ruleset is not expanded
+            if self.policy_target == PolicyTarget::ClashMeta && !ruleset.geo.is_empty() {
+                let mut output_content = String::new();
+                for rule in &ruleset.geo {
+                    if max_allowed_rules > 0 && self.total_rules >= max_allowed_rules {
+                        break;
+                    }
+                    let transformed = transform_rule_to_common(rule, rule_group, false);
+                    output_content.push_str(&format!("  - {}\n", transformed));
+                    self.total_rules += 1;
+                }
+                return Some(output_content);
             }
 
-            // Trim whitespace from line
-            let mut str_line = line.trim().to_string();
-            let line_size = str_line.len();
+            // Get rule content from the shared future-like container
+            let retrieved_rules = ruleset.get_rule_content();
 
-            // Skip empty lines and comments (';', '#', or '//')
-            if line_size == 0
-                || (line_size >= 1 && (str_line.starts_with(';') || str_line.starts_with('#')))
-                || (line_size >= 2 && str_line.starts_with("//"))
-            {
+            // Skip empty rulesets
+            if retrieved_rules.is_empty() {
+                warn!(
+                    "Failed to fetch ruleset or ruleset is empty: '{}'!",
+                    ruleset.rule_path
+                );
                 continue;
             }
 
-            // Check if the rule type is supported by Clash
-            if !CLASH_RULE_TYPES
-                .iter()
-                .any(|&rule_type| starts_with(&str_line, rule_type))
-            {
-                continue;
+            let rule_types = if self.policy_target == PolicyTarget::ClashMeta {
+                &*CLASH_META_RULE_TYPES
+            } else {
+                &*CLASH_RULE_TYPES
+            };
+
+            // Handle special case for rules that start with "[]"
+            if starts_with(&retrieved_rules, "[]") {
+                let mut rule_line = retrieved_rules[2..].to_string();
+
+                if is_asn_rule(&rule_line) && !rule_types.contains("IP-ASN") {
+                    warn_dropped_asn_rules(&ruleset.rule_path, self.policy_target, 1);
+                    continue;
+                }
+
+                // Replace FINAL with MATCH for Clash compatibility
+                if starts_with(&rule_line, "FINAL") {
+                    rule_line = rule_line.replacen("FINAL", "MATCH", 1);
+                }
+
+                // Transform rule to common format
+                let transformed = transform_rule_to_common(&rule_line, rule_group, false);
+                self.total_rules += 1;
+                return Some(format!("  - {}\n", transformed));
             }
 
-            // Remove inline comments
-            if let Some(comment_pos) = find_str(&str_line, "//") {
-                str_line = str_line[..comment_pos].to_string();
-                str_line = trim(&str_line).to_string();
+            // Convert ruleset based on its type
+            let processed_rules =
+                convert_ruleset_cached(&retrieved_rules, ruleset.rule_type, &self.settings);
+            let mut output_content = String::new();
+            let mut dropped_asn_rules = 0;
+
+            // Process each line in the ruleset
+            for line in processed_rules.lines() {
+                // Check if we've reached the maximum number of rules
+                if max_allowed_rules > 0 && self.total_rules >= max_allowed_rules {
+                    break;
+                }
+
+                // Trim whitespace from line
+                let mut str_line = line.trim().to_string();
+                let line_size = str_line.len();
+
+                // Skip empty lines and comments (';', '#', or '//')
+                if line_size == 0
+                    || (line_size >= 1 && (str_line.starts_with(';') || str_line.starts_with('#')))
+                    || (line_size >= 2 && str_line.starts_with("//"))
+                {
+                    continue;
+                }
+
+                // Check if the rule type is supported by Clash
+                if !rule_types
+                    .iter()
+                    .any(|&rule_type| starts_with(&str_line, rule_type))
+                {
+                    if is_asn_rule(&str_line) {
+                        dropped_asn_rules += 1;
+                    }
+                    continue;
+                }
+
+                // Remove inline comments
+                if let Some(comment_pos) = find_str(&str_line, "//") {
+                    str_line = str_line[..comment_pos].to_string();
+                    str_line = trim(&str_line).to_string();
+                }
+
+                // Transform rule to common format and add to output
+                let transformed = transform_rule_to_common(&str_line, rule_group, false);
+                output_content.push_str(&format!("  - {}\n", transformed));
+                self.total_rules += 1;
             }
+            warn_dropped_asn_rules(&ruleset.rule_path, self.policy_target, dropped_asn_rules);
 
-            // Transform rule to common format and add to output
-            let transformed = transform_rule_to_common(&str_line, rule_group, false);
-            output_content.push_str(&format!("  - {}\n", transformed));
-            total_rules += 1;
+            if !output_content.is_empty() {
+                return Some(output_content);
+            }
         }
-    }
 
-    output_content
+        None
+    }
 }
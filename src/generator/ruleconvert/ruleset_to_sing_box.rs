@@ -9,7 +9,8 @@ use crate::Settings;
 use log::warn;
 use serde_json::{json, Map, Value};
 
-use super::convert_ruleset::convert_ruleset;
+use super::common::{is_asn_rule, translate_policy, warn_dropped_asn_rules, PolicyTarget};
+use super::convert_cache::convert_ruleset_cached;
 use super::ruleset::SINGBOX_RULE_TYPES;
 
 /// Converts rulesets to Sing-Box format and updates the JSON configuration
@@ -23,10 +24,8 @@ pub fn ruleset_to_sing_box(
     base_rule: &mut Value,
     ruleset_content_array: &[RulesetContent],
     overwrite_original_rules: bool,
+    settings: &Settings,
 ) {
-    // Get global settings
-    let settings = Settings::current();
-
     // Create rules array
     let mut rules = Value::Array(Vec::new());
 
@@ -79,7 +78,14 @@ pub fn ruleset_to_sing_box(
             break;
         }
 
-        let rule_group = &ruleset.group;
+        let Some(rule_group) = translate_policy(&ruleset.group, PolicyTarget::SingBox) else {
+            warn!(
+                "Skipping ruleset '{}': sing-box has no {} policy",
+                ruleset.rule_path, ruleset.group
+            );
+            continue;
+        };
+        let rule_group = &rule_group;
         let retrieved_rules = ruleset.get_rule_content();
 
         if retrieved_rules.is_empty() {
@@ -94,8 +100,15 @@ pub fn ruleset_to_sing_box(
         if starts_with(&retrieved_rules, "[]") {
             let str_line = &retrieved_rules[2..];
 
+            // sing-box only falls back to `final` after every rule, so the
+            // ones configured after it are left out as they are elsewhere
             if starts_with(str_line, "FINAL") || starts_with(str_line, "MATCH") {
                 final_rule = rule_group.clone();
+                break;
+            }
+
+            if is_asn_rule(str_line) {
+                warn_dropped_asn_rules(&ruleset.rule_path, PolicyTarget::SingBox, 1);
                 continue;
             }
 
@@ -135,12 +148,13 @@ pub fn ruleset_to_sing_box(
         }
 
         // Convert ruleset based on its type
-        let converted_rules = convert_ruleset(&retrieved_rules, ruleset.rule_type);
+        let converted_rules = convert_ruleset_cached(&retrieved_rules, ruleset.rule_type, settings);
 
         // Create a new rule object for this ruleset
         let mut rule_obj = Map::new();
 
         // Process each rule line
+        let mut dropped_asn_rules = 0;
         for line in converted_rules.lines() {
             if settings.max_allowed_rules > 0 && total_rules >= settings.max_allowed_rules {
                 break;
@@ -173,6 +187,9 @@ pub fn ruleset_to_sing_box(
 
             // Skip if rule type is not supported
             if !SINGBOX_RULE_TYPES.contains(rule_type) {
+                if is_asn_rule(rule_type) {
+                    dropped_asn_rules += 1;
+                }
                 continue;
             }
 
@@ -193,6 +210,7 @@ pub fn ruleset_to_sing_box(
                 total_rules += 1;
             }
         }
+        warn_dropped_asn_rules(&ruleset.rule_path, PolicyTarget::SingBox, dropped_asn_rules);
 
         // Only add if rule object is not empty
         if !rule_obj.is_empty() {
@@ -218,3 +236,33 @@ pub fn ruleset_to_sing_box(
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_asn_rules_are_dropped() {
+        let ruleset = |path: &str, group: &str, content: &str| {
+            let mut ruleset = RulesetContent::new(path, group);
+            ruleset.set_rule_content(content);
+            ruleset
+        };
+        let rulesets = vec![
+            ruleset(
+                "https://example.com/Cloudflare.list",
+                "Proxy",
+                "IP-ASN,13335,no-resolve\nIP-CIDR,1.1.1.0/24,no-resolve\n",
+            ),
+            ruleset("", "DIRECT", "[]IP-ASN,4134"),
+        ];
+
+        let mut config = json!({});
+        ruleset_to_sing_box(&mut config, &rulesets, true, &Settings::default());
+        let rules = config["route"]["rules"].as_array().unwrap();
+        let last = rules.last().unwrap();
+        assert_eq!(last["ip_cidr"], json!(["1.1.1.0/24"]));
+        assert_eq!(last["outbound"], "Proxy");
+        assert!(rules.iter().all(|rule| rule.get("ip_asn").is_none()));
+    }
+}
@@ -13,8 +13,10 @@ use lazy_static::lazy_static;
 use log::warn;
 use std::collections::HashSet;
 
-use super::common::transform_rule_to_common;
-use super::convert_ruleset::convert_ruleset;
+use super::common::{
+    is_asn_rule, transform_rule_to_common, translate_policy, warn_dropped_asn_rules, PolicyTarget,
+};
+use super::convert_cache::convert_ruleset_cached;
 
 lazy_static! {
     static ref QUANX_RULE_TYPES: HashSet<&'static str> = {
@@ -96,6 +98,7 @@ lazy_static! {
         types.insert("AND");
         types.insert("OR");
         types.insert("NOT");
+        types.insert("IP-ASN");
         types.insert("PROCESS-NAME");
         types.insert("IN-PORT");
         types.insert("DEST-PORT");
@@ -104,6 +107,11 @@ lazy_static! {
     };
 }
 
+/// `[Remote Rule]` line of Loon sending the rules of `url` to `group`
+fn loon_remote_rule(url: &str, group: &str) -> String {
+    format!("{}, policy={}, tag={}, enabled=true", url, group, group)
+}
+
 /// Converts rulesets to Surge format and updates the INI configuration
 ///
 /// # Arguments
@@ -123,10 +131,8 @@ pub async fn ruleset_to_surge(
     surge_ver: i32,
     overwrite_original_rules: bool,
     remote_path_prefix: &str,
+    settings: &Settings,
 ) {
-    // Get global settings
-    let settings = Settings::current();
-
     // Set the appropriate section based on surge_ver
     match surge_ver {
         0 => base_rule.set_current_section("RoutingRule"), // Mellow
@@ -149,6 +155,15 @@ pub async fn ruleset_to_surge(
     let mut all_rules = Vec::new();
     let mut total_rules = 0;
 
+    let policy_target = match surge_ver {
+        0 => PolicyTarget::Mellow,
+        -1 => PolicyTarget::QuantumultX,
+        -2 => PolicyTarget::Quantumult,
+        -3 => PolicyTarget::Surfboard,
+        -4 => PolicyTarget::Loon,
+        _ => PolicyTarget::Surge,
+    };
+
     // Process each ruleset
     for ruleset in ruleset_content_array {
         // Check if we've reached the maximum number of rules
@@ -156,7 +171,14 @@ pub async fn ruleset_to_surge(
             break;
         }
 
-        let rule_group = &ruleset.group;
+        let Some(rule_group) = translate_policy(&ruleset.group, policy_target) else {
+            warn!(
+                "Skipping ruleset '{}': {:?} has no {} policy",
+                ruleset.rule_path, policy_target, ruleset.group
+            );
+            continue;
+        };
+        let rule_group = &rule_group;
         let rule_path = &ruleset.rule_path;
         let rule_path_typed = &ruleset.rule_path_typed;
 
@@ -166,6 +188,10 @@ pub async fn ruleset_to_surge(
             if str_line == "MATCH" {
                 str_line = "FINAL".to_string();
             }
+            if is_asn_rule(&str_line) && surge_ver <= 2 {
+                warn_dropped_asn_rules(rule_path, policy_target, 1);
+                continue;
+            }
 
             if surge_ver == -1 || surge_ver == -2 {
                 str_line = transform_rule_to_common(&str_line, rule_group, true);
@@ -239,14 +265,17 @@ pub async fn ruleset_to_surge(
                     let _ = base_rule.set("filter_remote", "{NONAME}", &str_line);
                     continue;
                 } else if surge_ver == -4 && !remote_path_prefix.is_empty() {
-                    let str_line = format!(
-                        "{}/getruleset?type=1&url={},{}",
+                    let url = format!(
+                        "{}/getruleset?type=1&url={}",
                         remote_path_prefix,
-                        url_safe_base64_encode(rule_path_typed),
-                        rule_group
+                        url_safe_base64_encode(rule_path_typed)
                     );
 
-                    let _ = base_rule.set("Remote Rule", "{NONAME}", &str_line);
+                    let _ = base_rule.set(
+                        "Remote Rule",
+                        "{NONAME}",
+                        &loon_remote_rule(&url, rule_group),
+                    );
                     continue;
                 }
             } else if is_link(rule_path) {
@@ -293,8 +322,24 @@ pub async fn ruleset_to_surge(
                     let _ = base_rule.set("filter_remote", "{NONAME}", &str_line);
                     continue;
                 } else if surge_ver == -4 {
-                    let str_line = format!("{},{}", rule_path, rule_group);
-                    let _ = base_rule.set("Remote Rule", "{NONAME}", &str_line);
+                    // Loon reads Surge rule lists, other types are converted
+                    // by the server when it is known
+                    let url = if ruleset.rule_type == crate::models::RulesetType::Surge
+                        || remote_path_prefix.is_empty()
+                    {
+                        rule_path.to_string()
+                    } else {
+                        format!(
+                            "{}/getruleset?type=1&url={}",
+                            remote_path_prefix,
+                            url_safe_base64_encode(rule_path_typed)
+                        )
+                    };
+                    let _ = base_rule.set(
+                        "Remote Rule",
+                        "{NONAME}",
+                        &loon_remote_rule(&url, rule_group),
+                    );
                     continue;
                 }
             } else if !is_local_rule_file {
@@ -312,7 +357,8 @@ pub async fn ruleset_to_surge(
             }
 
             // Convert the ruleset based on its type
-            let converted_rules = convert_ruleset(&retrieved_rules, ruleset.rule_type);
+            let converted_rules =
+                convert_ruleset_cached(&retrieved_rules, ruleset.rule_type, settings);
             // let line_break = if converted_rules.contains("\r\n") {
             //     '\r'
             // } else {
@@ -320,6 +366,7 @@ pub async fn ruleset_to_surge(
             // };
 
             // Process each rule line
+            let mut dropped_asn_rules = 0;
             for line in converted_rules.lines() {
                 // Check if we've reached the maximum number of rules
                 if settings.max_allowed_rules > 0 && total_rules >= settings.max_allowed_rules {
@@ -368,6 +415,9 @@ pub async fn ruleset_to_surge(
                 };
 
                 if !rule_supported {
+                    if is_asn_rule(&str_line) {
+                        dropped_asn_rules += 1;
+                    }
                     continue;
                 }
 
@@ -395,6 +445,7 @@ pub async fn ruleset_to_surge(
                 all_rules.push(str_line);
                 total_rules += 1;
             }
+            warn_dropped_asn_rules(rule_path, policy_target, dropped_asn_rules);
         }
     }
 
@@ -403,3 +454,49 @@ pub async fn ruleset_to_surge(
         let _ = base_rule.set_current("{NONAME}", &rule);
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_asn_rules_are_kept_for_surge_only() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("cloudflare.list");
+        let content = "IP-ASN,13335,no-resolve\nIP-CIDR,1.1.1.0/24,no-resolve\n";
+        std::fs::write(&path, content).unwrap();
+
+        let mut list = RulesetContent::new(&path.to_string_lossy(), "Proxy");
+        list.set_rule_content(content);
+        let mut inline = RulesetContent::new("", "DIRECT");
+        inline.set_rule_content("[]IP-ASN,4134");
+        let rulesets = vec![list, inline];
+
+        let rules = |surge_ver: i32, section: &'static str| {
+            let rulesets = rulesets.clone();
+            async move {
+                let mut ini = IniReader::new();
+                let settings = Settings::default();
+                ruleset_to_surge(&mut ini, &rulesets, surge_ver, false, "", &settings).await;
+                ini.get_all(section, "{NONAME}").unwrap_or_default()
+            }
+        };
+
+        assert_eq!(
+            rules(4, "Rule").await,
+            [
+                "IP-ASN,13335,Proxy,no-resolve",
+                "IP-CIDR,1.1.1.0/24,Proxy,no-resolve",
+                "IP-ASN,4134,DIRECT",
+            ]
+        );
+        assert_eq!(
+            rules(-1, "filter_local").await,
+            ["IP-CIDR,1.1.1.0/24,Proxy,no-resolve"]
+        );
+        assert_eq!(
+            rules(2, "Rule").await,
+            ["IP-CIDR,1.1.1.0/24,Proxy,no-resolve"]
+        );
+    }
+}
@@ -0,0 +1,423 @@
+//! Structural checks on generated configs, catching what a client would
+//! refuse to load: duplicate proxy names, group members or rule targets that
+//! do not exist, empty groups and invalid ports
+
+use std::collections::HashSet;
+
+use serde::Serialize;
+
+use crate::models::SubconverterTarget;
+
+/// Policies Clash knows without a proxy or group of that name
+const CLASH_BUILTIN_POLICIES: [&str; 5] = ["DIRECT", "REJECT", "REJECT-DROP", "PASS", "COMPATIBLE"];
+
+/// Policies Surge knows without a proxy or group of that name
+const SURGE_BUILTIN_POLICIES: [&str; 5] = [
+    "DIRECT",
+    "REJECT",
+    "REJECT-TINYGIF",
+    "REJECT-DROP",
+    "REJECT-NO-DROP",
+];
+
+/// Trailing rule options that come after the policy
+const RULE_OPTIONS: [&str; 5] = [
+    "no-resolve",
+    "src",
+    "dns-failed",
+    "extended-matching",
+    "pre-matching",
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    /// The client will refuse the config or misroute traffic
+    Error,
+    /// The config loads but part of it has no effect
+    Warning,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Finding {
+    pub severity: Severity,
+    pub message: String,
+}
+
+/// Findings of a config, serialized as the report `validate=true` answers with
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct ValidationReport {
+    pub findings: Vec<Finding>,
+}
+
+impl ValidationReport {
+    pub fn has_errors(&self) -> bool {
+        self.findings
+            .iter()
+            .any(|finding| finding.severity == Severity::Error)
+    }
+
+    fn error(&mut self, message: String) {
+        self.findings.push(Finding {
+            severity: Severity::Error,
+            message,
+        });
+    }
+
+    fn warning(&mut self, message: String) {
+        self.findings.push(Finding {
+            severity: Severity::Warning,
+            message,
+        });
+    }
+}
+
+/// Check a config generated for `target`. Targets producing plain node lists
+/// have nothing to cross-reference and always pass
+pub fn validate_config(target: &SubconverterTarget, content: &str) -> ValidationReport {
+    match target {
+        SubconverterTarget::Clash | SubconverterTarget::ClashR | SubconverterTarget::Auto => {
+            validate_clash(content)
+        }
+        SubconverterTarget::Surge(_) | SubconverterTarget::Surfboard => validate_surge(content),
+        SubconverterTarget::SingBox => validate_singbox(content),
+        _ => ValidationReport::default(),
+    }
+}
+
+fn check_port(report: &mut ValidationReport, name: &str, port: Option<i64>) {
+    match port {
+        Some(port) if (1..=65535).contains(&port) => {}
+        Some(port) => report.error(format!("proxy '{}' has invalid port {}", name, port)),
+        None => report.error(format!("proxy '{}' has no valid port", name)),
+    }
+}
+
+fn check_unique(report: &mut ValidationReport, seen: &mut HashSet<String>, kind: &str, name: &str) {
+    if !seen.insert(name.to_string()) {
+        report.error(format!("duplicate {} name '{}'", kind, name));
+    }
+}
+
+/// Policy of a rule line, the last field that is not an option
+fn rule_policy(rule: &str) -> Option<&str> {
+    rule.rsplit(',')
+        .map(str::trim)
+        .find(|field| !RULE_OPTIONS.contains(field))
+}
+
+/// Check rule targets, `known` holds every proxy, group and built-in policy
+fn check_rules<'a>(
+    report: &mut ValidationReport,
+    rules: impl Iterator<Item = &'a str>,
+    known: &HashSet<String>,
+) {
+    let mut final_seen = false;
+    for rule in rules {
+        let rule_type = rule.split(',').next().unwrap_or_default().trim();
+        if final_seen {
+            report.warning(format!("rule '{}' comes after the final rule", rule));
+        }
+        if rule_type == "MATCH" || rule_type == "FINAL" {
+            final_seen = true;
+        }
+        match rule_policy(rule) {
+            Some(policy) if known.contains(policy) => {}
+            Some(policy) => report.error(format!(
+                "rule '{}' targets unknown policy '{}'",
+                rule, policy
+            )),
+            None => report.error(format!("rule '{}' has no policy", rule)),
+        }
+    }
+}
+
+fn validate_clash(content: &str) -> ValidationReport {
+    let mut report = ValidationReport::default();
+    let yaml: serde_yaml::Value = match serde_yaml::from_str(content) {
+        Ok(yaml) => yaml,
+        Err(e) => {
+            report.error(format!("config is not valid YAML: {}", e));
+            return report;
+        }
+    };
+
+    let mut known: HashSet<String> = CLASH_BUILTIN_POLICIES.map(String::from).into();
+    let mut names = HashSet::new();
+    for proxy in yaml["proxies"].as_sequence().into_iter().flatten() {
+        let name = proxy["name"].as_str().unwrap_or_default();
+        check_unique(&mut report, &mut names, "proxy", name);
+        check_port(&mut report, name, proxy["port"].as_i64());
+        known.insert(name.to_string());
+    }
+
+    let groups = yaml["proxy-groups"]
+        .as_sequence()
+        .cloned()
+        .unwrap_or_default();
+    for group in &groups {
+        let name = group["name"].as_str().unwrap_or_default();
+        check_unique(&mut report, &mut names, "group", name);
+        known.insert(name.to_string());
+    }
+    for group in &groups {
+        let name = group["name"].as_str().unwrap_or_default();
+        let members = group["proxies"].as_sequence().cloned().unwrap_or_default();
+        let has_providers = group["use"]
+            .as_sequence()
+            .is_some_and(|providers| !providers.is_empty());
+        let includes_all = [
+            "include-all",
+            "include-all-proxies",
+            "include-all-providers",
+        ]
+        .iter()
+        .any(|key| group[*key].as_bool() == Some(true));
+        if members.is_empty() && !has_providers && !includes_all {
+            report.error(format!("group '{}' has no members", name));
+        }
+        for member in members.iter().filter_map(|member| member.as_str()) {
+            if !known.contains(member) {
+                report.error(format!(
+                    "group '{}' references unknown proxy or group '{}'",
+                    name, member
+                ));
+            }
+        }
+    }
+
+    let rules = yaml["rules"].as_sequence().cloned().unwrap_or_default();
+    check_rules(
+        &mut report,
+        rules.iter().filter_map(|rule| rule.as_str()),
+        &known,
+    );
+    report
+}
+
+/// Lines of an INI section, without blank lines and comments
+fn section_lines<'a>(content: &'a str, section: &str) -> Vec<&'a str> {
+    let header = format!("[{}]", section);
+    content
+        .lines()
+        .map(str::trim)
+        .skip_while(|line| !line.eq_ignore_ascii_case(&header))
+        .skip(1)
+        .take_while(|line| !line.starts_with('['))
+        .filter(|line| {
+            !line.is_empty()
+                && !line.starts_with('#')
+                && !line.starts_with(';')
+                && !line.starts_with("//")
+        })
+        .collect()
+}
+
+fn validate_surge(content: &str) -> ValidationReport {
+    let mut report = ValidationReport::default();
+    let mut known: HashSet<String> = SURGE_BUILTIN_POLICIES.map(String::from).into();
+    let mut names = HashSet::new();
+
+    for line in section_lines(content, "Proxy") {
+        let Some((name, value)) = line.split_once('=') else {
+            continue;
+        };
+        let name = name.trim();
+        check_unique(&mut report, &mut names, "proxy", name);
+        known.insert(name.to_string());
+        let fields: Vec<&str> = value.split(',').map(str::trim).collect();
+        if !matches!(fields[0], "direct" | "reject" | "reject-tinygif") {
+            check_port(
+                &mut report,
+                name,
+                fields.get(2).and_then(|port| port.parse().ok()),
+            );
+        }
+    }
+
+    let groups: Vec<(&str, Vec<&str>)> = section_lines(content, "Proxy Group")
+        .into_iter()
+        .filter_map(|line| line.split_once('='))
+        .map(|(name, value)| (name.trim(), value.split(',').map(str::trim).collect()))
+        .collect();
+    for (name, _) in &groups {
+        check_unique(&mut report, &mut names, "group", name);
+        known.insert(name.to_string());
+    }
+    for (name, fields) in &groups {
+        // The first field is the group type, options are key=value
+        let members: Vec<&str> = fields
+            .iter()
+            .skip(1)
+            .copied()
+            .filter(|field| !field.contains('='))
+            .collect();
+        let has_external = fields.iter().any(|field| {
+            field.starts_with("policy-path")
+                || field.starts_with("include-all-proxies")
+                || field.starts_with("include-other-group")
+        });
+        if members.is_empty() && !has_external {
+            report.error(format!("group '{}' has no members", name));
+        }
+        for member in members {
+            if !known.contains(member) {
+                report.error(format!(
+                    "group '{}' references unknown proxy or group '{}'",
+                    name, member
+                ));
+            }
+        }
+    }
+
+    check_rules(
+        &mut report,
+        section_lines(content, "Rule").into_iter(),
+        &known,
+    );
+    report
+}
+
+fn validate_singbox(content: &str) -> ValidationReport {
+    let mut report = ValidationReport::default();
+    let json: serde_json::Value = match serde_json::from_str(content) {
+        Ok(json) => json,
+        Err(e) => {
+            report.error(format!("config is not valid JSON: {}", e));
+            return report;
+        }
+    };
+
+    let outbounds = json["outbounds"].as_array().cloned().unwrap_or_default();
+    let mut known = HashSet::new();
+    for outbound in &outbounds {
+        let tag = outbound["tag"].as_str().unwrap_or_default();
+        check_unique(&mut report, &mut known, "outbound", tag);
+        if outbound.get("server").is_some() {
+            check_port(&mut report, tag, outbound["server_port"].as_i64());
+        }
+    }
+
+    for outbound in &outbounds {
+        if !matches!(outbound["type"].as_str(), Some("selector" | "urltest")) {
+            continue;
+        }
+        let tag = outbound["tag"].as_str().unwrap_or_default();
+        let members = outbound["outbounds"]
+            .as_array()
+            .cloned()
+            .unwrap_or_default();
+        if members.is_empty() {
+            report.error(format!("group '{}' has no members", tag));
+        }
+        for member in members.iter().filter_map(|member| member.as_str()) {
+            if !known.contains(member) {
+                report.error(format!(
+                    "group '{}' references unknown outbound '{}'",
+                    tag, member
+                ));
+            }
+        }
+    }
+
+    let route = &json["route"];
+    for rule in route["rules"].as_array().into_iter().flatten() {
+        if let Some(outbound) = rule["outbound"].as_str() {
+            if !known.contains(outbound) {
+                report.error(format!("rule targets unknown outbound '{}'", outbound));
+            }
+        }
+    }
+    if let Some(outbound) = route["final"].as_str() {
+        if !known.contains(outbound) {
+            report.error(format!("final targets unknown outbound '{}'", outbound));
+        }
+    }
+    report
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn messages(report: &ValidationReport) -> Vec<&str> {
+        report
+            .findings
+            .iter()
+            .map(|finding| finding.message.as_str())
+            .collect()
+    }
+
+    #[test]
+    fn test_clash_config_references_are_checked() {
+        let config = r#"
+proxies:
+  - {name: A, type: ss, server: 1.2.3.4, port: 8388}
+  - {name: A, type: ss, server: 1.2.3.5, port: 70000}
+proxy-groups:
+  - {name: Proxy, type: select, proxies: [A, Missing, DIRECT]}
+  - {name: Empty, type: select, proxies: []}
+  - {name: Providers, type: select, use: [provider_1]}
+rules:
+  - DOMAIN,example.com,Proxy
+  - IP-CIDR,10.0.0.0/8,Nowhere,no-resolve
+  - MATCH,DIRECT
+  - DOMAIN,late.example.com,DIRECT
+"#;
+        let report = validate_config(&SubconverterTarget::Clash, config);
+        assert_eq!(
+            messages(&report),
+            [
+                "duplicate proxy name 'A'",
+                "proxy 'A' has invalid port 70000",
+                "group 'Proxy' references unknown proxy or group 'Missing'",
+                "group 'Empty' has no members",
+                "rule 'IP-CIDR,10.0.0.0/8,Nowhere,no-resolve' targets unknown policy 'Nowhere'",
+                "rule 'DOMAIN,late.example.com,DIRECT' comes after the final rule",
+            ]
+        );
+        assert!(report.has_errors());
+        assert_eq!(report.findings[5].severity, Severity::Warning);
+    }
+
+    #[test]
+    fn test_surge_config_references_are_checked() {
+        let config = "[Proxy]\n\
+                      A = ss, 1.2.3.4, 8388, encrypt-method=aes-256-gcm, password=pass\n\
+                      [Proxy Group]\n\
+                      Proxy = select, A, DIRECT\n\
+                      Auto = url-test, B, url=http://www.gstatic.com/generate_204\n\
+                      [Rule]\n\
+                      # comment\n\
+                      DOMAIN,example.com,Proxy\n\
+                      FINAL,Proxy,dns-failed\n";
+        let report = validate_config(&SubconverterTarget::Surge(4), config);
+        assert_eq!(
+            messages(&report),
+            ["group 'Auto' references unknown proxy or group 'B'"]
+        );
+    }
+
+    #[test]
+    fn test_singbox_config_references_are_checked() {
+        let config = r#"{
+            "outbounds": [
+                {"type": "shadowsocks", "tag": "A", "server": "1.2.3.4", "server_port": 8388},
+                {"type": "selector", "tag": "Proxy", "outbounds": ["A", "B"]},
+                {"type": "direct", "tag": "DIRECT"}
+            ],
+            "route": {"rules": [{"domain": ["example.com"], "outbound": "Proxy"}], "final": "DIRECT"}
+        }"#;
+        let report = validate_config(&SubconverterTarget::SingBox, config);
+        assert_eq!(
+            messages(&report),
+            ["group 'Proxy' references unknown outbound 'B'"]
+        );
+    }
+
+    #[test]
+    fn test_node_lists_always_pass() {
+        let report = validate_config(&SubconverterTarget::SS, "not checked");
+        assert!(report.findings.is_empty());
+    }
+}
@@ -1,4 +1,6 @@
+use crate::models::proxy_node::combined::CombinedProxy;
 use crate::utils::is_empty_option_string;
+use crate::utils::tribool::OptionSetExt;
 use crate::{generator::yaml::clash::output_proxy_types::*, Proxy, ProxyType};
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
@@ -214,6 +216,39 @@ impl ClashProxyOutput {
     }
 }
 
+impl ClashProxyOutput {
+    /// Convert a node under a new name with the request's udp, tfo and scv
+    /// overrides applied, without copying the whole node first
+    ///
+    /// The output matches converting a copy of the node given the same remark
+    /// and [`Proxy::apply_overrides`], for every node the Clash exporter keeps.
+    pub fn from_node(
+        proxy: &Proxy,
+        remark: &str,
+        udp: Option<bool>,
+        tfo: Option<bool>,
+        scv: Option<bool>,
+    ) -> Self {
+        let mut output = ClashProxyOutput::from(proxy);
+        let common = output.common_mut();
+        common.name = remark.to_string();
+        common.tfo.set_if_some(tfo);
+        common.skip_cert_verify.set_if_some(scv);
+        match &mut output {
+            // VLESS nodes carrying their own settings take udp from them
+            ClashProxyOutput::VLess(_)
+                if matches!(proxy.combined_proxy, Some(CombinedProxy::Vless(_))) => {}
+            _ => output.common_mut().udp.set_if_some(udp),
+        }
+        match &mut output {
+            ClashProxyOutput::Hysteria(hysteria) => hysteria.fast_open.set_if_some(tfo),
+            ClashProxyOutput::Hysteria2(hysteria2) => hysteria2.fast_open.set_if_some(tfo),
+            _ => {}
+        }
+        output
+    }
+}
+
 /// Trait for common operations on all ClashProxy variants
 pub trait ClashProxyCommon {
     /// Get a reference to the common options
@@ -393,6 +428,13 @@ impl Default for ClashYamlOutput {
 /// Implementation of From trait for ClashProxyOutput
 impl From<Proxy> for ClashProxyOutput {
     fn from(proxy: Proxy) -> Self {
+        ClashProxyOutput::from(&proxy)
+    }
+}
+
+/// Converts a borrowed node, only the fields written out are copied
+impl From<&Proxy> for ClashProxyOutput {
+    fn from(proxy: &Proxy) -> Self {
         match proxy.proxy_type {
             ProxyType::Shadowsocks => ClashProxyOutput::Shadowsocks(ShadowsocksProxy::from(proxy)),
             ProxyType::ShadowsocksR => {
@@ -57,8 +57,8 @@ impl ClashOutputAnyTLS {
     }
 }
 
-impl From<Proxy> for ClashOutputAnyTLS {
-    fn from(proxy: Proxy) -> Self {
+impl From<&Proxy> for ClashOutputAnyTLS {
+    fn from(proxy: &Proxy) -> Self {
         let common =
             CommonProxyOptions::builder(proxy.remark.clone(), proxy.hostname.clone(), proxy.port)
                 .udp(proxy.udp)
@@ -81,11 +81,7 @@ impl From<Proxy> for ClashOutputAnyTLS {
                 anytls.idle_session_timeout = anytls_proxy.idle_session_timeout;
                 anytls.min_idle_session = anytls_proxy.min_idle_session;
                 // 处理 ALPN
-                if let Some(alpn) = &anytls_proxy.alpn {
-                    if !alpn.is_empty() {
-                        anytls.alpn = Some(alpn.iter().cloned().collect());
-                    }
-                }
+                anytls.alpn = anytls_proxy.alpn.clone();
             }
         }
         anytls
@@ -26,8 +26,8 @@ impl HttpProxy {
     }
 }
 
-impl From<Proxy> for HttpProxy {
-    fn from(proxy: Proxy) -> Self {
+impl From<&Proxy> for HttpProxy {
+    fn from(proxy: &Proxy) -> Self {
         let common =
             CommonProxyOptions::builder(proxy.remark.clone(), proxy.hostname.clone(), proxy.port)
                 .udp(proxy.udp)
@@ -38,8 +38,8 @@ impl From<Proxy> for HttpProxy {
 
         let mut http = HttpProxy::new(common);
 
-        http.username = proxy.username;
-        http.password = proxy.password;
+        http.username = proxy.username.clone();
+        http.password = proxy.password.clone();
 
         http
     }
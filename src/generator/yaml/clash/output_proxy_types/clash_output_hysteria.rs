@@ -86,8 +86,8 @@ impl HysteriaProxy {
     }
 }
 
-impl From<Proxy> for HysteriaProxy {
-    fn from(proxy: Proxy) -> Self {
+impl From<&Proxy> for HysteriaProxy {
+    fn from(proxy: &Proxy) -> Self {
         let common =
             CommonProxyOptions::builder(proxy.remark.clone(), proxy.hostname.clone(), proxy.port)
                 .udp(proxy.udp)
@@ -98,8 +98,8 @@ impl From<Proxy> for HysteriaProxy {
 
         let mut hysteria = HysteriaProxy::new(common);
 
-        hysteria.ports = proxy.ports;
-        hysteria.protocol = proxy.protocol;
+        hysteria.ports = proxy.ports.clone();
+        hysteria.protocol = proxy.protocol.clone();
         hysteria.obfs_protocol = proxy.obfs.clone();
 
         if proxy.up_speed > 0 {
@@ -112,18 +112,16 @@ impl From<Proxy> for HysteriaProxy {
             hysteria.down_speed = Some(proxy.down_speed);
         }
 
-        hysteria.auth = proxy.auth;
-        hysteria.auth_str = proxy.auth_str;
+        hysteria.auth = proxy.auth.clone();
+        hysteria.auth_str = proxy.auth_str.clone();
 
         hysteria.obfs = proxy.obfs.clone();
-        hysteria.fingerprint = proxy.fingerprint;
+        hysteria.fingerprint = proxy.fingerprint.clone();
 
-        if !proxy.alpn.is_empty() {
-            hysteria.alpn = Some(proxy.alpn.into_iter().collect());
-        }
+        hysteria.alpn = proxy.alpn.clone();
 
-        hysteria.ca = proxy.ca;
-        hysteria.ca_str = proxy.ca_str;
+        hysteria.ca = proxy.ca.clone();
+        hysteria.ca_str = proxy.ca_str.clone();
 
         if proxy.recv_window_conn > 0 {
             hysteria.recv_window_conn = Some(proxy.recv_window_conn);
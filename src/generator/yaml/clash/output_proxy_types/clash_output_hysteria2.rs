@@ -77,8 +77,8 @@ impl Hysteria2Proxy {
     }
 }
 
-impl From<Proxy> for Hysteria2Proxy {
-    fn from(proxy: Proxy) -> Self {
+impl From<&Proxy> for Hysteria2Proxy {
+    fn from(proxy: &Proxy) -> Self {
         let common =
             CommonProxyOptions::builder(proxy.remark.clone(), proxy.hostname.clone(), proxy.port)
                 .udp(proxy.udp)
@@ -93,10 +93,10 @@ impl From<Proxy> for Hysteria2Proxy {
             hysteria2.ca_str = Some(ca_str.to_owned());
         }
 
-        hysteria2.password = proxy.password;
-        hysteria2.obfs = proxy.obfs;
-        hysteria2.obfs_password = proxy.obfs_param;
-        hysteria2.ports = proxy.ports;
+        hysteria2.password = proxy.password.clone();
+        hysteria2.obfs = proxy.obfs.clone();
+        hysteria2.obfs_password = proxy.obfs_param.clone();
+        hysteria2.ports = proxy.ports.clone();
 
         if proxy.up_speed > 0 {
             hysteria2.up = Some(format!("{}Mbps", proxy.up_speed));
@@ -106,14 +106,12 @@ impl From<Proxy> for Hysteria2Proxy {
             hysteria2.down = Some(format!("{}Mbps", proxy.down_speed));
         }
 
-        hysteria2.fingerprint = proxy.fingerprint;
+        hysteria2.fingerprint = proxy.fingerprint.clone();
 
-        if !proxy.alpn.is_empty() {
-            hysteria2.alpn = Some(proxy.alpn.into_iter().collect());
-        }
+        hysteria2.alpn = proxy.alpn.clone();
 
-        hysteria2.ca = proxy.ca;
-        hysteria2.ca_str = proxy.ca_str;
+        hysteria2.ca = proxy.ca.clone();
+        hysteria2.ca_str = proxy.ca_str.clone();
 
         if proxy.cwnd > 0 {
             hysteria2.cwnd = Some(proxy.cwnd);
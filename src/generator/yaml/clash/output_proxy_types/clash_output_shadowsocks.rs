@@ -56,8 +56,8 @@ impl ShadowsocksProxy {
     }
 }
 
-impl From<Proxy> for ShadowsocksProxy {
-    fn from(proxy: Proxy) -> Self {
+impl From<&Proxy> for ShadowsocksProxy {
+    fn from(proxy: &Proxy) -> Self {
         let common =
             CommonProxyOptions::builder(proxy.remark.clone(), proxy.hostname.clone(), proxy.port)
                 .udp(proxy.udp)
@@ -68,11 +68,11 @@ impl From<Proxy> for ShadowsocksProxy {
 
         let mut ss = ShadowsocksProxy::new(common);
 
-        ss.cipher = proxy.encrypt_method;
-        ss.password = proxy.password;
-        ss.plugin = proxy.plugin;
+        ss.cipher = proxy.encrypt_method.clone();
+        ss.password = proxy.password.clone();
+        ss.plugin = proxy.plugin.clone();
 
-        if let Some(plugin_opts) = proxy.plugin_option {
+        if let Some(plugin_opts) = &proxy.plugin_option {
             let mut opts = HashMap::new();
 
             for opt in plugin_opts.split(';') {
@@ -38,8 +38,8 @@ impl ShadowsocksRProxy {
     }
 }
 
-impl From<Proxy> for ShadowsocksRProxy {
-    fn from(proxy: Proxy) -> Self {
+impl From<&Proxy> for ShadowsocksRProxy {
+    fn from(proxy: &Proxy) -> Self {
         let common =
             CommonProxyOptions::builder(proxy.remark.clone(), proxy.hostname.clone(), proxy.port)
                 .udp(proxy.udp)
@@ -50,12 +50,12 @@ impl From<Proxy> for ShadowsocksRProxy {
 
         let mut ssr = ShadowsocksRProxy::new(common);
 
-        ssr.cipher = proxy.encrypt_method;
-        ssr.password = proxy.password;
-        ssr.protocol = proxy.protocol;
-        ssr.protocol_param = proxy.protocol_param;
-        ssr.obfs = proxy.obfs;
-        ssr.obfs_param = proxy.obfs_param;
+        ssr.cipher = proxy.encrypt_method.clone();
+        ssr.password = proxy.password.clone();
+        ssr.protocol = proxy.protocol.clone();
+        ssr.protocol_param = proxy.protocol_param.clone();
+        ssr.obfs = proxy.obfs.clone();
+        ssr.obfs_param = proxy.obfs_param.clone();
 
         ssr
     }
@@ -33,8 +33,8 @@ impl SnellProxy {
     }
 }
 
-impl From<Proxy> for SnellProxy {
-    fn from(proxy: Proxy) -> Self {
+impl From<&Proxy> for SnellProxy {
+    fn from(proxy: &Proxy) -> Self {
         let common =
             CommonProxyOptions::builder(proxy.remark.clone(), proxy.hostname.clone(), proxy.port)
                 .udp(proxy.udp)
@@ -45,11 +45,11 @@ impl From<Proxy> for SnellProxy {
 
         let mut snell = SnellProxy::new(common);
 
-        snell.psk = proxy.password;
+        snell.psk = proxy.password.clone();
         snell.version = Some(proxy.snell_version as u32);
-        snell.obfs = proxy.obfs;
+        snell.obfs = proxy.obfs.clone();
 
-        if let Some(obfs_opts) = proxy.obfs_param {
+        if let Some(obfs_opts) = &proxy.obfs_param {
             let mut opts = HashMap::new();
 
             for opt in obfs_opts.split(';') {
@@ -26,8 +26,8 @@ impl Socks5Proxy {
     }
 }
 
-impl From<Proxy> for Socks5Proxy {
-    fn from(proxy: Proxy) -> Self {
+impl From<&Proxy> for Socks5Proxy {
+    fn from(proxy: &Proxy) -> Self {
         let common =
             CommonProxyOptions::builder(proxy.remark.clone(), proxy.hostname.clone(), proxy.port)
                 .udp(proxy.udp)
@@ -38,8 +38,8 @@ impl From<Proxy> for Socks5Proxy {
 
         let mut socks5 = Socks5Proxy::new(common);
 
-        socks5.username = proxy.username;
-        socks5.password = proxy.password;
+        socks5.username = proxy.username.clone();
+        socks5.password = proxy.password.clone();
 
         socks5
     }
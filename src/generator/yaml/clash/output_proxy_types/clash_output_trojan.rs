@@ -12,6 +12,8 @@ pub struct TrojanProxy {
     pub common: CommonProxyOptions,
     #[serde(skip_serializing_if = "is_empty_option_string")]
     pub password: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub alpn: Option<Vec<String>>,
     #[serde(skip_serializing_if = "is_empty_option_string")]
     pub network: Option<String>,
     #[serde(skip_serializing_if = "Option::is_none")]
@@ -32,7 +34,10 @@ pub struct WsOptions {
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(rename_all = "kebab-case")]
 pub struct GrpcOptions {
-    #[serde(skip_serializing_if = "is_empty_option_string")]
+    #[serde(
+        rename = "grpc-service-name",
+        skip_serializing_if = "is_empty_option_string"
+    )]
     pub service_name: Option<String>,
 }
 
@@ -42,6 +47,7 @@ impl TrojanProxy {
         Self {
             common,
             password: None,
+            alpn: None,
             network: None,
             ws_opts: None,
             grpc_opts: None,
@@ -49,19 +55,21 @@ impl TrojanProxy {
     }
 }
 
-impl From<Proxy> for TrojanProxy {
-    fn from(proxy: Proxy) -> Self {
+impl From<&Proxy> for TrojanProxy {
+    fn from(proxy: &Proxy) -> Self {
         let common =
             CommonProxyOptions::builder(proxy.remark.clone(), proxy.hostname.clone(), proxy.port)
                 .udp(proxy.udp)
                 .tfo(proxy.tcp_fast_open)
                 .skip_cert_verify(proxy.allow_insecure)
                 .sni(proxy.sni.clone())
+                .client_fingerprint(proxy.fingerprint.clone())
                 .build();
 
         let mut trojan = TrojanProxy::new(common);
 
-        trojan.password = proxy.password;
+        trojan.password = proxy.password.clone();
+        trojan.alpn = proxy.alpn.clone();
         trojan.network = proxy.transfer_protocol.clone();
 
         if let Some(network) = &proxy.transfer_protocol {
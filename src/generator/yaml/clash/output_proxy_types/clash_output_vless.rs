@@ -144,8 +144,8 @@ impl VLessProxy {
     }
 }
 
-impl From<Proxy> for VLessProxy {
-    fn from(proxy: Proxy) -> Self {
+impl From<&Proxy> for VLessProxy {
+    fn from(proxy: &Proxy) -> Self {
         let common =
             CommonProxyOptions::builder(proxy.remark.clone(), proxy.hostname.clone(), proxy.port)
                 .udp(proxy.udp)
@@ -174,9 +174,7 @@ impl From<Proxy> for VLessProxy {
                 vless.client_fingerprint = vless_proxy.client_fingerprint.clone();
 
                 // 处理 ALPN
-                if !vless_proxy.alpn.is_empty() {
-                    vless.alpn = Some(vless_proxy.alpn.iter().cloned().collect());
-                }
+                vless.alpn = vless_proxy.alpn.clone();
 
                 // 处理 Reality 配置
                 if let (Some(public_key), Some(short_id)) = (
@@ -18,6 +18,8 @@ pub struct VmessProxy {
     #[serde(skip_serializing_if = "is_empty_option_string")]
     pub cipher: Option<String>,
     #[serde(skip_serializing_if = "is_empty_option_string")]
+    pub servername: Option<String>,
+    #[serde(skip_serializing_if = "is_empty_option_string")]
     pub network: Option<String>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub ws_opts: Option<VmessWsOptions>,
@@ -59,7 +61,10 @@ pub struct VmessH2Options {
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(rename_all = "kebab-case")]
 pub struct VmessGrpcOptions {
-    #[serde(skip_serializing_if = "is_empty_option_string")]
+    #[serde(
+        rename = "grpc-service-name",
+        skip_serializing_if = "is_empty_option_string"
+    )]
     pub service_name: Option<String>,
 }
 
@@ -71,6 +76,7 @@ impl VmessProxy {
             uuid: None,
             alter_id: 0,
             cipher: None,
+            servername: None,
             network: None,
             ws_opts: None,
             http_opts: None,
@@ -80,14 +86,15 @@ impl VmessProxy {
     }
 }
 
-impl From<Proxy> for VmessProxy {
-    fn from(proxy: Proxy) -> Self {
+impl From<&Proxy> for VmessProxy {
+    fn from(proxy: &Proxy) -> Self {
         let common =
             CommonProxyOptions::builder(proxy.remark.clone(), proxy.hostname.clone(), proxy.port)
                 .udp(proxy.udp)
                 .tfo(proxy.tcp_fast_open)
                 .skip_cert_verify(proxy.allow_insecure)
-                .sni(proxy.sni.clone())
+                .tls(proxy.tls_secure.then_some(true))
+                .client_fingerprint(proxy.fingerprint.clone())
                 .build();
 
         let mut vmess = VmessProxy::new(common);
@@ -95,6 +102,8 @@ impl From<Proxy> for VmessProxy {
         vmess.uuid = proxy.user_id.clone();
         vmess.alter_id = proxy.alter_id as u32;
         vmess.cipher = proxy.encrypt_method.clone();
+        // VMess names the TLS server `servername` rather than `sni`
+        vmess.servername = proxy.sni.clone();
         vmess.network = proxy.transfer_protocol.clone();
 
         if let Some(network) = &proxy.transfer_protocol {
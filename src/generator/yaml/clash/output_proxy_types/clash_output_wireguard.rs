@@ -47,8 +47,8 @@ impl WireGuardProxy {
     }
 }
 
-impl From<Proxy> for WireGuardProxy {
-    fn from(proxy: Proxy) -> Self {
+impl From<&Proxy> for WireGuardProxy {
+    fn from(proxy: &Proxy) -> Self {
         let common =
             CommonProxyOptions::builder(proxy.remark.clone(), proxy.hostname.clone(), proxy.port)
                 .udp(proxy.udp)
@@ -1,2 +1,3 @@
 pub mod clash;
 pub mod proxy_group_output;
+pub mod style;
@@ -145,7 +145,7 @@ pub struct ClashProxyGroup {
     #[serde(skip_serializing_if = "is_zero_u32")]
     pub interval: u32,
 
-    /// Timeout in seconds for tests
+    /// Timeout in milliseconds for tests
     #[serde(skip_serializing_if = "is_zero_u32")]
     pub timeout: u32,
 
@@ -158,8 +158,8 @@ pub struct ClashProxyGroup {
     pub strategy: String,
 
     /// Whether to use lazy loading
-    #[serde(skip_serializing_if = "is_false")]
-    pub lazy: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub lazy: Option<bool>,
 
     /// Whether to disable UDP support
     #[serde(rename = "disable-udp", skip_serializing_if = "is_false")]
@@ -172,6 +172,30 @@ pub struct ClashProxyGroup {
     /// Whether to evaluate before use
     #[serde(rename = "evaluate-before-use", skip_serializing_if = "is_false")]
     pub evaluate_before_use: bool,
+
+    /// Icon URL for Stash and Clash.Meta dashboards
+    #[serde(skip_serializing_if = "String::is_empty")]
+    pub icon: String,
+
+    /// Whether to hide the group from dashboards
+    #[serde(skip_serializing_if = "is_false")]
+    pub hidden: bool,
+
+    /// Whether the group takes every proxy without listing them
+    #[serde(rename = "include-all", skip_serializing_if = "is_false")]
+    pub include_all: bool,
+
+    /// Regexes Clash.Meta matches proxies against, separated by backticks
+    #[serde(skip_serializing_if = "String::is_empty")]
+    pub filter: String,
+
+    /// Regex of proxies Clash.Meta leaves out
+    #[serde(rename = "exclude-filter", skip_serializing_if = "String::is_empty")]
+    pub exclude_filter: String,
+
+    /// Proxy types Clash.Meta leaves out, separated by `|`
+    #[serde(rename = "exclude-type", skip_serializing_if = "String::is_empty")]
+    pub exclude_type: String,
 }
 
 // Helper functions for serde skip conditions
@@ -207,10 +231,16 @@ impl From<&ProxyGroupConfig> for ClashProxyGroup {
             timeout: 0,
             tolerance: 0,
             strategy: String::new(),
-            lazy: false,
+            lazy: None,
             disable_udp: config.disable_udp,
             persistent: config.persistent,
             evaluate_before_use: config.evaluate_before_use,
+            icon: config.icon.clone(),
+            hidden: config.hidden,
+            include_all: config.include_all,
+            filter: String::new(),
+            exclude_filter: String::new(),
+            exclude_type: String::new(),
         };
 
         // Add type-specific fields
@@ -220,11 +250,13 @@ impl From<&ProxyGroupConfig> for ClashProxyGroup {
                 clash_group.lazy = config.lazy;
                 clash_group.url = config.url.clone();
                 clash_group.interval = config.interval;
+                clash_group.timeout = config.timeout * 1000;
                 clash_group.tolerance = config.tolerance;
             }
             ProxyGroupType::URLTest | ProxyGroupType::Smart | ProxyGroupType::Fallback => {
                 clash_group.url = config.url.clone();
                 clash_group.interval = config.interval;
+                clash_group.timeout = config.timeout * 1000;
                 clash_group.tolerance = config.tolerance;
 
                 // Only URLTest and Smart use lazy loading
@@ -239,7 +271,10 @@ impl From<&ProxyGroupConfig> for ClashProxyGroup {
         }
 
         // If proxies list is empty and no providers, add DIRECT
-        if clash_group.proxies.is_empty() && clash_group.using_provider.is_empty() {
+        if clash_group.proxies.is_empty()
+            && clash_group.using_provider.is_empty()
+            && !clash_group.include_all
+        {
             clash_group.proxies = vec!["DIRECT".to_string()];
         }
 
@@ -263,7 +298,10 @@ pub fn convert_proxy_groups(
                 clash_group.proxies = filtered_nodes.clone();
 
                 // If proxies list is empty and no providers, add DIRECT
-                if clash_group.proxies.is_empty() && clash_group.using_provider.is_empty() {
+                if clash_group.proxies.is_empty()
+                    && clash_group.using_provider.is_empty()
+                    && !clash_group.include_all
+                {
                     clash_group.proxies = vec!["DIRECT".to_string()];
                 }
             }
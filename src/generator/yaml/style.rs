@@ -0,0 +1,201 @@
+use serde_yaml::{Mapping, Value as YamlValue};
+
+/// Serialize a Clash config, writing the `proxies` and `proxy-groups` sequences in the
+/// requested style while leaving the rest of the document to serde_yaml.
+///
+/// Supported styles:
+/// - `block`: one key per line, as serde_yaml writes it
+/// - `flow`: one `- {name: x, type: ss, ...}` line per item
+/// - `compact`: the whole sequence on one line, `[{...}, {...}]`
+///
+pub fn dump_clash_yaml(
+    node: &YamlValue,
+    proxies_style: &str,
+    groups_style: &str,
+) -> Result<String, serde_yaml::Error> {
+    if is_block(proxies_style) && is_block(groups_style) {
+        return serde_yaml::to_string(node);
+    }
+    dump_clash_config(node, proxies_style, groups_style, None).map(|(output, _)| output)
+}
+
+/// Same as [`dump_clash_yaml`], but writes the generated `rules` text in place of the
+/// `rules_key` entry of the base config, or at the end when the base has none.
+///
+/// Top level keys keep the order they have in `node`.
+pub fn dump_clash_yaml_with_rules(
+    node: &YamlValue,
+    proxies_style: &str,
+    groups_style: &str,
+    rules_key: &str,
+    rules: &str,
+) -> Result<String, serde_yaml::Error> {
+    let (mut output, after) = dump_clash_yaml_split(node, proxies_style, groups_style, rules_key)?;
+    output.push_str(rules);
+    output.push_str(&after);
+    Ok(output)
+}
+
+/// Serialize a Clash config without its `rules_key` entry, split where the
+/// generated rules go, so they can be written between the two parts
+pub fn dump_clash_yaml_split(
+    node: &YamlValue,
+    proxies_style: &str,
+    groups_style: &str,
+    rules_key: &str,
+) -> Result<(String, String), serde_yaml::Error> {
+    dump_clash_config(node, proxies_style, groups_style, Some(rules_key))
+}
+
+/// Writes the config, switching to the second part of the output once the
+/// `rules_key` entry is reached
+fn dump_clash_config(
+    node: &YamlValue,
+    proxies_style: &str,
+    groups_style: &str,
+    rules_key: Option<&str>,
+) -> Result<(String, String), serde_yaml::Error> {
+    let map = match node.as_mapping() {
+        Some(map) => map,
+        None => return Ok((serde_yaml::to_string(node)?, String::new())),
+    };
+
+    let mut before = String::new();
+    let mut after = None;
+    for (key, value) in map {
+        if rules_key.is_some() && key.as_str() == rules_key {
+            after.get_or_insert_with(String::new);
+            continue;
+        }
+        let output = after.as_mut().unwrap_or(&mut before);
+        let style = match key.as_str() {
+            Some("proxies") => proxies_style,
+            Some("proxy-groups") => groups_style,
+            _ => "block",
+        };
+        match value.as_sequence() {
+            Some(items) if !is_block(style) => {
+                output.push_str(&flow_value(key));
+                output.push(':');
+                if items.is_empty() {
+                    output.push_str(" []\n");
+                } else if style == "compact" {
+                    let items: Vec<String> = items.iter().map(flow_value).collect();
+                    output.push_str(&format!(" [{}]\n", items.join(", ")));
+                } else {
+                    output.push('\n');
+                    for item in items {
+                        output.push_str(&format!("  - {}\n", flow_value(item)));
+                    }
+                }
+            }
+            _ => {
+                let mut entry = Mapping::new();
+                entry.insert(key.clone(), value.clone());
+                output.push_str(&serde_yaml::to_string(&entry)?);
+            }
+        }
+    }
+    Ok((before, after.unwrap_or_default()))
+}
+
+fn is_block(style: &str) -> bool {
+    style != "flow" && style != "compact"
+}
+
+/// Write a value as a single-line YAML flow node
+fn flow_value(value: &YamlValue) -> String {
+    match value {
+        YamlValue::Null => "null".to_string(),
+        YamlValue::Bool(b) => b.to_string(),
+        YamlValue::Number(n) => n.to_string(),
+        YamlValue::String(s) => flow_string(s),
+        YamlValue::Sequence(seq) => {
+            let items: Vec<String> = seq.iter().map(flow_value).collect();
+            format!("[{}]", items.join(", "))
+        }
+        YamlValue::Mapping(map) => {
+            let items: Vec<String> = map
+                .iter()
+                .map(|(k, v)| format!("{}: {}", flow_value(k), flow_value(v)))
+                .collect();
+            format!("{{{}}}", items.join(", "))
+        }
+        YamlValue::Tagged(tagged) => format!("{} {}", tagged.tag, flow_value(&tagged.value)),
+    }
+}
+
+/// Write a string scalar, quoting it whenever a plain scalar would be misread in flow context
+fn flow_string(s: &str) -> String {
+    let plain = serde_yaml::to_string(s).unwrap_or_default();
+    let plain = plain.trim_end_matches('\n');
+    if plain.is_empty() || plain.contains(['\n', ',', '[', ']', '{', '}']) {
+        // JSON strings are valid double-quoted YAML scalars
+        serde_json::to_string(s).unwrap_or_default()
+    } else {
+        plain.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FIXTURE: &str = r#"
+port: 7890
+proxies:
+  - name: "HK, 01"
+    type: vmess
+    server: example.com
+    port: 443
+    alpn: [h2, http/1.1]
+    ws-opts:
+      path: /ws?ed=2048
+      headers:
+        Host: "[cdn].example.com"
+  - name: JP 01
+    type: ss
+    server: 1.2.3.4
+    port: 8388
+    udp: true
+    password: "multi\nline"
+proxy-groups:
+  - name: Proxy
+    type: select
+    proxies: ["HK, 01", JP 01, DIRECT]
+rules:
+  - MATCH,Proxy
+"#;
+
+    fn fixture() -> YamlValue {
+        serde_yaml::from_str(FIXTURE).unwrap()
+    }
+
+    #[test]
+    fn test_flow_style_writes_one_line_per_item() {
+        let node = fixture();
+        let output = dump_clash_yaml(&node, "flow", "flow").unwrap();
+
+        assert!(output.contains("\n  - {name: \"HK, 01\", type: vmess"));
+        assert!(output.contains("\n  - {name: Proxy, type: select"));
+        assert!(output.contains("\nrules:\n- MATCH,Proxy\n"));
+        assert_eq!(output.lines().filter(|l| l.starts_with("  - {")).count(), 3);
+        assert_eq!(serde_yaml::from_str::<YamlValue>(&output).unwrap(), node);
+    }
+
+    #[test]
+    fn test_compact_and_block_styles_parse_identically() {
+        let node = fixture();
+        let block = dump_clash_yaml(&node, "block", "block").unwrap();
+        assert_eq!(block, serde_yaml::to_string(&node).unwrap());
+
+        let compact = dump_clash_yaml(&node, "compact", "block").unwrap();
+        assert!(compact.lines().any(|l| l.starts_with("proxies: [{name: ")));
+        assert!(compact.contains("\nproxy-groups:\n- name: Proxy\n"));
+        assert_eq!(serde_yaml::from_str::<YamlValue>(&compact).unwrap(), node);
+        assert_eq!(
+            serde_yaml::from_str::<YamlValue>(&compact).unwrap(),
+            serde_yaml::from_str::<YamlValue>(&block).unwrap()
+        );
+    }
+}
@@ -0,0 +1,746 @@
+//! Conversion for Rust programs embedding subconverter, what `/sub` does
+//! without the HTTP request around it
+//!
+//! ```no_run
+//! # async fn run() -> Result<(), libsubconverter::SubconverterError> {
+//! use libsubconverter::{Subconverter, SubconverterTarget};
+//!
+//! let mut converter = Subconverter::new(SubconverterTarget::Clash);
+//! converter
+//!     .base("base/all_base.tpl")
+//!     .subscription("ss://YWVzLTI1Ni1nY206cGFzcw@1.2.3.4:8388#Example");
+//! let config = converter.convert().await?;
+//! println!("{}", config.body);
+//! # Ok(())
+//! # }
+//! ```
+
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use crate::interfaces::subconverter::{subconverter, SubconverterConfigBuilder, SubconverterError};
+use crate::models::ruleset::RulesetConfigs;
+use crate::models::{Proxy, ProxyGroupConfigs, SubconverterTarget};
+use crate::Settings;
+
+/// A generated config
+#[derive(Debug, Clone)]
+pub struct ConvertedConfig {
+    pub body: String,
+    /// MIME type `/sub` serves the config with
+    pub content_type: String,
+    /// Headers `/sub` adds, e.g. `Subscription-UserInfo`
+    pub headers: HashMap<String, String>,
+}
+
+/// Builder for a single conversion
+///
+/// Callers are trusted like the command line, so local files and scripts are
+/// allowed. Call `options().authorized(false)` when the input comes from
+/// someone else.
+pub struct Subconverter {
+    target: SubconverterTarget,
+    builder: SubconverterConfigBuilder,
+}
+
+impl Subconverter {
+    pub fn new(target: SubconverterTarget) -> Self {
+        let mut builder = SubconverterConfigBuilder::new();
+        builder.target(target.clone()).authorized(true);
+        Self { target, builder }
+    }
+
+    /// Base template of the target, a file path or URL
+    pub fn base(&mut self, base: &str) -> &mut Self {
+        match self.target {
+            SubconverterTarget::Surge(_) => self.builder.surge_rule_base(base),
+            SubconverterTarget::Surfboard => self.builder.surfboard_rule_base(base),
+            SubconverterTarget::Mellow => self.builder.mellow_rule_base(base),
+            SubconverterTarget::Quantumult => self.builder.quan_rule_base(base),
+            SubconverterTarget::QuantumultX => self.builder.quanx_rule_base(base),
+            SubconverterTarget::Loon => self.builder.loon_rule_base(base),
+            SubconverterTarget::SSSub => self.builder.sssub_rule_base(base),
+            SubconverterTarget::SingBox => self.builder.singbox_rule_base(base),
+            _ => self.builder.clash_rule_base(base),
+        };
+        self
+    }
+
+    /// Nodes that are already parsed
+    pub fn nodes(&mut self, nodes: Vec<Proxy>) -> &mut Self {
+        self.builder.nodes(nodes);
+        self
+    }
+
+    /// Raw subscription content, e.g. a base64 link list or a Clash config
+    pub fn subscription(&mut self, content: &str) -> &mut Self {
+        self.builder.add_content(content);
+        self
+    }
+
+    /// Subscription URL, node link or local file to fetch nodes from
+    pub fn url(&mut self, url: &str) -> &mut Self {
+        self.builder.add_url(url);
+        self
+    }
+
+    pub fn rulesets(&mut self, rulesets: RulesetConfigs) -> &mut Self {
+        self.builder.ruleset_configs(rulesets);
+        self
+    }
+
+    pub fn groups(&mut self, groups: ProxyGroupConfigs) -> &mut Self {
+        self.builder.proxy_groups(groups);
+        self
+    }
+
+    /// Settings snapshot to convert with instead of the global settings
+    pub fn settings(&mut self, settings: Arc<Settings>) -> &mut Self {
+        self.builder.settings(settings);
+        self
+    }
+
+    /// Every other option `/sub` understands
+    pub fn options(&mut self) -> &mut SubconverterConfigBuilder {
+        &mut self.builder
+    }
+
+    /// Run the conversion, a config produced in chunks is collected into the
+    /// body
+    pub async fn convert(self) -> Result<ConvertedConfig, SubconverterError> {
+        let config = self
+            .builder
+            .build()
+            .map_err(|e| SubconverterError::InvalidInput(format!("Configuration error: {}", e)))?;
+        let result = subconverter(config).await?;
+        let body = match result.stream {
+            Some(stream) => result.content + stream.into_string().as_str(),
+            None => result.content,
+        };
+        Ok(ConvertedConfig {
+            body,
+            content_type: self.target.content_type().to_string(),
+            headers: result.headers,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::interfaces::subconverter::{UPLOAD_ERROR_HEADER, UPLOAD_URL_HEADER};
+    use crate::models::ruleset::RulesetConfig;
+    use crate::models::{ProxyGroupConfig, ProxyGroupType};
+
+    #[tokio::test]
+    async fn test_convert_subscription_content_with_own_settings() {
+        let mut group = ProxyGroupConfig::new("Proxy".to_string(), ProxyGroupType::Select);
+        group.proxies = vec![".*".to_string()];
+        let settings = Arc::new(Settings {
+            enable_rule_gen: false,
+            ..Settings::default()
+        });
+
+        let mut converter = Subconverter::new(SubconverterTarget::Clash);
+        converter
+            .settings(settings)
+            .subscription(
+                "ss://YWVzLTI1Ni1nY206cGFzcw@1.2.3.4:8388#One\n\
+                 trojan://secret@2.3.4.5:443#Two",
+            )
+            .groups(vec![group]);
+        converter.options().enable_rule_generator(false);
+        let config = converter.convert().await.unwrap();
+
+        assert_eq!(config.content_type, "application/yaml");
+        let yaml: serde_yaml::Value = serde_yaml::from_str(&config.body).unwrap();
+        let names: Vec<&str> = yaml["proxies"]
+            .as_sequence()
+            .unwrap()
+            .iter()
+            .map(|proxy| proxy["name"].as_str().unwrap())
+            .collect();
+        assert_eq!(names, ["One", "Two"]);
+        assert_eq!(
+            yaml["proxy-groups"][0]["proxies"]
+                .as_sequence()
+                .unwrap()
+                .len(),
+            2
+        );
+
+        // Rulesets are read and the configs generated with the given settings
+        // as well, without any option set on the converter
+        let dir = tempfile::tempdir().unwrap();
+        let list = dir.path().join("own_settings.list");
+        let rulesets = vec![RulesetConfig {
+            group: "Proxy".to_string(),
+            url: list.to_string_lossy().to_string(),
+            ..Default::default()
+        }];
+        let settings = Arc::new(Settings {
+            cache_ruleset: 0,
+            max_allowed_rules: 1,
+            singbox_add_clash_modes: true,
+            ..Settings::default()
+        });
+        let convert = |target: SubconverterTarget| {
+            let settings = Arc::clone(&settings);
+            let rulesets = rulesets.clone();
+            async move {
+                let mut converter = Subconverter::new(target);
+                converter
+                    .settings(settings)
+                    .subscription("ss://YWVzLTI1Ni1nY206cGFzcw@1.2.3.4:8388#One")
+                    .rulesets(rulesets);
+                converter.convert().await.unwrap().body
+            }
+        };
+
+        std::fs::write(&list, "DOMAIN,one.example.com\nDOMAIN,two.example.com\n").unwrap();
+        let clash = convert(SubconverterTarget::Clash).await;
+        assert!(clash.contains("DOMAIN,one.example.com,Proxy"), "{}", clash);
+        assert!(!clash.contains("two.example.com"), "{}", clash);
+        // The global settings would serve the list from the ruleset cache
+        std::fs::write(&list, "DOMAIN,three.example.com\n").unwrap();
+        let clash = convert(SubconverterTarget::Clash).await;
+        assert!(
+            clash.contains("DOMAIN,three.example.com,Proxy"),
+            "{}",
+            clash
+        );
+
+        let singbox: serde_json::Value =
+            serde_json::from_str(&convert(SubconverterTarget::SingBox).await).unwrap();
+        assert!(singbox["outbounds"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .any(|outbound| outbound["tag"] == "GLOBAL"));
+        assert!(singbox["route"]["rules"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .any(|rule| rule["clash_mode"] == "Global"));
+    }
+
+    #[tokio::test]
+    async fn test_invalid_subscription_content_is_an_input_error() {
+        let mut converter = Subconverter::new(SubconverterTarget::SS);
+        converter.subscription("not a subscription");
+
+        let error = converter.convert().await.unwrap_err();
+        assert_eq!(error.status_code(), 400);
+    }
+
+    async fn nodelist(target: SubconverterTarget, subscription: &str) -> String {
+        let mut converter = Subconverter::new(target);
+        converter.subscription(subscription);
+        converter
+            .options()
+            .nodelist(true)
+            .enable_rule_generator(false);
+        converter.convert().await.unwrap().body
+    }
+
+    async fn clash_nodelist(subscription: &str) -> String {
+        nodelist(SubconverterTarget::Clash, subscription).await
+    }
+
+    #[tokio::test]
+    async fn test_clash_nodelist_round_trips_as_input() {
+        let provider = r#"proxies:
+  - {name: WS, type: vmess, server: 1.2.3.4, port: 443, uuid: b831381d-6324-4d53-ad4f-8cda48b30811, alterId: 0, cipher: auto, tls: true, servername: cdn.example.com, client-fingerprint: chrome, network: ws, ws-opts: {path: /ws, headers: {Host: cdn.example.com}}}
+  - {name: GRPC, type: vmess, server: 1.2.3.4, port: 443, uuid: b831381d-6324-4d53-ad4f-8cda48b30811, alterId: 0, cipher: auto, network: grpc, grpc-opts: {grpc-service-name: svc}}
+  - {name: Trojan, type: trojan, server: 2.3.4.5, port: 443, password: secret, sni: trojan.example.com, alpn: [h2], client-fingerprint: chrome, network: ws, ws-opts: {path: /t, headers: {Host: t.example.com}}}
+"#;
+        let first = clash_nodelist(provider).await;
+        let yaml: serde_yaml::Value = serde_yaml::from_str(&first).unwrap();
+        let ws = &yaml["proxies"][0];
+        assert_eq!(ws["tls"].as_bool(), Some(true));
+        assert_eq!(ws["servername"].as_str(), Some("cdn.example.com"));
+        assert_eq!(ws["client-fingerprint"].as_str(), Some("chrome"));
+        assert_eq!(ws["ws-opts"]["path"].as_str(), Some("/ws"));
+        assert_eq!(
+            yaml["proxies"][1]["grpc-opts"]["grpc-service-name"].as_str(),
+            Some("svc")
+        );
+        assert_eq!(yaml["proxies"][2]["alpn"][0].as_str(), Some("h2"));
+        assert_eq!(yaml["proxies"][2]["ws-opts"]["path"].as_str(), Some("/t"));
+
+        assert_eq!(clash_nodelist(&first).await, first);
+
+        // A bare sequence of proxies is a provider file as well
+        let list = serde_yaml::to_string(&yaml["proxies"]).unwrap();
+        assert_eq!(clash_nodelist(&list).await, first);
+    }
+
+    #[tokio::test]
+    async fn test_alpn_lists_survive_conversion() {
+        let links = "vless://b831381d-6324-4d53-ad4f-8cda48b30811@1.2.3.4:443?security=tls&sni=v.example.com&alpn=h2,,http/1.1,h2&type=tcp#VLESS\n\
+                     trojan://secret@2.3.4.5:443?sni=t.example.com&alpn=http/1.1,h2#Trojan\n\
+                     hysteria2://secret@3.4.5.6:443?sni=h.example.com&alpn=h3#Hysteria2";
+        let yaml: serde_yaml::Value = serde_yaml::from_str(&clash_nodelist(links).await).unwrap();
+        let alpn = |yaml: &serde_yaml::Value, index: usize| -> Vec<String> {
+            yaml["proxies"][index]["alpn"]
+                .as_sequence()
+                .map(|alpn| {
+                    alpn.iter()
+                        .map(|protocol| protocol.as_str().unwrap().to_string())
+                        .collect()
+                })
+                .unwrap_or_default()
+        };
+        assert_eq!(alpn(&yaml, 0), ["h2", "http/1.1"]);
+        assert_eq!(alpn(&yaml, 1), ["http/1.1", "h2"]);
+        assert_eq!(alpn(&yaml, 2), ["h3"]);
+
+        let provider = r#"proxies:
+  - {name: Trojan, type: trojan, server: 2.3.4.5, port: 443, password: secret, alpn: "h2, http/1.1"}
+  - {name: Hysteria2, type: hysteria2, server: 3.4.5.6, port: 443, password: secret, alpn: [h3, "", h3]}
+  - {name: Plain, type: trojan, server: 2.3.4.5, port: 443, password: secret, alpn: []}
+"#;
+        let yaml: serde_yaml::Value =
+            serde_yaml::from_str(&clash_nodelist(provider).await).unwrap();
+        assert_eq!(alpn(&yaml, 0), ["h2", "http/1.1"]);
+        assert_eq!(alpn(&yaml, 1), ["h3"]);
+        assert!(yaml["proxies"][2].get("alpn").is_none());
+
+        let singbox: serde_json::Value =
+            serde_json::from_str(&nodelist(SubconverterTarget::SingBox, links).await).unwrap();
+        let outbound = |tag: &str| {
+            singbox["outbounds"]
+                .as_array()
+                .unwrap()
+                .iter()
+                .find(|outbound| outbound["tag"] == tag)
+                .unwrap()
+                .clone()
+        };
+        assert_eq!(
+            outbound("Trojan")["tls"]["alpn"],
+            serde_json::json!(["http/1.1", "h2"])
+        );
+        assert_eq!(
+            outbound("Hysteria2")["tls"]["alpn"],
+            serde_json::json!(["h3"])
+        );
+    }
+
+    #[tokio::test]
+    async fn test_tls_server_name_differs_from_host_header() {
+        let provider = r#"proxies:
+  - {name: WS, type: vmess, server: 1.2.3.4, port: 443, uuid: b831381d-6324-4d53-ad4f-8cda48b30811, alterId: 0, cipher: auto, tls: true, servername: sni.example.com, network: ws, ws-opts: {path: /ws, headers: {Host: cdn.example.com}}}
+"#;
+        let yaml: serde_yaml::Value =
+            serde_yaml::from_str(&clash_nodelist(provider).await).unwrap();
+        let ws = &yaml["proxies"][0];
+        assert_eq!(ws["servername"].as_str(), Some("sni.example.com"));
+        assert_eq!(
+            ws["ws-opts"]["headers"]["Host"].as_str(),
+            Some("cdn.example.com")
+        );
+        assert!(ws.get("sni").is_none());
+
+        let singbox: serde_json::Value =
+            serde_json::from_str(&nodelist(SubconverterTarget::SingBox, provider).await).unwrap();
+        let outbound = &singbox["outbounds"][0];
+        assert_eq!(outbound["tls"]["server_name"], "sni.example.com");
+        assert_eq!(outbound["transport"]["headers"]["Host"], "cdn.example.com");
+
+        let surge = nodelist(SubconverterTarget::Surge(4), provider).await;
+        assert!(surge.contains("sni=sni.example.com"), "{}", surge);
+        assert!(surge.contains("Host:cdn.example.com"), "{}", surge);
+    }
+
+    #[tokio::test]
+    async fn test_default_sni_only_fills_tls_nodes_without_one() {
+        let provider = r#"proxies:
+  - {name: Own, type: vmess, server: 1.2.3.4, port: 443, uuid: b831381d-6324-4d53-ad4f-8cda48b30811, alterId: 0, cipher: auto, tls: true, servername: sni.example.com}
+  - {name: Bare, type: vmess, server: 1.2.3.4, port: 443, uuid: b831381d-6324-4d53-ad4f-8cda48b30811, alterId: 0, cipher: auto, tls: true}
+  - {name: Plain, type: vmess, server: 1.2.3.4, port: 80, uuid: b831381d-6324-4d53-ad4f-8cda48b30811, alterId: 0, cipher: auto}
+  - {name: Trojan, type: trojan, server: 2.3.4.5, port: 443, password: secret}
+"#;
+        let mut converter = Subconverter::new(SubconverterTarget::Clash);
+        converter.subscription(provider);
+        converter
+            .options()
+            .nodelist(true)
+            .enable_rule_generator(false)
+            .sni(Some("default.example.com".to_string()));
+        let body = converter.convert().await.unwrap().body;
+        let yaml: serde_yaml::Value = serde_yaml::from_str(&body).unwrap();
+        let proxies = &yaml["proxies"];
+        assert_eq!(proxies[0]["servername"].as_str(), Some("sni.example.com"));
+        assert_eq!(
+            proxies[1]["servername"].as_str(),
+            Some("default.example.com")
+        );
+        assert!(proxies[2].get("servername").is_none());
+        assert_eq!(proxies[3]["sni"].as_str(), Some("default.example.com"));
+    }
+
+    #[tokio::test]
+    async fn test_gbk_subscription_remarks_survive() {
+        use base64::Engine;
+
+        let links = "ss://YWVzLTI1Ni1nY206cGFzcw@1.2.3.4:8388#香港 01\n\
+                     trojan://secret@2.3.4.5:443#日本 02";
+        let (gbk, _, _) = encoding_rs::GBK.encode(links);
+        let dir = tempfile::tempdir().unwrap();
+        let plain = dir.path().join("gbk.txt");
+        let encoded = dir.path().join("gbk_base64.txt");
+        std::fs::write(&plain, &gbk).unwrap();
+        std::fs::write(
+            &encoded,
+            base64::engine::general_purpose::STANDARD.encode(&gbk),
+        )
+        .unwrap();
+
+        for path in [plain, encoded] {
+            let mut converter = Subconverter::new(SubconverterTarget::Clash);
+            converter.url(path.to_str().unwrap());
+            converter
+                .options()
+                .authorized(true)
+                .nodelist(true)
+                .enable_rule_generator(false);
+            let body = converter.convert().await.unwrap().body;
+            let yaml: serde_yaml::Value = serde_yaml::from_str(&body).unwrap();
+            let names: Vec<&str> = yaml["proxies"]
+                .as_sequence()
+                .unwrap()
+                .iter()
+                .map(|proxy| proxy["name"].as_str().unwrap())
+                .collect();
+            assert_eq!(names, ["香港 01", "日本 02"], "{}", path.display());
+        }
+    }
+
+    #[tokio::test]
+    async fn test_ipv6_servers_are_stored_without_brackets() {
+        use base64::Engine;
+
+        let engine = &base64::engine::general_purpose::STANDARD;
+        let legacy_ss = engine.encode("aes-256-gcm:pass@2001:db8::2:8388");
+        let vmess = engine.encode(
+            r#"{"v":"2","ps":"VMess","add":"[2001:db8::3]","port":"443","id":"b831381d-6324-4d53-ad4f-8cda48b30811","aid":"0","net":"tcp"}"#,
+        );
+        let subscription = format!(
+            "ss://YWVzLTI1Ni1nY206cGFzcw@[2001:db8::1]:8388#SS\n\
+             ss://{}#Legacy\n\
+             vmess://{}\n\
+             trojan://secret@[2001:db8::4]:443#Trojan\n\
+             hysteria2://secret@[2001:db8::5]:443#Hy2",
+            legacy_ss, vmess
+        );
+        let servers = [
+            "2001:db8::1",
+            "2001:db8::2",
+            "2001:db8::3",
+            "2001:db8::4",
+            "2001:db8::5",
+        ];
+
+        let clash = nodelist(SubconverterTarget::Clash, &subscription).await;
+        let yaml: serde_yaml::Value = serde_yaml::from_str(&clash).unwrap();
+        let clash_servers: Vec<&str> = yaml["proxies"]
+            .as_sequence()
+            .unwrap()
+            .iter()
+            .map(|proxy| proxy["server"].as_str().unwrap())
+            .collect();
+        assert_eq!(clash_servers, servers);
+
+        let surge = nodelist(SubconverterTarget::Surge(4), &subscription).await;
+        for server in servers {
+            assert!(surge.contains(&format!(", {}, ", server)), "{}", surge);
+        }
+        assert!(!surge.contains('['), "{}", surge);
+
+        let singbox = nodelist(SubconverterTarget::SingBox, &subscription).await;
+        let json: serde_json::Value = serde_json::from_str(&singbox).unwrap();
+        let singbox_servers: Vec<&str> = json["outbounds"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .filter_map(|outbound| outbound["server"].as_str())
+            .collect();
+        assert_eq!(singbox_servers, servers);
+
+        // Links need the brackets back to be read again
+        let mixed = nodelist(SubconverterTarget::Mixed, &subscription).await;
+        assert!(mixed.contains("@[2001:db8::1]:8388"), "{}", mixed);
+        assert!(
+            mixed.contains("trojan://secret@[2001:db8::4]:443"),
+            "{}",
+            mixed
+        );
+        let again = nodelist(SubconverterTarget::Clash, &mixed).await;
+        let yaml: serde_yaml::Value = serde_yaml::from_str(&again).unwrap();
+        assert_eq!(yaml["proxies"][0]["server"].as_str(), Some("2001:db8::1"));
+    }
+
+    #[tokio::test]
+    async fn test_full_mihomo_config_converts_to_links() {
+        let config = include_str!("../../tests/fixtures/clash/mihomo.yaml");
+        let mut converter = Subconverter::new(SubconverterTarget::Mixed);
+        converter.subscription(config);
+        converter
+            .options()
+            .nodelist(true)
+            .enable_rule_generator(false)
+            .debug(true);
+        let converted = converter.convert().await.unwrap();
+
+        let schemes: Vec<&str> = converted
+            .body
+            .lines()
+            .filter(|line| !line.starts_with('#'))
+            .filter_map(|line| line.split_once("://").map(|(scheme, _)| scheme))
+            .collect();
+        assert_eq!(schemes, ["ss", "vmess", "vless", "trojan"]);
+
+        // Groups, rules and the Meta-only types leave the rest alone
+        let report: serde_json::Value =
+            serde_json::from_str(&converted.headers["X-Conversion-Report"]).unwrap();
+        assert_eq!(report["sources"][0]["nodes"], 6);
+        let dropped: Vec<(&str, &str)> = report["dropped"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|entry| {
+                (
+                    entry["scheme"].as_str().unwrap(),
+                    entry["reason"].as_str().unwrap(),
+                )
+            })
+            .collect();
+        assert_eq!(dropped.len(), 4);
+        assert!(dropped[0].1.contains("Broken VMess"), "{:?}", dropped);
+        assert_eq!(
+            dropped[1..],
+            [
+                ("mieru", "unsupported proxy type, 1 skipped"),
+                ("ssh", "unsupported proxy type, 1 skipped"),
+                ("tuic", "unsupported proxy type, 2 skipped"),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_insert_urls_keep_ssd_remark_info() {
+        let ssd = format!(
+            "ssd://{}",
+            crate::utils::base64::base64_encode(
+                r#"{"airport": "Airport", "port": 8388, "encryption": "aes-128-gcm", "password": "secret", "servers": [{"server": "1.2.3.4", "remarks": "HK 01", "ratio": 1.5}]}"#
+            )
+        );
+        let mut converter = Subconverter::new(SubconverterTarget::Clash);
+        converter.subscription("ss://YWVzLTI1Ni1nY206cGFzcw@5.6.7.8:8388#Own");
+        converter
+            .options()
+            .add_insert_url(&format!("data:,{}", crate::utils::url::url_encode(&ssd)))
+            .ssd_remark_info(true)
+            .nodelist(true)
+            .enable_rule_generator(false);
+        let body = converter.convert().await.unwrap().body;
+        let yaml: serde_yaml::Value = serde_yaml::from_str(&body).unwrap();
+        let names: Vec<&str> = yaml["proxies"]
+            .as_sequence()
+            .unwrap()
+            .iter()
+            .map(|proxy| proxy["name"].as_str().unwrap())
+            .collect();
+        assert!(names.contains(&"Airport - HK 01 (1.5x)"), "{:?}", names);
+    }
+
+    #[tokio::test]
+    async fn test_debug_lists_dropped_entries() {
+        let subscription = "ss://YWVzLTI1Ni1nY206cGFzcw@1.1.1.1:8388#Kept\n\
+                            ss://YWVzLTI1Ni1nY206cGFzcw@1.1.1.1:99999#Over\n";
+        let convert = |target: SubconverterTarget, debug: bool| async move {
+            let mut converter = Subconverter::new(target);
+            converter.subscription(subscription);
+            converter
+                .options()
+                .nodelist(true)
+                .enable_rule_generator(false)
+                .debug(debug);
+            converter.convert().await.unwrap()
+        };
+
+        let clash = convert(SubconverterTarget::Clash, true).await;
+        assert!(
+            clash.body.starts_with(
+                "# Conversion report\n# content 1: 1 nodes\n# dropped from content 1 line 2 (ss): "
+            ),
+            "{}",
+            clash.body
+        );
+        let yaml: serde_yaml::Value = serde_yaml::from_str(&clash.body).unwrap();
+        assert_eq!(yaml["proxies"].as_sequence().unwrap().len(), 1);
+        let header: serde_json::Value =
+            serde_json::from_str(&clash.headers["X-Conversion-Report"]).unwrap();
+        assert_eq!(header["sources"][0]["nodes"], 1);
+        assert_eq!(header["dropped_total"], 1);
+
+        let plain = convert(SubconverterTarget::Clash, false).await;
+        assert!(!plain.body.contains("# Conversion report"));
+        assert!(!plain.headers.contains_key("X-Conversion-Report"));
+        // JSON and base64 outputs have nowhere to put comments, only the
+        // header carries the report
+        let singbox = convert(SubconverterTarget::SingBox, true).await;
+        assert!(serde_json::from_str::<serde_json::Value>(&singbox.body).is_ok());
+        assert!(singbox.headers.contains_key("X-Conversion-Report"));
+        assert!(!convert(SubconverterTarget::SS, true)
+            .await
+            .body
+            .contains("# Conversion report"));
+    }
+
+    #[tokio::test]
+    async fn test_no_nodes_left_after_filtering_is_an_error() {
+        let convert = |allow_empty: bool| async move {
+            let mut converter = Subconverter::new(SubconverterTarget::V2Ray);
+            converter.subscription(
+                "ss://YWVzLTI1Ni1nY206cGFzcw@1.1.1.1:8388#HK 01\n\
+                 ss://YWVzLTI1Ni1nY206cGFzcw@1.1.1.2:8388#JP 01\n",
+            );
+            converter
+                .options()
+                .exclude_remarks(vec!["HK".to_string()])
+                .enable_rule_generator(false)
+                .allow_empty(allow_empty);
+            converter.convert().await
+        };
+
+        let error = convert(false).await.unwrap_err();
+        assert_eq!(error.status_code(), 400);
+        assert_eq!(
+            error.to_string(),
+            "No valid nodes after filtering: 2 nodes parsed, \
+             1 removed by include/exclude remarks, 1 not supported by v2ray"
+        );
+        assert!(convert(true).await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_failed_upload_still_returns_the_config() {
+        let mut converter = Subconverter::new(SubconverterTarget::Clash);
+        converter.subscription("ss://YWVzLTI1Ni1nY206cGFzcw@1.1.1.1:8388#A");
+        converter
+            .options()
+            .nodelist(true)
+            .enable_rule_generator(false)
+            .upload(true);
+        // Neither gistconf.ini nor gist_token, the upload fails before any request
+        let config = converter.convert().await.unwrap();
+        assert!(config.body.contains("name: A"), "{}", config.body);
+        assert_eq!(
+            config.headers.get(UPLOAD_ERROR_HEADER).map(String::as_str),
+            Some("gistconf.ini not found")
+        );
+        assert!(!config.headers.contains_key(UPLOAD_URL_HEADER));
+    }
+
+    #[tokio::test]
+    async fn test_duplicate_remarks_are_numbered_for_every_target() {
+        let subscription = "ss://YWVzLTI1Ni1nY206cGFzcw@1.2.3.4:8388#HK\n\
+                            trojan://secret@2.3.4.5:443#HK\n\
+                            trojan://secret@3.4.5.6:443#JP\n\
+                            ss://YWVzLTI1Ni1nY206cGFzcw@4.5.6.7:8388#HK";
+        let mut group = ProxyGroupConfig::new("Proxy".to_string(), ProxyGroupType::Select);
+        group.proxies = vec![".*".to_string()];
+
+        let mut converter = Subconverter::new(SubconverterTarget::Clash);
+        converter
+            .subscription(subscription)
+            .groups(vec![group.clone()]);
+        converter.options().enable_rule_generator(false);
+        let body = converter.convert().await.unwrap().body;
+        let yaml: serde_yaml::Value = serde_yaml::from_str(&body).unwrap();
+        let names: Vec<&str> = yaml["proxies"]
+            .as_sequence()
+            .unwrap()
+            .iter()
+            .map(|proxy| proxy["name"].as_str().unwrap())
+            .collect();
+        assert_eq!(names, ["HK", "HK 2", "JP", "HK 3"]);
+        let members: Vec<&str> = yaml["proxy-groups"][0]["proxies"]
+            .as_sequence()
+            .unwrap()
+            .iter()
+            .map(|name| name.as_str().unwrap())
+            .collect();
+        assert_eq!(members, names);
+
+        let dir = tempfile::tempdir().unwrap();
+        let base = dir.path().join("surge.conf");
+        std::fs::write(
+            &base,
+            "[General]\nloglevel = notify\n[Proxy]\n[Proxy Group]\n",
+        )
+        .unwrap();
+        let mut converter = Subconverter::new(SubconverterTarget::Surge(4));
+        converter
+            .base(base.to_str().unwrap())
+            .subscription(subscription)
+            .groups(vec![group]);
+        converter
+            .options()
+            .enable_rule_generator(false)
+            .duplicate_remark_format("{name} #{n}");
+        let body = converter.convert().await.unwrap().body;
+        assert!(
+            body.contains("Proxy = select,HK,HK #2,JP,HK #3"),
+            "{}",
+            body
+        );
+        assert!(body.contains("\nHK #3 = ss, 4.5.6.7, 8388"), "{}", body);
+    }
+
+    #[tokio::test]
+    async fn test_append_type_format_for_clash_and_surge() {
+        let subscription = "ss://YWVzLTI1Ni1nY206cGFzcw@1.2.3.4:8388#HK\n\
+                            trojan://secret@2.3.4.5:443#JP";
+
+        let mut converter = Subconverter::new(SubconverterTarget::Clash);
+        converter.subscription(subscription);
+        converter
+            .options()
+            .enable_rule_generator(false)
+            .append_proxy_type(true)
+            .append_type_format("{remark} | {type:lower} {{{port}}}");
+        let body = converter.convert().await.unwrap().body;
+        let yaml: serde_yaml::Value = serde_yaml::from_str(&body).unwrap();
+        let names: Vec<&str> = yaml["proxies"]
+            .as_sequence()
+            .unwrap()
+            .iter()
+            .map(|proxy| proxy["name"].as_str().unwrap())
+            .collect();
+        assert_eq!(names, ["HK | ss {8388}", "JP | trojan {443}"]);
+
+        // Unknown placeholders fall back to the default format
+        let dir = tempfile::tempdir().unwrap();
+        let base = dir.path().join("surge.conf");
+        std::fs::write(&base, "[General]\nloglevel = notify\n[Proxy]\n").unwrap();
+        let mut converter = Subconverter::new(SubconverterTarget::Surge(4));
+        converter
+            .base(base.to_str().unwrap())
+            .subscription(subscription);
+        converter
+            .options()
+            .enable_rule_generator(false)
+            .append_proxy_type(true)
+            .append_type_format("{remark} | {protocol}");
+        let body = converter.convert().await.unwrap().body;
+        assert!(body.contains("\n[SS] HK = ss, 1.2.3.4, 8388"), "{}", body);
+        assert!(
+            body.contains("\n[Trojan] JP = trojan, 2.3.4.5, 443"),
+            "{}",
+            body
+        );
+    }
+}
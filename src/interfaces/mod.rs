@@ -1,3 +1,7 @@
+pub mod converter;
+pub mod report;
 pub mod subconverter;
 
+pub use converter::*;
+pub use report::*;
 pub use subconverter::*;
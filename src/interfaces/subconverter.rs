@@ -1,25 +1,42 @@
+use crate::generator::config::formats::remote_base::fetch_remote_base;
 use crate::generator::config::formats::single::{proxy_to_single, ProxyUriTypes};
 use crate::generator::config::formats::ssd::proxy_to_ssd;
 use crate::generator::config::formats::{
     loon::proxy_to_loon, mellow::proxy_to_mellow, quan::proxy_to_quan, quanx::proxy_to_quanx,
     singbox::proxy_to_singbox, ss_sub::proxy_to_ss_sub, surge::proxy_to_surge,
 };
-use crate::generator::exports::proxy_to_clash::proxy_to_clash;
+use crate::generator::config::group::{
+    auto_groups, resolve_group_references, sanitize_group_names,
+};
+use crate::generator::config::remark::{disambiguate_remarks, index_remarks};
+use crate::generator::exports::proxy_to_clash::{clash_skip_reason, proxy_to_clash_chunks};
+use crate::generator::exports::{ConfigChunks, ExportError, STREAM_THRESHOLD};
+use crate::interfaces::report::ConversionReport;
 use crate::models::ruleset::RulesetConfigs;
 use crate::models::{
-    ExtraSettings, Proxy, ProxyGroupConfigs, RegexMatchConfig, RulesetContent, SubconverterTarget,
+    Base64Style, DedupMode, ExtraSettings, Proxy, ProxyGroupConfigs, ProxyProvider,
+    RegexMatchConfig, RulesetContent, SubconverterTarget,
+};
+use crate::parser::explodes::{
+    explode_conf_content_with_diagnostics, log_diagnostics, NodeDiagnostic,
 };
+use crate::parser::infoparser::get_info_nodes_from_sub_info;
 use crate::parser::parse_settings::ParseSettings;
-use crate::parser::subparser::add_nodes;
+use crate::parser::subparser::{add_nodes, filter_nodes};
 use crate::rulesets::ruleset::refresh_rulesets;
+use crate::template::{render_template_as, template_error_message, TemplateFormat};
 use crate::utils::file_get_async;
+use crate::utils::geoip::locate_nodes;
 use crate::utils::http::parse_proxy;
-use crate::utils::http::web_get_async;
+use crate::utils::rhai_filter::{filter_nodes_with_rhai_async, is_rhai_script};
+use crate::utils::url_encode;
 use crate::{Settings, TemplateArgs};
 use case_insensitive_string::CaseInsensitiveString;
 use log::{debug, error, info, warn};
 use serde::Serialize;
 use std::collections::HashMap;
+use std::sync::Arc;
+use thiserror::Error;
 
 #[derive(Debug, Clone, Default)]
 pub struct RuleBases {
@@ -44,6 +61,10 @@ pub struct SubconverterConfig {
     pub urls: Vec<String>,
     /// URLs to insert
     pub insert_urls: Vec<String>,
+    /// Nodes handed in already parsed, used as they are
+    pub nodes: Vec<Proxy>,
+    /// Raw subscription contents, parsed like a downloaded subscription
+    pub contents: Vec<String>,
     /// Whether to prepend inserted nodes
     pub prepend_insert: bool,
     /// Custom group name
@@ -52,6 +73,8 @@ pub struct SubconverterConfig {
     pub ruleset_configs: RulesetConfigs,
     /// Custom proxy groups
     pub proxy_groups: ProxyGroupConfigs,
+    /// Whether groups by country are generated ahead of `proxy_groups`
+    pub autogroup: bool,
     /// Include nodes matching these remarks
     pub include_remarks: Vec<String>,
     /// Exclude nodes matching these remarks
@@ -70,6 +93,9 @@ pub struct SubconverterConfig {
     pub update_strict: bool,
     /// Managed config prefix
     pub managed_config_prefix: String,
+    /// Path and query of the request below the API base path, e.g.
+    /// `/sub?target=surge&ver=4&url=...`, the managed config URL points at it
+    pub request_path: Option<String>,
     /// Upload path
     pub upload_path: Option<String>,
     /// Whether to upload the result
@@ -88,6 +114,16 @@ pub struct SubconverterConfig {
     pub template_args: Option<TemplateArgs>,
     /// Request headers
     pub request_headers: Option<HashMap<String, String>>,
+    /// Whether a config generated from large rulesets may be returned as
+    /// chunks instead of a single string
+    pub stream_output: bool,
+    /// Settings to convert with, the global settings when `None`
+    pub settings: Option<Arc<Settings>>,
+    /// Whether a report of where the nodes and rules went is put ahead of
+    /// the config and in the `X-Conversion-Report` header
+    pub debug: bool,
+    /// Whether a config is generated when filtering leaves no node
+    pub allow_empty: bool,
 }
 
 /// Builder for SubconverterConfig
@@ -110,10 +146,13 @@ impl SubconverterConfigBuilder {
                 target: SubconverterTarget::Clash,
                 urls: Vec::new(),
                 insert_urls: Vec::new(),
+                nodes: Vec::new(),
+                contents: Vec::new(),
                 prepend_insert: false,
                 group_name: None,
                 ruleset_configs: RulesetConfigs::default(),
                 proxy_groups: Vec::new(),
+                autogroup: false,
                 include_remarks: Vec::new(),
                 exclude_remarks: Vec::new(),
                 extra: ExtraSettings::default(),
@@ -123,6 +162,7 @@ impl SubconverterConfigBuilder {
                 filter_script: None,
                 update_strict: false,
                 managed_config_prefix: String::new(),
+                request_path: None,
                 upload_path: None,
                 upload: false,
                 proxy: None,
@@ -132,6 +172,10 @@ impl SubconverterConfigBuilder {
                 rule_bases: RuleBases::default(),
                 template_args: None,
                 request_headers: None,
+                stream_output: false,
+                settings: None,
+                debug: false,
+                allow_empty: false,
             },
         }
     }
@@ -200,6 +244,29 @@ impl SubconverterConfigBuilder {
         self
     }
 
+    /// Set nodes that are already parsed
+    pub fn nodes(&mut self, nodes: Vec<Proxy>) -> &mut Self {
+        self.config.nodes = nodes;
+        self
+    }
+
+    /// Add raw subscription content, e.g. a base64 link list or a Clash config
+    pub fn add_content(&mut self, content: &str) -> &mut Self {
+        self.config.contents.push(content.to_string());
+        self
+    }
+
+    /// Set the settings to convert with instead of the global ones
+    ///
+    /// The defaults of the export options taken from the settings, e.g. the
+    /// rule generator switch and the Clash styles, are replaced with theirs,
+    /// so this is set before those options.
+    pub fn settings(&mut self, settings: Arc<Settings>) -> &mut Self {
+        self.config.extra.use_settings(Arc::clone(&settings));
+        self.config.settings = Some(settings);
+        self
+    }
+
     /// Set whether to prepend inserted nodes
     pub fn prepend_insert(&mut self, prepend: bool) -> &mut Self {
         self.config.prepend_insert = prepend;
@@ -279,12 +346,24 @@ impl SubconverterConfigBuilder {
         self
     }
 
+    /// Set the format of remarks with the proxy type, e.g. `{remark} | {type}`
+    pub fn append_type_format(&mut self, format: &str) -> &mut Self {
+        self.config.extra.append_type_format = format.to_string();
+        self
+    }
+
     /// Set whether to enable TCP Fast Open
     pub fn tfo(&mut self, tfo: Option<bool>) -> &mut Self {
         self.config.extra.tfo = tfo;
         self
     }
 
+    /// Set whether Surge nodes block QUIC, `None` keeps what the nodes have
+    pub fn block_quic(&mut self, block_quic: Option<bool>) -> &mut Self {
+        self.config.extra.block_quic = block_quic;
+        self
+    }
+
     /// Set whether to enable UDP
     pub fn udp(&mut self, udp: Option<bool>) -> &mut Self {
         self.config.extra.udp = udp;
@@ -303,6 +382,12 @@ impl SubconverterConfigBuilder {
         self
     }
 
+    /// Set the TLS server name of TLS nodes that have none
+    pub fn sni(&mut self, sni: Option<String>) -> &mut Self {
+        self.config.extra.sni = sni;
+        self
+    }
+
     /// Set whether to sort nodes
     pub fn sort(&mut self, sort: bool) -> &mut Self {
         self.config.extra.sort_flag = sort;
@@ -315,12 +400,109 @@ impl SubconverterConfigBuilder {
         self
     }
 
+    /// Set the patterns ranking nodes when sorting without a script
+    pub fn sort_priority(&mut self, patterns: Vec<String>) -> &mut Self {
+        self.config.extra.sort_priority = patterns;
+        self
+    }
+
+    /// Set how duplicate nodes are removed
+    pub fn dedup(&mut self, mode: DedupMode) -> &mut Self {
+        self.config.extra.dedup = mode;
+        self
+    }
+
+    /// Set the pattern renaming nodes whose remark is taken
+    pub fn duplicate_remark_format(&mut self, format: &str) -> &mut Self {
+        self.config.extra.duplicate_remark_format = format.to_string();
+        self
+    }
+
+    /// Set the template renaming nodes after sorting, e.g. `{country} {index:02}`
+    pub fn index_remark(&mut self, template: &str) -> &mut Self {
+        self.config.extra.index_remark = template.to_string();
+        self
+    }
+
+    /// Set whether to prepend nodes showing subscription traffic and expiry
+    pub fn append_info_nodes(&mut self, append: bool) -> &mut Self {
+        self.config.extra.append_info_nodes = append;
+        self
+    }
+
+    /// Set whether SSD remarks get the airport name and traffic ratio
+    pub fn ssd_remark_info(&mut self, append: bool) -> &mut Self {
+        self.config.extra.ssd_remark_info = append;
+        self
+    }
+
     /// Set whether to filter deprecated nodes
     pub fn filter_deprecated(&mut self, filter: bool) -> &mut Self {
         self.config.extra.filter_deprecated = filter;
         self
     }
 
+    /// Set the DNS section merged into Clash configs
+    pub fn clash_dns(&mut self, dns: Option<serde_yaml::Value>) -> &mut Self {
+        self.config.extra.clash_dns = dns;
+        self
+    }
+
+    /// Set whether the Clash output targets Clash.Meta
+    pub fn clash_meta(&mut self, meta: bool) -> &mut Self {
+        self.config.extra.clash_meta = meta;
+        self
+    }
+
+    /// Set the TUN section merged into Clash.Meta configs
+    pub fn clash_tun(&mut self, tun: Option<serde_yaml::Value>) -> &mut Self {
+        self.config.extra.clash_tun = tun;
+        self
+    }
+
+    /// Set the sniffer section merged into Clash.Meta configs
+    pub fn clash_sniffer(&mut self, sniffer: Option<serde_yaml::Value>) -> &mut Self {
+        self.config.extra.clash_sniffer = sniffer;
+        self
+    }
+
+    /// Set whether the TUN and sniffer sections override the ones in the base
+    pub fn clash_meta_overwrite(&mut self, overwrite: bool) -> &mut Self {
+        self.config.extra.clash_meta_overwrite = overwrite;
+        self
+    }
+
+    /// Set whether Clash.Meta groups keep their regex rules as native filters
+    pub fn native_filter(&mut self, native_filter: bool) -> &mut Self {
+        self.config.extra.native_filter = native_filter;
+        self
+    }
+
+    /// Set whether Clash configs reference subscriptions as proxy providers
+    pub fn clash_providers(&mut self, providers: bool) -> &mut Self {
+        self.config.extra.clash_providers = providers;
+        self
+    }
+
+    /// Set whether QuantumultX configs reference subscriptions as
+    /// `server_remote` resources
+    pub fn quanx_server_remote(&mut self, server_remote: bool) -> &mut Self {
+        self.config.extra.quanx_server_remote = server_remote;
+        self
+    }
+
+    /// Set the lines merged into the `rewrite_remote` section of QuantumultX configs
+    pub fn quanx_rewrite_remote(&mut self, rewrite_remote: Vec<String>) -> &mut Self {
+        self.config.extra.quanx_rewrite_remote = rewrite_remote;
+        self
+    }
+
+    /// Set the lines merged into the `task_local` section of QuantumultX configs
+    pub fn quanx_task_local(&mut self, task_local: Vec<String>) -> &mut Self {
+        self.config.extra.quanx_task_local = task_local;
+        self
+    }
+
     /// Set whether to use new field names in Clash
     pub fn clash_new_field_name(&mut self, new_field: bool) -> &mut Self {
         self.config.extra.clash_new_field_name = new_field;
@@ -344,6 +526,24 @@ impl SubconverterConfigBuilder {
         self
     }
 
+    /// Set whether Surge gets a module of rules instead of a full profile
+    pub fn surge_module(&mut self, module: bool) -> &mut Self {
+        self.config.extra.surge_module = module;
+        self
+    }
+
+    /// Set whether Loon gets a plugin of rules instead of a full profile
+    pub fn loon_plugin(&mut self, plugin: bool) -> &mut Self {
+        self.config.extra.loon_plugin = plugin;
+        self
+    }
+
+    /// Set how subscriptions of share links are base64 encoded
+    pub fn base64_style(&mut self, style: Base64Style) -> &mut Self {
+        self.config.extra.base64_style = style;
+        self
+    }
+
     /// Set whether to enable rule generator
     pub fn enable_rule_generator(&mut self, enable: bool) -> &mut Self {
         self.config.extra.enable_rule_generator = enable;
@@ -356,6 +556,18 @@ impl SubconverterConfigBuilder {
         self
     }
 
+    /// Set whether Clash configs drop the proxies of the base
+    pub fn overwrite_original_proxies(&mut self, overwrite: bool) -> &mut Self {
+        self.config.extra.overwrite_original_proxies = overwrite;
+        self
+    }
+
+    /// Set whether Clash configs drop the groups of the base
+    pub fn overwrite_original_groups(&mut self, overwrite: bool) -> &mut Self {
+        self.config.extra.overwrite_original_groups = overwrite;
+        self
+    }
+
     /// Set device ID
     pub fn device_id(&mut self, device_id: Option<String>) -> &mut Self {
         self.config.device_id = device_id;
@@ -393,6 +605,20 @@ impl SubconverterConfigBuilder {
         self
     }
 
+    /// Set the base URL this server was reached at, used in place of an empty
+    /// managed config prefix
+    pub fn external_base_url(&mut self, url: String) -> &mut Self {
+        self.config.extra.external_base_url = url;
+        self
+    }
+
+    /// Set the path and query of the request, see
+    /// [`SubconverterConfig::request_path`]
+    pub fn request_path(&mut self, path: Option<String>) -> &mut Self {
+        self.config.request_path = path;
+        self
+    }
+
     /// Set upload path
     pub fn upload_path(&mut self, path: Option<String>) -> &mut Self {
         self.config.upload_path = path;
@@ -500,12 +726,44 @@ impl SubconverterConfigBuilder {
         self
     }
 
+    /// Allow large configs to be returned as chunks
+    pub fn stream_output(&mut self, stream_output: bool) -> &mut Self {
+        self.config.stream_output = stream_output;
+        self
+    }
+
+    /// Report where the nodes and rules went, as comments ahead of the
+    /// config and in the `X-Conversion-Report` header
+    pub fn debug(&mut self, debug: bool) -> &mut Self {
+        self.config.debug = debug;
+        self.config.extra.debug = debug;
+        self
+    }
+
+    /// Generate a url-test group per country, a group for the other nodes
+    /// and a select group between them ahead of the custom groups
+    pub fn autogroup(&mut self, autogroup: bool) -> &mut Self {
+        self.config.autogroup = autogroup;
+        self
+    }
+
+    /// Generate a config without nodes instead of failing when filtering
+    /// removes every node, for rule-only configs
+    pub fn allow_empty(&mut self, allow_empty: bool) -> &mut Self {
+        self.config.allow_empty = allow_empty;
+        self
+    }
+
     /// Build the final configuration
     pub fn build(self) -> Result<SubconverterConfig, String> {
         let config = self.config;
 
         // Basic validation
-        if config.urls.is_empty() && config.insert_urls.is_empty() {
+        if config.urls.is_empty()
+            && config.insert_urls.is_empty()
+            && config.nodes.is_empty()
+            && config.contents.is_empty()
+        {
             return Err("No URLs provided".to_string());
         }
 
@@ -513,6 +771,11 @@ impl SubconverterConfigBuilder {
     }
 }
 
+/// Response header with the raw URL of the Gist a config was uploaded to
+pub const UPLOAD_URL_HEADER: &str = "X-Upload-Url";
+/// Response header with why uploading a config failed
+pub const UPLOAD_ERROR_HEADER: &str = "X-Upload-Error";
+
 /// Represents the status of the Gist upload operation
 #[derive(Debug, Clone, Serialize)]
 #[serde(tag = "status", content = "url")]
@@ -526,16 +789,97 @@ pub enum UploadStatus {
 }
 
 /// Result of subscription conversion
-#[derive(Debug, Clone)]
+#[derive(Debug)]
 pub struct SubconverterResult {
-    /// Converted content
+    /// Converted content, empty when the config is returned in `stream`
     pub content: String,
+    /// Converted content left to be produced while it is sent
+    pub stream: Option<ConfigChunks>,
     /// Response headers
     pub headers: HashMap<String, String>,
     /// Status of the Gist upload
     pub upload_status: UploadStatus,
 }
 
+/// Errors of the conversion API, the message of each variant is what clients
+/// are shown
+#[derive(Error, Debug)]
+pub enum SubconverterError {
+    /// The request or the configs it refers to cannot be converted
+    #[error("{0}")]
+    InvalidInput(String),
+
+    /// A profile or other named resource does not exist
+    #[error("{0}")]
+    NotFound(String),
+
+    /// The request lacks the access token the API is configured with
+    #[error("{0}")]
+    Unauthorized(String),
+
+    /// A subscription, config or ruleset could not be downloaded
+    #[error("{source}")]
+    UpstreamFetch {
+        url: String,
+        source: Box<dyn std::error::Error + Send + Sync>,
+    },
+
+    /// `what` (e.g. a profile) was found but cannot be read
+    #[error("{detail}")]
+    ParseError { what: String, detail: String },
+
+    /// Conversion failed for reasons outside the request
+    #[error("{0}")]
+    Internal(String),
+}
+
+impl SubconverterError {
+    /// HTTP status code to report the error with
+    pub fn status_code(&self) -> u16 {
+        match self {
+            SubconverterError::InvalidInput(_) | SubconverterError::ParseError { .. } => 400,
+            SubconverterError::Unauthorized(_) => 403,
+            SubconverterError::NotFound(_) => 404,
+            SubconverterError::UpstreamFetch { .. } => 502,
+            SubconverterError::Internal(_) => 500,
+        }
+    }
+
+    /// Name the subscription `url` failed for, requests refused for what they
+    /// asked keep their status
+    fn for_url(self, kind: &str, url: &str) -> Self {
+        match self {
+            SubconverterError::Internal(e) => {
+                SubconverterError::Internal(format!("Failed to parse {} '{}': {}", kind, url, e))
+            }
+            e => e,
+        }
+    }
+
+    /// Message the API answers with, internal failures are marked as such
+    pub fn message(&self) -> String {
+        match self {
+            SubconverterError::Internal(_) => format!("Internal server error: {}", self),
+            _ => self.to_string(),
+        }
+    }
+}
+
+impl From<String> for SubconverterError {
+    fn from(message: String) -> Self {
+        SubconverterError::Internal(message)
+    }
+}
+
+impl From<ExportError> for SubconverterError {
+    fn from(error: ExportError) -> Self {
+        match error {
+            ExportError::InvalidBase(_) => SubconverterError::InvalidInput(error.to_string()),
+            ExportError::Serialize(_) => SubconverterError::Internal(error.to_string()),
+        }
+    }
+}
+
 /// Options for parsing subscriptions
 #[derive(Debug, Clone)]
 pub struct ParseOptions {
@@ -547,6 +891,9 @@ pub struct ParseOptions {
 
     /// Whether the request is authorized
     pub authorized: bool,
+
+    /// Whether SSD remarks get the airport name and traffic ratio
+    pub ssd_remark_info: bool,
 }
 
 impl Default for ParseOptions {
@@ -555,27 +902,74 @@ impl Default for ParseOptions {
             include_remarks: Vec::new(),
             exclude_remarks: Vec::new(),
             authorized: false,
+            ssd_remark_info: false,
         }
     }
 }
 
+/// What was left out while parsing subscriptions
+#[derive(Debug, Default)]
+pub struct ParseReport {
+    /// Entries which did not yield a usable node
+    pub diagnostics: Vec<NodeDiagnostic>,
+    /// Nodes removed by the include and exclude remarks
+    pub filtered: usize,
+}
+
+/// How many nodes were parsed and how many each filter removed, to explain a
+/// config which ends up without nodes
+#[derive(Debug, Default)]
+struct NodeCounts {
+    parsed: usize,
+    remarks: usize,
+    script: usize,
+    preprocess: usize,
+    target: usize,
+}
+
+impl NodeCounts {
+    fn summary(&self, target: &SubconverterTarget) -> String {
+        let mut summary = format!("{} nodes parsed", self.parsed);
+        let stages = [
+            (
+                self.remarks,
+                "removed by include/exclude remarks".to_string(),
+            ),
+            (self.script, "removed by the filter script".to_string()),
+            (self.preprocess, "removed while deduplicating".to_string()),
+            (self.target, format!("not supported by {}", target.to_str())),
+        ];
+        for (count, stage) in stages {
+            if count > 0 {
+                summary.push_str(&format!(", {} {}", count, stage));
+            }
+        }
+        summary
+    }
+}
+
 /// Parse a subscription URL and return a vector of proxies
 ///
 /// # Arguments
 /// * `url` - The subscription URL to parse
 /// * `options` - Options for parsing
+/// * `settings` - Settings the parse defaults come from
+/// * `report` - Receives what was left out
 ///
 /// # Returns
-/// * `Ok(Vec<Proxy>)` - The parsed proxies
-/// * `Err(String)` - Error message if parsing fails
+/// * `Ok((Vec<Proxy>, Option<String>))` - The parsed proxies and the subscription
+///   traffic info if the source provided any
+/// * `Err(SubconverterError)` - Why parsing failed
 pub async fn parse_subscription(
     url: &str,
     options: ParseOptions,
     group_id: i32,
     request_headers: &Option<HashMap<String, String>>,
-) -> Result<Vec<Proxy>, String> {
+    settings: &Settings,
+    report: &mut ParseReport,
+) -> Result<(Vec<Proxy>, Option<String>), SubconverterError> {
     // Create a new parse settings instance
-    let mut parse_settings = ParseSettings::default();
+    let mut parse_settings = ParseSettings::from_settings(settings);
 
     if let Some(headers) = request_headers {
         let mut i_request_headers = HashMap::new();
@@ -595,72 +989,217 @@ pub async fn parse_subscription(
     }
 
     parse_settings.authorized = options.authorized;
+    parse_settings.ssd_remark_info = options.ssd_remark_info;
 
     // Create a vector to hold the nodes
     let mut nodes = Vec::new();
 
     // Call add_nodes to do the actual parsing
     // We use group_id = 0 since we don't care about it in this context
-    add_nodes(url.to_string(), &mut nodes, group_id, &mut parse_settings).await?;
+    let result = add_nodes(url.to_string(), &mut nodes, group_id, &mut parse_settings).await;
+    report.diagnostics.append(&mut parse_settings.diagnostics);
+    report.filtered += parse_settings.filtered_nodes;
+    result?;
 
-    Ok(nodes)
+    Ok((nodes, parse_settings.sub_info))
 }
 
 /// Process a subscription conversion request
-pub async fn subconverter(mut config: SubconverterConfig) -> Result<SubconverterResult, String> {
+pub async fn subconverter(
+    mut config: SubconverterConfig,
+) -> Result<SubconverterResult, SubconverterError> {
     let mut response_headers = HashMap::new();
-    let mut nodes = Vec::new();
-    let global = Settings::current();
+    let mut nodes = std::mem::take(&mut config.nodes);
+    let global = match &config.settings {
+        Some(settings) => Arc::clone(settings),
+        None => Arc::clone(&Settings::current()),
+    };
+    // The generators read the settings from the export options
+    config.extra.settings = Arc::clone(&global);
 
     info!(
         "Processing subscription conversion request to {}",
         config.target.to_str()
     );
 
+    // Groups leaving out their test URL or timings probe with the configured defaults
+    for group in config.proxy_groups.iter_mut() {
+        group.apply_test_defaults(
+            &global.group_test_url,
+            global.group_interval,
+            global.group_timeout,
+            global.group_tolerance,
+            global.group_lazy,
+        );
+        // Only Clash configs understand include-all, list every node elsewhere
+        if group.include_all && !config.target.is_clash() {
+            group.proxies.insert(0, ".*".to_string());
+        }
+    }
+    // Groups listed by name in other groups are members, not node patterns
+    resolve_group_references(&mut config.proxy_groups);
+
     // Parse subscription URLs
     let opts = ParseOptions {
         include_remarks: config.include_remarks.clone(),
         exclude_remarks: config.exclude_remarks.clone(),
         authorized: config.authorized,
+        ssd_remark_info: config.extra.ssd_remark_info,
     };
 
-    // Parse insert URLs first if needed
+    // Parse insert URLs first if needed, they are never filtered by remark so
+    // self-hosted nodes survive rules meant for traffic info nodes. Insert
+    // groups count down from -1 to be matched with !!INSERT=
     let mut insert_nodes = Vec::new();
+    let mut report = ParseReport::default();
+    let mut conversion_report = ConversionReport::default();
     if !config.insert_urls.is_empty() {
+        let insert_opts = ParseOptions {
+            authorized: config.authorized,
+            ssd_remark_info: config.extra.ssd_remark_info,
+            ..Default::default()
+        };
         let mut group_id = -1;
         info!("Fetching node data from insert URLs");
-        for url in &config.insert_urls {
+        for (index, url) in config.insert_urls.iter().enumerate() {
             debug!("Parsing insert URL: {}", url);
-            match parse_subscription(url, opts.clone(), group_id, &config.request_headers).await {
-                Ok(mut parsed_nodes) => {
+            let (dropped_before, filtered_before) = (report.diagnostics.len(), report.filtered);
+            match parse_subscription(
+                url,
+                insert_opts.clone(),
+                group_id,
+                &config.request_headers,
+                &global,
+                &mut report,
+            )
+            .await
+            {
+                Ok((mut parsed_nodes, _)) => {
                     info!("Found {} nodes from insert URL", parsed_nodes.len());
+                    conversion_report.add_source(
+                        "insert url",
+                        index + 1,
+                        url,
+                        parsed_nodes.len(),
+                        report.filtered - filtered_before,
+                        &report.diagnostics[dropped_before..],
+                    );
                     insert_nodes.append(&mut parsed_nodes);
                 }
                 Err(e) => {
                     warn!("Failed to parse insert URL '{}': {}", url, e);
+                    conversion_report.add_failed_source("insert url", index + 1, url);
                     if !global.skip_failed_links {
-                        return Err(format!("Failed to parse insert URL '{}': {}", url, e));
+                        return Err(e.for_url("insert URL", url));
                     }
                 }
             }
-            group_id += 1;
+            group_id -= 1;
         }
     }
 
     let mut group_id = 0;
+    for (index, content) in config.contents.iter().enumerate() {
+        let mut parsed_nodes = Vec::new();
+        let mut dropped = Vec::new();
+        let count = explode_conf_content_with_diagnostics(content, &mut parsed_nodes, &mut dropped);
+        log_diagnostics(&dropped);
+        if count <= 0 {
+            return Err(SubconverterError::InvalidInput(
+                "Invalid subscription content".to_string(),
+            ));
+        }
+        let filtered = filter_nodes(
+            &mut parsed_nodes,
+            Some(&config.exclude_remarks).filter(|remarks| !remarks.is_empty()),
+            Some(&config.include_remarks).filter(|remarks| !remarks.is_empty()),
+            group_id,
+        );
+        report.filtered += filtered;
+        info!("Found {} nodes from content", parsed_nodes.len());
+        conversion_report.add_source(
+            "content",
+            index + 1,
+            "",
+            parsed_nodes.len(),
+            filtered,
+            &dropped,
+        );
+        report.diagnostics.append(&mut dropped);
+        nodes.append(&mut parsed_nodes);
+        group_id += 1;
+    }
+
     // Parse main URLs
     info!("Fetching node data from main URLs");
-    for url in &config.urls {
+    let provider_target = if config.extra.nodelist {
+        None
+    } else if config.target.is_clash() && config.extra.clash_providers {
+        Some("clash")
+    } else if config.target == SubconverterTarget::QuantumultX && config.extra.quanx_server_remote {
+        Some("quanx")
+    } else {
+        None
+    };
+    for (index, url) in config.urls.iter().enumerate() {
+        // The client fetches subscriptions itself in providers mode, only node links are inlined
+        if let Some(target) =
+            provider_target.filter(|_| url.starts_with("http://") || url.starts_with("https://"))
+        {
+            let name = format!("provider_{}", config.extra.proxy_providers.len() + 1);
+            let url_prefix = config.extra.self_url_prefix();
+            let provider_url = if url_prefix.is_empty() {
+                url.clone()
+            } else {
+                format!(
+                    "{}/sub?target={}&list=true&url={}",
+                    url_prefix,
+                    target,
+                    url_encode(url)
+                )
+            };
+            debug!("Referencing URL as proxy provider {}: {}", name, url);
+            config.extra.proxy_providers.push(ProxyProvider {
+                name,
+                url: provider_url,
+                interval: config.update_interval,
+            });
+            group_id += 1;
+            continue;
+        }
         debug!("Parsing URL: {}", url);
-        match parse_subscription(url, opts.clone(), group_id, &config.request_headers).await {
-            Ok(mut parsed_nodes) => {
+        let (dropped_before, filtered_before) = (report.diagnostics.len(), report.filtered);
+        match parse_subscription(
+            url,
+            opts.clone(),
+            group_id,
+            &config.request_headers,
+            &global,
+            &mut report,
+        )
+        .await
+        {
+            Ok((mut parsed_nodes, sub_info)) => {
                 info!("Found {} nodes from URL", parsed_nodes.len());
+                conversion_report.add_source(
+                    "url",
+                    index + 1,
+                    url,
+                    parsed_nodes.len(),
+                    report.filtered - filtered_before,
+                    &report.diagnostics[dropped_before..],
+                );
                 nodes.append(&mut parsed_nodes);
+                // The first subscription reporting usage speaks for the whole config
+                if config.sub_info.is_none() {
+                    config.sub_info = sub_info.filter(|info| !info.is_empty());
+                }
             }
             Err(e) => {
                 error!("Failed to parse URL '{}': {}", url, e);
+                conversion_report.add_failed_source("url", index + 1, url);
                 if !global.skip_failed_links {
-                    return Err(format!("Failed to parse URL '{}': {}", url, e));
+                    return Err(e.for_url("URL", url));
                 }
             }
         }
@@ -668,8 +1207,32 @@ pub async fn subconverter(mut config: SubconverterConfig) -> Result<Subconverter
     }
 
     // Exit if found nothing
-    if nodes.is_empty() && insert_nodes.is_empty() {
-        return Err("No nodes were found!".to_string());
+    let mut counts = NodeCounts {
+        parsed: nodes.len() + insert_nodes.len() + report.filtered,
+        remarks: report.filtered,
+        ..Default::default()
+    };
+    if counts.parsed == 0 && config.extra.proxy_providers.is_empty() {
+        return Err(SubconverterError::InvalidInput(
+            "No nodes were found!".to_string(),
+        ));
+    }
+
+    // Groups matching nodes by rule take them from every provider as well
+    if !config.extra.proxy_providers.is_empty() {
+        let provider_names: Vec<String> = config
+            .extra
+            .proxy_providers
+            .iter()
+            .map(|provider| provider.name.clone())
+            .collect();
+        for group in config.proxy_groups.iter_mut() {
+            if group.using_provider.is_empty()
+                && group.proxies.iter().any(|rule| !rule.starts_with("[]"))
+            {
+                group.using_provider = provider_names.clone();
+            }
+        }
     }
 
     // Merge insert nodes and main nodes
@@ -702,9 +1265,32 @@ pub async fn subconverter(mut config: SubconverterConfig) -> Result<Subconverter
     }
 
     // Apply filter script if available
+    let before_script = nodes.len();
     if global.enable_filter && config.extra.authorized {
         if let Some(_script) = &config.filter_script {
-            if !_script.is_empty() {
+            if is_rhai_script(_script) {
+                info!("Applying Rhai filter script");
+                let result = match _script.strip_prefix("path:") {
+                    Some(path) => match file_get_async(path, None).await {
+                        Ok(source) => filter_nodes_with_rhai_async(&mut nodes, source).await,
+                        Err(e) => Err(format!("can not read {}: {}", path, e)),
+                    },
+                    None => {
+                        let source = _script["rhai:".len()..].to_string();
+                        filter_nodes_with_rhai_async(&mut nodes, source).await
+                    }
+                };
+                match result {
+                    Ok(()) => info!("Filter script applied successfully"),
+                    Err(e) if global.filter_script_strict => {
+                        return Err(SubconverterError::InvalidInput(format!(
+                            "Filter script failed: {}",
+                            e
+                        )));
+                    }
+                    Err(e) => warn!("Filter script failed, keeping the nodes as they are: {}", e),
+                }
+            } else if !_script.is_empty() {
                 info!("Applying filter script");
                 if _script.starts_with("path:") {
                     let import_script = file_get_async(&_script[5..], None)
@@ -725,19 +1311,107 @@ pub async fn subconverter(mut config: SubconverterConfig) -> Result<Subconverter
         }
     }
 
+    counts.script = before_script.saturating_sub(nodes.len());
+
+    // Locate the servers so emojis and countries need no telling remark
+    if !global.geoip_db.is_empty() {
+        if let Err(e) = locate_nodes(&mut nodes, &global.geoip_db).await {
+            warn!("GeoIP lookup skipped: {}", e);
+        }
+    }
+
     // Process nodes (rename, emoji, sort, etc.)
+    let before_preprocess = nodes.len();
     preprocess_nodes(&mut nodes, &mut config.extra)
         .await
         .map_err(|e| e.to_string())?;
+    counts.preprocess = before_preprocess.saturating_sub(nodes.len());
+
+    // Numbered in the sorted order, duplicates from the template are renamed
+    if !config.extra.index_remark.is_empty() {
+        index_remarks(
+            &mut nodes,
+            &config.extra.index_remark,
+            &global.autogroup_countries,
+        );
+        disambiguate_remarks(&mut nodes, &config.extra.duplicate_remark_format);
+    }
+
+    // Country groups come ahead of the configured ones
+    if config.autogroup {
+        let mut groups = auto_groups(
+            &nodes,
+            &global.autogroup_countries,
+            global.autogroup_min_nodes,
+            &global.autogroup_name,
+        );
+        info!("Generated {} groups by country", groups.len());
+        for group in groups.iter_mut() {
+            group.apply_test_defaults(
+                &global.group_test_url,
+                global.group_interval,
+                global.group_timeout,
+                global.group_tolerance,
+                global.group_lazy,
+            );
+        }
+        groups.append(&mut config.proxy_groups);
+        config.proxy_groups = groups;
+    }
+
+    // Nodes the target can not hold are only skipped while generating
+    let clash_r = config.target == SubconverterTarget::ClashR;
+    for node in &nodes {
+        let reason = if !config.target.supports(node.proxy_type) {
+            Some(format!(
+                "{} not supported by {}",
+                node.proxy_type.to_string(),
+                config.target.to_str()
+            ))
+        } else if config.target.is_clash() {
+            clash_skip_reason(node, clash_r, config.extra.filter_deprecated).map(str::to_string)
+        } else {
+            None
+        };
+        if let Some(reason) = reason {
+            counts.target += 1;
+            conversion_report.skip(reason);
+        }
+    }
+    if nodes.len() == counts.target
+        && config.extra.proxy_providers.is_empty()
+        && !config.allow_empty
+    {
+        warn!(
+            "No nodes left after filtering: {}",
+            counts.summary(&config.target)
+        );
+        return Err(SubconverterError::InvalidInput(format!(
+            "No valid nodes after filtering: {}",
+            counts.summary(&config.target)
+        )));
+    }
+
+    // Info nodes skip renaming and sorting so they stay readable at the top
+    if config.extra.append_info_nodes {
+        if let Some(sub_info) = &config.sub_info {
+            let mut info_nodes = get_info_nodes_from_sub_info(sub_info);
+            if !info_nodes.is_empty() {
+                info!("Prepending {} subscription info nodes", info_nodes.len());
+                info_nodes.append(&mut nodes);
+                nodes = info_nodes;
+            }
+        }
+    }
 
     // Pass subscription info if provided
     if let Some(sub_info) = &config.sub_info {
         response_headers.insert("Subscription-UserInfo".to_string(), sub_info.clone());
     }
 
-    // Refresh rulesets if needed
+    // Refresh rulesets if needed, node lists have no rules
     let mut ruleset_content = Vec::new();
-    if config.extra.enable_rule_generator {
+    if config.extra.enable_rule_generator && !config.extra.nodelist {
         // TODO: Check if we're using custom rulesets or global rulesets
         // if config.ruleset_configs == global.custom_rulesets {
         //     refresh_rulesets(&config.ruleset_configs, &mut
@@ -747,52 +1421,83 @@ pub async fn subconverter(mut config: SubconverterConfig) -> Result<Subconverter
 
         // Refresh rulesets with custom configuration
         info!("Refreshing rulesets with custom configuration");
-        refresh_rulesets(&config.ruleset_configs, &mut ruleset_content).await;
+        refresh_rulesets(&config.ruleset_configs, &mut ruleset_content, &global).await;
 
         // Prepend proxy direct ruleset if needed
         if global.prepend_proxy_direct_ruleset {
             prepend_proxy_direct_ruleset(&mut ruleset_content, &nodes);
         }
+
+        if config.debug {
+            for ruleset in &config.ruleset_configs {
+                conversion_report.add_ruleset(&ruleset.group, &ruleset.url, &ruleset_content);
+            }
+        }
     }
 
+    // Group names the target can not hold are renamed the same way in the
+    // groups and the rules
+    sanitize_group_names(
+        &config.target,
+        &mut config.proxy_groups,
+        &mut ruleset_content,
+    );
+
+    // Configs expanding large rulesets are sent as they are written, unless
+    // the whole text is needed for an upload
+    let stream = config.stream_output
+        && !config.upload
+        && rulesets_size(&ruleset_content) >= STREAM_THRESHOLD;
+    let mut output_stream = None;
+
     // Generate output based on target
     let output_content = match &config.target {
         SubconverterTarget::Clash => {
             info!("Generate target: Clash");
             let base = config
                 .rule_bases
-                .get_base_content(&SubconverterTarget::Clash, config.template_args.as_ref())
-                .await;
-            proxy_to_clash(
+                .get_base_content(
+                    &SubconverterTarget::Clash,
+                    config.template_args.as_ref(),
+                    &global,
+                )
+                .await?;
+            let output = proxy_to_clash_chunks(
                 &mut nodes,
                 &base,
                 &mut ruleset_content,
                 &config.proxy_groups,
                 false,
                 &mut config.extra,
-            )
+            )?;
+            buffer_or_stream(output, stream, &mut output_stream)
         }
         SubconverterTarget::ClashR => {
             info!("Generate target: ClashR");
             let base = config
                 .rule_bases
-                .get_base_content(&SubconverterTarget::ClashR, config.template_args.as_ref())
-                .await;
-            proxy_to_clash(
+                .get_base_content(
+                    &SubconverterTarget::ClashR,
+                    config.template_args.as_ref(),
+                    &global,
+                )
+                .await?;
+            let output = proxy_to_clash_chunks(
                 &mut nodes,
                 &base,
                 &mut ruleset_content,
                 &config.proxy_groups,
                 true,
                 &mut config.extra,
-            )
+            )?;
+            buffer_or_stream(output, stream, &mut output_stream)
         }
         SubconverterTarget::Surge(ver) => {
             info!("Generate target: Surge {}", ver);
             let base = config
                 .rule_bases
-                .get_base_content(&config.target, config.template_args.as_ref())
-                .await;
+                .get_base_content(&config.target, config.template_args.as_ref(), &global)
+                .await?;
             let output = proxy_to_surge(
                 &mut nodes,
                 &base,
@@ -801,39 +1506,21 @@ pub async fn subconverter(mut config: SubconverterConfig) -> Result<Subconverter
                 *ver,
                 &mut config.extra,
             )
-            .await;
-
-            // Add managed configuration header if needed
-            if !config.managed_config_prefix.is_empty() && config.extra.enable_rule_generator {
-                let managed_url = format!(
-                    "{}sub?target=surge&ver={}&url={}",
-                    config.managed_config_prefix,
-                    ver,
-                    // URL would need to be properly encoded
-                    config.urls.join("|")
-                );
+            .await?;
 
-                format!(
-                    "#!MANAGED-CONFIG {} interval={} strict={}\n\n{}",
-                    managed_url,
-                    config.update_interval,
-                    if config.update_strict {
-                        "true"
-                    } else {
-                        "false"
-                    },
-                    output
-                )
-            } else {
+            // Modules are updated from the module list, not the profile
+            if config.extra.surge_module {
                 output
+            } else {
+                with_managed_config_line(&config, output)
             }
         }
         SubconverterTarget::Surfboard => {
             info!("Generate target: Surfboard");
             let base = config
                 .rule_bases
-                .get_base_content(&config.target, config.template_args.as_ref())
-                .await;
+                .get_base_content(&config.target, config.template_args.as_ref(), &global)
+                .await?;
             let output = proxy_to_surge(
                 &mut nodes,
                 &base,
@@ -842,38 +1529,16 @@ pub async fn subconverter(mut config: SubconverterConfig) -> Result<Subconverter
                 -3, // Special version for Surfboard
                 &mut config.extra,
             )
-            .await;
-
-            // Add managed configuration header if needed
-            if !config.managed_config_prefix.is_empty() && config.extra.enable_rule_generator {
-                let managed_url = format!(
-                    "{}sub?target=surfboard&url={}",
-                    config.managed_config_prefix,
-                    // URL would need to be properly encoded
-                    config.urls.join("|")
-                );
+            .await?;
 
-                format!(
-                    "#!MANAGED-CONFIG {} interval={} strict={}\n\n{}",
-                    managed_url,
-                    config.update_interval,
-                    if config.update_strict {
-                        "true"
-                    } else {
-                        "false"
-                    },
-                    output
-                )
-            } else {
-                output
-            }
+            with_managed_config_line(&config, output)
         }
         SubconverterTarget::Mellow => {
             info!("Generate target: Mellow");
             let base = config
                 .rule_bases
-                .get_base_content(&config.target, config.template_args.as_ref())
-                .await;
+                .get_base_content(&config.target, config.template_args.as_ref(), &global)
+                .await?;
             proxy_to_mellow(
                 &mut nodes,
                 &base,
@@ -881,14 +1546,14 @@ pub async fn subconverter(mut config: SubconverterConfig) -> Result<Subconverter
                 &config.proxy_groups,
                 &mut config.extra,
             )
-            .await
+            .await?
         }
         SubconverterTarget::SSSub => {
             info!("Generate target: SS Subscription");
             let base = config
                 .rule_bases
-                .get_base_content(&config.target, config.template_args.as_ref())
-                .await;
+                .get_base_content(&config.target, config.template_args.as_ref(), &global)
+                .await?;
             proxy_to_ss_sub(&base, &mut nodes, &mut config.extra)
         }
         SubconverterTarget::SS => {
@@ -905,7 +1570,11 @@ pub async fn subconverter(mut config: SubconverterConfig) -> Result<Subconverter
         }
         SubconverterTarget::V2Ray => {
             info!("Generate target: V2Ray");
-            proxy_to_single(&mut nodes, ProxyUriTypes::VMESS, &mut config.extra)
+            proxy_to_single(
+                &mut nodes,
+                ProxyUriTypes::VMESS | ProxyUriTypes::VLESS,
+                &mut config.extra,
+            )
         }
         SubconverterTarget::Trojan => {
             info!("Generate target: Trojan");
@@ -919,8 +1588,8 @@ pub async fn subconverter(mut config: SubconverterConfig) -> Result<Subconverter
             info!("Generate target: Quantumult");
             let base = config
                 .rule_bases
-                .get_base_content(&config.target, config.template_args.as_ref())
-                .await;
+                .get_base_content(&config.target, config.template_args.as_ref(), &global)
+                .await?;
             proxy_to_quan(
                 &mut nodes,
                 &base,
@@ -928,14 +1597,14 @@ pub async fn subconverter(mut config: SubconverterConfig) -> Result<Subconverter
                 &config.proxy_groups,
                 &mut config.extra,
             )
-            .await
+            .await?
         }
         SubconverterTarget::QuantumultX => {
             info!("Generate target: Quantumult X");
             let base = config
                 .rule_bases
-                .get_base_content(&config.target, config.template_args.as_ref())
-                .await;
+                .get_base_content(&config.target, config.template_args.as_ref(), &global)
+                .await?;
             proxy_to_quanx(
                 &mut nodes,
                 &base,
@@ -943,14 +1612,14 @@ pub async fn subconverter(mut config: SubconverterConfig) -> Result<Subconverter
                 &config.proxy_groups,
                 &mut config.extra,
             )
-            .await
+            .await?
         }
         SubconverterTarget::Loon => {
             info!("Generate target: Loon");
             let base = config
                 .rule_bases
-                .get_base_content(&config.target, config.template_args.as_ref())
-                .await;
+                .get_base_content(&config.target, config.template_args.as_ref(), &global)
+                .await?;
             proxy_to_loon(
                 &mut nodes,
                 &base,
@@ -958,7 +1627,7 @@ pub async fn subconverter(mut config: SubconverterConfig) -> Result<Subconverter
                 &config.proxy_groups,
                 &mut config.extra,
             )
-            .await
+            .await?
         }
         SubconverterTarget::SSD => {
             info!("Generate target: SSD");
@@ -973,8 +1642,8 @@ pub async fn subconverter(mut config: SubconverterConfig) -> Result<Subconverter
             info!("Generate target: SingBox");
             let base = config
                 .rule_bases
-                .get_base_content(&config.target, config.template_args.as_ref())
-                .await;
+                .get_base_content(&config.target, config.template_args.as_ref(), &global)
+                .await?;
             proxy_to_singbox(
                 &mut nodes,
                 &base,
@@ -990,19 +1659,51 @@ pub async fn subconverter(mut config: SubconverterConfig) -> Result<Subconverter
             info!("Generate target: Auto (defaulting to Clash)");
             let base = config
                 .rule_bases
-                .get_base_content(&SubconverterTarget::Clash, config.template_args.as_ref())
-                .await;
-            proxy_to_clash(
+                .get_base_content(
+                    &SubconverterTarget::Clash,
+                    config.template_args.as_ref(),
+                    &global,
+                )
+                .await?;
+            let output = proxy_to_clash_chunks(
                 &mut nodes,
                 &base,
                 &mut ruleset_content,
                 &config.proxy_groups,
                 false,
                 &mut config.extra,
-            )
+            )?;
+            buffer_or_stream(output, stream, &mut output_stream)
         }
     };
 
+    let (output_content, output_stream) = if config.debug && accepts_comments(&config) {
+        prepend_report(
+            output_content,
+            output_stream,
+            conversion_report.comment_block(),
+        )
+    } else {
+        (output_content, output_stream)
+    };
+    if config.debug {
+        response_headers.insert(
+            "X-Conversion-Report".to_string(),
+            conversion_report.header_value(),
+        );
+    }
+
+    // Clash clients read the update interval and the profile page from headers
+    if config.target.is_clash() && config.update_interval > 0 {
+        response_headers.insert(
+            "profile-update-interval".to_string(),
+            (config.update_interval / 3600).max(1).to_string(),
+        );
+        if let Some(url) = managed_config_url(&config) {
+            response_headers.insert("profile-web-page-url".to_string(), url);
+        }
+    }
+
     // Set filename header if provided
     if let Some(filename) = &config.filename {
         response_headers.insert(
@@ -1044,28 +1745,49 @@ pub async fn subconverter(mut config: SubconverterConfig) -> Result<Subconverter
                                                                        * the main logic */
         };
 
-        // Use filename as path if provided, otherwise use the derived gist_name
-        let gist_path = config.filename.clone().unwrap_or_else(|| gist_name.clone());
-
-        info!(
-            "Attempting to upload result to Gist: name='{}', path='{}', write_manage_url={}",
-            gist_name, gist_path, write_manage_url
-        );
+        // Use upload_path or the filename as path if provided, an empty path
+        // is looked up by gist_name in gistconf.ini
+        let gist_path = config
+            .upload_path
+            .clone()
+            .filter(|path| !path.is_empty())
+            .or_else(|| config.filename.clone())
+            .unwrap_or_default();
+
+        let uploaded = if global.upload_kind.eq_ignore_ascii_case("webdav") {
+            // Files are named after the target unless the request names one
+            let path = if gist_path.is_empty() {
+                gist_name
+            } else {
+                gist_path
+            };
+            info!("Attempting to upload result to WebDAV: path='{}'", path);
+            upload_to_webdav(&global, &path, output_content.clone()).await
+        } else {
+            info!(
+                "Attempting to upload result to Gist: name='{}', path='{}', write_manage_url={}",
+                gist_name, gist_path, write_manage_url
+            );
+            crate::upload::gist::upload_gist(
+                &gist_name,
+                gist_path,
+                output_content.clone(), // Clone content for upload
+                write_manage_url,
+                &global,
+            )
+            .await
+        };
 
-        match crate::upload::gist::upload_gist(
-            &gist_name,
-            gist_path,
-            output_content.clone(), // Clone content for upload
-            write_manage_url,
-        )
-        .await
-        {
+        match uploaded {
             Ok(url) => {
-                info!("Successfully uploaded result to Gist: {}", url);
+                info!("Successfully uploaded result: {}", url);
+                response_headers.insert(UPLOAD_URL_HEADER.to_string(), url.clone());
                 upload_status = UploadStatus::Success(url);
             }
             Err(e) => {
-                warn!("Failed to upload result to Gist: {}", e);
+                // The config is still returned, only the upload failed
+                warn!("Failed to upload result: {}", e);
+                response_headers.insert(UPLOAD_ERROR_HEADER.to_string(), header_safe(&e));
                 upload_status = UploadStatus::Failure(e);
             }
         }
@@ -1074,11 +1796,141 @@ pub async fn subconverter(mut config: SubconverterConfig) -> Result<Subconverter
     info!("Conversion completed");
     Ok(SubconverterResult {
         content: output_content,
+        stream: output_stream,
         headers: response_headers,
         upload_status: upload_status,
     })
 }
 
+/// Upload `content` to `path` under the WebDAV collection of the settings
+#[cfg(not(target_arch = "wasm32"))]
+async fn upload_to_webdav(
+    global: &Settings,
+    path: &str,
+    content: String,
+) -> Result<String, String> {
+    let target = crate::upload::webdav::WebDavTarget {
+        url: global.upload_url.clone(),
+        username: global.upload_username.clone(),
+        password: global.upload_password.clone(),
+        proxy: global.proxy_config.clone(),
+    };
+    crate::upload::webdav::upload_webdav(&target, path, content).await
+}
+
+#[cfg(target_arch = "wasm32")]
+async fn upload_to_webdav(_: &Settings, _: &str, _: String) -> Result<String, String> {
+    Err("WebDAV upload is not available in WebAssembly builds".to_string())
+}
+
+/// Total size of the fetched rulesets, which make up most of a generated config
+fn rulesets_size(rulesets: &[RulesetContent]) -> usize {
+    rulesets
+        .iter()
+        .filter_map(|ruleset| {
+            let content = ruleset.rule_content.read().ok()?;
+            content.as_ref().map(String::len)
+        })
+        .sum()
+}
+
+/// Keep the chunks to be sent as they are produced, or join them right away
+fn buffer_or_stream(output: ConfigChunks, stream: bool, slot: &mut Option<ConfigChunks>) -> String {
+    if stream {
+        *slot = Some(output);
+        String::new()
+    } else {
+        output.into_string()
+    }
+}
+
+/// `message` with everything a header value can not hold replaced by spaces
+fn header_safe(message: &str) -> String {
+    message
+        .chars()
+        .map(|c| if c.is_ascii_graphic() { c } else { ' ' })
+        .collect()
+}
+
+/// Externally visible URL the config for `config` is served at, `None`
+/// without a managed config prefix or external base URL
+///
+/// The request path is used as is so every argument of the request is kept,
+/// conversions not started by a request get a `/sub` URL of their target and
+/// subscriptions.
+fn managed_config_url(config: &SubconverterConfig) -> Option<String> {
+    let url_prefix = config.extra.self_url_prefix();
+    if url_prefix.is_empty() {
+        return None;
+    }
+    let path = match &config.request_path {
+        Some(path) => path.clone(),
+        None => {
+            let target = match &config.target {
+                SubconverterTarget::Surge(ver) => format!("surge&ver={}", ver),
+                target => target.to_str(),
+            };
+            format!(
+                "/sub?target={}&url={}",
+                target,
+                url_encode(&config.urls.join("|"))
+            )
+        }
+    };
+    Some(format!(
+        "{}/{}",
+        url_prefix.trim_end_matches('/'),
+        path.trim_start_matches('/')
+    ))
+}
+
+/// `output` with the `#!MANAGED-CONFIG` line Surge and Surfboard update the
+/// config from, node lists are left alone
+fn with_managed_config_line(config: &SubconverterConfig, output: String) -> String {
+    match managed_config_url(config) {
+        Some(url) if !config.extra.nodelist => format!(
+            "#!MANAGED-CONFIG {} interval={} strict={}\n\n{}",
+            url, config.update_interval, config.update_strict, output
+        ),
+        _ => output,
+    }
+}
+
+/// Whether the config generated for `config` may carry `#` comments, JSON
+/// and base64 encoded outputs can not
+fn accepts_comments(config: &SubconverterConfig) -> bool {
+    match config.target {
+        SubconverterTarget::Quantumult => !config.extra.nodelist,
+        SubconverterTarget::Mixed | SubconverterTarget::SSSub | SubconverterTarget::SingBox => {
+            false
+        }
+        ref target => !target.is_simple(),
+    }
+}
+
+/// Put the report block ahead of the config, after the `#!` lines Surge and
+/// Loon only read at the very top
+fn prepend_report(
+    mut content: String,
+    stream: Option<ConfigChunks>,
+    block: String,
+) -> (String, Option<ConfigChunks>) {
+    if let Some(stream) = stream {
+        return (
+            content,
+            Some(ConfigChunks::new(std::iter::once(block).chain(stream))),
+        );
+    }
+    let mut offset = 0;
+    while content[offset..].starts_with("#!") {
+        offset = content[offset..]
+            .find('\n')
+            .map_or(content.len(), |end| offset + end + 1);
+    }
+    content.insert_str(offset, &block);
+    (content, None)
+}
+
 /// Preprocess nodes before conversion
 pub async fn preprocess_nodes(
     nodes: &mut Vec<Proxy>,
@@ -1117,10 +1969,9 @@ fn prepend_proxy_direct_ruleset(ruleset_content: &mut Vec<RulesetContent>, nodes
 
 impl RuleBases {
     /// Load rule base content from files or URLs
-    pub async fn load_content(&self) -> HashMap<SubconverterTarget, String> {
+    pub async fn load_content(&self, global: &Settings) -> HashMap<SubconverterTarget, String> {
         let mut base_content = HashMap::new();
 
-        let global = Settings::current();
         let proxy_config = parse_proxy(&global.proxy_config);
 
         // Helper function to load content from file or URL
@@ -1131,9 +1982,11 @@ impl RuleBases {
 
             // Check if path is a URL
             if path.starts_with("http://") || path.starts_with("https://") {
-                match web_get_async(path, &proxy_config, None).await {
-                    Ok(response) => {
-                        let content = response.body;
+                let max_redirects = u8::try_from(global.max_redirects).unwrap_or(u8::MAX);
+                match fetch_remote_base(path, &proxy_config, global.cache_config, max_redirects)
+                    .await
+                {
+                    Ok(content) => {
                         if content.is_empty() {
                             debug!("Empty content from URL: {}", path);
                             return None;
@@ -1211,37 +2064,37 @@ impl RuleBases {
     }
 
     /// Get base content for a specific target
+    ///
+    /// A base at a web address which can not be fetched fails the request,
+    /// unless a copy was cached before.
     pub async fn get_base_content(
         &self,
         target: &SubconverterTarget,
         template_args: Option<&TemplateArgs>,
-    ) -> String {
-        let global = Settings::current();
+        global: &Settings,
+    ) -> Result<String, SubconverterError> {
         let proxy_config = parse_proxy(&global.proxy_config);
+        let cache_config = global.cache_config;
+        let max_redirects = u8::try_from(global.max_redirects).unwrap_or(u8::MAX);
 
         // Helper function to load content from file or URL
-        let load_content = async move |path: &str| -> String {
+        let load_content = async move |path: &str| -> Result<String, SubconverterError> {
             if path.is_empty() {
-                return String::new();
+                return Ok(String::new());
             }
 
             // Check if path is a URL
             if path.starts_with("http://") || path.starts_with("https://") {
-                match web_get_async(path, &proxy_config, None).await {
-                    Ok(response) => {
-                        let content = response.body;
-                        if content.is_empty() {
-                            debug!("Empty content from URL: {}", path);
-                            return String::new();
-                        }
-                        debug!("Loaded rule base from URL: {}", path);
-                        content
-                    }
-                    Err(e) => {
-                        warn!("Failed to load rule base from URL {}: {}", path, e);
-                        String::new()
-                    }
+                let content = fetch_remote_base(path, &proxy_config, cache_config, max_redirects)
+                    .await
+                    .map_err(|e| SubconverterError::UpstreamFetch {
+                        url: path.to_string(),
+                        source: format!("failed to fetch base template {}: {}", path, e).into(),
+                    })?;
+                if content.is_empty() {
+                    debug!("Empty content from URL: {}", path);
                 }
+                Ok(content)
             } else {
                 // Treat as file path, then try base/ fallback.
                 let candidate_paths = [path.to_string(), format!("base/{path}")];
@@ -1249,7 +2102,7 @@ impl RuleBases {
                     match file_get_async(&candidate, None).await {
                         Ok(content) => {
                             debug!("Loaded rule base from file: {}", candidate);
-                            return content;
+                            return Ok(content);
                         }
                         Err(e) => {
                             debug!("Failed to load rule base from file {}: {}", candidate, e);
@@ -1260,46 +2113,80 @@ impl RuleBases {
                     "Failed to load rule base from file {} and base fallback",
                     path
                 );
-                String::new()
+                Ok(String::new())
             }
         };
 
         // Get path based on target
-        let path = match target {
-            SubconverterTarget::Clash | SubconverterTarget::ClashR => &self.clash_rule_base,
-            SubconverterTarget::Surge(_) => &self.surge_rule_base,
-            SubconverterTarget::Surfboard => &self.surfboard_rule_base,
-            SubconverterTarget::Mellow => &self.mellow_rule_base,
-            SubconverterTarget::Quantumult => &self.quan_rule_base,
-            SubconverterTarget::QuantumultX => &self.quanx_rule_base,
-            SubconverterTarget::Loon => &self.loon_rule_base,
-            SubconverterTarget::SSSub => &self.sssub_rule_base,
-            SubconverterTarget::SingBox => &self.singbox_rule_base,
-            _ => return String::new(),
+        let Some(path) = self.base(target) else {
+            return Ok(String::new());
         };
 
         // Load the base content
-        let content = load_content(path).await;
+        let content = load_content(path).await?;
         if content.is_empty() {
-            return content;
+            return Ok(content);
         }
 
         // Apply template if template args are provided
         if let Some(args) = template_args {
             // Using template rendering
             info!("Applying template to rule base for {}", target.to_str());
-            match crate::template::render_template(&content, args, &global.template_path) {
+            let format = if target.is_clash() {
+                TemplateFormat::Yaml
+            } else {
+                TemplateFormat::Text
+            };
+            let mut args = args.clone();
+            if args.managed_prefix.is_empty() {
+                args.managed_prefix = global.managed_config_url();
+            }
+            match render_template_as(&content, &args, &global.template_path, format) {
                 Ok(rendered) => {
                     debug!("Successfully rendered template for rule base");
-                    rendered
-                }
-                Err(e) => {
-                    warn!("Failed to render template for rule base: {}", e);
-                    content // Return original content if rendering fails
+                    Ok(rendered)
                 }
+                Err(e) => Err(SubconverterError::InvalidInput(format!(
+                    "Invalid base template: {}",
+                    template_error_message(&*e)
+                ))),
             }
         } else {
-            content
+            Ok(content)
+        }
+    }
+
+    /// The rule base of `target`, `None` for targets without one
+    pub fn base(&self, target: &SubconverterTarget) -> Option<&String> {
+        match target {
+            SubconverterTarget::Clash | SubconverterTarget::ClashR => Some(&self.clash_rule_base),
+            SubconverterTarget::Surge(_) => Some(&self.surge_rule_base),
+            SubconverterTarget::Surfboard => Some(&self.surfboard_rule_base),
+            SubconverterTarget::Mellow => Some(&self.mellow_rule_base),
+            SubconverterTarget::Quantumult => Some(&self.quan_rule_base),
+            SubconverterTarget::QuantumultX => Some(&self.quanx_rule_base),
+            SubconverterTarget::Loon => Some(&self.loon_rule_base),
+            SubconverterTarget::SSSub => Some(&self.sssub_rule_base),
+            SubconverterTarget::SingBox => Some(&self.singbox_rule_base),
+            _ => None,
+        }
+    }
+
+    /// The rule base of `target` to change, `None` for targets without one
+    pub fn base_mut(&mut self, target: &SubconverterTarget) -> Option<&mut String> {
+        match target {
+            SubconverterTarget::Clash | SubconverterTarget::ClashR => {
+                Some(&mut self.clash_rule_base)
+            }
+            SubconverterTarget::Surge(_) => Some(&mut self.surge_rule_base),
+            SubconverterTarget::Surfboard => Some(&mut self.surfboard_rule_base),
+            SubconverterTarget::Mellow => Some(&mut self.mellow_rule_base),
+            SubconverterTarget::Quantumult => Some(&mut self.quan_rule_base),
+            SubconverterTarget::QuantumultX => Some(&mut self.quanx_rule_base),
+            SubconverterTarget::Loon => Some(&mut self.loon_rule_base),
+            SubconverterTarget::SSSub => Some(&mut self.sssub_rule_base),
+            SubconverterTarget::SingBox => Some(&mut self.singbox_rule_base),
+            _ => None,
         }
     }
 
@@ -1382,3 +2269,394 @@ impl RuleBases {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::RegexMatchConfig;
+    use crate::models::{ProxyGroupConfig, ProxyGroupType};
+    use crate::parser::explodes::{explode_loon, explode_quanx, explode_surge};
+    use crate::utils::ini_reader::IniReader;
+    use std::io::Write;
+
+    fn links_file(links: &[&str]) -> tempfile::NamedTempFile {
+        let mut file = tempfile::NamedTempFile::new().unwrap();
+        writeln!(file, "{}", links.join("\n")).unwrap();
+        file
+    }
+
+    #[test]
+    fn test_error_status_codes() {
+        let fetch = SubconverterError::UpstreamFetch {
+            url: "https://example.com/rules.list".to_string(),
+            source: "timed out".into(),
+        };
+        assert_eq!(fetch.status_code(), 502);
+        assert_eq!(fetch.message(), "timed out");
+        assert_eq!(SubconverterError::NotFound("x".into()).status_code(), 404);
+        assert_eq!(SubconverterError::Unauthorized("x".into()).status_code(), 403);
+        let internal = SubconverterError::Internal("disk full".into());
+        assert_eq!(internal.status_code(), 500);
+        assert_eq!(internal.message(), "Internal server error: disk full");
+    }
+
+    #[tokio::test]
+    async fn test_insert_nodes_are_prepended_and_not_filtered() {
+        let main = links_file(&[
+            "trojan://secret@main.example.com:443#Main1",
+            "trojan://secret@main.example.com:8443#剩余流量10G",
+        ]);
+        let insert = links_file(&["trojan://secret@self.example.com:443#剩余流量Self"]);
+
+        let mut builder = SubconverterConfigBuilder::new();
+        builder
+            .target(SubconverterTarget::Clash)
+            .nodelist(true)
+            .authorized(true)
+            .add_url(main.path().to_str().unwrap())
+            .add_insert_url(insert.path().to_str().unwrap())
+            .prepend_insert(true)
+            .add_exclude_remark("剩余流量");
+        let result = subconverter(builder.build().unwrap()).await.unwrap();
+
+        let inserted = result.content.find("剩余流量Self").unwrap();
+        let main = result.content.find("Main1").unwrap();
+        assert!(inserted < main);
+        assert!(!result.content.contains("剩余流量10G"));
+    }
+
+    #[tokio::test]
+    async fn test_subscription_userinfo_header_does_not_need_info_nodes() {
+        let nodes = links_file(&["trojan://secret@self.example.com:443#Self"]);
+        let sub_info = "upload=0; download=1024; total=2048".to_string();
+
+        let mut builder = SubconverterConfigBuilder::new();
+        builder
+            .target(SubconverterTarget::Clash)
+            .nodelist(true)
+            .authorized(true)
+            .add_url(nodes.path().to_str().unwrap())
+            .sub_info(Some(sub_info.clone()));
+        let result = subconverter(builder.build().unwrap()).await.unwrap();
+
+        assert_eq!(result.headers.get("Subscription-UserInfo"), Some(&sub_info));
+        assert!(!result.content.contains("剩余流量"));
+    }
+
+    #[tokio::test]
+    async fn test_clash_providers_reference_subscription_urls() {
+        let nodes = links_file(&["trojan://secret@self.example.com:443#Self"]);
+        let mut group = ProxyGroupConfig::new("Proxy".to_string(), ProxyGroupType::Select);
+        group.proxies = vec![".*".to_string(), "[]DIRECT".to_string()];
+
+        let mut builder = SubconverterConfigBuilder::new();
+        builder
+            .target(SubconverterTarget::Clash)
+            .authorized(true)
+            .clash_providers(true)
+            .managed_config_prefix("http://127.0.0.1:25500".to_string())
+            .add_url("https://sub.example.com/api?token=1")
+            .add_url(nodes.path().to_str().unwrap())
+            .proxy_groups(vec![group]);
+        let result = subconverter(builder.build().unwrap()).await.unwrap();
+
+        let yaml: serde_yaml::Value = serde_yaml::from_str(&result.content).unwrap();
+        let provider = &yaml["proxy-providers"]["provider_1"];
+        assert_eq!(
+            provider["url"].as_str(),
+            Some(
+                "http://127.0.0.1:25500/sub?target=clash&list=true&url=https%3A%2F%2Fsub.example.com%2Fapi%3Ftoken%3D1"
+            )
+        );
+        assert_eq!(provider["type"].as_str(), Some("http"));
+
+        let group = &yaml["proxy-groups"][0];
+        assert_eq!(group["use"][0].as_str(), Some("provider_1"));
+        assert_eq!(group["proxies"][0].as_str(), Some("DIRECT"));
+        assert_eq!(group["proxies"][1].as_str(), Some("Self"));
+        assert_eq!(yaml["proxies"].as_sequence().map(|s| s.len()), Some(1));
+    }
+
+    #[tokio::test]
+    async fn test_autogroup_adds_country_groups_ahead_of_custom_ones() {
+        let nodes = links_file(&[
+            "trojan://secret@hk1.example.com:443#HK 01",
+            "trojan://secret@hk2.example.com:443#香港 02",
+            "trojan://secret@jp1.example.com:443#JP 01",
+            "trojan://secret@relay.example.com:443#Relay",
+        ]);
+        let mut group = ProxyGroupConfig::new("Final".to_string(), ProxyGroupType::Select);
+        group.proxies = vec!["[]Proxy".to_string(), "[]DIRECT".to_string()];
+
+        let mut builder = SubconverterConfigBuilder::new();
+        builder
+            .target(SubconverterTarget::Clash)
+            .authorized(true)
+            .autogroup(true)
+            .add_url(nodes.path().to_str().unwrap())
+            .proxy_groups(vec![group]);
+        let result = subconverter(builder.build().unwrap()).await.unwrap();
+
+        let yaml: serde_yaml::Value = serde_yaml::from_str(&result.content).unwrap();
+        let groups = yaml["proxy-groups"].as_sequence().unwrap();
+        let names: Vec<&str> = groups.iter().filter_map(|g| g["name"].as_str()).collect();
+        assert_eq!(names, vec!["Proxy", "🇭🇰 Hong Kong", "Other", "Final"]);
+        assert_eq!(groups[1]["type"].as_str(), Some("url-test"));
+        assert!(groups[1]["url"].as_str().is_some());
+        assert_eq!(
+            groups[1]["proxies"],
+            serde_yaml::from_str::<serde_yaml::Value>("[HK 01, 香港 02]").unwrap()
+        );
+        assert_eq!(
+            groups[2]["proxies"],
+            serde_yaml::from_str::<serde_yaml::Value>("[JP 01, Relay]").unwrap()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_external_base_url_stands_in_for_managed_config_prefix() {
+        let mut builder = SubconverterConfigBuilder::new();
+        builder
+            .target(SubconverterTarget::Clash)
+            .authorized(true)
+            .allow_empty(true)
+            .clash_providers(true)
+            .external_base_url("https://sub.example.com/api".to_string())
+            .add_url("https://sub.example.com/api?token=1");
+        let result = subconverter(builder.build().unwrap()).await.unwrap();
+
+        let yaml: serde_yaml::Value = serde_yaml::from_str(&result.content).unwrap();
+        assert_eq!(
+            yaml["proxy-providers"]["provider_1"]["url"].as_str(),
+            Some(
+                "https://sub.example.com/api/sub?target=clash&list=true&url=https%3A%2F%2Fsub.example.com%2Fapi%3Ftoken%3D1"
+            )
+        );
+    }
+
+    #[tokio::test]
+    async fn test_quanx_sections_are_merged_into_the_base() {
+        let nodes = links_file(&["trojan://secret@self.example.com:443#Self"]);
+        let mut base = tempfile::NamedTempFile::new().unwrap();
+        writeln!(
+            base,
+            "[general]\nnetwork_check_url=http://www.gstatic.com/generate_204\n\n\
+             [rewrite_remote]\nhttps://rewrite.example.com/a.conf, tag=A, enabled=true"
+        )
+        .unwrap();
+        let mut group = ProxyGroupConfig::new("Proxy".to_string(), ProxyGroupType::Select);
+        group.proxies = vec!["(HK|JP)".to_string(), "[]direct".to_string()];
+
+        let mut builder = SubconverterConfigBuilder::new();
+        builder
+            .target(SubconverterTarget::QuantumultX)
+            .authorized(true)
+            .quanx_rule_base(base.path().to_str().unwrap())
+            .quanx_server_remote(true)
+            .managed_config_prefix("http://127.0.0.1:25500".to_string())
+            .quanx_rewrite_remote(vec![
+                "https://rewrite.example.com/a.conf, tag=A again, enabled=true".to_string(),
+                "/rewrite/b.conf, tag=B, enabled=true".to_string(),
+            ])
+            .quanx_task_local(vec![
+                "0 9 * * * https://task.example.com/sign.js, tag=Sign, enabled=true".to_string(),
+            ])
+            .add_url("https://sub.example.com/api?token=1")
+            .add_url(nodes.path().to_str().unwrap())
+            .proxy_groups(vec![group]);
+        let result = subconverter(builder.build().unwrap()).await.unwrap();
+
+        let mut ini = IniReader::new();
+        ini.store_any_line = true;
+        for section in [
+            "rewrite_remote",
+            "task_local",
+            "server_remote",
+            "server_local",
+        ] {
+            ini.add_direct_save_section(section);
+        }
+        ini.parse(&result.content).unwrap();
+        let lines = |section| ini.get_all(section, "{NONAME}").unwrap_or_default();
+
+        assert_eq!(
+            lines("rewrite_remote"),
+            [
+                "https://rewrite.example.com/a.conf, tag=A, enabled=true",
+                "http://127.0.0.1:25500/rewrite/b.conf, tag=B, enabled=true",
+            ]
+        );
+        assert_eq!(
+            lines("task_local"),
+            ["0 9 * * * https://task.example.com/sign.js, tag=Sign, enabled=true"]
+        );
+        let server_remote = lines("server_remote");
+        assert_eq!(server_remote.len(), 1);
+        assert!(server_remote[0].starts_with(
+            "http://127.0.0.1:25500/sub?target=quanx&list=true&url=https%3A%2F%2Fsub.example.com%2Fapi%3Ftoken%3D1, tag=provider_1"
+        ));
+        assert_eq!(lines("server_local").len(), 1);
+        assert_eq!(
+            ini.get_all("policy", "static").unwrap(),
+            ["Proxy, direct, server-tag-regex=(HK|JP), resource-tag-regex=^(provider_1)$"]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_node_lists_parse_back_into_the_same_nodes() {
+        let nodes = links_file(&[
+            "trojan://secret@trojan.example.com:443#HK%20Trojan",
+            "ss://YWVzLTEyOC1nY206cGFzcw@ss.example.com:8388#JP%20SS",
+        ]);
+        let explodes: [(SubconverterTarget, fn(&str, &mut Vec<Proxy>) -> bool); 3] = [
+            (SubconverterTarget::Surge(4), explode_surge),
+            (SubconverterTarget::QuantumultX, explode_quanx),
+            (SubconverterTarget::Loon, explode_loon),
+        ];
+        for (target, explode) in explodes {
+            let mut builder = SubconverterConfigBuilder::new();
+            builder
+                .target(target.clone())
+                .nodelist(true)
+                .authorized(true)
+                .append_proxy_type(true)
+                .add_emoji(true)
+                .rename_array(vec![RegexMatchConfig::new(
+                    "^HK ".to_string(),
+                    "Hong Kong ".to_string(),
+                    String::new(),
+                )])
+                .emoji_array(vec![RegexMatchConfig::new(
+                    "Hong Kong".to_string(),
+                    "🇭🇰".to_string(),
+                    String::new(),
+                )])
+                .add_url(nodes.path().to_str().unwrap());
+            let result = subconverter(builder.build().unwrap()).await.unwrap();
+            // Only the node lines, no sections
+            assert!(
+                !result
+                    .content
+                    .lines()
+                    .any(|line| line.starts_with('[') && line.ends_with(']')),
+                "{:?}: {}",
+                target,
+                result.content
+            );
+
+            let mut parsed = Vec::new();
+            assert!(explode(&result.content, &mut parsed), "{:?}", target);
+            let remarks: Vec<&str> = parsed.iter().map(|node| node.remark.as_str()).collect();
+            assert_eq!(
+                remarks,
+                ["[Trojan] 🇭🇰 Hong Kong Trojan", "[SS] JP SS"],
+                "{:?}",
+                target
+            );
+            assert_eq!(parsed[0].hostname, "trojan.example.com");
+            assert_eq!(parsed[1].port, 8388);
+            assert_eq!(parsed[1].encrypt_method.as_deref(), Some("aes-128-gcm"));
+        }
+    }
+
+    #[tokio::test]
+    async fn test_invalid_clash_base_is_an_input_error() {
+        let nodes = links_file(&["trojan://secret@self.example.com:443#Self"]);
+        let mut base = tempfile::NamedTempFile::new().unwrap();
+        writeln!(base, "port: 7890\nproxies: [unclosed").unwrap();
+
+        let mut builder = SubconverterConfigBuilder::new();
+        builder
+            .target(SubconverterTarget::Clash)
+            .authorized(true)
+            .clash_rule_base(base.path().to_str().unwrap())
+            .add_url(nodes.path().to_str().unwrap());
+        let error = subconverter(builder.build().unwrap()).await.unwrap_err();
+
+        assert_eq!(error.status_code(), 400);
+        assert!(error.to_string().contains("line"));
+    }
+
+    #[tokio::test]
+    async fn test_invalid_surge_base_is_an_input_error() {
+        let nodes = links_file(&["trojan://secret@self.example.com:443#Self"]);
+        let base = tempfile::NamedTempFile::new().unwrap();
+
+        let mut builder = SubconverterConfigBuilder::new();
+        builder
+            .target(SubconverterTarget::Surge(4))
+            .authorized(true)
+            .surge_rule_base(base.path().to_str().unwrap())
+            .add_url(nodes.path().to_str().unwrap());
+        let error = subconverter(builder.build().unwrap()).await.unwrap_err();
+
+        assert_eq!(error.status_code(), 400);
+        assert_eq!(error.to_string(), "Invalid base config: Empty document");
+    }
+
+    #[tokio::test]
+    async fn test_excluding_every_node_is_an_input_error() {
+        let nodes = links_file(&["trojan://secret@self.example.com:443#Self"]);
+
+        let mut builder = SubconverterConfigBuilder::new();
+        builder
+            .target(SubconverterTarget::Clash)
+            .nodelist(true)
+            .authorized(true)
+            .add_url(nodes.path().to_str().unwrap())
+            .add_exclude_remark("Self");
+        let error = subconverter(builder.build().unwrap()).await.unwrap_err();
+
+        assert!(matches!(error, SubconverterError::InvalidInput(_)));
+        assert_eq!(error.status_code(), 400);
+    }
+
+    #[tokio::test]
+    async fn test_managed_config_line_and_update_headers() {
+        let nodes = links_file(&["trojan://secret@self.example.com:443#Self"]);
+        let mut base = tempfile::NamedTempFile::new().unwrap();
+        writeln!(base, "[General]\nloglevel = notify").unwrap();
+
+        let mut builder = SubconverterConfigBuilder::new();
+        builder
+            .target(SubconverterTarget::Surge(4))
+            .authorized(true)
+            .surge_rule_base(base.path().to_str().unwrap())
+            .add_url(nodes.path().to_str().unwrap())
+            .managed_config_prefix("https://sub.example.com/api/".to_string())
+            .request_path(Some("/sub?target=surge&ver=4&url=a%7Cb".to_string()))
+            .update_interval(43200)
+            .update_strict(true);
+        let result = subconverter(builder.build().unwrap()).await.unwrap();
+        assert_eq!(
+            result.content.lines().next(),
+            Some(
+                "#!MANAGED-CONFIG https://sub.example.com/api/sub?target=surge&ver=4&url=a%7Cb interval=43200 strict=true"
+            )
+        );
+
+        let mut builder = SubconverterConfigBuilder::new();
+        builder
+            .target(SubconverterTarget::Clash)
+            .nodelist(true)
+            .authorized(true)
+            .add_url(nodes.path().to_str().unwrap())
+            .managed_config_prefix("https://sub.example.com".to_string())
+            .update_interval(43200);
+        let result = subconverter(builder.build().unwrap()).await.unwrap();
+        assert_eq!(
+            result
+                .headers
+                .get("profile-update-interval")
+                .map(String::as_str),
+            Some("12")
+        );
+        assert_eq!(
+            result.headers.get("profile-web-page-url"),
+            Some(&format!(
+                "https://sub.example.com/sub?target=clash&url={}",
+                url_encode(nodes.path().to_str().unwrap())
+            ))
+        );
+    }
+}
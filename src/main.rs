@@ -1,11 +1,14 @@
+use std::io::{Read, Write};
 use std::sync::Arc;
 
-use actix_web::{test, web, App, HttpServer};
-use clap::Parser;
+use actix_web::dev::ServerHandle;
+use actix_web::{test, App, HttpServer};
+use clap::{Args as ClapArgs, Parser, Subcommand};
 use env_logger::Env;
 use log::{error, info};
 use std::fs;
 
+use libsubconverter::api::{generate_artifacts, sub_process, SubconverterQuery};
 use libsubconverter::settings::settings::settings_struct::init_settings;
 use libsubconverter::{web_handlers, Settings};
 
@@ -32,6 +35,61 @@ struct Args {
     /// Output file path for subscription conversion (must be used with --url)
     #[arg(short, long, value_name = "OUTPUT_FILE")]
     output: Option<String>,
+
+    /// Render every artifact of the generator config to its path and exit
+    #[arg(short, long)]
+    generate: bool,
+
+    /// Generator config listing the artifacts for -g
+    #[arg(long, value_name = "FILE", default_value = "generate.ini")]
+    generate_config: String,
+
+    /// Only render the named artifacts with -g, can be repeated or comma separated
+    #[arg(long, value_name = "NAME", value_delimiter = ',')]
+    artifact: Vec<String>,
+
+    #[command(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Convert subscriptions once and exit, without starting the server
+    Convert(ConvertArgs),
+}
+
+/// Flags of `convert`, everything `/sub` accepts can be given with --param
+#[derive(ClapArgs, Debug)]
+struct ConvertArgs {
+    /// Target format (e.g., clash, surge, singbox)
+    #[arg(short, long, value_name = "TARGET")]
+    target: String,
+
+    /// Subscription URL, link or local file, can be repeated. `-` reads the
+    /// subscription content from stdin
+    #[arg(short, long, value_name = "URL")]
+    url: Vec<String>,
+
+    /// File listing subscription URLs one per line, `-` reads them from stdin
+    #[arg(long, value_name = "FILE")]
+    url_file: Option<String>,
+
+    /// External configuration file or URL
+    #[arg(long, value_name = "CONFIG")]
+    config: Option<String>,
+
+    /// Any other /sub query parameter, can be repeated (e.g., -P emoji=false)
+    #[arg(short = 'P', long = "param", value_name = "KEY=VALUE")]
+    params: Vec<String>,
+
+    /// Output file path, stdout when omitted or `-`
+    #[arg(short, long, value_name = "OUTPUT_FILE")]
+    output: Option<String>,
+
+    /// Check the generated config and fail with a JSON report when the client
+    /// would refuse it, warnings are appended as comments
+    #[arg(long)]
+    validate: bool,
 }
 
 #[actix_web::main]
@@ -53,6 +111,38 @@ async fn main() -> std::io::Result<()> {
         .await
         .map_err(|e| std::io::Error::new(std::io::ErrorKind::Other, e.to_string()))?;
 
+    if let Some(Command::Convert(convert_args)) = args.command {
+        let code = match run_convert(
+            convert_args,
+            &mut std::io::stdin().lock(),
+            &mut std::io::stdout().lock(),
+        )
+        .await
+        {
+            Ok(()) => 0,
+            Err(e) => {
+                eprintln!("{}", e);
+                1
+            }
+        };
+        std::process::exit(code);
+    }
+
+    if args.generate {
+        let mut artifacts = args.artifact;
+        if artifacts.is_empty() {
+            artifacts = Settings::current()
+                .generate_profiles
+                .split(',')
+                .map(str::trim)
+                .filter(|name| !name.is_empty())
+                .map(str::to_string)
+                .collect();
+        }
+        let code = run_generate(&args.generate_config, &artifacts).await;
+        std::process::exit(code);
+    }
+
     // Check if URL is provided for direct processing
     if let Some(url) = args.url {
         let output_file = args
@@ -67,8 +157,18 @@ async fn main() -> std::io::Result<()> {
         // Create a test app with the same configuration as the web app
         let app = test::init_service(App::new().configure(web_handlers::config)).await;
 
-        // Create a test request with the correct URI
-        let req = test::TestRequest::get().uri(&url).to_request();
+        // Create a test request with the correct URI, routes live under the
+        // configured base path
+        let api_base_path = Settings::current().api_base_path.clone();
+        let under_base_path = url
+            .strip_prefix(&api_base_path)
+            .is_some_and(|rest| rest.is_empty() || rest.starts_with(['/', '?']));
+        let uri = if under_base_path {
+            url.clone()
+        } else {
+            format!("{}{}", api_base_path, url)
+        };
+        let req = test::TestRequest::get().uri(&uri).to_request();
 
         // Execute the request
         let resp = test::call_service(&app, req).await;
@@ -89,8 +189,8 @@ async fn main() -> std::io::Result<()> {
         Ok(()) // Exit after processing the URL
     } else {
         // Proceed with starting the web server
-        // Ensure we have a valid listen address
-        let listen_address = {
+        // Ensure we have at least one valid listener
+        let (listeners, unix_socket_mode) = {
             // Get a mutable reference to the current settings
             let mut settings_guard = Settings::current_mut();
             let settings = Arc::make_mut(&mut *settings_guard);
@@ -104,34 +204,425 @@ async fn main() -> std::io::Result<()> {
             }
             if settings.listen_address.trim().is_empty() {
                 error!("Empty listen_address in settings, defaulting to 127.0.0.1");
-                format!("127.0.0.1:{}", settings.listen_port)
-            } else {
-                // Check if the address contains a port
-                if settings.listen_address.contains(':') {
-                    // Already has a port, use as is
-                    settings.listen_address.clone()
-                } else {
-                    // No port specified, use the one from settings
-                    format!("{}:{}", settings.listen_address, settings.listen_port)
-                }
+                settings.listen_address = "127.0.0.1".to_string();
             }
+            (
+                parse_listeners(&settings.listen_address, settings.listen_port),
+                settings.unix_socket_mode,
+            )
         };
 
         let max_concur_threads = Settings::current().max_concur_threads;
+        let shutdown_timeout = Settings::current().shutdown_timeout;
 
-        info!("Subconverter starting on {}", listen_address);
+        // Fail fast on an unusable certificate instead of serving plain HTTP
+        let tls_config = {
+            let settings = Settings::current();
+            if settings.tls_enabled() {
+                match web_handlers::tls::server_config(&settings.tls_cert, &settings.tls_key) {
+                    Ok(config) => Some(config),
+                    Err(e) => {
+                        error!("Failed to load TLS configuration: {}", e);
+                        std::process::exit(1);
+                    }
+                }
+            } else {
+                None
+            }
+        };
+
+        let api_base_path = Settings::current().api_base_path.clone();
+        if !api_base_path.is_empty() {
+            info!("Serving API under {}", api_base_path);
+        }
 
         // Start web server
-        HttpServer::new(move || {
+        let mut server = HttpServer::new(move || {
             App::new()
-                // Register web handlers
+                // Register web handlers, including the health check
                 .configure(web_handlers::config)
-                // For health check
-                .route("/", web::get().to(|| async { "Subconverter is running!" }))
         })
-        .bind(listen_address)?
         .workers(max_concur_threads as usize)
-        .run()
-        .await
+        // Signals are handled below so SIGHUP can reload instead of stopping
+        .disable_signals()
+        .shutdown_timeout(shutdown_timeout as u64);
+
+        for listener in listeners {
+            server = match listener {
+                Listener::Tcp(address) => match &tls_config {
+                    Some(config) => {
+                        info!("Subconverter starting on HTTPS {}", address);
+                        server.bind_rustls(address, config.clone())?
+                    }
+                    None => {
+                        info!("Subconverter starting on TCP {}", address);
+                        server.bind(address)?
+                    }
+                },
+                #[cfg(unix)]
+                Listener::Unix(path) => {
+                    use std::os::unix::fs::PermissionsExt;
+
+                    remove_stale_unix_socket(&path)?;
+                    let server = server.bind_uds(&path)?;
+                    fs::set_permissions(&path, fs::Permissions::from_mode(unix_socket_mode))?;
+                    info!(
+                        "Subconverter starting on unix socket {} (mode {:o})",
+                        path, unix_socket_mode
+                    );
+                    server
+                }
+                #[cfg(not(unix))]
+                Listener::Unix(path) => {
+                    return Err(std::io::Error::new(
+                        std::io::ErrorKind::Unsupported,
+                        format!(
+                            "unix socket listener {} is not supported on this platform",
+                            path
+                        ),
+                    ));
+                }
+            };
+        }
+
+        let server = server.run();
+        actix_web::rt::spawn(handle_signals(server.handle(), shutdown_timeout));
+
+        // Kept alive until the server stops, dropping it ends the watch
+        let _watcher = if Settings::current().watch_config {
+            match web_handlers::watch::watch_config_files() {
+                Ok(watcher) => Some(watcher),
+                Err(e) => {
+                    error!("Failed to watch configuration files: {}", e);
+                    None
+                }
+            }
+        } else {
+            None
+        };
+        server.await
+    }
+}
+
+/// Run a single conversion for `convert`, the error is the message the HTTP
+/// API would have answered with
+async fn run_convert(
+    args: ConvertArgs,
+    stdin: &mut dyn Read,
+    stdout: &mut dyn Write,
+) -> Result<(), String> {
+    let mut urls = Vec::new();
+    let mut stdin_file = None;
+    let url_file = args.url_file.as_deref();
+    for url in args.url {
+        if url != "-" {
+            urls.push(url);
+            continue;
+        }
+        if stdin_file.is_some() || url_file == Some("-") {
+            return Err("stdin can only be read once".to_string());
+        }
+        // Subscription content is read from local files, so stdin goes
+        // through one as well, removed again when it is dropped
+        let mut file = tempfile::NamedTempFile::new()
+            .map_err(|e| format!("failed to create temporary file: {}", e))?;
+        std::io::copy(stdin, &mut file).map_err(|e| format!("failed to read stdin: {}", e))?;
+        urls.push(file.path().to_string_lossy().into_owned());
+        stdin_file = Some(file);
+    }
+    if let Some(file) = url_file {
+        let list = if file == "-" {
+            std::io::read_to_string(stdin)
+        } else {
+            fs::read_to_string(file)
+        }
+        .map_err(|e| format!("failed to read {}: {}", file, e))?;
+        urls.extend(
+            list.lines()
+                .map(str::trim)
+                .filter(|line| !line.is_empty() && !line.starts_with('#'))
+                .map(str::to_string),
+        );
+    }
+
+    let mut items = Vec::new();
+    for param in &args.params {
+        let Some((key, value)) = param.split_once('=') else {
+            return Err(format!("invalid parameter '{}', expected KEY=VALUE", param));
+        };
+        set_query_item(&mut items, key, value);
+    }
+    set_query_item(&mut items, "target", &args.target);
+    if !urls.is_empty() {
+        set_query_item(&mut items, "url", &urls.join("|"));
+    }
+    if let Some(config) = &args.config {
+        set_query_item(&mut items, "config", config);
+    }
+    if args.validate {
+        set_query_item(&mut items, "validate", "true");
+    }
+
+    let query =
+        SubconverterQuery::from_pairs(&items).map_err(|e| format!("invalid parameters: {}", e))?;
+    // Local subscriptions and configs are readable by whoever runs the binary
+    Arc::make_mut(&mut *Settings::current_mut()).generator_mode = true;
+
+    let result = sub_process(None, query).await;
+    drop(stdin_file);
+    let response = result.map_err(|e| e.message())?;
+    if response.status_code >= 400 {
+        return Err(response.content);
+    }
+
+    let write_result = match args.output.as_deref() {
+        None | Some("-") => match response.stream {
+            Some(mut stream) => stream.try_for_each(|chunk| stdout.write_all(chunk.as_bytes())),
+            None => stdout.write_all(response.content.as_bytes()),
+        }
+        .and_then(|_| stdout.flush()),
+        Some(path) => {
+            let result = fs::write(path, response.into_content());
+            if result.is_ok() {
+                info!("Successfully wrote result to {}", path);
+            }
+            result
+        }
+    };
+    write_result.map_err(|e| format!("failed to write output: {}", e))
+}
+
+/// Run `-g`, reporting every failed artifact, the exit code is non-zero when
+/// any of them failed
+async fn run_generate(config_path: &str, artifacts: &[String]) -> i32 {
+    // Local subscriptions and configs are readable by whoever runs the binary
+    Arc::make_mut(&mut *Settings::current_mut()).generator_mode = true;
+
+    let settings = Arc::clone(&Settings::current());
+    let results = match generate_artifacts(settings, config_path, artifacts).await {
+        Ok(results) => results,
+        Err(e) => {
+            eprintln!("{}", e);
+            return 1;
+        }
+    };
+    let failed: Vec<_> = results.iter().filter(|result| !result.is_ok()).collect();
+    for result in &failed {
+        eprintln!(
+            "{}: {}",
+            result.name,
+            result.error.as_deref().unwrap_or_default()
+        );
+    }
+    if failed.is_empty() {
+        info!("Generated {} artifacts", results.len());
+        0
+    } else {
+        eprintln!("{} of {} artifacts failed", failed.len(), results.len());
+        1
+    }
+}
+
+/// Set a query parameter, replacing any earlier value of the same key
+fn set_query_item(items: &mut Vec<(String, String)>, key: &str, value: &str) {
+    items.retain(|(k, _)| k != key);
+    items.push((key.to_string(), value.to_string()));
+}
+
+/// Stop accepting connections and let in-flight requests finish on SIGTERM or
+/// Ctrl+C, and reload the configuration like `/updateconf` on SIGHUP
+async fn handle_signals(handle: ServerHandle, shutdown_timeout: u32) {
+    #[cfg(unix)]
+    {
+        use actix_web::rt::signal::unix::{signal, SignalKind};
+
+        let signals = (
+            signal(SignalKind::terminate()),
+            signal(SignalKind::interrupt()),
+            signal(SignalKind::hangup()),
+        );
+        let (mut terminate, mut interrupt, mut hangup) = match signals {
+            (Ok(terminate), Ok(interrupt), Ok(hangup)) => (terminate, interrupt, hangup),
+            _ => {
+                error!("Failed to install signal handlers");
+                return;
+            }
+        };
+
+        loop {
+            tokio::select! {
+                _ = terminate.recv() => {
+                    info!("SIGTERM received");
+                    break;
+                }
+                _ = interrupt.recv() => {
+                    info!("SIGINT received");
+                    break;
+                }
+                _ = hangup.recv() => {
+                    info!("SIGHUP received, reloading configuration");
+                    match web_handlers::reload_server_configuration().await {
+                        Ok(_) => info!("Configuration reloaded"),
+                        Err(e) => error!("Failed to reload configuration: {}", e),
+                    }
+                }
+            }
+        }
+    }
+
+    #[cfg(not(unix))]
+    {
+        if let Err(e) = actix_web::rt::signal::ctrl_c().await {
+            error!("Failed to listen for Ctrl+C: {}", e);
+            return;
+        }
+        info!("Ctrl+C received");
+    }
+
+    info!(
+        "Shutting down, waiting up to {}s for in-flight requests",
+        shutdown_timeout
+    );
+    handle.stop(true).await;
+}
+
+/// A single address the web server binds to
+#[derive(Debug, PartialEq)]
+enum Listener {
+    Tcp(String),
+    Unix(String),
+}
+
+/// Parse a comma separated listen setting into listeners. Entries prefixed
+/// with `unix:` are unix domain socket paths, anything else is a TCP address
+/// that gets the configured port appended when it has none.
+fn parse_listeners(listen_address: &str, listen_port: u32) -> Vec<Listener> {
+    listen_address
+        .split(',')
+        .map(str::trim)
+        .filter(|address| !address.is_empty())
+        .map(|address| {
+            if let Some(path) = address.strip_prefix("unix:") {
+                Listener::Unix(path.to_string())
+            } else if address.contains(':') {
+                // Already has a port, use as is
+                Listener::Tcp(address.to_string())
+            } else {
+                // No port specified, use the one from settings
+                Listener::Tcp(format!("{}:{}", address, listen_port))
+            }
+        })
+        .collect()
+}
+
+/// Remove a socket left behind by a previous run, but never clobber a regular
+/// file that happens to live at the configured path
+#[cfg(unix)]
+fn remove_stale_unix_socket(path: &str) -> std::io::Result<()> {
+    use std::os::unix::fs::FileTypeExt;
+
+    match fs::symlink_metadata(path) {
+        Ok(metadata) if metadata.file_type().is_socket() => {
+            info!("Removing stale unix socket {}", path);
+            fs::remove_file(path)
+        }
+        Ok(_) => Err(std::io::Error::new(
+            std::io::ErrorKind::AlreadyExists,
+            format!("{} exists and is not a unix socket", path),
+        )),
+        Err(_) => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    const SS_LINK: &str = "ss://YWVzLTI1Ni1nY206cGFzcw@1.2.3.4:8388#Test";
+
+    fn convert_args(args: &[&str]) -> ConvertArgs {
+        // A non-empty pref_path keeps sub_process from searching for pref files
+        {
+            let mut guard = Settings::current_mut();
+            let settings = Arc::make_mut(&mut *guard);
+            if settings.pref_path.is_empty() {
+                settings.pref_path = "test".to_string();
+            }
+        }
+        let args = Args::try_parse_from(["subconverter-rs", "convert"].iter().chain(args)).unwrap();
+        match args.command {
+            Some(Command::Convert(args)) => args,
+            other => panic!("not a convert command: {:?}", other),
+        }
+    }
+
+    #[actix_web::test]
+    async fn test_convert_reads_subscription_from_stdin() {
+        let args = convert_args(&["-t", "clash", "-u", "-", "-P", "list=true"]);
+        let mut stdout = Vec::new();
+        run_convert(args, &mut Cursor::new(SS_LINK), &mut stdout)
+            .await
+            .unwrap();
+
+        let output = String::from_utf8(stdout).unwrap();
+        assert!(output.contains("1.2.3.4"), "{}", output);
+    }
+
+    #[actix_web::test]
+    async fn test_convert_rejects_invalid_target() {
+        let args = convert_args(&["-t", "bogus", "-u", SS_LINK]);
+        let mut stdout = Vec::new();
+        let result = run_convert(args, &mut std::io::empty(), &mut stdout).await;
+
+        assert_eq!(result, Err("Invalid target parameter".to_string()));
+        assert!(stdout.is_empty());
+    }
+
+    #[actix_web::test]
+    async fn test_convert_validate_reports_errors() {
+        let args = convert_args(&[
+            "-t",
+            "clash",
+            "-u",
+            SS_LINK,
+            "-P",
+            "groups=Proxy`select`[]Missing",
+            "--validate",
+        ]);
+        let mut stdout = Vec::new();
+        let error = run_convert(args, &mut std::io::empty(), &mut stdout)
+            .await
+            .unwrap_err();
+
+        assert!(
+            error.contains("unknown proxy or group 'Missing'"),
+            "{}",
+            error
+        );
+        assert!(stdout.is_empty());
+    }
+
+    #[actix_web::test]
+    async fn test_convert_writes_output_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let output = dir.path().join("nodes.yaml");
+        let args = convert_args(&[
+            "-t",
+            "clash",
+            "-u",
+            SS_LINK,
+            "-P",
+            "list=true",
+            "-o",
+            output.to_str().unwrap(),
+        ]);
+        let mut stdout = Vec::new();
+        run_convert(args, &mut std::io::empty(), &mut stdout)
+            .await
+            .unwrap();
+
+        assert!(stdout.is_empty());
+        assert!(fs::read_to_string(&output).unwrap().contains("1.2.3.4"));
     }
 }
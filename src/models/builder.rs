@@ -1,3 +1,5 @@
+use crate::models::parse_alpn;
+use crate::utils::network::strip_ipv6_brackets;
 use crate::{Proxy, ProxyType};
 
 impl Proxy {
@@ -17,7 +19,7 @@ impl Proxy {
             proxy_type,
             group: group.to_owned(),
             remark: remark.to_owned(),
-            hostname: server.to_owned(),
+            hostname: strip_ipv6_brackets(server).to_owned(),
             port,
             udp,
             tcp_fast_open: tfo,
@@ -78,7 +80,7 @@ impl Proxy {
         } else {
             Some(edge.to_owned())
         };
-        proxy.server_name = if sni.is_empty() {
+        proxy.sni = if sni.is_empty() {
             None
         } else {
             Some(sni.to_owned())
@@ -292,7 +294,7 @@ impl Proxy {
             proxy_type: ProxyType::Trojan,
             group,
             remark,
-            hostname,
+            hostname: strip_ipv6_brackets(&hostname).to_owned(),
             port,
             password: Some(password),
             transfer_protocol: network,
@@ -327,7 +329,7 @@ impl Proxy {
             proxy_type: ProxyType::Snell,
             group,
             remark,
-            hostname,
+            hostname: strip_ipv6_brackets(&hostname).to_owned(),
             port,
             password: Some(password),
             obfs: Some(obfs),
@@ -368,7 +370,7 @@ impl Proxy {
             proxy_type: ProxyType::WireGuard,
             group,
             remark,
-            hostname,
+            hostname: strip_ipv6_brackets(&hostname).to_owned(),
             port,
             self_ip: Some(self_ip),
             self_ipv6: Some(self_ipv6),
@@ -407,16 +409,11 @@ impl Proxy {
         allow_insecure: Option<bool>,
         underlying_proxy: Option<String>,
     ) -> Self {
-        let mut alpn_set = std::collections::HashSet::new();
-        for proto in alpn {
-            alpn_set.insert(proto);
-        }
-
         Proxy {
             proxy_type: ProxyType::Hysteria2,
             group,
             remark,
-            hostname,
+            hostname: strip_ipv6_brackets(&hostname).to_owned(),
             port,
             ports,
             up_speed: up_speed.unwrap_or(0),
@@ -426,7 +423,7 @@ impl Proxy {
             obfs_param: obfs_param,
             sni: sni,
             fingerprint: fingerprint,
-            alpn: alpn_set,
+            alpn: parse_alpn(alpn),
             ca: ca,
             ca_str: ca_str,
             cwnd: cwnd.unwrap_or(0),
@@ -462,16 +459,11 @@ impl Proxy {
         allow_insecure: Option<bool>,
         underlying_proxy: Option<String>,
     ) -> Self {
-        let mut alpn_set = std::collections::HashSet::new();
-        for proto in alpn {
-            alpn_set.insert(proto);
-        }
-
         Proxy {
             proxy_type: ProxyType::Hysteria,
             group,
             remark,
-            hostname,
+            hostname: strip_ipv6_brackets(&hostname).to_owned(),
             port,
             ports: Some(ports),
             protocol: Some(protocol),
@@ -488,7 +480,7 @@ impl Proxy {
             recv_window: recv_window.unwrap_or(0),
             disable_mtu_discovery,
             hop_interval: hop_interval.unwrap_or(0),
-            alpn: alpn_set,
+            alpn: parse_alpn(alpn),
             tcp_fast_open,
             allow_insecure,
             underlying_proxy,
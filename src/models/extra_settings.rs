@@ -1,8 +1,82 @@
-use std::{cmp::Ordering, str::FromStr};
+use std::{cmp::Ordering, str::FromStr, sync::Arc};
 
-use crate::{utils::file_get_async, Settings};
+use crate::{
+    generator::config::remark::DEFAULT_DUPLICATE_REMARK_FORMAT,
+    utils::{
+        base64::{base64_encode, url_safe_base64_encode},
+        file_get_async,
+        node_manip::sort_nodes,
+    },
+    Settings,
+};
 
-use super::{Proxy, ProxyType, RegexMatchConfig, RegexMatchConfigs};
+use super::{Proxy, RegexMatchConfig, RegexMatchConfigs};
+
+/// How duplicate nodes are removed from merged subscriptions
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum DedupMode {
+    /// Keep every node
+    #[default]
+    Disabled,
+    /// Drop nodes connecting to the same server with the same settings
+    Server,
+    /// Drop nodes whose processed remark is already taken
+    Remark,
+}
+
+impl DedupMode {
+    /// Parses the `dedup` request argument, unknown values disable deduplication
+    pub fn from_arg(value: &str) -> Self {
+        match value.trim().to_lowercase().as_str() {
+            "true" | "1" | "yes" | "on" | "server" => DedupMode::Server,
+            "remark" => DedupMode::Remark,
+            _ => DedupMode::Disabled,
+        }
+    }
+}
+
+/// How subscriptions of share links are base64 encoded
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Base64Style {
+    /// Standard alphabet without padding, as v2rayN writes them
+    #[default]
+    Unpadded,
+    /// Standard alphabet with `=` padding
+    Padded,
+    /// URL-safe alphabet without padding
+    UrlSafe,
+}
+
+impl Base64Style {
+    /// Parses the `b64` request argument, unknown values keep the default
+    pub fn from_arg(value: &str) -> Self {
+        match value.trim().to_lowercase().as_str() {
+            "padded" | "pad" | "standard" => Base64Style::Padded,
+            "urlsafe" | "url" => Base64Style::UrlSafe,
+            _ => Base64Style::Unpadded,
+        }
+    }
+
+    /// `input` encoded in this style
+    pub fn encode(&self, input: &str) -> String {
+        match self {
+            Base64Style::Unpadded => base64_encode(input).trim_end_matches('=').to_string(),
+            Base64Style::Padded => base64_encode(input),
+            Base64Style::UrlSafe => url_safe_base64_encode(input),
+        }
+    }
+}
+
+/// A subscription Clash fetches by itself instead of having its nodes inlined
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ProxyProvider {
+    /// Name groups reference the provider by
+    pub name: String,
+    /// URL serving the provider's proxies
+    pub url: String,
+    /// Seconds between provider updates
+    pub interval: u32,
+}
 
 /// Settings for subscription export operations
 pub struct ExtraSettings {
@@ -22,6 +96,14 @@ pub struct ExtraSettings {
     pub append_proxy_type: bool,
     /// Whether to output as node list
     pub nodelist: bool,
+    /// Whether Surge gets a module of rules instead of a full profile
+    pub surge_module: bool,
+    /// Whether Loon gets a plugin of rules instead of a full profile
+    pub loon_plugin: bool,
+    /// How subscriptions of share links are base64 encoded
+    pub base64_style: Base64Style,
+    /// Whether outputs note the nodes they leave out in comments
+    pub debug: bool,
     /// Whether to sort nodes
     pub sort_flag: bool,
     /// Whether to filter deprecated nodes
@@ -34,26 +116,79 @@ pub struct ExtraSettings {
     pub surge_ssr_path: String,
     /// Prefix for managed configs
     pub managed_config_prefix: String,
+    /// Base URL this server was reached at, from the request and the
+    /// forwarding headers of trusted proxies, including the API base path
+    pub external_base_url: String,
     /// QuantumultX device ID
     pub quanx_dev_id: String,
+    /// Lines merged into the `rewrite_remote` section of QuantumultX configs
+    pub quanx_rewrite_remote: Vec<String>,
+    /// Lines merged into the `task_local` section of QuantumultX configs
+    pub quanx_task_local: Vec<String>,
+    /// Whether QuantumultX configs reference subscriptions as `server_remote`
+    /// resources instead of inlining their nodes
+    pub quanx_server_remote: bool,
     /// UDP support flag
     pub udp: Option<bool>,
     /// TCP Fast Open support flag
     pub tfo: Option<bool>,
+    /// Surge `block-quic` of every node
+    pub block_quic: Option<bool>,
     /// Skip certificate verification flag
     pub skip_cert_verify: Option<bool>,
     /// TLS 1.3 support flag
     pub tls13: Option<bool>,
+    /// TLS server name of TLS nodes that have none, a node's own `sni` wins
+    pub sni: Option<String>,
     /// Whether to use classical ruleset in Clash
     pub clash_classical_ruleset: bool,
     /// Script for sorting nodes
     pub sort_script: String,
+    /// Patterns ranking nodes when sorting, earlier patterns sort first
+    pub sort_priority: Vec<String>,
+    /// How duplicate nodes are removed
+    pub dedup: DedupMode,
+    /// Pattern renaming nodes whose remark is taken, `{name}` and `{n}` are
+    /// replaced with the remark and a counter starting at 2
+    pub duplicate_remark_format: String,
+    /// Template renaming nodes after sorting, e.g. `{country} {index:02}`,
+    /// remarks are kept when empty
+    pub index_remark: String,
+    /// Template of remarks with `append_proxy_type`, e.g. `[{type}] {remark}`
+    pub append_type_format: String,
+    /// Whether to prepend nodes showing subscription traffic and expiry
+    pub append_info_nodes: bool,
+    /// Whether SSD remarks get the airport name and traffic ratio
+    pub ssd_remark_info: bool,
+    /// DNS section merged into Clash configs
+    pub clash_dns: Option<serde_yaml::Value>,
+    /// Whether the Clash output targets Clash.Meta (mihomo)
+    pub clash_meta: bool,
+    /// TUN section merged into Clash.Meta configs
+    pub clash_tun: Option<serde_yaml::Value>,
+    /// Sniffer section merged into Clash.Meta configs
+    pub clash_sniffer: Option<serde_yaml::Value>,
+    /// Whether the TUN and sniffer sections override the ones in the base
+    pub clash_meta_overwrite: bool,
+    /// Whether Clash.Meta groups keep their regex rules as native filters
+    pub native_filter: bool,
+    /// Whether Clash configs reference subscriptions as proxy providers
+    pub clash_providers: bool,
+    /// Providers created from subscription URLs in providers mode, and the
+    /// `server_remote` resources of QuantumultX configs
+    pub proxy_providers: Vec<ProxyProvider>,
     /// Style for Clash proxies output
     pub clash_proxies_style: String,
     /// Style for Clash proxy groups output
     pub clash_proxy_groups_style: String,
+    /// Whether Clash configs drop the proxies of the base
+    pub overwrite_original_proxies: bool,
+    /// Whether Clash configs drop the groups of the base
+    pub overwrite_original_groups: bool,
     /// Whether the export is authorized
     pub authorized: bool,
+    /// Settings the export is made with, the defaults above come from them
+    pub settings: Arc<Settings>,
     /// JavaScript runtime context (not implemented in Rust version)
     #[cfg(feature = "js-runtime")]
     pub js_context: Option<rquickjs::Context>,
@@ -73,21 +208,51 @@ impl std::fmt::Debug for ExtraSettings {
             .field("remove_emoji", &self.remove_emoji)
             .field("append_proxy_type", &self.append_proxy_type)
             .field("nodelist", &self.nodelist)
+            .field("surge_module", &self.surge_module)
+            .field("loon_plugin", &self.loon_plugin)
+            .field("base64_style", &self.base64_style)
+            .field("debug", &self.debug)
             .field("sort_flag", &self.sort_flag)
             .field("filter_deprecated", &self.filter_deprecated)
             .field("clash_new_field_name", &self.clash_new_field_name)
             .field("clash_script", &self.clash_script)
             .field("surge_ssr_path", &self.surge_ssr_path)
             .field("managed_config_prefix", &self.managed_config_prefix)
+            .field("external_base_url", &self.external_base_url)
             .field("quanx_dev_id", &self.quanx_dev_id)
+            .field("quanx_rewrite_remote", &self.quanx_rewrite_remote)
+            .field("quanx_task_local", &self.quanx_task_local)
+            .field("quanx_server_remote", &self.quanx_server_remote)
             .field("udp", &self.udp)
             .field("tfo", &self.tfo)
+            .field("block_quic", &self.block_quic)
             .field("skip_cert_verify", &self.skip_cert_verify)
             .field("tls13", &self.tls13)
+            .field("sni", &self.sni)
             .field("clash_classical_ruleset", &self.clash_classical_ruleset)
             .field("sort_script", &self.sort_script)
+            .field("sort_priority", &self.sort_priority)
+            .field("dedup", &self.dedup)
+            .field("duplicate_remark_format", &self.duplicate_remark_format)
+            .field("index_remark", &self.index_remark)
+            .field("append_type_format", &self.append_type_format)
+            .field("append_info_nodes", &self.append_info_nodes)
+            .field("ssd_remark_info", &self.ssd_remark_info)
+            .field("clash_dns", &self.clash_dns)
+            .field("clash_meta", &self.clash_meta)
+            .field("clash_tun", &self.clash_tun)
+            .field("clash_sniffer", &self.clash_sniffer)
+            .field("clash_meta_overwrite", &self.clash_meta_overwrite)
+            .field("native_filter", &self.native_filter)
+            .field("clash_providers", &self.clash_providers)
+            .field("proxy_providers", &self.proxy_providers)
             .field("clash_proxies_style", &self.clash_proxies_style)
             .field("clash_proxy_groups_style", &self.clash_proxy_groups_style)
+            .field(
+                "overwrite_original_proxies",
+                &self.overwrite_original_proxies,
+            )
+            .field("overwrite_original_groups", &self.overwrite_original_groups)
             .field("authorized", &self.authorized)
             .finish()
     }
@@ -95,8 +260,13 @@ impl std::fmt::Debug for ExtraSettings {
 
 impl Default for ExtraSettings {
     fn default() -> Self {
-        let global = Settings::current();
+        ExtraSettings::from_settings(Arc::clone(&Settings::current()))
+    }
+}
 
+impl ExtraSettings {
+    /// Export settings with the defaults of `global`
+    pub fn from_settings(global: Arc<Settings>) -> Self {
         ExtraSettings {
             enable_rule_generator: global.enable_rule_gen,
             overwrite_original_rules: global.overwrite_original_rules,
@@ -106,19 +276,48 @@ impl Default for ExtraSettings {
             remove_emoji: false,
             append_proxy_type: false,
             nodelist: false,
+            surge_module: false,
+            loon_plugin: false,
+            base64_style: Base64Style::Unpadded,
+            debug: false,
             sort_flag: false,
             filter_deprecated: false,
             clash_new_field_name: true,
             clash_script: false,
             surge_ssr_path: global.surge_ssr_path.clone(),
             managed_config_prefix: String::new(),
+            external_base_url: String::new(),
             quanx_dev_id: String::new(),
+            quanx_rewrite_remote: Vec::new(),
+            quanx_task_local: Vec::new(),
+            quanx_server_remote: false,
             udp: None,
             tfo: None,
+            block_quic: None,
             skip_cert_verify: None,
             tls13: None,
+            sni: None,
             clash_classical_ruleset: false,
             sort_script: String::new(),
+            sort_priority: Vec::new(),
+            dedup: DedupMode::Disabled,
+            duplicate_remark_format: if global.duplicate_remark_format.is_empty() {
+                DEFAULT_DUPLICATE_REMARK_FORMAT.to_string()
+            } else {
+                global.duplicate_remark_format.clone()
+            },
+            index_remark: global.index_remark.clone(),
+            append_type_format: global.append_type_format.clone(),
+            append_info_nodes: false,
+            ssd_remark_info: false,
+            clash_dns: None,
+            clash_meta: false,
+            clash_tun: None,
+            clash_sniffer: None,
+            clash_meta_overwrite: false,
+            native_filter: false,
+            clash_providers: false,
+            proxy_providers: Vec::new(),
             clash_proxies_style: if global.clash_proxies_style.is_empty() {
                 "flow".to_string()
             } else {
@@ -129,13 +328,43 @@ impl Default for ExtraSettings {
             } else {
                 global.clash_proxy_groups_style.clone()
             },
+            overwrite_original_proxies: global.overwrite_original_proxies,
+            overwrite_original_groups: global.overwrite_original_groups,
             authorized: false,
+            settings: global,
             #[cfg(feature = "js-runtime")]
             js_context: None,
             #[cfg(feature = "js-runtime")]
             js_runtime: None,
         }
     }
+
+    /// Makes the export with `settings`, the defaults taken from the
+    /// previous settings are replaced with theirs
+    pub fn use_settings(&mut self, settings: Arc<Settings>) {
+        let defaults = ExtraSettings::from_settings(settings);
+        self.enable_rule_generator = defaults.enable_rule_generator;
+        self.overwrite_original_rules = defaults.overwrite_original_rules;
+        self.surge_ssr_path = defaults.surge_ssr_path;
+        self.duplicate_remark_format = defaults.duplicate_remark_format;
+        self.index_remark = defaults.index_remark;
+        self.append_type_format = defaults.append_type_format;
+        self.clash_proxies_style = defaults.clash_proxies_style;
+        self.clash_proxy_groups_style = defaults.clash_proxy_groups_style;
+        self.overwrite_original_proxies = defaults.overwrite_original_proxies;
+        self.overwrite_original_groups = defaults.overwrite_original_groups;
+        self.settings = defaults.settings;
+    }
+
+    /// Prefix of URLs pointing back at this server, the managed config prefix
+    /// or else the URL the request came in on
+    pub fn self_url_prefix(&self) -> &str {
+        if self.managed_config_prefix.is_empty() {
+            &self.external_base_url
+        } else {
+            &self.managed_config_prefix
+        }
+    }
 }
 
 #[cfg(feature = "js-runtime")]
@@ -269,16 +498,7 @@ impl ExtraSettings {
                 return Err(e.into());
             }
         } else {
-            // Default sort by remark
-            nodes.sort_by(|a, b| {
-                if a.proxy_type == ProxyType::Unknown {
-                    return Ordering::Greater;
-                }
-                if b.proxy_type == ProxyType::Unknown {
-                    return Ordering::Less;
-                }
-                a.remark.cmp(&b.remark)
-            });
+            sort_nodes(nodes, &self.sort_priority);
         }
         Ok(())
     }
@@ -61,14 +61,14 @@ pub mod regex_match_config;
 pub mod ruleset;
 pub mod subconverter_target;
 
-pub use extra_settings::ExtraSettings;
+pub use extra_settings::{Base64Style, DedupMode, ExtraSettings, ProxyProvider};
 pub use proxy_group_config::{
     BalanceStrategy, ProxyGroupConfig, ProxyGroupConfigs, ProxyGroupType,
 };
 pub use regex_match_config::{RegexMatchConfig, RegexMatchConfigs};
 pub use subconverter_target::SubconverterTarget;
 
-pub use proxy::{Proxy, ProxyType};
+pub use proxy::{parse_alpn, Proxy, ProxyType};
 pub use ruleset::{RulesetConfig, RulesetContent, RulesetType};
 
 // Re-export constants to module scope for use by other modules
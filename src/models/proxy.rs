@@ -7,6 +7,7 @@ use std::collections::HashSet;
 use serde::{Deserialize, Serialize};
 
 use super::proxy_node::combined::CombinedProxy;
+use crate::utils::tribool::OptionSetExt;
 
 /// Represents the type of a proxy.
 /// This is the canonical enum used for proxy type identification across the
@@ -60,8 +61,14 @@ pub struct Proxy {
     #[serde(flatten)]
     pub combined_proxy: Option<CombinedProxy>,
     pub id: u32,
+    /// Index of the subscription the node comes from, nodes from insert URLs
+    /// count down from -1
     pub group_id: i32,
     pub group: String,
+    /// `tag:` given to the subscription URL the node comes from
+    pub tag: String,
+    /// Country code of the server found in the `geoip_db`, empty when unknown
+    pub country: String,
     pub remark: String,
     pub hostname: String,
     pub port: u16,
@@ -91,13 +98,14 @@ pub struct Proxy {
 
     pub udp: Option<bool>,
     pub tcp_fast_open: Option<bool>,
+    /// Surge `block-quic`, `None` leaves it to Surge
+    pub block_quic: Option<bool>,
     pub allow_insecure: Option<bool>,
     pub tls13: Option<bool>,
 
     pub underlying_proxy: Option<String>,
 
     pub snell_version: u16,
-    pub server_name: Option<String>,
 
     pub self_ip: Option<String>,
     pub self_ipv6: Option<String>,
@@ -118,6 +126,12 @@ pub struct Proxy {
     pub down_speed: u32,
     pub auth: Option<String>,
     pub auth_str: Option<String>,
+    /// TLS server name of every node type, see [`Proxy::tls_server_name`]
+    ///
+    /// Parsers take the first of `sni`, `servername` / `server_name`,
+    /// `peer` and `tls-host` / `tls-name` the source gives, the `Host` header
+    /// is never copied here.
+    #[serde(alias = "ServerName")]
     pub sni: Option<String>,
     pub fingerprint: Option<String>,
     pub ca: Option<String>,
@@ -126,9 +140,14 @@ pub struct Proxy {
     pub recv_window: u32,
     pub disable_mtu_discovery: Option<bool>,
     pub hop_interval: u32,
-    pub alpn: HashSet<String>,
+    /// TLS ALPN protocols in order of preference
+    pub alpn: Option<Vec<String>>,
 
     pub cwnd: u32,
+
+    /// Placeholder showing subscription traffic or expiry, not a usable server
+    #[serde(skip)]
+    pub info_node: bool,
 }
 
 /// Implement Default for Proxy
@@ -140,6 +159,8 @@ impl Default for Proxy {
             id: 0,
             group_id: 0,
             group: String::new(),
+            tag: String::new(),
+            country: String::new(),
             remark: String::new(),
             hostname: String::new(),
             port: 0,
@@ -164,11 +185,11 @@ impl Default for Proxy {
             quic_secret: None,
             udp: None,
             tcp_fast_open: None,
+            block_quic: None,
             allow_insecure: None,
             tls13: None,
             underlying_proxy: None,
             snell_version: 0,
-            server_name: None,
             self_ip: None,
             self_ipv6: None,
             public_key: None,
@@ -193,8 +214,9 @@ impl Default for Proxy {
             recv_window: 0,
             disable_mtu_discovery: None,
             hop_interval: 0,
-            alpn: HashSet::new(),
+            alpn: None,
             cwnd: 0,
+            info_node: false,
         }
     }
 }
@@ -216,6 +238,18 @@ impl<'js> IntoJs<'js> for Proxy {
 }
 
 impl Proxy {
+    /// Server name to send in the TLS handshake
+    ///
+    /// The `sni` of the node, or the `Host` header of its transport when it has
+    /// none, so a CDN fronted node whose header and SNI differ keeps both.
+    /// `None` leaves it to the client, which uses the server address.
+    pub fn tls_server_name(&self) -> Option<&str> {
+        [self.sni.as_deref(), self.host.as_deref()]
+            .into_iter()
+            .flatten()
+            .find(|name| !name.is_empty())
+    }
+
     pub fn is_combined_proxy(&self) -> bool {
         matches!(
             self.proxy_type,
@@ -271,25 +305,16 @@ impl Proxy {
         self
     }
 
-    /// 使用默认值应用 tribool 属性，如果属性值为 None 则设置为提供的默认值
-    pub fn apply_default_values(
+    /// 应用请求中的 tribool 覆盖值：为 Some 时覆盖节点自身的值，为 None 时保留节点原值
+    pub fn apply_overrides(
         mut self,
-        default_udp: Option<bool>,
-        default_tfo: Option<bool>,
-        default_scv: Option<bool>,
+        udp: Option<bool>,
+        tfo: Option<bool>,
+        scv: Option<bool>,
     ) -> Self {
-        if self.udp.is_none() {
-            self.udp = default_udp;
-        }
-
-        if self.tcp_fast_open.is_none() {
-            self.tcp_fast_open = default_tfo;
-        }
-
-        if self.allow_insecure.is_none() {
-            self.allow_insecure = default_scv;
-        }
-
+        self.udp.set_if_some(udp);
+        self.tcp_fast_open.set_if_some(tfo);
+        self.allow_insecure.set_if_some(scv);
         self
     }
 }
@@ -305,3 +330,47 @@ pub const SNELL_DEFAULT_GROUP: &str = "SnellProvider";
 pub const WG_DEFAULT_GROUP: &str = "WireGuardProvider";
 pub const HYSTERIA_DEFAULT_GROUP: &str = "HysteriaProvider";
 pub const HYSTERIA2_DEFAULT_GROUP: &str = "Hysteria2Provider";
+
+/// ALPN protocols listed in `values`, each of which may hold several separated
+/// by commas
+///
+/// Blank and repeated protocols are left out, the order is kept as it is the
+/// order of preference. `None` when no protocol is left.
+pub fn parse_alpn<I, S>(values: I) -> Option<Vec<String>>
+where
+    I: IntoIterator<Item = S>,
+    S: AsRef<str>,
+{
+    let mut alpn: Vec<String> = Vec::new();
+    for value in values {
+        for protocol in value.as_ref().split(',').map(str::trim) {
+            if !protocol.is_empty() && !alpn.iter().any(|known| known == protocol) {
+                alpn.push(protocol.to_string());
+            }
+        }
+    }
+    (!alpn.is_empty()).then_some(alpn)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_alpn() {
+        assert_eq!(
+            parse_alpn(["h2,http/1.1"]),
+            Some(vec!["h2".to_string(), "http/1.1".to_string()])
+        );
+        assert_eq!(
+            parse_alpn(["h3", " h2 ", "", "h3", "h2,,http/1.1"]),
+            Some(vec![
+                "h3".to_string(),
+                "h2".to_string(),
+                "http/1.1".to_string()
+            ])
+        );
+        assert_eq!(parse_alpn([" , "]), None);
+        assert_eq!(parse_alpn(Vec::<String>::new()), None);
+    }
+}
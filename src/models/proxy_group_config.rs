@@ -63,14 +63,26 @@ pub struct ProxyGroupConfig {
     pub tolerance: u32,
     /// Strategy for load balancing
     pub strategy: BalanceStrategy,
-    /// Whether to use lazy loading
-    pub lazy: bool,
+    /// Whether to use lazy loading, unset to use the configured default
+    pub lazy: Option<bool>,
     /// Whether to disable UDP support
     pub disable_udp: bool,
     /// Whether to persist connections
     pub persistent: bool,
     /// Whether to evaluate before use
     pub evaluate_before_use: bool,
+    /// Icon URL shown by Stash and Clash.Meta dashboards
+    pub icon: String,
+    /// Whether to hide the group from Stash and Clash.Meta dashboards
+    pub hidden: bool,
+    /// Whether the group takes every proxy, letting Clash.Meta list them
+    pub include_all: bool,
+    /// Whether Clash.Meta keeps the regex rules of this group as native filters
+    pub native_filter: bool,
+    /// Regex of nodes Clash.Meta leaves out of a natively filtered group
+    pub exclude_filter: String,
+    /// Proxy types Clash.Meta leaves out of a natively filtered group, `|` separated
+    pub exclude_type: String,
 }
 
 impl Default for ProxyGroupConfig {
@@ -85,10 +97,16 @@ impl Default for ProxyGroupConfig {
             timeout: 0,
             tolerance: 0,
             strategy: BalanceStrategy::ConsistentHashing,
-            lazy: false,
+            lazy: None,
             disable_udp: false,
             persistent: false,
             evaluate_before_use: false,
+            icon: String::new(),
+            hidden: false,
+            include_all: false,
+            native_filter: false,
+            exclude_filter: String::new(),
+            exclude_type: String::new(),
         }
     }
 }
@@ -112,6 +130,44 @@ impl ProxyGroupConfig {
     pub fn strategy_str(&self) -> &'static str {
         self.strategy.as_str()
     }
+
+    /// Whether this group probes its members with a test URL
+    pub fn is_url_test(&self) -> bool {
+        matches!(
+            self.group_type,
+            ProxyGroupType::URLTest
+                | ProxyGroupType::Fallback
+                | ProxyGroupType::LoadBalance
+                | ProxyGroupType::Smart
+        )
+    }
+
+    /// Fill in the test URL and timings this group leaves unset
+    pub fn apply_test_defaults(
+        &mut self,
+        url: &str,
+        interval: u32,
+        timeout: u32,
+        tolerance: u32,
+        lazy: bool,
+    ) {
+        if !self.is_url_test() {
+            return;
+        }
+        if self.url.is_empty() {
+            self.url = url.to_string();
+        }
+        if self.interval == 0 {
+            self.interval = interval;
+        }
+        if self.timeout == 0 {
+            self.timeout = timeout;
+        }
+        if self.tolerance == 0 {
+            self.tolerance = tolerance;
+        }
+        self.lazy.get_or_insert(lazy);
+    }
 }
 
 /// A collection of proxy group configurations
@@ -132,13 +188,13 @@ impl Serialize for ProxyGroupConfig {
         match self.group_type {
             ProxyGroupType::LoadBalance => {
                 field_count += 4; // strategy, url, interval, tolerance
-                if !self.lazy {
+                if self.lazy == Some(false) {
                     field_count += 1; // lazy
                 }
             }
             ProxyGroupType::URLTest | ProxyGroupType::Smart => {
                 field_count += 2; // url, interval
-                if !self.lazy {
+                if self.lazy == Some(false) {
                     field_count += 1; // lazy
                 }
                 if self.tolerance > 0 {
@@ -164,6 +220,15 @@ impl Serialize for ProxyGroupConfig {
         if self.evaluate_before_use {
             field_count += 1;
         }
+        if !self.icon.is_empty() {
+            field_count += 1;
+        }
+        if self.hidden {
+            field_count += 1;
+        }
+        if self.include_all {
+            field_count += 1;
+        }
 
         // Add fields for proxies and provider
         if !self.proxies.is_empty() {
@@ -192,8 +257,8 @@ impl Serialize for ProxyGroupConfig {
             ProxyGroupType::LoadBalance => {
                 // Load balancing specific fields
                 state.serialize_field("strategy", &self.strategy_str())?;
-                if !self.lazy {
-                    state.serialize_field("lazy", &self.lazy)?;
+                if self.lazy == Some(false) {
+                    state.serialize_field("lazy", &false)?;
                 }
                 state.serialize_field("url", &self.url)?;
                 if self.interval > 0 {
@@ -205,8 +270,8 @@ impl Serialize for ProxyGroupConfig {
             }
             ProxyGroupType::URLTest | ProxyGroupType::Smart => {
                 // URL-test specific fields
-                if !self.lazy {
-                    state.serialize_field("lazy", &self.lazy)?;
+                if self.lazy == Some(false) {
+                    state.serialize_field("lazy", &false)?;
                 }
                 state.serialize_field("url", &self.url)?;
                 if self.interval > 0 {
@@ -239,6 +304,15 @@ impl Serialize for ProxyGroupConfig {
         if self.evaluate_before_use {
             state.serialize_field("evaluate-before-use", &self.evaluate_before_use)?;
         }
+        if !self.icon.is_empty() {
+            state.serialize_field("icon", &self.icon)?;
+        }
+        if self.hidden {
+            state.serialize_field("hidden", &self.hidden)?;
+        }
+        if self.include_all {
+            state.serialize_field("include-all", &self.include_all)?;
+        }
 
         // Add proxies list if not empty
         if !self.proxies.is_empty() {
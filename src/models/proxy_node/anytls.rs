@@ -1,5 +1,4 @@
 use serde::{Deserialize, Serialize};
-use std::collections::HashSet;
 
 /// Represents the AnyTLS proxy details
 #[derive(Debug, Clone, Default, Serialize, Deserialize)]
@@ -7,7 +6,7 @@ use std::collections::HashSet;
 pub struct AnyTlsProxy {
     pub password: String,
     #[serde(skip_serializing_if = "Option::is_none")]
-    pub alpn: Option<HashSet<String>>, // Using HashSet for uniqueness, similar to vless alpn
+    pub alpn: Option<Vec<String>>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub sni: Option<String>,
     #[serde(skip_serializing_if = "Option::is_none")]
@@ -1,12 +1,12 @@
 use serde::{Deserialize, Serialize};
-use std::collections::{HashMap, HashSet};
+use std::collections::HashMap;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct VlessProxy {
     pub uuid: String,
     pub flow: Option<String>,
     pub tls: bool,
-    pub alpn: HashSet<String>,
+    pub alpn: Option<Vec<String>>,
     pub udp: bool,
     pub packet_addr: Option<bool>,
     pub xudp: Option<bool>,
@@ -34,7 +34,7 @@ impl Default for VlessProxy {
             uuid: String::new(),
             flow: None,
             tls: false,
-            alpn: HashSet::new(),
+            alpn: None,
             udp: true,
             packet_addr: None,
             xudp: None,
@@ -1,6 +1,7 @@
 use serde::Deserialize;
 
 use crate::utils::{
+    cached_regex,
     matcher::{
         apply_compiled_rule_to_string, compile_rule, replace_with_compiled_regex, CompiledRule,
     },
@@ -44,7 +45,7 @@ impl RegexMatchConfig {
         self.compiled_rule = Some(compile_rule(&self._match));
         // Also pre-compile the regex specifically for the replacement logic
         // Use the same case-insensitivity as reg_find/compile_rule(Plain/Remarks)
-        self.compiled_regex_for_replace = Regex::new(&format!("(?i){}", self._match)).ok();
+        self.compiled_regex_for_replace = cached_regex(&format!("(?i){}", self._match)).ok();
     }
 
     pub fn process(&self, remark: &mut String) {
@@ -3,8 +3,10 @@ use std::{
     sync::{Arc, RwLock},
 };
 
+use crate::generator::ruleconvert::Behavior;
+
 /// Enum defining the type of ruleset
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub enum RulesetType {
     Surge,
     Quanx,
@@ -52,10 +54,35 @@ pub struct RulesetConfig {
     pub group: String,
     pub url: String,
     pub interval: u32,
+    /// Behavior the ruleset is served as to Clash, detected from its rules
+    /// when not given
+    pub behavior: Option<Behavior>,
+    /// GEOSITE and GEOIP rules replacing the ruleset for Clash.Meta, which
+    /// has the same lists built in, see [`parse_geo_rule`]
+    pub geo: Vec<String>,
 }
 
 pub type RulesetConfigs = Vec<RulesetConfig>;
 
+/// Parses a `geosite:<category>` or `geoip:<country>` ruleset option into the
+/// rule it stands for without a policy, e.g. `geoip:cn` is `GEOIP,CN`
+pub fn parse_geo_rule(option: &str) -> Option<String> {
+    let (kind, name) = option.trim().split_once(':')?;
+    let name = name.trim();
+    let valid = !name.is_empty()
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '!' | '@' | '.'));
+    if !valid {
+        return None;
+    }
+    match kind.trim().to_ascii_lowercase().as_str() {
+        "geosite" => Some(format!("GEOSITE,{}", name.to_ascii_lowercase())),
+        "geoip" => Some(format!("GEOIP,{}", name.to_ascii_uppercase())),
+        _ => None,
+    }
+}
+
 /// Represents a ruleset with its metadata and content
 /// Matches the C++ struct RulesetContent:
 /// ```cpp
@@ -81,6 +108,15 @@ pub struct RulesetContent {
     pub rule_content: Arc<RwLock<Option<String>>>,
 
     pub update_interval: u32, // update_interval in C++
+
+    /// Whether the content came from the ruleset cache instead of a fetch
+    pub from_cache: bool,
+
+    /// Behavior given on the ruleset line, see [`RulesetConfig::behavior`]
+    pub behavior: Option<Behavior>,
+
+    /// Rules replacing the ruleset for Clash.Meta, see [`RulesetConfig::geo`]
+    pub geo: Vec<String>,
 }
 
 impl RulesetContent {
@@ -93,6 +129,9 @@ impl RulesetContent {
             rule_type: RulesetType::default(),
             rule_content: Arc::new(RwLock::new(None)),
             update_interval: 0,
+            from_cache: false,
+            behavior: None,
+            geo: Vec::new(),
         }
     }
 
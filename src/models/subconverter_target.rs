@@ -1,3 +1,4 @@
+use crate::models::ProxyType;
 use crate::settings::external::ExternalSettings;
 
 /// The output format for subconverter
@@ -74,10 +75,84 @@ impl SubconverterTarget {
         }
     }
 
+    /// MIME type of the configs generated for this target
+    pub fn content_type(&self) -> &'static str {
+        match self {
+            SubconverterTarget::Clash | SubconverterTarget::ClashR => "application/yaml",
+            SubconverterTarget::SingBox => "application/json",
+            SubconverterTarget::SSSub | SubconverterTarget::SSD => "application/json",
+            _ => "text/plain",
+        }
+    }
+
     pub fn is_clash(&self) -> bool {
         matches!(self, SubconverterTarget::Clash | SubconverterTarget::ClashR)
     }
 
+    /// Whether configs for this target can hold nodes of `proxy_type`, the
+    /// generators leave the others out
+    ///
+    /// Errs on the side of yes, some generators also skip nodes of a
+    /// supported type which use options the client lacks.
+    pub fn supports(&self, proxy_type: ProxyType) -> bool {
+        use ProxyType::*;
+        match self {
+            SubconverterTarget::Auto | SubconverterTarget::Clash | SubconverterTarget::ClashR => {
+                !matches!(proxy_type, Unknown | HTTPS)
+            }
+            SubconverterTarget::Surge(_) | SubconverterTarget::Surfboard => matches!(
+                proxy_type,
+                Shadowsocks
+                    | ShadowsocksR
+                    | VMess
+                    | Trojan
+                    | Snell
+                    | HTTP
+                    | HTTPS
+                    | Socks5
+                    | WireGuard
+                    | Hysteria2
+            ),
+            SubconverterTarget::Loon => matches!(
+                proxy_type,
+                Shadowsocks | ShadowsocksR | VMess | Trojan | HTTP | HTTPS | Socks5 | WireGuard
+            ),
+            SubconverterTarget::QuantumultX => matches!(
+                proxy_type,
+                Shadowsocks | ShadowsocksR | VMess | Trojan | HTTP | HTTPS | Socks5
+            ),
+            SubconverterTarget::Quantumult => matches!(
+                proxy_type,
+                Shadowsocks | ShadowsocksR | VMess | HTTP | HTTPS | Socks5
+            ),
+            SubconverterTarget::Mellow => {
+                matches!(proxy_type, Shadowsocks | VMess | HTTP | Socks5)
+            }
+            SubconverterTarget::SingBox => matches!(
+                proxy_type,
+                Shadowsocks
+                    | ShadowsocksR
+                    | VMess
+                    | Trojan
+                    | HTTP
+                    | HTTPS
+                    | Socks5
+                    | WireGuard
+                    | Hysteria
+                    | Hysteria2
+            ),
+            SubconverterTarget::SS
+            | SubconverterTarget::SSR
+            | SubconverterTarget::SSSub
+            | SubconverterTarget::SSD => matches!(proxy_type, Shadowsocks | ShadowsocksR),
+            SubconverterTarget::V2Ray => proxy_type == VMess,
+            SubconverterTarget::Trojan => proxy_type == Trojan,
+            SubconverterTarget::Mixed => {
+                matches!(proxy_type, Shadowsocks | ShadowsocksR | VMess | Trojan)
+            }
+        }
+    }
+
     /// Returns true if the target represents a simple type (e.g. ss, ssr, trojan)
     pub fn is_simple(&self) -> bool {
         matches!(
@@ -1,21 +1,137 @@
+use std::borrow::Cow;
+use std::fmt;
+
+use crate::models::ProxyType;
 use crate::utils::base64::url_safe_base64_decode;
 use crate::Proxy;
 
+/// How deep base64 blobs inside a mixed subscription are decoded
+const MAX_MIXED_DEPTH: usize = 3;
+
+/// An entry of a subscription which was dropped instead of becoming a node
+#[derive(Debug, Clone, PartialEq)]
+pub struct NodeDiagnostic {
+    /// 1-based line when the subscription is a link list, nested blobs
+    /// report their own line
+    pub line: Option<usize>,
+    /// Scheme or proxy type of the entry, empty for lines which are no link
+    pub scheme: String,
+    /// What is wrong, with the offending value
+    pub reason: String,
+}
+
+impl fmt::Display for NodeDiagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some(line) = self.line {
+            write!(f, "line {}: ", line)?;
+        }
+        if !self.scheme.is_empty() {
+            write!(f, "{}: ", self.scheme)?;
+        }
+        f.write_str(&self.reason)
+    }
+}
+
+/// Whether a client could connect to `node`, the reason when it could not
+///
+/// Port 0 and empty hosts are rejected, the parsers already refuse ports
+/// above 65535. Nodes of unknown type are placeholders and always pass.
+pub fn check_node(node: &Proxy) -> Result<(), String> {
+    if node.proxy_type == ProxyType::Unknown {
+        return Ok(());
+    }
+    if node.hostname.trim().is_empty() {
+        return Err(format!("empty host in '{}'", node.remark));
+    }
+    if node.port == 0 {
+        return Err(format!("invalid port 0 in '{}'", node.remark));
+    }
+    Ok(())
+}
+
+/// Drop the nodes from `start` on which fail [`check_node`], recording why
+pub fn drop_invalid_nodes(
+    nodes: &mut Vec<Proxy>,
+    start: usize,
+    diagnostics: &mut Vec<NodeDiagnostic>,
+) {
+    let mut index = 0;
+    nodes.retain(|node| {
+        index += 1;
+        if index <= start {
+            return true;
+        }
+        match check_node(node) {
+            Ok(()) => true,
+            Err(reason) => {
+                diagnostics.push(NodeDiagnostic {
+                    line: None,
+                    scheme: node.proxy_type.to_string().to_lowercase(),
+                    reason,
+                });
+                false
+            }
+        }
+    });
+}
+
+/// Log the entries dropped from one source
+pub fn log_diagnostics(diagnostics: &[NodeDiagnostic]) {
+    if diagnostics.is_empty() {
+        return;
+    }
+    let summary: Vec<String> = diagnostics.iter().map(ToString::to_string).collect();
+    log::warn!(
+        "Dropped {} entries of the subscription: {}",
+        diagnostics.len(),
+        summary.join("; ")
+    );
+}
+
+/// Scheme of `link` when it starts with one, e.g. `vmess` for `VMess://...`
+fn link_scheme(link: &str) -> Option<&str> {
+    let (scheme, _) = link.split_once("://")?;
+    let valid = !scheme.is_empty()
+        && scheme.starts_with(|c: char| c.is_ascii_alphabetic())
+        && scheme
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '+' | '-' | '.'));
+    valid.then_some(scheme)
+}
+
+/// `link` with its scheme lowercased so the parsers can match on it
+fn normalize_scheme(link: &str) -> Cow<'_, str> {
+    match link_scheme(link) {
+        Some(scheme) if scheme.chars().any(|c| c.is_ascii_uppercase()) => {
+            Cow::Owned(scheme.to_ascii_lowercase() + &link[scheme.len()..])
+        }
+        _ => Cow::Borrowed(link),
+    }
+}
+
 /// Explode a proxy link into a Proxy object
 ///
 /// This function detects the type of proxy link and calls the appropriate
 /// parser
 pub fn explode(link: &str, node: &mut Proxy) -> bool {
     // Trim the link
-    let link = link.trim();
+    let link = link.trim().trim_start_matches('\u{feff}');
 
     // Check for empty link
     if link.is_empty() {
         return false;
     }
+    let link = normalize_scheme(link);
+    let link = link.as_ref();
 
     // Detect link type and call appropriate parser
     if link.starts_with("vmess://") {
+        // Shadowrocket encodes only the server ahead of a query string, the
+        // other flavors are told apart by trying them
+        if super::vmess::is_shadowrocket_vmess(link) {
+            return super::vmess::explode_shadowrocket(link, node);
+        }
+
         // Try new VMess parser first
         if super::vmess::explode_std_vmess_new(link, node) {
             return true;
@@ -31,10 +147,6 @@ pub fn explode(link: &str, node: &mut Proxy) -> bool {
             return true;
         }
 
-        if super::vmess::explode_shadowrocket(link, node) {
-            return true;
-        }
-
         if super::vmess::explode_kitsunebi(link, node) {
             return true;
         }
@@ -84,8 +196,21 @@ pub fn explode(link: &str, node: &mut Proxy) -> bool {
 /// This function parses a subscription content (which may contain multiple
 /// proxy links) and returns a vector of Proxy objects
 pub fn explode_sub(sub: &str, nodes: &mut Vec<Proxy>) -> bool {
+    let mut diagnostics = Vec::new();
+    let parsed = explode_sub_with_diagnostics(sub, nodes, &mut diagnostics);
+    log_diagnostics(&diagnostics);
+    parsed
+}
+
+/// [`explode_sub`], the lines of a link list which yield no node are added to
+/// `diagnostics` instead of being logged
+fn explode_sub_with_diagnostics(
+    sub: &str,
+    nodes: &mut Vec<Proxy>,
+    diagnostics: &mut Vec<NodeDiagnostic>,
+) -> bool {
     // Trim the subscription content
-    let sub = sub.trim();
+    let sub = sub.trim().trim_start_matches('\u{feff}');
 
     // Check for empty subscription
     if sub.is_empty() {
@@ -106,13 +231,19 @@ pub fn explode_sub(sub: &str, nodes: &mut Vec<Proxy>) -> bool {
         && (sub.contains("\"Proxy\":")
             || sub.contains("\"proxies\":")
             || sub.contains("Proxy:")
-            || sub.contains("proxies:"))
+            || sub.contains("proxies:")
+            || super::explode_clash::is_clash_proxy_list(sub))
     {
-        if super::explode_clash::explode_clash(sub, nodes) {
+        if super::explode_clash::explode_clash_with_diagnostics(sub, nodes, diagnostics) {
             processed = true;
         }
     }
 
+    // Try to parse as Loon configuration, it shares Surge's [Proxy] section
+    if !processed && super::loon::is_loon_content(sub) && super::loon::explode_loon(sub, nodes) {
+        processed = true;
+    }
+
     // Try to parse as Surge configuration
     if !processed && super::surge::explode_surge(sub, nodes) {
         processed = true;
@@ -123,44 +254,120 @@ pub fn explode_sub(sub: &str, nodes: &mut Vec<Proxy>) -> bool {
         // Try to decode as base64
         let decoded = url_safe_base64_decode(sub);
 
-        // Check if it's a Surge format after decoding
-        if decoded.contains("vmess=")
-            || decoded.contains("shadowsocks=")
-            || decoded.contains("http=")
-            || decoded.contains("trojan=")
+        // Check if it's a Quantumult X server list after decoding
+        if super::quanx::is_quanx_content(&decoded) && super::quanx::explode_quanx(&decoded, nodes)
         {
-            if super::surge::explode_surge(&decoded, nodes) {
-                return true;
-            }
+            return true;
         }
 
-        // Split by newlines or spaces depending on content
-        let delimiter = if decoded.contains('\n') {
-            '\n'
-        } else if decoded.contains('\r') {
-            '\r'
-        } else {
-            ' '
-        };
+        diagnostics.append(&mut explode_mixed(&decoded, nodes));
+    }
+
+    !nodes.is_empty()
+}
 
-        let lines: Vec<&str> = decoded.split(delimiter).collect();
+/// Parse a plain-text subscription mixing links of any scheme, one or more per
+/// line, and return the lines which yielded no node
+///
+/// Blank lines and comments are skipped. A line which is itself base64 of a
+/// link list is decoded and parsed in turn. Links on one line are split at
+/// the whitespace before a scheme, so remarks may contain spaces.
+pub fn explode_mixed(content: &str, nodes: &mut Vec<Proxy>) -> Vec<NodeDiagnostic> {
+    let mut errors = Vec::new();
+    // Some subscriptions end their lines with a bare CR
+    let content = if content.contains('\n') {
+        Cow::Borrowed(content)
+    } else {
+        Cow::Owned(content.replace('\r', "\n"))
+    };
+    explode_mixed_lines(&content, nodes, &mut errors, 0);
+    errors
+}
 
-        log::info!("Found {} lines in explode_sub process", lines.len());
+fn explode_mixed_lines(
+    content: &str,
+    nodes: &mut Vec<Proxy>,
+    errors: &mut Vec<NodeDiagnostic>,
+    depth: usize,
+) {
+    for (index, line) in content.lines().enumerate() {
+        let line = line.trim().trim_start_matches('\u{feff}').trim();
+        if line.is_empty() || line.starts_with('#') || line.starts_with("//") {
+            continue;
+        }
+        let mut error = |scheme: &str, reason: String| {
+            errors.push(NodeDiagnostic {
+                line: Some(index + 1),
+                scheme: scheme.to_string(),
+                reason,
+            })
+        };
 
-        for line in lines {
-            let line = line.trim().trim_end_matches('\r');
-            if line.is_empty() {
-                continue;
+        if link_scheme(line).is_none() {
+            if depth < MAX_MIXED_DEPTH && is_base64_blob(line) {
+                let decoded = url_safe_base64_decode(line);
+                if decoded
+                    .lines()
+                    .any(|line| link_scheme(line.trim()).is_some())
+                {
+                    explode_mixed_lines(&decoded, nodes, errors, depth + 1);
+                    continue;
+                }
             }
+            error("", format!("not a proxy link: {}", truncate_line(line)));
+            continue;
+        }
 
+        for link in split_links(line) {
+            let scheme = link_scheme(link).unwrap_or_default().to_ascii_lowercase();
             let mut node = Proxy::default();
-            if explode(line, &mut node) {
-                nodes.push(node);
+            if !explode(link, &mut node) {
+                error(&scheme, format!("invalid link {}", truncate_line(link)));
+                continue;
+            }
+            match check_node(&node) {
+                Ok(()) => nodes.push(node),
+                Err(reason) => error(&scheme, reason),
             }
         }
     }
+}
 
-    !nodes.is_empty()
+/// Whether `line` could be a base64 encoded link list
+fn is_base64_blob(line: &str) -> bool {
+    line.len() >= 8
+        && line
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '+' | '/' | '-' | '_' | '='))
+}
+
+/// Split a line holding several links at the whitespace before each scheme
+fn split_links(line: &str) -> Vec<&str> {
+    let mut links = Vec::new();
+    let mut start = 0;
+    for (index, _) in line.match_indices(char::is_whitespace) {
+        if index < start {
+            continue;
+        }
+        let rest = line[index..].trim_start();
+        if link_scheme(rest.split_whitespace().next().unwrap_or_default()).is_some() {
+            let link = line[start..index].trim();
+            if !link.is_empty() {
+                links.push(link);
+            }
+            start = line.len() - rest.len();
+        }
+    }
+    links.push(line[start..].trim());
+    links
+}
+
+/// Shorten a rejected line for the log
+fn truncate_line(line: &str) -> String {
+    match line.char_indices().nth(48) {
+        Some((index, _)) => format!("{}...", &line[..index]),
+        None => line.to_string(),
+    }
 }
 
 /// Explodes a configuration file content into a vector of Proxy objects
@@ -175,6 +382,21 @@ pub fn explode_sub(sub: &str, nodes: &mut Vec<Proxy>) -> bool {
 /// # Returns
 /// Number of nodes successfully parsed, or 0 if parsing failed
 pub fn explode_conf_content(content: &str, nodes: &mut Vec<Proxy>) -> i32 {
+    let mut diagnostics = Vec::new();
+    let count = explode_conf_content_with_diagnostics(content, nodes, &mut diagnostics);
+    log_diagnostics(&diagnostics);
+    count
+}
+
+/// [`explode_conf_content`], entries which yield no usable node are added to
+/// `diagnostics` instead of being logged
+///
+/// Nodes failing [`check_node`] are dropped whichever format they came from.
+pub fn explode_conf_content_with_diagnostics(
+    content: &str,
+    nodes: &mut Vec<Proxy>,
+    diagnostics: &mut Vec<NodeDiagnostic>,
+) -> i32 {
     // Trim the content
     let content = content.trim();
 
@@ -186,8 +408,14 @@ pub fn explode_conf_content(content: &str, nodes: &mut Vec<Proxy>) -> i32 {
     let orig_size = nodes.len();
     let mut parsed = false;
 
+    // Try to parse as sing-box configuration or outbound list
+    if (content.starts_with('{') || content.starts_with('['))
+        && super::singbox::explode_singbox(content, nodes)
+    {
+        parsed = true;
+    }
     // Try to parse as JSON
-    if content.starts_with('{') {
+    else if content.starts_with('{') {
         // Try to parse as V2Ray configuration
         if super::vmess::explode_vmess_conf(content, nodes) {
             parsed = true;
@@ -200,8 +428,11 @@ pub fn explode_conf_content(content: &str, nodes: &mut Vec<Proxy>) -> i32 {
         }
     }
     // Try to parse as YAML/Clash
-    else if content.contains("proxies:") || content.contains("Proxy:") {
-        if super::explode_clash::explode_clash(content, nodes) {
+    else if content.contains("proxies:")
+        || content.contains("Proxy:")
+        || super::explode_clash::is_clash_proxy_list(content)
+    {
+        if super::explode_clash::explode_clash_with_diagnostics(content, nodes, diagnostics) {
             parsed = true;
         }
     }
@@ -217,12 +448,24 @@ pub fn explode_conf_content(content: &str, nodes: &mut Vec<Proxy>) -> i32 {
             parsed = true;
         }
     }
+    // Try to parse as Loon configuration
+    else if content.contains("[Proxy]") && super::loon::is_loon_content(content) {
+        if super::loon::explode_loon(content, nodes) {
+            parsed = true;
+        }
+    }
     // Try to parse as Surge configuration
     else if content.contains("[Proxy]") {
         if super::surge::explode_surge(content, nodes) {
             parsed = true;
         }
     }
+    // Try to parse as Quantumult X configuration or server list
+    else if super::quanx::is_quanx_content(content) {
+        if super::quanx::explode_quanx(content, nodes) {
+            parsed = true;
+        }
+    }
     // Try to parse as Quantumult configuration
     else if content.contains(" = vmess")
         || content.contains(" = shadowsocks")
@@ -236,13 +479,126 @@ pub fn explode_conf_content(content: &str, nodes: &mut Vec<Proxy>) -> i32 {
     }
 
     // If no specific format was detected, try as a simple subscription
-    if !parsed && explode_sub(content, nodes) {
+    if !parsed && explode_sub_with_diagnostics(content, nodes, diagnostics) {
         parsed = true;
     }
 
+    drop_invalid_nodes(nodes, orig_size, diagnostics);
     if parsed {
         (nodes.len() - orig_size) as i32
     } else {
         0
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::ProxyType;
+
+    /// Every scheme the mixed parser knows, written the way providers do
+    const MIXED_FIXTURE: &str = "\u{feff}# Mixed subscription\r\n\
+        \r\n\
+        ss://YWVzLTI1Ni1nY206cGFzcw@1.1.1.1:8388#My SS Node\r\n\
+        ssr://ZXhhbXBsZS5jb206ODM4ODphdXRoX2FlczEyOF9tZDU6YWVzLTI1Ni1jZmI6dGxzMS4yX3RpY2tldF9hdXRoOmRHVnpkQT09Lz9vYmZzcGFyYW09ZEdWemRBPT0mcHJvdG9wYXJhbT1kR1Z6ZEE9PSZyZW1hcmtzPVZHVnpkQ0JUVTFJPSZncm91cD1WR1Z6ZENCVFUxST0=\r\n\
+        VMESS://eyJ2IjoiMiIsInBzIjoiVk1lc3MgTm9kZSIsImFkZCI6IjMuMy4zLjMiLCJwb3J0IjoiNDQzIiwiaWQiOiJiODMxMzgxZC02MzI0LTRkNTMtYWQ0Zi04Y2RhNDhiMzA4MTEiLCJhaWQiOiIwIiwibmV0Ijoid3MiLCJ0eXBlIjoibm9uZSIsImhvc3QiOiIiLCJwYXRoIjoiL3dzIiwidGxzIjoidGxzIn0=\r\n\
+        trojan://secret@2.2.2.2:443?sni=trojan.example.com#Trojan Node\r\n\
+        Hysteria2://pass@7.7.7.7:443?sni=example.com#HY2\r\n\
+        vless://b831381d-6324-4d53-ad4f-8cda48b30811@6.6.6.6:443?encryption=none&security=tls&type=ws&path=%2Fws#VLESS\r\n\
+        ss://YWVzLTI1Ni1nY206cGFzcw@8.8.8.8:8388#First trojan://secret@9.9.9.9:443#Second\r\n\
+        dHJvamFuOi8vc2VjcmV0QDQuNC40LjQ6NDQzI05lc3RlZCBPbmUKc3M6Ly9ZV1Z6TFRJMU5pMW5ZMjA2Y0dGemN3QDUuNS41LjU6ODM4OCNOZXN0ZWQgVHdv\r\n\
+        hello world\r\n\
+        unknown://whatever\r\n";
+
+    #[test]
+    fn test_explode_mixed_fixture() {
+        let mut nodes = Vec::new();
+        let errors = explode_mixed(MIXED_FIXTURE, &mut nodes);
+        let remarks: Vec<&str> = nodes.iter().map(|node| node.remark.as_str()).collect();
+        assert_eq!(
+            remarks,
+            [
+                "My SS Node",
+                "Test SSR",
+                "VMess Node",
+                "Trojan Node",
+                "HY2",
+                "VLESS",
+                "First",
+                "Second",
+                "Nested One",
+                "Nested Two"
+            ]
+        );
+        assert_eq!(nodes[2].proxy_type, ProxyType::VMess);
+        assert_eq!(nodes[4].proxy_type, ProxyType::Hysteria2);
+        assert_eq!(nodes[5].proxy_type, ProxyType::Vless);
+
+        let lines: Vec<Option<usize>> = errors.iter().map(|error| error.line).collect();
+        assert_eq!(lines, [Some(11), Some(12)], "{:?}", errors);
+        assert_eq!(errors[1].scheme, "unknown");
+        assert!(errors[1].reason.starts_with("invalid link unknown://"));
+    }
+
+    #[test]
+    fn test_explode_sub_keeps_remarks_with_spaces() {
+        let mut nodes = Vec::new();
+        assert!(explode_sub(
+            "ss://YWVzLTI1Ni1nY206cGFzcw@1.1.1.1:8388#Tokyo 01 ss://YWVzLTI1Ni1nY206cGFzcw@1.1.1.2:8388#Tokyo 02",
+            &mut nodes
+        ));
+        let remarks: Vec<&str> = nodes.iter().map(|node| node.remark.as_str()).collect();
+        assert_eq!(remarks, ["Tokyo 01", "Tokyo 02"]);
+    }
+
+    #[test]
+    fn test_invalid_ports_and_hosts_are_reported() {
+        use base64::Engine;
+
+        let vmess = |add: &str, port: &str| {
+            let json = format!(
+                r#"{{"v":"2","ps":"VMess","add":"{}","port":"{}","id":"b831381d-6324-4d53-ad4f-8cda48b30811","aid":"0","net":"tcp"}}"#,
+                add, port
+            );
+            format!(
+                "vmess://{}",
+                base64::engine::general_purpose::STANDARD.encode(json)
+            )
+        };
+        let content = format!(
+            "ss://YWVzLTI1Ni1nY206cGFzcw@1.1.1.1:65535#Max\n\
+             trojan://secret@[2001:db8::1]:443#V6\n\
+             {}\n\
+             {}\n\
+             ss://YWVzLTI1Ni1nY206cGFzcw@1.1.1.1:99999#Over\n",
+            vmess("", "443"),
+            vmess("1.1.1.1", "0")
+        );
+        let mut nodes = Vec::new();
+        let errors = explode_mixed(&content, &mut nodes);
+        let remarks: Vec<&str> = nodes.iter().map(|node| node.remark.as_str()).collect();
+        assert_eq!(remarks, ["Max", "V6"]);
+        assert_eq!(nodes[0].port, 65535);
+
+        let lines: Vec<Option<usize>> = errors.iter().map(|error| error.line).collect();
+        assert_eq!(lines, [Some(3), Some(4), Some(5)], "{:?}", errors);
+        assert_eq!(errors[0].scheme, "vmess");
+        assert!(errors[0].to_string().starts_with("line 3: vmess: "));
+        assert!(errors[2].reason.contains("99999"), "{}", errors[2]);
+
+        let clash = "proxies:\n  \
+            - {name: A, type: ss, server: 1.1.1.1, port: 8388, cipher: aes-256-gcm, password: pass}\n  \
+            - {name: B, type: ss, server: '', port: 8388, cipher: aes-256-gcm, password: pass}\n  \
+            - {name: C, type: ss, server: 1.1.1.1, port: 0, cipher: aes-256-gcm, password: pass}\n";
+        let mut nodes = Vec::new();
+        let mut diagnostics = Vec::new();
+        assert_eq!(
+            explode_conf_content_with_diagnostics(clash, &mut nodes, &mut diagnostics),
+            1
+        );
+        assert_eq!(nodes[0].remark, "A");
+        assert_eq!(diagnostics.len(), 2, "{:?}", diagnostics);
+        assert_eq!(diagnostics[0].reason, "empty host in 'B'");
+        assert_eq!(diagnostics[1].reason, "invalid port 0 in 'C'");
+    }
+}
@@ -4,17 +4,46 @@ use crate::{
         SNELL_DEFAULT_GROUP, SOCKS_DEFAULT_GROUP, SSR_DEFAULT_GROUP, SS_DEFAULT_GROUP,
         TROJAN_DEFAULT_GROUP, V2RAY_DEFAULT_GROUP, WG_DEFAULT_GROUP,
     },
-    parser::yaml::clash::parse_clash_yaml,
+    parser::yaml::clash::parse_clash_yaml_with_diagnostics,
 };
 use serde_yaml::Value;
 
+use super::NodeDiagnostic;
+
+/// Whether `content` is a bare YAML sequence of Clash proxies, the other
+/// form of a proxy provider file besides a `proxies:` only document
+pub fn is_clash_proxy_list(content: &str) -> bool {
+    let first_line = content
+        .lines()
+        .map(str::trim)
+        .find(|line| !line.is_empty() && !line.starts_with('#'));
+    first_line.is_some_and(|line| line.starts_with("- ") || line == "-")
+        && content.contains("type:")
+        && content.contains("server:")
+}
+
 /// Parse a Clash YAML configuration into a vector of Proxy objects
 pub fn explode_clash(content: &str, nodes: &mut Vec<Proxy>) -> bool {
+    let mut diagnostics = Vec::new();
+    let parsed = explode_clash_with_diagnostics(content, nodes, &mut diagnostics);
+    super::common::log_diagnostics(&diagnostics);
+    parsed
+}
+
+/// [`explode_clash`], proxies of unknown types and malformed ones are added to
+/// `diagnostics` while the rest of the config is still used
+pub fn explode_clash_with_diagnostics(
+    content: &str,
+    nodes: &mut Vec<Proxy>,
+    diagnostics: &mut Vec<NodeDiagnostic>,
+) -> bool {
     // 首先尝试使用新的YAML解析器
-    match parse_clash_yaml(content) {
+    let mut skipped = Vec::new();
+    match parse_clash_yaml_with_diagnostics(content, &mut skipped) {
         Ok(mut proxies) => {
             if !proxies.is_empty() {
                 nodes.append(&mut proxies);
+                diagnostics.append(&mut skipped);
                 return true;
             }
         }
@@ -31,9 +60,9 @@ pub fn explode_clash(content: &str, nodes: &mut Vec<Proxy>) -> bool {
         Err(_) => return false,
     };
 
-    // Extract proxies section
-    let proxies = match yaml.get("proxies") {
-        Some(Value::Sequence(seq)) => seq,
+    // Extract proxies section, a proxy provider file may be the bare list
+    let proxies = match (&yaml, yaml.get("proxies")) {
+        (Value::Sequence(seq), _) | (_, Some(Value::Sequence(seq))) => seq,
         _ => match yaml.get("Proxy") {
             Some(Value::Sequence(seq)) => seq,
             _ => return false,
@@ -50,6 +79,9 @@ pub fn explode_clash(content: &str, nodes: &mut Vec<Proxy>) -> bool {
         }
     }
 
+    if !success {
+        diagnostics.append(&mut skipped);
+    }
     success
 }
 
@@ -65,10 +97,18 @@ fn parse_clash_proxy(proxy: &Value) -> Option<Proxy> {
     let name = proxy.get("name").and_then(|v| v.as_str()).unwrap_or("");
     let server = proxy.get("server").and_then(|v| v.as_str()).unwrap_or("");
     let port_value = proxy.get("port").and_then(|v| v.as_u64()).unwrap_or(0);
-    let port = port_value as u16;
+    let Ok(port) = u16::try_from(port_value) else {
+        log::warn!(
+            "Skipped Clash proxy '{}': invalid port {}",
+            name,
+            port_value
+        );
+        return None;
+    };
 
-    // Skip if missing essential information
-    if name.is_empty() || server.is_empty() || port == 0 {
+    // Skip if missing essential information, an empty server or port 0 are
+    // reported when the nodes are checked
+    if name.is_empty() {
         return None;
     }
 
@@ -78,7 +118,7 @@ fn parse_clash_proxy(proxy: &Value) -> Option<Proxy> {
     let skip_cert_verify = proxy.get("skip-cert-verify").and_then(|v| v.as_bool());
 
     // Process based on proxy type
-    match proxy_type.as_str() {
+    let mut node = match proxy_type.as_str() {
         "ss" | "shadowsocks" => {
             parse_clash_ss(proxy, name, server, port, udp, tfo, skip_cert_verify)
         }
@@ -97,7 +137,15 @@ fn parse_clash_proxy(proxy: &Value) -> Option<Proxy> {
         "hysteria" => parse_clash_hysteria(proxy, name, server, port, tfo, skip_cert_verify),
         "hysteria2" => parse_clash_hysteria2(proxy, name, server, port, tfo, skip_cert_verify),
         _ => None,
-    }
+    }?;
+
+    // Kept for Surge, which takes `block-quic` for every policy
+    node.block_quic = match proxy.get("block-quic") {
+        Some(Value::Bool(block)) => Some(*block),
+        Some(Value::String(value)) => super::surge::parse_block_quic(value),
+        _ => None,
+    };
+    Some(node)
 }
 
 /// Parse a Shadowsocks proxy from Clash YAML
@@ -728,6 +776,19 @@ fn parse_clash_wireguard(
 }
 
 /// Parse a Hysteria proxy from Clash YAML
+/// The `alpn` of `proxy`, a sequence or a single comma separated string
+fn alpn_values(proxy: &Value) -> Vec<String> {
+    match proxy.get("alpn") {
+        Some(Value::Sequence(seq)) => seq
+            .iter()
+            .filter_map(Value::as_str)
+            .map(str::to_string)
+            .collect(),
+        Some(Value::String(alpn)) => vec![alpn.to_string()],
+        _ => Vec::new(),
+    }
+}
+
 fn parse_clash_hysteria(
     proxy: &Value,
     name: &str,
@@ -770,11 +831,7 @@ fn parse_clash_hysteria(
 
     // Get TLS settings
     let sni = proxy.get("sni").and_then(|v| v.as_str()).unwrap_or("");
-    let alpn_value = proxy.get("alpn").and_then(|v| v.as_str()).unwrap_or("");
-    let mut alpn = Vec::new();
-    if !alpn_value.is_empty() {
-        alpn.push(alpn_value.to_string());
-    }
+    let alpn = alpn_values(proxy);
 
     let fingerprint = proxy
         .get("fingerprint")
@@ -898,15 +955,7 @@ fn parse_clash_hysteria2(
         Some(v) => Some(v.to_owned()),
         None => None,
     };
-    let alpn = proxy
-        .get("alpn")
-        .and_then(|v| v.as_sequence())
-        .map(|v| {
-            v.iter()
-                .map(|v| v.as_str().unwrap_or("").to_owned())
-                .collect()
-        })
-        .unwrap_or_default();
+    let alpn = alpn_values(proxy);
 
     let fingerprint = match proxy.get("fingerprint").and_then(|v| v.as_str()) {
         Some(v) => Some(v.to_owned()),
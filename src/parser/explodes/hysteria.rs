@@ -1,4 +1,8 @@
-use crate::{models::HYSTERIA_DEFAULT_GROUP, utils::url_decode, Proxy};
+use crate::{
+    models::HYSTERIA_DEFAULT_GROUP,
+    utils::{network::strip_ipv6_brackets, url_decode},
+    Proxy,
+};
 use std::collections::HashMap;
 use url::Url;
 
@@ -17,7 +21,7 @@ pub fn explode_hysteria(hysteria: &str, node: &mut Proxy) -> bool {
 
     // Extract host and port
     let host = match url.host_str() {
-        Some(host) => host,
+        Some(host) => strip_ipv6_brackets(host),
         None => return false,
     };
     let port = url.port().unwrap_or(443);
@@ -54,7 +58,11 @@ pub fn explode_hysteria(hysteria: &str, node: &mut Proxy) -> bool {
     let obfs_param = params.get("obfsParam").map(|s| s.as_str()).unwrap_or("");
 
     // Extract SNI
-    let sni = params.get("peer").map(|s| s.as_str()).unwrap_or(host);
+    let sni = params
+        .get("sni")
+        .or_else(|| params.get("peer"))
+        .map(|s| s.as_str())
+        .unwrap_or(host);
 
     // Extract insecure
     let insecure = params
@@ -0,0 +1,363 @@
+use std::collections::HashMap;
+
+use crate::models::{
+    Proxy, HTTP_DEFAULT_GROUP, SOCKS_DEFAULT_GROUP, SSR_DEFAULT_GROUP, SS_DEFAULT_GROUP,
+    TROJAN_DEFAULT_GROUP, V2RAY_DEFAULT_GROUP,
+};
+
+/// Options only Loon writes, Surge spells them differently
+const LOON_ONLY_OPTIONS: [&str; 6] = [
+    "transport",
+    "over-tls",
+    "tls-name",
+    "alterId",
+    "obfs-name",
+    "protocol-param",
+];
+
+/// Whether the `[Proxy]` section of `content` is written in Loon's syntax
+/// rather than Surge's, both use the same section name
+pub fn is_loon_content(content: &str) -> bool {
+    proxy_lines(content).any(|(_, config)| {
+        let parts = split_parts(config);
+        matches!(parts[0], "Shadowsocks" | "ShadowsocksR")
+            || parts
+                .iter()
+                .skip(1)
+                .any(|part| part.starts_with('"') && part.ends_with('"') && part.len() > 1)
+            || parts.iter().skip(1).any(|part| {
+                split_option(part).is_some_and(|(key, _)| LOON_ONLY_OPTIONS.contains(&key))
+            })
+    })
+}
+
+/// Parse the `[Proxy]` section of a Loon configuration into a vector of Proxy
+/// objects, e.g.
+/// `Name = vmess, example.com, 443, auto, "uuid", transport=ws, path=/ws, over-tls=true`
+///
+/// Options may be written as `key=value` or `key:value`. Every line is read
+/// from node lists without a `[Proxy]` section.
+pub fn explode_loon(content: &str, nodes: &mut Vec<Proxy>) -> bool {
+    let mut success = false;
+
+    for (name, config) in proxy_lines(content) {
+        let mut node = Proxy::default();
+        if parse_loon_proxy(name, config, &mut node) {
+            nodes.push(node);
+            success = true;
+        }
+    }
+
+    success
+}
+
+/// `(name, config)` of every line in the `[Proxy]` section, or of every line
+/// when there is none
+fn proxy_lines(content: &str) -> impl Iterator<Item = (&str, &str)> {
+    let mut in_proxy_section = !content.contains("[Proxy]");
+    content.lines().filter_map(move |line| {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') || line.starts_with(';') {
+            return None;
+        }
+        if line.starts_with('[') && line.ends_with(']') {
+            in_proxy_section = line == "[Proxy]";
+            return None;
+        }
+        if !in_proxy_section {
+            return None;
+        }
+        let (name, config) = line.split_once('=')?;
+        Some((name.trim(), config.trim()))
+    })
+}
+
+/// Split a proxy line at the commas outside of quotes
+fn split_parts(config: &str) -> Vec<&str> {
+    let mut parts = Vec::new();
+    let mut start = 0;
+    let mut quoted = false;
+    for (i, c) in config.char_indices() {
+        match c {
+            '"' => quoted = !quoted,
+            ',' if !quoted => {
+                parts.push(config[start..i].trim());
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+    parts.push(config[start..].trim());
+    parts
+}
+
+/// Split a `key=value` or `key:value` option, positional values like quoted
+/// passwords are not options
+fn split_option(part: &str) -> Option<(&str, &str)> {
+    let index = part.find(['=', ':'])?;
+    let key = &part[..index];
+    if key.is_empty() || !key.chars().all(|c| c.is_ascii_alphanumeric() || c == '-') {
+        return None;
+    }
+    Some((key, part[index + 1..].trim().trim_matches('"')))
+}
+
+fn unquote(value: &str) -> &str {
+    value.trim_matches('"')
+}
+
+/// Parse a single proxy line, `config` is what follows `Name =`
+fn parse_loon_proxy(name: &str, config: &str, node: &mut Proxy) -> bool {
+    let parts = split_parts(config);
+    if parts.len() < 3 {
+        return false;
+    }
+
+    let proxy_type = parts[0].to_lowercase();
+    let server = parts[1];
+    let port = match parts[2].parse::<u16>() {
+        Ok(p) => p,
+        Err(_) => return false,
+    };
+    if port == 0 {
+        return false;
+    }
+
+    // Positional values come before the options
+    let positional: Vec<&str> = parts[3..]
+        .iter()
+        .take_while(|part| split_option(part).is_none())
+        .map(|part| unquote(part))
+        .collect();
+    let options: HashMap<&str, &str> = parts[3 + positional.len()..]
+        .iter()
+        .filter_map(|part| split_option(part))
+        .collect();
+    let option = |key: &str| options.get(key).copied().unwrap_or_default();
+    let flag = |key: &str| {
+        options
+            .get(key)
+            .map(|value| *value == "true" || *value == "1")
+    };
+    let positional_at = |index: usize| positional.get(index).copied().unwrap_or_default();
+
+    let udp = flag("udp");
+    let tfo = flag("fast-open");
+    let scv = flag("skip-cert-verify");
+
+    match proxy_type.as_str() {
+        "shadowsocks" => {
+            if positional.len() < 2 {
+                return false;
+            }
+            let obfs = match option("obfs-name") {
+                "" => option("obfs"),
+                obfs => obfs,
+            };
+            let (plugin, plugin_opts) = match obfs {
+                "http" | "tls" => {
+                    let mut opts = format!("obfs={}", obfs);
+                    if !option("obfs-host").is_empty() {
+                        opts.push_str(&format!(";obfs-host={}", option("obfs-host")));
+                    }
+                    ("simple-obfs", opts)
+                }
+                _ => ("", String::new()),
+            };
+            *node = Proxy::ss_construct(
+                SS_DEFAULT_GROUP,
+                name,
+                server,
+                port,
+                positional[1],
+                positional[0],
+                plugin,
+                &plugin_opts,
+                udp,
+                tfo,
+                scv,
+                None,
+                "",
+            );
+        }
+        "shadowsocksr" => {
+            if positional.len() < 2 {
+                return false;
+            }
+            *node = Proxy::ssr_construct(
+                SSR_DEFAULT_GROUP,
+                name,
+                server,
+                port,
+                option("protocol"),
+                positional[0],
+                option("obfs"),
+                positional[1],
+                option("obfs-param"),
+                option("protocol-param"),
+                udp,
+                tfo,
+                scv,
+                "",
+            );
+        }
+        "vmess" => {
+            if positional.len() < 2 {
+                return false;
+            }
+            let net = match option("transport") {
+                "" => "tcp",
+                transport => transport,
+            };
+            let tls = if flag("over-tls") == Some(true) {
+                "tls"
+            } else {
+                ""
+            };
+            *node = Proxy::vmess_construct(
+                V2RAY_DEFAULT_GROUP,
+                name,
+                server,
+                port,
+                "",
+                positional[1],
+                option("alterId").parse::<u16>().unwrap_or(0),
+                net,
+                positional[0],
+                option("path"),
+                option("host"),
+                "",
+                tls,
+                option("tls-name"),
+                udp,
+                tfo,
+                scv,
+                None,
+                "",
+            );
+        }
+        "trojan" => {
+            if positional.is_empty() {
+                return false;
+            }
+            let ws = option("transport") == "ws";
+            let not_empty = |value: &str| (!value.is_empty()).then(|| value.to_string());
+            *node = Proxy::trojan_construct(
+                TROJAN_DEFAULT_GROUP.to_string(),
+                name.to_string(),
+                server.to_string(),
+                port,
+                positional[0].to_string(),
+                ws.then(|| "ws".to_string()),
+                not_empty(option("host")),
+                not_empty(option("path")),
+                not_empty(option("tls-name")),
+                true,
+                udp,
+                tfo,
+                scv,
+                None,
+                None,
+            );
+        }
+        "http" | "https" => {
+            *node = Proxy::http_construct(
+                HTTP_DEFAULT_GROUP,
+                name,
+                server,
+                port,
+                positional_at(0),
+                positional_at(1),
+                proxy_type == "https" || flag("over-tls") == Some(true),
+                tfo,
+                scv,
+                None,
+                "",
+            );
+        }
+        "socks5" => {
+            *node = Proxy::socks_construct(
+                SOCKS_DEFAULT_GROUP,
+                name,
+                server,
+                port,
+                positional_at(0),
+                positional_at(1),
+                udp,
+                tfo,
+                scv,
+                "",
+            );
+        }
+        _ => return false,
+    }
+
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::ProxyType;
+
+    const LOON_CONF: &str = r#"[General]
+skip-proxy = 192.168.0.0/16
+
+[Proxy]
+SS = Shadowsocks,1.2.3.4,443,aes-128-gcm,"pass,word",obfs-name=http,obfs-host=bing.com,fast-open=false,udp=true
+SSR = ShadowsocksR,2.3.4.5,443,aes-256-cfb,"pass",protocol=auth_aes128_md5,protocol-param=,obfs=tls1.2_ticket_auth,obfs-param=bing.com
+VMess = vmess, 3.4.5.6, 443, auto, "b831381d-6324-4d53-ad4f-8cda48b30811", transport:ws, path:/x, host:cdn.example.com, over-tls:true, alterId:2
+Trojan = trojan,4.5.6.7,443,"secret",tls-name=trojan.example.com,skip-cert-verify=true
+HTTP = http,5.6.7.8,8080,user,"pass"
+
+[Proxy Group]
+Proxy = select,SS,VMess
+"#;
+
+    #[test]
+    fn test_explode_loon_config() {
+        assert!(is_loon_content(LOON_CONF));
+        let mut nodes = Vec::new();
+        assert!(explode_loon(LOON_CONF, &mut nodes));
+        let names: Vec<&str> = nodes.iter().map(|node| node.remark.as_str()).collect();
+        assert_eq!(names, ["SS", "SSR", "VMess", "Trojan", "HTTP"]);
+
+        let ss = &nodes[0];
+        assert_eq!(ss.encrypt_method.as_deref(), Some("aes-128-gcm"));
+        assert_eq!(ss.password.as_deref(), Some("pass,word"));
+        assert_eq!(
+            ss.plugin_option.as_deref(),
+            Some("obfs=http;obfs-host=bing.com")
+        );
+        assert_eq!(ss.udp, Some(true));
+
+        assert_eq!(nodes[1].proxy_type, ProxyType::ShadowsocksR);
+        assert_eq!(nodes[1].protocol.as_deref(), Some("auth_aes128_md5"));
+
+        let vmess = &nodes[2];
+        assert_eq!(
+            vmess.user_id.as_deref(),
+            Some("b831381d-6324-4d53-ad4f-8cda48b30811")
+        );
+        assert_eq!(vmess.alter_id, 2);
+        assert_eq!(vmess.transfer_protocol.as_deref(), Some("ws"));
+        assert_eq!(vmess.path.as_deref(), Some("/x"));
+        assert_eq!(vmess.host.as_deref(), Some("cdn.example.com"));
+        assert!(vmess.tls_secure);
+
+        let trojan = &nodes[3];
+        assert_eq!(trojan.password.as_deref(), Some("secret"));
+        assert_eq!(trojan.sni.as_deref(), Some("trojan.example.com"));
+        assert_eq!(trojan.allow_insecure, Some(true));
+
+        assert_eq!(nodes[4].username.as_deref(), Some("user"));
+        assert_eq!(nodes[4].password.as_deref(), Some("pass"));
+    }
+
+    #[test]
+    fn test_surge_proxy_section_is_not_loon() {
+        let surge = "[Proxy]\n\
+                     VMess = vmess, 1.2.3.4, 443, username=b831381d-6324-4d53-ad4f-8cda48b30811, ws=true, tls=true\n";
+        assert!(!is_loon_content(surge));
+    }
+}
@@ -4,8 +4,11 @@ mod http;
 mod httpsub;
 mod hysteria;
 mod hysteria2;
+mod loon;
 mod netch;
 mod quan;
+mod quanx;
+mod singbox;
 mod snell;
 mod socks;
 mod ss;
@@ -17,17 +20,26 @@ mod vless;
 mod vmess;
 mod wireguard;
 
-pub use common::{explode, explode_conf_content, explode_sub};
-pub use explode_clash::explode_clash;
+pub use common::{
+    check_node, drop_invalid_nodes, explode, explode_conf_content,
+    explode_conf_content_with_diagnostics, explode_mixed, explode_sub, log_diagnostics,
+    NodeDiagnostic,
+};
+pub use explode_clash::{explode_clash, explode_clash_with_diagnostics, is_clash_proxy_list};
 pub use http::explode_http;
 pub use httpsub::explode_http_sub;
 pub use hysteria::explode_hysteria;
 pub use hysteria2::{explode_hysteria2, explode_std_hysteria2};
+pub use loon::{explode_loon, is_loon_content};
 pub use netch::{explode_netch, explode_netch_conf};
 pub use quan::explode_quan;
+pub use quanx::{explode_quanx, is_quanx_content};
+pub use singbox::explode_singbox;
 pub use snell::{explode_snell, explode_snell_surge};
 pub use socks::explode_socks;
-pub use ss::{explode_ss, explode_ss_android, explode_ss_conf, explode_ssd};
+pub use ss::{
+    explode_ss, explode_ss_android, explode_ss_conf, explode_ssd, explode_ssd_with_info,
+};
 pub use ssr::{explode_ssr, explode_ssr_conf};
 pub use sstap::explode_sstap;
 pub use surge::explode_surge;
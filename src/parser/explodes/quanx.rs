@@ -0,0 +1,325 @@
+use std::collections::HashMap;
+
+use crate::models::{
+    Proxy, HTTP_DEFAULT_GROUP, SOCKS_DEFAULT_GROUP, SSR_DEFAULT_GROUP, SS_DEFAULT_GROUP,
+    TROJAN_DEFAULT_GROUP, V2RAY_DEFAULT_GROUP,
+};
+
+const QUANX_TYPES: [&str; 5] = ["shadowsocks", "vmess", "trojan", "http", "socks5"];
+
+/// Whether `content` is a Quantumult X config or a bare list of its
+/// `server_local` lines
+pub fn is_quanx_content(content: &str) -> bool {
+    content.contains("[server_local]")
+        || content
+            .lines()
+            .any(|line| split_server_type(line.trim()).is_some())
+}
+
+/// Parse Quantumult X `server_local` lines into a vector of Proxy objects, e.g.
+/// `vmess=example.com:443, method=aes-128-gcm, password=<uuid>, obfs=wss, tag=Name`
+///
+/// Only the `[server_local]` section is read when the content is a whole
+/// config, every line otherwise.
+pub fn explode_quanx(content: &str, nodes: &mut Vec<Proxy>) -> bool {
+    let mut in_server_section = !content.contains("[server_local]");
+    let mut success = false;
+
+    for line in content.lines() {
+        // Skip empty lines and comments
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') || line.starts_with(';') {
+            continue;
+        }
+
+        if line.starts_with('[') && line.ends_with(']') {
+            in_server_section = line == "[server_local]";
+            continue;
+        }
+        if !in_server_section {
+            continue;
+        }
+
+        let mut node = Proxy::default();
+        if parse_quanx_line(line, &mut node) {
+            nodes.push(node);
+            success = true;
+        }
+    }
+
+    success
+}
+
+/// Split `vmess=host:port, ...` into the server type and the rest
+fn split_server_type(line: &str) -> Option<(&str, &str)> {
+    let (server_type, config) = line.split_once('=')?;
+    let server_type = server_type.trim();
+    QUANX_TYPES
+        .contains(&server_type)
+        .then_some((server_type, config.trim()))
+}
+
+/// Parse a single `server_local` line
+fn parse_quanx_line(line: &str, node: &mut Proxy) -> bool {
+    let Some((server_type, config)) = split_server_type(line) else {
+        return false;
+    };
+
+    let mut parts = config.split(',').map(str::trim);
+    let Some((server, port)) = parts.next().and_then(|address| address.rsplit_once(':')) else {
+        return false;
+    };
+    let server = server.trim_start_matches('[').trim_end_matches(']');
+    let port = match port.parse::<u16>() {
+        Ok(p) => p,
+        Err(_) => return false,
+    };
+    if port == 0 {
+        return false;
+    }
+
+    let options: HashMap<&str, &str> = parts
+        .filter_map(|part| part.split_once('='))
+        .map(|(key, value)| (key.trim(), value.trim().trim_matches('"')))
+        .collect();
+    let option = |key: &str| options.get(key).copied().unwrap_or_default();
+    let flag = |key: &str| {
+        options
+            .get(key)
+            .map(|value| *value == "true" || *value == "1")
+    };
+
+    let default_name = format!("{}:{}", server, port);
+    let name = match option("tag") {
+        "" => default_name.as_str(),
+        tag => tag,
+    };
+    let udp = flag("udp-relay");
+    let tfo = flag("fast-open");
+    let scv = flag("tls-verification").map(|verify| !verify);
+    let tls13 = flag("tls13");
+    let obfs = option("obfs");
+    let obfs_host = option("obfs-host");
+    let obfs_uri = option("obfs-uri");
+
+    match server_type {
+        "shadowsocks" if options.contains_key("ssr-protocol") => {
+            *node = Proxy::ssr_construct(
+                SSR_DEFAULT_GROUP,
+                name,
+                server,
+                port,
+                option("ssr-protocol"),
+                option("method"),
+                if obfs.is_empty() { "plain" } else { obfs },
+                option("password"),
+                obfs_host,
+                option("ssr-protocol-param"),
+                udp,
+                tfo,
+                scv,
+                "",
+            );
+        }
+        "shadowsocks" => {
+            let (plugin, plugin_opts) = match obfs {
+                "http" | "tls" => {
+                    let mut opts = format!("obfs={}", obfs);
+                    if !obfs_host.is_empty() {
+                        opts.push_str(&format!(";obfs-host={}", obfs_host));
+                    }
+                    ("simple-obfs", opts)
+                }
+                "ws" | "wss" => {
+                    let mut opts = "mode=websocket;".to_string();
+                    if obfs == "wss" {
+                        opts.push_str("tls;");
+                    }
+                    if !obfs_host.is_empty() {
+                        opts.push_str(&format!("host={};", obfs_host));
+                    }
+                    if !obfs_uri.is_empty() {
+                        opts.push_str(&format!("path={};", obfs_uri));
+                    }
+                    ("v2ray-plugin", opts)
+                }
+                _ => ("", String::new()),
+            };
+            *node = Proxy::ss_construct(
+                SS_DEFAULT_GROUP,
+                name,
+                server,
+                port,
+                option("password"),
+                option("method"),
+                plugin,
+                &plugin_opts,
+                udp,
+                tfo,
+                scv,
+                tls13,
+                "",
+            );
+        }
+        "vmess" => {
+            let net = if obfs == "ws" || obfs == "wss" {
+                "ws"
+            } else {
+                "tcp"
+            };
+            let tls = if obfs == "wss" || obfs == "over-tls" {
+                "tls"
+            } else {
+                ""
+            };
+            // Without AEAD the server expects a legacy alterId
+            let aid = if option("aead") == "false" { 1 } else { 0 };
+            *node = Proxy::vmess_construct(
+                V2RAY_DEFAULT_GROUP,
+                name,
+                server,
+                port,
+                "",
+                option("password"),
+                aid,
+                net,
+                match option("method") {
+                    "" => "auto",
+                    method => method,
+                },
+                obfs_uri,
+                obfs_host,
+                "",
+                tls,
+                if tls.is_empty() { "" } else { obfs_host },
+                udp,
+                tfo,
+                scv,
+                tls13,
+                "",
+            );
+        }
+        "trojan" => {
+            let ws = obfs == "ws" || obfs == "wss";
+            let sni = match option("tls-host") {
+                "" if obfs == "wss" || obfs == "over-tls" => obfs_host,
+                host => host,
+            };
+            *node = Proxy::trojan_construct(
+                TROJAN_DEFAULT_GROUP.to_string(),
+                name.to_string(),
+                server.to_string(),
+                port,
+                option("password").to_string(),
+                ws.then(|| "ws".to_string()),
+                (ws && !obfs_host.is_empty()).then(|| obfs_host.to_string()),
+                (ws && !obfs_uri.is_empty()).then(|| obfs_uri.to_string()),
+                (!sni.is_empty()).then(|| sni.to_string()),
+                option("over-tls") != "false" || obfs == "wss" || obfs == "over-tls",
+                udp,
+                tfo,
+                scv,
+                tls13,
+                None,
+            );
+        }
+        "http" => {
+            *node = Proxy::http_construct(
+                HTTP_DEFAULT_GROUP,
+                name,
+                server,
+                port,
+                option("username"),
+                option("password"),
+                option("over-tls") == "true",
+                tfo,
+                scv,
+                tls13,
+                "",
+            );
+        }
+        _ => {
+            *node = Proxy::socks_construct(
+                SOCKS_DEFAULT_GROUP,
+                name,
+                server,
+                port,
+                option("username"),
+                option("password"),
+                udp,
+                tfo,
+                scv,
+                "",
+            );
+        }
+    }
+
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::ProxyType;
+
+    #[test]
+    fn test_explode_quanx_config() {
+        let content = r#"[general]
+server_check_url=http://www.gstatic.com/generate_204
+
+[server_local]
+shadowsocks=1.2.3.4:8388, method=aes-128-gcm, password=pass, obfs=wss, obfs-host=cdn.example.com, obfs-uri=/ss, fast-open=true, udp-relay=true, tag=SS
+vmess=example.com:443, method=chacha20-ietf-poly1305, password=b831381d-6324-4d53-ad4f-8cda48b30811, obfs=wss, obfs-host=example.com, obfs-uri=/ws, tls-verification=false, tag=VMess
+trojan=2.3.4.5:443, password=secret, over-tls=true, tls-host=trojan.example.com, tag=Trojan
+http=3.4.5.6:8080, username=user, password=pass, over-tls=true, tag=HTTPS
+
+[filter_local]
+final, direct
+"#;
+        assert!(is_quanx_content(content));
+        let mut nodes = Vec::new();
+        assert!(explode_quanx(content, &mut nodes));
+        let names: Vec<&str> = nodes.iter().map(|node| node.remark.as_str()).collect();
+        assert_eq!(names, ["SS", "VMess", "Trojan", "HTTPS"]);
+
+        let ss = &nodes[0];
+        assert_eq!(ss.plugin.as_deref(), Some("v2ray-plugin"));
+        assert_eq!(
+            ss.plugin_option.as_deref(),
+            Some("mode=websocket;tls;host=cdn.example.com;path=/ss;")
+        );
+        assert_eq!(ss.tcp_fast_open, Some(true));
+        assert_eq!(ss.udp, Some(true));
+
+        let vmess = &nodes[1];
+        assert_eq!(vmess.transfer_protocol.as_deref(), Some("ws"));
+        assert!(vmess.tls_secure);
+        assert_eq!(vmess.path.as_deref(), Some("/ws"));
+        assert_eq!(vmess.allow_insecure, Some(true));
+
+        let trojan = &nodes[2];
+        assert_eq!(trojan.sni.as_deref(), Some("trojan.example.com"));
+        assert!(trojan.tls_secure);
+
+        assert_eq!(nodes[3].proxy_type, ProxyType::HTTPS);
+    }
+
+    #[test]
+    fn test_explode_quanx_server_list() {
+        let content = "shadowsocks=1.2.3.4:8388, method=aes-128-gcm, password=pass, obfs=http, obfs-host=bing.com\n\
+                       vmess=[2001:db8::1]:80, method=none, password=b831381d-6324-4d53-ad4f-8cda48b30811, aead=false";
+        assert!(is_quanx_content(content));
+        let mut nodes = Vec::new();
+        assert!(explode_quanx(content, &mut nodes));
+        assert_eq!(nodes[0].remark, "1.2.3.4:8388");
+        assert_eq!(
+            nodes[0].plugin_option.as_deref(),
+            Some("obfs=http;obfs-host=bing.com")
+        );
+        assert_eq!(nodes[1].hostname, "2001:db8::1");
+        assert_eq!(nodes[1].alter_id, 1);
+        assert!(!is_quanx_content(
+            "ss://YWVzLTI1Ni1nY206cGFzcw@1.2.3.4:8388"
+        ));
+    }
+}
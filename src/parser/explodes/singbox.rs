@@ -0,0 +1,496 @@
+use std::collections::HashMap;
+
+use log::{debug, warn};
+use serde_json::Value;
+
+use crate::models::proxy_node::combined::CombinedProxy;
+use crate::models::proxy_node::vless::VlessProxy;
+use crate::models::{
+    parse_alpn, Proxy, ProxyType, HTTP_DEFAULT_GROUP, HYSTERIA2_DEFAULT_GROUP, SOCKS_DEFAULT_GROUP,
+    SS_DEFAULT_GROUP, TROJAN_DEFAULT_GROUP, V2RAY_DEFAULT_GROUP, WG_DEFAULT_GROUP,
+};
+
+/// Parse a sing-box configuration, or a bare array of its outbounds, into a
+/// vector of Proxy objects
+///
+/// Tags become remarks. Selector, urltest, direct, block and dns outbounds are
+/// skipped, and so are TUIC outbounds and `multiplex` options, which have no
+/// counterpart in `Proxy` yet. WireGuard is read from both `outbounds` and
+/// the newer `endpoints`.
+pub fn explode_singbox(content: &str, nodes: &mut Vec<Proxy>) -> bool {
+    let json: Value = match serde_json::from_str(content) {
+        Ok(json) => json,
+        Err(_) => return false,
+    };
+
+    let outbounds: Vec<&Value> = match &json {
+        Value::Array(outbounds) => outbounds.iter().collect(),
+        Value::Object(config) => ["outbounds", "endpoints"]
+            .iter()
+            .filter_map(|key| config.get(*key).and_then(Value::as_array))
+            .flatten()
+            .collect(),
+        _ => return false,
+    };
+
+    let orig_size = nodes.len();
+    for outbound in outbounds {
+        let outbound_type = str_field(outbound, "type");
+        match outbound_type {
+            "selector" | "urltest" | "direct" | "block" | "dns" | "" => continue,
+            _ => {}
+        }
+        match parse_singbox_outbound(outbound_type, outbound) {
+            Some(node) => nodes.push(node),
+            None => warn!(
+                "Skipping sing-box outbound '{}' of type {}",
+                str_field(outbound, "tag"),
+                outbound_type
+            ),
+        }
+    }
+
+    nodes.len() > orig_size
+}
+
+fn str_field<'a>(value: &'a Value, key: &str) -> &'a str {
+    value.get(key).and_then(Value::as_str).unwrap_or_default()
+}
+
+fn bool_field(value: &Value, key: &str) -> Option<bool> {
+    value.get(key).and_then(Value::as_bool)
+}
+
+fn opt_string(value: &str) -> Option<String> {
+    (!value.is_empty()).then(|| value.to_string())
+}
+
+/// The `tls` object of an outbound, flattened
+#[derive(Default)]
+struct TlsOptions {
+    enabled: bool,
+    server_name: String,
+    insecure: Option<bool>,
+    alpn: Option<Vec<String>>,
+    fingerprint: String,
+    reality_public_key: String,
+    reality_short_id: String,
+}
+
+fn tls_options(outbound: &Value) -> TlsOptions {
+    let Some(tls) = outbound.get("tls") else {
+        return TlsOptions::default();
+    };
+    let reality = tls.get("reality").unwrap_or(&Value::Null);
+    TlsOptions {
+        enabled: bool_field(tls, "enabled").unwrap_or(false),
+        server_name: str_field(tls, "server_name").to_string(),
+        insecure: bool_field(tls, "insecure"),
+        alpn: match tls.get("alpn") {
+            Some(Value::Array(alpn)) => parse_alpn(alpn.iter().filter_map(Value::as_str)),
+            Some(Value::String(alpn)) => parse_alpn([alpn]),
+            _ => None,
+        },
+        fingerprint: str_field(tls.get("utls").unwrap_or(&Value::Null), "fingerprint").to_string(),
+        reality_public_key: str_field(reality, "public_key").to_string(),
+        reality_short_id: str_field(reality, "short_id").to_string(),
+    }
+}
+
+/// The `transport` object of an outbound, flattened into network, path
+/// (or gRPC service name) and host
+fn transport_options(outbound: &Value) -> (String, String, String) {
+    let Some(transport) = outbound.get("transport") else {
+        return ("tcp".to_string(), String::new(), String::new());
+    };
+    let network = match str_field(transport, "type") {
+        "" => "tcp",
+        network => network,
+    };
+    let path = match network {
+        "grpc" => str_field(transport, "service_name"),
+        _ => str_field(transport, "path"),
+    };
+    let host = transport
+        .get("headers")
+        .and_then(|headers| headers.get("Host"))
+        .or_else(|| transport.get("host"))
+        .map(|host| match host {
+            Value::Array(hosts) => hosts.first().and_then(Value::as_str).unwrap_or_default(),
+            host => host.as_str().unwrap_or_default(),
+        })
+        .unwrap_or_default();
+    (network.to_string(), path.to_string(), host.to_string())
+}
+
+/// Parse a single outbound, `None` when it cannot be represented
+fn parse_singbox_outbound(outbound_type: &str, outbound: &Value) -> Option<Proxy> {
+    let remark = str_field(outbound, "tag");
+    let server = str_field(outbound, "server");
+    let port = outbound
+        .get("server_port")
+        .and_then(Value::as_u64)
+        .and_then(|port| u16::try_from(port).ok())
+        .unwrap_or(0);
+    let tfo = bool_field(outbound, "tcp_fast_open");
+    let udp = match str_field(outbound, "network") {
+        "tcp" => Some(false),
+        _ => None,
+    };
+    let tls = tls_options(outbound);
+    if outbound.get("multiplex").is_some() {
+        debug!(
+            "Ignoring multiplex options of sing-box outbound '{}'",
+            remark
+        );
+    }
+
+    if outbound_type == "wireguard" {
+        return parse_singbox_wireguard(outbound, remark, server, port);
+    }
+    if server.is_empty() || port == 0 {
+        return None;
+    }
+
+    let node = match outbound_type {
+        "shadowsocks" => {
+            let plugin = match str_field(outbound, "plugin") {
+                "obfs-local" => "simple-obfs",
+                plugin => plugin,
+            };
+            Proxy::ss_construct(
+                SS_DEFAULT_GROUP,
+                remark,
+                server,
+                port,
+                str_field(outbound, "password"),
+                str_field(outbound, "method"),
+                plugin,
+                str_field(outbound, "plugin_opts"),
+                udp,
+                tfo,
+                None,
+                None,
+                "",
+            )
+        }
+        "vmess" => {
+            let (network, path, host) = transport_options(outbound);
+            let cipher = match str_field(outbound, "security") {
+                "" => "auto",
+                security => security,
+            };
+            Proxy::vmess_construct(
+                V2RAY_DEFAULT_GROUP,
+                remark,
+                server,
+                port,
+                "",
+                str_field(outbound, "uuid"),
+                outbound
+                    .get("alter_id")
+                    .and_then(Value::as_u64)
+                    .unwrap_or(0) as u16,
+                &network,
+                cipher,
+                &path,
+                &host,
+                "",
+                if tls.enabled { "tls" } else { "" },
+                &tls.server_name,
+                udp,
+                tfo,
+                tls.insecure,
+                None,
+                "",
+            )
+        }
+        "vless" => parse_singbox_vless(outbound, remark, server, port, tls),
+        "trojan" => {
+            let (network, path, host) = transport_options(outbound);
+            let mut node = Proxy::trojan_construct(
+                TROJAN_DEFAULT_GROUP.to_string(),
+                remark.to_string(),
+                server.to_string(),
+                port,
+                str_field(outbound, "password").to_string(),
+                (network != "tcp").then_some(network),
+                opt_string(&host),
+                opt_string(&path),
+                opt_string(&tls.server_name),
+                true,
+                udp,
+                tfo,
+                tls.insecure,
+                None,
+                None,
+            );
+            node.alpn = tls.alpn;
+            node
+        }
+        "hysteria2" => {
+            let obfs = outbound.get("obfs").unwrap_or(&Value::Null);
+            let mbps = |key: &str| {
+                outbound
+                    .get(key)
+                    .and_then(Value::as_u64)
+                    .map(|mbps| mbps as u32)
+            };
+            let ports = outbound
+                .get("server_ports")
+                .and_then(Value::as_array)
+                .map(|ports| {
+                    ports
+                        .iter()
+                        .filter_map(Value::as_str)
+                        .map(|range| range.replace(':', "-"))
+                        .collect::<Vec<_>>()
+                        .join(",")
+                });
+            Proxy::hysteria2_construct(
+                HYSTERIA2_DEFAULT_GROUP.to_string(),
+                remark.to_string(),
+                server.to_string(),
+                port,
+                ports,
+                mbps("up_mbps"),
+                mbps("down_mbps"),
+                str_field(outbound, "password").to_string(),
+                opt_string(str_field(obfs, "type")),
+                opt_string(str_field(obfs, "password")),
+                opt_string(&tls.server_name),
+                opt_string(&tls.fingerprint),
+                tls.alpn.unwrap_or_default(),
+                None,
+                None,
+                None,
+                tfo,
+                tls.insecure,
+                None,
+            )
+        }
+        "http" => Proxy::http_construct(
+            HTTP_DEFAULT_GROUP,
+            remark,
+            server,
+            port,
+            str_field(outbound, "username"),
+            str_field(outbound, "password"),
+            tls.enabled,
+            tfo,
+            tls.insecure,
+            None,
+            "",
+        ),
+        "socks" => Proxy::socks_construct(
+            SOCKS_DEFAULT_GROUP,
+            remark,
+            server,
+            port,
+            str_field(outbound, "username"),
+            str_field(outbound, "password"),
+            udp,
+            tfo,
+            None,
+            "",
+        ),
+        _ => return None,
+    };
+    Some(node)
+}
+
+fn parse_singbox_vless(
+    outbound: &Value,
+    remark: &str,
+    server: &str,
+    port: u16,
+    tls: TlsOptions,
+) -> Proxy {
+    let (network, path, host) = transport_options(outbound);
+    let mut vless = VlessProxy {
+        uuid: str_field(outbound, "uuid").to_string(),
+        flow: opt_string(str_field(outbound, "flow")),
+        tls: tls.enabled,
+        alpn: tls.alpn,
+        network: Some(network.clone()),
+        servername: opt_string(&tls.server_name),
+        client_fingerprint: opt_string(&tls.fingerprint),
+        skip_cert_verify: tls.insecure,
+        reality_public_key: opt_string(&tls.reality_public_key),
+        reality_short_id: opt_string(&tls.reality_short_id),
+        packet_encoding: opt_string(str_field(outbound, "packet_encoding")),
+        ..VlessProxy::default()
+    };
+    match network.as_str() {
+        "ws" | "httpupgrade" => {
+            vless.ws_path = opt_string(&path);
+            if !host.is_empty() {
+                vless.ws_headers = Some(HashMap::from([("Host".to_string(), host)]));
+            }
+        }
+        "http" => {
+            vless.h2_path = opt_string(&path);
+            vless.h2_host = (!host.is_empty()).then(|| vec![host]);
+        }
+        "grpc" => vless.grpc_service_name = opt_string(&path),
+        _ => {}
+    }
+
+    Proxy {
+        proxy_type: ProxyType::Vless,
+        combined_proxy: Some(CombinedProxy::Vless(vless)),
+        group: V2RAY_DEFAULT_GROUP.to_string(),
+        remark: remark.to_string(),
+        hostname: server.to_string(),
+        port,
+        ..Default::default()
+    }
+}
+
+/// WireGuard as a legacy outbound or as an endpoint with its peers
+fn parse_singbox_wireguard(
+    outbound: &Value,
+    remark: &str,
+    server: &str,
+    port: u16,
+) -> Option<Proxy> {
+    let peer = outbound
+        .get("peers")
+        .and_then(Value::as_array)
+        .and_then(|peers| peers.first());
+    let (server, port, public_key, pre_shared_key) = match peer {
+        Some(peer) => (
+            str_field(peer, "address"),
+            peer.get("port")
+                .and_then(Value::as_u64)
+                .and_then(|port| u16::try_from(port).ok())
+                .unwrap_or(0),
+            str_field(peer, "public_key"),
+            str_field(peer, "pre_shared_key"),
+        ),
+        None => (
+            server,
+            port,
+            str_field(outbound, "peer_public_key"),
+            str_field(outbound, "pre_shared_key"),
+        ),
+    };
+    let private_key = str_field(outbound, "private_key");
+    if server.is_empty() || port == 0 || private_key.is_empty() || public_key.is_empty() {
+        return None;
+    }
+
+    let addresses: Vec<&str> = outbound
+        .get("address")
+        .or_else(|| outbound.get("local_address"))
+        .and_then(Value::as_array)
+        .map(|addresses| addresses.iter().filter_map(Value::as_str).collect())
+        .unwrap_or_default();
+    let strip_prefix = |address: &str| address.split('/').next().unwrap_or_default().to_string();
+    let self_ip = addresses
+        .iter()
+        .find(|address| !address.contains(':'))
+        .map(|address| strip_prefix(address))
+        .unwrap_or_default();
+    let self_ipv6 = addresses
+        .iter()
+        .find(|address| address.contains(':'))
+        .map(|address| strip_prefix(address))
+        .unwrap_or_default();
+
+    Some(Proxy::wireguard_construct(
+        WG_DEFAULT_GROUP.to_string(),
+        remark.to_string(),
+        server.to_string(),
+        port,
+        self_ip,
+        self_ipv6,
+        private_key.to_string(),
+        public_key.to_string(),
+        pre_shared_key.to_string(),
+        Vec::new(),
+        outbound
+            .get("mtu")
+            .and_then(Value::as_u64)
+            .map(|mtu| mtu as u16),
+        None,
+        String::new(),
+        String::new(),
+        None,
+        None,
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_explode_singbox_config() {
+        let content = r#"{
+            "outbounds": [
+                {"type": "selector", "tag": "proxy", "outbounds": ["SS", "VMess"]},
+                {"type": "shadowsocks", "tag": "SS", "server": "1.2.3.4", "server_port": 8388,
+                 "method": "aes-128-gcm", "password": "pass", "multiplex": {"enabled": true}},
+                {"type": "vmess", "tag": "VMess", "server": "2.3.4.5", "server_port": 443,
+                 "uuid": "b831381d-6324-4d53-ad4f-8cda48b30811", "alter_id": 0,
+                 "tls": {"enabled": true, "server_name": "cdn.example.com", "insecure": true},
+                 "transport": {"type": "ws", "path": "/ws", "headers": {"Host": "cdn.example.com"}}},
+                {"type": "vless", "tag": "VLESS", "server": "3.4.5.6", "server_port": 443,
+                 "uuid": "b831381d-6324-4d53-ad4f-8cda48b30811", "flow": "xtls-rprx-vision",
+                 "tls": {"enabled": true, "server_name": "www.example.com",
+                         "reality": {"enabled": true, "public_key": "pbk", "short_id": "sid"}}},
+                {"type": "trojan", "tag": "Trojan", "server": "4.5.6.7", "server_port": 443,
+                 "password": "secret", "tls": {"enabled": true, "server_name": "trojan.example.com"},
+                 "transport": {"type": "grpc", "service_name": "tunnel"}},
+                {"type": "hysteria2", "tag": "Hy2", "server": "5.6.7.8", "server_port": 443,
+                 "password": "hy2", "obfs": {"type": "salamander", "password": "obfs"}},
+                {"type": "tuic", "tag": "TUIC", "server": "6.7.8.9", "server_port": 443},
+                {"type": "direct", "tag": "direct"}
+            ],
+            "endpoints": [
+                {"type": "wireguard", "tag": "WG", "address": ["172.16.0.2/32", "fd01::2/128"],
+                 "private_key": "private", "mtu": 1280,
+                 "peers": [{"address": "7.8.9.10", "port": 2408, "public_key": "public"}]}
+            ]
+        }"#;
+
+        let mut nodes = Vec::new();
+        assert!(explode_singbox(content, &mut nodes));
+        let names: Vec<&str> = nodes.iter().map(|node| node.remark.as_str()).collect();
+        assert_eq!(names, ["SS", "VMess", "VLESS", "Trojan", "Hy2", "WG"]);
+
+        let vmess = &nodes[1];
+        assert_eq!(vmess.transfer_protocol.as_deref(), Some("ws"));
+        assert_eq!(vmess.host.as_deref(), Some("cdn.example.com"));
+        assert!(vmess.tls_secure);
+        assert_eq!(vmess.allow_insecure, Some(true));
+
+        match &nodes[2].combined_proxy {
+            Some(CombinedProxy::Vless(vless)) => {
+                assert_eq!(vless.flow.as_deref(), Some("xtls-rprx-vision"));
+                assert_eq!(vless.reality_public_key.as_deref(), Some("pbk"));
+            }
+            other => panic!("expected a VLESS node, got {:?}", other),
+        }
+
+        assert_eq!(nodes[3].transfer_protocol.as_deref(), Some("grpc"));
+        assert_eq!(nodes[3].path.as_deref(), Some("tunnel"));
+        assert_eq!(nodes[4].obfs.as_deref(), Some("salamander"));
+        assert_eq!(nodes[5].hostname, "7.8.9.10");
+        assert_eq!(nodes[5].self_ip.as_deref(), Some("172.16.0.2"));
+    }
+
+    #[test]
+    fn test_explode_singbox_outbound_list() {
+        let content =
+            r#"[{"type": "socks", "tag": "SOCKS", "server": "1.2.3.4", "server_port": 1080}]"#;
+        let mut nodes = Vec::new();
+        assert!(explode_singbox(content, &mut nodes));
+        assert_eq!(nodes[0].proxy_type, ProxyType::Socks5);
+
+        // V2Ray configs have outbounds too, but no sing-box types
+        let v2ray = r#"{"outbounds": [{"protocol": "vmess", "settings": {}}]}"#;
+        assert!(!explode_singbox(v2ray, &mut nodes));
+    }
+}
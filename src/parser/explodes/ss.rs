@@ -1,7 +1,7 @@
 use crate::models::{Proxy, SS_DEFAULT_GROUP};
+use crate::utils::base64::url_safe_base64_decode;
+use crate::utils::network::split_host_port;
 use crate::utils::url::url_decode;
-use base64::engine::general_purpose::STANDARD;
-use base64::Engine;
 use serde_json::Value;
 
 /// Parse a Shadowsocks link into a Proxy object
@@ -36,12 +36,13 @@ pub fn explode_ss(ss: &str, node: &mut Proxy) -> bool {
         // Parse query parameters
         for (key, value) in url::form_urlencoded::parse(addition.as_bytes()) {
             if key == "plugin" {
-                let plugins = url_decode(&value);
-                if let Some(semicolon_pos) = plugins.find(';') {
-                    plugin = plugins[..semicolon_pos].to_string();
-                    plugin_opts = plugins[semicolon_pos + 1..].to_string();
-                } else {
-                    plugin = plugins;
+                // Already decoded, a second pass would garble options with `%`
+                match value.split_once(';') {
+                    Some((name, opts)) => {
+                        plugin = name.to_string();
+                        plugin_opts = opts.to_string();
+                    }
+                    None => plugin = value.into_owned(),
                 }
             } else if key == "group" {
                 if !value.is_empty() {
@@ -67,16 +68,14 @@ pub fn explode_ss(ss: &str, node: &mut Proxy) -> bool {
         let secret = parts[0];
         let server_port = parts[1];
 
-        // Parse server and port
-        let server_port_parts: Vec<&str> = server_port.split(':').collect();
-        if server_port_parts.len() < 2 {
-            return false;
+        // Parse server and port, the server may be a bracketed IPv6 literal
+        match split_host_port(server_port) {
+            Some((host, p)) => {
+                server = host.to_string();
+                port = p;
+            }
+            None => return false,
         }
-        server = server_port_parts[0].to_string();
-        port = match server_port_parts[1].parse::<u16>() {
-            Ok(p) => p,
-            Err(_) => return false,
-        };
 
         // Decode the secret part
         let decoded_secret = crate::utils::base64::url_safe_base64_decode(secret);
@@ -110,16 +109,14 @@ pub fn explode_ss(ss: &str, node: &mut Proxy) -> bool {
         method = method_pass_parts[0].to_string();
         password = method_pass_parts[1..].join(":"); // In case password contains colons
 
-        // Parse server and port
-        let server_port_parts: Vec<&str> = server_port.split(':').collect();
-        if server_port_parts.len() < 2 {
-            return false;
+        // Parse server and port, the server may be a bracketed IPv6 literal
+        match split_host_port(server_port) {
+            Some((host, p)) => {
+                server = host.to_string();
+                port = p;
+            }
+            None => return false,
         }
-        server = server_port_parts[0].to_string();
-        port = match server_port_parts[1].parse::<u16>() {
-            Ok(p) => p,
-            Err(_) => return false,
-        };
     }
 
     // Skip if port is 0
@@ -152,66 +149,87 @@ pub fn explode_ss(ss: &str, node: &mut Proxy) -> bool {
     true
 }
 
-/// Parse a SSD (Shadowsocks subscription) link into a vector of Proxy objects
+/// Parse an SSD subscription (`ssd://` followed by base64 encoded JSON) into
+/// a vector of Proxy objects
 pub fn explode_ssd(link: &str, nodes: &mut Vec<Proxy>) -> bool {
+    explode_ssd_with_info(link, nodes, false)
+}
+
+/// Same as [`explode_ssd`], with `remark_info` the airport name and the
+/// traffic ratio are added to every remark
+///
+/// Servers inherit the port, encryption, password and plugin of the envelope
+/// when they leave them out, and are grouped under the airport name.
+/// Malformed servers are skipped.
+pub fn explode_ssd_with_info(link: &str, nodes: &mut Vec<Proxy>, remark_info: bool) -> bool {
     // Check if the link starts with ssd://
-    if !link.starts_with("ssd://") {
+    let Some(encoded) = link.trim().strip_prefix("ssd://") else {
         return false;
-    }
-
-    // Extract the base64 part
-    let encoded = &link[6..];
-
-    // Decode base64
-    let decoded = match STANDARD.decode(encoded) {
-        Ok(bytes) => match String::from_utf8(bytes) {
-            Ok(s) => s,
-            Err(_) => return false,
-        },
-        Err(_) => return false,
     };
 
     // Parse as JSON
-    let json: Value = match serde_json::from_str(&decoded) {
+    let json: Value = match serde_json::from_str(&url_safe_base64_decode(encoded)) {
         Ok(json) => json,
-        Err(_) => return false,
+        Err(e) => {
+            log::warn!("Invalid SSD subscription: {}", e);
+            return false;
+        }
     };
 
-    // Extract common fields
+    let Some(servers) = json["servers"].as_array() else {
+        return false;
+    };
+
+    // Defaults inherited by the servers
     let airport = json["airport"].as_str().unwrap_or("");
-    let port = json["port"].as_u64().unwrap_or(0) as u16;
+    let port = ssd_port(&json["port"]).unwrap_or(0);
     let encryption = json["encryption"].as_str().unwrap_or("");
     let password = json["password"].as_str().unwrap_or("");
+    let plugin = json["plugin"].as_str().unwrap_or("");
+    let plugin_opts = json["plugin_options"].as_str().unwrap_or("");
+    let group = if airport.is_empty() {
+        SS_DEFAULT_GROUP
+    } else {
+        airport
+    };
 
-    // Extract servers
-    if !json["servers"].is_array() {
-        return false;
-    }
-
-    let servers = json["servers"].as_array().unwrap();
-
-    for server in servers {
+    let orig_size = nodes.len();
+    for (index, server) in servers.iter().enumerate() {
         let server_host = server["server"].as_str().unwrap_or("");
-        let server_port = server["port"].as_u64().unwrap_or(port as u64) as u16;
+        let server_port = ssd_port(&server["port"]).unwrap_or(port);
         let server_encryption = server["encryption"].as_str().unwrap_or(encryption);
         let server_password = server["password"].as_str().unwrap_or(password);
-        let server_remark = server["remarks"].as_str().unwrap_or("");
-        let server_plugin = server["plugin"].as_str().unwrap_or("");
-        let server_plugin_opts = server["plugin_options"].as_str().unwrap_or("");
+        if server_host.is_empty()
+            || server_port == 0
+            || server_encryption.is_empty()
+            || server_password.is_empty()
+        {
+            log::warn!("Skipping malformed SSD server #{}: {}", index, server);
+            continue;
+        }
 
-        // Create formatted remark
-        let formatted_remark = format!("{} - {}", airport, server_remark);
+        let mut remark = match server["remarks"].as_str().unwrap_or("") {
+            "" => format!("{}:{}", server_host, server_port),
+            remark => remark.to_string(),
+        };
+        if remark_info {
+            if !airport.is_empty() {
+                remark = format!("{} - {}", airport, remark);
+            }
+            if let Some(ratio) = server["ratio"].as_f64() {
+                remark = format!("{} ({}x)", remark, ratio);
+            }
+        }
 
-        // Create the proxy object
         let node = Proxy::ss_construct(
-            SS_DEFAULT_GROUP,
-            &formatted_remark,
+            group,
+            &remark,
             server_host,
             server_port,
             server_password,
             server_encryption,
-            server_plugin,
-            server_plugin_opts,
+            server["plugin"].as_str().unwrap_or(plugin),
+            server["plugin_options"].as_str().unwrap_or(plugin_opts),
             None,
             None,
             None,
@@ -222,7 +240,16 @@ pub fn explode_ssd(link: &str, nodes: &mut Vec<Proxy>) -> bool {
         nodes.push(node);
     }
 
-    !nodes.is_empty()
+    nodes.len() > orig_size
+}
+
+/// SSD ports are numbers, some providers write them as strings
+fn ssd_port(value: &Value) -> Option<u16> {
+    match value {
+        Value::Number(port) => port.as_u64().and_then(|port| u16::try_from(port).ok()),
+        Value::String(port) => port.parse().ok(),
+        _ => None,
+    }
 }
 
 /// Parse Android Shadowsocks configuration into a vector of Proxy objects
@@ -260,7 +287,10 @@ pub fn explode_ss_android(content: &str, nodes: &mut Vec<Proxy>) -> bool {
             continue;
         }
 
-        let port_num = config["server_port"].as_u64().unwrap_or(0) as u16;
+        let port_num = config["server_port"]
+            .as_u64()
+            .and_then(|port| u16::try_from(port).ok())
+            .unwrap_or(0);
         if port_num == 0 {
             continue;
         }
@@ -325,7 +355,10 @@ pub fn explode_ss_conf(content: &str, nodes: &mut Vec<Proxy>) -> bool {
 
         // Extract fields
         let server = json["server"].as_str().unwrap_or("");
-        let port_num = json["server_port"].as_u64().unwrap_or(0) as u16;
+        let port_num = json["server_port"]
+            .as_u64()
+            .and_then(|port| u16::try_from(port).ok())
+            .unwrap_or(0);
         if server.is_empty() || port_num == 0 {
             return false;
         }
@@ -375,7 +408,10 @@ pub fn explode_ss_conf(content: &str, nodes: &mut Vec<Proxy>) -> bool {
         for server_json in servers {
             // Extract fields
             let server = server_json["server"].as_str().unwrap_or("");
-            let port_num = server_json["server_port"].as_u64().unwrap_or(0) as u16;
+            let port_num = server_json["server_port"]
+                .as_u64()
+                .and_then(|port| u16::try_from(port).ok())
+                .unwrap_or(0);
             if server.is_empty() || port_num == 0 {
                 continue;
             }
@@ -421,3 +457,48 @@ pub fn explode_ss_conf(content: &str, nodes: &mut Vec<Proxy>) -> bool {
 
     false
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::base64::base64_encode;
+
+    fn ssd_link() -> String {
+        let json = r#"{
+            "airport": "Airport",
+            "port": 8388,
+            "encryption": "aes-128-gcm",
+            "password": "default",
+            "servers": [
+                {"server": "1.2.3.4", "remarks": "HK 01", "ratio": 1.5},
+                {"server": "2.3.4.5", "port": "443", "password": "own", "remarks": "JP 01"},
+                {"remarks": "no server"},
+                "not an object"
+            ]
+        }"#;
+        format!("ssd://{}", base64_encode(json))
+    }
+
+    #[test]
+    fn test_explode_ssd_inherits_defaults() {
+        let mut nodes = Vec::new();
+        assert!(explode_ssd(&ssd_link(), &mut nodes));
+        assert_eq!(nodes.len(), 2);
+
+        assert_eq!(nodes[0].remark, "HK 01");
+        assert_eq!(nodes[0].group, "Airport");
+        assert_eq!(nodes[0].port, 8388);
+        assert_eq!(nodes[0].password.as_deref(), Some("default"));
+        assert_eq!(nodes[1].port, 443);
+        assert_eq!(nodes[1].password.as_deref(), Some("own"));
+        assert_eq!(nodes[1].encrypt_method.as_deref(), Some("aes-128-gcm"));
+    }
+
+    #[test]
+    fn test_explode_ssd_remark_info() {
+        let mut nodes = Vec::new();
+        assert!(explode_ssd_with_info(&ssd_link(), &mut nodes, true));
+        assert_eq!(nodes[0].remark, "Airport - HK 01 (1.5x)");
+        assert_eq!(nodes[1].remark, "Airport - JP 01");
+    }
+}
@@ -1,80 +1,102 @@
-use crate::models::{Proxy, SSR_DEFAULT_GROUP, SS_CIPHERS};
-use crate::utils::base64::url_safe_base64_decode;
+use crate::models::{Proxy, SSR_CIPHERS, SSR_DEFAULT_GROUP, SS_CIPHERS};
+use crate::utils::base64::base64_decode_strict;
+use crate::utils::network::strip_ipv6_brackets;
+use crate::utils::url::url_decode;
 use serde_json::Value;
 use url::Url;
 
+/// Decode a Base64 field of an SSR link
+///
+/// Some links pad with `_` instead of `=`, which is taken as padding where
+/// padding can be. Fields which are no Base64 of text were never encoded and
+/// are kept as they are.
+fn decode_field(value: &str) -> String {
+    let value = value.trim();
+    let padding = value.len() - value.trim_end_matches('_').len();
+    if (1..=2).contains(&padding) && (value.len() - padding) % 4 == 4 - padding {
+        if let Some(decoded) = base64_decode_strict(&value[..value.len() - padding]) {
+            return decoded;
+        }
+    }
+    base64_decode_strict(value).unwrap_or_else(|| value.to_string())
+}
+
+/// Decode a remarks or group field, which some links percent-encode before
+/// the Base64
+fn decode_name(value: &str) -> String {
+    let decoded = decode_field(value);
+    if decoded.contains('%') {
+        url_decode(&decoded)
+    } else {
+        decoded
+    }
+}
+
 /// Parse a ShadowsocksR link into a Proxy object
 /// Based on the C++ implementation in explodeSSR function
+///
+/// Missing parameters are left empty and an empty protocol or obfs is taken
+/// as `origin` or `plain`. Only links without a usable server, port or
+/// cipher are rejected.
 pub fn explode_ssr(ssr: &str, node: &mut Proxy) -> bool {
     // Check if the link starts with ssr://
-    if !ssr.starts_with("ssr://") {
+    let Some(encoded) = ssr.strip_prefix("ssr://") else {
         return false;
-    }
-
-    // Extract the base64 part and decode it
-    let encoded = &ssr[6..];
+    };
 
-    // Decode base64
-    let mut decoded = url_safe_base64_decode(encoded);
+    let mut decoded = decode_field(encoded).replace('\r', "");
     if decoded.is_empty() {
         return false;
     }
 
-    // Replace \r with empty string
-    decoded = decoded.replace('\r', "");
-
     // Extract query parameters if present
-    let mut _strobfs = String::new();
     let mut group = String::new();
     let mut remarks = String::new();
     let mut obfsparam = String::new();
     let mut protoparam = String::new();
 
-    if let Some(query_pos) = decoded.find("/?") {
-        _strobfs = decoded[query_pos + 2..].to_string();
-        decoded = decoded[..query_pos].to_string();
-
-        // Parse query parameters
-        let url_str = format!("http://localhost/?{}", _strobfs);
+    if let Some(query_pos) = decoded.find("/?").or_else(|| decoded.find('?')) {
+        let query = decoded[query_pos..].trim_start_matches('/');
+        let url_str = format!("http://localhost/{}", query);
         if let Ok(url) = Url::parse(&url_str) {
             for (key, value) in url.query_pairs() {
-                let decoded_value = url_safe_base64_decode(&value);
-
                 match key.as_ref() {
-                    "group" => group = decoded_value,
-                    "remarks" => remarks = decoded_value,
-                    "obfsparam" => obfsparam = decoded_value.replace(" ", ""),
-                    "protoparam" => protoparam = decoded_value.replace(" ", ""),
+                    "group" => group = decode_name(&value),
+                    "remarks" => remarks = decode_name(&value),
+                    "obfsparam" => obfsparam = decode_field(&value).replace(' ', ""),
+                    "protoparam" => protoparam = decode_field(&value).replace(' ', ""),
                     _ => {}
                 }
             }
         }
+        decoded.truncate(query_pos);
     }
 
-    // Parse the main part of the URL (server:port:protocol:method:obfs:password)
-    let parts: Vec<&str> = decoded.split(':').collect();
+    // Parse the main part of the URL (server:port:protocol:method:obfs:password),
+    // from the right as an IPv6 server has colons of its own
+    let mut parts: Vec<&str> = decoded.trim_end_matches('/').rsplitn(6, ':').collect();
     if parts.len() < 6 {
         return false;
     }
+    parts.reverse();
 
-    let server = parts[0];
-    let port_str = parts[1];
-    let protocol = parts[2];
-    let method = parts[3];
-    let obfs = parts[4];
-    let password_encoded = parts[5];
-
-    // Decode password (base64 encoded)
-    let password = url_safe_base64_decode(password_encoded);
-
-    // Parse port
-    let port = match port_str.parse::<u16>() {
-        Ok(p) => p,
-        Err(_) => return false,
+    let server = strip_ipv6_brackets(parts[0].trim());
+    let protocol = match parts[2].trim() {
+        "" => "origin",
+        protocol => protocol,
+    };
+    let method = parts[3].trim();
+    let obfs = match parts[4].trim() {
+        "" => "plain",
+        obfs => obfs,
     };
+    let password = decode_field(parts[5]);
 
-    // Skip if port is 0
-    if port == 0 {
+    let port = match parts[1].trim().parse::<u16>() {
+        Ok(port) if port != 0 => port,
+        _ => return false,
+    };
+    if server.is_empty() || !(SSR_CIPHERS.contains(&method) || SS_CIPHERS.contains(&method)) {
         return false;
     }
 
@@ -87,10 +109,7 @@ pub fn explode_ssr(ssr: &str, node: &mut Proxy) -> bool {
     }
 
     // Check if this should be an SS or SSR proxy
-    if SS_CIPHERS.iter().any(|c| *c == method)
-        && (obfs.is_empty() || obfs == "plain")
-        && (protocol.is_empty() || protocol == "origin")
-    {
+    if SS_CIPHERS.contains(&method) && obfs == "plain" && protocol == "origin" {
         // Create SS proxy
         *node = Proxy::ss_construct(
             &group, &remarks, server, port, &password, method, "", "", None, None, None, None, "",
@@ -136,7 +155,10 @@ pub fn explode_ssr_conf(content: &str, nodes: &mut Vec<Proxy>) -> bool {
 
     for config in configs {
         let server = config["server"].as_str().unwrap_or("");
-        let port = config["server_port"].as_u64().unwrap_or(0) as u16;
+        let port = config["server_port"]
+            .as_u64()
+            .and_then(|port| u16::try_from(port).ok())
+            .unwrap_or(0);
         let protocol = config["protocol"].as_str().unwrap_or("");
         let method = config["method"].as_str().unwrap_or("");
         let obfs = config["obfs"].as_str().unwrap_or("");
@@ -271,6 +293,53 @@ mod tests {
         assert_eq!(node.remark, format!("{} ({})", server, port));
     }
 
+    #[test]
+    fn test_explode_ssr_nonstandard_links() {
+        let link = |inner: &str| format!("ssr://{}", STANDARD.encode(inner));
+
+        // `_` padding, no obfsparam and protoparam, remarks percent-encoded
+        // before the Base64
+        let mut node = Proxy::default();
+        let remarks = STANDARD.encode("%E9%A6%99%E6%B8%AF%2001 100%");
+        assert!(explode_ssr(
+            &link(&format!(
+                "hk.example.com:443:auth_aes128_md5:chacha20-ietf:tls1.2_ticket_auth:cGFzcw__/?remarks={}",
+                remarks
+            )),
+            &mut node
+        ));
+        assert_eq!(node.proxy_type, ProxyType::ShadowsocksR);
+        assert_eq!(node.password.as_deref(), Some("pass"));
+        assert_eq!(node.remark, "香港 01 100%");
+        assert_eq!(node.obfs_param.as_deref(), Some(""));
+        assert_eq!(node.protocol_param.as_deref(), Some(""));
+
+        // Parameters which were never encoded, empty protocol and obfs
+        let mut node = Proxy::default();
+        assert!(explode_ssr(
+            &link("jp.example.com:8443::none::dGVzdA/?protoparam=1234:abcd&obfsparam=test"),
+            &mut node
+        ));
+        assert_eq!(node.protocol.as_deref(), Some("origin"));
+        assert_eq!(node.obfs.as_deref(), Some("plain"));
+        assert_eq!(node.protocol_param.as_deref(), Some("1234:abcd"));
+        assert_eq!(node.obfs_param.as_deref(), Some("test"));
+
+        // No usable server, port or cipher
+        for inner in [
+            ":443:origin:aes-256-cfb:plain:dGVzdA",
+            "example.com:0:origin:aes-256-cfb:plain:dGVzdA",
+            "example.com:443:origin:rot13:plain:dGVzdA",
+            "example.com:443:origin::plain:dGVzdA",
+        ] {
+            assert!(
+                !explode_ssr(&link(inner), &mut Proxy::default()),
+                "{}",
+                inner
+            );
+        }
+    }
+
     #[test]
     fn test_explode_ssr_conf_valid() {
         let mut nodes = Vec::new();
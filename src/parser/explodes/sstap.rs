@@ -1,6 +1,4 @@
-use crate::models::{
-    Proxy, SOCKS_DEFAULT_GROUP, SSR_DEFAULT_GROUP, SS_CIPHERS, SS_DEFAULT_GROUP,
-};
+use crate::models::{Proxy, SOCKS_DEFAULT_GROUP, SSR_DEFAULT_GROUP, SS_CIPHERS, SS_DEFAULT_GROUP};
 use serde_json::{from_str, Value};
 
 /// Parse a SSTap JSON configuration into a vector of Proxy objects
@@ -30,7 +28,10 @@ pub fn explode_sstap(content: &str, nodes: &mut Vec<Proxy>) -> bool {
         let group = config["group"].as_str().unwrap_or("");
         let remarks = config["remarks"].as_str().unwrap_or("");
         let server = config["server"].as_str().unwrap_or("");
-        let port = config["server_port"].as_u64().unwrap_or(0) as u16;
+        let port = config["server_port"]
+            .as_u64()
+            .and_then(|port| u16::try_from(port).ok())
+            .unwrap_or(0);
 
         // Skip if port is 0
         if port == 0 {
@@ -4,12 +4,15 @@ use crate::models::{
 };
 
 /// Parse a Surge configuration into a vector of Proxy objects
+///
+/// Only the `[Proxy]` section is read when the content is a whole config,
+/// every line of a node list otherwise.
 pub fn explode_surge(content: &str, nodes: &mut Vec<Proxy>) -> bool {
     // Split the content into lines
     let lines: Vec<&str> = content.lines().collect();
 
     // Track the section we're currently in
-    let mut in_proxy_section = false;
+    let mut in_proxy_section = !content.contains("[Proxy]");
     let mut success = false;
 
     for line in lines {
@@ -50,50 +53,63 @@ pub fn explode_surge(content: &str, nodes: &mut Vec<Proxy>) -> bool {
         // Parse the proxy based on the configuration format
         let mut node = Proxy::default();
 
-        if config.starts_with("custom,") {
+        let parsed = if config.starts_with("custom,") {
             // Surge 2 style custom proxy (essentially a shadowsocks proxy)
-            if parse_surge_custom_ss(config, name, &mut node) {
-                nodes.push(node);
-                success = true;
-            }
+            parse_surge_custom_ss(config, name, &mut node)
         } else if config.starts_with("ss,") || config.starts_with("shadowsocks,") {
             // Surge 3 style ss proxy
-            if parse_surge_ss(config, name, &mut node) {
-                nodes.push(node);
-                success = true;
-            }
+            parse_surge_ss(config, name, &mut node)
         } else if config.starts_with("socks5") || config.starts_with("socks5-tls") {
-            if parse_surge_socks(config, name, &mut node) {
-                nodes.push(node);
-                success = true;
-            }
+            parse_surge_socks(config, name, &mut node)
         } else if config.starts_with("vmess,") {
             // Surge 4 style vmess proxy
-            if parse_surge_vmess(config, name, &mut node) {
-                nodes.push(node);
-                success = true;
-            }
+            parse_surge_vmess(config, name, &mut node)
         } else if config.starts_with("http") || config.starts_with("https") {
-            if parse_surge_http(config, name, &mut node) {
-                nodes.push(node);
-                success = true;
-            }
+            parse_surge_http(config, name, &mut node)
         } else if config.starts_with("trojan") {
-            if parse_surge_trojan(config, name, &mut node) {
-                nodes.push(node);
-                success = true;
-            }
+            parse_surge_trojan(config, name, &mut node)
         } else if config.starts_with("snell") {
-            if parse_surge_snell(config, name, &mut node) {
-                nodes.push(node);
-                success = true;
-            }
+            parse_surge_snell(config, name, &mut node)
+        } else {
+            false
+        };
+
+        if parsed {
+            parse_policy_options(config, &mut node);
+            nodes.push(node);
+            success = true;
         }
     }
 
     success
 }
 
+/// Split a `key=value` option, the value may contain `=` and be quoted
+fn split_option(part: &str) -> Option<(&str, &str)> {
+    let (key, value) = part.split_once('=')?;
+    Some((key.trim(), value.trim().trim_matches('"')))
+}
+
+/// Read the options Surge takes for every policy, `block-quic` and `test-url`
+fn parse_policy_options(config: &str, node: &mut Proxy) {
+    for (key, value) in config.split(',').filter_map(split_option) {
+        match key {
+            "block-quic" => node.block_quic = parse_block_quic(value),
+            "test-url" if !value.is_empty() => node.test_url = Some(value.to_string()),
+            _ => {}
+        }
+    }
+}
+
+/// `on` and `off` of `block-quic`, `None` for `auto` leaving it to Surge
+pub(super) fn parse_block_quic(value: &str) -> Option<bool> {
+    match value.to_lowercase().as_str() {
+        "on" | "true" | "1" => Some(true),
+        "off" | "false" | "0" => Some(false),
+        _ => None,
+    }
+}
+
 /// Parse a Surge 2 custom Shadowsocks configuration line
 fn parse_surge_custom_ss(config: &str, name: &str, node: &mut Proxy) -> bool {
     // Split the configuration into parts
@@ -129,14 +145,7 @@ fn parse_surge_custom_ss(config: &str, name: &str, node: &mut Proxy) -> bool {
 
     // Parse additional parameters
     for i in 6..parts.len() {
-        if parts[i].contains('=') {
-            let param_parts: Vec<&str> = parts[i].split('=').collect();
-            if param_parts.len() != 2 {
-                continue;
-            }
-            let key = param_parts[0].trim();
-            let value = param_parts[1].trim();
-
+        if let Some((key, value)) = split_option(parts[i]) {
             match key {
                 "obfs" => {
                     plugin = "simple-obfs".to_string();
@@ -218,14 +227,7 @@ fn parse_surge_ss(config: &str, name: &str, node: &mut Proxy) -> bool {
 
     // Parse additional parameters
     for i in 3..parts.len() {
-        if parts[i].contains('=') {
-            let param_parts: Vec<&str> = parts[i].split('=').collect();
-            if param_parts.len() != 2 {
-                continue;
-            }
-            let key = param_parts[0].trim();
-            let value = param_parts[1].trim();
-
+        if let Some((key, value)) = split_option(parts[i]) {
             match key {
                 "encrypt-method" => {
                     method = value.to_string();
@@ -308,17 +310,19 @@ fn parse_surge_http(config: &str, name: &str, node: &mut Proxy) -> bool {
     let mut password = "";
     let mut tfo = None;
     let mut scv = None;
+    let mut tls13 = None;
 
     // Parse additional parameters
-    for i in 3..parts.len() {
-        if parts[i].starts_with("username=") {
-            username = &parts[i][9..];
-        } else if parts[i].starts_with("password=") {
-            password = &parts[i][9..];
-        } else if parts[i] == "tfo=true" {
-            tfo = Some(true);
-        } else if parts[i] == "skip-cert-verify=true" {
-            scv = Some(true);
+    for part in &parts[3..] {
+        if let Some((key, value)) = split_option(part) {
+            match key {
+                "username" => username = value,
+                "password" => password = value,
+                "tfo" => tfo = Some(value == "true" || value == "1"),
+                "skip-cert-verify" => scv = Some(value == "true" || value == "1"),
+                "tls13" => tls13 = Some(value == "true" || value == "1"),
+                _ => {}
+            }
         }
     }
 
@@ -333,7 +337,7 @@ fn parse_surge_http(config: &str, name: &str, node: &mut Proxy) -> bool {
         is_https,
         tfo,
         scv,
-        None,
+        tls13,
         "",
     );
 
@@ -373,14 +377,7 @@ fn parse_surge_socks(config: &str, name: &str, node: &mut Proxy) -> bool {
 
     // Parse additional parameters
     for i in 5..parts.len() {
-        if parts[i].contains('=') {
-            let param_parts: Vec<&str> = parts[i].split('=').collect();
-            if param_parts.len() != 2 {
-                continue;
-            }
-            let key = param_parts[0].trim();
-            let value = param_parts[1].trim();
-
+        if let Some((key, value)) = split_option(parts[i]) {
             match key {
                 "udp-relay" => {
                     udp = Some(value == "true" || value == "1");
@@ -442,6 +439,7 @@ fn parse_surge_vmess(config: &str, name: &str, node: &mut Proxy) -> bool {
     let mut host = String::new();
     let mut edge = String::new();
     let mut tls = String::new();
+    let mut sni = String::new();
     let mut udp = None;
     let mut tfo = None;
     let mut scv = None;
@@ -450,14 +448,7 @@ fn parse_surge_vmess(config: &str, name: &str, node: &mut Proxy) -> bool {
 
     // Parse additional parameters
     for i in 3..parts.len() {
-        if parts[i].contains('=') {
-            let param_parts: Vec<&str> = parts[i].split('=').collect();
-            if param_parts.len() != 2 {
-                continue;
-            }
-            let key = param_parts[0].trim();
-            let value = param_parts[1].trim();
-
+        if let Some((key, value)) = split_option(parts[i]) {
             match key {
                 "username" => {
                     id = value.to_string();
@@ -484,20 +475,17 @@ fn parse_surge_vmess(config: &str, name: &str, node: &mut Proxy) -> bool {
                 }
                 "ws-headers" => {
                     // Parse headers in the format "Host:example.com|Edge:example.edge"
-                    let headers: Vec<&str> = value.split('|').collect();
-                    for header in headers {
-                        let header_parts: Vec<&str> = header.split(':').collect();
-                        if header_parts.len() == 2 {
-                            let header_name = header_parts[0].trim().to_lowercase();
-                            let header_value = header_parts[1].trim();
-                            if header_name == "host" {
-                                host = header_value.trim_matches('"').to_string();
-                            } else if header_name == "edge" {
-                                edge = header_value.trim_matches('"').to_string();
-                            }
+                    for (name, header_value) in parse_ws_headers(value) {
+                        if name == "host" {
+                            host = header_value;
+                        } else if name == "edge" {
+                            edge = header_value;
                         }
                     }
                 }
+                "sni" => {
+                    sni = value.to_string();
+                }
                 "udp-relay" => {
                     udp = Some(value == "true" || value == "1");
                 }
@@ -537,7 +525,7 @@ fn parse_surge_vmess(config: &str, name: &str, node: &mut Proxy) -> bool {
         &host,
         &edge,
         &tls,
-        "",
+        &sni,
         udp,
         tfo,
         scv,
@@ -548,6 +536,21 @@ fn parse_surge_vmess(config: &str, name: &str, node: &mut Proxy) -> bool {
     true
 }
 
+/// Parse `ws-headers`, e.g. `Host:example.com|Edge:"example.edge"`, header
+/// names are lowercased
+fn parse_ws_headers(value: &str) -> Vec<(String, String)> {
+    value
+        .split('|')
+        .filter_map(|header| header.split_once(':'))
+        .map(|(name, value)| {
+            (
+                name.trim().to_lowercase(),
+                value.trim().trim_matches('"').to_string(),
+            )
+        })
+        .collect()
+}
+
 /// Parse a Surge Trojan configuration line
 fn parse_surge_trojan(config: &str, name: &str, node: &mut Proxy) -> bool {
     // Split the configuration into parts
@@ -571,6 +574,9 @@ fn parse_surge_trojan(config: &str, name: &str, node: &mut Proxy) -> bool {
 
     // Default values
     let mut password = String::new();
+    let mut sni = String::new();
+    let mut network = None;
+    let mut path = String::new();
     let mut host = String::new();
     let mut udp = None;
     let mut tfo = None;
@@ -578,20 +584,27 @@ fn parse_surge_trojan(config: &str, name: &str, node: &mut Proxy) -> bool {
 
     // Parse additional parameters
     for i in 3..parts.len() {
-        if parts[i].contains('=') {
-            let param_parts: Vec<&str> = parts[i].split('=').collect();
-            if param_parts.len() != 2 {
-                continue;
-            }
-            let key = param_parts[0].trim();
-            let value = param_parts[1].trim();
-
+        if let Some((key, value)) = split_option(parts[i]) {
             match key {
                 "password" => {
                     password = value.to_string();
                 }
                 "sni" => {
-                    host = value.to_string();
+                    sni = value.to_string();
+                }
+                "ws" => {
+                    network = (value == "true").then(|| "ws".to_string());
+                }
+                "ws-path" => {
+                    path = value.to_string();
+                }
+                "ws-headers" => {
+                    if let Some((_, value)) = parse_ws_headers(value)
+                        .into_iter()
+                        .find(|(name, _)| name == "host")
+                    {
+                        host = value;
+                    }
                 }
                 "udp-relay" => {
                     udp = Some(value == "true" || value == "1");
@@ -623,10 +636,10 @@ fn parse_surge_trojan(config: &str, name: &str, node: &mut Proxy) -> bool {
         server.to_string(),
         port,
         password,
-        None,
-        if host.is_empty() { None } else { Some(host) },
-        None,
-        None,
+        network,
+        (!host.is_empty()).then_some(host),
+        (!path.is_empty()).then_some(path),
+        (!sni.is_empty()).then_some(sni),
         true,
         udp,
         tfo,
@@ -670,13 +683,9 @@ fn parse_surge_snell(config: &str, name: &str, node: &mut Proxy) -> bool {
 
     // Parse additional parameters
     for i in 3..parts.len() {
-        // Split by equals sign
-        let param_parts: Vec<&str> = parts[i].split('=').collect();
-        if param_parts.len() != 2 {
+        let Some((key, value)) = split_option(parts[i]) else {
             continue;
-        }
-        let key = param_parts[0].trim();
-        let value = param_parts[1].trim();
+        };
 
         match key {
             "psk" => password = value.to_string(),
@@ -712,3 +721,66 @@ fn parse_surge_snell(config: &str, name: &str, node: &mut Proxy) -> bool {
 
     true
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::ProxyType;
+
+    const SURGE_CONF: &str = r#"[General]
+loglevel = notify
+
+[Proxy]
+DIRECT = direct
+SS = ss, 1.2.3.4, 8388, encrypt-method=aes-256-gcm, password=pass=word, obfs=tls, obfs-host=bing.com, udp-relay=true, block-quic=on, test-url=http://cp.cloudflare.com/
+VMess = vmess, 2.3.4.5, 443, username=b831381d-6324-4d53-ad4f-8cda48b30811, ws=true, ws-path=/ws?ed=2048, ws-headers=Host:"cdn.example.com", tls=true, sni=cdn.example.com, skip-cert-verify=true
+Trojan = trojan, 3.4.5.6, 443, password=secret, sni=trojan.example.com, ws=true, ws-path=/tj, ws-headers=Host:tj.example.com
+Snell = snell, 4.5.6.7, 6160, psk=key, obfs=http, version=3, block-quic=off
+HTTPS = https, 5.6.7.8, 443, username=user, password=pass, skip-cert-verify=1
+SOCKS = socks5, 6.7.8.9, 1080, user, pass, udp-relay=true
+
+[Proxy Group]
+Proxy = select, SS, VMess
+"#;
+
+    #[test]
+    fn test_explode_surge_config() {
+        let mut nodes = Vec::new();
+        assert!(explode_surge(SURGE_CONF, &mut nodes));
+        let names: Vec<&str> = nodes.iter().map(|node| node.remark.as_str()).collect();
+        assert_eq!(names, ["SS", "VMess", "Trojan", "Snell", "HTTPS", "SOCKS"]);
+
+        let ss = &nodes[0];
+        assert_eq!(ss.password.as_deref(), Some("pass=word"));
+        assert_eq!(ss.plugin.as_deref(), Some("simple-obfs"));
+        assert_eq!(
+            ss.plugin_option.as_deref(),
+            Some("obfs=tls;obfs-host=bing.com")
+        );
+        assert_eq!(ss.udp, Some(true));
+        assert_eq!(ss.block_quic, Some(true));
+        assert_eq!(ss.test_url.as_deref(), Some("http://cp.cloudflare.com/"));
+
+        let vmess = &nodes[1];
+        assert_eq!(vmess.transfer_protocol.as_deref(), Some("ws"));
+        assert_eq!(vmess.path.as_deref(), Some("/ws?ed=2048"));
+        assert_eq!(vmess.host.as_deref(), Some("cdn.example.com"));
+        assert_eq!(vmess.sni.as_deref(), Some("cdn.example.com"));
+        assert!(vmess.tls_secure);
+        assert_eq!(vmess.allow_insecure, Some(true));
+
+        let trojan = &nodes[2];
+        assert_eq!(trojan.proxy_type, ProxyType::Trojan);
+        assert_eq!(trojan.transfer_protocol.as_deref(), Some("ws"));
+        assert_eq!(trojan.path.as_deref(), Some("/tj"));
+        assert_eq!(trojan.host.as_deref(), Some("tj.example.com"));
+        assert_eq!(trojan.sni.as_deref(), Some("trojan.example.com"));
+
+        assert_eq!(nodes[3].snell_version, 3);
+        assert_eq!(nodes[3].block_quic, Some(false));
+        assert_eq!(vmess.block_quic, None);
+        assert_eq!(nodes[4].proxy_type, ProxyType::HTTPS);
+        assert_eq!(nodes[4].allow_insecure, Some(true));
+        assert_eq!(nodes[5].username.as_deref(), Some("user"));
+    }
+}
@@ -1,4 +1,8 @@
-use crate::{models::TROJAN_DEFAULT_GROUP, utils::url_decode, Proxy};
+use crate::{
+    models::{parse_alpn, TROJAN_DEFAULT_GROUP},
+    utils::url_decode,
+    Proxy,
+};
 use std::collections::HashMap;
 use url::Url;
 
@@ -16,7 +20,7 @@ pub fn explode_trojan(trojan: &str, node: &mut Proxy) -> bool {
     };
 
     // Extract password
-    let password = url.username();
+    let password = url_decode(url.username());
     if password.is_empty() {
         return false;
     }
@@ -61,25 +65,30 @@ pub fn explode_trojan(trojan: &str, node: &mut Proxy) -> bool {
         .map(|s| url_decode(s))
         .unwrap_or_else(|| TROJAN_DEFAULT_GROUP.to_string());
 
-    // Handle WebSocket support
-    let mut network = None;
-    let mut path = None;
-
+    // Transport, the legacy `ws=1&wspath=` form and the standard `type=`
+    let mut network = params.get("type").map(|s| s.to_string());
+    let mut path = if network.as_deref() == Some("grpc") {
+        params.get("serviceName").map(|s| s.to_string())
+    } else {
+        params.get("path").map(|s| s.to_string())
+    };
     if params.get("ws").map(|s| s == "1").unwrap_or(false) {
         network = Some("ws".to_string());
-        path = params.get("wspath").map(|s| s.to_string());
-    } else if params.get("type").map(|s| s == "ws").unwrap_or(false) {
-        network = Some("ws".to_string());
-        if let Some(p) = params.get("path") {
-            let p_str = p.to_string();
-            if p_str.starts_with("%2F") {
-                path = Some(url_decode(&p_str));
-            } else {
-                path = Some(p_str);
-            }
-        }
+        path = params.get("wspath").map(|s| s.to_string()).or(path);
     }
 
+    // WebSocket requests fall back to the SNI as their Host header
+    let host_param = params.get("host").map(|s| s.to_string()).or_else(|| {
+        if network.as_deref() == Some("ws") {
+            sni.clone()
+        } else {
+            None
+        }
+    });
+
+    let alpn = params.get("alpn").and_then(|s| parse_alpn([s]));
+    let fingerprint = params.get("fp").map(|s| s.to_string());
+
     // Extract remark from the fragment
     let remark = url_decode(&url.fragment().unwrap_or(""));
     let formatted_remark = if remark.is_empty() {
@@ -94,9 +103,9 @@ pub fn explode_trojan(trojan: &str, node: &mut Proxy) -> bool {
         formatted_remark,
         host.to_string(),
         port,
-        password.to_string(),
+        password,
         network,
-        sni.clone(),
+        host_param,
         path,
         sni,
         true,             // tls_secure
@@ -106,6 +115,8 @@ pub fn explode_trojan(trojan: &str, node: &mut Proxy) -> bool {
         None,             // tls13
         None,             // underlying_proxy
     );
+    node.alpn = alpn;
+    node.fingerprint = fingerprint;
 
     true
 }
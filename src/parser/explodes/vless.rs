@@ -1,8 +1,9 @@
 use crate::models::proxy_node::combined::CombinedProxy;
 use crate::models::proxy_node::vless::VlessProxy;
-use crate::models::{Proxy, ProxyType};
+use crate::models::{parse_alpn, Proxy, ProxyType};
+use crate::utils::network::strip_ipv6_brackets;
 use crate::utils::url_decode;
-use std::collections::{HashMap, HashSet};
+use std::collections::HashMap;
 use url::Url;
 
 /// Parse a VLESS link into a Proxy object
@@ -44,19 +45,13 @@ pub fn explode_vless(vless: &str, node: &mut Proxy) -> bool {
         .map(|s| s.ends_with("tls") || s == "reality")
         .unwrap_or(false);
 
+    // REALITY handshakes need a client fingerprint, chrome unless given
     let fingerprint = params
         .get("fp")
         .map(|s| s.to_string())
-        .unwrap_or_else(|| "chrome".to_string());
-
-    let alpn = params
-        .get("alpn")
-        .map(|s| {
-            s.split(',')
-                .map(|s| s.trim().to_string())
-                .collect::<HashSet<_>>()
-        })
-        .unwrap_or_default();
+        .or_else(|| params.contains_key("pbk").then(|| "chrome".to_string()));
+
+    let alpn = params.get("alpn").and_then(|s| parse_alpn([s]));
 
     let sni = params.get("sni").map(|s| s.to_string());
 
@@ -87,7 +82,7 @@ pub fn explode_vless(vless: &str, node: &mut Proxy) -> bool {
     }
     vless_proxy.network = Some(network.clone());
     vless_proxy.servername = sni;
-    vless_proxy.client_fingerprint = Some(fingerprint);
+    vless_proxy.client_fingerprint = fingerprint;
     vless_proxy.flow = flow;
 
     // Handle Reality options
@@ -99,7 +94,7 @@ pub fn explode_vless(vless: &str, node: &mut Proxy) -> bool {
     // Handle network-specific options
     match network.as_str() {
         "tcp" => {
-            if fake_type != "none" {
+            if fake_type == "http" {
                 let mut http_headers = HashMap::new();
                 let mut http_path = vec!["/".to_string()];
 
@@ -165,7 +160,7 @@ pub fn explode_vless(vless: &str, node: &mut Proxy) -> bool {
     node.proxy_type = ProxyType::Vless;
     node.combined_proxy = Some(CombinedProxy::Vless(vless_proxy));
     node.remark = url_decode(url.fragment().unwrap_or(""));
-    node.hostname = host.to_string();
+    node.hostname = strip_ipv6_brackets(host).to_string();
     node.port = port;
 
     true
@@ -2,6 +2,7 @@ use std::time::{Duration, UNIX_EPOCH};
 
 use crate::models::{Proxy, RegexMatchConfigs};
 use crate::utils::base64::url_safe_base64_decode;
+use crate::utils::cached_regex;
 use crate::utils::system::safe_system_time;
 use crate::utils::url::get_url_arg;
 use regex::Regex;
@@ -110,7 +111,7 @@ pub fn get_sub_info_from_nodes(
         // Extract stream info if not already found
         if stream_info.is_empty() {
             for rule in stream_rules {
-                let re = Regex::new(&rule._match).ok()?;
+                let re = cached_regex(&rule._match).ok()?;
                 if re.is_match(remarks) {
                     let new_remark = re.replace(remarks, &rule.replace).to_string();
                     if new_remark != *remarks {
@@ -124,7 +125,7 @@ pub fn get_sub_info_from_nodes(
         // Extract time info if not already found
         if time_info.is_empty() {
             for rule in time_rules {
-                let re = Regex::new(&rule._match).ok()?;
+                let re = cached_regex(&rule._match).ok()?;
                 if re.is_match(remarks) {
                     let new_remark = re.replace(remarks, &rule.replace).to_string();
                     if new_remark != *remarks {
@@ -236,3 +237,117 @@ pub fn get_sub_info_from_ssd(sub: &str) -> Option<String> {
 
     Some(result)
 }
+
+/// Formats a byte count with the largest fitting binary unit, e.g. `123.45GB`
+fn int_to_stream(bytes: u64) -> String {
+    let units = ["B", "KB", "MB", "GB", "TB", "PB", "EB"];
+    let mut value = bytes as f64;
+    let mut index = 0;
+    while value >= 1024.0 && index < units.len() - 1 {
+        value /= 1024.0;
+        index += 1;
+    }
+    format!("{:.2}{}", value, units[index])
+}
+
+/// Formats a unix timestamp as a UTC `yyyy-mm-dd` date
+pub(crate) fn timestamp_to_date(timestamp: u64) -> String {
+    // Civil date from days since epoch, see http://howardhinnant.github.io/date_algorithms.html
+    let days = (timestamp / 86400) as i64 + 719468;
+    let era = days.div_euclid(146097);
+    let day_of_era = days.rem_euclid(146097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let mp = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+    format!("{:04}-{:02}-{:02}", year, month, day)
+}
+
+/// Builds placeholder nodes showing the remaining traffic and expiry date from
+/// a subscription info string such as `upload=0; download=1024; total=2048; expire=1735689600`
+///
+/// The nodes point to an unroutable local Shadowsocks server and are marked as
+/// info nodes so that only select groups offer them
+pub fn get_info_nodes_from_sub_info(sub_info: &str) -> Vec<Proxy> {
+    let mut upload: u64 = 0;
+    let mut download: u64 = 0;
+    let mut total: u64 = 0;
+    let mut expire: u64 = 0;
+    for item in sub_info.split([';', ',']) {
+        let Some((key, value)) = item.trim().split_once('=') else {
+            continue;
+        };
+        let value = value.trim().parse::<f64>().unwrap_or(0.0) as u64;
+        match key.trim() {
+            "upload" => upload = value,
+            "download" => download = value,
+            "total" => total = value,
+            "expire" => expire = value,
+            _ => {}
+        }
+    }
+
+    let mut remarks = Vec::new();
+    if total > 0 {
+        let left = total.saturating_sub(upload.saturating_add(download));
+        remarks.push(format!(
+            "剩余流量: {} / {}",
+            int_to_stream(left),
+            int_to_stream(total)
+        ));
+    }
+    if expire > 0 {
+        remarks.push(format!("过期时间: {}", timestamp_to_date(expire)));
+    }
+
+    remarks
+        .iter()
+        .map(|remark| {
+            let mut node = Proxy::ss_construct(
+                "",
+                remark,
+                "127.0.0.1",
+                1234,
+                "info",
+                "aes-128-gcm",
+                "",
+                "",
+                None,
+                None,
+                None,
+                None,
+                "",
+            );
+            node.info_node = true;
+            node
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_info_nodes_from_sub_info() {
+        let nodes = get_info_nodes_from_sub_info(
+            "upload=1073741824; download=1073741824; total=10737418240; expire=1735689600",
+        );
+        let remarks: Vec<_> = nodes.iter().map(|node| node.remark.as_str()).collect();
+        assert_eq!(
+            remarks,
+            ["剩余流量: 8.00GB / 10.00GB", "过期时间: 2025-01-01"]
+        );
+        assert!(nodes.iter().all(|node| node.info_node));
+
+        let nodes = get_info_nodes_from_sub_info("upload=0, download=0, total=0");
+        assert!(nodes.is_empty());
+
+        // Out of range usage saturates instead of overflowing
+        let nodes = get_info_nodes_from_sub_info("upload=1e30; download=1e30; total=1024");
+        assert_eq!(nodes[0].remark, "剩余流量: 0.00B / 1.00KB");
+    }
+}
@@ -1,6 +1,7 @@
 use std::collections::HashMap;
 
 use crate::models::RegexMatchConfigs;
+use crate::parser::explodes::NodeDiagnostic;
 use crate::utils::http::{parse_proxy, ProxyConfig};
 use crate::Settings;
 use case_insensitive_string::CaseInsensitiveString;
@@ -30,9 +31,25 @@ pub struct ParseSettings {
     /// Whether operations requiring authorization are allowed
     pub authorized: bool,
 
+    /// Whether SSD remarks get the airport name and traffic ratio
+    pub ssd_remark_info: bool,
+
+    /// Directories local subscriptions are read from without authorization
+    pub local_dirs: Vec<String>,
+
+    /// Whether the `proxy-providers` of Clash configs are read from their
+    /// `path:` files inside `local_dirs`
+    pub clash_provider_files: bool,
+
     /// HTTP request headers
     pub request_header: Option<HashMap<CaseInsensitiveString, String>>,
 
+    /// Entries dropped from the subscriptions parsed with these settings
+    pub diagnostics: Vec<NodeDiagnostic>,
+
+    /// Nodes removed by `exclude_remarks` and `include_remarks`
+    pub filtered_nodes: usize,
+
     /// JavaScript runtime - optional depending on feature flags
     #[cfg(feature = "js_runtime")]
     pub js_runtime: Option<()>, // Placeholder for actual JS runtime type
@@ -44,9 +61,13 @@ pub struct ParseSettings {
 
 impl Default for ParseSettings {
     fn default() -> Self {
-        // Get global settings
-        let settings = Settings::current();
+        ParseSettings::from_settings(&Settings::current())
+    }
+}
 
+impl ParseSettings {
+    /// Parse settings with the defaults of `settings`
+    pub fn from_settings(settings: &Settings) -> Self {
         ParseSettings {
             proxy: parse_proxy(&settings.proxy_subscription),
             exclude_remarks: if settings.exclude_remarks.is_empty() {
@@ -63,7 +84,14 @@ impl Default for ParseSettings {
             time_rules: None,   // TODO: Get from global settings
             sub_info: None,
             authorized: !settings.api_access_token.is_empty(),
+            ssd_remark_info: false,
+            local_dirs: std::iter::once("base".to_string())
+                .chain(settings.local_sub_dirs.iter().cloned())
+                .collect(),
+            clash_provider_files: settings.clash_provider_files,
             request_header: None,
+            diagnostics: Vec::new(),
+            filtered_nodes: 0,
             #[cfg(feature = "js_runtime")]
             js_runtime: None,
             #[cfg(feature = "js_runtime")]
@@ -1,13 +1,32 @@
+use std::path::Path;
+
+use crate::interfaces::SubconverterError;
 use crate::models::Proxy;
 use crate::parser::explodes::*;
 use crate::parser::infoparser::{get_sub_info_from_nodes, get_sub_info_from_ssd};
 use crate::parser::parse_settings::ParseSettings;
+use crate::utils::base64::url_safe_base64_decode;
+use crate::utils::file::canonicalize_path;
 use crate::utils::http::get_sub_info_from_header;
 use crate::utils::matcher::{apply_matcher, reg_find};
 use crate::utils::network::is_link;
 use crate::utils::url::url_decode;
-use crate::utils::{file_exists, file_get_async, web_get_async};
-use log::warn;
+use crate::utils::{file_get_async, web_get_async};
+use case_insensitive_string::CaseInsensitiveString;
+use log::{info, warn};
+
+/// Split a leading `tag:name,` or `ua:agent,` option off a subscription
+/// link, as `(key, value, rest)`
+///
+/// Only these prefixes are split off, so commas in the link itself are kept.
+fn split_link_option(link: &str) -> Option<(&str, &str, &str)> {
+    let key = ["tag", "ua"].into_iter().find(|key| {
+        link.strip_prefix(key)
+            .is_some_and(|rest| rest.starts_with(':'))
+    })?;
+    let (value, rest) = link[key.len() + 1..].split_once(',')?;
+    Some((key, value, rest))
+}
 
 /// Equivalent to ConfType enum in C++
 #[derive(Debug, PartialEq, Eq)]
@@ -31,13 +50,14 @@ pub enum ConfType {
 ///
 /// # Returns
 /// * `Ok(())` on success
-/// * `Err(String)` with error message on failure
+/// * `Err(SubconverterError)` on failure, `Unauthorized` for local files
+///   outside the allowed directories
 pub async fn add_nodes(
     mut link: String,
     all_nodes: &mut Vec<Proxy>,
     group_id: i32,
     parse_settings: &mut ParseSettings,
-) -> Result<(), String> {
+) -> Result<(), SubconverterError> {
     // Extract references to settings for easier access
     let proxy = &parse_settings.proxy;
     let exclude_remarks = parse_settings.exclude_remarks.as_ref();
@@ -59,15 +79,17 @@ pub async fn add_nodes(
     #[cfg(feature = "js_runtime")]
     if authorized && link.starts_with("script:") {
         // Script processing would go here
-        return Err("Script processing not implemented".to_string());
+        return Err("Script processing not implemented".to_string().into());
     }
 
-    // Handle tag: prefix for custom group
-    if link.starts_with("tag:") {
-        if let Some(pos) = link.find(',') {
-            custom_group = link[4..pos].to_string();
-            link = link[pos + 1..].to_string();
+    // Options given before the link, the link itself may contain commas
+    let mut user_agent = None;
+    while let Some((key, value, rest)) = split_link_option(&link) {
+        match key {
+            "tag" => custom_group = value.to_string(),
+            _ => user_agent = Some(value.to_string()),
         }
+        link = rest.to_string();
     }
 
     // Handle null node
@@ -78,6 +100,9 @@ pub async fn add_nodes(
         return Ok(());
     }
 
+    // file:// URLs and bare paths are only read from the allowed directories
+    let local_path = resolve_local_path(&link, &parse_settings.local_dirs, authorized).await?;
+
     // Determine link type
     let link_type = if link.starts_with("https://t.me/socks") || link.starts_with("tg://socks") {
         ConfType::SOCKS
@@ -87,7 +112,8 @@ pub async fn add_nodes(
         ConfType::SUB
     } else if link.starts_with("Netch://") {
         ConfType::Netch
-    } else if file_exists(&link).await {
+    } else if let Some(path) = local_path {
+        link = path;
         ConfType::Local
     } else {
         // Default to Unknown for direct proxy links or invalid links
@@ -104,21 +130,33 @@ pub async fn add_nodes(
                 }
             }
 
-            // Download subscription content
-            let response = match web_get_async(&link, proxy, request_header).await {
-                Ok(response) => response,
-                Err(e) => {
-                    warn!("Failed to get subscription content from {}: {}", link, e);
-                    return Err(format!("HTTP request failed: {}", e));
+            // Download subscription content, data: URIs carry it themselves
+            let (sub_content, headers) = if link.starts_with("data:") {
+                (decode_data_uri(&link)?, Default::default())
+            } else {
+                // A `ua:` of the link replaces the user agent of the request
+                let mut headers = request_header.cloned().unwrap_or_default();
+                if let Some(user_agent) = &user_agent {
+                    headers.insert(CaseInsensitiveString::new("User-Agent"), user_agent.clone());
+                }
+                let request_header = Some(&headers).filter(|headers| !headers.is_empty());
+                match web_get_async(&link, proxy, request_header).await {
+                    Ok(response) => (response.body, response.headers),
+                    Err(e) => {
+                        warn!("Failed to get subscription content from {}: {}", link, e);
+                        return Err(format!("HTTP request failed: {}", e).into());
+                    }
                 }
             };
 
-            let sub_content = response.body;
-            let headers = response.headers;
-
             if !sub_content.is_empty() {
                 // Parse the subscription content
-                let result = explode_conf_content(&sub_content, &mut nodes);
+                let mut diagnostics = Vec::new();
+                let result =
+                    explode_content(&sub_content, &mut nodes, parse_settings, &mut diagnostics)
+                        .await;
+                log_diagnostics(&diagnostics);
+                parse_settings.diagnostics.append(&mut diagnostics);
                 if result > 0 {
                     // Get subscription info
                     if sub_content.starts_with("ssd://") {
@@ -148,13 +186,15 @@ pub async fn add_nodes(
                     }
 
                     // Filter nodes and set group info
-                    filter_nodes(&mut nodes, exclude_remarks, include_remarks, group_id);
+                    parse_settings.filtered_nodes +=
+                        filter_nodes(&mut nodes, exclude_remarks, include_remarks, group_id);
 
                     // Set group_id and custom_group for all nodes
                     for node in &mut nodes {
                         node.group_id = group_id;
                         if !custom_group.is_empty() {
                             node.group = custom_group.clone();
+                            node.tag = custom_group.clone();
                         }
                     }
 
@@ -162,19 +202,18 @@ pub async fn add_nodes(
                     all_nodes.append(&mut nodes);
                     Ok(())
                 } else {
-                    Err(format!("Invalid subscription: '{}'", sub_content))
+                    Err(format!("Invalid subscription: '{}'", sub_content).into())
                 }
             } else {
-                Err("Cannot download subscription data".to_string())
+                Err("Cannot download subscription data".to_string().into())
             }
         }
         ConfType::Local => {
-            if !authorized {
-                return Err("Not authorized to access local files".to_string());
-            }
-
             // Read and parse local file
-            let result = explode_conf(&link, &mut nodes).await;
+            let mut diagnostics = Vec::new();
+            let result = explode_conf(&link, &mut nodes, parse_settings, &mut diagnostics).await;
+            log_diagnostics(&diagnostics);
+            parse_settings.diagnostics.append(&mut diagnostics);
             if result > 0 {
                 // The rest is similar to SUB case
                 // Get subscription info
@@ -198,38 +237,127 @@ pub async fn add_nodes(
                     }
                 }
 
-                filter_nodes(&mut nodes, exclude_remarks, include_remarks, group_id);
+                parse_settings.filtered_nodes +=
+                    filter_nodes(&mut nodes, exclude_remarks, include_remarks, group_id);
 
                 // Set group_id and custom_group for all nodes
                 for node in &mut nodes {
                     node.group_id = group_id;
                     if !custom_group.is_empty() {
                         node.group = custom_group.clone();
+                        node.tag = custom_group.clone();
                     }
                 }
 
                 all_nodes.append(&mut nodes);
                 Ok(())
             } else {
-                Err("Invalid configuration file".to_string())
+                Err("Invalid configuration file".to_string().into())
             }
         }
         _ => {
             // Handle direct link to a single proxy
             if explode(&link, &mut node) {
                 if node.proxy_type == crate::models::ProxyType::Unknown {
-                    return Err("No valid link found".to_string());
+                    return Err("No valid link found".to_string().into());
+                }
+                if let Err(reason) = check_node(&node) {
+                    let diagnostic = NodeDiagnostic {
+                        line: None,
+                        scheme: node.proxy_type.to_string().to_lowercase(),
+                        reason,
+                    };
+                    warn!("Dropped the link: {}", diagnostic);
+                    parse_settings.diagnostics.push(diagnostic);
+                    return Err("No valid link found".to_string().into());
                 }
                 node.group_id = group_id;
                 if !custom_group.is_empty() {
-                    node.group = custom_group;
+                    node.group = custom_group.clone();
+                    node.tag = custom_group;
                 }
                 all_nodes.push(node);
                 Ok(())
             } else {
-                Err("No valid link found".to_string())
+                Err("No valid link found".to_string().into())
+            }
+        }
+    }
+}
+
+/// Resolve a `file://` URL or a bare path to the file it names, `None` when
+/// `link` is neither
+///
+/// Relative paths are tried as given and then under each of `roots`, the way
+/// rulesets fall back to `base/`. Unless `authorized`, the file has to be
+/// inside one of `roots` once symlinks are followed.
+async fn resolve_local_path(
+    link: &str,
+    roots: &[String],
+    authorized: bool,
+) -> Result<Option<String>, SubconverterError> {
+    let file_url = link.strip_prefix("file://");
+    let path = match file_url {
+        Some(path) => url_decode(path),
+        None if link.contains("://") || link.starts_with("data:") => return Ok(None),
+        None => link.to_string(),
+    };
+
+    let mut candidates = vec![path.clone()];
+    if Path::new(&path).is_relative() {
+        candidates.extend(
+            roots
+                .iter()
+                .map(|root| Path::new(root).join(&path).to_string_lossy().to_string()),
+        );
+    }
+    let mut resolved = None;
+    for candidate in &candidates {
+        if let Ok(canonical) = canonicalize_path(candidate).await {
+            resolved = Some(canonical);
+            break;
+        }
+    }
+    let Some(resolved) = resolved else {
+        return match file_url {
+            Some(_) => Err(SubconverterError::NotFound(format!(
+                "local subscription not found: {}",
+                path
+            ))),
+            None => Ok(None),
+        };
+    };
+
+    if !authorized {
+        let mut allowed = false;
+        for root in roots {
+            if let Ok(root) = canonicalize_path(root).await {
+                if resolved.starts_with(&root) {
+                    allowed = true;
+                    break;
+                }
             }
         }
+        if !allowed {
+            warn!("Refusing to read {} outside the allowed directories", path);
+            return Err(SubconverterError::Unauthorized(format!(
+                "Not allowed to read local file: {}",
+                path
+            )));
+        }
+    }
+    Ok(Some(resolved.to_string_lossy().to_string()))
+}
+
+/// Content of a `data:[<mediatype>][;base64],<data>` URI
+fn decode_data_uri(link: &str) -> Result<String, SubconverterError> {
+    let (meta, data) = link["data:".len()..]
+        .split_once(',')
+        .ok_or_else(|| SubconverterError::InvalidInput("Malformed data: URI".to_string()))?;
+    if meta.split(';').any(|param| param == "base64") {
+        Ok(url_safe_base64_decode(&url_decode(data)))
+    } else {
+        Ok(url_decode(data))
     }
 }
 
@@ -249,35 +377,122 @@ fn get_url_arg(url: &str, arg_name: &str) -> Option<String> {
 
 /// Parses a configuration file into a vector of Proxy objects
 /// Returns the number of proxies parsed
-async fn explode_conf(path: &str, nodes: &mut Vec<Proxy>) -> i32 {
+async fn explode_conf(
+    path: &str,
+    nodes: &mut Vec<Proxy>,
+    parse_settings: &ParseSettings,
+    diagnostics: &mut Vec<NodeDiagnostic>,
+) -> i32 {
     // TODO: 安全问题，但是旧版subconverter也有……
     match file_get_async(path, None).await {
-        Ok(content) => explode_conf_content(&content, nodes),
+        Ok(content) => explode_content(&content, nodes, parse_settings, diagnostics).await,
         Err(_) => 0,
     }
 }
 
-/// Filters nodes based on include/exclude rules
-fn filter_nodes(
+/// [`explode_conf_content_with_diagnostics`], SSD remarks follow
+/// `ssd_remark_info` and the proxy providers of Clash configs are read when
+/// `clash_provider_files` is set
+async fn explode_content(
+    content: &str,
+    nodes: &mut Vec<Proxy>,
+    parse_settings: &ParseSettings,
+    diagnostics: &mut Vec<NodeDiagnostic>,
+) -> i32 {
+    if !content.trim_start().starts_with("ssd://") {
+        let mut count = explode_conf_content_with_diagnostics(content, nodes, diagnostics);
+        if parse_settings.clash_provider_files {
+            count +=
+                explode_clash_provider_files(content, nodes, parse_settings, diagnostics).await;
+        }
+        return count;
+    }
+    let orig_size = nodes.len();
+    explode_ssd_with_info(content, nodes, parse_settings.ssd_remark_info);
+    drop_invalid_nodes(nodes, orig_size, diagnostics);
+    (nodes.len() - orig_size) as i32
+}
+
+/// Nodes of the `proxy-providers` of a Clash config, read from their `path:`
+/// files
+///
+/// Only files inside `local_dirs` are read, whether or not the request is
+/// authorized. Providers without a file, e.g. `http` ones never fetched, are
+/// left out.
+async fn explode_clash_provider_files(
+    content: &str,
+    nodes: &mut Vec<Proxy>,
+    parse_settings: &ParseSettings,
+    diagnostics: &mut Vec<NodeDiagnostic>,
+) -> i32 {
+    let Ok(mut config) = serde_yaml::from_str::<serde_yaml::Value>(content) else {
+        return 0;
+    };
+    if config.apply_merge().is_err() {
+        return 0;
+    }
+    let Some(providers) = config
+        .get("proxy-providers")
+        .and_then(serde_yaml::Value::as_mapping)
+    else {
+        return 0;
+    };
+
+    let mut count = 0;
+    for (name, provider) in providers {
+        let name = name.as_str().unwrap_or_default();
+        let Some(path) = provider.get("path").and_then(serde_yaml::Value::as_str) else {
+            continue;
+        };
+        let path = match resolve_local_path(path, &parse_settings.local_dirs, false).await {
+            Ok(Some(path)) => path,
+            Ok(None) => {
+                info!("Skipped proxy provider {}: {} not found", name, path);
+                continue;
+            }
+            Err(e) => {
+                diagnostics.push(NodeDiagnostic {
+                    line: None,
+                    scheme: "proxy-provider".to_string(),
+                    reason: format!("skipped {:?}: {}", name, e),
+                });
+                continue;
+            }
+        };
+        match file_get_async(&path, None).await {
+            Ok(provider_content) => {
+                count +=
+                    explode_conf_content_with_diagnostics(&provider_content, nodes, diagnostics);
+            }
+            Err(e) => warn!("Failed to read proxy provider {}: {}", name, e),
+        }
+    }
+    count
+}
+
+/// Filters nodes based on include/exclude rules, returns how many nodes were
+/// removed
+pub(crate) fn filter_nodes(
     nodes: &mut Vec<Proxy>,
     exclude_remarks: Option<&Vec<String>>,
     include_remarks: Option<&Vec<String>>,
     group_id: i32,
-) {
+) -> usize {
+    let before = nodes.len();
     let mut node_index = 0;
     let mut i = 0;
 
     while i < nodes.len() {
         if should_ignore(&nodes[i], exclude_remarks, include_remarks) {
             // Log that node is ignored
-            println!(
+            info!(
                 "Node {} - {} has been ignored and will not be added.",
                 nodes[i].group, nodes[i].remark
             );
             nodes.remove(i);
         } else {
             // Log that node is added
-            println!(
+            info!(
                 "Node {} - {} has been added.",
                 nodes[i].group, nodes[i].remark
             );
@@ -287,6 +502,7 @@ fn filter_nodes(
             i += 1;
         }
     }
+    before - nodes.len()
 }
 
 /// Determines if a node should be ignored based on its remarks and the filtering rules
@@ -335,3 +551,172 @@ fn should_ignore(
     // A node is ignored if it's excluded OR not included
     excluded || !included
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::url_encode;
+
+    const SS_LINK: &str = "ss://YWVzLTI1Ni1nY206cGFzcw@1.2.3.4:8388#Local";
+
+    async fn add_link(link: &str, local_dirs: &[&Path]) -> Result<Vec<Proxy>, SubconverterError> {
+        let mut parse_settings = ParseSettings::default();
+        parse_settings.authorized = false;
+        parse_settings.local_dirs = local_dirs
+            .iter()
+            .map(|dir| dir.to_string_lossy().to_string())
+            .collect();
+        let mut nodes = Vec::new();
+        add_nodes(link.to_string(), &mut nodes, 0, &mut parse_settings).await?;
+        Ok(nodes)
+    }
+
+    #[test]
+    fn test_split_link_option() {
+        assert_eq!(
+            split_link_option("tag:airport1,ua:clash.meta,https://a.com/sub?x=1,2"),
+            Some(("tag", "airport1", "ua:clash.meta,https://a.com/sub?x=1,2"))
+        );
+        assert_eq!(
+            split_link_option("ua:clash.meta,https://a.com/sub?x=1,2"),
+            Some(("ua", "clash.meta", "https://a.com/sub?x=1,2"))
+        );
+        assert_eq!(split_link_option("https://a.com/sub?tag=1,2"), None);
+        assert_eq!(split_link_option("tagged:https://a.com"), None);
+    }
+
+    #[tokio::test]
+    async fn test_tag_option_is_kept_on_nodes() {
+        let encoded = crate::utils::base64::base64_encode(SS_LINK);
+        let nodes = add_link(
+            &format!("tag:airport1,ua:agent,data:text/plain;base64,{}", encoded),
+            &[],
+        )
+        .await
+        .unwrap();
+        assert_eq!(nodes[0].tag, "airport1");
+        assert_eq!(nodes[0].group, "airport1");
+    }
+
+    #[tokio::test]
+    async fn test_data_uri_subscriptions() {
+        let encoded = crate::utils::base64::base64_encode(SS_LINK);
+        let nodes = add_link(&format!("data:text/plain;base64,{}", encoded), &[])
+            .await
+            .unwrap();
+        assert_eq!(nodes[0].remark, "Local");
+
+        let nodes = add_link(&format!("data:,{}", url_encode(SS_LINK)), &[])
+            .await
+            .unwrap();
+        assert_eq!(nodes[0].hostname, "1.2.3.4");
+    }
+
+    #[tokio::test]
+    async fn test_clash_provider_files_stay_inside_allowed_dirs() {
+        let dir = tempfile::tempdir().unwrap();
+        let allowed = dir.path().join("allowed");
+        let outside = dir.path().join("outside");
+        std::fs::create_dir_all(allowed.join("providers")).unwrap();
+        std::fs::create_dir_all(&outside).unwrap();
+        let provider = |server: &str| {
+            format!(
+                "proxies:\n  - {{name: {0}, type: ss, server: {0}, port: 8388, cipher: aes-256-gcm, password: secret}}\n",
+                server
+            )
+        };
+        std::fs::write(allowed.join("providers/a.yaml"), provider("1.1.1.1")).unwrap();
+        std::fs::write(outside.join("b.yaml"), provider("2.2.2.2")).unwrap();
+        let config = format!(
+            "proxies:\n  - {{name: Own, type: ss, server: 3.3.3.3, port: 8388, cipher: aes-256-gcm, password: secret}}\n\
+             proxy-providers:\n  a: {{type: file, path: ./providers/a.yaml}}\n  b: {{type: file, path: {}}}\n  c: {{type: http, url: https://example.com/sub, path: ./providers/missing.yaml}}\n\
+             proxy-groups:\n  - {{name: Proxy, type: select, use: [a, b]}}\n",
+            outside.join("b.yaml").display()
+        );
+        std::fs::write(allowed.join("config.yaml"), config).unwrap();
+
+        let load = |clash_provider_files: bool| {
+            let allowed = allowed.clone();
+            async move {
+                let mut parse_settings = ParseSettings::default();
+                parse_settings.authorized = true;
+                parse_settings.local_dirs = vec![allowed.to_string_lossy().to_string()];
+                parse_settings.clash_provider_files = clash_provider_files;
+                let mut nodes = Vec::new();
+                add_nodes(
+                    "config.yaml".to_string(),
+                    &mut nodes,
+                    0,
+                    &mut parse_settings,
+                )
+                .await
+                .unwrap();
+                let servers: Vec<String> = nodes.into_iter().map(|node| node.hostname).collect();
+                (servers, parse_settings.diagnostics)
+            }
+        };
+
+        let (servers, _) = load(false).await;
+        assert_eq!(servers, ["3.3.3.3"]);
+        // Authorized requests do not read outside the directories either
+        let (servers, diagnostics) = load(true).await;
+        assert_eq!(servers, ["3.3.3.3", "1.1.1.1"]);
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(diagnostics[0].scheme, "proxy-provider");
+        assert!(diagnostics[0].reason.contains("\"b\""), "{:?}", diagnostics);
+    }
+
+    #[tokio::test]
+    async fn test_local_files_stay_inside_allowed_dirs() {
+        let dir = tempfile::tempdir().unwrap();
+        let allowed = dir.path().join("allowed");
+        let outside = dir.path().join("outside");
+        std::fs::create_dir_all(allowed.join("sub")).unwrap();
+        std::fs::create_dir_all(&outside).unwrap();
+        std::fs::write(allowed.join("sub/nodes.txt"), SS_LINK).unwrap();
+        std::fs::write(outside.join("nodes.txt"), SS_LINK).unwrap();
+
+        let file_url = format!("file://{}", allowed.join("sub/nodes.txt").display());
+        assert_eq!(add_link(&file_url, &[&allowed]).await.unwrap().len(), 1);
+        // Bare paths are looked up under the allowed directories
+        assert_eq!(
+            add_link("sub/nodes.txt", &[&allowed]).await.unwrap().len(),
+            1
+        );
+
+        let escapes = [
+            format!("file://{}", outside.join("nodes.txt").display()),
+            format!("file://{}/../outside/nodes.txt", allowed.display()),
+            "../outside/nodes.txt".to_string(),
+        ];
+        for link in &escapes {
+            let error = add_link(link, &[&allowed]).await.unwrap_err();
+            assert_eq!(error.status_code(), 403, "{}", link);
+        }
+
+        let missing = format!("file://{}", allowed.join("missing.txt").display());
+        let error = add_link(&missing, &[&allowed]).await.unwrap_err();
+        assert_eq!(error.status_code(), 404);
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_symlinks_out_of_allowed_dirs_are_refused() {
+        let dir = tempfile::tempdir().unwrap();
+        let allowed = dir.path().join("allowed");
+        let outside = dir.path().join("outside");
+        std::fs::create_dir_all(&allowed).unwrap();
+        std::fs::create_dir_all(&outside).unwrap();
+        std::fs::write(outside.join("nodes.txt"), SS_LINK).unwrap();
+        std::os::unix::fs::symlink(outside.join("nodes.txt"), allowed.join("file.txt")).unwrap();
+        std::os::unix::fs::symlink(&outside, allowed.join("dir")).unwrap();
+
+        for link in ["file.txt", "dir/nodes.txt"] {
+            let error = add_link(link, &[&allowed]).await.unwrap_err();
+            assert_eq!(error.status_code(), 403, "{}", link);
+        }
+        let file_url = format!("file://{}", allowed.join("dir/nodes.txt").display());
+        let error = add_link(&file_url, &[&allowed]).await.unwrap_err();
+        assert_eq!(error.status_code(), 403);
+    }
+}
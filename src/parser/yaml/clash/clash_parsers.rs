@@ -1,8 +1,12 @@
+use std::collections::BTreeMap;
+
+use serde::Deserialize;
+use serde_yaml::Value;
+
 use crate::models::Proxy;
+use crate::parser::explodes::NodeDiagnostic;
 use crate::parser::yaml::clash::clash_proxy_types::ClashProxyYamlInput;
 
-use super::ClashYamlInput;
-
 /// Parse Clash configuration from YAML string
 ///
 /// This function is the Rust equivalent of the C++ `explodeClash` function.
@@ -11,15 +15,65 @@ use super::ClashYamlInput;
 /// 2. Proper error handling with Result type
 /// 3. Automatic deserialization using serde
 /// 4. Cleaner pattern matching compared to C++ if/else chains
+///
+/// Besides a whole config, a proxy provider file is accepted: either a document
+/// with only `proxies:` or a bare sequence of proxies.
 pub fn parse_clash_yaml(content: &str) -> Result<Vec<Proxy>, String> {
-    let clash_input: ClashYamlInput = match serde_yaml::from_str(content) {
-        Ok(input) => input,
-        Err(e) => return Err(format!("Failed to parse Clash YAML: {}", e)),
+    parse_clash_yaml_with_diagnostics(content, &mut Vec::new())
+}
+
+/// [`parse_clash_yaml`], proxies which can not be used are added to
+/// `diagnostics` instead of failing the whole document
+///
+/// Proxies of a type no parser knows are counted per type, malformed ones are
+/// reported by name. Everything besides `proxies` is ignored and `<<` merge
+/// keys are resolved first, so full configs with groups, rules and anchors
+/// parse as well.
+pub fn parse_clash_yaml_with_diagnostics(
+    content: &str,
+    diagnostics: &mut Vec<NodeDiagnostic>,
+) -> Result<Vec<Proxy>, String> {
+    let mut document: Value =
+        serde_yaml::from_str(content).map_err(|e| format!("Failed to parse Clash YAML: {}", e))?;
+    document
+        .apply_merge()
+        .map_err(|e| format!("Failed to parse Clash YAML: {}", e))?;
+
+    let items = match document {
+        Value::Sequence(items) => items,
+        Value::Mapping(mut config) => match config.remove("proxies") {
+            Some(Value::Sequence(items)) => items,
+            _ => Vec::new(),
+        },
+        _ => return Err("Failed to parse Clash YAML: not a config".to_string()),
     };
 
     let mut proxies = Vec::new();
+    let mut unknown_types = BTreeMap::<String, usize>::new();
+
+    for item in items {
+        let proxy_type = item
+            .get("type")
+            .and_then(Value::as_str)
+            .unwrap_or_default()
+            .to_string();
+        let name = item
+            .get("name")
+            .and_then(Value::as_str)
+            .unwrap_or_default()
+            .to_string();
+        let proxy = match ClashProxyYamlInput::deserialize(item) {
+            Ok(proxy) => proxy,
+            Err(e) => {
+                diagnostics.push(NodeDiagnostic {
+                    line: None,
+                    scheme: proxy_type,
+                    reason: format!("skipped proxy {:?}: {}", name, e),
+                });
+                continue;
+            }
+        };
 
-    for proxy in clash_input.extract_proxies() {
         match proxy {
             ClashProxyYamlInput::Shadowsocks(ss) => {
                 proxies.push(ss.into());
@@ -58,10 +112,18 @@ pub fn parse_clash_yaml(content: &str) -> Result<Vec<Proxy>, String> {
                 proxies.push(anytls.into());
             }
             ClashProxyYamlInput::Unknown => {
-                // Skip unknown proxy types
+                *unknown_types.entry(proxy_type).or_default() += 1;
             }
         }
     }
 
+    for (proxy_type, count) in unknown_types {
+        diagnostics.push(NodeDiagnostic {
+            line: None,
+            scheme: proxy_type,
+            reason: format!("unsupported proxy type, {} skipped", count),
+        });
+    }
+
     Ok(proxies)
 }
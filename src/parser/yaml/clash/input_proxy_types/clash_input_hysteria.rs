@@ -2,7 +2,7 @@ use serde::Deserialize;
 
 use crate::models::proxy::Proxy;
 use crate::models::proxy::ProxyType;
-use crate::utils::deserialize::deserialize_string_or_number;
+use crate::utils::deserialize::{deserialize_alpn, deserialize_string_or_number};
 use crate::utils::tribool::OptionSetExt;
 
 /// Represents a Hysteria proxy in Clash configuration
@@ -36,7 +36,7 @@ pub struct ClashInputHysteria {
     sni: Option<String>,
     #[serde(default)]
     fingerprint: Option<String>,
-    #[serde(default)]
+    #[serde(default, deserialize_with = "deserialize_alpn")]
     alpn: Option<Vec<String>>,
     #[serde(default)]
     ca: Option<String>,
@@ -195,12 +195,7 @@ impl Into<Proxy> for ClashInputHysteria {
         proxy.sni = self.sni;
         proxy.fingerprint = self.fingerprint;
 
-        // Handle alpn as a HashSet
-        if let Some(alpn_values) = self.alpn {
-            for value in alpn_values {
-                proxy.alpn.insert(value);
-            }
-        }
+        proxy.alpn = self.alpn;
 
         proxy.ca = self.ca;
         proxy.ca_str = self.ca_str;
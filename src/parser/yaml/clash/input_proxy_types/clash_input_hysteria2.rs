@@ -1,10 +1,8 @@
-use std::collections::HashSet;
-
 use serde::Deserialize;
 
 use crate::models::proxy::Proxy;
 use crate::models::proxy::ProxyType;
-use crate::utils::deserialize::deserialize_string_or_number;
+use crate::utils::deserialize::{deserialize_alpn, deserialize_string_or_number};
 use crate::utils::tribool::OptionSetExt;
 
 /// Represents a Hysteria2 proxy in Clash configuration
@@ -29,8 +27,8 @@ pub struct ClashInputHysteria2 {
     obfs_password: Option<String>,
     #[serde(default)]
     fingerprint: Option<String>,
-    #[serde(default)]
-    alpn: Option<String>,
+    #[serde(default, deserialize_with = "deserialize_alpn")]
+    alpn: Option<Vec<String>>,
     #[serde(default)]
     ca: Option<String>,
     #[serde(alias = "ca-str", default)]
@@ -94,8 +92,8 @@ impl ClashInputHysteria2 {
         self.fingerprint.as_deref()
     }
 
-    pub fn alpn(&self) -> Option<&str> {
-        self.alpn.as_deref()
+    pub fn alpn(&self) -> Option<&Vec<String>> {
+        self.alpn.as_ref()
     }
 
     pub fn ca(&self) -> Option<&str> {
@@ -158,16 +156,7 @@ impl Into<Proxy> for ClashInputHysteria2 {
         // Set TLS related fields
         proxy.fingerprint = self.fingerprint;
 
-        // Handle alpn as a comma-separated string to HashSet
-        if let Some(alpn_value) = self.alpn {
-            let mut alpn_set = HashSet::new();
-            for value in alpn_value.split(',').map(|s| s.trim().to_string()) {
-                if !value.is_empty() {
-                    alpn_set.insert(value);
-                }
-            }
-            proxy.alpn = alpn_set;
-        }
+        proxy.alpn = self.alpn;
 
         proxy.ca = self.ca;
         proxy.ca_str = self.ca_str;
@@ -1,8 +1,9 @@
-
 use serde::Deserialize;
 
+use super::clash_input_vless::{GrpcOptions, WsOptions};
 use crate::models::proxy::Proxy;
 use crate::models::proxy::ProxyType;
+use crate::utils::deserialize::deserialize_alpn;
 use crate::utils::tribool::OptionSetExt;
 
 /// Represents a Trojan proxy in Clash configuration
@@ -23,6 +24,14 @@ pub struct ClashInputTrojan {
     network: Option<String>,
     #[serde(default)]
     sni: Option<String>,
+    #[serde(default, deserialize_with = "deserialize_alpn")]
+    alpn: Option<Vec<String>>,
+    #[serde(alias = "client-fingerprint", default)]
+    client_fingerprint: Option<String>,
+    #[serde(alias = "ws-opts", default)]
+    ws_opts: Option<WsOptions>,
+    #[serde(alias = "grpc-opts", default)]
+    grpc_opts: Option<GrpcOptions>,
 }
 
 impl ClashInputTrojan {
@@ -61,6 +70,14 @@ impl ClashInputTrojan {
     pub fn sni(&self) -> Option<&str> {
         self.sni.as_deref()
     }
+
+    pub fn alpn(&self) -> Option<&Vec<String>> {
+        self.alpn.as_ref()
+    }
+
+    pub fn client_fingerprint(&self) -> Option<&str> {
+        self.client_fingerprint.as_deref()
+    }
 }
 
 impl Into<Proxy> for ClashInputTrojan {
@@ -75,8 +92,24 @@ impl Into<Proxy> for ClashInputTrojan {
         proxy.tcp_fast_open.set_if_some(self.tfo);
         proxy.allow_insecure.set_if_some(self.skip_cert_verify);
         proxy.sni = self.sni;
+        proxy.fingerprint = self.client_fingerprint;
+        proxy.alpn = self.alpn;
 
         if let Some(net) = self.network {
+            match net.as_str() {
+                "ws" => {
+                    if let Some(opts) = self.ws_opts {
+                        proxy.path = opts.path;
+                        proxy.host = opts
+                            .headers
+                            .and_then(|headers| headers.get("Host").cloned());
+                    }
+                }
+                "grpc" => {
+                    proxy.path = self.grpc_opts.and_then(|opts| opts.grpc_service_name);
+                }
+                _ => {}
+            }
             proxy.transfer_protocol = Some(net);
         }
 
@@ -6,6 +6,7 @@ use crate::models::proxy::Proxy;
 use crate::models::proxy::ProxyType;
 use crate::models::proxy_node::combined::CombinedProxy;
 use crate::models::proxy_node::vless::VlessProxy;
+use crate::utils::deserialize::deserialize_alpn;
 
 /// Represents a VLESS proxy in Clash configuration
 #[derive(Debug, Clone, Deserialize)]
@@ -19,7 +20,7 @@ pub struct ClashInputVLess {
     flow: Option<String>,
     #[serde(default)]
     tls: Option<bool>,
-    #[serde(default)]
+    #[serde(default, deserialize_with = "deserialize_alpn")]
     alpn: Option<Vec<String>>,
     #[serde(default)]
     udp: Option<bool>,
@@ -232,12 +233,7 @@ impl Into<Proxy> for ClashInputVLess {
         vless_proxy.servername = self.servername;
         vless_proxy.client_fingerprint = self.client_fingerprint;
 
-        // Handle ALPN
-        if let Some(alpn_values) = self.alpn {
-            for value in alpn_values {
-                vless_proxy.alpn.insert(value);
-            }
-        }
+        vless_proxy.alpn = self.alpn;
 
         // Handle network-specific options
         if let Some(net) = self.network.as_deref() {
@@ -2,6 +2,7 @@ use std::collections::HashMap;
 
 use serde::Deserialize;
 
+use super::clash_input_vless::{GrpcOptions, H2Options, HttpOptions, WsOptions};
 use crate::models::proxy::Proxy;
 use crate::models::proxy::ProxyType;
 use crate::utils::tribool::OptionSetExt;
@@ -29,10 +30,22 @@ pub struct ClashInputVMess {
     ws_path: Option<String>,
     #[serde(alias = "ws-headers", default)]
     ws_headers: Option<HashMap<String, String>>,
+    #[serde(alias = "ws-opts", default)]
+    ws_opts: Option<WsOptions>,
+    #[serde(alias = "http-opts", default)]
+    http_opts: Option<HttpOptions>,
+    #[serde(alias = "h2-opts", default)]
+    h2_opts: Option<H2Options>,
+    #[serde(alias = "grpc-opts", default)]
+    grpc_opts: Option<GrpcOptions>,
     #[serde(default)]
     tls: Option<bool>,
     #[serde(alias = "servername", default)]
     servername: Option<String>,
+    #[serde(default)]
+    sni: Option<String>,
+    #[serde(alias = "client-fingerprint", default)]
+    client_fingerprint: Option<String>,
 }
 
 impl ClashInputVMess {
@@ -91,6 +104,10 @@ impl ClashInputVMess {
     pub fn servername(&self) -> Option<&str> {
         self.servername.as_deref()
     }
+
+    pub fn client_fingerprint(&self) -> Option<&str> {
+        self.client_fingerprint.as_deref()
+    }
 }
 
 impl Into<Proxy> for ClashInputVMess {
@@ -107,17 +124,25 @@ impl Into<Proxy> for ClashInputVMess {
         proxy.tcp_fast_open.set_if_some(self.tfo);
         proxy.allow_insecure.set_if_some(self.skip_cert_verify);
         proxy.tls_secure = self.tls.unwrap_or(false);
-        proxy.server_name = self.servername;
+        proxy.sni = self
+            .sni
+            .into_iter()
+            .chain(self.servername)
+            .find(|name| !name.is_empty());
+        proxy.fingerprint = self.client_fingerprint;
 
         // Network protocol handling
         if let Some(net) = self.network {
             proxy.transfer_protocol = Some(net.clone());
             match net.as_str() {
                 "ws" => {
-                    if let Some(path) = self.ws_path {
-                        proxy.path = Some(path);
-                    }
-                    if let Some(headers) = self.ws_headers {
+                    // Meta nests the options, the legacy keys are still read
+                    let (path, headers) = match self.ws_opts {
+                        Some(opts) => (opts.path, opts.headers),
+                        None => (self.ws_path, self.ws_headers),
+                    };
+                    proxy.path = path;
+                    if let Some(headers) = headers {
                         if let Some(host) = headers.get("Host") {
                             proxy.host = Some(host.clone());
                         }
@@ -127,32 +152,41 @@ impl Into<Proxy> for ClashInputVMess {
                     }
                 }
                 "http" => {
-                    if let Some(path) = self.ws_path {
-                        proxy.path = Some(path);
-                    }
-                    if let Some(headers) = self.ws_headers {
-                        if let Some(host) = headers.get("Host") {
-                            proxy.host = Some(host.clone());
-                        }
-                        if let Some(edge) = headers.get("Edge") {
-                            proxy.edge = Some(edge.clone());
+                    if let Some(opts) = self.http_opts {
+                        proxy.path = opts.path.and_then(|paths| paths.into_iter().next());
+                        proxy.host = opts
+                            .headers
+                            .and_then(|headers| headers.get("Host")?.first().cloned());
+                    } else {
+                        proxy.path = self.ws_path;
+                        if let Some(headers) = self.ws_headers {
+                            if let Some(host) = headers.get("Host") {
+                                proxy.host = Some(host.clone());
+                            }
+                            if let Some(edge) = headers.get("Edge") {
+                                proxy.edge = Some(edge.clone());
+                            }
                         }
                     }
                 }
                 "h2" => {
-                    if let Some(path) = self.ws_path {
-                        proxy.path = Some(path);
-                    }
-                    if let Some(headers) = self.ws_headers {
-                        if let Some(host) = headers.get("Host") {
-                            proxy.host = Some(host.clone());
+                    if let Some(opts) = self.h2_opts {
+                        proxy.path = opts.path;
+                        proxy.host = opts.host.and_then(|hosts| hosts.into_iter().next());
+                    } else {
+                        proxy.path = self.ws_path;
+                        if let Some(headers) = self.ws_headers {
+                            if let Some(host) = headers.get("Host") {
+                                proxy.host = Some(host.clone());
+                            }
                         }
                     }
                 }
                 "grpc" => {
-                    if let Some(path) = self.ws_path {
-                        proxy.path = Some(path);
-                    }
+                    proxy.path = match self.grpc_opts {
+                        Some(opts) => opts.grpc_service_name,
+                        None => self.ws_path,
+                    };
                     proxy.host = Some(self.server);
                 }
                 _ => {}
@@ -4,5 +4,5 @@ mod clash_proxy_types;
 mod input_proxy_types;
 
 pub use clash_input::ClashYamlInput;
-pub use clash_parsers::parse_clash_yaml;
+pub use clash_parsers::{parse_clash_yaml, parse_clash_yaml_with_diagnostics};
 pub use clash_proxy_types::ClashProxyYamlInput;
@@ -5,6 +5,7 @@ use std::pin::Pin;
 use futures::future::join_all;
 use log::{debug, info, warn}; // Import for parallel execution
 
+use crate::generator::ruleconvert::Behavior;
 use crate::models::ruleset::{get_ruleset_type_from_url, RulesetContent, RulesetType};
 use crate::models::RulesetConfig;
 use crate::utils::file::read_file_async;
@@ -18,8 +19,21 @@ pub async fn fetch_ruleset(
     url: &str,
     proxy: &ProxyConfig,
     cache_timeout: u32,
-    _async_fetch: bool,
+    async_fetch: bool,
 ) -> Result<String, String> {
+    fetch_ruleset_with_cache_status(url, proxy, cache_timeout, async_fetch)
+        .await
+        .map(|(content, _)| content)
+}
+
+/// Same as [`fetch_ruleset`], also telling whether the content came from the
+/// cache
+pub async fn fetch_ruleset_with_cache_status(
+    url: &str,
+    proxy: &ProxyConfig,
+    cache_timeout: u32,
+    _async_fetch: bool,
+) -> Result<(String, bool), String> {
     debug!("Requesting ruleset from: {}", url);
     let mut local_path = url.to_string();
 
@@ -39,7 +53,7 @@ pub async fn fetch_ruleset(
         let cache_key = &local_path;
         if let Some(content) = memory_cache::get_if_valid(cache_key, cache_timeout) {
             debug!("Using cached ruleset for URL: {}", url);
-            return Ok(content);
+            return Ok((content, true));
         }
     }
 
@@ -61,7 +75,7 @@ pub async fn fetch_ruleset(
                     }
                 }
 
-                return Ok(content);
+                return Ok((content, false));
             }
             Err(e) => return Err(format!("Error reading rule file: {}", e)),
         }
@@ -76,7 +90,7 @@ pub async fn fetch_ruleset(
                     warn!("Failed to store ruleset in cache: {}", e);
                 }
             }
-            Ok(content)
+            Ok((content, false))
         }
         Err(e) => Err(e),
     }
@@ -96,12 +110,11 @@ async fn fetch_from_url(url: &str, proxy: &ProxyConfig) -> Result<String, String
 pub async fn refresh_rulesets(
     ruleset_list: &[RulesetConfig],
     ruleset_content_array: &mut Vec<RulesetContent>,
+    settings: &Settings,
 ) {
     // Clear existing ruleset content
     ruleset_content_array.clear();
 
-    // Get global settings
-    let settings = Settings::current();
     let proxy = parse_proxy(&settings.proxy_ruleset);
 
     // Create a vector of boxed futures for parallel ruleset fetching
@@ -112,6 +125,8 @@ pub async fn refresh_rulesets(
         let rule_group = ruleset_config.group.clone();
         let rule_url = ruleset_config.url.clone();
         let interval = ruleset_config.interval;
+        let behavior = ruleset_config.behavior;
+        let geo = ruleset_config.geo.clone();
 
         // Keep inline rules in fetch result stream to preserve input order.
         if let Some(pos) = rule_url.find("[]") {
@@ -130,6 +145,9 @@ pub async fn refresh_rulesets(
                     original_url: original_url_clone,
                     url_type: RulesetType::default(),
                     interval,
+                    behavior,
+                    geo,
+                    from_cache: false,
                     content: Some(inline_rule),
                 }
             };
@@ -158,9 +176,14 @@ pub async fn refresh_rulesets(
 
                     // Create the future and box it
                     let future = async move {
-                        let content =
-                            fetch_ruleset(&fetch_url, &proxy_clone, cache_ruleset, async_fetch)
-                                .await;
+                        let content = fetch_ruleset_with_cache_status(
+                            &fetch_url,
+                            &proxy_clone,
+                            cache_ruleset,
+                            async_fetch,
+                        )
+                        .await
+                        .ok();
 
                         FetchResult {
                             url: fetch_url,
@@ -168,7 +191,10 @@ pub async fn refresh_rulesets(
                             original_url: original_url_clone, // Use cloned original URL
                             url_type: detected_type,
                             interval,
-                            content: content.ok(), // Convert Result to Option
+                            behavior,
+                            geo,
+                            from_cache: content.as_ref().is_some_and(|(_, cached)| *cached),
+                            content: content.map(|(content, _)| content),
                         }
                     };
 
@@ -192,8 +218,14 @@ pub async fn refresh_rulesets(
 
             // Create the future and box it
             let future = async move {
-                let content =
-                    fetch_ruleset(&fetch_url, &proxy_clone, cache_ruleset, async_fetch).await;
+                let content = fetch_ruleset_with_cache_status(
+                    &fetch_url,
+                    &proxy_clone,
+                    cache_ruleset,
+                    async_fetch,
+                )
+                .await
+                .ok();
 
                 FetchResult {
                     url: fetch_url, // Use fetch_url (which is same as original_url here)
@@ -201,7 +233,10 @@ pub async fn refresh_rulesets(
                     original_url: original_url_clone,
                     url_type: RulesetType::default(),
                     interval,
-                    content: content.ok(),
+                    behavior,
+                    geo,
+                    from_cache: content.as_ref().is_some_and(|(_, cached)| *cached),
+                    content: content.map(|(content, _)| content),
                 }
             };
 
@@ -220,6 +255,9 @@ pub async fn refresh_rulesets(
             ruleset.rule_path_typed = result.original_url; // Store the original URL with type prefix
             ruleset.rule_type = result.url_type;
             ruleset.update_interval = result.interval;
+            ruleset.behavior = result.behavior;
+            ruleset.geo = result.geo;
+            ruleset.from_cache = result.from_cache;
 
             // Set rule content
             ruleset.set_rule_content(&content);
@@ -239,12 +277,11 @@ pub async fn refresh_rulesets(
 pub async fn refresh_rulesets(
     ruleset_list: &[RulesetConfig],
     ruleset_content_array: &mut Vec<RulesetContent>,
+    settings: &Settings,
 ) {
     // Clear existing ruleset content
     ruleset_content_array.clear();
 
-    // Get global settings
-    let settings = Settings::current();
     let proxy = parse_proxy(&settings.proxy_ruleset);
 
     // Process rulesets sequentially
@@ -252,6 +289,8 @@ pub async fn refresh_rulesets(
         let rule_group = ruleset_config.group.clone();
         let rule_url = ruleset_config.url.clone();
         let interval = ruleset_config.interval;
+        let behavior = ruleset_config.behavior;
+        let geo = ruleset_config.geo.clone();
 
         // Handle inline rules directly
         if let Some(pos) = rule_url.find("[]") {
@@ -297,15 +336,19 @@ pub async fn refresh_rulesets(
         let async_fetch = settings.async_fetch_ruleset; // This flag might be less relevant now, but kept for consistency
 
         let content_result =
-            fetch_ruleset(&fetch_url, &proxy_clone, cache_ruleset, async_fetch).await;
+            fetch_ruleset_with_cache_status(&fetch_url, &proxy_clone, cache_ruleset, async_fetch)
+                .await;
 
         // Process the result
         match content_result {
-            Ok(content) => {
+            Ok((content, from_cache)) => {
                 let mut ruleset = RulesetContent::new(&fetch_url, &rule_group); // Use fetched URL
                 ruleset.rule_path_typed = original_url; // Store original URL
                 ruleset.rule_type = detected_type;
                 ruleset.update_interval = interval;
+                ruleset.behavior = behavior;
+                ruleset.geo = geo;
+                ruleset.from_cache = from_cache;
                 ruleset.set_rule_content(&content);
                 ruleset_content_array.push(ruleset);
             }
@@ -327,6 +370,9 @@ struct FetchResult {
     original_url: String,
     url_type: RulesetType,
     interval: u32,
+    behavior: Option<Behavior>,
+    geo: Vec<String>,
+    from_cache: bool,
     content: Option<String>,
 }
 
@@ -50,6 +50,10 @@ impl From<YamlExternalSettings> for ExternalSettings {
         // Copy template arguments
         settings.tpl_args = yaml_settings.tpl_args;
 
+        // Copy QuantumultX sections
+        settings.quanx_rewrite_remote = yaml_settings.quantumultx.rewrite_remote;
+        settings.quanx_task_local = yaml_settings.quantumultx.task_local;
+
         settings
     }
 }
@@ -97,6 +101,10 @@ impl From<TomlExternalSettings> for ExternalSettings {
         // Copy template arguments
         settings.tpl_args = toml_settings.tpl_args;
 
+        // Copy QuantumultX sections
+        settings.quanx_rewrite_remote = toml_settings.quantumultx.rewrite_remote;
+        settings.quanx_task_local = toml_settings.quantumultx.task_local;
+
         settings
     }
 }
@@ -118,8 +126,8 @@ impl From<IniExternalSettings> for ExternalSettings {
         settings.singbox_rule_base = ini_settings.singbox_rule_base;
 
         // Rule generation options
-        settings.enable_rule_generator = Some(ini_settings.enable_rule_generator);
-        settings.overwrite_original_rules = Some(ini_settings.overwrite_original_rules);
+        settings.enable_rule_generator = ini_settings.enable_rule_generator;
+        settings.overwrite_original_rules = ini_settings.overwrite_original_rules;
 
         // Emoji options
         settings.add_emoji = ini_settings.add_emoji;
@@ -138,6 +146,10 @@ impl From<IniExternalSettings> for ExternalSettings {
         // Copy template arguments
         settings.tpl_args = ini_settings.tpl_args;
 
+        // Copy QuantumultX sections
+        settings.quanx_rewrite_remote = ini_settings.quanx_rewrite_remote;
+        settings.quanx_task_local = ini_settings.quanx_task_local;
+
         settings
     }
 }
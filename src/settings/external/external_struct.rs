@@ -3,8 +3,9 @@ use std::collections::HashMap;
 use toml;
 
 use crate::models::{ProxyGroupConfig, RegexMatchConfig, RulesetConfig};
+use crate::rulesets::ruleset::fetch_ruleset;
 use crate::settings::Settings;
-use crate::utils::file::load_content_async;
+use crate::utils::http::parse_proxy;
 // TODO: Implement template rendering module similar to C++ render_template function
 
 use super::ini_external::IniExternalSettings;
@@ -46,6 +47,10 @@ pub struct ExternalSettings {
 
     // Template arguments
     pub tpl_args: Option<HashMap<String, String>>,
+
+    // QuantumultX sections
+    pub quanx_rewrite_remote: Vec<String>,
+    pub quanx_task_local: Vec<String>,
 }
 
 impl ExternalSettings {
@@ -62,15 +67,21 @@ impl ExternalSettings {
     // }
 
     /// Load external configuration from file or URL asynchronously
+    ///
+    /// Content is fetched like rulesets, through the configured proxy and
+    /// config cache, with local paths also looked up under `base/`
     pub async fn load_from_file(path: &str) -> Result<Self, Box<dyn std::error::Error>> {
-        // Load content from file or URL asynchronously
-        let _content = load_content_async(path).await?;
+        let (proxy, cache_timeout) = {
+            let global = Settings::current();
+            (parse_proxy(&global.proxy_config), global.cache_config)
+        };
+        let content = fetch_ruleset(path, &proxy, cache_timeout, false).await?;
 
-        Self::parse_content(&_content).await
+        Self::parse_content(&content).await
     }
 
     /// Parse the content and return an ExternalSettings object
-    async fn parse_content(content: &str) -> Result<Self, Box<dyn std::error::Error>> {
+    pub(crate) async fn parse_content(content: &str) -> Result<Self, Box<dyn std::error::Error>> {
         // TODO: Implement template rendering here
         // In C++: if(render_template(config, *ext.tpl_args, base_content, global.templatePath) != 0)
         //           base_content = config;
@@ -117,3 +128,50 @@ impl ExternalSettings {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_parse_ini_external_config() {
+        let content = "[custom]\n\
+            ruleset=DIRECT,[]GEOIP,CN\n\
+            ruleset=Proxy,[]FINAL\n\
+            custom_proxy_group=Proxy`select`.*\n\
+            custom_proxy_group=Auto`url-test`(HK|SG)`http://www.gstatic.com/generate_204`300,,50\n\
+            exclude_remarks=(到期|剩余流量)\n\
+            exclude_remarks=^.{1,2}$\n\
+            overwrite_original_rules=true\n\
+            clash_rule_base=base/all_base.tpl\n\
+            [quantumultx]\n\
+            rewrite_remote=https://rewrite.example.com/a.conf, tag=A, enabled=true\n\
+            task_local=0 9 * * * https://task.example.com/sign.js, tag=Sign\n";
+        let ext = ExternalSettings::parse_content(content).await.unwrap();
+
+        assert_eq!(ext.custom_rulesets.len(), 2);
+        assert_eq!(ext.custom_proxy_groups.len(), 2);
+        assert_eq!(ext.custom_proxy_groups[1].name, "Auto");
+        assert_eq!(ext.exclude_remarks, vec!["(到期|剩余流量)", "^.{1,2}$"]);
+        assert_eq!(ext.enable_rule_generator, None);
+        assert_eq!(ext.overwrite_original_rules, Some(true));
+        assert_eq!(ext.clash_rule_base, "base/all_base.tpl");
+        assert_eq!(
+            ext.quanx_rewrite_remote,
+            vec!["https://rewrite.example.com/a.conf, tag=A, enabled=true"]
+        );
+        assert_eq!(
+            ext.quanx_task_local,
+            vec!["0 9 * * * https://task.example.com/sign.js, tag=Sign"]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_malformed_external_config_is_an_error() {
+        assert!(
+            ExternalSettings::parse_content("<html>404 Not Found</html>")
+                .await
+                .is_err()
+        );
+    }
+}
@@ -23,8 +23,8 @@ pub struct IniExternalSettings {
     pub singbox_rule_base: String,
 
     // Rule generation options
-    pub enable_rule_generator: bool,
-    pub overwrite_original_rules: bool,
+    pub enable_rule_generator: Option<bool>,
+    pub overwrite_original_rules: Option<bool>,
 
     // Emoji options
     pub add_emoji: Option<bool>,
@@ -46,6 +46,10 @@ pub struct IniExternalSettings {
     // Template arguments
     pub tpl_args: Option<HashMap<String, String>>,
 
+    // QuantumultX sections
+    pub quanx_rewrite_remote: Vec<String>,
+    pub quanx_task_local: Vec<String>,
+
     // processed fields
     #[serde(skip)]
     pub parsed_custom_proxy_groups: ProxyGroupConfigs,
@@ -65,8 +69,11 @@ impl IniExternalSettings {
     /// Load settings from INI format
     pub fn load_from_ini(&mut self, content: &str) -> Result<(), Box<dyn std::error::Error>> {
         let mut current_section = String::new();
+        let mut has_custom_section = false;
 
-        for line in content.lines() {
+        // Files saved on Windows may start with a BOM and end lines with CRLF
+        let content = content.strip_prefix('\u{FEFF}').unwrap_or(content);
+        for line in content.split(['\r', '\n']) {
             let trimmed = line.trim();
 
             // Skip empty lines and comments
@@ -77,6 +84,7 @@ impl IniExternalSettings {
             // Check for section header
             if trimmed.starts_with('[') && trimmed.ends_with(']') {
                 current_section = trimmed[1..trimmed.len() - 1].to_string();
+                has_custom_section |= current_section == "custom";
                 continue;
             }
 
@@ -88,10 +96,15 @@ impl IniExternalSettings {
                 match current_section.as_str() {
                     "custom" => self.process_custom_section(key, value),
                     "template" => self.process_template_section(key, value),
+                    "quantumultx" => self.process_quanx_section(key, value),
                     _ => {} // Ignore unknown sections
                 }
             }
         }
+
+        if !has_custom_section {
+            return Err("no [custom] section found".into());
+        }
         Ok(())
     }
 
@@ -107,17 +120,14 @@ impl IniExternalSettings {
             "sssub_rule_base" => self.sssub_rule_base = value.to_string(),
             "singbox_rule_base" => self.singbox_rule_base = value.to_string(),
             "enable_rule_generator" => {
-                self.enable_rule_generator = parse_bool_with_true_default(value)
+                self.enable_rule_generator = Some(parse_bool_with_true_default(value))
             }
-            "overwrite_original_rules" => self.overwrite_original_rules = parse_bool(value),
+            "overwrite_original_rules" => self.overwrite_original_rules = Some(parse_bool(value)),
             "add_emoji" => self.add_emoji = Some(parse_bool(value)),
             "remove_old_emoji" => self.remove_old_emoji = Some(parse_bool(value)),
-            "include_remarks" => {
-                self.include_remarks = value.split(',').map(|s| s.trim().to_string()).collect();
-            }
-            "exclude_remarks" => {
-                self.exclude_remarks = value.split(',').map(|s| s.trim().to_string()).collect();
-            }
+            // Each line holds one pattern, regexes may contain commas
+            "include_remarks" => self.include_remarks.push(value.to_string()),
+            "exclude_remarks" => self.exclude_remarks.push(value.to_string()),
             "ruleset" | "surge_ruleset" => {
                 self.rulesets.push(value.to_string());
             }
@@ -146,6 +156,14 @@ impl IniExternalSettings {
         }
     }
 
+    fn process_quanx_section(&mut self, key: &str, value: &str) {
+        match key {
+            "rewrite_remote" => self.quanx_rewrite_remote.push(value.to_string()),
+            "task_local" => self.quanx_task_local.push(value.to_string()),
+            _ => {}
+        }
+    }
+
     pub async fn process_imports(&mut self) -> Result<(), Box<dyn std::error::Error>> {
         let global = Settings::current();
         let proxy_config = parse_proxy(&global.proxy_config);
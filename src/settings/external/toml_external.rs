@@ -81,11 +81,20 @@ pub struct CustomSettings {
     pub custom_proxy_groups: Vec<ProxyGroupConfigInToml>,
 }
 
+/// Sections merged into QuantumultX configs
+#[derive(Debug, Clone, Deserialize, Default)]
+#[serde(default)]
+pub struct QuantumultXSettings {
+    pub rewrite_remote: Vec<String>,
+    pub task_local: Vec<String>,
+}
+
 /// Main TOML external settings structure
 #[derive(Debug, Clone, Deserialize, Default)]
 #[serde(default)]
 pub struct TomlExternalSettings {
     pub custom: CustomSettings,
+    pub quantumultx: QuantumultXSettings,
     #[serde(deserialize_with = "deserialize_template_args_as_hash_map")]
     pub tpl_args: Option<HashMap<String, String>>,
 
@@ -1,8 +1,10 @@
+use crate::generator::ruleconvert::Behavior;
 use crate::models::cron::{CronTaskConfig, CronTaskConfigs};
 use crate::models::proxy_group_config::{ProxyGroupConfig, ProxyGroupConfigs, ProxyGroupType};
 use crate::models::regex_match_config::{RegexMatchConfig, RegexMatchConfigs};
-use crate::models::ruleset::{RulesetConfig, RulesetConfigs};
+use crate::models::ruleset::{parse_geo_rule, RulesetConfig, RulesetConfigs};
 use crate::utils::string::starts_with;
+use regex::Regex;
 
 /// Parse group times string into interval, timeout, and tolerance values
 /// Similar to the C++ parseGroupTimes function in settings.h
@@ -25,6 +27,36 @@ fn parse_group_times(src: &str, interval: &mut u32, timeout: &mut u32, tolerance
     }
 }
 
+/// Whether a group line field holds `interval,timeout,tolerance` values
+fn is_group_times(src: &str) -> bool {
+    !src.is_empty() && src.chars().all(|c| c.is_ascii_digit() || c == ',')
+}
+
+fn is_test_url(src: &str) -> bool {
+    src.starts_with("http://") || src.starts_with("https://")
+}
+
+/// Parse a Stash or Clash.Meta group option such as `icon=` or
+/// `include-all=`, returning whether the item was one
+fn parse_group_extension(conf: &mut ProxyGroupConfig, item: &str) -> bool {
+    if let Some(icon) = item.strip_prefix("icon=") {
+        conf.icon = icon.to_string();
+    } else if let Some(hidden) = item.strip_prefix("hidden=") {
+        conf.hidden = hidden == "true";
+    } else if let Some(include_all) = item.strip_prefix("include-all=") {
+        conf.include_all = include_all == "true";
+    } else if let Some(native_filter) = item.strip_prefix("native-filter=") {
+        conf.native_filter = native_filter == "true";
+    } else if let Some(exclude_filter) = item.strip_prefix("exclude-filter=") {
+        conf.exclude_filter = exclude_filter.to_string();
+    } else if let Some(exclude_type) = item.strip_prefix("exclude-type=") {
+        conf.exclude_type = exclude_type.to_string();
+    } else {
+        return false;
+    }
+    true
+}
+
 /// Trait for parsing types from INI string arrays
 pub trait FromIni<T> {
     /// Convert from INI string array to the target type
@@ -46,8 +78,16 @@ impl FromIni<ProxyGroupConfigs> for ProxyGroupConfigs {
             let mut rules_upper_bound;
             let mut conf = ProxyGroupConfig::default();
 
-            let v_array: Vec<&str> = x.split('`').collect();
-            if v_array.len() < 3 {
+            // Stash and Clash.Meta extensions may appear anywhere after the type
+            let v_array: Vec<&str> = x
+                .split('`')
+                .enumerate()
+                .filter(|(i, item)| *i < 2 || !parse_group_extension(&mut conf, item))
+                .map(|(_, item)| item)
+                .collect();
+            // Groups taking every proxy need no rules
+            let min_bound = if conf.include_all { 2 } else { 3 };
+            if v_array.len() < min_bound {
                 continue;
             }
 
@@ -66,28 +106,27 @@ impl FromIni<ProxyGroupConfigs> for ProxyGroupConfigs {
                 _ => ProxyGroupType::Select,
             };
 
+            // The trailing test URL and `interval,timeout,tolerance` may be
+            // left out, the configured group defaults fill them in later
             if conf.group_type == ProxyGroupType::URLTest
                 || conf.group_type == ProxyGroupType::LoadBalance
                 || conf.group_type == ProxyGroupType::Fallback
             {
-                if rules_upper_bound < 5 {
-                    continue;
+                let last = v_array[rules_upper_bound - 1];
+                if rules_upper_bound > min_bound && is_group_times(last) {
+                    rules_upper_bound -= 1;
+                    parse_group_times(
+                        last,
+                        &mut conf.interval,
+                        &mut conf.timeout,
+                        &mut conf.tolerance,
+                    );
+                }
+                let url = v_array[rules_upper_bound - 1];
+                if rules_upper_bound > min_bound && (url.is_empty() || is_test_url(url)) {
+                    rules_upper_bound -= 1;
+                    conf.url = url.to_string();
                 }
-                rules_upper_bound -= 2;
-                conf.url = v_array[rules_upper_bound].to_string();
-
-                let mut interval = 0;
-                let mut timeout = 5;
-                let mut tolerance = 0;
-                parse_group_times(
-                    v_array[rules_upper_bound + 1],
-                    &mut interval,
-                    &mut timeout,
-                    &mut tolerance,
-                );
-                conf.interval = interval;
-                conf.timeout = timeout;
-                conf.tolerance = tolerance;
             }
 
             for i in 2..rules_upper_bound {
@@ -96,6 +135,13 @@ impl FromIni<ProxyGroupConfigs> for ProxyGroupConfigs {
                     for provider in provider_list {
                         conf.using_provider.push(provider.to_string());
                     }
+                } else if let Some(name) = v_array[i].strip_prefix("[]") {
+                    // Literal members are used as they are, only without the
+                    // blanks around the name, and an empty one means nothing
+                    let name = name.trim();
+                    if !name.is_empty() {
+                        conf.proxies.push(format!("[]{}", name));
+                    }
                 } else {
                     conf.proxies.push(v_array[i].to_string());
                 }
@@ -131,16 +177,35 @@ impl FromIni<Vec<RulesetConfig>> for RulesetConfigs {
                 continue;
             }
 
+            // GEOSITE and GEOIP options close the line,
+            // `group,url[,interval][,behavior][,geosite:name][,geoip:code]`
+            let mut line = x.as_str();
+            while let Some(gpos) = line.rfind(',').filter(|gpos| *gpos > pos) {
+                let Some(rule) = parse_geo_rule(&line[gpos + 1..]) else {
+                    break;
+                };
+                conf.geo.insert(0, rule);
+                line = &line[..gpos];
+            }
+
+            // A behavior may follow the interval
+            if let Some(bpos) = line.rfind(',').filter(|bpos| *bpos > pos) {
+                if let Ok(behavior) = line[bpos + 1..].parse::<Behavior>() {
+                    conf.behavior = Some(behavior);
+                    line = &line[..bpos];
+                }
+            }
+
             // Check if there's an interval specified
-            let epos = x.rfind(',');
+            let epos = line.rfind(',');
             if pos != epos.unwrap_or(pos) {
                 let epos = epos.unwrap();
-                if let Ok(interval) = x[epos + 1..].parse::<u32>() {
+                if let Ok(interval) = line[epos + 1..].parse::<u32>() {
                     conf.interval = interval;
                 }
-                conf.url = x[pos + 1..epos].to_string();
+                conf.url = line[pos + 1..epos].to_string();
             } else {
-                conf.url = x[pos + 1..].to_string();
+                conf.url = line[pos + 1..].to_string();
             }
 
             confs.push(conf);
@@ -150,6 +215,49 @@ impl FromIni<Vec<RulesetConfig>> for RulesetConfigs {
     }
 }
 
+/// Parse a sed-style `s/pattern/replacement/flags` substitution into a match
+/// and replace pair. `\/` escapes a slash, `\N` references a capture group
+/// and the `i` flag makes the pattern case-insensitive.
+fn parse_sed_rule(rule: &str) -> Option<(String, String)> {
+    let body = rule.strip_prefix("s/")?;
+
+    let mut parts = vec![String::new()];
+    let mut chars = body.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' if chars.peek() == Some(&'/') => {
+                parts.last_mut()?.push('/');
+                chars.next();
+            }
+            '\\' => {
+                parts.last_mut()?.push(c);
+                if let Some(next) = chars.next() {
+                    parts.last_mut()?.push(next);
+                }
+            }
+            '/' => parts.push(String::new()),
+            _ => parts.last_mut()?.push(c),
+        }
+    }
+
+    if parts.len() != 3 || parts[0].is_empty() || !parts[2].chars().all(|f| f == 'i' || f == 'g') {
+        return None;
+    }
+
+    let pattern = if parts[2].contains('i') {
+        format!("(?i){}", parts[0])
+    } else {
+        parts[0].clone()
+    };
+    let replace = Regex::new(r"\\(\d+)")
+        .ok()?
+        .replace_all(&parts[1], |caps: &regex::Captures| {
+            format!("${{{}}}", &caps[1])
+        })
+        .to_string();
+    Some((pattern, replace))
+}
+
 /// Implementation for parsing RegexMatchConfig from INI string lines with
 /// delimiter
 impl FromIniWithDelimiter<RegexMatchConfigs> for RegexMatchConfigs {
@@ -166,6 +274,15 @@ impl FromIniWithDelimiter<RegexMatchConfigs> for RegexMatchConfigs {
                 continue;
             }
 
+            // Handle sed-style substitutions
+            if let Some((pattern, replace)) = parse_sed_rule(x) {
+                conf._match = pattern;
+                conf.replace = replace;
+                conf.compile();
+                confs.push(conf);
+                continue;
+            }
+
             // Handle match/replace case
             let pos = x.rfind(delimiter);
             conf._match = x[..pos.unwrap_or(x.len())].to_string();
@@ -214,3 +331,120 @@ impl FromIni<CronTaskConfigs> for CronTaskConfigs {
         confs
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ruleset_geo_options_close_the_line() {
+        let lines: Vec<String> = [
+            "DIRECT,https://example.com/China.list,86400,domain,geosite:cn,geoip:cn",
+            "DIRECT,https://example.com/ChinaIp.list,geoip:CN",
+            "DIRECT,https://example.com/Lan.list,geo:cn",
+        ]
+        .iter()
+        .map(|line| line.to_string())
+        .collect();
+        let confs = RulesetConfigs::from_ini(&lines);
+        assert_eq!(confs[0].url, "https://example.com/China.list");
+        assert_eq!(confs[0].interval, 86400);
+        assert_eq!(confs[0].behavior, Some(Behavior::Domain));
+        assert_eq!(confs[0].geo, ["GEOSITE,cn", "GEOIP,CN"]);
+        assert_eq!(confs[1].url, "https://example.com/ChinaIp.list");
+        assert_eq!(confs[1].geo, ["GEOIP,CN"]);
+        // Only `geosite:` and `geoip:` name the rules
+        assert!(confs[2].geo.is_empty());
+    }
+
+    #[test]
+    fn test_ruleset_behavior_follows_interval() {
+        let lines: Vec<String> = [
+            "DIRECT,https://example.com/Download.list,86400,classical",
+            "DIRECT,https://example.com/China.list,domain",
+            "DIRECT,https://example.com/Lan.list,3600",
+            "DIRECT,[]GEOIP,CN",
+        ]
+        .iter()
+        .map(|line| line.to_string())
+        .collect();
+        let confs = RulesetConfigs::from_ini(&lines);
+        let fields: Vec<(&str, u32, Option<Behavior>)> = confs
+            .iter()
+            .map(|conf| (conf.url.as_str(), conf.interval, conf.behavior))
+            .collect();
+        assert_eq!(
+            fields,
+            [
+                (
+                    "https://example.com/Download.list",
+                    86400,
+                    Some(Behavior::Classical)
+                ),
+                ("https://example.com/China.list", 0, Some(Behavior::Domain)),
+                ("https://example.com/Lan.list", 3600, None),
+                ("[]GEOIP,CN", 0, None),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_group_test_fields_are_optional() {
+        let lines = vec![
+            "Auto`url-test`(HK|SG)`http://cp.cloudflare.com`60,3,50".to_string(),
+            "Fallback`fallback`.*`[]DIRECT".to_string(),
+            "Balance`load-balance`.*`,,80".to_string(),
+        ];
+        let mut groups = ProxyGroupConfigs::from_ini(&lines);
+        assert_eq!(groups.len(), 3);
+
+        let auto = &groups[0];
+        assert_eq!(auto.proxies, vec!["(HK|SG)"]);
+        assert_eq!(auto.url, "http://cp.cloudflare.com");
+        assert_eq!((auto.interval, auto.timeout, auto.tolerance), (60, 3, 50));
+
+        assert_eq!(groups[1].proxies, vec![".*", "[]DIRECT"]);
+        assert!(groups[1].url.is_empty());
+        assert_eq!(groups[2].proxies, vec![".*"]);
+        assert_eq!(groups[2].tolerance, 80);
+
+        for group in groups.iter_mut() {
+            group.apply_test_defaults("http://www.gstatic.com/generate_204", 300, 5, 0, true);
+        }
+        assert_eq!(groups[0].url, "http://cp.cloudflare.com");
+        assert_eq!(groups[0].interval, 60);
+        assert_eq!(groups[1].url, "http://www.gstatic.com/generate_204");
+        assert_eq!((groups[1].interval, groups[1].timeout), (300, 5));
+        assert_eq!((groups[2].interval, groups[2].tolerance), (300, 80));
+        assert_eq!(groups[2].lazy, Some(true));
+    }
+
+    #[test]
+    fn test_literal_members_are_trimmed() {
+        let lines = vec!["Proxy`select`[] DIRECT `.*`[]`[]Auto".to_string()];
+        let groups = ProxyGroupConfigs::from_ini(&lines);
+        assert_eq!(groups[0].proxies, vec!["[]DIRECT", ".*", "[]Auto"]);
+    }
+
+    #[test]
+    fn test_group_extensions_are_parsed() {
+        let lines = vec![
+            "Proxy`select`icon=https://example.com/proxy.png`.*`hidden=true".to_string(),
+            "All`select`include-all=true".to_string(),
+            "Auto`url-test`include-all=true``300,,".to_string(),
+        ];
+        let groups = ProxyGroupConfigs::from_ini(&lines);
+        assert_eq!(groups.len(), 3);
+
+        assert_eq!(groups[0].icon, "https://example.com/proxy.png");
+        assert!(groups[0].hidden);
+        assert_eq!(groups[0].proxies, vec![".*"]);
+
+        assert!(groups[1].include_all);
+        assert!(groups[1].proxies.is_empty());
+
+        assert!(groups[2].include_all);
+        assert!(groups[2].proxies.is_empty());
+        assert_eq!(groups[2].interval, 300);
+    }
+}
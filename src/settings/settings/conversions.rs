@@ -3,14 +3,60 @@
 use std::collections::HashMap;
 
 use super::ini_settings::IniSettings;
-use super::settings_struct::{default_listen_address, Settings};
+use super::settings_struct::{
+    default_listen_address, normalize_api_base_path, parse_unix_socket_mode, Settings,
+};
 use super::toml_settings::TomlSettings;
 use super::yaml_settings::YamlSettings;
 
 use crate::constants::log_level::{
-    LOG_LEVEL_DEBUG, LOG_LEVEL_ERROR, LOG_LEVEL_INFO, LOG_LEVEL_VERBOSE, LOG_LEVEL_WARNING,
+    LOG_LEVEL_DEBUG, LOG_LEVEL_ERROR, LOG_LEVEL_FATAL, LOG_LEVEL_INFO, LOG_LEVEL_VERBOSE,
+    LOG_LEVEL_WARNING,
 };
 
+/// Log level from its name in the preference files, numbers are taken as is
+fn parse_log_level(level: &str) -> u32 {
+    match level.trim().to_lowercase().as_str() {
+        "verbose" => LOG_LEVEL_VERBOSE,
+        "debug" => LOG_LEVEL_DEBUG,
+        "info" => LOG_LEVEL_INFO,
+        "warn" | "warning" => LOG_LEVEL_WARNING,
+        "error" => LOG_LEVEL_ERROR,
+        "fatal" => LOG_LEVEL_FATAL,
+        other => other.parse().unwrap_or(LOG_LEVEL_INFO),
+    }
+}
+
+/// Aliases by URI, entries missing either side are left out as in `[aliases]`
+fn aliases_map(aliases: impl Iterator<Item = (String, String)>) -> HashMap<String, String> {
+    aliases
+        .filter(|(uri, target)| !uri.is_empty() && !target.is_empty())
+        .collect()
+}
+
+/// Switches applied the same way whichever format the settings come from,
+/// once every field is copied
+///
+/// As in the original readConf, caching only applies with `enable_cache`,
+/// rulesets only with rule generation enabled, and tasks enable the cron.
+fn apply_switches(settings: &mut Settings, enable_cache: bool) {
+    if !enable_cache {
+        settings.cache_subscription = 0;
+        settings.cache_config = 0;
+        settings.cache_ruleset = 0;
+        settings.serve_cache_on_fetch_fail = false;
+    }
+    if !settings.enable_rule_gen {
+        settings.custom_rulesets.clear();
+        settings.overwrite_original_rules = false;
+        settings.update_ruleset_on_request = false;
+    }
+    settings.enable_cron = !settings.cron_tasks.is_empty();
+    if settings.listen_address.trim().is_empty() {
+        settings.listen_address = default_listen_address();
+    }
+}
+
 // Conversion from YamlSettings to Settings
 impl From<YamlSettings> for Settings {
     fn from(yaml_settings: YamlSettings) -> Self {
@@ -22,8 +68,12 @@ impl From<YamlSettings> for Settings {
         settings.include_remarks = yaml_settings.common.include_remarks;
         settings.enable_filter = yaml_settings.common.enable_filter;
         settings.filter_script = yaml_settings.common.filter_script;
+        settings.filter_script_strict = yaml_settings.common.filter_script_strict;
+        settings.geoip_db = yaml_settings.common.geoip_db;
         settings.api_mode = yaml_settings.common.api_mode;
         settings.api_access_token = yaml_settings.common.api_access_token;
+        settings.gist_token = yaml_settings.common.gist_token;
+        settings.gist_id = yaml_settings.common.gist_id;
         settings.base_path = yaml_settings.common.base_path;
         settings.clash_base = yaml_settings.common.clash_rule_base;
         settings.surge_base = yaml_settings.common.surge_rule_base;
@@ -34,22 +84,19 @@ impl From<YamlSettings> for Settings {
         settings.loon_base = yaml_settings.common.loon_rule_base;
         settings.ssub_base = yaml_settings.common.sssub_rule_base;
         settings.singbox_base = yaml_settings.common.singbox_rule_base;
+        settings.clash_dns = yaml_settings.common.clash_dns;
+        settings.clash_tun = yaml_settings.common.clash_tun;
+        settings.clash_sniffer = yaml_settings.common.clash_sniffer;
+        settings.clash_meta_overwrite = yaml_settings.common.clash_meta_overwrite;
         settings.proxy_config = yaml_settings.common.proxy_config;
         settings.proxy_ruleset = yaml_settings.common.proxy_ruleset;
         settings.proxy_subscription = yaml_settings.common.proxy_subscription;
         settings.append_type = yaml_settings.common.append_proxy_type;
         settings.reload_conf_on_request = yaml_settings.common.reload_conf_on_request;
-
-        // Process default_url and insert_url
-        if !yaml_settings.common.default_url.is_empty() {
-            settings.default_urls = yaml_settings.common.default_url;
-        }
-
-        if !yaml_settings.common.insert_url.is_empty() {
-            settings.insert_urls = yaml_settings.common.insert_url;
-            settings.enable_insert = yaml_settings.common.enable_insert;
-            settings.prepend_insert = yaml_settings.common.prepend_insert_url;
-        }
+        settings.default_urls = yaml_settings.common.default_url;
+        settings.insert_urls = yaml_settings.common.insert_url;
+        settings.enable_insert = yaml_settings.common.enable_insert;
+        settings.prepend_insert = yaml_settings.common.prepend_insert_url;
 
         // Node preferences
         settings.udp_flag = yaml_settings.node_pref.udp_flag;
@@ -58,11 +105,33 @@ impl From<YamlSettings> for Settings {
         settings.tls13_flag = yaml_settings.node_pref.tls13_flag;
         settings.enable_sort = yaml_settings.node_pref.sort_flag;
         settings.sort_script = yaml_settings.node_pref.sort_script;
+        settings.sort_priority = yaml_settings.node_pref.sort_priority;
+        settings.duplicate_remark_format = yaml_settings.node_pref.duplicate_remark_format;
+        settings.index_remark = yaml_settings.node_pref.index_remark;
+        settings.append_type_format = yaml_settings.node_pref.append_type_format;
         settings.filter_deprecated = yaml_settings.node_pref.filter_deprecated_nodes;
         settings.append_userinfo = yaml_settings.node_pref.append_sub_userinfo;
+        settings.append_info_nodes = yaml_settings.node_pref.append_info_nodes;
+        if let Some(url) = yaml_settings.node_pref.group_test_url.clone() {
+            settings.group_test_url = url;
+        }
+        if let Some(interval) = yaml_settings.node_pref.group_interval {
+            settings.group_interval = interval;
+        }
+        if let Some(timeout) = yaml_settings.node_pref.group_timeout {
+            settings.group_timeout = timeout;
+        }
+        if let Some(tolerance) = yaml_settings.node_pref.group_tolerance {
+            settings.group_tolerance = tolerance;
+        }
+        if let Some(lazy) = yaml_settings.node_pref.group_lazy {
+            settings.group_lazy = lazy;
+        }
         settings.clash_use_new_field = yaml_settings.node_pref.clash_use_new_field_name;
         settings.clash_proxies_style = yaml_settings.node_pref.clash_proxies_style;
         settings.clash_proxy_groups_style = yaml_settings.node_pref.clash_proxy_groups_style;
+        settings.overwrite_original_proxies = yaml_settings.node_pref.overwrite_original_proxies;
+        settings.overwrite_original_groups = yaml_settings.node_pref.overwrite_original_groups;
         settings.singbox_add_clash_modes = yaml_settings.node_pref.singbox_add_clash_modes;
         // Managed config
         settings.write_managed_config = yaml_settings.managed_config.write_managed_config;
@@ -79,19 +148,40 @@ impl From<YamlSettings> for Settings {
         settings.add_emoji = yaml_settings.emojis.add_emoji;
         settings.remove_emoji = yaml_settings.emojis.remove_old_emoji;
 
+        settings.aliases = aliases_map(
+            yaml_settings
+                .aliases
+                .into_iter()
+                .map(|alias| (alias.uri, alias.target)),
+        );
+        // User agents
+        settings.user_agent_targets.extend(
+            yaml_settings
+                .user_agents
+                .into_iter()
+                .map(|entry| (entry.ua, entry.args)),
+        );
+        settings.target_defaults = yaml_settings.target_defaults;
+
         // Server
         settings.listen_address = yaml_settings.server.listen;
         settings.listen_port = yaml_settings.server.port;
+        settings.serve_file = !yaml_settings.server.serve_file_root.is_empty();
+        settings.serve_file_root = yaml_settings.server.serve_file_root;
+        settings.api_base_path = normalize_api_base_path(&yaml_settings.server.api_base_path);
+        settings.unix_socket_mode = parse_unix_socket_mode(&yaml_settings.server.unix_socket_mode);
+        settings.tls_cert = yaml_settings.server.tls_cert;
+        settings.tls_key = yaml_settings.server.tls_key;
+        settings.shutdown_timeout = yaml_settings.server.shutdown_timeout;
+        settings.upload_kind = yaml_settings.server.upload.kind;
+        settings.upload_url = yaml_settings.server.upload.url;
+        settings.upload_username = yaml_settings.server.upload.username;
+        settings.upload_password = yaml_settings.server.upload.password;
+        settings.trusted_proxies = yaml_settings.server.trusted_proxies;
+        settings.watch_config = yaml_settings.server.watch_config;
 
         // Advanced
-        settings.log_level = match yaml_settings.advanced.log_level.as_str() {
-            "debug" => LOG_LEVEL_DEBUG,
-            "info" => LOG_LEVEL_INFO,
-            "warning" => LOG_LEVEL_WARNING,
-            "error" => LOG_LEVEL_ERROR,
-            "verbose" => LOG_LEVEL_VERBOSE,
-            _ => LOG_LEVEL_INFO,
-        };
+        settings.log_level = parse_log_level(&yaml_settings.advanced.log_level);
         if yaml_settings.advanced.print_debug_info {
             settings.log_level = LOG_LEVEL_VERBOSE;
         }
@@ -100,17 +190,23 @@ impl From<YamlSettings> for Settings {
         settings.max_allowed_rulesets = yaml_settings.advanced.max_allowed_rulesets;
         settings.max_allowed_rules = yaml_settings.advanced.max_allowed_rules;
         settings.max_allowed_download_size = yaml_settings.advanced.max_allowed_download_size;
+        settings.max_redirects = yaml_settings.advanced.max_redirects;
         settings.cache_subscription = yaml_settings.advanced.cache_subscription;
         settings.cache_config = yaml_settings.advanced.cache_config;
         settings.cache_ruleset = yaml_settings.advanced.cache_ruleset;
+        settings.convert_cache_entries = yaml_settings.advanced.convert_cache_entries;
+        settings.convert_cache_size = yaml_settings.advanced.convert_cache_size;
+        settings.bypass_convert_cache = yaml_settings.advanced.bypass_convert_cache;
+        settings.serve_cache_on_fetch_fail = yaml_settings.advanced.serve_cache_on_fetch_fail;
         settings.script_clean_context = yaml_settings.advanced.script_clean_context;
         settings.async_fetch_ruleset = yaml_settings.advanced.async_fetch_ruleset;
         settings.skip_failed_links = yaml_settings.advanced.skip_failed_links;
+        settings.local_sub_dirs = yaml_settings.advanced.local_sub_dirs.clone();
+        settings.clash_provider_files = yaml_settings.advanced.clash_provider_files;
 
         // Template
         settings.template_path = yaml_settings.template.template_path;
         settings.template_vars = yaml_settings.template.globals;
-        settings.template_vars = HashMap::new();
 
         // Ruleset settings
         settings.enable_rule_gen = yaml_settings.rulesets.enabled;
@@ -122,10 +218,15 @@ impl From<YamlSettings> for Settings {
         settings.stream_rules = yaml_settings.parsed_stream_rule;
         settings.time_rules = yaml_settings.parsed_time_rule;
         settings.emojis = yaml_settings.parsed_emoji_rules;
+        settings.autogroup = yaml_settings.autogroup.enable;
+        settings.autogroup_name = yaml_settings.autogroup.name;
+        settings.autogroup_min_nodes = yaml_settings.autogroup.min_nodes;
+        settings.autogroup_countries = yaml_settings.parsed_autogroup_countries;
         settings.custom_proxy_groups = yaml_settings.parsed_proxy_group;
         settings.custom_rulesets = yaml_settings.parsed_ruleset;
         settings.cron_tasks = yaml_settings.parsed_tasks;
 
+        apply_switches(&mut settings, yaml_settings.advanced.enable_cache);
         settings
     }
 }
@@ -142,6 +243,8 @@ impl From<TomlSettings> for Settings {
         settings.include_remarks = common.include_remarks;
         settings.api_mode = common.api_mode;
         settings.api_access_token = common.api_access_token;
+        settings.gist_token = common.gist_token;
+        settings.gist_id = common.gist_id;
         settings.base_path = common.base_path;
         settings.clash_base = common.clash_rule_base;
         settings.surge_base = common.surge_rule_base;
@@ -152,6 +255,10 @@ impl From<TomlSettings> for Settings {
         settings.loon_base = common.loon_rule_base;
         settings.ssub_base = common.sssub_rule_base;
         settings.singbox_base = common.singbox_rule_base;
+        settings.clash_dns = common.clash_dns;
+        settings.clash_tun = common.clash_tun;
+        settings.clash_sniffer = common.clash_sniffer;
+        settings.clash_meta_overwrite = common.clash_meta_overwrite;
         settings.proxy_config = common.proxy_config;
         settings.proxy_ruleset = common.proxy_ruleset;
         settings.proxy_subscription = common.proxy_subscription;
@@ -160,17 +267,12 @@ impl From<TomlSettings> for Settings {
 
         settings.enable_filter = common.enable_filter;
         settings.filter_script = common.filter_script;
-
-        // Process default_url and insert_url
-        if !common.default_urls.is_empty() {
-            settings.default_urls = common.default_urls;
-        }
-
-        if !common.insert_urls.is_empty() {
-            settings.insert_urls = common.insert_urls;
-            settings.enable_insert = common.enable_insert;
-            settings.prepend_insert = common.prepend_insert_url;
-        }
+        settings.filter_script_strict = common.filter_script_strict;
+        settings.geoip_db = common.geoip_db;
+        settings.default_urls = common.default_urls;
+        settings.insert_urls = common.insert_urls;
+        settings.enable_insert = common.enable_insert;
+        settings.prepend_insert = common.prepend_insert_url;
 
         // Node preferences
         let node_pref = &toml_settings.node_pref;
@@ -180,11 +282,33 @@ impl From<TomlSettings> for Settings {
         settings.tls13_flag = node_pref.tls13_flag;
         settings.enable_sort = node_pref.sort_flag;
         settings.sort_script = node_pref.sort_script.clone();
+        settings.sort_priority = node_pref.sort_priority.clone();
+        settings.duplicate_remark_format = node_pref.duplicate_remark_format.clone();
+        settings.index_remark = node_pref.index_remark.clone();
+        settings.append_type_format = node_pref.append_type_format.clone();
         settings.filter_deprecated = node_pref.filter_deprecated_nodes;
         settings.append_userinfo = node_pref.append_sub_userinfo;
+        settings.append_info_nodes = node_pref.append_info_nodes;
+        if let Some(url) = node_pref.group_test_url.clone() {
+            settings.group_test_url = url;
+        }
+        if let Some(interval) = node_pref.group_interval {
+            settings.group_interval = interval;
+        }
+        if let Some(timeout) = node_pref.group_timeout {
+            settings.group_timeout = timeout;
+        }
+        if let Some(tolerance) = node_pref.group_tolerance {
+            settings.group_tolerance = tolerance;
+        }
+        if let Some(lazy) = node_pref.group_lazy {
+            settings.group_lazy = lazy;
+        }
         settings.clash_use_new_field = node_pref.clash_use_new_field_name;
         settings.clash_proxies_style = node_pref.clash_proxies_style.clone();
         settings.clash_proxy_groups_style = node_pref.clash_proxy_groups_style.clone();
+        settings.overwrite_original_proxies = node_pref.overwrite_original_proxies;
+        settings.overwrite_original_groups = node_pref.overwrite_original_groups;
         settings.singbox_add_clash_modes = node_pref.singbox_add_clash_modes;
 
         // Managed config
@@ -202,20 +326,41 @@ impl From<TomlSettings> for Settings {
         settings.add_emoji = toml_settings.emojis.add_emoji;
         settings.remove_emoji = toml_settings.emojis.remove_old_emoji;
 
+        settings.aliases = aliases_map(
+            toml_settings
+                .aliases
+                .iter()
+                .map(|alias| (alias.uri.clone(), alias.target.clone())),
+        );
+        // User agents
+        settings.user_agent_targets.extend(
+            toml_settings
+                .user_agents
+                .iter()
+                .map(|entry| (entry.ua.clone(), entry.args.clone())),
+        );
+        settings.target_defaults = toml_settings.target_defaults.clone();
+
         // Server
         settings.listen_address = toml_settings.server.listen.clone();
         settings.listen_port = toml_settings.server.port;
+        settings.serve_file = !toml_settings.server.serve_file_root.is_empty();
+        settings.serve_file_root = toml_settings.server.serve_file_root.clone();
+        settings.api_base_path = normalize_api_base_path(&toml_settings.server.api_base_path);
+        settings.unix_socket_mode = parse_unix_socket_mode(&toml_settings.server.unix_socket_mode);
+        settings.tls_cert = toml_settings.server.tls_cert.clone();
+        settings.tls_key = toml_settings.server.tls_key.clone();
+        settings.shutdown_timeout = toml_settings.server.shutdown_timeout;
+        let upload = &toml_settings.server.upload;
+        settings.upload_kind = upload.kind.clone();
+        settings.upload_url = upload.url.clone();
+        settings.upload_username = upload.username.clone();
+        settings.upload_password = upload.password.clone();
+        settings.trusted_proxies = toml_settings.server.trusted_proxies.clone();
+        settings.watch_config = toml_settings.server.watch_config;
 
         // Advanced
-        let log_level = &toml_settings.advanced.log_level;
-        settings.log_level = match log_level.as_str() {
-            "debug" => LOG_LEVEL_DEBUG,
-            "info" => LOG_LEVEL_INFO,
-            "warning" => LOG_LEVEL_WARNING,
-            "error" => LOG_LEVEL_ERROR,
-            "verbose" => LOG_LEVEL_VERBOSE,
-            _ => LOG_LEVEL_INFO,
-        };
+        settings.log_level = parse_log_level(&toml_settings.advanced.log_level);
         if toml_settings.advanced.print_debug_info {
             settings.log_level = LOG_LEVEL_VERBOSE;
         }
@@ -224,38 +369,43 @@ impl From<TomlSettings> for Settings {
         settings.max_allowed_rulesets = toml_settings.advanced.max_allowed_rulesets;
         settings.max_allowed_rules = toml_settings.advanced.max_allowed_rules;
         settings.max_allowed_download_size = toml_settings.advanced.max_allowed_download_size;
+        settings.max_redirects = toml_settings.advanced.max_redirects;
         settings.cache_subscription = toml_settings.advanced.cache_subscription;
         settings.cache_config = toml_settings.advanced.cache_config;
         settings.cache_ruleset = toml_settings.advanced.cache_ruleset;
+        settings.convert_cache_entries = toml_settings.advanced.convert_cache_entries;
+        settings.convert_cache_size = toml_settings.advanced.convert_cache_size;
+        settings.bypass_convert_cache = toml_settings.advanced.bypass_convert_cache;
+        settings.serve_cache_on_fetch_fail = toml_settings.advanced.serve_cache_on_fetch_fail;
         settings.script_clean_context = toml_settings.advanced.script_clean_context;
         settings.async_fetch_ruleset = toml_settings.advanced.async_fetch_ruleset;
         settings.skip_failed_links = toml_settings.advanced.skip_failed_links;
+        settings.local_sub_dirs = toml_settings.advanced.local_sub_dirs.clone();
+        settings.clash_provider_files = toml_settings.advanced.clash_provider_files;
 
         // Template
         settings.template_path = toml_settings.template.template_path.clone();
         settings.template_vars = toml_settings.template.globals;
 
         // Ruleset settings
-        if !toml_settings.rulesets.is_empty() && toml_settings.rulesets[0].ruleset.is_some() {
-            settings.enable_rule_gen = toml_settings.ruleset.enabled;
-            settings.overwrite_original_rules = toml_settings.ruleset.overwrite_original_rules;
-            settings.update_ruleset_on_request = toml_settings.ruleset.update_ruleset_on_request;
-        }
-
-        // Ensure listen_address is not empty
-        if settings.listen_address.trim().is_empty() {
-            settings.listen_address = default_listen_address();
-        }
+        settings.enable_rule_gen = toml_settings.ruleset.enabled;
+        settings.overwrite_original_rules = toml_settings.ruleset.overwrite_original_rules;
+        settings.update_ruleset_on_request = toml_settings.ruleset.update_ruleset_on_request;
 
         // update fields processed
         settings.renames = toml_settings.parsed_rename;
         settings.stream_rules = toml_settings.parsed_stream_rule;
         settings.time_rules = toml_settings.parsed_time_rule;
         settings.emojis = toml_settings.parsed_emoji_rules;
+        settings.autogroup = toml_settings.autogroup.enable;
+        settings.autogroup_name = toml_settings.autogroup.name.clone();
+        settings.autogroup_min_nodes = toml_settings.autogroup.min_nodes;
+        settings.autogroup_countries = toml_settings.parsed_autogroup_countries;
         settings.custom_proxy_groups = toml_settings.parsed_proxy_group;
         settings.custom_rulesets = toml_settings.parsed_ruleset;
         settings.cron_tasks = toml_settings.parsed_tasks;
 
+        apply_switches(&mut settings, toml_settings.advanced.enable_cache);
         settings
     }
 }
@@ -269,6 +419,8 @@ impl From<IniSettings> for Settings {
         // Process in the same order as the C++ readConf function
         settings.api_mode = ini_settings.api_mode;
         settings.api_access_token = ini_settings.api_access_token;
+        settings.gist_token = ini_settings.gist_token;
+        settings.gist_id = ini_settings.gist_id;
         settings.default_urls = if !ini_settings.default_url.is_empty() {
             ini_settings
                 .default_url
@@ -293,6 +445,8 @@ impl From<IniSettings> for Settings {
         settings.include_remarks = ini_settings.include_remarks;
         settings.filter_script = ini_settings.filter_script.clone();
         settings.enable_filter = ini_settings.enable_filter;
+        settings.filter_script_strict = ini_settings.filter_script_strict;
+        settings.geoip_db = ini_settings.geoip_db.clone();
         settings.base_path = ini_settings.base_path.clone();
         settings.clash_base = ini_settings.clash_base.clone();
         settings.surge_base = ini_settings.surge_base.clone();
@@ -303,6 +457,10 @@ impl From<IniSettings> for Settings {
         settings.loon_base = ini_settings.loon_base.clone();
         settings.ssub_base = ini_settings.ssub_base.clone();
         settings.singbox_base = ini_settings.singbox_base.clone();
+        settings.clash_dns = ini_settings.clash_dns.clone();
+        settings.clash_tun = ini_settings.clash_tun.clone();
+        settings.clash_sniffer = ini_settings.clash_sniffer.clone();
+        settings.clash_meta_overwrite = ini_settings.clash_meta_overwrite;
         settings.default_ext_config = ini_settings.default_ext_config.clone();
         settings.append_type = ini_settings.append_type;
         settings.proxy_config = ini_settings.proxy_config.clone();
@@ -321,11 +479,33 @@ impl From<IniSettings> for Settings {
         settings.tls13_flag = ini_settings.tls13_flag;
         settings.enable_sort = ini_settings.enable_sort;
         settings.sort_script = ini_settings.sort_script.clone();
+        settings.sort_priority = ini_settings.sort_priority.clone();
+        settings.duplicate_remark_format = ini_settings.duplicate_remark_format.clone();
+        settings.index_remark = ini_settings.index_remark.clone();
+        settings.append_type_format = ini_settings.append_type_format.clone();
         settings.filter_deprecated = ini_settings.filter_deprecated;
         settings.append_userinfo = ini_settings.append_sub_userinfo;
+        settings.append_info_nodes = ini_settings.append_info_nodes;
+        if let Some(url) = ini_settings.group_test_url.clone() {
+            settings.group_test_url = url;
+        }
+        if let Some(interval) = ini_settings.group_interval {
+            settings.group_interval = interval;
+        }
+        if let Some(timeout) = ini_settings.group_timeout {
+            settings.group_timeout = timeout;
+        }
+        if let Some(tolerance) = ini_settings.group_tolerance {
+            settings.group_tolerance = tolerance;
+        }
+        if let Some(lazy) = ini_settings.group_lazy {
+            settings.group_lazy = lazy;
+        }
         settings.clash_use_new_field = ini_settings.clash_use_new_field;
         settings.clash_proxies_style = ini_settings.clash_proxies_style.clone();
         settings.clash_proxy_groups_style = ini_settings.clash_proxy_groups_style.clone();
+        settings.overwrite_original_proxies = ini_settings.overwrite_original_proxies;
+        settings.overwrite_original_groups = ini_settings.overwrite_original_groups;
         settings.singbox_add_clash_modes = ini_settings.singbox_add_clash_modes;
         // Set rename_node from parsed_rename
         settings.renames = ini_settings.parsed_rename;
@@ -344,15 +524,9 @@ impl From<IniSettings> for Settings {
 
         // RULESET SECTION
         settings.enable_rule_gen = ini_settings.enable_rule_gen;
-        if ini_settings.enable_rule_gen {
-            settings.overwrite_original_rules = ini_settings.overwrite_original_rules;
-            settings.update_ruleset_on_request = ini_settings.update_ruleset_on_request;
-            // Convert string rulesets to RulesetConfig
-            settings.custom_rulesets = ini_settings.parsed_ruleset;
-        } else {
-            settings.overwrite_original_rules = false;
-            settings.update_ruleset_on_request = false;
-        }
+        settings.overwrite_original_rules = ini_settings.overwrite_original_rules;
+        settings.update_ruleset_on_request = ini_settings.update_ruleset_on_request;
+        settings.custom_rulesets = ini_settings.parsed_ruleset;
         // PROXY GROUP SECTION
         settings.custom_proxy_groups = ini_settings.parsed_proxy_group;
 
@@ -362,9 +536,10 @@ impl From<IniSettings> for Settings {
 
         // ALIASES SECTION
         settings.aliases = ini_settings.aliases;
+        settings.user_agent_targets.extend(ini_settings.user_agents);
+        settings.target_defaults = ini_settings.target_defaults;
 
         // TASKS SECTION
-        settings.enable_cron = ini_settings.enable_cron;
         settings.cron_tasks = ini_settings.parsed_tasks;
 
         // SERVER SECTION
@@ -372,9 +547,22 @@ impl From<IniSettings> for Settings {
         settings.listen_port = ini_settings.listen_port;
         settings.serve_file = ini_settings.serve_file;
         settings.serve_file_root = ini_settings.serve_file_root.clone();
+        settings.api_base_path = normalize_api_base_path(&ini_settings.api_base_path);
+        settings.unix_socket_mode = parse_unix_socket_mode(&ini_settings.unix_socket_mode);
+        settings.tls_cert = ini_settings.tls_cert.clone();
+        settings.tls_key = ini_settings.tls_key.clone();
+        if let Some(shutdown_timeout) = ini_settings.shutdown_timeout {
+            settings.shutdown_timeout = shutdown_timeout;
+        }
+        settings.upload_kind = ini_settings.upload_kind.clone();
+        settings.upload_url = ini_settings.upload_url.clone();
+        settings.upload_username = ini_settings.upload_username.clone();
+        settings.upload_password = ini_settings.upload_password.clone();
+        settings.trusted_proxies = ini_settings.trusted_proxies.clone();
+        settings.watch_config = ini_settings.watch_config;
 
         // ADVANCED SECTION
-        settings.log_level = ini_settings.log_level;
+        settings.log_level = parse_log_level(&ini_settings.log_level);
         if ini_settings.print_dbg_info {
             settings.log_level = LOG_LEVEL_VERBOSE;
         }
@@ -383,31 +571,36 @@ impl From<IniSettings> for Settings {
         settings.max_allowed_rulesets = ini_settings.max_allowed_rulesets;
         settings.max_allowed_rules = ini_settings.max_allowed_rules;
         settings.max_allowed_download_size = ini_settings.max_allowed_download_size;
-        if ini_settings.enable_cache {
-            settings.cache_subscription = ini_settings.cache_subscription;
-            settings.cache_config = ini_settings.cache_config;
-            settings.cache_ruleset = ini_settings.cache_ruleset;
-            settings.serve_cache_on_fetch_fail = ini_settings.serve_cache_on_fetch_fail;
-        } else {
-            settings.cache_subscription = 0;
-            settings.cache_config = 0;
-            settings.cache_ruleset = 0;
-            settings.serve_cache_on_fetch_fail = false;
+        settings.max_redirects = ini_settings.max_redirects;
+        settings.cache_subscription = ini_settings.cache_subscription;
+        settings.cache_config = ini_settings.cache_config;
+        settings.cache_ruleset = ini_settings.cache_ruleset;
+        settings.serve_cache_on_fetch_fail = ini_settings.serve_cache_on_fetch_fail;
+        if let Some(entries) = ini_settings.convert_cache_entries {
+            settings.convert_cache_entries = entries;
         }
+        if let Some(size) = ini_settings.convert_cache_size {
+            settings.convert_cache_size = size;
+        }
+        settings.bypass_convert_cache = ini_settings.bypass_convert_cache;
         settings.script_clean_context = ini_settings.script_clean_context;
         settings.async_fetch_ruleset = ini_settings.async_fetch_ruleset;
         settings.skip_failed_links = ini_settings.skip_failed_links;
+        settings.local_sub_dirs = ini_settings.local_sub_dirs.clone();
+        settings.clash_provider_files = ini_settings.clash_provider_files;
 
         // EMOJIS SECTION
         settings.add_emoji = ini_settings.add_emoji;
         settings.remove_emoji = ini_settings.remove_emoji;
         settings.emojis = ini_settings.parsed_emoji_rules;
 
-        // Ensure listen_address is not empty, as done in the C++ code
-        if settings.listen_address.trim().is_empty() {
-            settings.listen_address = default_listen_address();
-        }
+        // AUTOGROUP SECTION
+        settings.autogroup = ini_settings.autogroup;
+        settings.autogroup_name = ini_settings.autogroup_name.clone();
+        settings.autogroup_min_nodes = ini_settings.autogroup_min_nodes;
+        settings.autogroup_countries = ini_settings.parsed_autogroup_countries;
 
+        apply_switches(&mut settings, ini_settings.enable_cache);
         settings
     }
 }
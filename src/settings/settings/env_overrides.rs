@@ -0,0 +1,301 @@
+//! Overrides of settings fields from environment variables, for deployments
+//! which configure the server without a preference file
+//!
+//! Every scalar field of [`Settings`] is read from `SUBCONVERTER_` followed by
+//! the field name in upper case, e.g. `SUBCONVERTER_API_ACCESS_TOKEN` or
+//! `SUBCONVERTER_LISTEN_ADDRESS`. A few fields are also read under the key
+//! they have in the preference file, such as `SUBCONVERTER_LISTEN`.
+
+use super::settings_struct::{normalize_api_base_path, Settings};
+
+/// Prefix of the environment variables read by [`Settings::apply_env_overrides`]
+pub const ENV_PREFIX: &str = "SUBCONVERTER_";
+
+/// A settings field which can be set from the text of an environment variable
+trait EnvValue: Sized {
+    fn parse_env(value: &str) -> Result<Self, String>;
+    fn show(&self) -> String;
+}
+
+impl EnvValue for String {
+    fn parse_env(value: &str) -> Result<Self, String> {
+        Ok(value.to_string())
+    }
+
+    fn show(&self) -> String {
+        self.clone()
+    }
+}
+
+impl EnvValue for bool {
+    fn parse_env(value: &str) -> Result<Self, String> {
+        match value.trim().to_lowercase().as_str() {
+            "true" | "1" | "yes" | "on" => Ok(true),
+            "false" | "0" | "no" | "off" => Ok(false),
+            _ => Err(format!("'{}' is not a boolean", value)),
+        }
+    }
+
+    fn show(&self) -> String {
+        self.to_string()
+    }
+}
+
+impl EnvValue for Option<bool> {
+    /// An empty value unsets the flag
+    fn parse_env(value: &str) -> Result<Self, String> {
+        if value.trim().is_empty() {
+            return Ok(None);
+        }
+        bool::parse_env(value).map(Some)
+    }
+
+    fn show(&self) -> String {
+        match self {
+            Some(value) => value.to_string(),
+            None => "unset".to_string(),
+        }
+    }
+}
+
+macro_rules! impl_env_number {
+    ($($number:ty),*) => {
+        $(
+            impl EnvValue for $number {
+                fn parse_env(value: &str) -> Result<Self, String> {
+                    value
+                        .trim()
+                        .parse()
+                        .map_err(|e| format!("'{}' is not a valid number: {}", value, e))
+                }
+
+                fn show(&self) -> String {
+                    self.to_string()
+                }
+            }
+        )*
+    };
+}
+
+impl_env_number!(u32, i64, usize);
+
+/// Whether the value of `field` is kept out of logs
+fn is_secret(field: &str) -> bool {
+    field.contains("token") || field.contains("password")
+}
+
+/// Name and value of the first of `names` set in the environment
+fn lookup_var(
+    lookup: &impl Fn(&str) -> Option<String>,
+    names: &[&str],
+) -> Option<(String, String)> {
+    names.iter().find_map(|name| {
+        let var = format!("{}{}", ENV_PREFIX, name.to_uppercase());
+        lookup(&var).map(|value| (var, value))
+    })
+}
+
+/// Override each listed field which has an environment variable, the field
+/// name comes first, then the aliases
+macro_rules! override_fields {
+    ($settings:ident, $lookup:ident, $overridden:ident; $($field:ident $(| $alias:literal)*),* $(,)?) => {
+        $(
+            if let Some((var, value)) =
+                lookup_var(&$lookup, &[stringify!($field) $(, $alias)*])
+            {
+                $settings.$field =
+                    EnvValue::parse_env(&value).map_err(|e| format!("{}: {}", var, e))?;
+                $overridden.push(describe(stringify!($field), &$settings.$field.show()));
+            }
+        )*
+    };
+}
+
+fn describe(field: &str, value: &str) -> String {
+    if is_secret(field) {
+        format!("{}=<redacted>", field)
+    } else {
+        format!("{}={}", field, value)
+    }
+}
+
+impl Settings {
+    /// Override fields from `SUBCONVERTER_*` environment variables
+    ///
+    /// # Returns
+    /// The overridden fields as `field=value` with secrets redacted, or the
+    /// name of the variable which could not be converted
+    pub fn apply_env_overrides(&mut self) -> Result<Vec<String>, String> {
+        self.apply_env_overrides_from(|name| std::env::var(name).ok())
+    }
+
+    /// Same as [`Settings::apply_env_overrides`] with the variables looked up
+    /// by `lookup`
+    pub fn apply_env_overrides_from(
+        &mut self,
+        lookup: impl Fn(&str) -> Option<String>,
+    ) -> Result<Vec<String>, String> {
+        let mut overridden = Vec::new();
+        override_fields!(self, lookup, overridden;
+            default_ext_config,
+            managed_config_prefix,
+            max_pending_conns,
+            max_concur_threads,
+            prepend_insert,
+            skip_failed_links,
+            api_mode,
+            write_managed_config,
+            enable_rule_gen | "enable_rule_generator",
+            update_ruleset_on_request,
+            overwrite_original_rules,
+            append_userinfo,
+            append_info_nodes,
+            group_test_url,
+            group_interval,
+            group_timeout,
+            group_tolerance,
+            group_lazy,
+            async_fetch_ruleset,
+            surge_resolve_hostname,
+            api_access_token,
+            gist_token,
+            gist_id,
+            base_path,
+            custom_group,
+            log_level,
+            max_allowed_download_size,
+            max_redirects,
+            template_path,
+            prepend_proxy_direct_ruleset,
+            generator_mode,
+            generate_profiles,
+            reload_conf_on_request,
+            add_emoji,
+            remove_emoji,
+            autogroup,
+            autogroup_name,
+            autogroup_min_nodes,
+            append_type,
+            append_type_format,
+            filter_deprecated,
+            udp_flag,
+            tfo_flag | "tcp_fast_open_flag",
+            skip_cert_verify | "skip_cert_verify_flag",
+            tls13_flag,
+            enable_insert,
+            enable_sort | "sort_flag",
+            update_strict,
+            clash_use_new_field,
+            singbox_add_clash_modes,
+            clash_proxies_style,
+            clash_proxy_groups_style,
+            overwrite_original_proxies,
+            overwrite_original_groups,
+            proxy_config,
+            proxy_ruleset,
+            proxy_subscription,
+            update_interval,
+            sort_script,
+            duplicate_remark_format,
+            index_remark,
+            filter_script,
+            enable_filter,
+            filter_script_strict,
+            geoip_db,
+            clash_base | "clash_rule_base",
+            surge_base | "surge_rule_base",
+            surfboard_base | "surfboard_rule_base",
+            mellow_base | "mellow_rule_base",
+            quan_base | "quan_rule_base",
+            quanx_base | "quanx_rule_base",
+            loon_base | "loon_rule_base",
+            ssub_base | "sssub_rule_base",
+            singbox_base | "singbox_rule_base",
+            clash_dns,
+            clash_tun,
+            clash_sniffer,
+            clash_meta_overwrite,
+            surge_ssr_path,
+            quanx_dev_id,
+            serve_cache_on_fetch_fail,
+            cache_subscription,
+            cache_config,
+            cache_ruleset,
+            convert_cache_entries,
+            convert_cache_size,
+            bypass_convert_cache,
+            listen_address | "listen",
+            listen_port | "port",
+            serve_file,
+            serve_file_root,
+            api_base_path,
+            tls_cert,
+            tls_key,
+            shutdown_timeout,
+            watch_config,
+            upload_kind,
+            upload_url,
+            upload_username,
+            upload_password,
+            max_allowed_rulesets,
+            max_allowed_rules,
+            script_clean_context,
+            enable_cron,
+        );
+
+        // Fields the preference files do not store as they are read
+        self.api_base_path = normalize_api_base_path(&self.api_base_path);
+        if let Some((var, value)) = lookup_var(&lookup, &["unix_socket_mode"]) {
+            let digits = value.trim().trim_start_matches("0o");
+            self.unix_socket_mode = u32::from_str_radix(digits, 8)
+                .ok()
+                .filter(|mode| *mode <= 0o777)
+                .ok_or_else(|| format!("{}: '{}' is not an octal file mode", var, value))?;
+            overridden.push(describe(
+                "unix_socket_mode",
+                &format!("{:o}", self.unix_socket_mode),
+            ));
+        }
+
+        Ok(overridden)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    #[test]
+    fn test_env_overrides() {
+        let env = HashMap::from([
+            ("SUBCONVERTER_API_ACCESS_TOKEN", "secret"),
+            ("SUBCONVERTER_LISTEN", "0.0.0.0"),
+            ("SUBCONVERTER_LISTEN_PORT", "8080"),
+            ("SUBCONVERTER_UDP_FLAG", "true"),
+            ("SUBCONVERTER_API_BASE_PATH", "sub/"),
+            ("SUBCONVERTER_UNIX_SOCKET_MODE", "600"),
+        ]);
+        let mut settings = Settings::default();
+        let overridden = settings
+            .apply_env_overrides_from(|name| env.get(name).map(|value| value.to_string()))
+            .unwrap();
+
+        assert_eq!(settings.api_access_token, "secret");
+        assert_eq!(settings.listen_address, "0.0.0.0");
+        assert_eq!(settings.listen_port, 8080);
+        assert_eq!(settings.udp_flag, Some(true));
+        assert_eq!(settings.api_base_path, "/sub");
+        assert_eq!(settings.unix_socket_mode, 0o600);
+        assert!(overridden.contains(&"api_access_token=<redacted>".to_string()));
+        assert!(overridden.contains(&"listen_address=0.0.0.0".to_string()));
+        assert!(!overridden.iter().any(|field| field.contains("secret")));
+
+        let error = Settings::default()
+            .apply_env_overrides_from(|name| {
+                (name == "SUBCONVERTER_PORT").then(|| "eighty".to_string())
+            })
+            .unwrap_err();
+        assert!(error.starts_with("SUBCONVERTER_PORT: "), "{}", error);
+    }
+}
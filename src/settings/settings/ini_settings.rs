@@ -20,6 +20,10 @@ pub struct IniSettings {
     pub api_mode: bool,
     #[serde(default)]
     pub api_access_token: String,
+    #[serde(default)]
+    pub gist_token: String,
+    #[serde(default)]
+    pub gist_id: String,
 
     #[serde(default)]
     pub default_url: String,
@@ -48,10 +52,12 @@ pub struct IniSettings {
     #[serde(default = "default_max_concur_threads")]
     pub max_concur_threads: u32,
     #[serde(default)]
-    pub prepend_insert: bool,
-    #[serde(default)]
     pub skip_failed_links: bool,
     #[serde(default)]
+    pub local_sub_dirs: Vec<String>,
+    #[serde(default)]
+    pub clash_provider_files: bool,
+    #[serde(default)]
     pub write_managed_config: bool,
     #[serde(default = "default_true")]
     pub enable_rule_gen: bool,
@@ -64,15 +70,24 @@ pub struct IniSettings {
     #[serde(default = "default_true")]
     pub append_sub_userinfo: bool,
     #[serde(default)]
+    pub append_info_nodes: bool,
+    pub group_test_url: Option<String>,
+    pub group_interval: Option<u32>,
+    pub group_timeout: Option<u32>,
+    pub group_tolerance: Option<u32>,
+    pub group_lazy: Option<bool>,
+    #[serde(default)]
     pub async_fetch_ruleset: bool,
     #[serde(default)]
     pub surge_resolve_hostname: bool,
     pub base_path: String,
     pub custom_group: String,
     #[serde(default = "default_log_level")]
-    pub log_level: u32,
+    pub log_level: String,
     #[serde(default = "default_max_download_size")]
     pub max_allowed_download_size: i64,
+    #[serde(default = "default_max_redirects")]
+    pub max_redirects: u32,
     pub template_path: String,
     #[serde(default)]
     pub template_vars: HashMap<String, String>,
@@ -92,6 +107,15 @@ pub struct IniSettings {
     #[serde(default)]
     pub emoji_rules: Vec<String>,
 
+    #[serde(default)]
+    pub autogroup: bool,
+    #[serde(default = "default_autogroup_name")]
+    pub autogroup_name: String,
+    #[serde(default = "default_autogroup_min_nodes")]
+    pub autogroup_min_nodes: usize,
+    #[serde(default)]
+    pub autogroup_countries: Vec<String>,
+
     #[serde(default)]
     pub append_type: bool,
     #[serde(default = "default_true")]
@@ -111,12 +135,24 @@ pub struct IniSettings {
     #[serde(default)]
     pub rename_node: Vec<String>,
     #[serde(default)]
+    pub sort_priority: Vec<String>,
+    #[serde(default)]
+    pub duplicate_remark_format: String,
+    #[serde(default)]
+    pub index_remark: String,
+    #[serde(default)]
+    pub append_type_format: String,
+    #[serde(default)]
     pub stream_rule: Vec<String>,
     #[serde(default)]
     pub time_rule: Vec<String>,
 
     pub clash_proxies_style: String,
     pub clash_proxy_groups_style: String,
+    #[serde(default)]
+    pub overwrite_original_proxies: bool,
+    #[serde(default)]
+    pub overwrite_original_groups: bool,
     pub proxy_config: String,
     pub proxy_ruleset: String,
     pub proxy_subscription: String,
@@ -126,6 +162,8 @@ pub struct IniSettings {
 
     pub enable_filter: bool,
     pub filter_script: String,
+    pub filter_script_strict: bool,
+    pub geoip_db: String,
 
     // Base configs
     pub clash_base: String,
@@ -137,6 +175,10 @@ pub struct IniSettings {
     pub loon_base: String,
     pub ssub_base: String,
     pub singbox_base: String,
+    pub clash_dns: String,
+    pub clash_tun: String,
+    pub clash_sniffer: String,
+    pub clash_meta_overwrite: bool,
     pub surge_ssr_path: String,
     pub quanx_dev_id: String,
 
@@ -151,6 +193,12 @@ pub struct IniSettings {
     pub cache_config: u32,
     #[serde(default = "default_cache_ruleset")]
     pub cache_ruleset: u32,
+    #[serde(default)]
+    pub convert_cache_entries: Option<usize>,
+    #[serde(default)]
+    pub convert_cache_size: Option<usize>,
+    #[serde(default)]
+    pub bypass_convert_cache: bool,
 
     // Limits
     #[serde(default = "default_max_rulesets")]
@@ -162,8 +210,6 @@ pub struct IniSettings {
 
     // Cron system
     #[serde(default)]
-    pub enable_cron: bool,
-    #[serde(default)]
     pub cron_tasks: Vec<String>,
 
     // Custom rulesets and groups
@@ -176,10 +222,36 @@ pub struct IniSettings {
     #[serde(default)]
     pub serve_file: bool,
     pub serve_file_root: String,
+    #[serde(default)]
+    pub api_base_path: String,
+    #[serde(default)]
+    pub unix_socket_mode: String,
+    #[serde(default)]
+    pub tls_cert: String,
+    #[serde(default)]
+    pub tls_key: String,
+    #[serde(default)]
+    pub shutdown_timeout: Option<u32>,
+    #[serde(default)]
+    pub upload_kind: String,
+    #[serde(default)]
+    pub upload_url: String,
+    #[serde(default)]
+    pub upload_username: String,
+    #[serde(default)]
+    pub upload_password: String,
+    #[serde(default)]
+    pub trusted_proxies: Vec<String>,
+    #[serde(default)]
+    pub watch_config: bool,
 
     // Aliases
     #[serde(default)]
     pub aliases: HashMap<String, String>,
+    #[serde(default)]
+    pub user_agents: HashMap<String, String>,
+    #[serde(default)]
+    pub target_defaults: HashMap<String, String>,
 
     #[serde(skip)]
     pub parsed_rename: RegexMatchConfigs,
@@ -190,6 +262,8 @@ pub struct IniSettings {
     #[serde(skip)]
     pub parsed_emoji_rules: RegexMatchConfigs,
     #[serde(skip)]
+    pub parsed_autogroup_countries: RegexMatchConfigs,
+    #[serde(skip)]
     pub parsed_proxy_group: ProxyGroupConfigs,
     #[serde(skip)]
     pub parsed_ruleset: RulesetConfigs,
@@ -218,14 +292,18 @@ fn default_true() -> bool {
     true
 }
 
-fn default_log_level() -> u32 {
-    1 // LOG_LEVEL_INFO
+fn default_log_level() -> String {
+    "info".to_string()
 }
 
 fn default_max_download_size() -> i64 {
     32 * 1024 * 1024 // 32MB
 }
 
+fn default_max_redirects() -> u32 {
+    3
+}
+
 fn default_cache_subscription() -> u32 {
     60
 }
@@ -246,10 +324,38 @@ fn default_max_rules() -> usize {
     32768
 }
 
+fn default_autogroup_name() -> String {
+    "Proxy".to_string()
+}
+
+fn default_autogroup_min_nodes() -> usize {
+    2
+}
+
 impl IniSettings {
-    /// Create a new settings instance with default values
+    /// Create a new settings instance with the defaults of keys left out of
+    /// the file, the same as in the YAML and TOML formats
     pub fn new() -> Self {
-        Self::default()
+        Self {
+            listen_address: default_listen_address(),
+            listen_port: default_listen_port(),
+            max_pending_conns: default_max_pending_conns(),
+            max_concur_threads: default_max_concur_threads(),
+            enable_rule_gen: true,
+            append_sub_userinfo: true,
+            clash_use_new_field: true,
+            log_level: default_log_level(),
+            max_allowed_download_size: default_max_download_size(),
+            max_redirects: default_max_redirects(),
+            cache_subscription: default_cache_subscription(),
+            cache_config: default_cache_config(),
+            cache_ruleset: default_cache_ruleset(),
+            max_allowed_rulesets: default_max_rulesets(),
+            max_allowed_rules: default_max_rules(),
+            autogroup_name: default_autogroup_name(),
+            autogroup_min_nodes: default_autogroup_min_nodes(),
+            ..Self::default()
+        }
     }
 
     /// Process imports in configuration
@@ -274,6 +380,17 @@ impl IniSettings {
         self.parsed_emoji_rules =
             RegexMatchConfigs::from_ini_with_delimiter(&self.emoji_rules, ",");
 
+        // Process autogroup countries
+        import_items(
+            &mut self.autogroup_countries,
+            false,
+            &proxy_config,
+            &self.base_path,
+        )
+        .await?;
+        self.parsed_autogroup_countries =
+            RegexMatchConfigs::from_ini_with_delimiter(&self.autogroup_countries, ",");
+
         // Process custom_proxy_group
         import_items(
             &mut self.custom_proxy_group,
@@ -298,7 +415,7 @@ impl IniSettings {
     /// Load settings from file or URL
     pub async fn load_from_file(path: &str) -> Result<Self, Box<dyn std::error::Error>> {
         let content = fs::read_to_string(path)?;
-        let mut settings = Self::default();
+        let mut settings = Self::new();
         settings.load_from_ini(&content)?;
 
         // Process any imports in the configuration
@@ -316,7 +433,9 @@ impl IniSettings {
     pub fn load_from_ini(&mut self, content: &str) -> Result<(), Box<dyn std::error::Error>> {
         let mut current_section = String::new();
 
-        for line in content.lines() {
+        // Files saved on Windows may start with a BOM and end lines with CRLF
+        let content = content.strip_prefix('\u{FEFF}').unwrap_or(content);
+        for line in content.split(['\r', '\n']) {
             let trimmed = line.trim();
 
             // Skip empty lines and comments
@@ -348,10 +467,13 @@ impl IniSettings {
                     }
                     "template" => self.process_template_section(key, value),
                     "aliases" => self.process_aliases_section(key, value),
+                    "user_agents" => self.process_user_agents_section(key, value),
+                    "target_defaults" => self.process_target_defaults_section(key, value),
                     "tasks" => self.process_tasks_section(key, value),
                     "server" => self.process_server_section(key, value),
                     "advanced" => self.process_advanced_section(key, value),
                     "emojis" => self.process_emoji_section(key, value),
+                    "autogroup" => self.process_autogroup_section(key, value),
                     _ => {} // Ignore unknown sections
                 }
             }
@@ -363,14 +485,18 @@ impl IniSettings {
         match key {
             "api_mode" => self.api_mode = parse_bool(value),
             "api_access_token" => self.api_access_token = value.to_string(),
+            "gist_token" => self.gist_token = value.to_string(),
+            "gist_id" => self.gist_id = value.to_string(),
             "default_url" => self.default_url = value.to_string(),
             "enable_insert" => self.enable_insert = parse_bool(value),
             "insert_url" => self.insert_url = value.to_string(),
-            "prepend_insert_url" => self.prepend_insert = parse_bool(value),
+            "prepend_insert_url" => self.prepend_insert_url = parse_bool(value),
             "exclude_remarks" => self.exclude_remarks.push(value.to_owned()),
             "include_remarks" => self.include_remarks.push(value.to_owned()),
             "enable_filter" => self.enable_filter = parse_bool(value),
             "filter_script" => self.filter_script = value.to_string(),
+            "filter_script_strict" => self.filter_script_strict = parse_bool(value),
+            "geoip_db" => self.geoip_db = value.to_string(),
             "base_path" => self.base_path = value.to_string(),
             "clash_rule_base" => self.clash_base = value.to_string(),
             "surge_rule_base" => self.surge_base = value.to_string(),
@@ -381,6 +507,10 @@ impl IniSettings {
             "loon_rule_base" => self.loon_base = value.to_string(),
             "sssub_rule_base" => self.ssub_base = value.to_string(),
             "singbox_rule_base" => self.singbox_base = value.to_string(),
+            "clash_dns" => self.clash_dns = value.to_string(),
+            "clash_tun" => self.clash_tun = value.to_string(),
+            "clash_sniffer" => self.clash_sniffer = value.to_string(),
+            "clash_meta_overwrite" => self.clash_meta_overwrite = parse_bool(value),
             "default_external_config" => self.default_ext_config = value.to_string(),
             "append_proxy_type" => self.append_type = parse_bool(value),
             "proxy_config" => self.proxy_config = value.to_string(),
@@ -407,11 +537,25 @@ impl IniSettings {
             "tls13_flag" => self.tls13_flag = Some(parse_bool(value)),
             "sort_flag" => self.enable_sort = parse_bool(value),
             "sort_script" => self.sort_script = value.to_string(),
-            "filter_deprecated" => self.filter_deprecated = parse_bool(value),
+            "sort_priority" => self.sort_priority.push(value.to_string()),
+            "duplicate_remark_format" => self.duplicate_remark_format = value.to_string(),
+            "index_remark" => self.index_remark = value.to_string(),
+            "append_type_format" => self.append_type_format = value.to_string(),
+            "filter_deprecated" | "filter_deprecated_nodes" => {
+                self.filter_deprecated = parse_bool(value)
+            }
             "append_sub_userinfo" => self.append_sub_userinfo = parse_bool(value),
+            "append_info_nodes" => self.append_info_nodes = parse_bool(value),
+            "group_test_url" => self.group_test_url = Some(value.to_string()),
+            "group_interval" => self.group_interval = value.parse().ok(),
+            "group_timeout" => self.group_timeout = value.parse().ok(),
+            "group_tolerance" => self.group_tolerance = value.parse().ok(),
+            "group_lazy" => self.group_lazy = Some(parse_bool(value)),
             "clash_use_new_field_name" => self.clash_use_new_field = parse_bool(value),
             "clash_proxies_style" => self.clash_proxies_style = value.to_string(),
             "clash_proxy_groups_style" => self.clash_proxy_groups_style = value.to_string(),
+            "overwrite_original_proxies" => self.overwrite_original_proxies = parse_bool(value),
+            "overwrite_original_groups" => self.overwrite_original_groups = parse_bool(value),
             "singbox_add_clash_modes" => self.singbox_add_clash_modes = parse_bool(value),
             "rename_node" => self.rename_node.push(value.to_string()),
             _ => {}
@@ -450,6 +594,20 @@ impl IniSettings {
         }
     }
 
+    fn process_autogroup_section(&mut self, key: &str, value: &str) {
+        match key {
+            "enable" => self.autogroup = parse_bool(value),
+            "name" => self.autogroup_name = value.to_string(),
+            "min_nodes" => {
+                if let Ok(val) = value.parse() {
+                    self.autogroup_min_nodes = val;
+                }
+            }
+            "country" => self.autogroup_countries.push(value.to_string()),
+            _ => {}
+        }
+    }
+
     fn process_ruleset_section(&mut self, key: &str, value: &str) {
         match key {
             "enabled" => self.enable_rule_gen = parse_bool(value),
@@ -491,13 +649,25 @@ impl IniSettings {
         }
     }
 
+    fn process_user_agents_section(&mut self, key: &str, value: &str) {
+        if !key.is_empty() {
+            self.user_agents.insert(key.to_string(), value.to_string());
+        }
+    }
+
+    fn process_target_defaults_section(&mut self, key: &str, value: &str) {
+        if !key.is_empty() && !value.is_empty() {
+            self.target_defaults
+                .insert(key.to_string(), value.to_string());
+        }
+    }
+
     fn process_tasks_section(&mut self, key: &str, value: &str) {
         match key {
+            // Format is "name`cronexp`path`timeout", parsed in process_imports
             "task" => {
                 if !value.is_empty() {
-                    // Format is typically "name`cronexp`path`timeout"
-                    self.enable_cron = true;
-                    // Tasks will be processed in process_imports
+                    self.cron_tasks.push(value.to_string());
                 }
             }
             _ => {}
@@ -516,17 +686,35 @@ impl IniSettings {
                 self.serve_file_root = value.to_string();
                 self.serve_file = !self.serve_file_root.is_empty();
             }
+            "api_base_path" => self.api_base_path = value.to_string(),
+            "unix_socket_mode" => self.unix_socket_mode = value.to_string(),
+            "tls_cert" => self.tls_cert = value.to_string(),
+            "tls_key" => self.tls_key = value.to_string(),
+            "upload_kind" => self.upload_kind = value.to_string(),
+            "upload_url" => self.upload_url = value.to_string(),
+            "upload_username" => self.upload_username = value.to_string(),
+            "upload_password" => self.upload_password = value.to_string(),
+            "trusted_proxies" => {
+                self.trusted_proxies = value
+                    .split(',')
+                    .map(str::trim)
+                    .filter(|proxy| !proxy.is_empty())
+                    .map(str::to_string)
+                    .collect()
+            }
+            "shutdown_timeout" => {
+                if let Ok(val) = value.parse() {
+                    self.shutdown_timeout = Some(val)
+                }
+            }
+            "watch_config" => self.watch_config = parse_bool(value),
             _ => {}
         }
     }
 
     fn process_advanced_section(&mut self, key: &str, value: &str) {
         match key {
-            "log_level" => {
-                if let Ok(val) = value.parse() {
-                    self.log_level = val
-                }
-            }
+            "log_level" => self.log_level = value.to_string(),
             "print_debug_info" => self.print_dbg_info = parse_bool(value),
             "max_pending_connections" => {
                 if let Ok(val) = value.parse() {
@@ -553,6 +741,11 @@ impl IniSettings {
                     self.max_allowed_download_size = val
                 }
             }
+            "max_redirects" => {
+                if let Ok(val) = value.parse() {
+                    self.max_redirects = val
+                }
+            }
             "enable_cache" => {
                 self.enable_cache = parse_bool(value);
             }
@@ -571,10 +764,23 @@ impl IniSettings {
                     self.cache_ruleset = val
                 }
             }
+            "convert_cache_entries" => {
+                if let Ok(val) = value.parse() {
+                    self.convert_cache_entries = Some(val)
+                }
+            }
+            "convert_cache_size" => {
+                if let Ok(val) = value.parse() {
+                    self.convert_cache_size = Some(val)
+                }
+            }
+            "bypass_convert_cache" => self.bypass_convert_cache = parse_bool(value),
             "serve_cache_on_fetch_fail" => self.serve_cache_on_fetch_fail = parse_bool(value),
             "script_clean_context" => self.script_clean_context = parse_bool(value),
             "async_fetch_ruleset" => self.async_fetch_ruleset = parse_bool(value),
             "skip_failed_links" => self.skip_failed_links = parse_bool(value),
+            "local_sub_dirs" => self.local_sub_dirs.push(value.to_owned()),
+            "clash_provider_files" => self.clash_provider_files = parse_bool(value),
             _ => {}
         }
     }
@@ -33,6 +33,7 @@
 ///
 /// Template variables can be accessed from settings.template_vars HashMap
 pub mod conversions;
+pub mod env_overrides;
 pub mod ini_settings;
 pub mod settings_struct;
 pub mod toml_settings;
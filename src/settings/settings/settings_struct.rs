@@ -111,6 +111,47 @@ type GlobalLockReadGuard<'a, T> = std::sync::RwLockReadGuard<'a, T>;
 #[cfg(not(target_arch = "wasm32"))]
 type GlobalLockWriteGuard<'a, T> = std::sync::RwLockWriteGuard<'a, T>;
 
+/// Format of a preference file
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SettingsFormat {
+    Ini,
+    Yaml,
+    Toml,
+}
+
+impl SettingsFormat {
+    /// Format of a preference file, from the extension of its path or URL
+    /// when it has a known one, otherwise from the content
+    ///
+    /// Content with a top level `common:` key is YAML, content TOML accepts is
+    /// TOML and anything else is INI.
+    pub fn detect(content: &str, file_path: &str) -> Self {
+        let path = file_path
+            .split(['?', '#'])
+            .next()
+            .unwrap_or_default()
+            .to_lowercase();
+        if path.ends_with(".yml") || path.ends_with(".yaml") {
+            return SettingsFormat::Yaml;
+        }
+        if path.ends_with(".toml") {
+            return SettingsFormat::Toml;
+        }
+        if path.ends_with(".ini") || path.ends_with(".conf") {
+            return SettingsFormat::Ini;
+        }
+
+        let content = content.strip_prefix('\u{FEFF}').unwrap_or(content);
+        if content.lines().any(|line| line.trim_end() == "common:") {
+            SettingsFormat::Yaml
+        } else if toml::from_str::<toml::Value>(content).is_ok() {
+            SettingsFormat::Toml
+        } else {
+            SettingsFormat::Ini
+        }
+    }
+}
+
 /// Settings structure to hold global configuration
 #[derive(Debug, Clone)]
 pub struct Settings {
@@ -133,6 +174,15 @@ pub struct Settings {
     pub renames: RegexMatchConfigs,
     pub emojis: RegexMatchConfigs,
 
+    /// Whether `autogroup` is on for requests leaving it out
+    pub autogroup: bool,
+    /// Name of the select group choosing between the country groups
+    pub autogroup_name: String,
+    /// Countries with fewer nodes go to the `Other` group
+    pub autogroup_min_nodes: usize,
+    /// Country patterns with their group names, built-in ones when empty
+    pub autogroup_countries: RegexMatchConfigs,
+
     pub default_urls: Vec<String>,
     pub insert_urls: Vec<String>,
     pub managed_config_prefix: String,
@@ -140,25 +190,50 @@ pub struct Settings {
     pub max_concur_threads: u32,
     pub prepend_insert: bool,
     pub skip_failed_links: bool,
+    /// Directories `file://` URLs and bare paths in `url=` may be read from
+    /// besides `base/`
+    pub local_sub_dirs: Vec<String>,
+    /// Whether the `path:` files of the `proxy-providers` in Clash configs
+    /// are read, only from the same directories as `local_sub_dirs`
+    pub clash_provider_files: bool,
     pub api_mode: bool,
     pub write_managed_config: bool,
     pub enable_rule_gen: bool,
     pub update_ruleset_on_request: bool,
     pub overwrite_original_rules: bool,
     pub append_userinfo: bool,
+    pub append_info_nodes: bool,
+    /// Defaults for url-test, fallback and load-balance groups which omit them
+    pub group_test_url: String,
+    pub group_interval: u32,
+    pub group_timeout: u32,
+    pub group_tolerance: u32,
+    pub group_lazy: bool,
     pub async_fetch_ruleset: bool,
     pub surge_resolve_hostname: bool,
     pub api_access_token: String,
+    /// GitHub token for `upload=true`, over the one in gistconf.ini
+    pub gist_token: String,
+    /// Gist updated by `upload=true`, a new Gist is created when empty
+    pub gist_id: String,
     pub base_path: String,
     pub custom_group: String,
     pub log_level: u32,
     pub max_allowed_download_size: i64,
+    /// Redirects followed when fetching a remote base template
+    pub max_redirects: u32,
     pub template_path: String,
     /// Template variables used for template rendering
     pub template_vars: HashMap<String, String>,
     pub prepend_proxy_direct_ruleset: bool,
 
     pub aliases: HashMap<String, String>,
+    /// Arguments used for requests without `target` from clients whose
+    /// User-Agent contains the key, e.g. `Surge` => `target=surge&ver=4`
+    pub user_agent_targets: HashMap<String, String>,
+    /// Arguments used for the options a request leaves unset, by target,
+    /// e.g. `clash` => `udp=true&emoji=true`
+    pub target_defaults: HashMap<String, String>,
 
     // Generator settings
     pub generator_mode: bool,
@@ -181,13 +256,34 @@ pub struct Settings {
     pub singbox_add_clash_modes: bool,
     pub clash_proxies_style: String,
     pub clash_proxy_groups_style: String,
+    /// Whether Clash configs drop the proxies of the base instead of keeping
+    /// them under the field name not written
+    pub overwrite_original_proxies: bool,
+    /// Whether Clash configs drop the groups of the base instead of merging
+    /// the generated ones into them
+    pub overwrite_original_groups: bool,
     pub proxy_config: String,
     pub proxy_ruleset: String,
     pub proxy_subscription: String,
     pub update_interval: u32,
     pub sort_script: String,
+    /// Patterns ranking nodes when sorting without a script, nodes matching
+    /// earlier patterns come first and unmatched nodes keep their order
+    pub sort_priority: Vec<String>,
+    /// Pattern renaming nodes with a taken remark, `{name} {n}` when empty
+    pub duplicate_remark_format: String,
+    /// Template renaming nodes after sorting, e.g. `{country} {index:02}`
+    pub index_remark: String,
+    /// Format of remarks with `append_type`, `[{type}] {remark}` when empty
+    pub append_type_format: String,
     pub filter_script: String,
     pub enable_filter: bool,
+    /// Whether a failing Rhai filter script fails the request instead of
+    /// being skipped with a warning
+    pub filter_script_strict: bool,
+    /// MaxMind DB country database (e.g. GeoLite2-Country.mmdb) locating
+    /// nodes whose remark names no country, needs the `geoip` feature
+    pub geoip_db: String,
 
     // Base configs
     pub clash_base: String,
@@ -199,6 +295,10 @@ pub struct Settings {
     pub loon_base: String,
     pub ssub_base: String,
     pub singbox_base: String,
+    pub clash_dns: String,
+    pub clash_tun: String,
+    pub clash_sniffer: String,
+    pub clash_meta_overwrite: bool,
     pub surge_ssr_path: String,
     pub quanx_dev_id: String,
 
@@ -207,12 +307,40 @@ pub struct Settings {
     pub cache_subscription: u32,
     pub cache_config: u32,
     pub cache_ruleset: u32,
+    /// Bounds of the in-memory cache of converted rulesets, in entries and bytes
+    pub convert_cache_entries: usize,
+    pub convert_cache_size: usize,
+    /// Convert rulesets on every use, for debugging conversions
+    pub bypass_convert_cache: bool,
 
     // Server
     pub listen_address: String,
     pub listen_port: u32,
     pub serve_file: bool,
     pub serve_file_root: String,
+    /// Path prefix all API routes are served under, normalized with a leading
+    /// slash and no trailing slash (empty when served at the root)
+    pub api_base_path: String,
+    /// Permission bits applied to unix domain sockets created from
+    /// `unix:` entries in `listen_address`
+    pub unix_socket_mode: u32,
+    /// PEM certificate chain and private key, HTTPS is enabled when both are set
+    pub tls_cert: String,
+    pub tls_key: String,
+    /// Seconds to wait for in-flight requests to finish on graceful shutdown
+    pub shutdown_timeout: u32,
+    /// Where `upload=true` sends configs, `webdav` or the default `gist`
+    pub upload_kind: String,
+    /// WebDAV collection configs are uploaded under, with its credentials
+    pub upload_url: String,
+    pub upload_username: String,
+    pub upload_password: String,
+    /// Addresses of reverse proxies whose `Forwarded` and `X-Forwarded-*`
+    /// headers are trusted, `*` trusts every peer
+    pub trusted_proxies: Vec<String>,
+    /// Reload when the preference file, the base directory or the profiles
+    /// change on disk, read at startup only
+    pub watch_config: bool,
 
     // Limits
     pub max_allowed_rulesets: usize,
@@ -222,6 +350,10 @@ pub struct Settings {
     // Cron system
     pub enable_cron: bool,
     pub cron_tasks: CronTaskConfigs,
+
+    /// Fields set from `SUBCONVERTER_*` environment variables as
+    /// `field=value`, secrets redacted
+    pub env_overrides: Vec<String>,
 }
 
 // Default value functions for serde
@@ -241,6 +373,51 @@ pub fn default_max_concur_threads() -> u32 {
     4
 }
 
+pub fn default_autogroup_name() -> String {
+    "Proxy".to_string()
+}
+
+pub fn default_autogroup_min_nodes() -> usize {
+    2
+}
+
+/// Clients recognized by their User-Agent out of the box
+pub fn default_user_agent_targets() -> HashMap<String, String> {
+    [
+        ("Clash", "target=clash&new_name=true"),
+        ("ClashForAndroid", "target=clash&new_name=true"),
+        ("ClashforWindows", "target=clash&new_name=true"),
+        ("ClashX", "target=clash&new_name=true"),
+        ("clash-verge", "target=clash&new_name=true&meta=true"),
+        ("clash.meta", "target=clash&new_name=true&meta=true"),
+        ("mihomo", "target=clash&new_name=true&meta=true"),
+        ("Stash", "target=clash&new_name=true&meta=true"),
+        ("Surge", "target=surge&ver=4"),
+        ("Surfboard", "target=surfboard"),
+        ("Quantumult", "target=quan"),
+        ("Quantumult%20X", "target=quanx"),
+        ("Quantumult X", "target=quanx"),
+        ("Loon", "target=loon"),
+        ("sing-box", "target=singbox"),
+        ("SFA", "target=singbox"),
+        ("SFI", "target=singbox"),
+        ("SFM", "target=singbox"),
+        ("Shadowrocket", "target=mixed"),
+        ("Potatso", "target=mixed"),
+        ("Pharos", "target=mixed"),
+        ("v2rayN", "target=mixed"),
+        ("v2rayNG", "target=mixed"),
+        ("Kitsunebi", "target=v2ray"),
+        ("Qv2ray", "target=v2ray"),
+        ("V2rayU", "target=v2ray"),
+        ("V2RayX", "target=v2ray"),
+        ("Trojan-Qt5", "target=trojan"),
+    ]
+    .into_iter()
+    .map(|(user_agent, args)| (user_agent.to_string(), args.to_string()))
+    .collect()
+}
+
 pub fn default_true() -> bool {
     true
 }
@@ -253,6 +430,10 @@ pub fn default_max_download_size() -> i64 {
     32 * 1024 * 1024 // 32MB
 }
 
+pub fn default_max_redirects() -> u32 {
+    3
+}
+
 pub fn default_cache_subscription() -> u32 {
     60
 }
@@ -265,6 +446,14 @@ pub fn default_cache_ruleset() -> u32 {
     21600
 }
 
+pub fn default_convert_cache_entries() -> usize {
+    256
+}
+
+pub fn default_convert_cache_size() -> usize {
+    32 * 1024 * 1024 // 32MB
+}
+
 pub fn default_max_rulesets() -> usize {
     64
 }
@@ -273,6 +462,39 @@ pub fn default_max_rules() -> usize {
     32768
 }
 
+pub fn default_shutdown_timeout() -> u32 {
+    30
+}
+
+pub fn default_unix_socket_mode() -> u32 {
+    0o660
+}
+
+/// Parse an octal permission string such as `660` or `0o660`, falling back to
+/// the default mode when empty or invalid
+pub fn parse_unix_socket_mode(mode: &str) -> u32 {
+    let mode = mode.trim();
+    let digits = mode.strip_prefix("0o").unwrap_or(mode);
+    if digits.is_empty() {
+        return default_unix_socket_mode();
+    }
+    u32::from_str_radix(digits, 8)
+        .ok()
+        .filter(|mode| *mode <= 0o777)
+        .unwrap_or_else(default_unix_socket_mode)
+}
+
+/// Normalize an API base path to have a leading slash and no trailing slash,
+/// returning an empty string for the root
+pub fn normalize_api_base_path(path: &str) -> String {
+    let trimmed = path.trim().trim_matches('/');
+    if trimmed.is_empty() {
+        String::new()
+    } else {
+        format!("/{}", trimmed)
+    }
+}
+
 impl Default for Settings {
     fn default() -> Self {
         Settings {
@@ -287,7 +509,13 @@ impl Default for Settings {
             time_rules: Vec::new(),
             renames: RegexMatchConfigs::new(),
             emojis: RegexMatchConfigs::new(),
+            autogroup: false,
+            autogroup_name: default_autogroup_name(),
+            autogroup_min_nodes: default_autogroup_min_nodes(),
+            autogroup_countries: RegexMatchConfigs::new(),
             aliases: HashMap::new(),
+            user_agent_targets: default_user_agent_targets(),
+            target_defaults: HashMap::new(),
             default_urls: Vec::new(),
             insert_urls: Vec::new(),
             managed_config_prefix: String::new(),
@@ -295,19 +523,30 @@ impl Default for Settings {
             max_concur_threads: default_max_concur_threads(),
             prepend_insert: false,
             skip_failed_links: false,
+            local_sub_dirs: Vec::new(),
+            clash_provider_files: false,
             api_mode: false,
             write_managed_config: false,
             enable_rule_gen: default_true(),
             update_ruleset_on_request: false,
             overwrite_original_rules: false,
             append_userinfo: default_true(),
+            append_info_nodes: false,
+            group_test_url: "http://www.gstatic.com/generate_204".to_string(),
+            group_interval: 300,
+            group_timeout: 5,
+            group_tolerance: 0,
+            group_lazy: true,
             async_fetch_ruleset: false,
             surge_resolve_hostname: false,
             api_access_token: String::new(),
+            gist_token: String::new(),
+            gist_id: String::new(),
             base_path: String::new(),
             custom_group: String::new(),
             log_level: default_log_level(),
             max_allowed_download_size: default_max_download_size(),
+            max_redirects: default_max_redirects(),
             template_path: String::new(),
             template_vars: HashMap::new(),
 
@@ -333,13 +572,21 @@ impl Default for Settings {
             singbox_add_clash_modes: false,
             clash_proxies_style: String::new(),
             clash_proxy_groups_style: String::new(),
+            overwrite_original_proxies: false,
+            overwrite_original_groups: false,
             proxy_config: String::new(),
             proxy_ruleset: String::new(),
             proxy_subscription: String::new(),
             update_interval: 0,
             sort_script: String::new(),
+            sort_priority: Vec::new(),
+            duplicate_remark_format: String::new(),
+            index_remark: String::new(),
+            append_type_format: String::new(),
             filter_script: String::new(),
             enable_filter: false,
+            filter_script_strict: false,
+            geoip_db: String::new(),
             // Base configs
             clash_base: String::new(),
             surge_base: String::new(),
@@ -350,6 +597,10 @@ impl Default for Settings {
             loon_base: String::new(),
             ssub_base: String::new(),
             singbox_base: String::new(),
+            clash_dns: String::new(),
+            clash_tun: String::new(),
+            clash_sniffer: String::new(),
+            clash_meta_overwrite: false,
             surge_ssr_path: String::new(),
             quanx_dev_id: String::new(),
 
@@ -358,12 +609,26 @@ impl Default for Settings {
             cache_subscription: default_cache_subscription(),
             cache_config: default_cache_config(),
             cache_ruleset: default_cache_ruleset(),
+            convert_cache_entries: default_convert_cache_entries(),
+            convert_cache_size: default_convert_cache_size(),
+            bypass_convert_cache: false,
 
             // Server
             listen_address: default_listen_address(),
             listen_port: default_listen_port(),
             serve_file: false,
             serve_file_root: String::new(),
+            api_base_path: String::new(),
+            unix_socket_mode: default_unix_socket_mode(),
+            tls_cert: String::new(),
+            tls_key: String::new(),
+            shutdown_timeout: default_shutdown_timeout(),
+            upload_kind: String::new(),
+            upload_url: String::new(),
+            upload_username: String::new(),
+            upload_password: String::new(),
+            trusted_proxies: Vec::new(),
+            watch_config: false,
 
             // Limits
             max_allowed_rulesets: default_max_rulesets(),
@@ -373,6 +638,8 @@ impl Default for Settings {
             // Cron system
             enable_cron: false,
             cron_tasks: CronTaskConfigs::new(),
+
+            env_overrides: Vec::new(),
         }
     }
 }
@@ -383,6 +650,94 @@ impl Settings {
         Self::default()
     }
 
+    /// Log the fields set from environment variables
+    pub fn log_env_overrides(&self) {
+        if !self.env_overrides.is_empty() {
+            debug!(
+                "Settings overridden by environment variables: {}",
+                self.env_overrides.join(", ")
+            );
+        }
+    }
+
+    /// Whether the built-in server should serve HTTPS
+    pub fn tls_enabled(&self) -> bool {
+        !self.tls_cert.is_empty() && !self.tls_key.is_empty()
+    }
+
+    /// Point managed config URLs at this server over HTTPS when TLS is active
+    /// and no prefix was configured explicitly
+    fn apply_tls_defaults(&mut self) {
+        if !self.tls_enabled() || !self.managed_config_prefix.is_empty() {
+            return;
+        }
+
+        let address = self
+            .listen_address
+            .split(',')
+            .map(str::trim)
+            .find(|address| !address.is_empty() && !address.starts_with("unix:"))
+            .unwrap_or_default();
+        // Split "host:port" and "[v6]:port" forms, the listener binds the
+        // given port instead of listen_port
+        let (host, port) = if let Some(end) = address.find(']') {
+            (&address[..=end], address[end + 1..].strip_prefix(':'))
+        } else if let Some((host, port)) = address
+            .split_once(':')
+            .filter(|_| address.matches(':').count() == 1)
+        {
+            (host, Some(port))
+        } else {
+            (address, None)
+        };
+        let host = match host {
+            "" | "0.0.0.0" | "::" | "[::]" => "127.0.0.1",
+            host => host,
+        };
+        let port = match port.filter(|port| !port.is_empty()) {
+            Some(port) => port.to_string(),
+            None => self.listen_port.to_string(),
+        };
+        self.managed_config_prefix = format!("https://{}:{}", host, port);
+    }
+
+    /// Arguments for a request from a client with `user_agent`, the entry with
+    /// the longest key found in it wins, then the one found first, case is
+    /// ignored. Entries without arguments are skipped
+    pub fn user_agent_args(&self, user_agent: &str) -> Option<&str> {
+        let user_agent = user_agent.to_lowercase();
+        self.user_agent_targets
+            .iter()
+            .filter(|(key, args)| !key.is_empty() && !args.is_empty())
+            .filter_map(|(key, args)| {
+                let position = user_agent.find(&key.to_lowercase())?;
+                Some(((key.len(), std::cmp::Reverse(position)), args))
+            })
+            .max_by_key(|(rank, _)| *rank)
+            .map(|(_, args)| args.as_str())
+    }
+
+    /// Default arguments configured for `target`, case is ignored
+    pub fn target_defaults_args(&self, target: &str) -> Option<&str> {
+        self.target_defaults
+            .iter()
+            .find(|(key, _)| key.eq_ignore_ascii_case(target))
+            .map(|(_, args)| args.as_str())
+    }
+
+    /// Managed config prefix with the API base path appended, used to build
+    /// URLs pointing back at this server
+    ///
+    /// A prefix which already ends with the base path, as written for a
+    /// reverse proxy, is kept as is.
+    pub fn managed_config_url(&self) -> String {
+        let prefix = self.managed_config_prefix.trim_end_matches('/');
+        if self.api_base_path.is_empty() || prefix.ends_with(&self.api_base_path) {
+            return self.managed_config_prefix.clone();
+        }
+        format!("{}{}", prefix, self.api_base_path)
+    }
+
     /// Get a mutable reference to the current settings
     pub fn current_mut() -> GlobalLockWriteGuard<'static, Arc<Settings>> {
         #[cfg(not(target_arch = "wasm32"))]
@@ -411,62 +766,37 @@ impl Settings {
         content: &str,
         file_path: &str,
     ) -> Result<Self, Box<dyn std::error::Error>> {
-        // Try to parse as YAML first
-        if content.contains("common:") {
-            let mut yaml_settings: crate::settings::settings::yaml_settings::YamlSettings =
-                serde_yaml::from_str(&content)?;
-            yaml_settings.process_imports_and_inis().await?;
-
-            let mut _settings = Settings::from(yaml_settings);
-
-            return Ok(_settings);
-        }
-        if file_path.ends_with(".yml") || file_path.ends_with(".yaml") {
-            return Err(format!(
-                "The file content as YAML is not match the file extension: {}",
-                file_path
-            )
-            .into());
-        }
-
-        // Try to parse as TOML
-        if toml::from_str::<toml::Value>(&content).is_ok() {
-            let mut toml_settings: crate::settings::settings::toml_settings::TomlSettings =
-                toml::from_str(&content)?;
-
-            toml_settings.process_imports().await?;
-
-            let mut settings = Settings::from(toml_settings);
+        let mut settings = Self::parse_content(content, file_path).await?;
+        settings.env_overrides = settings.apply_env_overrides()?;
+        settings.log_env_overrides();
+        settings.apply_tls_defaults();
+        Ok(settings)
+    }
 
-            // Ensure listen_address is not empty
-            if settings.listen_address.trim().is_empty() {
-                settings.listen_address = default_listen_address();
+    async fn parse_content(
+        content: &str,
+        file_path: &str,
+    ) -> Result<Self, Box<dyn std::error::Error>> {
+        match SettingsFormat::detect(content, file_path) {
+            SettingsFormat::Yaml => {
+                let mut yaml_settings: super::yaml_settings::YamlSettings =
+                    serde_yaml::from_str(content)?;
+                yaml_settings.process_imports_and_inis().await?;
+                Ok(Settings::from(yaml_settings))
+            }
+            SettingsFormat::Toml => {
+                let mut toml_settings: super::toml_settings::TomlSettings =
+                    toml::from_str(content)?;
+                toml_settings.process_imports().await?;
+                Ok(Settings::from(toml_settings))
+            }
+            SettingsFormat::Ini => {
+                let mut ini_settings = super::ini_settings::IniSettings::new();
+                ini_settings.load_from_ini(content)?;
+                ini_settings.process_imports().await?;
+                Ok(Settings::from(ini_settings))
             }
-
-            return Ok(settings);
-        }
-
-        if file_path.ends_with(".toml") {
-            return Err(format!(
-                "The file content as TOML is not match the file extension: {}",
-                file_path
-            )
-            .into());
-        }
-
-        // Default to INI
-        let mut ini_settings = crate::settings::settings::ini_settings::IniSettings::new();
-        ini_settings.load_from_ini(&content)?;
-        ini_settings.process_imports().await?;
-
-        let mut settings = Settings::from(ini_settings);
-
-        // Ensure listen_address is not empty
-        if settings.listen_address.trim().is_empty() {
-            settings.listen_address = default_listen_address();
         }
-
-        Ok(settings)
     }
 
     /// Load settings from file or URL asynchronously
@@ -590,7 +920,25 @@ pub async fn init_settings(args_path: &str) -> Result<(), Box<dyn std::error::Er
             return Ok(());
         }
     }
-    Err("No settings file found".into())
+
+    // Containers may be configured from the environment alone
+    let mut settings = Settings::default();
+    settings.env_overrides = settings.apply_env_overrides()?;
+    if settings.env_overrides.is_empty() {
+        return Err("No settings file found".into());
+    }
+    info!("No settings file found, using defaults and environment variables");
+    settings.log_env_overrides();
+    settings.apply_tls_defaults();
+    #[cfg(not(target_arch = "wasm32"))]
+    {
+        *GLOBAL.write().unwrap() = Arc::new(settings);
+    }
+    #[cfg(target_arch = "wasm32")]
+    {
+        *get_global().write().unwrap() = Arc::new(settings);
+    }
+    Ok(())
 }
 
 /// Update settings from content asynchronously
@@ -615,3 +963,188 @@ pub async fn update_settings_from_content(content: &str) -> Result<(), Box<dyn s
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tls_settings(listen_address: &str) -> Settings {
+        let mut settings = Settings {
+            listen_address: listen_address.to_string(),
+            listen_port: 25500,
+            tls_cert: "cert.pem".to_string(),
+            tls_key: "key.pem".to_string(),
+            ..Default::default()
+        };
+        settings.apply_tls_defaults();
+        settings
+    }
+
+    #[test]
+    fn test_tls_prefix_uses_the_port_the_server_binds() {
+        let prefix = |address: &str| tls_settings(address).managed_config_prefix;
+        assert_eq!(prefix("0.0.0.0"), "https://127.0.0.1:25500");
+        assert_eq!(prefix("0.0.0.0:8443"), "https://127.0.0.1:8443");
+        assert_eq!(prefix("sub.example.com:443"), "https://sub.example.com:443");
+        assert_eq!(prefix("[::1]:8443"), "https://[::1]:8443");
+        assert_eq!(prefix("[::]"), "https://127.0.0.1:25500");
+        assert_eq!(
+            prefix("unix:/tmp/sub.sock, example.com"),
+            "https://example.com:25500"
+        );
+
+        // An explicit prefix is left alone
+        let mut settings = tls_settings("0.0.0.0:8443");
+        settings.managed_config_prefix = "https://sub.example.com".to_string();
+        settings.apply_tls_defaults();
+        assert_eq!(settings.managed_config_prefix, "https://sub.example.com");
+    }
+
+    #[test]
+    fn test_longest_user_agent_match_wins() {
+        let mut settings = Settings::default();
+        let args = |settings: &Settings, user_agent: &str| {
+            settings.user_agent_args(user_agent).map(str::to_string)
+        };
+        assert_eq!(
+            args(&settings, "Quantumult%20X/1.0.30 (iPhone14,2; iOS 16.0)"),
+            Some("target=quanx".to_string())
+        );
+        assert_eq!(
+            args(&settings, "clash-verge/v1.3.8"),
+            Some("target=clash&new_name=true&meta=true".to_string())
+        );
+        assert_eq!(args(&settings, "curl/8.0"), None);
+
+        settings
+            .user_agent_targets
+            .insert("Stash".to_string(), String::new());
+        assert_eq!(
+            args(&settings, "Stash/2.4.0 Clash/1.9.0"),
+            Some("target=clash&new_name=true".to_string())
+        );
+    }
+
+    #[test]
+    fn test_managed_config_url_appends_base_path_once() {
+        let mut settings = Settings {
+            managed_config_prefix: "https://sub.example.com/".to_string(),
+            api_base_path: "/subconverter".to_string(),
+            ..Default::default()
+        };
+        assert_eq!(
+            settings.managed_config_url(),
+            "https://sub.example.com/subconverter"
+        );
+
+        settings.managed_config_prefix = "https://sub.example.com/subconverter".to_string();
+        assert_eq!(
+            settings.managed_config_url(),
+            "https://sub.example.com/subconverter"
+        );
+
+        settings.api_base_path = String::new();
+        assert_eq!(
+            settings.managed_config_url(),
+            "https://sub.example.com/subconverter"
+        );
+    }
+
+    /// Debug output of `settings` with the maps sorted, as hashing orders
+    /// them differently each time
+    fn comparable(mut settings: Settings) -> String {
+        let maps = [
+            &mut settings.template_vars,
+            &mut settings.aliases,
+            &mut settings.user_agent_targets,
+            &mut settings.target_defaults,
+        ]
+        .map(|map| {
+            std::mem::take(map)
+                .into_iter()
+                .collect::<std::collections::BTreeMap<_, _>>()
+        });
+        format!("{:#?}\n{:#?}", settings, maps)
+    }
+
+    #[tokio::test]
+    async fn test_pref_formats_parse_to_equal_settings() {
+        let ini = Settings::parse_content(
+            include_str!("../../../tests/fixtures/settings/pref.ini"),
+            "pref.ini",
+        )
+        .await
+        .unwrap();
+        let yaml = Settings::parse_content(
+            include_str!("../../../tests/fixtures/settings/pref.yml"),
+            "pref.yml",
+        )
+        .await
+        .unwrap();
+        let toml = Settings::parse_content(
+            include_str!("../../../tests/fixtures/settings/pref.toml"),
+            "pref.toml",
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(ini.api_access_token, "token123");
+        assert_eq!(ini.custom_rulesets.len(), 4);
+        assert_eq!(ini.custom_proxy_groups.len(), 2);
+        assert_eq!(ini.emojis.len(), 2);
+        assert!(ini.autogroup);
+        assert_eq!(ini.autogroup_min_nodes, 3);
+        assert_eq!(ini.autogroup_countries[1].replace, "Japan");
+        assert_eq!(ini.cache_subscription, 120);
+        assert_eq!(ini.template_vars["clash.http_port"], "7890");
+        assert!(ini.enable_cron);
+        assert!(ini.clash_provider_files);
+        assert_eq!(ini.api_base_path, "/api");
+        let ini = comparable(ini);
+        assert_eq!(ini, comparable(yaml));
+        assert_eq!(ini, comparable(toml));
+
+        // Keys left out get the same defaults in every format
+        let ini = Settings::parse_content("[common]\n", "pref.ini")
+            .await
+            .unwrap();
+        let yaml = Settings::parse_content("common: {}\n", "pref.yml")
+            .await
+            .unwrap();
+        let toml = Settings::parse_content("[common]\n", "pref.toml")
+            .await
+            .unwrap();
+        assert_eq!(ini.max_concur_threads, default_max_concur_threads());
+        let ini = comparable(ini);
+        assert_eq!(ini, comparable(yaml));
+        assert_eq!(ini, comparable(toml));
+    }
+
+    #[test]
+    fn test_settings_format_detection() {
+        // The extension wins over the content
+        assert_eq!(
+            SettingsFormat::detect("common:\n", "pref.toml"),
+            SettingsFormat::Toml
+        );
+        assert_eq!(
+            SettingsFormat::detect("[common]\n", "https://example.com/pref.yaml?token=1"),
+            SettingsFormat::Yaml
+        );
+        assert_eq!(
+            SettingsFormat::detect("[common]\napi_mode=true\n", "pref.ini"),
+            SettingsFormat::Ini
+        );
+
+        let detect = |content| SettingsFormat::detect(content, "https://example.com/pref");
+        assert_eq!(detect("common:\n  api_mode: true\n"), SettingsFormat::Yaml);
+        assert_eq!(
+            detect("[common]\napi_access_token = \"x\"\n"),
+            SettingsFormat::Toml
+        );
+        assert_eq!(
+            detect("[common]\nexclude_remarks=(到期)\n"),
+            SettingsFormat::Ini
+        );
+    }
+}
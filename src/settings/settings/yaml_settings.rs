@@ -21,16 +21,13 @@ fn default_true() -> bool {
     true
 }
 
-fn default_empty_string() -> String {
-    String::new()
-}
-
-fn default_system() -> String {
-    "SYSTEM".to_string()
+/// A section left out of the file, with the field defaults of an empty one
+fn empty_section<T: serde::de::DeserializeOwned>() -> T {
+    serde_yaml::from_str("{}").expect("an empty section always deserializes")
 }
 
-fn default_none() -> String {
-    "NONE".to_string()
+fn default_empty_string() -> String {
+    String::new()
 }
 
 fn default_listen_address() -> String {
@@ -41,6 +38,10 @@ fn default_listen_port() -> u32 {
     25500
 }
 
+fn default_shutdown_timeout() -> u32 {
+    30
+}
+
 fn default_max_pending_conns() -> u32 {
     10240
 }
@@ -53,6 +54,10 @@ fn default_info_log_level() -> String {
     "info".to_string()
 }
 
+fn default_max_redirects() -> u32 {
+    3
+}
+
 fn default_cache_subscription() -> u32 {
     60
 }
@@ -65,6 +70,14 @@ fn default_cache_ruleset() -> u32 {
     21600
 }
 
+fn default_convert_cache_entries() -> usize {
+    256
+}
+
+fn default_convert_cache_size() -> usize {
+    32 * 1024 * 1024 // 32MB
+}
+
 fn default_max_rulesets() -> usize {
     64
 }
@@ -73,6 +86,14 @@ fn default_max_rules() -> usize {
     32768
 }
 
+fn default_autogroup_name() -> String {
+    "Proxy".to_string()
+}
+
+fn default_autogroup_min_nodes() -> usize {
+    2
+}
+
 fn default_max_download_size() -> i64 {
     32 * 1024 * 1024 // 32MB
 }
@@ -91,16 +112,18 @@ pub struct UserInfoSettings {
 pub struct CommonSettings {
     pub api_mode: bool,
     pub api_access_token: String,
+    pub gist_token: String,
+    pub gist_id: String,
     pub default_url: Vec<String>,
-    #[serde(default = "default_true")]
     pub enable_insert: bool,
     pub insert_url: Vec<String>,
-    #[serde(default = "default_true")]
     pub prepend_insert_url: bool,
     pub exclude_remarks: Vec<String>,
     pub include_remarks: Vec<String>,
     pub enable_filter: bool,
     pub filter_script: String,
+    pub filter_script_strict: bool,
+    pub geoip_db: String,
     pub default_external_config: String,
     #[serde(default = "default_empty_string")]
     pub base_path: String,
@@ -113,11 +136,12 @@ pub struct CommonSettings {
     pub loon_rule_base: String,
     pub sssub_rule_base: String,
     pub singbox_rule_base: String,
-    #[serde(default = "default_system")]
+    pub clash_dns: String,
+    pub clash_tun: String,
+    pub clash_sniffer: String,
+    pub clash_meta_overwrite: bool,
     pub proxy_config: String,
-    #[serde(default = "default_system")]
     pub proxy_ruleset: String,
-    #[serde(default = "default_none")]
     pub proxy_subscription: String,
     pub append_proxy_type: bool,
     pub reload_conf_on_request: bool,
@@ -133,13 +157,26 @@ pub struct NodePreferences {
     pub tls13_flag: Option<bool>,
     pub sort_flag: bool,
     pub sort_script: String,
+    pub sort_priority: Vec<String>,
+    pub duplicate_remark_format: String,
+    pub index_remark: String,
+    pub append_type_format: String,
     pub filter_deprecated_nodes: bool,
     #[serde(default = "default_true")]
     pub append_sub_userinfo: bool,
+    #[serde(default)]
+    pub append_info_nodes: bool,
+    pub group_test_url: Option<String>,
+    pub group_interval: Option<u32>,
+    pub group_timeout: Option<u32>,
+    pub group_tolerance: Option<u32>,
+    pub group_lazy: Option<bool>,
     #[serde(default = "default_true")]
     pub clash_use_new_field_name: bool,
     pub clash_proxies_style: String,
     pub clash_proxy_groups_style: String,
+    pub overwrite_original_proxies: bool,
+    pub overwrite_original_groups: bool,
     pub singbox_add_clash_modes: bool,
     pub rename_node: Vec<RegexMatchRuleInYaml>,
 }
@@ -148,26 +185,18 @@ pub struct NodePreferences {
 #[derive(Debug, Clone, Deserialize, Default)]
 #[serde(default)]
 pub struct ManagedConfigSettings {
-    #[serde(default = "default_true")]
     pub write_managed_config: bool,
-    #[serde(default = "default_listen_address")]
     pub managed_config_prefix: String,
-    #[serde(default = "default_update_interval")]
     pub config_update_interval: u32,
     pub config_update_strict: bool,
     pub quanx_device_id: String,
 }
 
-fn default_update_interval() -> u32 {
-    86400 // 24 hours
-}
-
 /// Surge external proxy settings
 #[derive(Debug, Clone, Deserialize, Default)]
 #[serde(default)]
 pub struct SurgeExternalProxySettings {
     pub surge_ssr_path: String,
-    #[serde(default = "default_true")]
     pub resolve_hostname: bool,
 }
 
@@ -176,11 +205,29 @@ pub struct SurgeExternalProxySettings {
 #[serde(default)]
 pub struct EmojiSettings {
     pub add_emoji: bool,
-    #[serde(default = "default_true")]
     pub remove_old_emoji: bool,
     pub rules: Vec<RegexMatchRuleInYaml>,
 }
 
+/// Groups generated by country, the `autogroup` option
+#[derive(Debug, Clone, Deserialize)]
+pub struct AutogroupSettings {
+    #[serde(default)]
+    pub enable: bool,
+    #[serde(default = "default_autogroup_name")]
+    pub name: String,
+    #[serde(default = "default_autogroup_min_nodes")]
+    pub min_nodes: usize,
+    #[serde(default)]
+    pub countries: Vec<RegexMatchRuleInYaml>,
+}
+
+impl Default for AutogroupSettings {
+    fn default() -> Self {
+        empty_section()
+    }
+}
+
 /// Ruleset settings
 #[derive(Debug, Clone, Deserialize, Default)]
 #[serde(default)]
@@ -233,6 +280,16 @@ pub struct AliasConfig {
     pub target: String,
 }
 
+/// Arguments for requests without `target` from a client, by User-Agent
+#[derive(Debug, Clone, Deserialize, Default)]
+#[serde(default)]
+pub struct UserAgentConfig {
+    /// Part of the User-Agent, case is ignored
+    pub ua: String,
+    /// Arguments such as `target=surge&ver=4`, empty to not recognize `ua`
+    pub args: String,
+}
+
 /// Server settings
 #[derive(Debug, Clone, Deserialize, Default)]
 #[serde(default)]
@@ -242,6 +299,26 @@ pub struct ServerSettings {
     #[serde(default = "default_listen_port")]
     pub port: u32,
     pub serve_file_root: String,
+    pub api_base_path: String,
+    pub unix_socket_mode: String,
+    pub tls_cert: String,
+    pub tls_key: String,
+    #[serde(default = "default_shutdown_timeout")]
+    pub shutdown_timeout: u32,
+    pub upload: UploadSettings,
+    pub trusted_proxies: Vec<String>,
+    pub watch_config: bool,
+}
+
+/// Where `upload=true` sends configs
+#[derive(Debug, Clone, Deserialize, Default)]
+#[serde(default)]
+pub struct UploadSettings {
+    /// `webdav`, or `gist` which is the default
+    pub kind: String,
+    pub url: String,
+    pub username: String,
+    pub password: String,
 }
 
 /// Advanced settings
@@ -261,6 +338,8 @@ pub struct AdvancedSettings {
     pub max_allowed_rules: usize,
     #[serde(default = "default_max_download_size")]
     pub max_allowed_download_size: i64,
+    #[serde(default = "default_max_redirects")]
+    pub max_redirects: u32,
     pub enable_cache: bool,
     #[serde(default = "default_cache_subscription")]
     pub cache_subscription: u32,
@@ -268,10 +347,17 @@ pub struct AdvancedSettings {
     pub cache_config: u32,
     #[serde(default = "default_cache_ruleset")]
     pub cache_ruleset: u32,
-    #[serde(default = "default_true")]
+    #[serde(default = "default_convert_cache_entries")]
+    pub convert_cache_entries: usize,
+    #[serde(default = "default_convert_cache_size")]
+    pub convert_cache_size: usize,
+    pub bypass_convert_cache: bool,
+    pub serve_cache_on_fetch_fail: bool,
     pub script_clean_context: bool,
     pub async_fetch_ruleset: bool,
     pub skip_failed_links: bool,
+    pub local_sub_dirs: Vec<String>,
+    pub clash_provider_files: bool,
 }
 
 /// Main YAML settings structure
@@ -280,12 +366,15 @@ pub struct AdvancedSettings {
 pub struct YamlSettings {
     pub common: CommonSettings,
     pub userinfo: UserInfoSettings,
+    #[serde(default = "empty_section")]
     pub node_pref: NodePreferences,
     pub managed_config: ManagedConfigSettings,
     pub surge_external_proxy: SurgeExternalProxySettings,
     pub emojis: EmojiSettings,
+    #[serde(default = "empty_section")]
+    pub autogroup: AutogroupSettings,
 
-    #[serde(alias = "ruleset")]
+    #[serde(alias = "ruleset", default = "empty_section")]
     pub rulesets: RulesetSettings,
 
     #[serde(alias = "proxy_group")]
@@ -294,9 +383,12 @@ pub struct YamlSettings {
     #[serde(deserialize_with = "deserialize_template_as_template_settings")]
     pub template: TemplateSettings,
     pub aliases: Vec<AliasConfig>,
-    #[serde(skip)]
+    pub user_agents: Vec<UserAgentConfig>,
+    pub target_defaults: HashMap<String, String>,
     pub tasks: Vec<TaskConfigInYaml>,
+    #[serde(default = "empty_section")]
     pub server: ServerSettings,
+    #[serde(default = "empty_section")]
     pub advanced: AdvancedSettings,
 
     // Extra fields not in the YAML but needed for settings
@@ -309,6 +401,8 @@ pub struct YamlSettings {
     #[serde(skip)]
     pub parsed_emoji_rules: RegexMatchConfigs,
     #[serde(skip)]
+    pub parsed_autogroup_countries: RegexMatchConfigs,
+    #[serde(skip)]
     pub parsed_proxy_group: ProxyGroupConfigs,
     #[serde(skip)]
     pub parsed_ruleset: RulesetConfigs,
@@ -381,6 +475,17 @@ impl YamlSettings {
         .await?;
         self.parsed_emoji_rules = RegexMatchConfigs::from_ini_with_delimiter(&emoji_rules, ",");
 
+        // read autogroup countries
+        let mut countries = self
+            .autogroup
+            .countries
+            .iter()
+            .map(|rule| rule.to_ini_with_delimiter(","))
+            .collect::<Vec<String>>();
+        import_items(&mut countries, false, proxy_config, &self.common.base_path).await?;
+        self.parsed_autogroup_countries =
+            RegexMatchConfigs::from_ini_with_delimiter(&countries, ",");
+
         // read rulesets
         let mut rulesets = self
             .rulesets
@@ -3,6 +3,7 @@ use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 use std::fmt;
 
+use crate::models::ruleset::{get_ruleset_type_from_url, parse_geo_rule};
 use crate::models::{
     cron::CronTaskConfig, BalanceStrategy, ProxyGroupConfig, ProxyGroupType, RegexMatchConfig,
     RulesetConfig,
@@ -24,7 +25,7 @@ pub struct RegexMatchRuleInToml {
     #[serde(rename = "match")]
     pub match_str: Option<String>,
 
-    #[serde(alias = "emoji")]
+    #[serde(alias = "emoji", alias = "name")]
     pub replace: Option<String>,
     pub script: Option<String>,
     pub import: Option<String>,
@@ -61,6 +62,9 @@ pub struct RulesetConfigInToml {
     #[serde(rename = "type")]
     pub ruleset_type: Option<String>,
     pub interval: Option<u32>,
+    pub behavior: Option<String>,
+    /// `geosite:<category>` and `geoip:<country>` options, comma separated
+    pub geo: Option<String>,
     pub import: Option<String>,
 }
 
@@ -76,22 +80,31 @@ impl ImportableInToml for RulesetConfigInToml {
 
 impl Into<RulesetConfig> for RulesetConfigInToml {
     fn into(self) -> RulesetConfig {
+        let url = self.ruleset.unwrap_or_default();
+        // `type` stands in for the prefix INI and YAML rulesets carry in the URL
+        let prefix = match self.ruleset_type.as_deref() {
+            _ if url.starts_with("[]") || get_ruleset_type_from_url(&url).is_some() => "",
+            Some("clash-domain") => "clash-domain:",
+            Some("clash-ipcidr") => "clash-ipcidr:",
+            Some("clash-classic") | Some("clash-classical") => "clash-classical:",
+            Some("quantumultx") | Some("quanx") => "quanx:",
+            _ => "",
+        };
         RulesetConfig {
-            url: self.ruleset.unwrap_or_default(),
+            url: format!("{}{}", prefix, url),
             group: self.group,
-            interval: self.interval.unwrap_or(300),
+            interval: self.interval.unwrap_or_default(),
+            behavior: self.behavior.and_then(|behavior| behavior.parse().ok()),
+            geo: self
+                .geo
+                .iter()
+                .flat_map(|geo| geo.split(','))
+                .filter_map(parse_geo_rule)
+                .collect(),
         }
     }
 }
 
-fn default_test_url() -> Option<String> {
-    Some("http://www.gstatic.com/generate_204".to_string())
-}
-
-fn default_interval() -> Option<u32> {
-    Some(300)
-}
-
 /// Proxy group configuration
 #[derive(Debug, Clone, Serialize, Deserialize, Default)]
 #[serde(default)]
@@ -101,14 +114,22 @@ pub struct ProxyGroupConfigInToml {
     pub group_type: String,
     pub strategy: Option<String>,
     pub rule: Vec<String>,
-    #[serde(default = "default_test_url")]
     pub url: Option<String>,
-    #[serde(default = "default_interval")]
     pub interval: Option<u32>,
     pub lazy: Option<bool>,
     pub tolerance: Option<u32>,
     pub timeout: Option<u32>,
     pub disable_udp: Option<bool>,
+    pub icon: Option<String>,
+    pub hidden: Option<bool>,
+    #[serde(alias = "include-all")]
+    pub include_all: Option<bool>,
+    #[serde(alias = "native-filter")]
+    pub native_filter: Option<bool>,
+    #[serde(alias = "exclude-filter")]
+    pub exclude_filter: Option<String>,
+    #[serde(alias = "exclude-type")]
+    pub exclude_type: Option<String>,
     pub import: Option<String>,
 }
 
@@ -142,48 +163,29 @@ impl Into<ProxyGroupConfig> for ProxyGroupConfigInToml {
             _ => BalanceStrategy::ConsistentHashing,
         };
 
-        // 创建基本的 ProxyGroupConfig
-        let mut config = ProxyGroupConfig {
+        // 创建基本的 ProxyGroupConfig，未设置的测试参数在生成时使用全局默认值
+        ProxyGroupConfig {
             name: self.name,
             group_type,
             proxies: self.rule,
             url: self.url.unwrap_or_default(),
-            interval: self.interval.unwrap_or(300),
-            tolerance: self.tolerance.unwrap_or(0),
-            timeout: self.timeout.unwrap_or(5),
-            lazy: self.lazy.unwrap_or(false),
+            interval: self.interval.unwrap_or_default(),
+            tolerance: self.tolerance.unwrap_or_default(),
+            timeout: self.timeout.unwrap_or_default(),
+            lazy: self.lazy,
             disable_udp: self.disable_udp.unwrap_or(false),
             strategy,
             // 添加缺失的字段
             persistent: false,
             evaluate_before_use: false,
             using_provider: Vec::new(),
-        };
-
-        // 根据不同的代理组类型设置特定属性
-        match config.group_type {
-            ProxyGroupType::URLTest | ProxyGroupType::Smart => {
-                // 这些类型需要 URL 和 interval
-                if config.url.is_empty() {
-                    config.url = "http://www.gstatic.com/generate_204".to_string();
-                }
-            }
-            ProxyGroupType::LoadBalance => {
-                // 负载均衡需要 URL、interval 和 strategy
-                if config.url.is_empty() {
-                    config.url = "http://www.gstatic.com/generate_204".to_string();
-                }
-            }
-            ProxyGroupType::Fallback => {
-                // 故障转移需要 URL 和 interval
-                if config.url.is_empty() {
-                    config.url = "http://www.gstatic.com/generate_204".to_string();
-                }
-            }
-            _ => {}
+            icon: self.icon.unwrap_or_default(),
+            hidden: self.hidden.unwrap_or(false),
+            include_all: self.include_all.unwrap_or(false),
+            native_filter: self.native_filter.unwrap_or(false),
+            exclude_filter: self.exclude_filter.unwrap_or_default(),
+            exclude_type: self.exclude_type.unwrap_or_default(),
         }
-
-        config
     }
 }
 
@@ -227,6 +229,12 @@ where
 {
     struct TemplateSettingsVisitor;
 
+    #[derive(Debug, Clone, Deserialize)]
+    struct TemplateGlobalsVariable {
+        key: String,
+        value: toml::Value,
+    }
+
     impl<'de> Visitor<'de> for TemplateSettingsVisitor {
         type Value = TemplateSettings;
 
@@ -240,10 +248,20 @@ where
         {
             let mut template_settings = TemplateSettings::default();
             while let Some(key) = map.next_key::<String>()? {
-                let value = map.next_value::<String>()?;
                 if key == "template_path" {
-                    template_settings.template_path = value.clone();
+                    template_settings.template_path = map.next_value::<String>()?;
+                } else if key == "globals" {
+                    // `[[template.globals]]` tables, as `globals` in YAML
+                    let globals = map.next_value::<Vec<TemplateGlobalsVariable>>()?;
+                    for item in globals {
+                        let value = match item.value {
+                            toml::Value::String(value) => value,
+                            value => value.to_string(),
+                        };
+                        template_settings.globals.insert(item.key, value);
+                    }
                 } else {
+                    let value = map.next_value::<String>()?;
                     template_settings.globals.insert(key, value);
                 }
             }
@@ -13,6 +13,7 @@ use super::settings::yaml_settings::TemplateSettings;
 pub struct RegexMatchRuleInYaml {
     #[serde(rename = "match")]
     pub match_str: Option<String>,
+    #[serde(alias = "emoji", alias = "name")]
     pub replace: Option<String>,
     pub script: Option<String>,
     pub import: Option<String>,
@@ -72,6 +73,12 @@ impl ToIni for RulesetConfigInYaml {
                 if let Some(interval) = self.interval {
                     result = format!("{},{}", result, interval);
                 }
+                if let Some(behavior) = &self.behavior {
+                    result = format!("{},{}", result, behavior);
+                }
+                if let Some(geo) = &self.geo {
+                    result = format!("{},{}", result, geo);
+                }
                 return result;
             }
         }
@@ -113,12 +120,20 @@ pub struct ProxyGroupConfigInYaml {
     #[serde(rename = "type")]
     pub group_type: String,
     pub rule: Vec<String>,
-    #[serde(default = "default_test_url")]
     pub url: Option<String>,
-    #[serde(default = "default_interval")]
     pub interval: Option<u32>,
     pub tolerance: Option<u32>,
     pub timeout: Option<u32>,
+    pub icon: Option<String>,
+    pub hidden: Option<bool>,
+    #[serde(alias = "include-all")]
+    pub include_all: Option<bool>,
+    #[serde(alias = "native-filter")]
+    pub native_filter: Option<bool>,
+    #[serde(alias = "exclude-filter")]
+    pub exclude_filter: Option<String>,
+    #[serde(alias = "exclude-type")]
+    pub exclude_type: Option<String>,
     pub import: Option<String>,
 }
 
@@ -134,6 +149,26 @@ impl ToIni for ProxyGroupConfigInYaml {
         // Create initial array with name and type
         let mut temp_array = vec![self.name.clone(), self.group_type.clone()];
 
+        // Stash and Clash.Meta extensions
+        if let Some(icon) = self.icon.as_deref().filter(|icon| !icon.is_empty()) {
+            temp_array.push(format!("icon={}", icon));
+        }
+        if let Some(hidden) = self.hidden {
+            temp_array.push(format!("hidden={}", hidden));
+        }
+        if let Some(include_all) = self.include_all {
+            temp_array.push(format!("include-all={}", include_all));
+        }
+        if let Some(native_filter) = self.native_filter {
+            temp_array.push(format!("native-filter={}", native_filter));
+        }
+        if let Some(exclude_filter) = self.exclude_filter.as_deref().filter(|f| !f.is_empty()) {
+            temp_array.push(format!("exclude-filter={}", exclude_filter));
+        }
+        if let Some(exclude_type) = self.exclude_type.as_deref().filter(|t| !t.is_empty()) {
+            temp_array.push(format!("exclude-type={}", exclude_type));
+        }
+
         // Add all rules
         for rule in &self.rule {
             temp_array.push(rule.clone());
@@ -141,7 +176,7 @@ impl ToIni for ProxyGroupConfigInYaml {
 
         // Check if we have enough elements based on group type
         match self.group_type.as_str() {
-            "select" => {
+            "select" | "relay" => {
                 if temp_array.len() < 3 {
                     return String::new();
                 }
@@ -156,15 +191,14 @@ impl ToIni for ProxyGroupConfigInYaml {
                     return String::new();
                 }
 
-                // Add url
-                temp_array.push(
-                    self.url
-                        .clone()
-                        .unwrap_or_else(|| "http://www.gstatic.com/generate_204".to_string()),
-                );
+                // Add url, left empty to use the configured default
+                temp_array.push(self.url.clone().unwrap_or_default());
 
                 // Add interval, timeout, tolerance as a combined string
-                let interval = self.interval.unwrap_or(300).to_string();
+                let interval = match self.interval {
+                    Some(i) => i.to_string(),
+                    None => String::new(),
+                };
                 let timeout = match self.timeout {
                     Some(t) => t.to_string(),
                     None => String::new(),
@@ -183,14 +217,6 @@ impl ToIni for ProxyGroupConfigInYaml {
     }
 }
 
-fn default_test_url() -> Option<String> {
-    Some("http://www.gstatic.com/generate_204".to_string())
-}
-
-fn default_interval() -> Option<u32> {
-    Some(300)
-}
-
 /// Task configuration
 #[derive(Debug, Clone, Deserialize, Default)]
 #[serde(default)]
@@ -210,6 +236,9 @@ pub struct RulesetConfigInYaml {
     pub ruleset: Option<String>,
     pub group: String,
     pub interval: Option<u32>,
+    pub behavior: Option<String>,
+    /// `geosite:<category>` and `geoip:<country>` options, comma separated
+    pub geo: Option<String>,
     pub import: Option<String>,
 }
 
@@ -2,4 +2,7 @@
 
 pub mod template_renderer;
 
-pub use template_renderer::{render_template, render_template_file, TemplateArgs};
+pub use template_renderer::{
+    render_template, render_template_as, render_template_file, template_error_message,
+    TemplateArgs, TemplateFormat,
+};
@@ -1,14 +1,20 @@
 use crate::api::SubconverterQuery;
+use crate::parser::infoparser::timestamp_to_date;
+use crate::utils::system::safe_system_time;
 use crate::utils::{file_exists, file_get_async};
-use crate::Settings;
 use log::{debug, error};
+use minijinja::value::ValueKind;
 use minijinja::{
-    context, escape_formatter, Environment, Error as JinjaError, ErrorKind, UndefinedBehavior,
-    Value,
+    context, escape_formatter, AutoEscape, Environment, Error as JinjaError, ErrorKind,
+    UndefinedBehavior, Value,
 };
 use serde::Serialize;
 use serde_json::{Map as JsonMap, Value as JsonValue};
 use std::collections::HashMap;
+use std::time::UNIX_EPOCH;
+
+/// Auto escape format of templates rendered as YAML
+const YAML_ESCAPE: AutoEscape = AutoEscape::Custom("yaml");
 
 /// Template arguments container
 #[derive(Debug, Clone, Default, Serialize)]
@@ -24,6 +30,21 @@ pub struct TemplateArgs {
 
     /// Node list variables
     pub node_list: HashMap<String, String>,
+
+    /// Managed config URL of the settings, `global.managed_prefix` and the
+    /// links of `getLink` start with it
+    pub managed_prefix: String,
+}
+
+/// How a template writes the strings a request supplied
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum TemplateFormat {
+    /// As they are
+    #[default]
+    Text,
+    /// As YAML scalars, quoted where a plain scalar would change the
+    /// structure of the document
+    Yaml,
 }
 
 /// Render a template with the given arguments
@@ -37,9 +58,24 @@ pub struct TemplateArgs {
 /// * `Ok(String)` - The rendered template
 /// * `Err(String)` - Error message if rendering fails
 pub fn render_template(
+    content: &str,
+    args: &TemplateArgs,
+    include_scope: &str,
+) -> Result<String, Box<dyn std::error::Error>> {
+    render_template_as(content, args, include_scope, TemplateFormat::Text)
+}
+
+/// Render a template whose output is read as `format`
+///
+/// The context holds `global` (the template variables of the settings and
+/// `managed_prefix`), `request` (the query, `tpl_*` parameters included),
+/// `local`, `node_list` and `now` (`timestamp`, `date` and `time` in UTC).
+/// Variables which are not defined render empty.
+pub fn render_template_as(
     content: &str,
     args: &TemplateArgs,
     _include_scope: &str,
+    format: TemplateFormat,
 ) -> Result<String, Box<dyn std::error::Error>> {
     // let env_lock = match TEMPLATE_ENV.lock() {
     //     Ok(env) => env,
@@ -52,8 +88,12 @@ pub fn render_template(
     let mut env = Environment::new();
 
     // Copy settings from global environment
-    env.set_formatter(escape_formatter);
+    env.set_formatter(format_value);
     env.set_undefined_behavior(UndefinedBehavior::Chainable);
+    env.set_auto_escape_callback(move |_| match format {
+        TemplateFormat::Text => AutoEscape::None,
+        TemplateFormat::Yaml => YAML_ESCAPE,
+    });
 
     // Add the same filters and functions
     env.add_filter("trim", filter_trim);
@@ -63,7 +103,10 @@ pub fn render_template(
     env.add_filter("replace", filter_replace);
     env.add_filter("find", filter_find);
 
-    env.add_function("getLink", fn_get_link);
+    let managed_prefix = args.managed_prefix.clone();
+    env.add_function("getLink", move |path: Value| {
+        Ok::<_, JinjaError>(format!("{}{}", managed_prefix, path))
+    });
     env.add_function("startsWith", fn_starts_with);
     env.add_function("endsWith", fn_ends_with);
     env.add_function("bool", fn_to_bool);
@@ -72,30 +115,39 @@ pub fn render_template(
     env.add_function("default", fn_default);
     // env.add_function("fetch", fn_web_get);
 
-    // Build context object
-    let global_vars = expand_dotted_vars(&args.global_vars);
-    let local_vars = expand_dotted_vars(&args.local_vars);
+    // Build context object, the variables of the settings are trusted and
+    // written as they are in any format
+    let mut global_vars = args.global_vars.clone();
+    global_vars
+        .entry("managed_prefix".to_string())
+        .or_insert_with(|| args.managed_prefix.clone());
+    let global_vars = trusted_value(&expand_dotted_vars(&global_vars));
+    let local_vars = trusted_value(&expand_dotted_vars(&args.local_vars));
 
     // Create full context with all variables
     let context = context!(
         global => global_vars,
-        request => args.request_params,
+        request => request_vars(&args.request_params),
         local => local_vars,
-        node_list => args.node_list
+        node_list => args.node_list,
+        now => now_vars()
     );
 
     debug!("Template context: {:?}", context);
 
     // Parse and render the template
     match env.template_from_str(content) {
-        Ok(template) => match template.render(context) {
-            Ok(result) => Ok(result),
-            Err(e) => {
-                let error_msg = format!("Template render failed! Reason: {}", e);
-                error!("{}", error_msg);
-                Err(Box::new(e))
+        Ok(template) => {
+            log_undefined_variables(&env, &template, &context);
+            match template.render(&context) {
+                Ok(result) => Ok(result),
+                Err(e) => {
+                    let error_msg = format!("Template render failed! Reason: {}", e);
+                    error!("{}", error_msg);
+                    Err(Box::new(e))
+                }
             }
-        },
+        }
         Err(e) => {
             let error_msg = format!("Failed to parse template: {}", e);
             error!("{}", error_msg);
@@ -104,6 +156,140 @@ pub fn render_template(
     }
 }
 
+/// One line description of a template failure with the line it happened at,
+/// e.g. `syntax error at line 3: unexpected end of input`
+pub fn template_error_message(error: &(dyn std::error::Error + 'static)) -> String {
+    let Some(error) = error.downcast_ref::<JinjaError>() else {
+        return error.to_string();
+    };
+    let mut message = error.kind().to_string();
+    if let Some(line) = error.line() {
+        message.push_str(&format!(" at line {}", line));
+    }
+    if let Some(detail) = error.detail() {
+        message.push_str(&format!(": {}", detail));
+    }
+    message
+}
+
+/// Writes values as [`escape_formatter`] does, in YAML templates strings of
+/// the request are written as YAML scalars
+fn format_value(
+    out: &mut minijinja::Output,
+    state: &minijinja::State,
+    value: &Value,
+) -> Result<(), JinjaError> {
+    if state.auto_escape() != YAML_ESCAPE {
+        return escape_formatter(out, state, value);
+    }
+    if value.is_undefined() || value.is_none() {
+        return Ok(());
+    }
+    match value.as_str() {
+        Some(s) if value.kind() == ValueKind::String && !value.is_safe() => {
+            out.write_str(&yaml_scalar(s))?
+        }
+        _ => write!(out, "{}", value)?,
+    }
+    Ok(())
+}
+
+/// `s` as a plain YAML scalar when that keeps its meaning and the structure
+/// around it, otherwise double quoted
+fn yaml_scalar(s: &str) -> String {
+    let plain = !s.is_empty()
+        && s.trim() == s
+        && !s.chars().any(|c| c.is_control())
+        && !s.starts_with(|c: char| "?:,[]{}#&*!|>'\"%@`".contains(c))
+        && !s.starts_with("- ")
+        && s != "-"
+        && !s.ends_with(':')
+        && !s.contains(": ")
+        && !s.contains(" #")
+        && !s.contains(|c: char| ",[]{}".contains(c));
+    if plain {
+        s.to_string()
+    } else {
+        serde_json::to_string(s).unwrap_or_default()
+    }
+}
+
+/// Strings of `json` marked safe, so they are written as they are whatever
+/// the format of the template
+fn trusted_value(json: &JsonValue) -> Value {
+    match json {
+        JsonValue::Object(map) => map
+            .iter()
+            .map(|(key, value)| (key.clone(), trusted_value(value)))
+            .collect(),
+        JsonValue::Array(items) => Value::from(items.iter().map(trusted_value).collect::<Vec<_>>()),
+        JsonValue::String(s) => Value::from_safe_string(s.clone()),
+        other => Value::from_serialize(other),
+    }
+}
+
+/// The query as templates see it: options left unset are undefined, `tpl_*`
+/// parameters are added and control characters are dropped from every value,
+/// so a parameter can not start a new line of the config
+fn request_vars(query: &SubconverterQuery) -> JsonValue {
+    fn sanitize(value: &mut JsonValue) {
+        match value {
+            JsonValue::String(s) => s.retain(|c| !c.is_control()),
+            JsonValue::Array(items) => items.iter_mut().for_each(sanitize),
+            JsonValue::Object(map) => {
+                map.retain(|_, value| !value.is_null());
+                map.values_mut().for_each(sanitize);
+            }
+            _ => {}
+        }
+    }
+
+    let mut request = serde_json::to_value(query).unwrap_or_default();
+    if let JsonValue::Object(map) = &mut request {
+        for (key, value) in &query.template_params {
+            map.insert(key.clone(), JsonValue::String(value.clone()));
+        }
+    }
+    sanitize(&mut request);
+    request
+}
+
+/// The current time in UTC
+fn now_vars() -> Value {
+    let timestamp = safe_system_time()
+        .duration_since(UNIX_EPOCH)
+        .map(|elapsed| elapsed.as_secs())
+        .unwrap_or_default();
+    let seconds = timestamp % 86400;
+    context!(
+        timestamp => timestamp,
+        date => timestamp_to_date(timestamp),
+        time => format!(
+            "{:02}:{:02}:{:02}",
+            seconds / 3600,
+            seconds / 60 % 60,
+            seconds % 60
+        )
+    )
+}
+
+/// Logs the variables `template` uses which are neither in `context` nor
+/// functions, they render empty
+fn log_undefined_variables(env: &Environment, template: &minijinja::Template, context: &Value) {
+    for name in template.undeclared_variables(true) {
+        let root = name.split('.').next().unwrap_or_default();
+        if env.globals().any(|(global, _)| global == root) {
+            continue;
+        }
+        let value = name.split('.').fold(context.clone(), |value, part| {
+            value.get_attr(part).unwrap_or(Value::UNDEFINED)
+        });
+        if value.is_undefined() {
+            debug!("Template variable {} is undefined and renders empty", name);
+        }
+    }
+}
+
 fn expand_dotted_vars(vars: &HashMap<String, String>) -> JsonValue {
     fn insert_path(root: &mut JsonMap<String, JsonValue>, key: &str, value: &str) {
         let mut parts = key.split('.').filter(|p| !p.is_empty()).peekable();
@@ -242,12 +428,6 @@ fn filter_find(value: Value, pattern: Value) -> Result<bool, JinjaError> {
 
 // Function implementations
 
-fn fn_get_link(path: Value) -> Result<String, JinjaError> {
-    let path_str = path.to_string();
-    let settings = Settings::current();
-    Ok(format!("{}{}", settings.managed_config_prefix, path_str))
-}
-
 fn fn_starts_with(s: Value, prefix: Value) -> Result<bool, JinjaError> {
     let s_str = s.to_string();
     let prefix_str = prefix.to_string();
@@ -269,10 +449,99 @@ fn fn_to_string(n: Value) -> Result<String, JinjaError> {
     Ok(n.to_string())
 }
 
-fn fn_default(value: Value, default: Value) -> Result<String, JinjaError> {
+fn fn_default(value: Value, default: Value) -> Result<Value, JinjaError> {
     if value.is_undefined() || value.is_none() {
-        Ok(default.to_string())
+        Ok(default)
     } else {
-        Ok(value.to_string())
+        Ok(value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args() -> TemplateArgs {
+        let mut request_params = SubconverterQuery {
+            target: Some("clash".to_string()),
+            ..Default::default()
+        };
+        request_params.add_template_params([
+            ("tpl_name", "HK: 01 #fast"),
+            ("tpl_port", "7890"),
+            ("tpl_inject", "rule\nmode: global"),
+        ]);
+        TemplateArgs {
+            global_vars: HashMap::from([(
+                "clash.external_controller".to_string(),
+                ":9090".to_string(),
+            )]),
+            request_params,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_yaml_template_quotes_request_values() {
+        let content = "name: {{ request.tpl_name }}\n\
+                       port: {{ request.tpl_port }}\n\
+                       mode: {{ request.tpl_inject }}\n\
+                       external-controller: {{ global.clash.external_controller }}\n\
+                       emoji: {{ request.emoji }}{{ local.missing.value }}\n\
+                       target: {{ request.target }}\n";
+        let rendered = render_template_as(content, &args(), "", TemplateFormat::Yaml).unwrap();
+        assert_eq!(
+            rendered,
+            "name: \"HK: 01 #fast\"\n\
+             port: 7890\n\
+             mode: \"rulemode: global\"\n\
+             external-controller: :9090\n\
+             emoji: \n\
+             target: clash"
+        );
+        let yaml: serde_yaml::Value = serde_yaml::from_str(&rendered).unwrap();
+        assert_eq!(yaml["mode"], "rulemode: global");
+        assert_eq!(yaml["port"], 7890);
+
+        let rendered = render_template(content, &args(), "").unwrap();
+        assert!(rendered.starts_with("name: HK: 01 #fast\n"), "{}", rendered);
+    }
+
+    #[test]
+    fn test_template_errors_name_the_line() {
+        let error = render_template("mode: rule\n{% if %}\n", &args(), "").unwrap_err();
+        assert!(
+            template_error_message(&*error).starts_with("syntax error at line 2: "),
+            "{}",
+            template_error_message(&*error)
+        );
+    }
+
+    #[test]
+    fn test_yaml_scalar() {
+        for plain in ["HK 01", "7890", "true", "-1", "a:b", "🇭🇰 香港"] {
+            assert_eq!(yaml_scalar(plain), plain);
+        }
+        for (quoted, expected) in [
+            ("", r#""""#),
+            ("a: b", r#""a: b""#),
+            ("#tag", "\"#tag\""),
+            ("- item", r#""- item""#),
+            ("[a]", r#""[a]""#),
+            ("say \"hi\", bye", r#""say \"hi\", bye""#),
+            (" padded", r#"" padded""#),
+        ] {
+            assert_eq!(yaml_scalar(quoted), expected);
+        }
+    }
+
+    #[test]
+    fn test_now_is_in_context() {
+        let rendered = render_template("{{ now.date }} {{ now.time }}", &args(), "").unwrap();
+        let (date, time) = rendered.split_once(' ').unwrap();
+        assert_eq!(date.len(), 10);
+        assert_eq!(time.len(), 8);
+        assert_eq!(&date[4..5], "-");
+        assert_eq!(&time[2..3], ":");
     }
 }
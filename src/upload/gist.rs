@@ -24,6 +24,10 @@ fn build_gist_data(name: &str, content: &str) -> String {
 
 /// Uploads content to a GitHub Gist based on configuration in gistconf.ini.
 ///
+/// The `gist_token` and `gist_id` settings take precedence over the token and
+/// id in gistconf.ini, which is created when missing. The token falls back to
+/// the GITHUB_TOKEN environment variable.
+///
 /// # Arguments
 /// * `name` - The logical name of the content (used for looking up path in
 ///   ini).
@@ -32,6 +36,7 @@ fn build_gist_data(name: &str, content: &str) -> String {
 /// * `content` - The content to upload.
 /// * `write_manage_url` - Whether to prepend a #!MANAGED-CONFIG line if
 ///   updating an existing Gist.
+/// * `settings` - Settings holding the Gist token, id and proxy.
 ///
 /// # Returns
 /// * `Ok(String)` on success, containing the Gist raw URL.
@@ -41,27 +46,39 @@ pub async fn upload_gist(
     mut path: String,
     mut content: String,
     write_manage_url: bool,
+    settings: &Settings,
 ) -> Result<String, String> {
     let ini_path = "gistconf.ini";
-
-    if !file_exists(ini_path).await {
-        log::error!("gistconf.ini not found. Skipping...");
-        return Err("gistconf.ini not found".to_string());
-    }
+    let settings_token = settings.gist_token.clone();
+    let settings_id = settings.gist_id.clone();
+    let proxy = settings.proxy_config.clone();
 
     let mut ini = IniReader::new();
-    if let Err(e) = ini.parse_file(ini_path).await {
-        let err_msg = format!("Failed to parse gistconf.ini: {}", e);
-        log::error!("{}", err_msg);
-        return Err(err_msg);
-    }
+    if file_exists(ini_path).await {
+        if let Err(e) = ini.parse_file(ini_path).await {
+            let err_msg = format!("Failed to parse gistconf.ini: {}", e);
+            log::error!("{}", err_msg);
+            return Err(err_msg);
+        }
 
-    if ini.enter_section("common").is_err() {
-        log::error!("gistconf.ini has incorrect format ([common] section missing). Skipping...");
-        return Err("gistconf.ini has incorrect format".to_string());
+        if ini.enter_section("common").is_err() {
+            log::error!(
+                "gistconf.ini has incorrect format ([common] section missing). Skipping..."
+            );
+            return Err("gistconf.ini has incorrect format".to_string());
+        }
+    } else if settings_token.is_empty() {
+        log::error!("gistconf.ini not found and no gist_token is set. Skipping...");
+        return Err("gistconf.ini not found".to_string());
     }
 
-    let mut token = ini.get_current("token");
+    // A token from the settings is not written to gistconf.ini
+    let ini_token = ini.get_current("token");
+    let mut token = if settings_token.is_empty() {
+        ini_token.clone()
+    } else {
+        settings_token
+    };
     if token.is_empty() {
         log::info!(
             "Token not found in gistconf.ini, checking GITHUB_TOKEN environment variable..."
@@ -79,7 +96,11 @@ pub async fn upload_gist(
         }
     }
 
-    let mut id = ini.get_current("id");
+    let mut id = if settings_id.is_empty() {
+        ini.get_current("id")
+    } else {
+        settings_id
+    };
     let mut username = ini.get_current("username");
 
     if path.is_empty() {
@@ -91,7 +112,7 @@ pub async fn upload_gist(
         }
     }
 
-    let proxy_config = parse_proxy(&Settings::current().proxy_config);
+    let proxy_config = parse_proxy(&proxy);
     let mut headers = HashMap::new();
     headers.insert(
         CaseInsensitiveString::new("Authorization"),
@@ -199,17 +220,11 @@ pub async fn upload_gist(
     }
 
     // Update gistconf.ini
-    // Re-enter common section as it was left via get_current before
-    if ini.enter_section("common").is_ok() {
-        ini.erase_section(); // Erase the items within the section
-        ini.set_current("token", &token).ok(); // Pass as slice
-        ini.set_current("id", &id).ok(); // Pass as slice
-        ini.set_current("username", &username).ok(); // Pass as slice
-    } else {
-        // This shouldn't happen if the initial enter_section worked, but handle
-        // defensively
-        log::error!("Failed to re-enter [common] section to update ini.");
-    }
+    ini.set_current_section("common");
+    ini.erase_section(); // Erase the items within the section
+    ini.set_current("token", &ini_token).ok();
+    ini.set_current("id", &id).ok();
+    ini.set_current("username", &username).ok();
 
     // Set the new section based on the actual path used
     ini.set_current_section(&path);
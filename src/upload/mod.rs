@@ -1 +1,3 @@
 pub mod gist;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod webdav;
@@ -0,0 +1,224 @@
+use crate::utils::base64::base64_encode;
+use crate::utils::http::{parse_proxy, web_request_async, ProxyConfig};
+use crate::utils::lock_recovering;
+use crate::utils::url::url_encode;
+use case_insensitive_string::CaseInsensitiveString;
+use once_cell::sync::Lazy;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+/// One lock per remote file, uploads to the same file wait for each other
+static PATH_LOCKS: Lazy<Mutex<HashMap<String, Arc<tokio::sync::Mutex<()>>>>> =
+    Lazy::new(|| Mutex::new(HashMap::new()));
+
+/// Share of the lock of one remote file, the entry is removed with the last
+/// share so the map only holds files being uploaded
+struct PathLock {
+    url: String,
+    lock: Option<Arc<tokio::sync::Mutex<()>>>,
+}
+
+impl PathLock {
+    fn new(url: &str) -> Self {
+        let mut locks = lock_recovering(&PATH_LOCKS);
+        let lock = Arc::clone(locks.entry(url.to_string()).or_default());
+        PathLock {
+            url: url.to_string(),
+            lock: Some(lock),
+        }
+    }
+}
+
+impl Drop for PathLock {
+    fn drop(&mut self) {
+        // Shares are only taken with the map locked, so no one can pick this
+        // entry up between the check and the removal
+        let mut locks = lock_recovering(&PATH_LOCKS);
+        self.lock = None;
+        if locks
+            .get(&self.url)
+            .is_some_and(|lock| Arc::strong_count(lock) == 1)
+        {
+            locks.remove(&self.url);
+        }
+    }
+}
+
+/// Where to upload to, from the `upload_*` settings
+#[derive(Debug, Clone, Default)]
+pub struct WebDavTarget {
+    /// Collection the files are written under, e.g.
+    /// `https://cloud.example.com/remote.php/dav/files/me/subconverter`
+    pub url: String,
+    pub username: String,
+    pub password: String,
+    /// Proxy for the requests, same format as `proxy_config`
+    pub proxy: String,
+}
+
+/// URL of `path` under the collection `base`, every segment is encoded
+fn remote_url(base: &str, path: &str) -> String {
+    let segments: Vec<String> = path
+        .split('/')
+        .filter(|segment| !segment.is_empty())
+        .map(url_encode)
+        .collect();
+    format!("{}/{}", base.trim_end_matches('/'), segments.join("/"))
+}
+
+/// URLs of the collections holding `path` under `base`, outermost first
+fn parent_collections(base: &str, path: &str) -> Vec<String> {
+    let segments: Vec<&str> = path
+        .split('/')
+        .filter(|segment| !segment.is_empty())
+        .collect();
+    (1..segments.len())
+        .map(|depth| format!("{}/", remote_url(base, &segments[..depth].join("/"))))
+        .collect()
+}
+
+/// Uploads `content` to `path` on a WebDAV server such as Nextcloud
+///
+/// Collections missing on the way to `path` are created with `MKCOL` when the
+/// first `PUT` is refused. Uploads to the same file are serialized so their
+/// writes never interleave.
+///
+/// # Returns
+/// * `Ok(String)` on success, containing the URL of the uploaded file.
+/// * `Err(String)` on failure, containing an error message.
+pub async fn upload_webdav(
+    target: &WebDavTarget,
+    path: &str,
+    content: String,
+) -> Result<String, String> {
+    if target.url.is_empty() {
+        return Err("No upload_url is set for WebDAV".to_string());
+    }
+    if path.trim_matches('/').is_empty() {
+        return Err("No path to upload to".to_string());
+    }
+    // Dot segments would be resolved by the server, out of the collection
+    if path
+        .split('/')
+        .any(|segment| segment == "." || segment == "..")
+    {
+        return Err(format!("Invalid upload path: {}", path));
+    }
+
+    let url = remote_url(&target.url, path);
+    let path_lock = PathLock::new(&url);
+    let _guard = path_lock.lock.as_ref().unwrap().lock().await;
+
+    let proxy_config = parse_proxy(&target.proxy);
+    let mut headers = HashMap::new();
+    if !target.username.is_empty() || !target.password.is_empty() {
+        headers.insert(
+            CaseInsensitiveString::new("Authorization"),
+            format!(
+                "Basic {}",
+                base64_encode(&format!("{}:{}", target.username, target.password))
+            ),
+        );
+    }
+
+    let status = put(&url, &content, &proxy_config, &headers).await?;
+    // 409 Conflict is what RFC 4918 answers for a missing parent collection,
+    // some servers answer 404
+    let status = if status == 409 || status == 404 {
+        for collection in parent_collections(&target.url, path) {
+            let response =
+                web_request_async("MKCOL", &collection, None, &proxy_config, Some(&headers))
+                    .await
+                    .map_err(|e| format!("WebDAV MKCOL {} failed: {}", collection, e))?;
+            // 405 Method Not Allowed means the collection exists already
+            if !(200..300).contains(&response.status) && response.status != 405 {
+                return Err(format!(
+                    "WebDAV MKCOL {} failed with status {}",
+                    collection, response.status
+                ));
+            }
+        }
+        put(&url, &content, &proxy_config, &headers).await?
+    } else {
+        status
+    };
+
+    if !(200..300).contains(&status) {
+        return Err(format!("WebDAV PUT {} failed with status {}", url, status));
+    }
+    log::info!("Uploaded config to WebDAV: {}", url);
+    Ok(url)
+}
+
+async fn put(
+    url: &str,
+    content: &str,
+    proxy_config: &ProxyConfig,
+    headers: &HashMap<CaseInsensitiveString, String>,
+) -> Result<u16, String> {
+    web_request_async(
+        "PUT",
+        url,
+        Some(content.to_string()),
+        proxy_config,
+        Some(headers),
+    )
+    .await
+    .map(|response| response.status)
+    .map_err(|e| format!("WebDAV PUT {} failed: {}", url, e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_remote_paths() {
+        let base = "https://cloud.example.com/dav/files/me/";
+        assert_eq!(
+            remote_url(base, "/profiles/my clash.yaml"),
+            "https://cloud.example.com/dav/files/me/profiles/my%20clash.yaml"
+        );
+        assert_eq!(
+            parent_collections(base, "a/b/clash.yaml"),
+            [
+                "https://cloud.example.com/dav/files/me/a/",
+                "https://cloud.example.com/dav/files/me/a/b/"
+            ]
+        );
+        assert!(parent_collections(base, "clash.yaml").is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_dot_segments_are_refused() {
+        let target = WebDavTarget {
+            url: "https://cloud.example.com/dav/files/me".to_string(),
+            ..Default::default()
+        };
+        for path in [
+            "../other/clash.yaml",
+            "a/./clash.yaml",
+            "a/../../clash.yaml",
+        ] {
+            let error = upload_webdav(&target, path, String::new())
+                .await
+                .unwrap_err();
+            assert!(error.starts_with("Invalid upload path"), "{}", error);
+        }
+    }
+
+    #[test]
+    fn test_path_locks_are_removed_with_the_last_share() {
+        let url = "https://cloud.example.com/dav/files/me/locks.yaml";
+        let first = PathLock::new(url);
+        let second = PathLock::new(url);
+        assert!(Arc::ptr_eq(
+            first.lock.as_ref().unwrap(),
+            second.lock.as_ref().unwrap()
+        ));
+        drop(first);
+        assert!(lock_recovering(&PATH_LOCKS).contains_key(url));
+        drop(second);
+        assert!(!lock_recovering(&PATH_LOCKS).contains_key(url));
+    }
+}
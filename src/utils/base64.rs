@@ -1,3 +1,4 @@
+use crate::utils::encoding::decode_text;
 use base64::{
     engine::{general_purpose, DecodePaddingMode},
     Engine as _,
@@ -11,14 +12,15 @@ pub fn base64_encode(input: &str) -> String {
 /// Decodes a Base64 string to its original form.
 ///
 /// # Arguments
-/// * `input` - The Base64 encoded string.
+/// * `input` - The Base64 encoded string, padding is optional.
 /// * `accept_urlsafe` - A boolean indicating whether to accept URL-safe Base64 encoding.
 ///
 /// # Returns
-/// The decoded string, or an empty string if the input is invalid.
+/// The decoded string, or `None` if the input is invalid.
 fn base64_decode(input: &str, accept_urlsafe: bool) -> Option<String> {
     let purpose_config = general_purpose::GeneralPurposeConfig::new()
-        .with_decode_padding_mode(DecodePaddingMode::Indifferent);
+        .with_decode_padding_mode(DecodePaddingMode::Indifferent)
+        .with_decode_allow_trailing_bits(true);
     let engine = general_purpose::GeneralPurpose::new(
         if accept_urlsafe {
             &base64::alphabet::URL_SAFE
@@ -28,7 +30,7 @@ fn base64_decode(input: &str, accept_urlsafe: bool) -> Option<String> {
         purpose_config,
     );
     match engine.decode(input) {
-        Ok(decoded) => Some(String::from_utf8_lossy(&decoded).to_string()),
+        Ok(decoded) => Some(decode_text(&decoded, None)),
         Err(e) => {
             log::warn!("Failed to decode base64: {}", e);
             None
@@ -36,6 +38,19 @@ fn base64_decode(input: &str, accept_urlsafe: bool) -> Option<String> {
     }
 }
 
+/// Whether `input`, without whitespace, can be Base64 of either alphabet:
+/// only Base64 characters, padding only at the end and a length that is
+/// possible once the padding is dropped
+fn is_base64(input: &str) -> bool {
+    let unpadded = input.trim_end_matches('=');
+    !unpadded.is_empty()
+        && input.len() - unpadded.len() <= 2
+        && unpadded.len() % 4 != 1
+        && unpadded
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '+' | '/' | '-' | '_'))
+}
+
 /// Reverses a URL-safe Base64 string to standard Base64 format.
 pub fn url_safe_base64_reverse(input: &str) -> String {
     input.replace('-', "+").replace('_', "/")
@@ -46,15 +61,125 @@ pub fn url_safe_base64_apply(input: &str) -> String {
     input.replace('+', "-").replace('/', "_").replace('=', "") // Remove padding
 }
 
-/// Decodes a URL-safe Base64 string to its original form.
+/// Decodes a URL-safe or standard Base64 string to its original form.
+///
+/// Surrounding whitespace and line breaks are ignored and padding is optional.
+/// Content which is not Base64 is returned unchanged, spaces inside the
+/// content make it plain text.
 pub fn url_safe_base64_decode(input: &str) -> String {
-    match base64_decode(&url_safe_base64_reverse(input), false) {
+    let compact: String = input
+        .trim()
+        .chars()
+        .filter(|c| !matches!(c, '\r' | '\n' | '\t'))
+        .collect();
+    if !is_base64(&compact) {
+        return input.to_string();
+    }
+    match base64_decode(&url_safe_base64_reverse(&compact), false) {
         Some(decoded) => decoded,
         None => input.to_string(),
     }
 }
 
+/// Decodes a URL-safe or standard Base64 field to UTF-8 text.
+///
+/// Unlike [`url_safe_base64_decode`] nothing is guessed: `None` is returned
+/// when `input` is no canonical Base64, where the bits after the last byte
+/// are zero, or when the bytes are no UTF-8. Padding is optional.
+pub fn base64_decode_strict(input: &str) -> Option<String> {
+    let engine = general_purpose::GeneralPurpose::new(
+        &base64::alphabet::URL_SAFE,
+        general_purpose::GeneralPurposeConfig::new()
+            .with_decode_padding_mode(DecodePaddingMode::Indifferent),
+    );
+    let bytes = engine.decode(url_safe_base64_apply(input.trim())).ok()?;
+    String::from_utf8(bytes).ok()
+}
+
 /// Encodes a string to URL-safe Base64 format.
 pub fn url_safe_base64_encode(input: &str) -> String {
     url_safe_base64_apply(&base64_encode(input))
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Deterministic inputs of every length up to 64 bytes for the round trips
+    fn samples() -> impl Iterator<Item = String> {
+        let mut state: u32 = 0x2545_f491;
+        (0..256).map(move |i| {
+            (0..i % 64)
+                .map(|_| {
+                    state = state.wrapping_mul(1_103_515_245).wrapping_add(12_345);
+                    char::from(b' ' + (state >> 16) as u8 % 95)
+                })
+                .collect()
+        })
+    }
+
+    #[test]
+    fn test_decode_inverts_both_alphabets() {
+        for sample in samples().filter(|sample| !sample.is_empty()) {
+            let standard = base64_encode(&sample);
+            assert_eq!(url_safe_base64_decode(&standard), sample);
+            assert_eq!(
+                url_safe_base64_decode(standard.trim_end_matches('=')),
+                sample
+            );
+            assert_eq!(
+                url_safe_base64_decode(&url_safe_base64_encode(&sample)),
+                sample
+            );
+        }
+    }
+
+    #[test]
+    fn test_decode_messy_subscriptions() {
+        let links = "ss://YWVzLTI1Ni1nY206cGFzcw@1.2.3.4:8388#Test\ntrojan://pw@5.6.7.8:443";
+        let encoded = base64_encode(links);
+        let wrapped: Vec<String> = encoded
+            .as_bytes()
+            .chunks(76)
+            .map(|chunk| String::from_utf8_lossy(chunk).to_string())
+            .collect();
+
+        let messy = [
+            // MIME style line breaks, with CRLF and a trailing newline
+            wrapped.join("\n"),
+            wrapped.join("\r\n") + "\r\n",
+            // Padding dropped, URL-safe characters swapped in
+            url_safe_base64_apply(&encoded),
+            format!("  {}\n", encoded.trim_end_matches('=')),
+        ];
+        for sample in &messy {
+            assert_eq!(url_safe_base64_decode(sample), links, "{:?}", sample);
+        }
+    }
+
+    #[test]
+    fn test_strict_decode_refuses_garbage() {
+        assert_eq!(base64_decode_strict("dGVzdA==").as_deref(), Some("test"));
+        assert_eq!(base64_decode_strict("dGVzdA").as_deref(), Some("test"));
+        assert_eq!(base64_decode_strict("").as_deref(), Some(""));
+        // Trailing bits set, bytes which are no UTF-8, no Base64 at all
+        assert_eq!(base64_decode_strict("dGVzdB"), None);
+        assert_eq!(base64_decode_strict("dGVzdA__"), None);
+        assert_eq!(base64_decode_strict("test"), None);
+        assert_eq!(base64_decode_strict("1234:abcd"), None);
+    }
+
+    #[test]
+    fn test_decode_leaves_plain_text_alone() {
+        for plain in [
+            "ss://YWVzLTI1Ni1nY206cGFzcw@1.2.3.4:8388#Test",
+            "proxies:\n  - {name: a, type: ss}",
+            "abcde",
+            "ab==cd",
+            "hello world",
+            "",
+        ] {
+            assert_eq!(url_safe_base64_decode(plain), plain);
+        }
+    }
+}
@@ -67,3 +67,54 @@ where
 
     deserializer.deserialize_any(StringOrNumberVisitor)
 }
+
+/// Deserialize an ALPN list given either as a sequence or as a comma separated
+/// string, see [`parse_alpn`](crate::models::parse_alpn)
+pub fn deserialize_alpn<'de, D>(deserializer: D) -> Result<Option<Vec<String>>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    struct AlpnVisitor;
+
+    impl<'de> Visitor<'de> for AlpnVisitor {
+        type Value = Option<Vec<String>>;
+
+        fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+            formatter.write_str("string or sequence of strings")
+        }
+
+        fn visit_str<E>(self, value: &str) -> Result<Self::Value, E>
+        where
+            E: de::Error,
+        {
+            Ok(crate::models::parse_alpn([value]))
+        }
+
+        fn visit_seq<A>(self, mut seq: A) -> Result<Self::Value, A::Error>
+        where
+            A: de::SeqAccess<'de>,
+        {
+            let mut values: Vec<String> = Vec::new();
+            while let Some(value) = seq.next_element::<Option<String>>()? {
+                values.extend(value);
+            }
+            Ok(crate::models::parse_alpn(values))
+        }
+
+        fn visit_none<E>(self) -> Result<Self::Value, E>
+        where
+            E: de::Error,
+        {
+            Ok(None)
+        }
+
+        fn visit_unit<E>(self) -> Result<Self::Value, E>
+        where
+            E: de::Error,
+        {
+            Ok(None)
+        }
+    }
+
+    deserializer.deserialize_any(AlpnVisitor)
+}
@@ -0,0 +1,69 @@
+//! Decoding of upstream content which is not always UTF-8, some providers
+//! serve GBK
+
+use encoding_rs::{Encoding, GB18030, UTF_8};
+
+/// Decode `bytes` fetched from a subscription, ruleset or config to UTF-8
+///
+/// The encoding is taken from a BOM, then from the charset of `content_type`.
+/// Without either, bytes which are not UTF-8 are read as GB18030, a superset
+/// of GBK, when they are valid GB18030. Anything else is decoded lossily.
+pub fn decode_text(bytes: &[u8], content_type: Option<&str>) -> String {
+    if let Some((encoding, bom_length)) = Encoding::for_bom(bytes) {
+        return encoding
+            .decode_without_bom_handling(&bytes[bom_length..])
+            .0
+            .into_owned();
+    }
+
+    if let Some(encoding) = content_type.and_then(charset_of) {
+        // A UTF-8 charset is not trusted over what the bytes look like
+        if encoding != UTF_8 {
+            return encoding.decode_without_bom_handling(bytes).0.into_owned();
+        }
+    }
+
+    match std::str::from_utf8(bytes) {
+        Ok(text) => text.to_string(),
+        Err(_) => match GB18030.decode_without_bom_handling_and_without_replacement(bytes) {
+            Some(text) => text.into_owned(),
+            None => String::from_utf8_lossy(bytes).into_owned(),
+        },
+    }
+}
+
+/// Encoding named by the `charset` parameter of a Content-Type header
+fn charset_of(content_type: &str) -> Option<&'static Encoding> {
+    content_type.split(';').skip(1).find_map(|param| {
+        let (key, value) = param.split_once('=')?;
+        if !key.trim().eq_ignore_ascii_case("charset") {
+            return None;
+        }
+        Encoding::for_label(value.trim().trim_matches('"').as_bytes())
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_decode_text_detection() {
+        let remark = "香港 01 节点";
+        let (gbk, _, _) = encoding_rs::GBK.encode(remark);
+
+        assert_eq!(decode_text(remark.as_bytes(), None), remark);
+        assert_eq!(decode_text(&gbk, None), remark);
+        assert_eq!(decode_text(&gbk, Some("text/plain; charset=GBK")), remark);
+        assert_eq!(
+            decode_text(&gbk, Some("text/plain; charset=\"gb2312\"")),
+            remark
+        );
+        // Mislabelled as UTF-8
+        assert_eq!(decode_text(&gbk, Some("text/plain; charset=utf-8")), remark);
+
+        let mut with_bom = vec![0xEF, 0xBB, 0xBF];
+        with_bom.extend_from_slice(remark.as_bytes());
+        assert_eq!(decode_text(&with_bom, None), remark);
+    }
+}
@@ -4,12 +4,16 @@ use crate::utils::http::{parse_proxy, web_get_async};
 // Import platform-specific implementations
 #[cfg(not(target_arch = "wasm32"))]
 mod platform {
-    pub use crate::utils::file_std::{copy_file, file_exists, file_get_async, read_file_async};
+    pub use crate::utils::file_std::{
+        canonicalize_path, copy_file, file_exists, file_get_async, read_file_async,
+    };
 }
 
 #[cfg(target_arch = "wasm32")]
 mod platform {
-    pub use crate::utils::file_wasm::{copy_file, file_exists, file_get_async, read_file_async};
+    pub use crate::utils::file_wasm::{
+        canonicalize_path, copy_file, file_exists, file_get_async, read_file_async,
+    };
 }
 
 // Re-export platform-specific implementations
@@ -21,14 +25,15 @@ pub use platform::*;
 ///
 /// # Arguments
 /// * `path` - Path to the file or URL to load
+/// * `settings` - Settings holding the proxy URLs are fetched through
 ///
 /// # Returns
 /// * `Ok(String)` - The content
 /// * `Err(String)` - Error message if loading failed
-pub async fn load_content_async(path: &str) -> Result<String, String> {
+pub async fn load_content_async(path: &str, settings: &Settings) -> Result<String, String> {
     if path.starts_with("http://") || path.starts_with("https://") {
         // It's a URL, use HTTP client
-        match web_get_async(path, &parse_proxy(&Settings::current().proxy_config), None).await {
+        match web_get_async(path, &parse_proxy(&settings.proxy_config), None).await {
             Ok(response) => Ok(response.body),
             Err(e) => Err(format!("Failed to read file from URL: {}", e)),
         }
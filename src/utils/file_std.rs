@@ -1,13 +1,14 @@
+use crate::utils::encoding::decode_text;
 use std::fs;
 use std::io::{self, Read};
-use std::path::Path;
+use std::path::{Path, PathBuf};
 
 /// Read a file into a string
 pub fn read_file(path: &str) -> Result<String, io::Error> {
     let mut file = fs::File::open(path)?;
-    let mut contents = String::new();
-    file.read_to_string(&mut contents)?;
-    Ok(contents)
+    let mut contents = Vec::new();
+    file.read_to_end(&mut contents)?;
+    Ok(decode_text(&contents, None))
 }
 
 /// Async version of read_file that reads a file into a string asynchronously
@@ -19,7 +20,7 @@ pub fn read_file(path: &str) -> Result<String, io::Error> {
 /// * `Ok(String)` - The file contents
 /// * `Err(io::Error)` - If the file can't be read
 pub async fn read_file_async(path: &str) -> Result<String, io::Error> {
-    tokio::fs::read_to_string(path).await
+    Ok(decode_text(&tokio::fs::read(path).await?, None))
 }
 
 /// Check if a file exists
@@ -27,6 +28,11 @@ pub async fn file_exists(path: &str) -> bool {
     tokio::fs::metadata(path).await.is_ok()
 }
 
+/// Absolute path of an existing file with every symlink resolved
+pub async fn canonicalize_path(path: &str) -> io::Result<PathBuf> {
+    tokio::fs::canonicalize(path).await
+}
+
 /// Read the contents of a file as a string
 ///
 /// # Arguments
@@ -55,7 +61,7 @@ pub fn file_get<P: AsRef<Path>>(path: P, base_path: Option<&str>) -> io::Result<
             }
         }
     }
-    fs::read_to_string(path)
+    Ok(decode_text(&fs::read(path)?, None))
 }
 
 /// Copy a file from source to destination
@@ -109,5 +115,5 @@ pub async fn file_get_async<P: AsRef<Path>>(
             }
         }
     }
-    tokio::fs::read_to_string(path).await
+    Ok(decode_text(&tokio::fs::read(path).await?, None))
 }
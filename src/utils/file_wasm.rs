@@ -1,8 +1,9 @@
+use crate::utils::encoding::decode_text;
 use crate::vfs::{vercel_kv_vfs::VercelKvVfs, VfsError};
 use log::{debug, error, info, warn}; // Import log macros
 use once_cell::sync::Lazy;
 use std::io;
-use std::path::Path;
+use std::path::{Component, Path, PathBuf};
 use tokio::sync::Mutex; // Use Mutex if VFS needs mutable access, or if init is async
                         // Import the trait
 use crate::vfs::VirtualFileSystem;
@@ -55,10 +56,7 @@ pub async fn read_file(path: &str) -> Result<String, io::Error> {
     let vfs = get_vfs().await?;
     let bytes_result = vfs.read_file(path).await;
     match bytes_result {
-        Ok(bytes) => String::from_utf8(bytes).map_err(|e| {
-            error!("Failed to convert bytes to UTF-8 for {}: {}", path, e);
-            io::Error::new(io::ErrorKind::InvalidData, e)
-        }),
+        Ok(bytes) => Ok(decode_text(&bytes, None)),
         Err(e) => {
             warn!("VFS read_file failed for {}: {:?}", path, e);
             Err(map_vfs_error(e))
@@ -102,6 +100,29 @@ pub async fn file_exists(path: &str) -> bool {
     }
 }
 
+/// Path of an existing file with `.` and `..` resolved, the VFS has no
+/// symlinks to follow
+pub async fn canonicalize_path(path: &str) -> io::Result<PathBuf> {
+    let mut normalized = PathBuf::new();
+    for component in Path::new(path).components() {
+        match component {
+            Component::ParentDir => {
+                normalized.pop();
+            }
+            Component::CurDir => {}
+            other => normalized.push(other),
+        }
+    }
+    let normalized_str = normalized.to_string_lossy();
+    if !file_exists(&normalized_str).await {
+        return Err(io::Error::new(
+            io::ErrorKind::NotFound,
+            format!("{} does not exist", normalized_str),
+        ));
+    }
+    Ok(normalized)
+}
+
 /// Read the contents of a file as a string (async)
 ///
 /// # Arguments
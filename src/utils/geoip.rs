@@ -0,0 +1,242 @@
+//! Country of nodes looked up by their server in a GeoIP database
+//!
+//! Remarks like `Node-01` name no country, so with a `geoip_db` (a MaxMind DB
+//! country database such as GeoLite2-Country) the server of every node is
+//! located instead. Hostnames are resolved first, at most
+//! [`MAX_CONCURRENT_LOOKUPS`] at once and each within [`LOOKUP_TIMEOUT_MS`], so
+//! an unreachable DNS costs the conversion no more than that. The countries
+//! are cached by host across requests.
+
+use crate::Proxy;
+
+/// Hostnames resolved at the same time
+pub const MAX_CONCURRENT_LOOKUPS: usize = 16;
+
+/// Time to resolve one hostname, in milliseconds
+pub const LOOKUP_TIMEOUT_MS: u64 = 1_500;
+
+/// How long the country of a host is kept, in seconds
+pub const CACHE_TTL_SECS: u64 = 3_600;
+
+/// How long a host which could not be located is left alone, in seconds
+pub const FAILED_CACHE_TTL_SECS: u64 = 300;
+
+#[cfg(all(feature = "geoip", not(target_arch = "wasm32")))]
+mod lookup {
+    use super::*;
+    use futures::stream::{self, StreamExt};
+    use log::{debug, warn};
+    use maxminddb::{geoip2, Reader};
+    use once_cell::sync::Lazy;
+    use std::collections::HashMap;
+    use std::net::IpAddr;
+    use std::sync::{Arc, Mutex};
+    use std::time::{Duration, Instant};
+
+    type Database = Arc<Reader<Vec<u8>>>;
+
+    /// A country with the time it was looked up, `None` when the host could
+    /// not be resolved or is not in the database
+    type CachedCountry = (Option<String>, Instant);
+
+    /// Countries by host
+    static COUNTRIES: Lazy<Mutex<HashMap<String, CachedCountry>>> = Lazy::new(Default::default);
+
+    /// The database opened last and its path
+    static DATABASE: Lazy<Mutex<Option<(String, Database)>>> = Lazy::new(Default::default);
+
+    pub async fn locate_nodes(nodes: &mut [Proxy], db_path: &str) -> Result<(), String> {
+        let mut hosts: Vec<&str> = nodes
+            .iter()
+            .filter(|node| !node.info_node && !node.hostname.is_empty())
+            .map(|node| node.hostname.as_str())
+            .collect();
+        hosts.sort_unstable();
+        hosts.dedup();
+
+        let mut found = cached_countries(&hosts);
+        let missing: Vec<String> = hosts
+            .iter()
+            .filter(|host| !found.contains_key(**host))
+            .map(|host| host.to_string())
+            .collect();
+        if !missing.is_empty() {
+            let database = open_database(db_path)?;
+            let answers: Vec<(String, Option<String>)> = stream::iter(missing)
+                .map(|host| {
+                    let database = database.clone();
+                    async move {
+                        let country = match resolve(&host).await {
+                            Some(ip) => country_of(&database, ip),
+                            None => None,
+                        };
+                        (host, country)
+                    }
+                })
+                .buffer_unordered(MAX_CONCURRENT_LOOKUPS)
+                .collect()
+                .await;
+            store_countries(&answers);
+            found.extend(answers);
+        }
+
+        for node in nodes.iter_mut().filter(|node| !node.info_node) {
+            if let Some(Some(country)) = found.get(&node.hostname) {
+                node.country = country.clone();
+            }
+        }
+        Ok(())
+    }
+
+    /// The countries of `hosts` which are cached and not expired yet
+    fn cached_countries(hosts: &[&str]) -> HashMap<String, Option<String>> {
+        let Ok(cache) = COUNTRIES.lock() else {
+            return HashMap::new();
+        };
+        hosts
+            .iter()
+            .filter_map(|host| {
+                let (country, stored) = cache.get(*host)?;
+                let ttl = if country.is_some() {
+                    CACHE_TTL_SECS
+                } else {
+                    FAILED_CACHE_TTL_SECS
+                };
+                (stored.elapsed() < Duration::from_secs(ttl))
+                    .then(|| (host.to_string(), country.clone()))
+            })
+            .collect()
+    }
+
+    /// Caches the countries of hosts, dropping the expired ones
+    pub(super) fn store_countries(answers: &[(String, Option<String>)]) {
+        let Ok(mut cache) = COUNTRIES.lock() else {
+            return;
+        };
+        cache.retain(|_, (_, stored)| stored.elapsed() < Duration::from_secs(CACHE_TTL_SECS));
+        let now = Instant::now();
+        for (host, country) in answers {
+            cache.insert(host.clone(), (country.clone(), now));
+        }
+    }
+
+    /// The database at `path`, opened again only when the path changes
+    fn open_database(path: &str) -> Result<Database, String> {
+        let mut database = DATABASE
+            .lock()
+            .map_err(|e| format!("Failed to lock GeoIP database: {}", e))?;
+        if let Some((opened, reader)) = database.as_ref() {
+            if opened == path {
+                return Ok(reader.clone());
+            }
+        }
+        let reader = Reader::open_readfile(path)
+            .map(Arc::new)
+            .map_err(|e| format!("Failed to open GeoIP database {}: {}", path, e))?;
+        *database = Some((path.to_string(), reader.clone()));
+        Ok(reader)
+    }
+
+    /// The address of `host`, resolving hostnames within [`LOOKUP_TIMEOUT_MS`]
+    pub(super) async fn resolve(host: &str) -> Option<IpAddr> {
+        if let Ok(ip) = host.trim_start_matches('[').trim_end_matches(']').parse() {
+            return Some(ip);
+        }
+        let lookup = tokio::net::lookup_host((host, 0));
+        match tokio::time::timeout(Duration::from_millis(LOOKUP_TIMEOUT_MS), lookup).await {
+            Ok(Ok(mut addresses)) => addresses.next().map(|address| address.ip()),
+            Ok(Err(e)) => {
+                debug!("Failed to resolve {}: {}", host, e);
+                None
+            }
+            Err(_) => {
+                warn!("Resolving {} timed out", host);
+                None
+            }
+        }
+    }
+
+    /// The ISO country code of `ip` in the database
+    fn country_of(database: &Reader<Vec<u8>>, ip: IpAddr) -> Option<String> {
+        let record: geoip2::Country = database.lookup(ip).ok()?;
+        record
+            .country
+            .and_then(|country| country.iso_code)
+            .map(str::to_string)
+    }
+}
+
+/// Sets the country of every node from the `db_path` database
+///
+/// Nodes whose server can not be resolved or is not in the database keep an
+/// empty country, so do info nodes.
+///
+/// # Returns
+/// * `Ok(())` - The nodes which could be located have their country
+/// * `Err(String)` - The database could not be opened
+#[cfg(all(feature = "geoip", not(target_arch = "wasm32")))]
+pub async fn locate_nodes(nodes: &mut [Proxy], db_path: &str) -> Result<(), String> {
+    lookup::locate_nodes(nodes, db_path).await
+}
+
+/// GeoIP lookups need the `geoip` feature on native targets
+#[cfg(not(all(feature = "geoip", not(target_arch = "wasm32"))))]
+pub async fn locate_nodes(_nodes: &mut [Proxy], _db_path: &str) -> Result<(), String> {
+    Err(
+        "GeoIP is not supported in this build, please enable geoip feature in cargo build"
+            .to_string(),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn node(hostname: &str) -> Proxy {
+        Proxy {
+            remark: "Node-01".to_string(),
+            hostname: hostname.to_string(),
+            ..Default::default()
+        }
+    }
+
+    #[tokio::test]
+    #[cfg(all(feature = "geoip", not(target_arch = "wasm32")))]
+    async fn test_cached_countries_need_no_database() {
+        lookup::store_countries(&[
+            ("hk.geoip.test".to_string(), Some("HK".to_string())),
+            ("nowhere.geoip.test".to_string(), None),
+        ]);
+        let mut nodes = vec![node("hk.geoip.test"), node("nowhere.geoip.test")];
+        locate_nodes(&mut nodes, "missing.mmdb").await.unwrap();
+        assert_eq!(nodes[0].country, "HK");
+        assert_eq!(nodes[1].country, "");
+
+        let mut nodes = vec![node("203.0.113.7")];
+        assert!(locate_nodes(&mut nodes, "missing.mmdb").await.is_err());
+        assert_eq!(nodes[0].country, "");
+    }
+
+    #[tokio::test]
+    #[cfg(all(feature = "geoip", not(target_arch = "wasm32")))]
+    async fn test_addresses_are_not_resolved() {
+        assert_eq!(
+            lookup::resolve("203.0.113.7").await,
+            Some("203.0.113.7".parse().unwrap())
+        );
+        assert_eq!(
+            lookup::resolve("[2001:db8::1]").await,
+            Some("2001:db8::1".parse().unwrap())
+        );
+    }
+
+    #[tokio::test]
+    #[cfg(not(feature = "geoip"))]
+    async fn test_geoip_needs_the_feature() {
+        let mut nodes = vec![node("203.0.113.7")];
+        assert!(locate_nodes(&mut nodes, "GeoLite2-Country.mmdb")
+            .await
+            .is_err());
+        assert_eq!(nodes[0].country, "");
+    }
+}
@@ -6,7 +6,8 @@ use std::collections::HashMap;
 mod platform {
     pub use crate::utils::http_std::{
         get_sub_info_from_header, get_sub_info_from_response, parse_proxy, web_get, web_get_async,
-        web_patch_async, web_post_async, HttpError, HttpResponse, ProxyConfig,
+        web_get_with_redirects_async, web_patch_async, web_post_async, web_request_async,
+        HttpError, HttpResponse, ProxyConfig,
     };
 }
 
@@ -14,7 +15,8 @@ mod platform {
 mod platform {
     pub use crate::utils::http_wasm::{
         get_sub_info_from_header, get_sub_info_from_response, parse_proxy, web_get, web_get_async,
-        web_patch_async, web_post_async, HttpError, HttpResponse, ProxyConfig,
+        web_get_with_redirects_async, web_patch_async, web_post_async, HttpError, HttpResponse,
+        ProxyConfig,
     };
 }
 
@@ -1,3 +1,4 @@
+use crate::utils::encoding::decode_text;
 use crate::utils::system::get_system_proxy;
 use awc::Client;
 use case_insensitive_string::CaseInsensitiveString;
@@ -106,6 +107,52 @@ pub async fn web_get_async(
 
     let client = client_builder.finish();
 
+    send_get(client, url, headers).await
+}
+
+/// Makes an HTTP GET request following at most `max_redirects` redirects
+///
+/// # Arguments
+/// * `url` - The URL to request
+/// * `proxy_config` - Proxy configuration
+/// * `headers` - Optional custom headers
+/// * `max_redirects` - Redirects to follow, none with 0
+///
+/// # Returns
+/// * `Ok(HttpResponse)` - The response with status, body, and headers
+/// * `Err(HttpError)` - Error details if the request failed or was redirected
+///   more often
+pub async fn web_get_with_redirects_async(
+    url: &str,
+    _proxy_config: &ProxyConfig,
+    headers: Option<&HashMap<CaseInsensitiveString, String>>,
+    max_redirects: u8,
+) -> Result<HttpResponse, HttpError> {
+    let client_builder = Client::builder().timeout(Duration::from_secs(DEFAULT_TIMEOUT));
+    let client_builder = if max_redirects == 0 {
+        client_builder.disable_redirects()
+    } else {
+        client_builder.max_redirects(max_redirects)
+    };
+
+    let response = send_get(client_builder.finish(), url, headers).await?;
+    // The client answers with the redirect it did not follow
+    if (300..400).contains(&response.status) && response.status != 304 {
+        return Err(HttpError {
+            message: format!("Redirected more than {} times", max_redirects),
+            status: Some(response.status),
+        });
+    }
+    Ok(response)
+}
+
+/// Sends a GET request with `client`, the body is decoded as its content type
+/// says
+async fn send_get(
+    client: Client,
+    url: &str,
+    headers: Option<&HashMap<CaseInsensitiveString, String>>,
+) -> Result<HttpResponse, HttpError> {
     // Build request with headers if specified
     let mut client_request = client
         .get(url)
@@ -142,7 +189,13 @@ pub async fn web_get_async(
     match response.body().await {
         Ok(body) => Ok(HttpResponse {
             status,
-            body: String::from_utf8(body.to_vec()).unwrap(),
+            body: decode_text(
+                &body,
+                resp_headers
+                    .iter()
+                    .find(|(key, _)| key.eq_ignore_ascii_case("content-type"))
+                    .map(|(_, value)| value.as_str()),
+            ),
             headers: resp_headers,
         }),
         Err(e) => Err(HttpError {
@@ -424,3 +477,70 @@ pub async fn web_patch_async(
         }),
     }
 }
+
+/// Makes an HTTP request with any method, e.g. the `PUT` and `MKCOL` of WebDAV
+///
+/// # Arguments
+/// * `method` - The request method
+/// * `url` - The URL to request
+/// * `data` - The request body data, none for an empty body
+/// * `proxy_config` - Proxy configuration
+/// * `headers` - Optional custom headers
+///
+/// # Returns
+/// * `Ok(HttpResponse)` - The response with status, body, and headers
+/// * `Err(HttpError)` - Error details if the request failed
+pub async fn web_request_async(
+    method: &str,
+    url: &str,
+    data: Option<String>,
+    _proxy_config: &ProxyConfig,
+    headers: Option<&HashMap<CaseInsensitiveString, String>>,
+) -> Result<HttpResponse, HttpError> {
+    let method = awc::http::Method::from_bytes(method.as_bytes()).map_err(|e| HttpError {
+        message: format!("Invalid request method {}: {}", method, e),
+        status: None,
+    })?;
+    let client = Client::builder()
+        .timeout(Duration::from_secs(DEFAULT_TIMEOUT))
+        .finish();
+
+    let mut client_request = client
+        .request(method.clone(), url)
+        .insert_header(("User-Agent", "subconverter-rs"));
+    if let Some(custom_headers) = headers {
+        for (key, value) in custom_headers {
+            client_request = client_request.insert_header((key.to_string(), value.to_string()));
+        }
+    }
+
+    let sent = match data {
+        Some(data) => client_request.send_body(data).await,
+        None => client_request.send().await,
+    };
+    let mut response = sent.map_err(|e| HttpError {
+        message: format!("Failed to send {} request: {}", method, e),
+        status: None,
+    })?;
+
+    let status = response.status().as_u16();
+
+    let mut resp_headers = HashMap::new();
+    for (key, value) in response.headers() {
+        if let Ok(v) = value.to_str() {
+            resp_headers.insert(key.to_string(), v.to_string());
+        }
+    }
+
+    match response.body().limit(10_000_000).await {
+        Ok(body) => Ok(HttpResponse {
+            status,
+            body: String::from_utf8_lossy(&body).into_owned(),
+            headers: resp_headers,
+        }),
+        Err(e) => Err(HttpError {
+            message: format!("Failed to read {} response body: {}", method, e),
+            status: Some(status),
+        }),
+    }
+}
@@ -25,6 +25,93 @@ extern "C" {
     fn js_response_status(response: &JsValue) -> js_sys::Promise;
 }
 
+#[cfg(feature = "wasm")]
+thread_local! {
+    static FETCH_HANDLER: std::cell::RefCell<Option<js_sys::Function>> =
+        const { std::cell::RefCell::new(None) };
+}
+
+/// Route every request through `handler(url, headers)` instead of `fetch`,
+/// `None` goes back to `fetch`. The handler returns the body as a string or an
+/// object with `status`, `body` and `headers`, either one directly or as a
+/// Promise.
+#[cfg(feature = "wasm")]
+#[wasm_bindgen]
+pub fn set_fetch_handler(handler: Option<js_sys::Function>) {
+    FETCH_HANDLER.with(|current| *current.borrow_mut() = handler);
+}
+
+#[cfg(feature = "wasm")]
+async fn fetch_with_handler(
+    handler: &js_sys::Function,
+    url: &str,
+    headers: Option<&HashMap<CaseInsensitiveString, String>>,
+) -> Result<HttpResponse, HttpError> {
+    let to_error = |e: JsValue| HttpError {
+        message: format!("Fetch handler failed: {:?}", e),
+        status: None,
+    };
+
+    let headers_obj = Object::new();
+    for (key, value) in headers.into_iter().flatten() {
+        js_sys::Reflect::set(
+            &headers_obj,
+            &JsValue::from_str(key.to_string().as_str()),
+            &JsValue::from_str(value),
+        )
+        .map_err(to_error)?;
+    }
+
+    let result = handler
+        .call2(&JsValue::NULL, &JsValue::from_str(url), &headers_obj)
+        .map_err(to_error)?;
+    let result = JsFuture::from(js_sys::Promise::resolve(&result))
+        .await
+        .map_err(to_error)?;
+
+    if let Some(body) = result.as_string() {
+        return Ok(HttpResponse {
+            status: 200,
+            body,
+            headers: HashMap::new(),
+        });
+    }
+
+    let get = |key: &str| js_sys::Reflect::get(&result, &JsValue::from_str(key));
+    let status = get("status")
+        .ok()
+        .and_then(|status| status.as_f64())
+        .map(|status| status as u16)
+        .unwrap_or(200);
+    let body = get("body")
+        .ok()
+        .and_then(|body| body.as_string())
+        .ok_or_else(|| HttpError {
+            message: "Fetch handler returned no body".to_string(),
+            status: Some(status),
+        })?;
+    let mut resp_headers = HashMap::new();
+    if let Ok(headers) = get("headers") {
+        if headers.is_object() {
+            let entries: Array = Object::entries(&Object::from(headers)).into();
+            for entry in entries.iter() {
+                let entry: Array = entry.into();
+                if let (Some(key), Some(value)) =
+                    (entry.get(0).as_string(), entry.get(1).as_string())
+                {
+                    resp_headers.insert(key, value);
+                }
+            }
+        }
+    }
+
+    Ok(HttpResponse {
+        status,
+        body,
+        headers: resp_headers,
+    })
+}
+
 #[derive(Debug, Clone)]
 pub struct ProxyConfig {
     pub proxy: Option<String>,
@@ -102,6 +189,11 @@ pub async fn web_get_async(
     _proxy_config: &ProxyConfig,
     headers: Option<&HashMap<CaseInsensitiveString, String>>,
 ) -> Result<HttpResponse, HttpError> {
+    #[cfg(feature = "wasm")]
+    if let Some(handler) = FETCH_HANDLER.with(|handler| handler.borrow().clone()) {
+        return fetch_with_handler(&handler, url, headers).await;
+    }
+
     // In WASM environment, we use the fetch API
     // Note: Proxy configuration is not supported in WASM environment
     #[allow(unused_mut)]
@@ -348,6 +440,17 @@ pub async fn web_get_async(
     }
 }
 
+/// Makes an HTTP GET request, redirects are followed by the fetch API or the
+/// fetch handler with their own limit
+pub async fn web_get_with_redirects_async(
+    url: &str,
+    proxy_config: &ProxyConfig,
+    headers: Option<&HashMap<CaseInsensitiveString, String>>,
+    _max_redirects: u8,
+) -> Result<HttpResponse, HttpError> {
+    web_get_async(url, proxy_config, headers).await
+}
+
 /// Synchronous version of web_get_async that uses tokio runtime to run the
 /// async function
 ///
@@ -72,6 +72,32 @@ pub struct IniReader {
     isolated_items_section: String,
     /// Store isolated lines (lines before any section)
     pub store_isolated_line: bool,
+    /// Cut `;` and `#` comments following a value, off for templates whose
+    /// lines are passed on to clients
+    pub strip_inline_comments: bool,
+}
+
+// `IniReaderError` holds an `io::Error` and cannot be cloned, a copy starts
+// without a last error
+impl Clone for IniReader {
+    fn clone(&self) -> Self {
+        IniReader {
+            content: self.content.clone(),
+            parsed: self.parsed,
+            current_section: self.current_section.clone(),
+            exclude_sections: self.exclude_sections.clone(),
+            include_sections: self.include_sections.clone(),
+            direct_save_sections: self.direct_save_sections.clone(),
+            section_order: self.section_order.clone(),
+            last_error: IniReaderError::None,
+            store_any_line: self.store_any_line,
+            allow_dup_section_titles: self.allow_dup_section_titles,
+            keep_empty_section: self.keep_empty_section,
+            isolated_items_section: self.isolated_items_section.clone(),
+            store_isolated_line: self.store_isolated_line,
+            strip_inline_comments: self.strip_inline_comments,
+        }
+    }
 }
 
 impl Default for IniReader {
@@ -97,6 +123,7 @@ impl IniReader {
             keep_empty_section: true,
             isolated_items_section: String::new(),
             store_isolated_line: false,
+            strip_inline_comments: true,
         }
     }
 
@@ -177,6 +204,19 @@ impl IniReader {
         s.trim().to_string()
     }
 
+    /// Cut a `;` or `#` comment following a value, the marker must come after
+    /// whitespace so that URLs with fragments and plugin options survive
+    fn strip_inline_comment(value: &str) -> &str {
+        let mut previous = ' ';
+        for (index, c) in value.char_indices() {
+            if (c == ';' || c == '#') && previous.is_whitespace() && index > 0 {
+                return value[..index].trim();
+            }
+            previous = c;
+        }
+        value.trim()
+    }
+
     /// Process escape characters in a string
     fn process_escape_char(s: &mut String) {
         // Replace escape sequences with actual characters
@@ -219,11 +259,7 @@ impl IniReader {
         }
 
         // Remove UTF-8 BOM if present
-        let content = if content.starts_with("\u{FEFF}") {
-            &content[3..]
-        } else {
-            content
-        };
+        let content = content.strip_prefix('\u{FEFF}').unwrap_or(content);
 
         let mut in_excluded_section = false;
         let mut in_direct_save_section = false;
@@ -241,9 +277,10 @@ impl IniReader {
             in_isolated_section = true;
         }
 
-        // Process each line
-        for line in content.lines() {
-            let line = Self::trim_whitespace(line);
+        // Process each line, files edited on Windows or old Macs end lines
+        // with CRLF or a bare CR
+        for line in content.split(['\r', '\n']) {
+            let mut line = Self::trim_whitespace(line);
 
             // Skip empty lines and comments
             if line.is_empty()
@@ -254,7 +291,9 @@ impl IniReader {
                 continue;
             }
 
-            let mut line = line.to_string();
+            if line.starts_with('[') && self.strip_inline_comments {
+                line = Self::strip_inline_comment(&line).to_string();
+            }
 
             // Process escape characters
             Self::process_escape_char(&mut line);
@@ -308,11 +347,12 @@ impl IniReader {
                 // Handle key=value pairs
                 else if let Some(pos) = pos_equal {
                     let item_name = line[0..pos].trim().to_string();
-                    let item_value = if pos + 1 < line.len() {
-                        line[pos + 1..].trim().to_string()
+                    let item_value = if self.strip_inline_comments {
+                        Self::strip_inline_comment(&line[pos + 1..])
                     } else {
-                        String::new()
+                        line[pos + 1..].trim()
                     };
+                    let item_value = item_value.to_string();
 
                     item_group.push((item_name, item_value));
                 }
@@ -392,6 +432,23 @@ impl IniReader {
         &self.section_order
     }
 
+    /// Remove a section and its items
+    pub fn remove_section(&mut self, section: &str) {
+        self.content.remove(section);
+        self.section_order.retain(|name| name != section);
+    }
+
+    /// Sort sections by their position in `order`, sections not listed keep
+    /// their relative order after the listed ones
+    pub fn sort_sections(&mut self, order: &[&str]) {
+        self.section_order.sort_by_key(|name| {
+            order
+                .iter()
+                .position(|section| section == name)
+                .unwrap_or(order.len())
+        });
+    }
+
     /// Set the current section
     pub fn set_current_section(&mut self, section: &str) {
         self.current_section = section.to_string();
@@ -709,3 +766,61 @@ impl IniReader {
         self.set(&self.current_section.clone(), "{NONAME}", item_val)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_windows_file() {
+        let content = "\u{FEFF}[Profile] ; saved by hand\r\n\
+                       target=clash\r\n\
+                       url=https://example.com/sub?token=abc#main ; comment\r\n\
+                       # a comment line\r\n\
+                       exclude=(?i)expire #no expiry nodes\r\n\
+                       [Other]\rkey=value\r";
+        let mut ini = IniReader::new();
+        ini.parse(content).unwrap();
+        assert_eq!(ini.get_section_names(), ["Profile", "Other"]);
+        assert_eq!(
+            ini.get_items("Profile").unwrap(),
+            [
+                ("target".to_string(), "clash".to_string()),
+                (
+                    "url".to_string(),
+                    "https://example.com/sub?token=abc#main".to_string()
+                ),
+                ("exclude".to_string(), "(?i)expire".to_string()),
+            ]
+        );
+        assert_eq!(ini.get("Other", "key"), "value");
+
+        let mut template = IniReader::new();
+        template.store_any_line = true;
+        template.strip_inline_comments = false;
+        template
+            .parse("[Proxy Group]\r\nProxy = select, HK #2, JP\r\n")
+            .unwrap();
+        assert_eq!(template.get("Proxy Group", "Proxy"), "select, HK #2, JP");
+    }
+
+    #[test]
+    fn test_sort_and_remove_sections() {
+        let mut ini = IniReader::new();
+        ini.parse("[Script]\na=1\n[Extra]\nb=2\n[Rule]\nc=3\n[General]\nd=4\n")
+            .unwrap();
+        ini.sort_sections(&["General", "Rule", "Script"]);
+        assert_eq!(
+            ini.get_section_names(),
+            ["General", "Rule", "Script", "Extra"]
+        );
+
+        ini.remove_section("Rule");
+        assert_eq!(ini.get_section_names(), ["General", "Script", "Extra"]);
+        assert!(!ini.section_exist("Rule"));
+        assert_eq!(
+            ini.to_string(),
+            "[General]\nd=4\n\n[Script]\na=1\n\n[Extra]\nb=2\n\n"
+        );
+    }
+}
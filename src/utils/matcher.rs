@@ -1,4 +1,5 @@
 use crate::models::{Proxy, ProxyType};
+use crate::utils::regexp::{cached_regex, normalize_replacement};
 use lazy_static::lazy_static;
 use regex::Regex;
 use std::collections::HashMap;
@@ -16,6 +17,7 @@ lazy_static! {
         Regex::new(r"^!!(?:UDPSUPPORT)=(.+?)(?:!!(.*))?$").unwrap();
     static ref SECURITY_REGEX: Regex = Regex::new(r"^!!(?:SECURITY)=(.+?)(?:!!(.*))?$").unwrap();
     static ref REMARKS_REGEX: Regex = Regex::new(r"^!!(?:REMARKS)=(.+?)(?:!!(.*))?$").unwrap();
+    static ref TAG_REGEX: Regex = Regex::new(r"^!!(?:TAG)=(.+?)(?:!!(.*))?$").unwrap();
     static ref PROXY_TYPES: HashMap<ProxyType, &'static str> = {
         let mut m = HashMap::new();
         m.insert(ProxyType::Shadowsocks, "SS");
@@ -43,7 +45,8 @@ lazy_static! {
 /// Supported special rules:
 /// - !!GROUP=<group_pattern> - Matches node's group against pattern
 /// - !!GROUPID=<id_range> - Matches node's group ID against range
-/// - !!INSERT=<id_range> - Like GROUPID but negates direction
+/// - !!INSERT=<id_range> - Matches the insert URL index of inserted nodes,
+///   counting from 1
 /// - !!TYPE=<type_pattern> - Matches node's proxy type against pattern
 /// - !!PORT=<port_range> - Matches node's port against range
 /// - !!SERVER=<server_pattern> - Matches node's hostname against pattern
@@ -51,6 +54,7 @@ lazy_static! {
 /// - !!UDPSUPPORT=<support_pattern> - Matches node's UDP support status
 /// - !!SECURITY=<security_pattern> - Matches node's security features
 /// - !!REMARKS=<remarks_pattern> - Matches node's remark against pattern
+/// - !!TAG=<tag_pattern> - Matches the `tag:` of node's subscription URL
 ///
 /// # Arguments
 /// * `rule` - The rule to match
@@ -69,11 +73,17 @@ pub fn apply_matcher(rule: &str, real_rule: &mut String, node: &Proxy) -> bool {
             return reg_find(&node.group, target);
         }
     } else if rule.starts_with("!!GROUPID=") || rule.starts_with("!!INSERT=") {
-        let dir = if rule.starts_with("!!INSERT=") { -1 } else { 1 };
+        let insert = rule.starts_with("!!INSERT=");
         if let Some(captures) = GROUPID_REGEX.captures(rule) {
             let target = captures.get(1).map_or("", |m| m.as_str());
             *real_rule = captures.get(2).map_or("", |m| m.as_str()).to_string();
-            return match_range(target, dir * (node.group_id as i32));
+            // Inserted nodes never match group ids and subscription nodes
+            // never match insert ids, whatever the range
+            if insert != (node.group_id < 0) {
+                return false;
+            }
+            let dir = if insert { -1 } else { 1 };
+            return match_range(target, dir * node.group_id);
         }
     } else if rule.starts_with("!!TYPE=") {
         if let Some(captures) = TYPE_REGEX.captures(rule) {
@@ -163,6 +173,12 @@ pub fn apply_matcher(rule: &str, real_rule: &mut String, node: &Proxy) -> bool {
             *real_rule = captures.get(2).map_or("", |m| m.as_str()).to_string();
             return reg_find(&node.remark, target);
         }
+    } else if rule.starts_with("!!TAG=") {
+        if let Some(captures) = TAG_REGEX.captures(rule) {
+            let target = captures.get(1).map_or("", |m| m.as_str());
+            *real_rule = captures.get(2).map_or("", |m| m.as_str()).to_string();
+            return reg_find(&node.tag, target);
+        }
     } else {
         *real_rule = rule.to_string();
     }
@@ -170,6 +186,40 @@ pub fn apply_matcher(rule: &str, real_rule: &mut String, node: &Proxy) -> bool {
     true
 }
 
+/// Get the part of a rule that is matched against the remark, i.e. whatever
+/// follows a special `!!KEY=value!!` prefix, or the rule itself without one
+///
+/// # Arguments
+/// * `rule` - The rule to inspect
+///
+/// # Returns
+/// * The remark pattern, empty when a special rule has none
+pub fn rule_remark_pattern(rule: &str) -> &str {
+    if !rule.starts_with("!!") {
+        return rule;
+    }
+
+    let special_regexes: [&Regex; 10] = [
+        &GROUPID_REGEX,
+        &GROUP_REGEX,
+        &TYPE_REGEX,
+        &PORT_REGEX,
+        &SERVER_REGEX,
+        &PROTOCOL_REGEX,
+        &UDPSUPPORT_REGEX,
+        &SECURITY_REGEX,
+        &REMARKS_REGEX,
+        &TAG_REGEX,
+    ];
+    for regex in special_regexes {
+        if let Some(captures) = regex.captures(rule) {
+            return captures.get(2).map_or("", |m| m.as_str());
+        }
+    }
+
+    rule
+}
+
 /// Match a number against a range specification
 ///
 /// Range specification can include:
@@ -237,12 +287,55 @@ pub fn reg_find(text: &str, pattern: &str) -> bool {
         return true;
     }
 
-    match Regex::new(&format!("(?i){}", pattern)) {
+    match cached_regex(&format!("(?i){}", pattern)) {
         Ok(re) => re.is_match(text),
         Err(_) => false,
     }
 }
 
+/// Get the id range of a `!!GROUPID=` or `!!INSERT=` rule when part of it
+/// refers to no subscription the nodes come from
+///
+/// # Arguments
+/// * `rule` - The rule to inspect
+/// * `nodelist` - The nodes the rule is matched against
+///
+/// # Returns
+/// * The id range of the rule if one of its ids or ranges is out of range
+/// * `None` for other rules and ranges within the known ids
+pub fn out_of_range_group_ids<'a>(rule: &'a str, nodelist: &[Proxy]) -> Option<&'a str> {
+    if !rule.starts_with("!!GROUPID=") && !rule.starts_with("!!INSERT=") {
+        return None;
+    }
+    let target = GROUPID_REGEX.captures(rule)?.get(1)?.as_str();
+    let insert = rule.starts_with("!!INSERT=");
+    let (first, last) = if insert {
+        (
+            1,
+            nodelist
+                .iter()
+                .map(|node| -node.group_id)
+                .max()
+                .unwrap_or(0),
+        )
+    } else {
+        (
+            0,
+            nodelist
+                .iter()
+                .map(|node| node.group_id)
+                .max()
+                .unwrap_or(-1),
+        )
+    };
+
+    let (ranges, _) = parse_range_string(target);
+    ranges
+        .iter()
+        .any(|range| range.upper < first || range.lower > last)
+        .then_some(target)
+}
+
 /// Check if a string fully matches a regular expression pattern
 ///
 /// # Arguments
@@ -257,7 +350,7 @@ pub fn reg_match(text: &str, pattern: &str) -> bool {
         return true;
     }
 
-    match Regex::new(&format!("(?i)^{}$", pattern)) {
+    match cached_regex(&format!("(?i)^{}$", pattern)) {
         Ok(re) => re.is_match(text),
         Err(_) => false,
     }
@@ -273,10 +366,11 @@ pub struct CompiledRange {
 pub enum CompiledMatcher {
     /// Match against group name (case-insensitive regex find)
     Group(Regex),
-    /// Match against group ID range
+    /// Match against group ID range, or the insert URL index with `insert`
     GroupId {
         ranges: Vec<CompiledRange>,
         negate: bool,
+        insert: bool,
     },
     /// Match against proxy type (case-insensitive regex match)
     Type(Regex),
@@ -297,6 +391,9 @@ pub enum CompiledMatcher {
     Security(Regex),
     /// Match against remark (case-insensitive regex find)
     Remarks(Regex),
+    /// Match against the tag of the subscription URL (case-insensitive regex
+    /// find)
+    Tag(Regex),
     /// A plain regex rule (equivalent to !!REMARKS= but without the prefix)
     Plain(Regex),
     /// Rule that always matches (e.g., empty rule)
@@ -357,20 +454,22 @@ pub fn compile_rule(rule: &str) -> CompiledRule {
     let matcher = if let Some(captures) = GROUP_REGEX.captures(rule) {
         sub_rule_str = captures.get(2).map(|m| m.as_str());
         let target = captures.get(1).map_or("", |m| m.as_str());
-        Regex::new(&format!("(?i){}", target))
+        cached_regex(&format!("(?i){}", target))
             .map(CompiledMatcher::Group)
             .unwrap_or(CompiledMatcher::Invalid)
     } else if let Some(captures) = GROUPID_REGEX.captures(rule) {
         sub_rule_str = captures.get(2).map(|m| m.as_str());
         let target = captures.get(1).map_or("", |m| m.as_str());
-        let dir = if rule.starts_with("!!INSERT=") { -1 } else { 1 }; // Apply direction modifier conceptually later
         let (ranges, negate) = parse_range_string(target);
-        // The 'dir' multiplier is handled during application, not compilation
-        CompiledMatcher::GroupId { ranges, negate }
+        CompiledMatcher::GroupId {
+            ranges,
+            negate,
+            insert: rule.starts_with("!!INSERT="),
+        }
     } else if let Some(captures) = TYPE_REGEX.captures(rule) {
         sub_rule_str = captures.get(2).map(|m| m.as_str());
         let target = captures.get(1).map_or("", |m| m.as_str());
-        Regex::new(&format!("(?i)^{}$", target))
+        cached_regex(&format!("(?i)^{}$", target))
             .map(CompiledMatcher::Type)
             .unwrap_or(CompiledMatcher::Invalid)
     } else if let Some(captures) = PORT_REGEX.captures(rule) {
@@ -381,19 +480,19 @@ pub fn compile_rule(rule: &str) -> CompiledRule {
     } else if let Some(captures) = SERVER_REGEX.captures(rule) {
         sub_rule_str = captures.get(2).map(|m| m.as_str());
         let target = captures.get(1).map_or("", |m| m.as_str());
-        Regex::new(&format!("(?i){}", target))
+        cached_regex(&format!("(?i){}", target))
             .map(CompiledMatcher::Server)
             .unwrap_or(CompiledMatcher::Invalid)
     } else if let Some(captures) = PROTOCOL_REGEX.captures(rule) {
         sub_rule_str = captures.get(2).map(|m| m.as_str());
         let target = captures.get(1).map_or("", |m| m.as_str());
-        Regex::new(&format!("(?i){}", target))
+        cached_regex(&format!("(?i){}", target))
             .map(CompiledMatcher::Protocol)
             .unwrap_or(CompiledMatcher::Invalid)
     } else if let Some(captures) = UDPSUPPORT_REGEX.captures(rule) {
         sub_rule_str = captures.get(2).map(|m| m.as_str());
         let target = captures.get(1).map_or("", |m| m.as_str());
-        Regex::new(&format!("(?i)^{}$", target))
+        cached_regex(&format!("(?i)^{}$", target))
             .map(CompiledMatcher::UdpSupport)
             .unwrap_or(CompiledMatcher::Invalid)
     } else if let Some(captures) = SECURITY_REGEX.captures(rule) {
@@ -410,21 +509,27 @@ pub fn compile_rule(rule: &str) -> CompiledRule {
         } else {
             target.to_string()
         };
-        Regex::new(&format!("(?i){}", normalized))
+        cached_regex(&format!("(?i){}", normalized))
             .map(CompiledMatcher::Security)
             .unwrap_or(CompiledMatcher::Invalid)
     } else if let Some(captures) = REMARKS_REGEX.captures(rule) {
         sub_rule_str = captures.get(2).map(|m| m.as_str());
         let target = captures.get(1).map_or("", |m| m.as_str());
-        Regex::new(&format!("(?i){}", target))
+        cached_regex(&format!("(?i){}", target))
             .map(CompiledMatcher::Remarks)
             .unwrap_or(CompiledMatcher::Invalid)
+    } else if let Some(captures) = TAG_REGEX.captures(rule) {
+        sub_rule_str = captures.get(2).map(|m| m.as_str());
+        let target = captures.get(1).map_or("", |m| m.as_str());
+        cached_regex(&format!("(?i){}", target))
+            .map(CompiledMatcher::Tag)
+            .unwrap_or(CompiledMatcher::Invalid)
     } else {
         // Treat as plain regex match against remark if no prefix
         if rule.is_empty() {
             CompiledMatcher::AlwaysTrue
         } else {
-            Regex::new(&format!("(?i){}", rule))
+            cached_regex(&format!("(?i){}", rule))
                 .map(CompiledMatcher::Plain)
                 .unwrap_or(CompiledMatcher::Invalid)
         }
@@ -442,7 +547,8 @@ pub fn compile_rule(rule: &str) -> CompiledRule {
                 || s.starts_with("PROTOCOL=")
                 || s.starts_with("UDPSUPPORT=")
                 || s.starts_with("SECURITY=")
-                || s.starts_with("REMARKS=");
+                || s.starts_with("REMARKS=")
+                || s.starts_with("TAG=");
 
             let normalized = if s.starts_with("!!") {
                 s.to_string()
@@ -468,19 +574,19 @@ pub fn compile_rule(rule: &str) -> CompiledRule {
 pub fn apply_compiled_rule(compiled_rule: &CompiledRule, node: &Proxy) -> bool {
     let primary_match = match &compiled_rule.matcher {
         CompiledMatcher::Group(re) => re.is_match(&node.group),
-        CompiledMatcher::GroupId { ranges, negate } => {
-            // Determine direction based on original rule (though not stored in compiled,
-            // assume GroupId is +1, Insert is -1 conceptually)
-            // We assume compile_rule was called on the original string, so we don't store
-            // 'dir' Let's refine this: The compile function needs to know if
-            // it's GROUPID or INSERT. For now, let's assume we only compile
-            // GROUPID-like rules or handle INSERT elsewhere. A better approach
-            // might be to store the direction in the CompiledMatcher enum.
-            // Let's stick to the original match_range logic for simplicity for now.
-            // We need the original rule string to call match_range properly,
-            // which defeats the purpose of compiling.
-            // Let's reimplement match_range logic here based on compiled ranges.
-            let target = node.group_id as i32; // Assume dir = 1 for now
+        CompiledMatcher::GroupId {
+            ranges,
+            negate,
+            insert,
+        } => {
+            if *insert != (node.group_id < 0) {
+                return false;
+            }
+            let target = if *insert {
+                -node.group_id
+            } else {
+                node.group_id
+            };
             let mut matched = false;
             for r in ranges {
                 if target >= r.lower && target <= r.upper {
@@ -546,6 +652,7 @@ pub fn apply_compiled_rule(compiled_rule: &CompiledRule, node: &Proxy) -> bool {
             re.is_match(&features)
         }
         CompiledMatcher::Remarks(re) | CompiledMatcher::Plain(re) => re.is_match(&node.remark),
+        CompiledMatcher::Tag(re) => re.is_match(&node.tag),
         CompiledMatcher::AlwaysTrue => true,
         CompiledMatcher::Invalid => false, // Invalid rules never match
     };
@@ -608,14 +715,14 @@ pub fn replace_with_compiled_regex(
         if literal {
             re.replace_all(text, regex::NoExpand(replacement))
         } else {
-            re.replace_all(text, replacement)
+            re.replace_all(text, normalize_replacement(replacement).as_str())
         }
     } else {
         // Find the first match for non-literal replacement
         if literal {
             re.replacen(text, 1, regex::NoExpand(replacement))
         } else {
-            re.replacen(text, 1, replacement)
+            re.replacen(text, 1, normalize_replacement(replacement).as_str())
         }
     };
     result.into_owned() // Convert Cow<str> to String
@@ -706,6 +813,22 @@ mod tests {
         assert!(!apply_matcher("!!GROUP=OtherGroup", &mut real_rule, &node));
     }
 
+    #[test]
+    fn test_apply_matcher_tag() {
+        let mut node = create_test_proxy();
+        node.tag = "airport1".to_string();
+        let mut real_rule = String::new();
+
+        assert!(apply_matcher("!!TAG=airport1!!HK", &mut real_rule, &node));
+        assert_eq!(real_rule, "HK");
+        assert!(!apply_matcher("!!TAG=airport2", &mut real_rule, &node));
+
+        let rule = compile_rule("!!GROUP=TestGroup!!TAG=^airport");
+        assert!(apply_compiled_rule(&rule, &node));
+        node.tag.clear();
+        assert!(!apply_compiled_rule(&rule, &node));
+    }
+
     #[test]
     fn test_apply_matcher_type() {
         let node = create_test_proxy();
@@ -987,8 +1110,6 @@ mod tests {
 
     #[test]
     fn test_compile_rule_groupid() {
-        // Note: Doesn't handle !!INSERT= direction multiplier directly in compiled
-        // struct yet
         let rule = compile_rule("!!GROUPID=1-5,10");
         assert!(matches!(rule.matcher, CompiledMatcher::GroupId { .. }));
         let node = create_proxy_for_compile_test(
@@ -1,6 +1,8 @@
 pub mod base64;
 pub mod deserialize;
+pub mod encoding;
 pub mod file;
+pub mod geoip;
 #[cfg(not(target_arch = "wasm32"))]
 pub mod file_std;
 #[cfg(target_arch = "wasm32")]
@@ -15,9 +17,12 @@ pub mod matcher;
 pub mod memory_cache;
 pub mod network;
 pub mod node_manip;
+pub mod parallel;
 pub mod regexp;
+pub mod rhai_filter;
 pub mod serialize;
 pub mod string;
+pub mod sync;
 pub mod system;
 pub mod tribool;
 pub mod url;
@@ -32,13 +37,16 @@ pub use ini_reader::IniReader;
 pub use network::{is_ipv4, is_ipv6, is_link};
 pub use node_manip::{append_type_to_remark, preprocess_nodes};
 pub use regexp::{
-    reg_find, reg_get_all_match, reg_get_match, reg_match, reg_replace, reg_trim, reg_valid,
+    cached_regex, compile_pattern, normalize_replacement, reg_find, reg_get_all_match,
+    reg_get_match, reg_match, reg_replace, reg_trim, reg_valid, regex_cache_stats,
+    RegexCacheStats,
 };
 pub use serialize::{is_empty_option_string, is_u32_option_zero};
 pub use string::{
-    ends_with, md5, remove_emoji, replace_all_distinct, starts_with, to_lower, trim,
+    ends_with, md5, natural_cmp, remove_emoji, replace_all_distinct, starts_with, to_lower, trim,
     trim_whitespace,
 };
+pub use sync::lock_recovering;
 pub use system::{get_env, get_system_proxy, sleep_ms};
 pub use url::{url_decode, url_encode};
 pub use useragent::{match_user_agent, ver_greater_equal};
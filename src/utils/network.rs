@@ -1,6 +1,7 @@
 //! Network utilities for IP address handling and validation
 
-use std::net::{IpAddr, ToSocketAddrs};
+use std::borrow::Cow;
+use std::net::{IpAddr, Ipv6Addr, ToSocketAddrs};
 
 /// Resolves a hostname to its IP address
 ///
@@ -67,9 +68,38 @@ pub fn is_ipv4(s: &str) -> bool {
 ///
 /// True if the string is a valid IPv6 address, false otherwise
 pub fn is_ipv6(s: &str) -> bool {
-    // Basic implementation - placeholder
-    // In a real implementation, we'd do proper IPv6 validation
-    s.contains(':')
+    s.parse::<Ipv6Addr>().is_ok()
+}
+
+/// Removes the brackets around an IPv6 literal, `[2001:db8::1]` becomes
+/// `2001:db8::1`, other hosts are returned as is
+pub fn strip_ipv6_brackets(host: &str) -> &str {
+    match host.strip_prefix('[').and_then(|h| h.strip_suffix(']')) {
+        Some(inner) if is_ipv6(inner) => inner,
+        _ => host,
+    }
+}
+
+/// Wraps an IPv6 literal in brackets for formats which write `host:port`
+pub fn bracket_ipv6(host: &str) -> Cow<'_, str> {
+    if is_ipv6(host) {
+        Cow::Owned(format!("[{}]", host))
+    } else {
+        Cow::Borrowed(host)
+    }
+}
+
+/// Formats `host:port`, bracketing IPv6 literals
+pub fn join_host_port(host: &str, port: u16) -> String {
+    format!("{}:{}", bracket_ipv6(host), port)
+}
+
+/// Splits `host:port` or `[ipv6]:port` at the last colon, the host is
+/// returned without brackets
+pub fn split_host_port(address: &str) -> Option<(&str, u16)> {
+    let (host, port) = address.rsplit_once(':')?;
+    let port = port.trim().parse::<u16>().ok()?;
+    Some((strip_ipv6_brackets(host.trim()), port))
 }
 
 /// Checks if a string is a valid URL
@@ -112,6 +142,28 @@ mod tests {
     fn test_is_ipv6_invalid() {
         assert!(!is_ipv6("192.168.1.1"));
         assert!(!is_ipv6("not an ip"));
+        assert!(!is_ipv6("example.com:443"));
+        assert!(!is_ipv6("[::1]"));
+    }
+
+    #[test]
+    fn test_ipv6_brackets() {
+        assert_eq!(strip_ipv6_brackets("[2001:db8::1]"), "2001:db8::1");
+        assert_eq!(strip_ipv6_brackets("2001:db8::1"), "2001:db8::1");
+        assert_eq!(strip_ipv6_brackets("[example.com]"), "[example.com]");
+        assert_eq!(bracket_ipv6("2001:db8::1"), "[2001:db8::1]");
+        assert_eq!(bracket_ipv6("example.com"), "example.com");
+        assert_eq!(join_host_port("::1", 443), "[::1]:443");
+        assert_eq!(join_host_port("1.2.3.4", 443), "1.2.3.4:443");
+        assert_eq!(
+            split_host_port("[2001:db8::1]:8388"),
+            Some(("2001:db8::1", 8388))
+        );
+        assert_eq!(
+            split_host_port("example.com:443"),
+            Some(("example.com", 443))
+        );
+        assert_eq!(split_host_port("example.com"), None);
     }
 
     #[test]
@@ -1,26 +1,54 @@
-use log::{debug, info};
+use log::{debug, info, warn};
+use regex::Regex;
 use std::cmp::Ordering;
+use std::collections::{BTreeMap, HashSet};
 
+use crate::generator::config::remark::disambiguate_remarks;
 use crate::models::{
-    extra_settings::ExtraSettings,
+    extra_settings::{DedupMode, ExtraSettings},
     proxy::{Proxy, ProxyType},
+    proxy_node::combined::CombinedProxy,
     regex_match_config::RegexMatchConfigs,
 };
 use crate::utils::{
-    matcher::{apply_matcher, reg_find},
-    reg_replace,
-    string::{remove_emoji, trim},
+    compile_pattern,
+    matcher::{
+        apply_matcher, compile_rule, reg_find, replace_with_compiled_regex, rule_remark_pattern,
+        CompiledMatcher,
+    },
+    string::{natural_cmp, remove_emoji, trim},
 };
 
 use super::matcher::apply_compiled_rule;
 
+/// Compiles the remark pattern of every rename rule once per request
+/// Invalid patterns are reported and skipped instead of failing the conversion
+fn compile_rename_patterns(rename_array: &RegexMatchConfigs) -> Vec<Option<Regex>> {
+    rename_array
+        .iter()
+        .map(|pattern| {
+            let real_rule = rule_remark_pattern(&pattern._match);
+            if !pattern.script.is_empty() || real_rule.is_empty() {
+                return None;
+            }
+            match compile_pattern(real_rule, false) {
+                Ok(regex) => Some(regex),
+                Err(e) => {
+                    warn!("Skipping invalid rename rule '{}': {}", pattern._match, e);
+                    None
+                }
+            }
+        })
+        .collect()
+}
+
 /// Applies a rename configuration to a node
 /// Similar to the C++ nodeRename function
-async fn node_rename(node: &mut Proxy, extra: &mut ExtraSettings) {
+async fn node_rename(node: &mut Proxy, extra: &mut ExtraSettings, compiled: &[Option<Regex>]) {
     extra.init_js_context();
     let rename_array = &extra.rename_array;
     let original_remark = node.remark.clone();
-    for pattern in rename_array {
+    for (pattern, regex) in rename_array.iter().zip(compiled) {
         if !pattern.script.is_empty() {
             match extra
                 .eval_get_rename_node_remark(node, pattern.script.clone())
@@ -33,10 +61,11 @@ async fn node_rename(node: &mut Proxy, extra: &mut ExtraSettings) {
                     log::error!("Error renaming node: {}", e);
                 }
             }
-        } else if !pattern._match.is_empty() {
+        } else if let Some(regex) = regex {
             let mut real_rule = String::new();
             if apply_matcher(&pattern._match, &mut real_rule, node) && !real_rule.is_empty() {
-                node.remark = reg_replace(&node.remark, &real_rule, &pattern.replace, true, false);
+                node.remark =
+                    replace_with_compiled_regex(&node.remark, regex, &pattern.replace, true, false);
             }
         }
     }
@@ -78,6 +107,24 @@ async fn add_emoji(node: &Proxy, emoji_array: &RegexMatchConfigs, extra: &ExtraS
         }
     }
 
+    // Without a telling remark the GeoIP country code picks the emoji
+    if !node.country.is_empty() {
+        let located = Proxy {
+            remark: node.country.clone(),
+            ..node.clone()
+        };
+        for pattern in emoji_array
+            .iter()
+            .filter(|pattern| pattern.script.is_empty() && !pattern.replace.is_empty())
+        {
+            if let Some(compiled_rule) = &pattern.compiled_rule {
+                if apply_compiled_rule(compiled_rule, &located) {
+                    return format!("{} {}", pattern.replace, node.remark);
+                }
+            }
+        }
+    }
+
     node.remark.clone()
 }
 
@@ -87,6 +134,12 @@ pub async fn preprocess_nodes(
     nodes: &mut Vec<Proxy>,
     extra: &mut ExtraSettings,
 ) -> Result<(), Box<dyn std::error::Error>> {
+    if extra.dedup == DedupMode::Server {
+        dedup_nodes(nodes, DedupMode::Server);
+    }
+
+    let compiled_renames = compile_rename_patterns(&extra.rename_array);
+
     // Process each node
     for node in nodes.iter_mut() {
         // Remove emoji if needed
@@ -94,8 +147,12 @@ pub async fn preprocess_nodes(
             node.remark = trim(&remove_emoji(&node.remark)).to_string();
         }
 
+        if let Some(sni) = &extra.sni {
+            apply_default_sni(node, sni);
+        }
+
         // Apply rename patterns
-        node_rename(node, extra).await;
+        node_rename(node, extra, &compiled_renames).await;
 
         // Add emoji if needed
         if extra.add_emoji {
@@ -110,16 +167,146 @@ pub async fn preprocess_nodes(
         }
     }
 
-    // Sort nodes if needed
-    if extra.sort_flag && extra.authorized {
+    if extra.dedup == DedupMode::Remark {
+        dedup_nodes(nodes, DedupMode::Remark);
+    }
+
+    // Sort nodes if needed, scripts only run for authorized requests
+    if extra.sort_flag {
         info!("Sorting {} nodes", nodes.len());
-        extra.eval_sort_nodes(nodes).await?;
+        if !extra.sort_script.is_empty() && extra.authorized {
+            extra.eval_sort_nodes(nodes).await?;
+        } else {
+            sort_nodes(nodes, &extra.sort_priority);
+        }
     }
 
+    // Numbered in output order, after renaming so the final names are unique
+    disambiguate_remarks(nodes, &extra.duplicate_remark_format);
+
     debug!("Node preprocessing completed for {} nodes", nodes.len());
     Ok(())
 }
 
+/// Gives a TLS node without a server name of its own the `sni` of the
+/// request, plain nodes and nodes that already name a server are left alone
+fn apply_default_sni(node: &mut Proxy, sni: &str) {
+    let fill = |name: &mut Option<String>| {
+        if name.as_deref().is_none_or(str::is_empty) {
+            *name = Some(sni.to_string());
+        }
+    };
+    match &mut node.combined_proxy {
+        Some(CombinedProxy::Vless(vless)) => {
+            if vless.tls {
+                fill(&mut vless.servername);
+            }
+        }
+        Some(CombinedProxy::AnyTls(anytls)) => fill(&mut anytls.sni),
+        _ => {
+            if node.tls_secure
+                || matches!(
+                    node.proxy_type,
+                    ProxyType::Trojan | ProxyType::Hysteria | ProxyType::Hysteria2
+                )
+            {
+                fill(&mut node.sni);
+            }
+        }
+    }
+}
+
+/// Removes duplicate nodes in place, keeping the first occurrence and the
+/// original order, returns how many nodes were removed
+pub fn dedup_nodes(nodes: &mut Vec<Proxy>, mode: DedupMode) -> usize {
+    let before = nodes.len();
+    let mut seen = HashSet::new();
+    match mode {
+        DedupMode::Disabled => return 0,
+        DedupMode::Server => nodes.retain(|node| match node_identity(node) {
+            Some(identity) => seen.insert(identity),
+            None => true,
+        }),
+        DedupMode::Remark => nodes.retain(|node| seen.insert(node.remark.clone())),
+    }
+
+    let removed = before - nodes.len();
+    if removed > 0 {
+        info!("Removed {} duplicate nodes", removed);
+    }
+    removed
+}
+
+/// Builds a key identifying the server a node connects to and how, ignoring
+/// its remark and the subscription it came from
+fn node_identity(node: &Proxy) -> Option<String> {
+    let mut value = serde_json::to_value(node).ok()?;
+    if let Some(fields) = value.as_object_mut() {
+        for field in ["Remark", "Id", "GroupId", "Group"] {
+            fields.remove(field);
+        }
+    }
+    Some(canonical_json(&value))
+}
+
+/// Renders a JSON value with sorted keys and array items, so that fields
+/// backed by hash sets and maps compare equal regardless of iteration order
+fn canonical_json(value: &serde_json::Value) -> String {
+    match value {
+        serde_json::Value::Array(items) => {
+            let mut items: Vec<String> = items.iter().map(canonical_json).collect();
+            items.sort();
+            format!("[{}]", items.join(","))
+        }
+        serde_json::Value::Object(fields) => {
+            let fields: BTreeMap<&String, String> = fields
+                .iter()
+                .map(|(key, value)| (key, canonical_json(value)))
+                .collect();
+            let fields: Vec<String> = fields
+                .into_iter()
+                .map(|(key, value)| format!("{:?}:{}", key, value))
+                .collect();
+            format!("{{{}}}", fields.join(","))
+        }
+        other => other.to_string(),
+    }
+}
+
+/// Sorts nodes without a script
+/// Nodes are ordered by remark in natural order with unknown types last, or
+/// when a priority list is given, by the first pattern they match, keeping the
+/// original order within each pattern and for nodes matching none
+pub fn sort_nodes(nodes: &mut [Proxy], priority: &[String]) {
+    if priority.is_empty() {
+        nodes.sort_by(|a, b| {
+            let a_unknown = a.proxy_type == ProxyType::Unknown;
+            let b_unknown = b.proxy_type == ProxyType::Unknown;
+            a_unknown
+                .cmp(&b_unknown)
+                .then_with(|| natural_cmp(&a.remark, &b.remark))
+        });
+        return;
+    }
+
+    let rules: Vec<_> = priority
+        .iter()
+        .map(|pattern| {
+            let rule = compile_rule(pattern);
+            if matches!(rule.matcher, CompiledMatcher::Invalid) {
+                warn!("Skipping invalid sort priority pattern '{}'", pattern);
+            }
+            rule
+        })
+        .collect();
+    nodes.sort_by_cached_key(|node| {
+        rules
+            .iter()
+            .position(|rule| apply_compiled_rule(rule, node))
+            .unwrap_or(rules.len())
+    });
+}
+
 /// Appends proxy type to node remark
 pub fn append_type_to_remark(nodes: &mut Vec<Proxy>) {
     for node in nodes.iter_mut() {
@@ -132,3 +319,157 @@ pub fn append_type_to_remark(nodes: &mut Vec<Proxy>) {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::settings::FromIniWithDelimiter;
+
+    fn emoji_rules(rules: &[&str]) -> RegexMatchConfigs {
+        let rules: Vec<String> = rules.iter().map(|rule| rule.to_string()).collect();
+        RegexMatchConfigs::from_ini_with_delimiter(&rules, ",")
+    }
+
+    fn node_with_remark(remark: &str) -> Proxy {
+        Proxy {
+            remark: remark.to_string(),
+            ..Default::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn test_add_emoji_first_match_wins() {
+        let extra = ExtraSettings::default();
+        let rules = emoji_rules(&["(?i:\\bHK\\d*\\b|Hong Kong),🇭🇰", "HK,🏳️‍🌈", "(?i:JP),🇯🇵"]);
+
+        let node = node_with_remark("HK 01");
+        assert_eq!(add_emoji(&node, &rules, &extra).await, "🇭🇰 HK 01");
+
+        let node = node_with_remark("Tokyo JP");
+        assert_eq!(add_emoji(&node, &rules, &extra).await, "🇯🇵 Tokyo JP");
+
+        let node = node_with_remark("US 01");
+        assert_eq!(add_emoji(&node, &rules, &extra).await, "US 01");
+
+        // The GeoIP country only counts when the remark matches no rule
+        let mut node = node_with_remark("Node-01");
+        node.country = "JP".to_string();
+        assert_eq!(add_emoji(&node, &rules, &extra).await, "🇯🇵 Node-01");
+        node.remark = "HK 01".to_string();
+        assert_eq!(add_emoji(&node, &rules, &extra).await, "🇭🇰 HK 01");
+    }
+
+    #[tokio::test]
+    async fn test_preprocess_nodes_renames_with_capture_groups() {
+        let mut extra = ExtraSettings::default();
+        let rules: Vec<String> = [
+            "s/^\\[Premium\\]\\s*//",
+            "(\\d+)x@$1倍率",
+            "(unclosed@x",
+            "^HK@香港",
+        ]
+        .iter()
+        .map(|rule| rule.to_string())
+        .collect();
+        extra.rename_array = RegexMatchConfigs::from_ini_with_delimiter(&rules, "@");
+        extra.add_emoji = false;
+        extra.remove_emoji = false;
+        extra.sort_flag = false;
+
+        let mut nodes = vec![node_with_remark("[Premium] HK 2x")];
+        preprocess_nodes(&mut nodes, &mut extra).await.unwrap();
+
+        assert_eq!(nodes[0].remark, "香港 2倍率");
+    }
+
+    #[test]
+    fn test_sort_nodes_natural_order() {
+        let mut nodes = vec![
+            node_with_remark("node10"),
+            node_with_remark("Node2"),
+            node_with_remark("node1"),
+        ];
+        for node in nodes.iter_mut() {
+            node.proxy_type = ProxyType::Shadowsocks;
+        }
+        sort_nodes(&mut nodes, &[]);
+
+        let remarks: Vec<_> = nodes.iter().map(|node| node.remark.as_str()).collect();
+        assert_eq!(remarks, ["node1", "Node2", "node10"]);
+    }
+
+    #[test]
+    fn test_sort_nodes_by_priority() {
+        let mut nodes = vec![
+            node_with_remark("US 1"),
+            node_with_remark("JP 2"),
+            node_with_remark("HK 1"),
+            node_with_remark("SG 1"),
+            node_with_remark("JP 1"),
+        ];
+        let priority = vec!["HK".to_string(), "JP".to_string(), "(invalid".to_string()];
+        sort_nodes(&mut nodes, &priority);
+
+        let remarks: Vec<_> = nodes.iter().map(|node| node.remark.as_str()).collect();
+        assert_eq!(remarks, ["HK 1", "JP 2", "JP 1", "US 1", "SG 1"]);
+    }
+
+    #[tokio::test]
+    async fn test_preprocess_nodes_does_not_double_emoji() {
+        let mut extra = ExtraSettings::default();
+        extra.rename_array = Vec::new();
+        extra.emoji_array = emoji_rules(&["(?i:Bandwidth),🏳️‍🌈", "(?i:HK),🇭🇰"]);
+        extra.add_emoji = true;
+        extra.remove_emoji = true;
+        extra.sort_flag = false;
+
+        let mut nodes = vec![
+            node_with_remark("🇭🇰 HK 01"),
+            node_with_remark("🏳️‍🌈 Bandwidth 10G"),
+        ];
+        preprocess_nodes(&mut nodes, &mut extra).await.unwrap();
+
+        assert_eq!(nodes[0].remark, "🇭🇰 HK 01");
+        assert_eq!(nodes[1].remark, "🏳️‍🌈 Bandwidth 10G");
+    }
+
+    fn server_node(remark: &str, group_id: i32, password: &str, alpn: &[&str]) -> Proxy {
+        Proxy {
+            proxy_type: ProxyType::Trojan,
+            remark: remark.to_string(),
+            group_id,
+            hostname: "example.com".to_string(),
+            port: 443,
+            password: Some(password.to_string()),
+            alpn: crate::models::parse_alpn(alpn),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_dedup_nodes_by_server() {
+        let mut nodes = vec![
+            server_node("HK 01", 0, "secret", &["h2", "http/1.1"]),
+            server_node("Hong Kong", 1, "secret", &["h2", "http/1.1"]),
+            server_node("HK 02", 1, "other", &["h2", "http/1.1"]),
+        ];
+        assert_eq!(dedup_nodes(&mut nodes, DedupMode::Server), 1);
+
+        let remarks: Vec<_> = nodes.iter().map(|node| node.remark.as_str()).collect();
+        assert_eq!(remarks, ["HK 01", "HK 02"]);
+    }
+
+    #[test]
+    fn test_dedup_nodes_by_remark() {
+        let mut nodes = vec![
+            server_node("HK 01", 0, "a", &[]),
+            server_node("JP 01", 0, "b", &[]),
+            server_node("HK 01", 1, "c", &[]),
+        ];
+        assert_eq!(dedup_nodes(&mut nodes, DedupMode::Disabled), 0);
+        assert_eq!(dedup_nodes(&mut nodes, DedupMode::Remark), 1);
+
+        let passwords: Vec<_> = nodes.iter().map(|node| node.password.as_deref()).collect();
+        assert_eq!(passwords, [Some("a"), Some("b")]);
+    }
+}
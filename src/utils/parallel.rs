@@ -0,0 +1,38 @@
+//! Order-preserving parallel helpers for CPU-bound per-node work
+
+/// Below this many items the work runs on the calling thread, handing it to
+/// the pool costs more than it saves
+const MIN_PARALLEL_ITEMS: usize = 256;
+
+/// Maps `items` with `f` on the rayon pool shared by all requests on native
+/// targets, so concurrent conversions do not each start their own threads.
+///
+/// Results are returned in the order of `items`, exactly as
+/// `items.iter().map(f).collect()` would return them. A panic in `f` is
+/// propagated to the caller.
+pub fn parallel_map<T, R, F>(items: &[T], f: F) -> Vec<R>
+where
+    T: Sync,
+    R: Send,
+    F: Fn(&T) -> R + Send + Sync,
+{
+    #[cfg(not(target_arch = "wasm32"))]
+    if items.len() >= MIN_PARALLEL_ITEMS {
+        use rayon::prelude::*;
+        return items.par_iter().map(f).collect();
+    }
+    items.iter().map(f).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parallel_map_keeps_order() {
+        let items: Vec<usize> = (0..10_000).collect();
+        let mapped = parallel_map(&items, |i| i * 2);
+        assert_eq!(mapped, items.iter().map(|i| i * 2).collect::<Vec<_>>());
+        assert_eq!(parallel_map(&items[..3], |i| *i), vec![0, 1, 2]);
+    }
+}
@@ -3,7 +3,94 @@
 //! This module provides utility functions for working with regular expressions,
 //! similar to the C++ implementation in subconverter.
 
+use lazy_static::lazy_static;
 use regex::{Regex, RegexBuilder};
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+
+/// Most compiled patterns kept at once. Patterns come from user supplied
+/// configs and queries, so the cache starts over once it is full instead of
+/// growing without bound
+const REGEX_CACHE_CAPACITY: usize = 1024;
+
+type RegexCacheKey = (String, bool, bool);
+
+lazy_static! {
+    static ref REGEX_CACHE: Mutex<HashMap<RegexCacheKey, Result<Regex, regex::Error>>> =
+        Mutex::new(HashMap::new());
+}
+static REGEX_CACHE_HITS: AtomicU64 = AtomicU64::new(0);
+static REGEX_CACHE_MISSES: AtomicU64 = AtomicU64::new(0);
+
+/// Counters of the process-wide regex cache
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RegexCacheStats {
+    pub hits: u64,
+    pub misses: u64,
+    /// Patterns currently cached, failed ones included
+    pub size: usize,
+}
+
+/// Compiles a pattern through the process-wide cache, so patterns applied to
+/// every node of every request are only compiled once
+///
+/// Compilation errors are cached as well, a bad pattern is reported without
+/// being compiled again.
+///
+/// # Arguments
+///
+/// * `pattern` - The pattern, compiled as by `Regex::new`
+pub fn cached_regex(pattern: &str) -> Result<Regex, regex::Error> {
+    cached_build(pattern, false, false)
+}
+
+/// Returns the hit and miss counters of the regex cache
+pub fn regex_cache_stats() -> RegexCacheStats {
+    let size = REGEX_CACHE.lock().map(|cache| cache.len()).unwrap_or(0);
+    RegexCacheStats {
+        hits: REGEX_CACHE_HITS.load(Ordering::Relaxed),
+        misses: REGEX_CACHE_MISSES.load(Ordering::Relaxed),
+        size,
+    }
+}
+
+fn cached_build(
+    pattern: &str,
+    case_insensitive: bool,
+    multi_line: bool,
+) -> Result<Regex, regex::Error> {
+    let key = (pattern.to_string(), case_insensitive, multi_line);
+    if let Some(cached) = REGEX_CACHE
+        .lock()
+        .ok()
+        .and_then(|cache| cache.get(&key).cloned())
+    {
+        REGEX_CACHE_HITS.fetch_add(1, Ordering::Relaxed);
+        return cached;
+    }
+
+    REGEX_CACHE_MISSES.fetch_add(1, Ordering::Relaxed);
+    let compiled = RegexBuilder::new(pattern)
+        .case_insensitive(case_insensitive)
+        .multi_line(multi_line)
+        .build();
+    if let Ok(mut cache) = REGEX_CACHE.lock() {
+        if cache.len() >= REGEX_CACHE_CAPACITY {
+            cache.clear();
+        }
+        cache.insert(key, compiled.clone());
+    }
+    compiled
+}
+
+/// Splits the `(?i)` prefix subconverter patterns use for case-insensitivity
+fn split_case_flag(match_pattern: &str) -> (&str, bool) {
+    match match_pattern.strip_prefix("(?i)") {
+        Some(pattern) => (pattern, true),
+        None => (match_pattern, false),
+    }
+}
 
 /// Checks if a regular expression pattern is valid
 ///
@@ -15,7 +102,7 @@ use regex::{Regex, RegexBuilder};
 ///
 /// `true` if the pattern is valid, `false` otherwise
 pub fn reg_valid(reg: &str) -> bool {
-    Regex::new(reg).is_ok()
+    cached_regex(reg).is_ok()
 }
 
 /// Finds if a pattern matches anywhere in the string
@@ -29,21 +116,8 @@ pub fn reg_valid(reg: &str) -> bool {
 ///
 /// `true` if the pattern is found, `false` otherwise
 pub fn reg_find(src: &str, match_pattern: &str) -> bool {
-    let (pattern, case_insensitive) = if match_pattern.starts_with("(?i)") {
-        (&match_pattern[4..], true)
-    } else {
-        (match_pattern, false)
-    };
-
-    if let Ok(regex) = RegexBuilder::new(pattern)
-        .case_insensitive(case_insensitive)
-        .multi_line(true)
-        .build()
-    {
-        regex.is_match(src)
-    } else {
-        false
-    }
+    let (pattern, case_insensitive) = split_case_flag(match_pattern);
+    cached_build(pattern, case_insensitive, true).is_ok_and(|regex| regex.is_match(src))
 }
 
 /// Replaces matches of a pattern with a replacement string
@@ -66,27 +140,74 @@ pub fn reg_replace(
     global: bool,
     multiline: bool,
 ) -> String {
-    let (pattern, case_insensitive) = if match_pattern.starts_with("(?i)") {
-        (&match_pattern[4..], true)
-    } else {
-        (match_pattern, false)
-    };
-
-    if let Ok(regex) = RegexBuilder::new(pattern)
-        .case_insensitive(case_insensitive)
-        .multi_line(multiline)
-        .build()
-    {
+    if let Ok(regex) = compile_pattern(match_pattern, multiline) {
+        let rep = normalize_replacement(rep);
         if global {
-            regex.replace_all(src, rep).to_string()
+            regex.replace_all(src, rep.as_str()).to_string()
         } else {
-            regex.replace(src, rep).to_string()
+            regex.replace(src, rep.as_str()).to_string()
         }
     } else {
         src.to_string()
     }
 }
 
+/// Compiles a pattern the same way `reg_replace` does, so callers applying the
+/// same pattern many times only pay for compilation once
+///
+/// # Arguments
+///
+/// * `match_pattern` - The pattern, case-insensitive when prefixed with `(?i)`
+/// * `multiline` - Whether to enable multiline mode
+pub fn compile_pattern(match_pattern: &str, multiline: bool) -> Result<Regex, regex::Error> {
+    let (pattern, case_insensitive) = split_case_flag(match_pattern);
+    cached_build(pattern, case_insensitive, multiline)
+}
+
+/// Rewrites numbered group references to the braced form, so `$1x` means
+/// group 1 followed by `x` as in the C++ implementation instead of a group
+/// named `1x`
+///
+/// # Arguments
+///
+/// * `rep` - The replacement string
+///
+/// # Returns
+///
+/// The replacement string with every `$N` written as `${N}`
+pub fn normalize_replacement(rep: &str) -> String {
+    let mut result = String::with_capacity(rep.len());
+    let mut chars = rep.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        if c != '$' {
+            result.push(c);
+            continue;
+        }
+
+        match chars.peek() {
+            Some('$') => {
+                result.push_str("$$");
+                chars.next();
+            }
+            Some(d) if d.is_ascii_digit() => {
+                let mut digits = String::new();
+                while let Some(&d) = chars.peek() {
+                    if !d.is_ascii_digit() {
+                        break;
+                    }
+                    digits.push(d);
+                    chars.next();
+                }
+                result.push_str(&format!("${{{}}}", digits));
+            }
+            _ => result.push('$'),
+        }
+    }
+
+    result
+}
+
 /// Checks if a string fully matches a pattern
 ///
 /// # Arguments
@@ -98,20 +219,9 @@ pub fn reg_replace(
 ///
 /// `true` if the string fully matches the pattern, `false` otherwise
 pub fn reg_match(src: &str, match_pattern: &str) -> bool {
-    let (pattern, case_insensitive) = if match_pattern.starts_with("(?i)") {
-        (&match_pattern[4..], true)
-    } else {
-        (match_pattern, false)
-    };
-
-    if let Ok(regex) = RegexBuilder::new(&format!("^{}$", pattern))
-        .case_insensitive(case_insensitive)
-        .build()
-    {
-        regex.is_match(src)
-    } else {
-        false
-    }
+    let (pattern, case_insensitive) = split_case_flag(match_pattern);
+    cached_build(&format!("^{}$", pattern), case_insensitive, false)
+        .is_ok_and(|regex| regex.is_match(src))
 }
 
 /// Gets the capturing groups from a regex match
@@ -125,17 +235,9 @@ pub fn reg_match(src: &str, match_pattern: &str) -> bool {
 ///
 /// A vector of matched capturing groups, or an empty vector if no match
 pub fn reg_get_match(src: &str, match_pattern: &str) -> Vec<String> {
-    let (pattern, case_insensitive) = if match_pattern.starts_with("(?i)") {
-        (&match_pattern[4..], true)
-    } else {
-        (match_pattern, false)
-    };
+    let (pattern, case_insensitive) = split_case_flag(match_pattern);
 
-    if let Ok(regex) = RegexBuilder::new(pattern)
-        .case_insensitive(case_insensitive)
-        .multi_line(true)
-        .build()
-    {
+    if let Ok(regex) = cached_build(pattern, case_insensitive, true) {
         if let Some(caps) = regex.captures(src) {
             let mut results = Vec::new();
             for i in 0..caps.len() {
@@ -164,19 +266,11 @@ pub fn reg_get_match(src: &str, match_pattern: &str) -> Vec<String> {
 ///
 /// A vector of matched strings
 pub fn reg_get_all_match(src: &str, match_pattern: &str, group_only: bool) -> Vec<String> {
-    let (pattern, case_insensitive) = if match_pattern.starts_with("(?i)") {
-        (&match_pattern[4..], true)
-    } else {
-        (match_pattern, false)
-    };
+    let (pattern, case_insensitive) = split_case_flag(match_pattern);
 
     let mut results = Vec::new();
 
-    if let Ok(regex) = RegexBuilder::new(pattern)
-        .case_insensitive(case_insensitive)
-        .multi_line(true)
-        .build()
-    {
+    if let Ok(regex) = cached_build(pattern, case_insensitive, true) {
         if group_only {
             for caps in regex.captures_iter(src) {
                 // Skip the 0th capture (the full match) when group_only is true
@@ -244,6 +338,23 @@ mod tests {
             reg_replace("hello world world", r"world", "universe", false, false),
             "hello universe world"
         );
+        // Group references directly followed by word characters
+        assert_eq!(reg_replace("2x", r"(\d+)x", "$1x", true, false), "2x");
+        assert_eq!(
+            reg_replace("3 倍率", r"(\d+) 倍率", "$1倍", true, false),
+            "3倍"
+        );
+    }
+
+    #[test]
+    fn test_normalize_replacement() {
+        assert_eq!(normalize_replacement("$1x"), "${1}x");
+        assert_eq!(
+            normalize_replacement("total=$2&left=$1"),
+            "total=${2}&left=${1}"
+        );
+        assert_eq!(normalize_replacement("$$1"), "$$1");
+        assert_eq!(normalize_replacement("${name} $"), "${name} $");
     }
 
     #[test]
@@ -272,6 +383,22 @@ mod tests {
         assert_eq!(group_only[1], "456");
     }
 
+    #[test]
+    fn test_patterns_are_compiled_once() {
+        let pattern = r"^cache-test-(\d+)$";
+        let before = regex_cache_stats();
+        assert!(reg_find("cache-test-1", pattern));
+        assert!(reg_find("cache-test-2", pattern));
+        let after = regex_cache_stats();
+        assert!(after.hits > before.hits);
+
+        // Failures are remembered too
+        let bad = "cache-test-[";
+        assert!(cached_regex(bad).is_err());
+        let key = (bad.to_string(), false, false);
+        assert!(REGEX_CACHE.lock().unwrap().get(&key).unwrap().is_err());
+    }
+
     #[test]
     fn test_reg_trim() {
         assert_eq!(reg_trim("  hello world  "), "hello world");
@@ -0,0 +1,185 @@
+//! Node filtering and renaming with Rhai scripts
+//!
+//! A Rhai filter script defines `filter(node)`, called with every node as a
+//! map of its fields (`Remark`, `Hostname`, `Port`, ...). It returns `true` to
+//! keep the node, `false` to drop it, or a string to keep the node renamed to
+//! that string.
+
+use crate::Proxy;
+
+/// Operations all calls of one script may take together
+pub const MAX_OPERATIONS: u64 = 1_000_000;
+
+/// Time all calls of one script may take together, in milliseconds
+pub const MAX_DURATION_MS: u64 = 2_000;
+
+/// Whether a filter script is written in Rhai rather than JavaScript, inline
+/// Rhai scripts start with `rhai:`, script files end with `.rhai`
+pub fn is_rhai_script(script: &str) -> bool {
+    script.starts_with("rhai:") || (script.starts_with("path:") && script.ends_with(".rhai"))
+}
+
+/// Run `filter(node)` of the Rhai `source` on every node, dropping and
+/// renaming nodes as it answers
+///
+/// The nodes are only changed when the script succeeded for all of them.
+///
+/// # Returns
+/// * `Ok(())` - The nodes were filtered
+/// * `Err(String)` - The script diagnostic, the nodes are left as they were
+#[cfg(feature = "rhai-runtime")]
+pub fn filter_nodes_with_rhai(nodes: &mut Vec<Proxy>, source: &str) -> Result<(), String> {
+    use rhai::{Dynamic, Engine, EvalAltResult, Scope};
+    use std::time::{Duration, Instant};
+
+    let mut engine = Engine::new();
+    engine.set_max_operations(MAX_OPERATIONS);
+    let started = Instant::now();
+    engine.on_progress(move |_| {
+        (started.elapsed() > Duration::from_millis(MAX_DURATION_MS))
+            .then(|| Dynamic::from("time budget exceeded"))
+    });
+
+    let ast = engine.compile(source).map_err(|e| e.to_string())?;
+    let mut scope = Scope::new();
+    let mut verdicts = Vec::with_capacity(nodes.len());
+    for node in nodes.iter() {
+        let value = rhai::serde::to_dynamic(node).map_err(|e| e.to_string())?;
+        let verdict: Dynamic = engine
+            .call_fn(&mut scope, &ast, "filter", (value,))
+            .map_err(|e| match *e {
+                EvalAltResult::ErrorTerminated(..) => format!(
+                    "node {}: time budget of {} ms exceeded",
+                    node.remark, MAX_DURATION_MS
+                ),
+                e => format!("node {}: {}", node.remark, e),
+            })?;
+        let verdict = if verdict.is_string() {
+            Some(verdict.into_string().unwrap_or_default())
+        } else {
+            let keep = verdict.as_bool().map_err(|type_name| {
+                format!(
+                    "node {}: filter returned {} instead of a bool or a string",
+                    node.remark, type_name
+                )
+            })?;
+            keep.then(|| node.remark.clone())
+        };
+        verdicts.push(verdict);
+    }
+
+    let mut verdicts = verdicts.into_iter();
+    nodes.retain_mut(|node| match verdicts.next().flatten() {
+        Some(remark) => {
+            node.remark = remark;
+            true
+        }
+        None => false,
+    });
+    Ok(())
+}
+
+/// [`filter_nodes_with_rhai`] on the blocking pool, so a script using up its
+/// time budget does not stall other requests on the async workers
+pub async fn filter_nodes_with_rhai_async(
+    nodes: &mut Vec<Proxy>,
+    source: String,
+) -> Result<(), String> {
+    #[cfg(not(target_arch = "wasm32"))]
+    {
+        let mut owned = std::mem::take(nodes);
+        let (owned, result) = tokio::task::spawn_blocking(move || {
+            let result = filter_nodes_with_rhai(&mut owned, &source);
+            (owned, result)
+        })
+        .await
+        .map_err(|e| format!("Filter script panicked: {}", e))?;
+        *nodes = owned;
+        result
+    }
+    #[cfg(target_arch = "wasm32")]
+    filter_nodes_with_rhai(nodes, &source)
+}
+
+/// Rhai scripts can not run without the `rhai-runtime` feature
+#[cfg(not(feature = "rhai-runtime"))]
+pub fn filter_nodes_with_rhai(_nodes: &mut Vec<Proxy>, _source: &str) -> Result<(), String> {
+    Err(
+        "Rhai is not supported in this build, please enable rhai-runtime feature in cargo build"
+            .to_string(),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[cfg(feature = "rhai-runtime")]
+    fn nodes() -> Vec<Proxy> {
+        ["HK 01", "HK 02", "JP 01"]
+            .iter()
+            .enumerate()
+            .map(|(index, remark)| Proxy {
+                remark: remark.to_string(),
+                hostname: format!("{}.example.com", index % 2),
+                port: 443,
+                ..Default::default()
+            })
+            .collect()
+    }
+
+    #[test]
+    #[cfg(feature = "rhai-runtime")]
+    fn test_filter_drops_and_renames_nodes() {
+        let mut nodes = nodes();
+        let script = r#"
+            fn filter(node) {
+                if node.Hostname == "1.example.com" { return false; }
+                if node.Remark.starts_with("JP") { return "Japan " + node.Port; }
+                true
+            }
+        "#;
+        filter_nodes_with_rhai(&mut nodes, script).unwrap();
+        let remarks: Vec<&str> = nodes.iter().map(|node| node.remark.as_str()).collect();
+        assert_eq!(remarks, ["HK 01", "Japan 443"]);
+    }
+
+    #[test]
+    #[cfg(feature = "rhai-runtime")]
+    fn test_failing_script_leaves_nodes_alone() {
+        let mut nodes = nodes();
+        let endless = "fn filter(node) { loop {} }";
+        let error = filter_nodes_with_rhai(&mut nodes, endless).unwrap_err();
+        assert!(error.starts_with("node HK 01:"), "{}", error);
+
+        let wrong_type = r#"fn filter(node) { if node.Remark == "JP 01" { 1 } else { false } }"#;
+        let error = filter_nodes_with_rhai(&mut nodes, wrong_type).unwrap_err();
+        assert!(error.contains("instead of a bool"), "{}", error);
+        assert!(filter_nodes_with_rhai(&mut nodes, "fn filter(node) {").is_err());
+        assert_eq!(nodes.len(), 3);
+    }
+
+    #[tokio::test]
+    #[cfg(feature = "rhai-runtime")]
+    async fn test_time_budget_stops_slow_scripts() {
+        // Every call stays well under MAX_OPERATIONS, only the time all of
+        // them take together is too much
+        let mut nodes: Vec<Proxy> = (0..10_000).flat_map(|_| nodes()).collect();
+        let slow = "fn filter(node) { for i in 0..100000 {} true }";
+        let started = std::time::Instant::now();
+        let error = filter_nodes_with_rhai_async(&mut nodes, slow.to_string())
+            .await
+            .unwrap_err();
+        assert!(error.ends_with("time budget of 2000 ms exceeded"), "{}", error);
+        assert!(started.elapsed() < std::time::Duration::from_millis(MAX_DURATION_MS * 3));
+        assert_eq!(nodes.len(), 30_000);
+    }
+
+    #[test]
+    fn test_rhai_scripts_are_recognized() {
+        assert!(is_rhai_script("rhai:fn filter(node) { true }"));
+        assert!(is_rhai_script("path:scripts/filter.rhai"));
+        assert!(!is_rhai_script("path:scripts/filter.js"));
+        assert!(!is_rhai_script("function filter(node) { return true; }"));
+    }
+}
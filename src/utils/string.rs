@@ -2,6 +2,7 @@
 
 use lazy_static::lazy_static;
 use regex::Regex;
+use std::cmp::Ordering;
 
 /// FNV-1a hash constants
 pub const HASH_PRIME: u64 = 0x100000001B3;
@@ -228,15 +229,21 @@ pub fn join<T: AsRef<str>>(parts: &[T], separator: &str) -> String {
 
 lazy_static! {
     // This regex targets characters with the Unicode Emoji property.
-    // Combining Presentation and Extended_Pictographic covers standard emojis, components, and sequences.
-    static ref EMOJI_REGEX: Regex = Regex::new(r"\p{Emoji_Presentation}|\p{Extended_Pictographic}").unwrap();
+    // Combining Presentation and Extended_Pictographic covers standard emojis, and the trailing
+    // class swallows the variation selectors, joiners, keycaps, skin tones and tags that glue
+    // sequences such as 🏳️‍🌈 together so no invisible leftovers remain.
+    static ref EMOJI_REGEX: Regex = Regex::new(
+        r"(?:\p{Emoji_Presentation}|\p{Extended_Pictographic})[\x{FE0F}\x{200D}\x{20E3}\x{E0020}-\x{E007F}\p{Emoji_Modifier}]*"
+    )
+    .unwrap();
 }
 
 /// Removes emoji characters from a string using a regular expression.
 ///
 /// This implementation uses the `regex` crate with Unicode property support.
 /// It targets characters with the `Emoji_Presentation` or `Extended_Pictographic`
-/// properties, which cover most standard emojis.
+/// properties, which cover most standard emojis, together with the sequence
+/// components attached to them.
 ///
 /// # Arguments
 ///
@@ -258,6 +265,58 @@ pub fn remove_emoji(s: &str) -> String {
     }
 }
 
+/// Compares two strings in natural order, case-insensitively and with runs of
+/// digits compared by value so that "node2" sorts before "node10"
+///
+/// # Arguments
+///
+/// * `a` - The first string
+/// * `b` - The second string
+///
+/// # Returns
+///
+/// The ordering of `a` relative to `b`
+pub fn natural_cmp(a: &str, b: &str) -> Ordering {
+    let mut a_chars = a.chars().peekable();
+    let mut b_chars = b.chars().peekable();
+
+    loop {
+        match (a_chars.peek().copied(), b_chars.peek().copied()) {
+            (None, None) => return a.cmp(b),
+            (None, Some(_)) => return Ordering::Less,
+            (Some(_), None) => return Ordering::Greater,
+            (Some(x), Some(y)) if x.is_ascii_digit() && y.is_ascii_digit() => {
+                let mut x_digits = String::new();
+                while let Some(c) = a_chars.next_if(char::is_ascii_digit) {
+                    x_digits.push(c);
+                }
+                let mut y_digits = String::new();
+                while let Some(c) = b_chars.next_if(char::is_ascii_digit) {
+                    y_digits.push(c);
+                }
+
+                let x_digits = x_digits.trim_start_matches('0');
+                let y_digits = y_digits.trim_start_matches('0');
+                let ordering = x_digits
+                    .len()
+                    .cmp(&y_digits.len())
+                    .then_with(|| x_digits.cmp(y_digits));
+                if ordering != Ordering::Equal {
+                    return ordering;
+                }
+            }
+            (Some(x), Some(y)) => {
+                let ordering = x.to_lowercase().cmp(y.to_lowercase());
+                if ordering != Ordering::Equal {
+                    return ordering;
+                }
+                a_chars.next();
+                b_chars.next();
+            }
+        }
+    }
+}
+
 /// Calculate MD5 hash for a string
 ///
 /// # Arguments
@@ -419,7 +478,21 @@ mod tests {
         // Test with no emoji
         assert_eq!(remove_emoji("Hello"), "Hello");
         // Test with only emoji
-        assert_eq!(remove_emoji("😀"), "😀"); // Preserves the original if all emoji
+        // Preserves the original if all emoji
+        assert_eq!(remove_emoji("😀"), "😀");
+        // Test with flags and joined sequences
+        assert_eq!(remove_emoji("🇭🇰 HK 01"), " HK 01");
+        assert_eq!(remove_emoji("🏳️‍🌈 Bandwidth"), " Bandwidth");
+        assert_eq!(remove_emoji("👍🏽Hello"), "Hello");
+    }
+
+    #[test]
+    fn test_natural_cmp() {
+        assert_eq!(natural_cmp("node2", "node10"), Ordering::Less);
+        assert_eq!(natural_cmp("Node 10", "node 9"), Ordering::Greater);
+        assert_eq!(natural_cmp("HK 01", "hk 1"), "HK 01".cmp("hk 1"));
+        assert_eq!(natural_cmp("a", "B"), Ordering::Less);
+        assert_eq!(natural_cmp("node", "node1"), Ordering::Less);
     }
 
     #[test]
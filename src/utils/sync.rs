@@ -0,0 +1,10 @@
+use std::sync::{Mutex, MutexGuard};
+
+/// Locks `mutex` even when a thread panicked while holding it
+///
+/// For caches whose entries are inserted whole, a panic while the lock was
+/// held cannot leave a half written entry behind, so a poisoned cache is
+/// still usable.
+pub fn lock_recovering<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(|e| e.into_inner())
+}
@@ -1,2 +1,4 @@
+pub mod tls;
+pub mod watch;
 pub mod web_api;
 pub use web_api::*;
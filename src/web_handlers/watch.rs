@@ -0,0 +1,154 @@
+//! Reloading the configuration when its files change on disk
+//!
+//! With `watch_config` enabled the preference file, the base directory and
+//! the profiles directory are watched. Changes are collected until writes
+//! have settled, then the same reload as `/updateconf` runs, which keeps the
+//! previous settings when the new ones can not be parsed.
+
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use actix_web::rt::time::timeout;
+use log::{error, info, warn};
+use notify::{Event, RecommendedWatcher, RecursiveMode, Watcher};
+use tokio::sync::mpsc;
+
+use crate::web_handlers::web_api::{reload_server_configuration, PROFILE_DIRS};
+use crate::Settings;
+
+/// Quiet time after the last change before reloading, editors and `cp`
+/// write a file in several steps
+const DEBOUNCE: Duration = Duration::from_millis(500);
+
+/// Files and directories whose changes trigger a reload
+#[derive(Debug, Clone, Default)]
+struct WatchedPaths {
+    /// The preference file, its directory is watched so files replaced by a
+    /// rename are still seen
+    pref_file: Option<PathBuf>,
+    /// Directories watched with everything below them
+    dirs: Vec<PathBuf>,
+}
+
+impl WatchedPaths {
+    /// Paths to watch for the current settings, those missing are left out
+    fn from_settings(settings: &Settings) -> Self {
+        let pref_file = (!settings.pref_path.is_empty())
+            .then(|| std::fs::canonicalize(&settings.pref_path).ok())
+            .flatten();
+        let base_path = if settings.base_path.is_empty() {
+            "base"
+        } else {
+            settings.base_path.as_str()
+        };
+        let mut dirs: Vec<PathBuf> = Vec::new();
+        for dir in [base_path, PROFILE_DIRS[0]] {
+            if let Ok(dir) = std::fs::canonicalize(dir) {
+                if dir.is_dir() && !dirs.iter().any(|known| dir.starts_with(known)) {
+                    dirs.push(dir);
+                }
+            }
+        }
+        WatchedPaths { pref_file, dirs }
+    }
+
+    /// Whether `event` touches one of the watched paths
+    fn is_relevant(&self, event: &Event) -> bool {
+        if event.kind.is_access() || event.kind.is_other() {
+            return false;
+        }
+        event.paths.iter().any(|path| self.contains(path))
+    }
+
+    fn contains(&self, path: &Path) -> bool {
+        self.pref_file.as_deref() == Some(path) || self.dirs.iter().any(|dir| path.starts_with(dir))
+    }
+}
+
+/// Start watching the configuration files, changes reload the server
+/// configuration until the returned watcher is dropped
+///
+/// Must be called from within the actix runtime.
+pub fn watch_config_files() -> Result<RecommendedWatcher, String> {
+    let paths = WatchedPaths::from_settings(&Settings::current());
+    let (tx, rx) = mpsc::unbounded_channel();
+
+    let filter = paths.clone();
+    let mut watcher =
+        notify::recommended_watcher(move |result: notify::Result<Event>| match result {
+            Ok(event) if filter.is_relevant(&event) => {
+                let _ = tx.send(());
+            }
+            Ok(_) => {}
+            Err(e) => warn!("Error watching configuration files: {}", e),
+        })
+        .map_err(|e| format!("failed to create file watcher: {}", e))?;
+
+    if let Some(parent) = paths.pref_file.as_deref().and_then(Path::parent) {
+        watcher
+            .watch(parent, RecursiveMode::NonRecursive)
+            .map_err(|e| format!("failed to watch '{}': {}", parent.display(), e))?;
+    }
+    for dir in &paths.dirs {
+        watcher
+            .watch(dir, RecursiveMode::Recursive)
+            .map_err(|e| format!("failed to watch '{}': {}", dir.display(), e))?;
+    }
+    if let Some(pref_file) = &paths.pref_file {
+        info!("Watching {} for changes", pref_file.display());
+    }
+    for dir in &paths.dirs {
+        info!("Watching {} for changes", dir.display());
+    }
+
+    actix_web::rt::spawn(reload_on_change(rx, DEBOUNCE));
+    Ok(watcher)
+}
+
+/// Reload once per burst of changes, after `quiet` has passed without another
+async fn reload_on_change(mut changes: mpsc::UnboundedReceiver<()>, quiet: Duration) {
+    while changes.recv().await.is_some() {
+        while let Ok(Some(())) = timeout(quiet, changes.recv()).await {}
+
+        info!("Configuration files changed, reloading");
+        match reload_server_configuration().await {
+            Ok(()) => info!("Configuration reloaded"),
+            Err(e) => error!(
+                "Failed to reload configuration, keeping the previous settings: {}",
+                e
+            ),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_only_changes_to_watched_paths_are_relevant() {
+        let paths = WatchedPaths {
+            pref_file: Some(PathBuf::from("/srv/sub/pref.toml")),
+            dirs: vec![PathBuf::from("/srv/sub/base")],
+        };
+        let event = |kind, path: &str| Event::new(kind).add_path(PathBuf::from(path));
+
+        assert!(paths.is_relevant(&event(
+            notify::EventKind::Modify(notify::event::ModifyKind::Any),
+            "/srv/sub/pref.toml"
+        )));
+        assert!(paths.is_relevant(&event(
+            notify::EventKind::Create(notify::event::CreateKind::File),
+            "/srv/sub/base/rules/custom.list"
+        )));
+        // Neighbours of the preference file share its directory
+        assert!(!paths.is_relevant(&event(
+            notify::EventKind::Modify(notify::event::ModifyKind::Any),
+            "/srv/sub/cache/abc"
+        )));
+        assert!(!paths.is_relevant(&event(
+            notify::EventKind::Access(notify::event::AccessKind::Any),
+            "/srv/sub/pref.toml"
+        )));
+    }
+}
@@ -1,19 +1,35 @@
 use std::collections::HashMap;
+use std::convert::Infallible;
+use std::net::IpAddr;
+use std::path::{Path, PathBuf};
 
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use actix_web::http::header::{self, HeaderName, HeaderValue};
+use actix_web::http::StatusCode;
 use actix_web::{web, HttpRequest, HttpResponse};
-use log::error;
-use serde::Deserialize;
+use log::{error, warn};
+use serde::{Deserialize, Serialize};
 
-use crate::api::{sub_process, SubResponse, SubconverterQuery};
-use crate::generator::ruleconvert::common::transform_rule_to_common;
-use crate::generator::ruleconvert::convert_ruleset::convert_ruleset;
+use crate::api::{load_profile_items, sub_process, SubResponse, SubconverterQuery};
+use crate::generator::config::formats::base_cache::clear_ini_base_cache;
+use crate::generator::exports::proxy_to_clash::clear_clash_base_cache;
+use crate::generator::ruleconvert::common::{
+    is_asn_rule, transform_rule_to_common, warn_dropped_asn_rules, PolicyTarget,
+};
+use crate::generator::ruleconvert::convert_cache::{clear_convert_cache, convert_ruleset_cached};
+use crate::generator::ruleconvert::{detect_ruleset_behavior, Behavior};
+use crate::interfaces::SubconverterError;
 use crate::models::ruleset::{get_ruleset_type_from_url, RULESET_TYPES};
 use crate::models::RulesetType;
 use crate::rulesets::ruleset::fetch_ruleset;
+use crate::settings::update_settings_from_file;
 use crate::utils::base64::url_safe_base64_decode;
 use crate::utils::file_exists;
 use crate::utils::http::parse_proxy;
 use crate::utils::ini_reader::IniReader;
+use crate::web_handlers::tls::reload_tls_certificate;
 use crate::Settings;
 impl SubResponse {
     /// Convert SubResponse to HttpResponse
@@ -29,31 +45,213 @@ impl SubResponse {
             ),
         };
 
-        // Add headers
+        // Add headers, a streamed body has no length known up front
         for (name, value) in self.headers {
+            if self.stream.is_some() && name.eq_ignore_ascii_case("content-length") {
+                continue;
+            }
             http_response.append_header((name, value));
         }
 
         // Set content type
         http_response.content_type(self.content_type);
 
+        // Send large configs chunk by chunk while they are produced
+        if let Some(stream) = self.stream {
+            let chunks = stream.map(|chunk| Ok::<_, Infallible>(web::Bytes::from(chunk)));
+            return http_response.streaming(futures::stream::iter(chunks));
+        }
+
         // Return response with content
         http_response.body(self.content)
     }
 }
 
+/// Header carrying the ID assigned to each conversion request
+const REQUEST_ID_HEADER: &str = "x-request-id";
+
+/// Error envelope returned to clients that send `Accept: application/json`
+#[derive(Debug, Serialize)]
+pub struct ErrorResponse {
+    pub error: ErrorDetail,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ErrorDetail {
+    /// HTTP status code of the response
+    pub code: u16,
+    pub message: String,
+    pub request_id: String,
+}
+
+/// Generate an ID unique within this process, used to correlate responses
+/// with log lines
+fn next_request_id() -> String {
+    static COUNTER: AtomicU64 = AtomicU64::new(0);
+    let nanos = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_nanos() as u64)
+        .unwrap_or_default();
+    uuid::Uuid::from_u64_pair(nanos, COUNTER.fetch_add(1, Ordering::Relaxed))
+        .hyphenated()
+        .to_string()
+}
+
+fn accepts_json(req: &HttpRequest) -> bool {
+    req.headers()
+        .get(header::ACCEPT)
+        .and_then(|value| value.to_str().ok())
+        .map(|accept| accept.contains("application/json"))
+        .unwrap_or(false)
+}
+
+fn set_request_id(response: &mut HttpResponse, request_id: &str) {
+    if let Ok(value) = HeaderValue::from_str(request_id) {
+        response
+            .headers_mut()
+            .insert(HeaderName::from_static(REQUEST_ID_HEADER), value);
+    }
+}
+
+/// Build an error response, as a JSON envelope when the client asks for one
+/// and as plain text otherwise
+fn error_response(
+    req: &HttpRequest,
+    request_id: &str,
+    status: StatusCode,
+    message: String,
+) -> HttpResponse {
+    let mut builder = HttpResponse::build(status);
+    builder.insert_header((REQUEST_ID_HEADER, request_id));
+    if accepts_json(req) {
+        builder.json(ErrorResponse {
+            error: ErrorDetail {
+                code: status.as_u16(),
+                message,
+                request_id: request_id.to_string(),
+            },
+        })
+    } else {
+        builder.body(message)
+    }
+}
+
+/// Answer with the status code the error maps to, internal errors keep the
+/// generic prefix clients have always seen
+fn subconverter_error_response(
+    req: &HttpRequest,
+    request_id: &str,
+    error: SubconverterError,
+) -> HttpResponse {
+    let status =
+        StatusCode::from_u16(error.status_code()).unwrap_or(StatusCode::INTERNAL_SERVER_ERROR);
+    error_response(req, request_id, status, error.message())
+}
+
+/// Turn the result of `sub_process` into a response tagged with the request ID
+fn sub_result_to_http_response(
+    req: &HttpRequest,
+    request_id: &str,
+    result: Result<SubResponse, SubconverterError>,
+) -> HttpResponse {
+    match result {
+        Ok(response) if response.status_code >= 400 && accepts_json(req) => {
+            let status = StatusCode::from_u16(response.status_code)
+                .unwrap_or(StatusCode::INTERNAL_SERVER_ERROR);
+            error_response(req, request_id, status, response.content)
+        }
+        Ok(response) => {
+            let mut http_response = response.to_http_response();
+            set_request_id(&mut http_response, request_id);
+            http_response
+        }
+        Err(e) => {
+            error!("[{}] Subconverter process error: {}", request_id, e);
+            subconverter_error_response(req, request_id, e)
+        }
+    }
+}
+
 #[derive(Debug, Deserialize)]
 pub struct ProfileQuery {
     pub name: String,
     pub token: Option<String>,
 }
 
+#[derive(Debug, Deserialize)]
+pub struct TokenQuery {
+    pub token: Option<String>,
+}
+
+/// Summary of a profile INI as returned by `/profiles`
+#[derive(Debug, Serialize)]
+pub struct ProfileEntry {
+    /// Name usable as the `name` argument of `/getprofile`
+    pub name: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub target: Option<String>,
+    /// Number of subscription URLs, the URLs themselves are never exposed
+    pub url_count: usize,
+    /// Last modification time in seconds since the Unix epoch
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub modified: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+/// Directories scanned by `/profiles`, in the same order `load_profile_query`
+/// resolves names
+pub(crate) const PROFILE_DIRS: [&str; 2] = ["profiles", "base/profiles"];
+
+/// Directory profile names are resolved against, the working directory unless
+/// the app registers another one with `app_data(web::Data::new(ProfileRoot(..)))`
+#[derive(Debug, Clone)]
+pub struct ProfileRoot(pub PathBuf);
+
+fn profile_root(req: &HttpRequest) -> PathBuf {
+    req.app_data::<web::Data<ProfileRoot>>()
+        .map(|root| root.0.clone())
+        .unwrap_or_else(|| PathBuf::from("."))
+}
+
 #[derive(Debug, Deserialize)]
 pub struct RulesetQuery {
     #[serde(rename = "type")]
     pub rule_type: i32,
     pub url: String,
     pub group: Option<String>,
+    /// `yaml` (the default) or `text` for the Clash payloads of types 3, 4
+    /// and 6
+    pub format: Option<String>,
+}
+
+/// How the Clash payloads of `/getruleset` are written
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum PayloadFormat {
+    /// A `payload:` document for `format: yaml` providers
+    Yaml,
+    /// One rule per line for `format: text` providers
+    Text,
+}
+
+impl PayloadFormat {
+    fn parse(format: Option<&str>) -> Result<Self, SubconverterError> {
+        match format.unwrap_or("yaml") {
+            "yaml" => Ok(PayloadFormat::Yaml),
+            "text" => Ok(PayloadFormat::Text),
+            other => Err(SubconverterError::InvalidInput(format!(
+                "unsupported ruleset format: {}",
+                other
+            ))),
+        }
+    }
+
+    fn write(self, lines: &[String]) -> (String, &'static str) {
+        match self {
+            PayloadFormat::Yaml => (build_clash_payload(lines), "application/yaml"),
+            PayloadFormat::Text => (lines.join("\n") + "\n", "text/plain"),
+        }
+    }
 }
 
 fn is_api_authorized(token: Option<&str>) -> bool {
@@ -65,41 +263,279 @@ fn is_api_authorized(token: Option<&str>) -> bool {
     token.unwrap_or_default() == settings.api_access_token
 }
 
-async fn load_profile_query(profile_name: &str) -> Result<SubconverterQuery, String> {
-    let mut candidate_paths = vec![profile_name.to_string()];
-    if !profile_name.starts_with("base/") {
-        candidate_paths.push(format!("base/{}", profile_name));
+/// `query` completed with the arguments configured for the User-Agent of the
+/// client when it names no target, the arguments of the request win
+fn query_for_user_agent(req: &HttpRequest, query: SubconverterQuery) -> SubconverterQuery {
+    if query
+        .target
+        .as_deref()
+        .is_some_and(|target| target != "auto")
+    {
+        return query;
     }
+    let settings = Settings::current();
+    let Some(args) = header_value(req, "user-agent")
+        .as_deref()
+        .and_then(|user_agent| settings.user_agent_args(user_agent))
+    else {
+        return query;
+    };
 
-    for path in candidate_paths {
-        if !file_exists(&path).await {
-            continue;
+    let mut pairs: Vec<(String, String)> =
+        url::form_urlencoded::parse(req.query_string().as_bytes())
+            .into_owned()
+            .filter(|(key, _)| key != "target")
+            .collect();
+    for (key, value) in url::form_urlencoded::parse(args.as_bytes()).into_owned() {
+        if !pairs.iter().any(|(existing, _)| *existing == key) {
+            pairs.push((key, value));
+        }
+    }
+    match SubconverterQuery::from_pairs(&pairs) {
+        Ok(query) => query,
+        Err(e) => {
+            error!("Invalid arguments '{}' for User-Agent: {}", args, e);
+            query
         }
+    }
+}
+
+/// Base URL clients reached this server at, with the API base path, e.g.
+/// `https://sub.example.com/subconverter`
+fn external_base_url(req: &HttpRequest) -> String {
+    let settings = Settings::current();
+    base_url_of_request(req, &settings.trusted_proxies, &settings.api_base_path)
+}
 
-        let mut ini = IniReader::new();
-        if let Err(e) = ini.parse_file(&path).await {
-            return Err(format!("failed to parse profile '{}': {}", path, e));
+/// Same as [`external_base_url`] with the settings handed in
+///
+/// `Forwarded` and `X-Forwarded-Proto`/`X-Forwarded-Host` are only read when
+/// the peer is one of `trusted_proxies`, anyone else could point the
+/// generated URLs anywhere.
+fn base_url_of_request(req: &HttpRequest, trusted_proxies: &[String], base_path: &str) -> String {
+    let trusted = trusted_proxies.iter().any(|proxy| {
+        proxy == "*"
+            || req
+                .peer_addr()
+                .is_some_and(|peer| proxy.parse::<IpAddr>().ok() == Some(peer.ip().to_canonical()))
+    });
+
+    let (mut scheme, mut host) = (None, None);
+    if trusted {
+        // The first element is what the client connected to
+        if let Some(forwarded) = header_value(req, "forwarded") {
+            for pair in forwarded.split(',').next().unwrap_or_default().split(';') {
+                let Some((key, value)) = pair.split_once('=') else {
+                    continue;
+                };
+                let value = value.trim().trim_matches('"').to_string();
+                match key.trim().to_ascii_lowercase().as_str() {
+                    "proto" => scheme = Some(value),
+                    "host" => host = Some(value),
+                    _ => {}
+                }
+            }
         }
+        let first = |name| {
+            header_value(req, name).and_then(|value| {
+                value
+                    .split(',')
+                    .next()
+                    .map(|value| value.trim().to_string())
+            })
+        };
+        scheme = scheme.or_else(|| first("x-forwarded-proto"));
+        host = host.or_else(|| first("x-forwarded-host"));
+    }
+
+    let scheme = scheme
+        .filter(|scheme| {
+            scheme.eq_ignore_ascii_case("http") || scheme.eq_ignore_ascii_case("https")
+        })
+        .map(|scheme| scheme.to_ascii_lowercase())
+        .unwrap_or_else(|| {
+            if req.app_config().secure() {
+                "https".to_string()
+            } else {
+                "http".to_string()
+            }
+        });
+    let is_host = |host: &String| {
+        !host.is_empty()
+            && host
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || "-._:[]".contains(c))
+    };
+    let host = host
+        .filter(is_host)
+        .or_else(|| header_value(req, "host").filter(is_host))
+        .unwrap_or_else(|| req.app_config().host().to_string());
+    format!("{}://{}{}", scheme, host, base_path)
+}
+
+fn header_value(req: &HttpRequest, name: &str) -> Option<String> {
+    req.headers()
+        .get(name)
+        .and_then(|value| value.to_str().ok())
+        .map(str::to_string)
+}
+
+async fn load_profile_query(
+    root: &Path,
+    profile_name: &str,
+) -> Result<SubconverterQuery, SubconverterError> {
+    let items = load_profile_items(root, profile_name).await?;
+    profile_items_to_query(&items).map_err(|e| SubconverterError::ParseError {
+        what: "profile".to_string(),
+        detail: format!(
+            "failed converting profile '{}' to query: {}",
+            profile_name, e
+        ),
+    })
+}
 
-        if ini.enter_section("Profile").is_err() {
-            return Err(format!("profile '{}' has no [Profile] section", path));
+/// Convert `[Profile]` items into a query the same way `/sub` would receive it
+fn profile_items_to_query(
+    items: &[(String, String)],
+) -> Result<SubconverterQuery, serde_urlencoded::de::Error> {
+    SubconverterQuery::from_pairs(items)
+}
+
+/// Resolve a profile name from `/profiles/{name}` to its file name, rejecting
+/// anything that could escape the profiles directory
+fn profile_file_name(name: &str) -> Result<String, String> {
+    if name.is_empty()
+        || name.starts_with('.')
+        || name.contains('/')
+        || name.contains('\\')
+        || name.contains("..")
+    {
+        return Err(format!("invalid profile name: {}", name));
+    }
+
+    if name.ends_with(".ini") {
+        Ok(name.to_string())
+    } else {
+        Ok(format!("{}.ini", name))
+    }
+}
+
+fn profile_value_to_string(value: &serde_json::Value) -> String {
+    match value {
+        serde_json::Value::String(s) => s.clone(),
+        other => other.to_string(),
+    }
+}
+
+async fn save_profile(
+    root: &Path,
+    name: &str,
+    values: &serde_json::Map<String, serde_json::Value>,
+) -> Result<String, String> {
+    let file_name = profile_file_name(name)?;
+
+    let mut items = Vec::with_capacity(values.len());
+    for (key, value) in values {
+        let value = profile_value_to_string(value);
+        if key.is_empty() || key.contains(['=', '\n', '\r', '[']) || value.contains(['\n', '\r']) {
+            return Err(format!("invalid profile item: {}", key));
         }
+        items.push((key.clone(), value));
+    }
+
+    profile_items_to_query(&items).map_err(|e| format!("invalid profile: {}", e))?;
+
+    let mut content = String::from("[Profile]\n");
+    for (key, value) in &items {
+        content.push_str(&format!("{}={}\n", key, value));
+    }
 
-        let items = ini
-            .get_items("Profile")
-            .map_err(|e| format!("failed reading [Profile] in '{}': {}", path, e))?;
+    // Write to a temporary file first so readers never see a partial profile
+    let dir = root.join(PROFILE_DIRS[0]);
+    let path = dir.join(&file_name);
+    let tmp_path = dir.join(format!(".{}.tmp", file_name));
+    tokio::fs::create_dir_all(&dir)
+        .await
+        .map_err(|e| format!("failed to create profile directory: {}", e))?;
+    tokio::fs::write(&tmp_path, content)
+        .await
+        .map_err(|e| format!("failed to write profile: {}", e))?;
+    if let Err(e) = tokio::fs::rename(&tmp_path, &path).await {
+        let _ = tokio::fs::remove_file(&tmp_path).await;
+        return Err(format!("failed to write profile: {}", e));
+    }
+
+    Ok(format!("profiles/{}", file_name))
+}
+
+async fn read_profile_entry(name: String, path: &str) -> ProfileEntry {
+    let modified = tokio::fs::metadata(path)
+        .await
+        .ok()
+        .and_then(|meta| meta.modified().ok())
+        .and_then(|time| time.duration_since(UNIX_EPOCH).ok())
+        .map(|duration| duration.as_secs());
 
-        let encoded = items
-            .into_iter()
-            .map(|(k, v)| format!("{}={}", urlencoding::encode(&k), urlencoding::encode(&v)))
-            .collect::<Vec<_>>()
-            .join("&");
+    let mut entry = ProfileEntry {
+        name,
+        target: None,
+        url_count: 0,
+        modified,
+        error: None,
+    };
 
-        return serde_urlencoded::from_str::<SubconverterQuery>(&encoded)
-            .map_err(|e| format!("failed converting profile '{}' to query: {}", path, e));
+    let mut ini = IniReader::new();
+    if let Err(e) = ini.parse_file(path).await {
+        entry.error = Some(format!("failed to parse profile: {}", e));
+        return entry;
+    }
+    if !ini.section_exist("Profile") {
+        entry.error = Some("profile has no [Profile] section".to_string());
+        return entry;
     }
 
-    Err(format!("profile not found: {}", profile_name))
+    let target = ini.get("Profile", "target");
+    if !target.is_empty() {
+        entry.target = Some(target);
+    }
+    entry.url_count = ini
+        .get("Profile", "url")
+        .split('|')
+        .filter(|url| !url.trim().is_empty())
+        .count();
+    entry
+}
+
+async fn list_profiles(root: &Path) -> Vec<ProfileEntry> {
+    let mut entries: Vec<ProfileEntry> = Vec::new();
+
+    for dir in PROFILE_DIRS {
+        let dir = root.join(dir);
+        let mut read_dir = match tokio::fs::read_dir(&dir).await {
+            Ok(read_dir) => read_dir,
+            Err(_) => continue,
+        };
+
+        while let Ok(Some(dir_entry)) = read_dir.next_entry().await {
+            let file_name = dir_entry.file_name().to_string_lossy().to_string();
+            if !file_name.ends_with(".ini") {
+                continue;
+            }
+
+            // Profiles under base/ are addressed without the prefix, exactly
+            // like the fallback in load_profile_query
+            let name = format!("profiles/{}", file_name);
+            if entries.iter().any(|entry| entry.name == name) {
+                continue;
+            }
+
+            let path = dir.join(&file_name);
+            entries.push(read_profile_entry(name, &path.to_string_lossy()).await);
+        }
+    }
+
+    entries.sort_by(|a, b| a.name.cmp(&b.name));
+    entries
 }
 
 fn build_clash_payload(lines: &[String]) -> String {
@@ -128,8 +564,13 @@ fn extract_rule_value(line: &str) -> Option<(String, String)> {
     Some((rule_type, value))
 }
 
-async fn build_ruleset_response(query: &RulesetQuery) -> Result<String, String> {
-    let settings = Settings::current();
+/// The ruleset `query` asks for with its content type
+async fn build_ruleset_response(
+    query: &RulesetQuery,
+) -> Result<(String, &'static str), SubconverterError> {
+    let format = PayloadFormat::parse(query.format.as_deref())?;
+    // The guard is released before the fetches, a reload waits for no upstream
+    let settings = Settings::current().clone();
     let proxy = parse_proxy(&settings.proxy_ruleset);
 
     let decoded_url = url_safe_base64_decode(&query.url);
@@ -163,9 +604,12 @@ async fn build_ruleset_response(query: &RulesetQuery) -> Result<String, String>
         settings.async_fetch_ruleset,
     )
     .await
-    .map_err(|e| format!("failed to fetch ruleset: {}", e))?;
+    .map_err(|e| SubconverterError::UpstreamFetch {
+        url: fetch_url.clone(),
+        source: format!("failed to fetch ruleset: {}", e).into(),
+    })?;
 
-    let surge_lines = normalize_rules_lines(&convert_ruleset(&raw, source_type));
+    let surge_lines = normalize_rules_lines(&convert_ruleset_cached(&raw, source_type, &settings));
     let group = query
         .group
         .as_deref()
@@ -173,48 +617,77 @@ async fn build_ruleset_response(query: &RulesetQuery) -> Result<String, String>
         .filter(|s| !s.is_empty())
         .unwrap_or_else(|| "DIRECT".to_string());
 
+    // Domain and ipcidr payloads leave out every other rule
+    if matches!(query.rule_type, 3 | 4)
+        && detect_ruleset_behavior(&surge_lines.join("\n")) == Behavior::Classical
+    {
+        warn!(
+            "Ruleset {} holds rules a {} provider can not, serve it as classical with type=6",
+            fetch_url,
+            if query.rule_type == 3 {
+                "domain"
+            } else {
+                "ipcidr"
+            }
+        );
+    }
+
     let output = match query.rule_type {
-        1 => surge_lines.join("\n") + "\n",
+        1 => (surge_lines.join("\n") + "\n", "text/plain"),
         2 => {
-            surge_lines
-                .iter()
-                .map(|line| transform_rule_to_common(line, &group, true))
-                .collect::<Vec<_>>()
-                .join("\n")
-                + "\n"
+            // Quantumult X filters have no IP-ASN rule
+            let (asn_lines, lines): (Vec<_>, Vec<_>) =
+                surge_lines.iter().partition(|line| is_asn_rule(line));
+            warn_dropped_asn_rules(&fetch_url, PolicyTarget::QuantumultX, asn_lines.len());
+            (
+                lines
+                    .into_iter()
+                    .map(|line| transform_rule_to_common(line, &group, true))
+                    .collect::<Vec<_>>()
+                    .join("\n")
+                    + "\n",
+                "text/plain",
+            )
         }
         3 => {
             let payload = surge_lines
                 .iter()
                 .filter_map(|line| extract_rule_value(line))
-                .filter_map(|(rule_type, value)| match rule_type.as_str() {
-                    "DOMAIN" => Some(value),
-                    "DOMAIN-SUFFIX" => Some(format!("+.{}", value)),
-                    "DOMAIN-KEYWORD" => Some(format!("*{}*", value)),
-                    _ => None,
+                .filter(|(rule_type, _)| {
+                    Behavior::of_rule_type(rule_type) == Some(Behavior::Domain)
+                })
+                .map(|(rule_type, value)| match rule_type.as_str() {
+                    "DOMAIN-SUFFIX" => format!("+.{}", value),
+                    "DOMAIN-KEYWORD" => format!("*{}*", value),
+                    _ => value,
                 })
                 .collect::<Vec<_>>();
-            build_clash_payload(&payload)
+            format.write(&payload)
         }
         4 => {
             let payload = surge_lines
                 .iter()
                 .filter_map(|line| extract_rule_value(line))
-                .filter_map(|(rule_type, value)| match rule_type.as_str() {
-                    "IP-CIDR" | "IP-CIDR6" => Some(value),
-                    _ => None,
+                .filter(|(rule_type, _)| {
+                    Behavior::of_rule_type(rule_type) == Some(Behavior::Ipcidr)
                 })
+                .map(|(_, value)| value)
                 .collect::<Vec<_>>();
-            build_clash_payload(&payload)
+            format.write(&payload)
+        }
+        6 => format.write(&surge_lines),
+        _ => {
+            return Err(SubconverterError::InvalidInput(
+                "unsupported ruleset type".to_string(),
+            ))
         }
-        6 => build_clash_payload(&surge_lines),
-        _ => return Err("unsupported ruleset type".to_string()),
     };
 
     Ok(output)
 }
 
 pub async fn version_handler() -> HttpResponse {
+    Settings::current().log_env_overrides();
     HttpResponse::Ok().body(format!(
         "subconverter v{} backend\n",
         env!("CARGO_PKG_VERSION")
@@ -222,13 +695,21 @@ pub async fn version_handler() -> HttpResponse {
 }
 
 pub async fn profile_handler(req: HttpRequest, query: web::Query<ProfileQuery>) -> HttpResponse {
+    let request_id = next_request_id();
     if !is_api_authorized(query.token.as_deref()) {
-        return HttpResponse::Forbidden().body("Forbidden");
+        return subconverter_error_response(
+            &req,
+            &request_id,
+            SubconverterError::Unauthorized("Forbidden".to_string()),
+        );
     }
 
-    let mut profile_query = match load_profile_query(&query.name).await {
+    let mut profile_query = match load_profile_query(&profile_root(&req), &query.name).await {
         Ok(q) => q,
-        Err(e) => return HttpResponse::BadRequest().body(e),
+        Err(e) => {
+            error!("[{}] getprofile error: {}", request_id, e);
+            return subconverter_error_response(&req, &request_id, e);
+        }
     };
 
     let mut request_headers = HashMap::new();
@@ -236,24 +717,77 @@ pub async fn profile_handler(req: HttpRequest, query: web::Query<ProfileQuery>)
         request_headers.insert(key.to_string(), value.to_str().unwrap_or("").to_string());
     }
     profile_query.request_headers = Some(request_headers);
+    profile_query.external_base_url = Some(external_base_url(&req));
 
-    match sub_process(Some(req.uri().to_string()), profile_query).await {
-        Ok(response) => response.to_http_response(),
+    let result = sub_process(Some(req.uri().to_string()), profile_query).await;
+    sub_result_to_http_response(&req, &request_id, result)
+}
+
+pub async fn profiles_handler(req: HttpRequest, query: web::Query<TokenQuery>) -> HttpResponse {
+    if !is_api_authorized(query.token.as_deref()) {
+        return HttpResponse::Forbidden().body("Forbidden");
+    }
+
+    HttpResponse::Ok().json(list_profiles(&profile_root(&req)).await)
+}
+
+pub async fn profile_update_handler(
+    req: HttpRequest,
+    path: web::Path<(String,)>,
+    query: web::Query<TokenQuery>,
+    body: web::Json<serde_json::Map<String, serde_json::Value>>,
+) -> HttpResponse {
+    if !is_api_authorized(query.token.as_deref()) {
+        return HttpResponse::Forbidden().body("Forbidden");
+    }
+
+    match save_profile(&profile_root(&req), &path.0, &body).await {
+        Ok(name) => HttpResponse::Ok().json(serde_json::json!({ "name": name })),
+        Err(e) => HttpResponse::BadRequest().body(e),
+    }
+}
+
+pub async fn profile_delete_handler(
+    req: HttpRequest,
+    path: web::Path<(String,)>,
+    query: web::Query<TokenQuery>,
+) -> HttpResponse {
+    if !is_api_authorized(query.token.as_deref()) {
+        return HttpResponse::Forbidden().body("Forbidden");
+    }
+
+    let file_name = match profile_file_name(&path.0) {
+        Ok(file_name) => file_name,
+        Err(e) => return HttpResponse::BadRequest().body(e),
+    };
+
+    match tokio::fs::remove_file(profile_root(&req).join(PROFILE_DIRS[0]).join(file_name)).await {
+        Ok(_) => HttpResponse::NoContent().finish(),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+            HttpResponse::NotFound().body(format!("profile not found: {}", path.0))
+        }
         Err(e) => {
-            error!("getprofile process error: {}", e);
-            HttpResponse::InternalServerError().body(format!("Internal server error: {}", e))
+            HttpResponse::InternalServerError().body(format!("failed to delete profile: {}", e))
         }
     }
 }
 
-pub async fn ruleset_handler(query: web::Query<RulesetQuery>) -> HttpResponse {
+pub async fn ruleset_handler(req: HttpRequest, query: web::Query<RulesetQuery>) -> HttpResponse {
+    let request_id = next_request_id();
     match build_ruleset_response(&query).await {
-        Ok(content) => HttpResponse::Ok().content_type("text/plain").body(content),
-        Err(e) => HttpResponse::BadRequest().body(e),
+        Ok((content, content_type)) => HttpResponse::Ok()
+            .content_type(content_type)
+            .insert_header((REQUEST_ID_HEADER, request_id.as_str()))
+            .body(content),
+        Err(e) => {
+            error!("[{}] getruleset error: {}", request_id, e);
+            subconverter_error_response(&req, &request_id, e)
+        }
     }
 }
 
 pub async fn sub_handler(req: HttpRequest, query: web::Query<SubconverterQuery>) -> HttpResponse {
+    let request_id = next_request_id();
     let req_url = req.uri().to_string();
 
     let mut request_headers = HashMap::new();
@@ -261,16 +795,12 @@ pub async fn sub_handler(req: HttpRequest, query: web::Query<SubconverterQuery>)
         request_headers.insert(key.to_string(), value.to_str().unwrap_or("").to_string());
     }
 
-    let mut modified_query = query.into_inner();
+    let mut modified_query = query_for_user_agent(&req, query.into_inner());
     modified_query.request_headers = Some(request_headers);
+    modified_query.external_base_url = Some(external_base_url(&req));
 
-    match sub_process(Some(req_url), modified_query).await {
-        Ok(response) => response.to_http_response(),
-        Err(e) => {
-            error!("Subconverter process error: {}", e);
-            HttpResponse::InternalServerError().body(format!("Internal server error: {}", e))
-        }
-    }
+    let result = sub_process(Some(req_url), modified_query).await;
+    sub_result_to_http_response(&req, &request_id, result)
 }
 
 /// Handler for simple conversion (no rules)
@@ -289,14 +819,14 @@ pub async fn simple_handler(
             // Create a modified query with the target set
             let mut modified_query = query.into_inner();
             modified_query.target = Some(target_type.clone());
+            modified_query.external_base_url = Some(external_base_url(&req));
 
             // Reuse the sub_handler logic
             match sub_process(Some(req_url), modified_query).await {
                 Ok(response) => response.to_http_response(),
                 Err(e) => {
                     error!("Subconverter process error: {}", e);
-                    HttpResponse::InternalServerError()
-                        .body(format!("Internal server error: {}", e))
+                    subconverter_error_response(&req, &next_request_id(), e)
                 }
             }
         }
@@ -314,26 +844,466 @@ pub async fn surge_to_clash_handler(
     // Create a modified query with the target set to Clash
     let mut modified_query = query.into_inner();
     modified_query.target = Some("clash".to_string());
+    modified_query.external_base_url = Some(external_base_url(&req));
 
     // Set nodelist to true for this special case
     modified_query.list = Some(true);
 
+    // `link` is the Surge config or subscription to read the nodes from
+    if modified_query.url.is_none() {
+        modified_query.url = url::form_urlencoded::parse(req.query_string().as_bytes())
+            .find(|(key, _)| key == "link")
+            .map(|(_, link)| link.into_owned());
+    }
+
     // Reuse the sub_process logic
     match sub_process(Some(req_url), modified_query).await {
         Ok(response) => response.to_http_response(),
         Err(e) => {
             error!("Subconverter process error: {}", e);
-            HttpResponse::InternalServerError().body(format!("Internal server error: {}", e))
+            subconverter_error_response(&req, &next_request_id(), e)
         }
     }
 }
 
-/// Register the API endpoints with Actix Web
+/// Reload settings from the preference file and pick up renewed TLS
+/// certificates, edited base templates and rulesets
+pub async fn reload_server_configuration() -> Result<(), String> {
+    let pref_path = Settings::current().pref_path.clone();
+    reload_server_configuration_from(&pref_path).await
+}
+
+/// Same as [`reload_server_configuration`] but reads the given preference
+/// file, an empty path keeps the current settings
+pub async fn reload_server_configuration_from(pref_path: &str) -> Result<(), String> {
+    if !pref_path.is_empty() {
+        update_settings_from_file(pref_path)
+            .await
+            .map_err(|e| e.to_string())?;
+    }
+    clear_clash_base_cache();
+    clear_ini_base_cache();
+    clear_convert_cache();
+    reload_tls_certificate()?;
+    Ok(())
+}
+
+pub async fn update_conf_handler(query: web::Query<TokenQuery>) -> HttpResponse {
+    if !is_api_authorized(query.token.as_deref()) {
+        return HttpResponse::Forbidden().body("Forbidden");
+    }
+
+    match reload_server_configuration().await {
+        Ok(_) => HttpResponse::Ok().body("done\n"),
+        Err(e) => {
+            error!("Failed to reload configuration: {}", e);
+            HttpResponse::InternalServerError()
+                .body(format!("Failed to reload configuration: {}", e))
+        }
+    }
+}
+
+pub async fn health_handler() -> HttpResponse {
+    HttpResponse::Ok().body("Subconverter is running!")
+}
+
+/// Register the API endpoints with Actix Web under the configured base path
 pub fn config(cfg: &mut web::ServiceConfig) {
-    cfg.route("/version", web::get().to(version_handler))
-        .route("/sub", web::get().to(sub_handler))
-        .route("/surge2clash", web::get().to(surge_to_clash_handler))
-        .route("/getprofile", web::get().to(profile_handler))
-        .route("/getruleset", web::get().to(ruleset_handler))
-        .route("/{target_type}", web::get().to(simple_handler));
+    let base_path = Settings::current().api_base_path.clone();
+    config_with_base_path(cfg, &base_path);
+}
+
+/// Register the API endpoints under `base_path`, which must already be
+/// normalized (leading slash, no trailing slash, or empty for the root)
+pub fn config_with_base_path(cfg: &mut web::ServiceConfig, base_path: &str) {
+    cfg.service(
+        web::scope(base_path)
+            .route("/", web::get().to(health_handler))
+            .route("/version", web::get().to(version_handler))
+            .route("/sub", web::get().to(sub_handler))
+            .route("/surge2clash", web::get().to(surge_to_clash_handler))
+            .route("/getprofile", web::get().to(profile_handler))
+            .route("/profiles", web::get().to(profiles_handler))
+            .route("/profiles/{name}", web::post().to(profile_update_handler))
+            .route("/profiles/{name}", web::delete().to(profile_delete_handler))
+            .route("/getruleset", web::get().to(ruleset_handler))
+            .route("/updateconf", web::get().to(update_conf_handler))
+            .route("/{target_type}", web::get().to(simple_handler)),
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::generator::exports::ConfigChunks;
+    use actix_web::test::{call_service, init_service, read_body, TestRequest};
+    use actix_web::App;
+    use std::sync::Arc;
+
+    fn init_test_settings() {
+        // A non-empty pref_path keeps sub_process from searching for pref files
+        let mut guard = Settings::current_mut();
+        let settings = Arc::make_mut(&mut *guard);
+        if settings.pref_path.is_empty() {
+            settings.pref_path = "test".to_string();
+        }
+    }
+
+    #[test]
+    fn test_profile_file_name_rejects_path_separators() {
+        assert_eq!(profile_file_name("mine").unwrap(), "mine.ini");
+        assert_eq!(profile_file_name("mine.ini").unwrap(), "mine.ini");
+        assert!(profile_file_name("../pref.ini").is_err());
+        assert!(profile_file_name("dir/mine").is_err());
+        assert!(profile_file_name("dir\\mine").is_err());
+        assert!(profile_file_name(".hidden").is_err());
+        assert!(profile_file_name("").is_err());
+    }
+
+    #[actix_web::test]
+    async fn test_profile_saved_on_windows_loads() {
+        let root = tempfile::tempdir().unwrap();
+        std::fs::write(
+            root.path().join("windows.ini"),
+            "\u{FEFF}[Profile]\r\n\
+             ; edited in Notepad\r\n\
+             target=clash\r\n\
+             url=https://example.com/sub?token=abc ; main airport\r\n\
+             exclude=(?i)expire\r\n",
+        )
+        .unwrap();
+
+        let query = load_profile_query(root.path(), "windows.ini")
+            .await
+            .unwrap();
+        assert_eq!(query.target.as_deref(), Some("clash"));
+        assert_eq!(
+            query.url.as_deref(),
+            Some("https://example.com/sub?token=abc")
+        );
+        assert_eq!(query.exclude.as_deref(), Some("(?i)expire"));
+    }
+
+    #[actix_web::test]
+    async fn test_streamed_response_drops_content_length() {
+        let chunks = ["port: 7890\n", "rules:\n", "  - MATCH,DIRECT\n"].map(String::from);
+        let response = SubResponse::ok(String::new(), "application/yaml".to_string())
+            .with_headers(HashMap::from([(
+                "Content-Length".to_string(),
+                "0".to_string(),
+            )]))
+            .with_stream(Some(ConfigChunks::new(chunks.into_iter())))
+            .to_http_response();
+
+        assert_eq!(response.status(), StatusCode::OK);
+        assert!(response.headers().get(header::CONTENT_LENGTH).is_none());
+        let body = actix_web::body::to_bytes(response.into_body())
+            .await
+            .unwrap();
+        assert_eq!(body, "port: 7890\nrules:\n  - MATCH,DIRECT\n");
+    }
+
+    #[actix_web::test]
+    async fn test_created_profile_is_servable() {
+        init_test_settings();
+        let root = tempfile::tempdir().unwrap();
+        let app = init_service(
+            App::new()
+                .app_data(web::Data::new(ProfileRoot(root.path().to_path_buf())))
+                .configure(config),
+        )
+        .await;
+        let name = "mine";
+
+        let req = TestRequest::post()
+            .uri(&format!("/profiles/{}", name))
+            .set_json(serde_json::json!({
+                "target": "clash",
+                "url": "ss://Y2hhY2hhMjAtaWV0Zi1wb2x5MTMwNTpwYXNzd29yZA@www.example.com:1080#Example",
+                "udp": true,
+            }))
+            .to_request();
+        let resp = call_service(&app, req).await;
+        assert!(resp.status().is_success());
+        assert!(root.path().join("profiles/mine.ini").exists());
+
+        let req = TestRequest::get()
+            .uri(&format!("/getprofile?name=profiles/{}.ini", name))
+            .to_request();
+        let resp = call_service(&app, req).await;
+        let status = resp.status();
+        let body = String::from_utf8(read_body(resp).await.to_vec()).unwrap();
+        assert!(status.is_success(), "{}", body);
+        assert!(body.contains("www.example.com"));
+
+        let req = TestRequest::delete()
+            .uri(&format!("/profiles/{}", name))
+            .to_request();
+        assert!(call_service(&app, req).await.status().is_success());
+
+        let req = TestRequest::get()
+            .uri(&format!("/getprofile?name=profiles/{}.ini", name))
+            .to_request();
+        assert!(call_service(&app, req).await.status().is_client_error());
+    }
+
+    #[test]
+    fn test_target_picked_from_user_agent() {
+        let query = |uri: &str, user_agent: &str| {
+            let req = TestRequest::get()
+                .uri(uri)
+                .insert_header(("user-agent", user_agent))
+                .to_http_request();
+            let query = web::Query::<SubconverterQuery>::from_query(req.query_string())
+                .unwrap()
+                .into_inner();
+            query_for_user_agent(&req, query)
+        };
+
+        let clash = query("/sub?url=a", "ClashforWindows/0.20.39");
+        assert_eq!(clash.target.as_deref(), Some("clash"));
+        assert_eq!(clash.new_name, Some(true));
+        assert_eq!(clash.url.as_deref(), Some("a"));
+
+        let surge = query("/sub?target=auto&ver=3&url=a", "Surge iOS/2920");
+        assert_eq!(surge.target.as_deref(), Some("surge"));
+        assert_eq!(surge.ver, 3);
+
+        let explicit = query("/sub?target=ss&url=a", "ClashforWindows/0.20.39");
+        assert_eq!(explicit.target.as_deref(), Some("ss"));
+        assert_eq!(explicit.new_name, None);
+
+        assert_eq!(query("/sub?url=a", "curl/8.0").target, None);
+    }
+
+    #[test]
+    fn test_base_url_respects_trusted_forwarding_headers() {
+        let proxy: std::net::SocketAddr = "10.0.0.2:40000".parse().unwrap();
+        let forwarded = || {
+            TestRequest::default()
+                .peer_addr(proxy)
+                .insert_header(("host", "127.0.0.1:25500"))
+                .insert_header(("x-forwarded-proto", "https"))
+                .insert_header(("x-forwarded-host", "sub.example.com, 10.0.0.2"))
+                .to_http_request()
+        };
+        let trusted = vec!["10.0.0.2".to_string()];
+
+        assert_eq!(
+            base_url_of_request(&forwarded(), &[], ""),
+            "http://127.0.0.1:25500"
+        );
+        assert_eq!(
+            base_url_of_request(&forwarded(), &["10.0.0.3".to_string()], ""),
+            "http://127.0.0.1:25500"
+        );
+        assert_eq!(
+            base_url_of_request(&forwarded(), &trusted, "/subconverter"),
+            "https://sub.example.com/subconverter"
+        );
+
+        let req = TestRequest::default()
+            .peer_addr(proxy)
+            .insert_header(("x-forwarded-host", "ignored.example.com"))
+            .insert_header((
+                "forwarded",
+                "proto=https;host=\"cdn.example.com\", for=10.0.0.9",
+            ))
+            .to_http_request();
+        assert_eq!(
+            base_url_of_request(&req, &["*".to_string()], ""),
+            "https://cdn.example.com"
+        );
+
+        let req = TestRequest::default()
+            .peer_addr(proxy)
+            .insert_header(("host", "127.0.0.1:25500"))
+            .insert_header(("x-forwarded-host", "evil.example.com/path?"))
+            .to_http_request();
+        assert_eq!(
+            base_url_of_request(&req, &trusted, ""),
+            "http://127.0.0.1:25500"
+        );
+    }
+
+    #[actix_web::test]
+    async fn test_ruleset_payload_formats() {
+        init_test_settings();
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("ads.list");
+        std::fs::write(
+            &path,
+            "# Ads\nDOMAIN-SUFFIX,ad.com\nDOMAIN,ads.example.com\nIP-CIDR,10.0.0.0/8\nIP-ASN,13335,no-resolve\n",
+        )
+        .unwrap();
+        let url = crate::utils::base64::url_safe_base64_encode(&path.to_string_lossy());
+        let app = init_service(App::new().configure(config)).await;
+        let get = |query: String| TestRequest::get().uri(&query).to_request();
+
+        let response = call_service(&app, get(format!("/getruleset?type=3&url={}", url))).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(
+            response.headers().get(header::CONTENT_TYPE).unwrap(),
+            "application/yaml"
+        );
+        assert_eq!(
+            read_body(response).await,
+            "payload:\n  - '+.ad.com'\n  - 'ads.example.com'\n"
+        );
+
+        let response = call_service(
+            &app,
+            get(format!("/getruleset?type=4&url={}&format=text", url)),
+        )
+        .await;
+        assert_eq!(
+            response.headers().get(header::CONTENT_TYPE).unwrap(),
+            "text/plain"
+        );
+        assert_eq!(read_body(response).await, "10.0.0.0/8\n");
+
+        let response = call_service(
+            &app,
+            get(format!("/getruleset?type=6&url={}&format=text", url)),
+        )
+        .await;
+        assert_eq!(
+            read_body(response).await,
+            "DOMAIN-SUFFIX,ad.com\nDOMAIN,ads.example.com\nIP-CIDR,10.0.0.0/8\nIP-ASN,13335,no-resolve\n"
+        );
+
+        let response = call_service(
+            &app,
+            get(format!("/getruleset?type=2&url={}&group=UHJveHk", url)),
+        )
+        .await;
+        assert_eq!(
+            read_body(response).await,
+            "DOMAIN-SUFFIX,ad.com,Proxy\nDOMAIN,ads.example.com,Proxy\nIP-CIDR,10.0.0.0/8,Proxy\n"
+        );
+
+        let response = call_service(
+            &app,
+            get(format!("/getruleset?type=3&url={}&format=json", url)),
+        )
+        .await;
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    }
+
+    #[actix_web::test]
+    async fn test_routes_served_under_base_path() {
+        let app =
+            init_service(App::new().configure(|cfg| config_with_base_path(cfg, "/subconverter")))
+                .await;
+
+        let req = TestRequest::get().uri("/subconverter/version").to_request();
+        assert!(call_service(&app, req).await.status().is_success());
+
+        let req = TestRequest::get().uri("/version").to_request();
+        assert_eq!(call_service(&app, req).await.status(), 404);
+
+        let req = TestRequest::get().uri("/clash").to_request();
+        assert_eq!(call_service(&app, req).await.status(), 404);
+    }
+
+    #[actix_web::test]
+    async fn test_invalid_profile_is_rejected() {
+        let app = init_service(App::new().configure(config)).await;
+
+        let req = TestRequest::post()
+            .uri("/profiles/invalid_profile")
+            .set_json(serde_json::json!({ "ver": "not-a-number" }))
+            .to_request();
+        assert_eq!(call_service(&app, req).await.status(), 400);
+        assert!(!file_exists("profiles/invalid_profile.ini").await);
+    }
+
+    #[actix_web::test]
+    async fn test_errors_use_json_envelope_when_accepted() {
+        let app = init_service(App::new().configure(config)).await;
+
+        let req = TestRequest::get()
+            .uri("/getprofile?name=profiles/missing_profile.ini")
+            .insert_header(("Accept", "application/json"))
+            .to_request();
+        let resp = call_service(&app, req).await;
+        assert_eq!(resp.status(), 404);
+        let request_id = resp
+            .headers()
+            .get(REQUEST_ID_HEADER)
+            .unwrap()
+            .to_str()
+            .unwrap()
+            .to_string();
+        let body: serde_json::Value = serde_json::from_slice(&read_body(resp).await).unwrap();
+        assert_eq!(body["error"]["code"], 404);
+        assert_eq!(body["error"]["request_id"], request_id.as_str());
+        assert!(body["error"]["message"]
+            .as_str()
+            .unwrap()
+            .contains("profile not found"));
+
+        let req = TestRequest::get()
+            .uri("/getprofile?name=profiles/missing_profile.ini")
+            .to_request();
+        let resp = call_service(&app, req).await;
+        assert_eq!(resp.status(), 404);
+        assert!(resp.headers().contains_key(REQUEST_ID_HEADER));
+        let body = read_body(resp).await;
+        assert!(body.starts_with(b"profile not found"));
+    }
+
+    #[actix_web::test]
+    async fn test_invalid_remark_filter_is_rejected() {
+        init_test_settings();
+        let app = init_service(App::new().configure(config)).await;
+
+        let req = TestRequest::get()
+            .uri("/sub?target=clash&url=ss%3A%2F%2Fexample&include=(HK")
+            .to_request();
+        let resp = call_service(&app, req).await;
+        assert_eq!(resp.status(), 400);
+        let body = read_body(resp).await;
+        assert_eq!(&body[..], b"Invalid regex in request: include=(HK");
+    }
+
+    #[actix_web::test]
+    async fn test_unusable_external_config_is_rejected() {
+        init_test_settings();
+        let app = init_service(App::new().configure(config)).await;
+
+        let req = TestRequest::get()
+            .uri("/sub?target=clash&url=ss%3A%2F%2Fexample&config=%2Fetc%2Fpasswd")
+            .to_request();
+        let resp = call_service(&app, req).await;
+        assert_eq!(resp.status(), 403);
+
+        let req = TestRequest::get()
+            .uri("/sub?target=clash&url=ss%3A%2F%2Fexample&config=config%2Fmissing.ini")
+            .to_request();
+        let resp = call_service(&app, req).await;
+        assert_eq!(resp.status(), 400);
+        let body = read_body(resp).await;
+        assert!(String::from_utf8_lossy(&body)
+            .starts_with("Failed to load external config from config/missing.ini"));
+    }
+
+    #[actix_web::test]
+    async fn test_reload_server_configuration_reads_pref_file() {
+        let previous = Arc::clone(&Settings::current());
+        let dir = tempfile::tempdir().unwrap();
+        let pref_path = dir.path().join("pref.ini");
+        std::fs::write(
+            &pref_path,
+            "[server]\nshutdown_timeout=7\n\n[advanced]\nmax_concurrent_threads=1\n",
+        )
+        .unwrap();
+
+        let result = reload_server_configuration_from(pref_path.to_str().unwrap()).await;
+        let shutdown_timeout = Settings::current().shutdown_timeout;
+        *Settings::current_mut() = previous;
+
+        assert!(result.is_ok(), "{:?}", result);
+        assert_eq!(shutdown_timeout, 7);
+    }
 }